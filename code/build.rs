@@ -0,0 +1,16 @@
+use std::process::Command;
+
+fn main() {
+    // Shown on the About screen. Builds without git just show "unknown".
+    if let Some(hash) = Command::new("git")
+        .args(["rev-parse", "--short", "HEAD"])
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+    {
+        println!("cargo:rustc-env=GIT_SHORT_HASH={}", hash.trim());
+    }
+    println!("cargo:rerun-if-changed=../.git/HEAD");
+    println!("cargo:rerun-if-changed=../.git/refs/heads");
+}
@@ -7,8 +7,11 @@ use core::{
 };
 
 use collect_array_ext_trait::CollectArray;
-use common::{Event, MAX_NFC_READERS, Request};
-use defmt::{Debug2Format, debug, error, info, warn};
+use common::{
+    Event, LINK_NOISE_WINDOW_MS, MAX_NFC_READERS, NFC_ALIVE_INTERVAL_MS, PROTOCOL_VERSION,
+    PacketErrorCounts, PacketReader, Press, Request, correct, led_frame_requests,
+};
+use defmt::{Debug2Format, Format, debug, error, info, warn};
 use display_interface::DisplayError;
 use embassy_embedded_hal::shared_bus::asynch::i2c::I2cDeviceWithConfig;
 use embassy_executor::Spawner;
@@ -16,7 +19,7 @@ use embassy_futures::select::{Either, select};
 use embassy_sync::{
     blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal, watch::Watch,
 };
-use embassy_time::{Duration, Instant, Timer};
+use embassy_time::{Duration, Instant, Timer, WithTimeout};
 use embedded_io_async::{Read, Write};
 use esp_backtrace as _;
 use esp_hal::{
@@ -34,8 +37,9 @@ use esp_hal::{
     usb_serial_jtag::UsbSerialJtag,
 };
 use heapless::Vec;
+use lib::{CardScanner, UartCardScanner, config::LedLayout};
 use mfrc522::Uid;
-use smart_leds::{RGB, brightness};
+use smart_leds::RGB;
 use ssd1306::{I2CDisplayInterface, Ssd1306Async, prelude::*, size::DisplaySize128x64};
 
 esp_bootloader_esp_idf::esp_app_desc!();
@@ -91,11 +95,64 @@ async fn main(spawner: Spawner) {
     SOFT_RESET_SIGNAL.wait().await;
     info!("Done  soft resetting");
 
+    let is_compatible = match StmLink.query_info().await {
+        Some(info) => {
+            info!("STM32 info: {}", info);
+            if info.protocol_version == PROTOCOL_VERSION {
+                true
+            } else {
+                warn!(
+                    "STM32 protocol version is {}, but we use {}. Not using NFC.",
+                    info.protocol_version, PROTOCOL_VERSION
+                );
+                false
+            }
+        }
+        None => {
+            warn!(
+                "STM32 did not respond with its info. It may be running an old firmware. Not using NFC."
+            );
+            false
+        }
+    };
+
     spawner.spawn(led_task()).unwrap();
     spawner.spawn(leds_task()).unwrap();
     spawner.spawn(rotary_switch_task()).unwrap();
     spawner.spawn(rotary_encoder_task()).unwrap();
-    spawner.spawn(nfc_task()).unwrap();
+    if is_compatible {
+        spawner.spawn(nfc_task()).unwrap();
+    }
+}
+
+#[derive(Debug, Format, Clone, Copy)]
+struct StmInfo {
+    fw_version: u32,
+    protocol_version: u16,
+    nfc_readers: u8,
+    total_leds: u8,
+    uptime_ms: u32,
+    packet_errors: PacketErrorCounts,
+}
+
+/// How long to wait for the STM32 to respond to a request
+const STM_RESPONSE_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// Talks to the STM32 through the UART tasks
+struct StmLink;
+
+impl StmLink {
+    /// Returns `None` if the STM32 didn't respond in time
+    async fn query_info(&self) -> Option<StmInfo> {
+        INFO_SIGNAL.reset();
+        REQUEST_SIGNALS[5].signal(Request::GetInfo);
+        NEW_REQUEST_SIGNAL.signal(());
+        INFO_SIGNAL
+            .wait()
+            .with_timeout(STM_RESPONSE_TIMEOUT)
+            .await
+            .ok()
+    }
 }
 
 #[embassy_executor::task]
@@ -187,6 +244,7 @@ async fn display_task(i2c: I2C0<'static>, scl: GPIO5<'static>, sda: GPIO6<'stati
 }
 
 type M = CriticalSectionRawMutex;
+const TOTAL_LEDS: usize = LedLayout::dev_grid().total_leds;
 
 static REQUEST_SIGNALS: [Signal<M, Request>; 6] = [
     Signal::new(),
@@ -196,6 +254,8 @@ static REQUEST_SIGNALS: [Signal<M, Request>; 6] = [
     Signal::new(),
     Signal::new(),
 ];
+/// A full frame takes multiple requests, so it is sent separately from [`REQUEST_SIGNALS`]
+static LEDS_FRAME_SIGNAL: Signal<M, [RGB<u8>; TOTAL_LEDS]> = Signal::new();
 static NEW_REQUEST_SIGNAL: Signal<CriticalSectionRawMutex, ()> = Signal::new();
 
 #[embassy_executor::task]
@@ -203,7 +263,16 @@ async fn uart_tx_task(mut uart_tx: UartTx<'static, Async>) {
     let mut buffer = [Default::default(); 1024];
     loop {
         NEW_REQUEST_SIGNAL.wait().await;
-        for request in REQUEST_SIGNALS.iter().flat_map(|signal| signal.try_take()) {
+        for request in REQUEST_SIGNALS
+            .iter()
+            .flat_map(|signal| signal.try_take())
+            .chain(
+                LEDS_FRAME_SIGNAL
+                    .try_take()
+                    .into_iter()
+                    .flat_map(|frame| led_frame_requests(&frame).collect::<Vec<_, 4>>()),
+            )
+        {
             let bytes_written = postcard::to_slice_cobs(&request, &mut buffer)
                 .unwrap()
                 .len();
@@ -218,32 +287,30 @@ async fn uart_tx_task(mut uart_tx: UartTx<'static, Async>) {
 }
 
 static SOFT_RESET_SIGNAL: Signal<M, ()> = Signal::new();
-static ROTARY_SWITCH_SIGNAL: Signal<M, bool> = Signal::new();
+static ROTARY_SWITCH_SIGNAL: Signal<M, (bool, Option<u32>)> = Signal::new();
 static ROTARY_ENCODER_SIGNAL: Signal<M, i64> = Signal::new();
 static NFC_SIGNAL: Signal<M, Vec<Option<Uid>, MAX_NFC_READERS>> = Signal::new();
+static INFO_SIGNAL: Signal<M, StmInfo> = Signal::new();
+static NFC_ALIVE_SIGNAL: Signal<M, ()> = Signal::new();
 
 #[embassy_executor::task]
 async fn uart_rx_task(mut uart_rx: UartRx<'static, Async>) {
-    let mut buffer = [Default::default(); 1024];
-    let mut buffer_len = 0;
+    let mut reader = PacketReader::<1024>::new();
     loop {
-        match uart_rx.read_async(&mut buffer[buffer_len..]).await {
+        match uart_rx.read_async(reader.unfilled()).await {
             Ok(bytes_read) => {
-                buffer_len += bytes_read;
-                loop {
-                    let data = &mut buffer[..buffer_len];
-                    let zero_pos = match data.iter().copied().position(|byte| byte == 0) {
-                        Some(pos) => pos,
-                        None => break,
-                    };
-                    let packet_len = zero_pos + 1;
-                    match postcard::from_bytes_cobs::<Event>(&mut data[..packet_len]) {
+                reader.received(bytes_read);
+                while let Some(result) = reader.next_packet::<Event>() {
+                    match result {
                         Ok(event) => match event {
                             Event::SoftResetComplete => {
                                 SOFT_RESET_SIGNAL.signal(());
                             }
-                            Event::RotarySwitch(value) => {
-                                ROTARY_SWITCH_SIGNAL.signal(value);
+                            Event::RotarySwitch {
+                                pressed,
+                                duration_ms,
+                            } => {
+                                ROTARY_SWITCH_SIGNAL.signal((pressed, duration_ms));
                             }
                             Event::RotaryEncoder(value) => {
                                 ROTARY_ENCODER_SIGNAL.signal(value);
@@ -251,13 +318,38 @@ async fn uart_rx_task(mut uart_rx: UartRx<'static, Async>) {
                             Event::Nfc(value) => {
                                 NFC_SIGNAL.signal(value);
                             }
+                            Event::Info {
+                                fw_version,
+                                protocol_version,
+                                nfc_readers,
+                                total_leds,
+                                uptime_ms,
+                                packet_errors,
+                            } => {
+                                INFO_SIGNAL.signal(StmInfo {
+                                    fw_version,
+                                    protocol_version,
+                                    nfc_readers,
+                                    total_leds,
+                                    uptime_ms,
+                                    packet_errors,
+                                });
+                            }
+                            Event::NfcAlive => {
+                                NFC_ALIVE_SIGNAL.signal(());
+                            }
+                            Event::LinkQualityWarning { cobs_errors } => {
+                                warn!(
+                                    "UART link noisy: the STM32 received {} corrupted requests in {}s",
+                                    cobs_errors,
+                                    LINK_NOISE_WINDOW_MS / 1000
+                                );
+                            }
                         },
                         Err(e) => {
-                            error!("error deserializing packet: {}", e);
+                            error!("error reading packet: {}", e);
                         }
                     }
-                    buffer.copy_within(packet_len..buffer_len, 0);
-                    buffer_len -= packet_len;
                 }
             }
             Err(e) => {
@@ -269,7 +361,6 @@ async fn uart_rx_task(mut uart_rx: UartRx<'static, Async>) {
 
 #[embassy_executor::task]
 async fn leds_task() {
-    const TOTAL_LEDS: usize = 64;
     // We can push this to the limit with an interval of 3ms!
     // But to actually be able to see that it's not skipping any LEDs, we reduce this.
     let frame_interval = Duration::from_millis(100);
@@ -278,7 +369,7 @@ async fn leds_task() {
     let mut receiver = IS_RUNNING.receiver().unwrap();
     loop {
         if !receiver.try_get().unwrap() {
-            REQUEST_SIGNALS[2].signal(Request::SetLeds(array::repeat(Default::default())));
+            LEDS_FRAME_SIGNAL.signal(array::repeat(Default::default()));
             NEW_REQUEST_SIGNAL.signal(());
             receiver.changed_and(|bool| *bool).await;
         }
@@ -294,14 +385,12 @@ async fn leds_task() {
         //     RGB::new(255, 50, 0)
         // };
         let alternate_color = Default::default();
-        let leds = brightness(
-            repeat_n(alternate_color, n)
-                .chain(once(RGB::new(255, 0, 0)))
-                .chain(repeat_n(alternate_color, TOTAL_LEDS - n - 1)),
-            5,
-        );
+        let leds = repeat_n(alternate_color, n)
+            .chain(once(RGB::new(255, 0, 0)))
+            .chain(repeat_n(alternate_color, TOTAL_LEDS - n - 1))
+            .map(|color| correct(color, 5));
 
-        REQUEST_SIGNALS[2].signal(Request::SetLeds(leds.collect_array().unwrap()));
+        LEDS_FRAME_SIGNAL.signal(leds.collect_array().unwrap());
         NEW_REQUEST_SIGNAL.signal(());
     }
 }
@@ -325,17 +414,20 @@ async fn led_task() {
 
 #[embassy_executor::task]
 async fn rotary_switch_task() {
-    REQUEST_SIGNALS[3].signal(Request::WatchRotarySwitch(true));
+    REQUEST_SIGNALS[2].signal(Request::WatchRotarySwitch(true));
     NEW_REQUEST_SIGNAL.signal(());
     loop {
-        let is_pressed = ROTARY_SWITCH_SIGNAL.wait().await;
-        info!("rotary button pressed? {}", is_pressed);
+        let (pressed, duration_ms) = ROTARY_SWITCH_SIGNAL.wait().await;
+        info!("rotary button pressed? {}", pressed);
+        if let Some(press) = Press::from_event(pressed, duration_ms) {
+            info!("rotary button {} press", press);
+        }
     }
 }
 
 #[embassy_executor::task]
 async fn rotary_encoder_task() {
-    REQUEST_SIGNALS[4].signal(Request::WatchRotaryEncoder(true));
+    REQUEST_SIGNALS[3].signal(Request::WatchRotaryEncoder(true));
     NEW_REQUEST_SIGNAL.signal(());
     loop {
         let position = ROTARY_ENCODER_SIGNAL.wait().await;
@@ -345,18 +437,36 @@ async fn rotary_encoder_task() {
 
 #[embassy_executor::task]
 async fn nfc_task() {
-    REQUEST_SIGNALS[5].signal(Request::WatchNfc(true));
+    REQUEST_SIGNALS[4].signal(Request::WatchNfc(true));
     NEW_REQUEST_SIGNAL.signal(());
+    let mut scanner = UartCardScanner::new(&NFC_SIGNAL);
     let mut last_updated = None;
     loop {
-        let nfc_tags = NFC_SIGNAL.wait().await;
-        let now = Instant::now();
-        let previously_updated = last_updated.replace(now);
-        info!(
-            "NFC tags: {} in {}us",
-            nfc_tags,
-            previously_updated.map(|before| (now - before).as_micros())
-        );
+        // The STM32 only sends the tags when they change
+        match select(
+            scanner.next_scan(),
+            NFC_ALIVE_SIGNAL
+                .wait()
+                .with_timeout(Duration::from_millis(2 * NFC_ALIVE_INTERVAL_MS)),
+        )
+        .await
+        {
+            Either::First(nfc_tags) => {
+                let now = Instant::now();
+                let previously_updated = last_updated.replace(now);
+                info!(
+                    "NFC tags: {} after {}us",
+                    nfc_tags,
+                    previously_updated.map(|before| (now - before).as_micros())
+                );
+            }
+            Either::Second(Ok(())) => {
+                debug!("NFC scanning is still running");
+            }
+            Either::Second(Err(_)) => {
+                warn!("NFC scanning stopped");
+            }
+        }
     }
 }
 
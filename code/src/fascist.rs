@@ -1,18 +1,18 @@
 #![no_std]
 #![no_main]
 
-use core::fmt::Write;
+use core::{cell::Cell, future::pending, pin::pin, sync::atomic::Ordering};
 
-use defmt::info;
+use common::{AMBER, BLINK_CODE_STEP_MS, BlinkCode, LedAnimator, LedWriter, correct};
+use defmt::{Debug2Format, info, warn};
 use embassy_embedded_hal::adapter::BlockingAsync;
 use embassy_executor::Spawner;
-use embassy_futures::join::*;
-use embassy_time::{Duration, Timer};
-use embedded_graphics::{
-    mono_font::{MonoTextStyleBuilder, iso_8859_16::FONT_7X14},
-    pixelcolor::BinaryColor,
-    prelude::*,
+use embassy_futures::{join::*, select::*};
+use embassy_sync::{
+    blocking_mutex::{Mutex, raw::CriticalSectionRawMutex},
+    signal::Signal,
 };
+use embassy_time::{Duration, Instant, TimeoutError, Timer, with_timeout};
 use esp_backtrace as _;
 use esp_bootloader_esp_idf::partitions::{
     DataPartitionSubType, PARTITION_TABLE_MAX_LEN, PartitionType, read_partition_table,
@@ -23,6 +23,7 @@ use esp_hal::{
     interrupt::software::SoftwareInterruptControl,
     rmt::Rmt,
     rng::{Trng, TrngSource},
+    system::software_reset,
     time::Rate,
     timer::timg::TimerGroup,
 };
@@ -30,24 +31,129 @@ use esp_hal_smartled::{SmartLedsAdapterAsync, buffer_size_async, smart_led_buffe
 use esp_println as _;
 use esp_radio::ble::controller::BleConnector;
 use esp_storage::FlashStorage;
+use game_pure::{
+    CommandAckTransport, LedsDisplay, Settings, ShutdownStorage, shutdown_peripheral,
+    sync::{
+        FascistBoardLeds, GamePhase, PeripheralSync, SYNC_MTU, SYNC_RESEND_MS, SpectatorState,
+        SyncMessage, fascist_adv_data,
+    },
+};
 use lib::{
-    CONNECTIONS_MAX, DrawWriter, FASCIST_DATA_BUFFER_LEN, FascistStorage, L2CAP_CHANNELS_MAX,
-    LED_BRIGHTNESS, PSM_L2CAP_EXAMPLES, PostcardValue, SERVICE_UUID, ScaleRgb,
-    config::SAVE_BOND_INFO,
+    CLOCK_SYNC, CONNECTIONS_MAX, DISPLAY_MISSING, DisplayInitRetry, ELECTION_TRACKER_COLOR,
+    FASCIST_BOARD_LEDS, FASCIST_DATA_BUFFER_LEN, FASCIST_LED_LAYOUT, FASCIST_TOTAL_LEDS,
+    FascistScreen, FascistStorage, L2CAP_CHANNELS_MAX, LEDS_DISABLED, PSM_L2CAP_EXAMPLES,
+    PairingEvent, PostcardValue, SERVICE_UUID, SkipUnchanged, SpectatorServer,
+    config::{
+        AURA_BLINK_INTERVAL, DISPLAY_INIT_RETRY_INTERVAL, L2CAP_ACCEPT_TIMEOUT, LED_FADE,
+        LED_FADE_FRAME_INTERVAL, SAVE_BOND_INFO,
+    },
+    fascist_aura_blinks, fascist_leds_frame, reply_gatt, serve_gatt, serve_spectator,
+    show_fascist_screen, ssd1306_rotation, try_init_display,
 };
 use sequential_storage::{
     cache::NoCache,
     map::{MapConfig, MapStorage},
 };
-use smart_leds::{RGB8, SmartLedsWriteAsync};
-use ssd1306::{
-    I2CDisplayInterface, Ssd1306Async, prelude::DisplayRotation, prelude::*,
-    size::DisplaySize128x64,
-};
+use ssd1306::{I2CDisplayInterface, Ssd1306Async, prelude::*, size::DisplaySize128x64};
 use trouble_host::prelude::*;
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
+/// The liberal board's time minus ours, so that our logs can be lined up with the liberal board's.
+/// 0 until the first time sync.
+static CENTRAL_CLOCK_OFFSET_MS: Mutex<CriticalSectionRawMutex, Cell<i64>> =
+    Mutex::new(Cell::new(0));
+
+defmt::timestamp!(
+    "{=u64:ms}",
+    Instant::now()
+        .as_millis()
+        .saturating_add_signed(CENTRAL_CLOCK_OFFSET_MS.lock(Cell::get))
+);
+
+/// Sends everything that `sync` has to send on the L2CAP channel
+async fn transmit<C: Controller>(
+    sync: &mut PeripheralSync,
+    channel: &mut L2capChannel<'_, DefaultPacketPool>,
+    stack: &Stack<'_, C, DefaultPacketPool>,
+) {
+    while let Some(message) = sync.poll_transmit(Instant::now().as_millis()) {
+        let Ok(frame) = message.encode() else {
+            warn!("Sync message doesn't fit in the MTU");
+            continue;
+        };
+        if let Err(e) = channel.send(stack, &frame).await {
+            warn!("L2CAP send error: {}", e);
+            break;
+        }
+    }
+}
+
+/// Acks the liberal board's command right away, since this board resets before the next sync message would be sent
+struct SyncChannelAck<'a, 'd, 's, C: Controller> {
+    sync: &'a mut PeripheralSync,
+    channel: &'a mut L2capChannel<'d, DefaultPacketPool>,
+    stack: &'a Stack<'s, C, DefaultPacketPool>,
+}
+
+impl<C: Controller> CommandAckTransport for SyncChannelAck<'_, '_, '_, C> {
+    async fn send_ack(&mut self) {
+        self.sync.ack_command();
+        transmit(self.sync, self.channel, self.stack).await;
+    }
+}
+
+/// This board only reads its storage, so no writes can be pending
+struct NothingToFlush;
+
+impl ShutdownStorage for NothingToFlush {
+    async fn flush_before_reset(&mut self) {}
+}
+
+/// Lets a spectator's phone connect with the other connection slot while the liberal board is connected.
+/// This never returns, so it should be dropped when the liberal board disconnects.
+async fn serve_spectators<C: Controller>(
+    peripheral: &mut Peripheral<'_, C, DefaultPacketPool>,
+    server: &SpectatorServer<'_>,
+    scan_data: &[u8],
+    phase: &Cell<GamePhase>,
+    state: &Signal<CriticalSectionRawMutex, SpectatorState>,
+) {
+    loop {
+        let adv_data = fascist_adv_data(phase.get());
+        let advertiser = match peripheral
+            .advertise(
+                &Default::default(),
+                Advertisement::ConnectableScannableUndirected {
+                    adv_data: &adv_data,
+                    scan_data,
+                },
+            )
+            .await
+        {
+            Ok(advertiser) => advertiser,
+            Err(e) => {
+                warn!("Failed to advertise for spectators: {}", e);
+                Timer::after(Duration::from_secs(1)).await;
+                continue;
+            }
+        };
+        let conn = match advertiser
+            .accept()
+            .await
+            .and_then(|conn| conn.with_attribute_server(server))
+        {
+            Ok(conn) => conn,
+            Err(e) => {
+                warn!("Failed to accept spectator: {}", e);
+                continue;
+            }
+        };
+        info!("Spectator connected");
+        serve_spectator(server, &conn, state).await;
+    }
+}
+
 #[esp_rtos::main]
 async fn main(spawner: Spawner) {
     let _ = spawner;
@@ -61,60 +167,127 @@ async fn main(spawner: Spawner) {
 
     info!("Welcome to the electronic board game Secret Hitler. This is the fascist board.");
 
-    // Some LEDS may be connected but not used
-    const TOTAL_LEDS: usize = 64;
-    // Index on a 8x8 grid
-    fn i(x: usize, y: usize) -> usize {
-        y * 8 + x
-    }
-    // No particular order to this as of now
-    let aura_leds = [i(0, 0), i(7, 0), i(0, 2), i(7, 2), i(0, 4), i(7, 4)];
-    // Each group of leds represents the LEDs for that policy slot
-    let policy_leds = [
-        [i(1, 1), i(1, 3)],
-        [i(2, 1), i(2, 3)],
-        [i(3, 1), i(3, 3)],
-        [i(4, 1), i(4, 3)],
-        [i(5, 1), i(5, 3)],
-        [i(6, 1), i(6, 3)],
-    ];
-
     let ws2812_gpio = p.GPIO2;
     let i2c_scl_gpio = p.GPIO0;
     let i2c_sda_gpio = p.GPIO1;
 
-    let mut buffer = smart_led_buffer!(buffer_size_async(TOTAL_LEDS));
-    let mut leds_adapter = SmartLedsAdapterAsync::new(
+    let mut buffer = smart_led_buffer!(buffer_size_async(FASCIST_TOTAL_LEDS));
+    let mut leds_adapter = LedWriter::new(SmartLedsAdapterAsync::new(
         Rmt::new(p.RMT, Rate::from_mhz(80))
             .unwrap()
             .into_async()
             .channel0,
         ws2812_gpio,
         &mut buffer,
-    );
-    let mut led_colors = [Default::default(); TOTAL_LEDS];
-
-    // Scaling factor
-    let aura_color = RGB8::new(255, 50, 50);
-    let liberal_color = RGB8::new(255, 0, 0);
-
-    // Turn on Aura LEDs
-    for aura_led_index in aura_leds {
-        led_colors[aura_led_index] = aura_color.scale(LED_BRIGHTNESS);
-    }
+    ));
 
-    // Turn on the policy LEDs
-    for policy in policy_leds {
-        for led_index in policy {
-            led_colors[led_index] = liberal_color.scale(LED_BRIGHTNESS);
+    // Settings are needed for the first LED frame
+    let mut flash = FlashStorage::new(p.FLASH);
+    let mut pt_mem = [0; PARTITION_TABLE_MAX_LEN];
+    let pt = read_partition_table(&mut flash, &mut pt_mem).unwrap();
+    let nvs = pt
+        .find_partition(PartitionType::Data(DataPartitionSubType::Nvs))
+        .unwrap()
+        .unwrap();
+    let nvs_partition = nvs.as_embedded_storage(&mut flash);
+    let map_config = MapConfig::new(0..nvs_partition.partition_size() as u32);
+    let mut map_storage = MapStorage::new(
+        BlockingAsync::new(nvs_partition),
+        map_config,
+        NoCache::new(),
+    );
+    let mut data_buffer = [Default::default(); FASCIST_DATA_BUFFER_LEN];
+    let stored_data = match map_storage
+        .fetch_item::<PostcardValue<FascistStorage>>(&mut data_buffer, &())
+        .await
+    {
+        Ok(stored_data) => stored_data.unwrap_or_default(),
+        Err(e) => {
+            // The stored data could be from an older version with different fields
+            warn!("Failed to load stored data: {}. Using defaults.", e);
+            Default::default()
         }
-    }
-
-    leds_adapter.write(led_colors).await.unwrap();
+    };
+    let settings = Settings::from(stored_data.settings);
+    // The latest LEDs synced from the liberal board
+    let leds_signal = Signal::<CriticalSectionRawMutex, LedsDisplay>::new();
+    // Wakes up the LED loop to show the blink code
+    let display_missing_signal = Signal::<CriticalSectionRawMutex, ()>::new();
+    // The BLE loop changes what the display shows while pairing
+    let screen_signal = Signal::<CriticalSectionRawMutex, FascistScreen>::new();
 
     let address: Address = Address::random(Efuse::mac_address());
 
-    join(
+    join3(
+        async {
+            // Nothing is shown on the policy slots until the liberal board syncs
+            let mut leds = None;
+            let mut last_leds_frame = SkipUnchanged::new();
+            let mut led_animator = LedAnimator::<FASCIST_TOTAL_LEDS>::new(LED_FADE.as_millis());
+            loop {
+                let now_ms = Instant::now().as_millis();
+                let blink_on = (now_ms / AURA_BLINK_INTERVAL.as_millis()).is_multiple_of(2);
+                // The LEDs fade to their new colors, and blinking is shown on top right away
+                led_animator.set_target(
+                    &fascist_leds_frame(leds.as_ref(), settings.led_brightness),
+                    now_ms,
+                );
+                let mut led_colors = led_animator.frame(now_ms);
+                let aura_blinks = fascist_aura_blinks(leds.as_ref());
+                if aura_blinks && !blink_on {
+                    for &aura_led_index in FASCIST_LED_LAYOUT.aura {
+                        led_colors[aura_led_index] = Default::default();
+                    }
+                }
+                // The same warning as on the liberal board, if this board shows the election tracker
+                let election_tracker_warning = leds.as_ref().and_then(|leds| {
+                    let led_index = FASCIST_BOARD_LEDS.election_tracker_warning(leds)?;
+                    Some((led_index, leds.brightness(settings.led_brightness)))
+                });
+                if let Some((led_index, brightness)) = election_tracker_warning
+                    && blink_on
+                {
+                    led_colors[led_index] = correct(ELECTION_TRACKER_COLOR, brightness);
+                }
+                let blink = aura_blinks || election_tracker_warning.is_some();
+                // Without a display, the first aura LED shows that it is missing
+                let display_missing = DISPLAY_MISSING.load(Ordering::Relaxed);
+                if display_missing {
+                    led_colors[FASCIST_LED_LAYOUT.aura[0]] =
+                        if BlinkCode::DisplayMissing.is_on(now_ms) {
+                            correct(AMBER, settings.led_brightness)
+                        } else {
+                            Default::default()
+                        };
+                }
+                if last_leds_frame.changed(&led_colors) {
+                    leds_adapter.write(&led_colors).await;
+                    LEDS_DISABLED.store(leds_adapter.disabled(), Ordering::Relaxed);
+                }
+
+                let fading = !led_animator.is_done(Instant::now().as_millis());
+                let wake_at = [
+                    blink.then(|| Instant::now() + AURA_BLINK_INTERVAL),
+                    fading.then(|| Instant::now() + LED_FADE_FRAME_INTERVAL),
+                    display_missing
+                        .then(|| Instant::now() + Duration::from_millis(BLINK_CODE_STEP_MS)),
+                ]
+                .into_iter()
+                .flatten()
+                .min();
+                let timer = async {
+                    match wake_at {
+                        Some(wake_at) => Timer::at(wake_at).await,
+                        None => pending::<()>().await,
+                    }
+                };
+                if let Either3::First(new_leds) =
+                    select3(leds_signal.wait(), timer, display_missing_signal.wait()).await
+                {
+                    leds = Some(new_leds);
+                }
+            }
+        },
         async {
             // Turn on the OLED display
             let i2c = I2c::new(
@@ -128,41 +301,57 @@ async fn main(spawner: Spawner) {
             let mut display = Ssd1306Async::new(
                 I2CDisplayInterface::new(i2c),
                 DisplaySize128x64,
-                DisplayRotation::Rotate0,
+                ssd1306_rotation(settings.rotate_display),
             )
             .into_buffered_graphics_mode();
-            display.init().await.unwrap();
-            let text_style = MonoTextStyleBuilder::new()
-                .font(&FONT_7X14)
-                .text_color(BinaryColor::On)
-                .build();
-            let mut writer = DrawWriter::new(&mut display, Point::zero(), text_style);
-            write!(writer, "{address}").unwrap();
-            display.flush().await.unwrap();
+            let mut init_retry = DisplayInitRetry::new(DISPLAY_INIT_RETRY_INTERVAL.as_millis());
+            let mut screen = FascistScreen::Address;
             // Invert the display ocassionally to not cause burn-in
-            let mut invert = false;
+            let invert_interval = Duration::from_secs(settings.invert_screen_interval_secs.into());
+            // Rendering stops if the display stops responding, and starts again once it is initialized again
             loop {
-                Timer::after(Duration::from_secs(60)).await;
-                invert = !invert;
-                display.set_invert(invert).await.unwrap();
+                while let Some(next_attempt_at) = init_retry.next_attempt_at() {
+                    Timer::at(Instant::from_millis(next_attempt_at)).await;
+                    try_init_display(&mut display, &mut init_retry).await;
+                    display_missing_signal.signal(());
+                }
+                if !show_fascist_screen(&mut display, screen, address.addr, &mut init_retry).await {
+                    display_missing_signal.signal(());
+                    continue;
+                }
+                let mut invert = false;
+                let mut invert_at = Instant::now() + invert_interval;
+                loop {
+                    match select(Timer::at(invert_at), screen_signal.wait()).await {
+                        Either::First(()) => {
+                            invert = !invert;
+                            // Not worth stopping for, since it is tried again next time
+                            if let Err(e) = display.set_invert(invert).await {
+                                warn!("Failed to invert the display: {}", Debug2Format(&e));
+                            }
+                            invert_at += invert_interval;
+                        }
+                        Either::Second(new_screen) => {
+                            if new_screen != screen {
+                                screen = new_screen;
+                                if !show_fascist_screen(
+                                    &mut display,
+                                    screen,
+                                    address.addr,
+                                    &mut init_retry,
+                                )
+                                .await
+                                {
+                                    display_missing_signal.signal(());
+                                    break;
+                                }
+                            }
+                        }
+                    }
+                }
             }
         },
         async {
-            let mut flash = FlashStorage::new(p.FLASH);
-            let mut pt_mem = [0; PARTITION_TABLE_MAX_LEN];
-            let pt = read_partition_table(&mut flash, &mut pt_mem).unwrap();
-            let nvs = pt
-                .find_partition(PartitionType::Data(DataPartitionSubType::Nvs))
-                .unwrap()
-                .unwrap();
-            let nvs_partition = nvs.as_embedded_storage(&mut flash);
-            let map_config = MapConfig::new(0..nvs_partition.partition_size() as u32);
-            let mut map_storage = MapStorage::new(
-                BlockingAsync::new(nvs_partition),
-                map_config,
-                NoCache::new(),
-            );
-
             let _trng_source = TrngSource::new(p.RNG, p.ADC1);
             let mut trng = Trng::try_new().unwrap();
             let radio = esp_radio::init().unwrap();
@@ -186,16 +375,16 @@ async fn main(spawner: Spawner) {
                 .set_random_generator_seed(&mut trng)
                 .set_io_capabilities(IoCapabilities::DisplayOnly);
 
-            let mut data_buffer = [Default::default(); FASCIST_DATA_BUFFER_LEN];
-            let stored_data = map_storage
-                .fetch_item::<PostcardValue<FascistStorage>>(&mut data_buffer, &())
-                .await
-                .unwrap()
-                .unwrap_or_default();
+            // let bond_counts = prune_bonds(&mut stored_data.saved_bonds);
+            // info!(
+            //     "Restored {} bonds, pruned {}",
+            //     bond_counts.restored, bond_counts.pruned
+            // );
             // for saved_bond_information in stored_data.saved_bonds.iter().cloned() {
-            //     stack
-            //         .add_bond_information(saved_bond_information.into())
-            //         .unwrap();
+            //     // Only valid bonds are left after pruning
+            //     if let Ok(bond_information) = saved_bond_information.try_into() {
+            //         stack.add_bond_information(bond_information).unwrap();
+            //     }
             // }
 
             let Host {
@@ -203,15 +392,10 @@ async fn main(spawner: Spawner) {
                 mut runner,
                 ..
             } = stack.build();
-
-            let mut adv_data = [0; 31];
-            let adv_data_len = AdStructure::encode_slice(
-                &[AdStructure::Flags(
-                    LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED,
-                )],
-                &mut adv_data[..],
-            )
-            .unwrap();
+            let server = SpectatorServer::new_default("SH Game F").unwrap();
+            // Wakes up the spectator connection to notify it
+            let spectator_signal = Signal::<CriticalSectionRawMutex, SpectatorState>::new();
+            server.set_state(SpectatorState::SETUP);
 
             let mut scan_data = [0; 31];
             let scan_data_len = AdStructure::encode_slice(
@@ -223,20 +407,46 @@ async fn main(spawner: Spawner) {
             )
             .unwrap();
 
+            let mut sync = PeripheralSync::new();
+            // The synced LEDs, with the winner on the aura after the game is over
+            let mut board_leds = FascistBoardLeds::new();
+            // Advertised so that a spectator app can see the result without connecting
+            let phase = Cell::new(GamePhase::Setup);
+
             join(runner.run(), async {
+                let mut screen = FascistScreen::Address;
                 loop {
-                    info!("Advertising, waiting for connection...");
-                    let advertiser = peripheral
+                    // The liberal board only connects to this advertisement, so this is updated every time it reconnects
+                    let adv_data = fascist_adv_data(phase.get());
+                    info!("Advertising phase {}, waiting for connection...", phase.get());
+                    let advertiser = match peripheral
                         .advertise(
                             &Default::default(),
                             Advertisement::ConnectableScannableUndirected {
-                                adv_data: &adv_data[..adv_data_len],
+                                adv_data: &adv_data,
                                 scan_data: &scan_data[..scan_data_len],
                             },
                         )
                         .await
-                        .unwrap();
-                    let conn = advertiser.accept().await.unwrap();
+                    {
+                        Ok(advertiser) => advertiser,
+                        Err(e) => {
+                            warn!("Failed to advertise: {}", e);
+                            Timer::after(Duration::from_secs(1)).await;
+                            continue;
+                        }
+                    };
+                    let conn = match advertiser
+                        .accept()
+                        .await
+                        .and_then(|conn| conn.with_attribute_server(&server))
+                    {
+                        Ok(conn) => conn,
+                        Err(e) => {
+                            warn!("Failed to accept connection: {}", e);
+                            continue;
+                        }
+                    };
                     info!("Connection established");
 
                     if SAVE_BOND_INFO {
@@ -244,39 +454,128 @@ async fn main(spawner: Spawner) {
                     }
 
                     let config = L2capChannelConfig {
-                        mtu: Some(PAYLOAD_LEN as u16),
+                        mtu: Some(SYNC_MTU as u16),
                         ..Default::default()
                     };
-                    let mut ch1 =
-                        L2capChannel::accept(&stack, &conn, &[PSM_L2CAP_EXAMPLES], &config)
-                            .await
-                            .unwrap();
+                    // Dropping the connection after an error disconnects, and then we advertise again.
+                    // Spectators read the GATT service while we wait, but they don't open the channel.
+                    let accept = select(
+                        with_timeout(
+                            L2CAP_ACCEPT_TIMEOUT,
+                            L2capChannel::accept(&stack, conn.raw(), &[PSM_L2CAP_EXAMPLES], &config),
+                        ),
+                        serve_gatt(&conn),
+                    )
+                    .await;
+                    let mut ch1 = match accept {
+                        Either::First(Ok(Ok(ch1))) => ch1,
+                        Either::First(Ok(Err(e))) => {
+                            warn!("Failed to accept L2CAP channel: {}", e);
+                            continue;
+                        }
+                        Either::First(Err(TimeoutError)) => {
+                            info!("No L2CAP channel opened, disconnecting so that the liberal board can connect");
+                            continue;
+                        }
+                        Either::Second(()) => {
+                            info!("Disconnected before opening an L2CAP channel");
+                            continue;
+                        }
+                    };
 
                     info!("L2CAP channel accepted");
-
-                    // Size of payload we're expecting
-                    const PAYLOAD_LEN: usize = 27;
-                    let mut rx = [0; PAYLOAD_LEN];
-                    for i in 0..10 {
-                        let len = ch1.receive(&stack, &mut rx).await.unwrap();
-                        assert_eq!(len, rx.len());
-                        assert_eq!(rx, [i; PAYLOAD_LEN]);
-                    }
-
-                    info!("L2CAP data received, echoing");
-                    Timer::after(Duration::from_secs(1)).await;
-                    for i in 0..10 {
-                        let tx = [i; PAYLOAD_LEN];
-                        ch1.send(&stack, &tx).await.unwrap();
-                    }
-                    info!("L2CAP data echoed");
-
+                    let mut rx = [0; SYNC_MTU];
+                    sync.connected();
+                    let mut channel_open = true;
+                    let mut spectators = pin!(serve_spectators(
+                        &mut peripheral,
+                        &server,
+                        &scan_data[..scan_data_len],
+                        &phase,
+                        &spectator_signal,
+                    ));
                     loop {
-                        if let ConnectionEvent::Disconnected { reason } = conn.next().await {
-                            info!("Disconnected. reason: {}", reason);
-                            break;
+                        let event = select4(
+                            conn.next(),
+                            async {
+                                if channel_open {
+                                    ch1.receive(&stack, &mut rx).await
+                                } else {
+                                    pending().await
+                                }
+                            },
+                            Timer::after_millis(SYNC_RESEND_MS),
+                            &mut spectators,
+                        )
+                        .await;
+                        match event {
+                            Either4::First(GattConnectionEvent::Disconnected { reason }) => {
+                                info!("Disconnected. reason: {}", reason);
+                                break;
+                            }
+                            Either4::First(GattConnectionEvent::Gatt { event }) => {
+                                reply_gatt(event).await;
+                            }
+                            // The passkey and pairing events need trouble-host's `security` feature.
+                            // Once it's enabled, they update `screen` with the matching PairingEvent.
+                            Either4::First(_) => {}
+                            Either4::Second(Ok(len)) => {
+                                match SyncMessage::<LedsDisplay>::decode(&rx[..len]) {
+                                    Ok(message) => {
+                                        let now = Instant::now().as_millis();
+                                        if let Some(leds) = sync.receive(message, now) {
+                                            phase.set(leds.phase);
+                                            let state = SpectatorState::from_leds(&leds);
+                                            server.set_state(state);
+                                            spectator_signal.signal(state);
+                                            board_leds.synced(leds);
+                                        }
+                                        CENTRAL_CLOCK_OFFSET_MS.lock(|offset| {
+                                            offset.set(sync.central_time(now) as i64 - now as i64)
+                                        });
+                                        CLOCK_SYNC.lock(|clock_sync| clock_sync.set(sync.clock_sync()));
+                                        if let Some(command) = sync.take_command() {
+                                            info!("Received {} from the liberal board", command);
+                                            if board_leds.command(command) {
+                                                sync.ack_command();
+                                            } else {
+                                                shutdown_peripheral(
+                                                    &mut NothingToFlush,
+                                                    &mut SyncChannelAck {
+                                                        sync: &mut sync,
+                                                        channel: &mut ch1,
+                                                        stack: &stack,
+                                                    },
+                                                    command,
+                                                    |_| {
+                                                        info!("Resetting");
+                                                        software_reset()
+                                                    },
+                                                )
+                                                .await;
+                                            }
+                                        }
+                                        if let Some(leds) = board_leds.leds() {
+                                            leds_signal.signal(leds);
+                                        }
+                                    }
+                                    Err(_) => warn!("Received invalid sync message"),
+                                }
+                            }
+                            Either4::Second(Err(e)) => {
+                                // Just wait for the disconnect instead of receiving errors in a loop
+                                warn!("L2CAP receive error: {}", e);
+                                channel_open = false;
+                                sync.disconnected();
+                            }
+                            Either4::Third(()) | Either4::Fourth(()) => {}
                         }
+                        transmit(&mut sync, &mut ch1, &stack).await;
                     }
+                    sync.disconnected();
+                    // A passkey that was being shown is no longer needed
+                    screen = screen.after(PairingEvent::Disconnected);
+                    screen_signal.signal(screen);
                 }
             })
             .await;
@@ -1,38 +1,158 @@
-use core::future::pending;
+use core::{array, cell::RefCell, future::pending, mem};
 
-use bt_hci::{
-    controller::{Controller, ExternalController},
-    param::AddrKind,
-};
+use bt_hci::controller::Controller;
+#[cfg(feature = "esp")]
+use bt_hci::controller::ExternalController;
 use defmt::{info, warn};
 use embassy_futures::{
-    join::join,
-    select::{Either, select},
+    join::{join, join_array},
+    select::{Either, Either3, select, select3},
 };
 use embassy_sync::{
-    blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel, signal::Signal,
+    blocking_mutex::{
+        self,
+        raw::{CriticalSectionRawMutex, NoopRawMutex, RawMutex},
+    },
+    channel::Channel,
+    mutex::Mutex,
+    signal::Signal,
 };
-use embassy_time::Duration;
+use embassy_time::{Duration, Instant, Timer, WithTimeout};
+#[cfg(feature = "esp")]
 use esp_hal::{efuse::Efuse, peripherals::BT};
-use esp_radio::ble::controller::{BleConnector, BleConnectorError};
-use game_pure::ConnectState;
+#[cfg(feature = "esp")]
+use esp_radio::ble::controller::BleConnector;
+use game_pure::{
+    CommandTransport, ConnectState, LedsDisplay, ScanPreset,
+    sync::{
+        BoardCommand, CentralSync, FascistBoardCards, SYNC_MTU, SYNC_RESEND_MS, SyncMessage,
+        SyncStatus,
+    },
+};
+use rand_core::RngCore;
 use trouble_host::{
-    Address, BleHostError, Host, HostResources, IoCapabilities, PacketPool,
+    Address, Host, HostResources, IoCapabilities, PacketPool, Stack,
     l2cap::{L2capChannel, L2capChannelConfig},
-    prelude::{Central, ConnectConfig, DefaultPacketPool, PhySet, ScanConfig},
+    prelude::{
+        Central, ConnectConfig, Connection, ConnectionEvent, DefaultPacketPool, PhySet, ScanConfig,
+    },
     scan::Scanner,
 };
 
 use crate::{
-    CONNECTIONS_MAX, L2CAP_CHANNELS_MAX, PSM_L2CAP_EXAMPLES, ScanChannel, ScanningEventHandler,
+    BleController, CLOCK_SYNC, CONNECTIONS_MAX, Entropy, Error, L2CAP_CHANNELS_MAX, OnDrop,
+    PSM_L2CAP_EXAMPLES, ScanChannel, ScanningEventHandler, config::scan_params,
 };
 
 #[derive(Debug, Default, PartialEq)]
 enum Command {
     #[default]
     Off,
-    Scan,
-    MaintainConnection(Address),
+    /// A different preset restarts the scan session with the new parameters
+    Scan(ScanPreset),
+    /// The addresses to connect to, and the one that the sync protocol runs with
+    MaintainConnections(heapless::Vec<Address, CONNECTIONS_MAX>, Option<Address>),
+}
+
+/// Enough for a connect and disconnect of each peripheral, so that none are lost before [`Ble2Api::next`] is polled
+const CONNECTION_UPDATES_LEN: usize = 4;
+
+/// Connection updates in the order that they happened.
+/// If it is full, the connection that changed waits until there is room, so no updates are lost.
+type ConnectionChannel<M> = Channel<M, (Address, ConnectState), CONNECTION_UPDATES_LEN>;
+
+/// How long we try to connect to one peripheral before letting the other peripherals try to connect
+const CONNECT_ATTEMPT_TIMEOUT: Duration = Duration::from_secs(2);
+/// After a failed connection attempt, we wait a random amount of time up to this before trying again.
+/// Otherwise, the peripheral that just failed could get to try again before the other peripherals.
+const CONNECT_RETRY_JITTER: Duration = Duration::from_millis(200);
+
+/// Returns `None` if it took too long to connect
+async fn connect<'stack, C: BleController, P: PacketPool>(
+    central: &mut CentralOrScanner<'stack, C, P>,
+    address: Address,
+) -> Option<Connection<'stack, P>> {
+    let result = central
+        .central()
+        .connect(&ConnectConfig {
+            connect_params: Default::default(),
+            scan_config: ScanConfig {
+                filter_accept_list: &[(address.kind, &address.addr)],
+                ..Default::default()
+            },
+        })
+        .with_timeout(CONNECT_ATTEMPT_TIMEOUT)
+        .await
+        .ok()?;
+    match result {
+        Ok(connection) => Some(connection),
+        Err(e) => {
+            warn!("BLE error: {}", e);
+            None
+        }
+    }
+}
+
+/// Returns once disconnected, or with an error if the L2CAP channel failed while still connected.
+/// With `runs_sync`, this opens the L2CAP channel and runs the sync protocol with the fascist board.
+async fn maintain_connection<'stack, C: Controller, P: PacketPool>(
+    stack: &'stack Stack<'stack, C, P>,
+    connection: &Connection<'_, P>,
+    ble: &Ble2,
+    runs_sync: bool,
+) -> Result<(), Error> {
+    let mut channel = if runs_sync {
+        info!("Connected, creating l2cap channel");
+        let config = L2capChannelConfig {
+            mtu: Some(SYNC_MTU as u16),
+            ..Default::default()
+        };
+        let channel = L2capChannel::create(stack, connection, PSM_L2CAP_EXAMPLES, &config).await?;
+        info!("L2CAP channel created, syncing with the fascist board");
+        Some(channel)
+    } else {
+        None
+    };
+    if runs_sync {
+        ble.sync.lock(|sync| sync.borrow_mut().connected());
+    }
+    let _sync_disconnected = OnDrop::new(|| {
+        if runs_sync {
+            ble.sync.lock(|sync| sync.borrow_mut().disconnected());
+        }
+    });
+    let mut rx = [0; SYNC_MTU];
+    loop {
+        if let Some(channel) = &mut channel {
+            ble.transmit_sync(stack, channel).await?;
+        }
+        let event = select3(
+            connection.next(),
+            async {
+                match &mut channel {
+                    Some(channel) => channel.receive(stack, &mut rx).await,
+                    None => pending().await,
+                }
+            },
+            async {
+                if runs_sync {
+                    // Messages that weren't acked are resent
+                    select(Timer::after_millis(SYNC_RESEND_MS), ble.sync_wake.wait()).await;
+                } else {
+                    pending().await
+                }
+            },
+        )
+        .await;
+        match event {
+            Either3::First(ConnectionEvent::Disconnected { reason }) => {
+                info!("Disconnected. reason: {}", reason);
+                return Ok(());
+            }
+            Either3::First(_) | Either3::Third(()) => {}
+            Either3::Second(len) => ble.receive_sync(&rx[..len?]),
+        }
+    }
 }
 
 enum CentralOrScanner<'stack, C: Controller, P: PacketPool> {
@@ -40,6 +160,8 @@ enum CentralOrScanner<'stack, C: Controller, P: PacketPool> {
     Scanner(Option<Scanner<'stack, C, P>>),
 }
 
+// The Option is only taken while switching between the central and the scanner
+#[allow(clippy::unwrap_used)]
 impl<'stack, C: Controller, P: PacketPool> CentralOrScanner<'stack, C, P> {
     pub fn new(central: Central<'stack, C, P>) -> Self {
         Self::Central(Some(central))
@@ -69,7 +191,15 @@ impl<'stack, C: Controller, P: PacketPool> CentralOrScanner<'stack, C, P> {
 pub struct Ble2 {
     command_signal: Signal<CriticalSectionRawMutex, Command>,
     scan_channel: ScanChannel,
-    connection_signal: Signal<CriticalSectionRawMutex, ConnectState>,
+    connection_channel: ConnectionChannel<CriticalSectionRawMutex>,
+    /// Our end of the sync protocol, which keeps its state across reconnecting
+    sync: blocking_mutex::Mutex<CriticalSectionRawMutex, RefCell<CentralSync>>,
+    /// Wakes the fascist board's connection when there is something new to send
+    sync_wake: Signal<CriticalSectionRawMutex, ()>,
+    /// The fascist board acked the command from [`Ble2Api::send_command`]
+    command_acked: Signal<CriticalSectionRawMutex, ()>,
+    /// The latest cards that the fascist board sent. Older cards don't matter once newer ones arrived.
+    fascist_cards: Signal<CriticalSectionRawMutex, FascistBoardCards>,
 }
 
 impl Ble2 {
@@ -77,21 +207,69 @@ impl Ble2 {
         Self {
             command_signal: Signal::new(),
             scan_channel: Channel::new(),
-            connection_signal: Signal::new(),
+            connection_channel: Channel::new(),
+            sync: blocking_mutex::Mutex::new(RefCell::new({
+                let mut sync = CentralSync::new();
+                // Both boards log with our clock
+                sync.send_time();
+                sync
+            })),
+            sync_wake: Signal::new(),
+            command_acked: Signal::new(),
+            fascist_cards: Signal::new(),
+        }
+    }
+
+    /// Sends everything that the sync protocol has to send
+    async fn transmit_sync<C: Controller, P: PacketPool>(
+        &self,
+        stack: &Stack<'_, C, P>,
+        channel: &mut L2capChannel<'_, P>,
+    ) -> Result<(), Error> {
+        while let Some(message) = self
+            .sync
+            .lock(|sync| sync.borrow_mut().poll_transmit(Instant::now().as_millis()))
+        {
+            let Ok(frame) = message.encode() else {
+                warn!("Sync message doesn't fit in the MTU");
+                continue;
+            };
+            channel.send(stack, &frame).await?;
         }
+        Ok(())
     }
 
-    pub fn run(
+    fn receive_sync(&self, bytes: &[u8]) {
+        let Ok(message) = SyncMessage::<FascistBoardCards>::decode(bytes) else {
+            warn!("Received invalid sync message");
+            return;
+        };
+        let (cards, command_acked, clock_sync) = self.sync.lock(|sync| {
+            let mut sync = sync.borrow_mut();
+            let cards = sync.receive(message, Instant::now().as_millis());
+            (cards, sync.is_command_acked(), sync.clock_sync())
+        });
+        CLOCK_SYNC.lock(|cell| cell.set(clock_sync));
+        if command_acked {
+            self.command_acked.signal(());
+        }
+        if let Some(cards) = cards {
+            self.fascist_cards.signal(cards);
+        }
+    }
+
+    /// The BLE controller can be anything that trouble-host supports.
+    /// `entropy` is used to add jitter to connection retries.
+    pub fn run<C: BleController>(
         &mut self,
-        controller: &esp_radio::Controller,
-        bt: BT,
+        controller: C,
+        our_address: Address,
+        entropy: Entropy<impl RngCore>,
     ) -> (impl Future<Output = ()>, Ble2Api<'_>) {
         let ble = &*self;
         (
             async move {
-                let connector = BleConnector::new(&controller, bt, Default::default()).unwrap();
-                let controller = ExternalController::<_, 20>::new(connector);
-                let our_address = Address::random(Efuse::mac_address());
+                let entropy = blocking_mutex::Mutex::<NoopRawMutex, _>::new(RefCell::new(entropy));
                 let mut resources =
                     HostResources::<DefaultPacketPool, CONNECTIONS_MAX, L2CAP_CHANNELS_MAX>::new();
                 let stack = trouble_host::new(controller, &mut resources)
@@ -116,19 +294,20 @@ impl Ble2 {
                             }
                         },
                         async {
-                            match command {
+                            match &command {
                                 Command::Off => {
                                     info!("stopped running BLE");
                                     pending::<()>().await;
                                 }
-                                Command::Scan => {
+                                Command::Scan(preset) => {
+                                    let params = scan_params(*preset);
                                     join(
                                         async {
                                             loop {
                                                 if let Err(e) = runner
-                                                    .run_with_handler(&ScanningEventHandler {
-                                                        channel: &ble.scan_channel,
-                                                    })
+                                                    .run_with_handler(&ScanningEventHandler::new(
+                                                        &ble.scan_channel,
+                                                    ))
                                                     .await
                                                 {
                                                     warn!("BLE error: {}", e);
@@ -140,10 +319,10 @@ impl Ble2 {
                                                 match central
                                                     .scanner()
                                                     .scan(&ScanConfig {
-                                                        active: true,
+                                                        active: params.active,
                                                         phys: PhySet::M1,
-                                                        interval: Duration::from_secs(1),
-                                                        window: Duration::from_secs(1),
+                                                        interval: params.interval,
+                                                        window: params.window,
                                                         ..Default::default()
                                                     })
                                                     .await
@@ -159,7 +338,11 @@ impl Ble2 {
                                     )
                                     .await;
                                 }
-                                Command::MaintainConnection(address) => {
+                                Command::MaintainConnections(addresses, sync_address) => {
+                                    // Only one connection can be created at a time,
+                                    // but once connected, each connection is maintained independently.
+                                    let central = Mutex::<NoopRawMutex, _>::new(&mut central);
+                                    let stack = &stack;
                                     join(
                                         async {
                                             loop {
@@ -168,60 +351,64 @@ impl Ble2 {
                                                 }
                                             }
                                         },
-                                        async {
-                                            let connection = loop {
-                                                match central
-                                                    .central()
-                                                    .connect(&ConnectConfig {
-                                                        connect_params: Default::default(),
-                                                        scan_config: ScanConfig {
-                                                            filter_accept_list: &[(
-                                                                AddrKind::RANDOM,
-                                                                &address.addr,
-                                                            )],
-                                                            ..Default::default()
-                                                        },
-                                                    })
+                                        join_array(array::from_fn::<_, CONNECTIONS_MAX, _>(|i| {
+                                            let address = addresses.get(i).copied();
+                                            let runs_sync =
+                                                address.is_some() && address == *sync_address;
+                                            let central = &central;
+                                            let entropy = &entropy;
+                                            async move {
+                                                let Some(address) = address else {
+                                                    return pending::<()>().await;
+                                                };
+                                                loop {
+                                                    let connection =
+                                                        connect(&mut **central.lock().await, address)
+                                                            .await;
+                                                    let Some(connection) = connection else {
+                                                        // Let the other peripherals try to connect
+                                                        Timer::after(entropy.lock(|entropy| {
+                                                            entropy
+                                                                .borrow_mut()
+                                                                .jitter(CONNECT_RETRY_JITTER)
+                                                        }))
+                                                        .await;
+                                                        continue;
+                                                    };
+                                                    ble.connection_channel
+                                                        .send((address, ConnectState::Connected))
+                                                        .await;
+                                                    // If we stop maintaining this connection (for example, to scan),
+                                                    // disconnect gracefully so the peripheral knows we are gone.
+                                                    // The runner will send the disconnect command.
+                                                    let disconnect_on_drop =
+                                                        OnDrop::new(|| connection.disconnect());
+                                                    match maintain_connection(
+                                                        stack,
+                                                        &connection,
+                                                        ble,
+                                                        runs_sync,
+                                                    )
                                                     .await
-                                                {
-                                                    Ok(connection) => break connection,
-                                                    Err(e) => {
-                                                        warn!("BLE error: {}", e);
+                                                    {
+                                                        Ok(()) => {
+                                                            // Already disconnected
+                                                            mem::forget(disconnect_on_drop);
+                                                        }
+                                                        Err(e) => {
+                                                            warn!(
+                                                                "Disconnecting from {} because of an error: {}",
+                                                                address, e
+                                                            );
+                                                            drop(disconnect_on_drop);
+                                                        }
                                                     }
+                                                    ble.connection_channel
+                                                        .send((address, ConnectState::Connecting))
+                                                        .await;
                                                 }
-                                            };
-                                            ble.connection_signal.signal(ConnectState::Connected);
-                                            info!("Connected, creating l2cap channel");
-                                            const PAYLOAD_LEN: usize = 27;
-                                            let config = L2capChannelConfig {
-                                                mtu: Some(PAYLOAD_LEN as u16),
-                                                ..Default::default()
-                                            };
-                                            let mut ch1 = L2capChannel::create(
-                                                &stack,
-                                                &connection,
-                                                PSM_L2CAP_EXAMPLES,
-                                                &config,
-                                            )
-                                            .await
-                                            .unwrap();
-                                            info!("New l2cap channel created, sending some data!");
-                                            for i in 0..10 {
-                                                let tx = [i; PAYLOAD_LEN];
-                                                ch1.send(&stack, &tx).await.unwrap();
-                                            }
-                                            info!("Sent data, waiting for them to be sent back");
-                                            let mut rx = [0; PAYLOAD_LEN];
-                                            for i in 0..10 {
-                                                let len =
-                                                    ch1.receive(&stack, &mut rx).await.unwrap();
-                                                assert_eq!(len, rx.len());
-                                                assert_eq!(rx, [i; PAYLOAD_LEN]);
                                             }
-
-                                            info!("Received successfully!");
-                                            core::future::pending::<()>().await;
-                                        },
+                                        })),
                                     )
                                     .await;
                                 }
@@ -242,9 +429,41 @@ impl Ble2 {
     }
 }
 
+#[cfg(feature = "esp")]
+impl Ble2 {
+    /// Runs with the ESP's built-in BLE controller.
+    /// `rng` should be the ESP's `Trng`.
+    /// Returns `None` if the BLE controller couldn't be initialized.
+    pub fn run_esp<'a>(
+        &'a mut self,
+        controller: &'a esp_radio::Controller,
+        bt: BT<'a>,
+        rng: impl RngCore + 'a,
+    ) -> Option<(impl Future<Output = ()> + 'a, Ble2Api<'a>)> {
+        let connector = match BleConnector::new(controller, bt, Default::default()) {
+            Ok(connector) => connector,
+            Err(e) => {
+                warn!(
+                    "Failed to create the BLE connector: {}",
+                    defmt::Debug2Format(&e)
+                );
+                return None;
+            }
+        };
+        Some(self.run(
+            ExternalController::<_, 20>::new(connector),
+            Address::random(Efuse::mac_address()),
+            Entropy::new(rng),
+        ))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum BleEvent {
     PeripheralScanned(Address),
-    ConnectionUpdate(ConnectState),
+    ConnectionUpdate(Address, ConnectState),
+    /// The fascist board's cards changed
+    FascistCards(FascistBoardCards),
 }
 
 pub struct Ble2Api<'a> {
@@ -256,30 +475,256 @@ impl Ble2Api<'_> {
         self.ble.command_signal.signal(Command::Off);
     }
 
-    pub fn scan(&mut self) {
-        self.ble.command_signal.signal(Command::Scan);
+    pub fn scan(&mut self, preset: ScanPreset) {
+        self.ble.command_signal.signal(Command::Scan(preset));
     }
 
     pub async fn next_scanned_address(&mut self) -> Address {
         self.ble.scan_channel.receive().await
     }
 
-    pub fn maintain_connection(&mut self, address: Address) {
+    /// The sync protocol runs with `fascist_board`, which should be one of `addresses`
+    pub fn maintain_connections(
+        &mut self,
+        addresses: heapless::Vec<Address, CONNECTIONS_MAX>,
+        fascist_board: Option<Address>,
+    ) {
         self.ble
             .command_signal
-            .signal(Command::MaintainConnection(address));
+            .signal(Command::MaintainConnections(addresses, fascist_board));
+    }
+
+    /// The LEDs that the fascist board should show, from [`game_pure::GameState::take_sync`].
+    /// They are sent once the fascist board is connected, and again after it reconnects.
+    pub fn sync(&mut self, leds: LedsDisplay) {
+        self.ble
+            .sync
+            .lock(|sync| sync.borrow_mut().set_outgoing(leds));
+        self.ble.sync_wake.signal(());
+    }
+
+    /// Sent once the fascist board is connected, and re-sent until it acks it, see [`game_pure::sync::SyncEngine::send_command`]
+    pub fn send_command(&mut self, command: BoardCommand) {
+        self.ble
+            .sync
+            .lock(|sync| sync.borrow_mut().send_command(command));
+        self.ble.command_acked.reset();
+        self.ble.sync_wake.signal(());
     }
 
+    /// Connection updates come in the order that they happened, and so do scanned addresses,
+    /// but a scanned address can come before a connection update that happened before it
     pub async fn next(&mut self) -> BleEvent {
-        use embassy_futures::select::{Either::*, *};
-        match select(
-            self.ble.scan_channel.receive(),
-            self.ble.connection_signal.wait(),
+        next_event(
+            &self.ble.scan_channel,
+            &self.ble.connection_channel,
+            &self.ble.fascist_cards,
         )
         .await
-        {
-            First(address) => BleEvent::PeripheralScanned(address),
-            Second(state) => BleEvent::ConnectionUpdate(state),
+    }
+}
+
+/// For [`game_pure::shutdown_central`]
+impl CommandTransport for Ble2Api<'_> {
+    /// Only sent if the sync channel to the fascist board is open
+    async fn send_command(&mut self, command: BoardCommand) -> bool {
+        let status = self.ble.sync.lock(|sync| sync.borrow().status());
+        match status {
+            SyncStatus::Handshaking | SyncStatus::Ready => {
+                Ble2Api::send_command(self, command);
+                true
+            }
+            SyncStatus::Disconnected | SyncStatus::VersionMismatch { .. } => false,
         }
     }
+
+    async fn wait_for_ack(&mut self, timeout_ms: u64) -> bool {
+        async {
+            while !self.ble.sync.lock(|sync| sync.borrow().is_command_acked()) {
+                self.ble.command_acked.wait().await;
+            }
+        }
+        .with_timeout(Duration::from_millis(timeout_ms))
+        .await
+        .is_ok()
+    }
+}
+
+async fn next_event<M: RawMutex>(
+    scan_channel: &Channel<M, Address, 1>,
+    connection_channel: &ConnectionChannel<M>,
+    fascist_cards: &Signal<M, FascistBoardCards>,
+) -> BleEvent {
+    match select3(
+        scan_channel.receive(),
+        connection_channel.receive(),
+        fascist_cards.wait(),
+    )
+    .await
+    {
+        Either3::First(address) => BleEvent::PeripheralScanned(address),
+        Either3::Second((address, state)) => BleEvent::ConnectionUpdate(address, state),
+        Either3::Third(cards) => BleEvent::FascistCards(cards),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use embassy_futures::{
+        block_on, poll_once,
+        select::{Either, select},
+    };
+    use game_pure::{PolicyCardId, Team};
+    use trouble_host::prelude::{AddrKind, BdAddr};
+
+    use super::*;
+    use crate::{Pcg32, fake_controller::FakeController};
+
+    fn address(byte: u8) -> Address {
+        Address {
+            kind: AddrKind::RANDOM,
+            addr: BdAddr::new([byte; 6]),
+        }
+    }
+
+    #[test]
+    fn connect_and_disconnect() {
+        let scan_channel = Channel::<NoopRawMutex, _, 1>::new();
+        let connection_channel = ConnectionChannel::<NoopRawMutex>::new();
+        let fascist_cards = Signal::<NoopRawMutex, _>::new();
+        // Connected and disconnected again before the events are read
+        for state in [ConnectState::Connected, ConnectState::Connecting] {
+            connection_channel.try_send((address(1), state)).unwrap();
+        }
+        connection_channel
+            .try_send((address(2), ConnectState::Connected))
+            .unwrap();
+        scan_channel.try_send(address(3)).unwrap();
+        let events = (0..4)
+            .map(|_| {
+                block_on(next_event(
+                    &scan_channel,
+                    &connection_channel,
+                    &fascist_cards,
+                ))
+            })
+            .collect::<heapless::Vec<_, 4>>();
+        let connection_updates = events
+            .iter()
+            .filter(|event| matches!(event, BleEvent::ConnectionUpdate(..)))
+            .cloned()
+            .collect::<heapless::Vec<_, 4>>();
+        assert_eq!(
+            connection_updates,
+            [
+                BleEvent::ConnectionUpdate(address(1), ConnectState::Connected),
+                BleEvent::ConnectionUpdate(address(1), ConnectState::Connecting),
+                BleEvent::ConnectionUpdate(address(2), ConnectState::Connected),
+            ]
+        );
+        assert!(events.contains(&BleEvent::PeripheralScanned(address(3))));
+        assert!(
+            poll_once(pin!(next_event(
+                &scan_channel,
+                &connection_channel,
+                &fascist_cards
+            )))
+            .is_pending()
+        );
+    }
+
+    #[test]
+    fn newest_fascist_cards() {
+        let scan_channel = Channel::<NoopRawMutex, _, 1>::new();
+        let connection_channel = ConnectionChannel::<NoopRawMutex>::new();
+        let fascist_cards = Signal::<NoopRawMutex, _>::new();
+        let card = |id| PolicyCardId {
+            team: Team::Fascist,
+            id,
+        };
+        fascist_cards.signal([card(1)].into_iter().collect());
+        // Sent again before the first cards were read, so only the newest cards matter
+        let newest = [card(1), card(2)]
+            .into_iter()
+            .collect::<FascistBoardCards>();
+        fascist_cards.signal(newest.clone());
+        assert_eq!(
+            block_on(next_event(
+                &scan_channel,
+                &connection_channel,
+                &fascist_cards
+            )),
+            BleEvent::FascistCards(newest)
+        );
+        assert!(
+            poll_once(pin!(next_event(
+                &scan_channel,
+                &connection_channel,
+                &fascist_cards
+            )))
+            .is_pending()
+        );
+    }
+
+    #[test]
+    fn full() {
+        let connection_channel = ConnectionChannel::<NoopRawMutex>::new();
+        for _ in 0..CONNECTION_UPDATES_LEN {
+            connection_channel
+                .try_send((address(1), ConnectState::Connected))
+                .unwrap();
+        }
+        // The connection waits instead of losing the update
+        let mut send = pin!(connection_channel.send((address(1), ConnectState::Connecting)));
+        assert!(poll_once(send.as_mut()).is_pending());
+        block_on(connection_channel.receive());
+        assert!(poll_once(send.as_mut()).is_ready());
+    }
+
+    /// The delays between connection retries with a seeded RNG, so that a change in how they are chosen is noticed
+    #[test]
+    fn retry_schedule() {
+        let mut entropy = Entropy::new(Pcg32::new(0));
+        let schedule: [_; 5] = array::from_fn(|_| entropy.jitter(CONNECT_RETRY_JITTER).as_millis());
+        assert_eq!(schedule, [118, 188, 70, 196, 176]);
+        assert!(
+            schedule
+                .iter()
+                .all(|&delay| delay <= CONNECT_RETRY_JITTER.as_millis())
+        );
+    }
+
+    /// Drives [`Ble2::run`] with a controller that has one peripheral advertising our service
+    #[test]
+    fn off_scan_and_connect() {
+        let peripheral = address(1);
+        let peripherals = [peripheral];
+        let mut ble = Ble2::new();
+        let (run, mut api) = ble.run(
+            FakeController::new(&peripherals),
+            address(0),
+            Entropy::new(Pcg32::new(0)),
+        );
+        let mut run = pin!(run);
+        // Off, so nothing is scanned
+        assert!(poll_once(run.as_mut()).is_pending());
+        assert!(poll_once(pin!(api.next())).is_pending());
+
+        api.scan(ScanPreset::Aggressive);
+        let Either::Second(event) = block_on(select(run.as_mut(), api.next())) else {
+            panic!("Ble2 stopped running");
+        };
+        assert_eq!(event, BleEvent::PeripheralScanned(peripheral));
+
+        api.maintain_connections([peripheral].into_iter().collect(), None);
+        let Either::Second(event) = block_on(select(run.as_mut(), api.next())) else {
+            panic!("Ble2 stopped running");
+        };
+        assert_eq!(
+            event,
+            BleEvent::ConnectionUpdate(peripheral, ConnectState::Connected)
+        );
+    }
 }
@@ -0,0 +1,14 @@
+use bt_hci::{cmd::le::LeSetScanParams, controller::ControllerCmdSync};
+
+/// A trait alias for all of the HCI commands that [`Ble2`](crate::ble_2::Ble2) uses,
+/// so that trouble-host's bounds don't need to be repeated everywhere.
+/// It is implemented for every controller that supports these commands and whose errors can be logged.
+pub trait BleController:
+    trouble_host::Controller<Error: defmt::Format> + ControllerCmdSync<LeSetScanParams>
+{
+}
+
+impl<C> BleController for C where
+    C: trouble_host::Controller<Error: defmt::Format> + ControllerCmdSync<LeSetScanParams>
+{
+}
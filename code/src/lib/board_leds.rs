@@ -0,0 +1,125 @@
+use common::correct;
+use game_pure::{LedsDisplay, Team};
+use smart_leds::RGB8;
+
+use crate::config::LedLayout;
+
+pub const ELECTION_TRACKER_COLOR: RGB8 = RGB8::new(0, 255, 0);
+
+/// A board's [`LedLayout`] and which board it is, since some LEDs are shown on either board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BoardLeds {
+    pub layout: LedLayout,
+    pub board: Team,
+}
+
+impl BoardLeds {
+    /// The election tracker LEDs to light up.
+    /// Empty if [`LedsDisplay::election_tracker_placement`] is the other board, or this board's PCB doesn't have them.
+    pub fn election_tracker(&self, leds: &LedsDisplay) -> &[usize] {
+        let tracker = self.layout.election_tracker_leds();
+        &tracker[..leds.election_tracker_leds_on(self.board).min(tracker.len())]
+    }
+
+    /// The last election tracker LED, which blinks to warn that the next failed election causes chaos
+    pub fn election_tracker_warning(&self, leds: &LedsDisplay) -> Option<usize> {
+        let tracker = self.layout.election_tracker?;
+        leds.election_tracker_warning_on(self.board)
+            .then(|| tracker[tracker.len() - 1])
+    }
+
+    /// Lights up [`BoardLeds::election_tracker`]
+    pub fn draw_election_tracker(&self, leds: &LedsDisplay, frame: &mut [RGB8], brightness: u8) {
+        for &led_index in self.election_tracker(leds) {
+            frame[led_index] = correct(ELECTION_TRACKER_COLOR, brightness);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game_pure::{AuraLedColor, ElectionTrackerPlacement, sync::GamePhase};
+
+    use super::*;
+
+    const LIBERAL: BoardLeds = BoardLeds {
+        layout: LedLayout::liberal_v1(),
+        board: Team::Liberal,
+    };
+    const FASCIST: BoardLeds = BoardLeds {
+        layout: LedLayout::fascist_v2(),
+        board: Team::Fascist,
+    };
+
+    fn leds(election_tracker_placement: ElectionTrackerPlacement) -> LedsDisplay {
+        LedsDisplay {
+            aura_led_color: AuraLedColor::BoardSpecific,
+            liberal_policy_leds: 0,
+            fascist_policy_leds: 0,
+            election_tracker_leds: 2,
+            election_tracker_warning: true,
+            blink_aura: false,
+            misplaced_board: None,
+            dimmed: false,
+            election_tracker_placement,
+            phase: GamePhase::Playing,
+        }
+    }
+
+    /// The LEDs that are lit up in a frame of `board`
+    fn lit(board: BoardLeds, leds: &LedsDisplay) -> heapless::Vec<usize, 64> {
+        let mut frame = [RGB8::default(); 64];
+        board.draw_election_tracker(leds, &mut frame, 255);
+        frame
+            .iter()
+            .enumerate()
+            .filter(|(_, color)| **color != RGB8::default())
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn on_liberal() {
+        let leds = leds(ElectionTrackerPlacement::Liberal);
+        let tracker = LIBERAL.layout.election_tracker_leds();
+        assert_eq!(lit(LIBERAL, &leds), tracker[..2]);
+        assert_eq!(LIBERAL.election_tracker_warning(&leds), Some(tracker[2]));
+        assert!(lit(FASCIST, &leds).is_empty());
+        assert_eq!(FASCIST.election_tracker_warning(&leds), None);
+    }
+
+    #[test]
+    fn on_fascist() {
+        let leds = leds(ElectionTrackerPlacement::Fascist);
+        let tracker = FASCIST.layout.election_tracker_leds();
+        assert!(lit(LIBERAL, &leds).is_empty());
+        assert_eq!(LIBERAL.election_tracker_warning(&leds), None);
+        assert_eq!(lit(FASCIST, &leds), tracker[..2]);
+        assert_eq!(FASCIST.election_tracker_warning(&leds), Some(tracker[2]));
+        // The first fascist board PCB doesn't have the LEDs, so nothing is shown
+        let fascist_v1 = BoardLeds {
+            layout: LedLayout::fascist_v1(),
+            ..FASCIST
+        };
+        assert!(lit(fascist_v1, &leds).is_empty());
+        assert_eq!(fascist_v1.election_tracker_warning(&leds), None);
+    }
+
+    #[test]
+    fn on_both() {
+        let mut leds = leds(ElectionTrackerPlacement::Both);
+        // Both boards show the same thing
+        for board in [LIBERAL, FASCIST] {
+            let tracker = board.layout.election_tracker_leds();
+            assert_eq!(lit(board, &leds), tracker[..2]);
+            assert_eq!(board.election_tracker_warning(&leds), Some(tracker[2]));
+        }
+        assert_eq!(lit(LIBERAL, &leds), lit(FASCIST, &leds));
+        leds.election_tracker_leds = 0;
+        leds.election_tracker_warning = false;
+        for board in [LIBERAL, FASCIST] {
+            assert!(lit(board, &leds).is_empty());
+            assert_eq!(board.election_tracker_warning(&leds), None);
+        }
+    }
+}
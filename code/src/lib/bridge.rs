@@ -0,0 +1,219 @@
+//! Turns the cards on the NFC readers into the cards that the [`game_pure::GameState`] expects,
+//! so that each firmware doesn't need its own glue.
+//!
+//! Every [`CardScanner`](crate::CardScanner) gives a [`ScanResult`], which is what [`interpret_scan`] takes.
+
+use common::{Event, MAX_NFC_READERS};
+use defmt::{Debug2Format, Format, warn};
+use game_pure::{CharacterCardId, DetectedPolicyCards, Team};
+use heapless::Vec;
+
+use crate::{CardKind, CardRegistry, CardUid, ScanResult, scan_result};
+
+/// What an NFC reader is under
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum ReaderRole {
+    /// A policy slot on that team's board
+    PolicySlot(Team),
+    DeadCharacter,
+}
+
+/// The role of each NFC reader, by the reader's index. Readers past the end aren't used.
+pub type SlotMap = Vec<ReaderRole, MAX_NFC_READERS>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScanInterpretation {
+    pub policy_cards: DetectedPolicyCards,
+    /// The character card in the dead character area
+    pub dead_character: Option<CharacterCardId>,
+    /// Cards that aren't in the registry, which could be registered
+    pub unknown: Vec<CardUid, MAX_NFC_READERS>,
+    /// What each reader scanned, for debugging
+    pub raw: ScanResult,
+}
+
+/// Cards that are on a reader that isn't used, or on a reader for a different kind of card, are ignored
+pub fn interpret_scan(
+    scan: &ScanResult,
+    registry: &CardRegistry,
+    roles: &SlotMap,
+) -> ScanInterpretation {
+    let mut interpretation = ScanInterpretation {
+        policy_cards: Default::default(),
+        dead_character: None,
+        unknown: Vec::new(),
+        raw: scan.clone(),
+    };
+    for (reader, uid) in scan.iter().enumerate() {
+        let Some(uid) = uid else {
+            continue;
+        };
+        let Some(kind) = registry.get(uid) else {
+            // There is one UID per reader, so this can't be full
+            let _ = interpretation.unknown.push(uid.clone());
+            continue;
+        };
+        // There are fewer readers than places in each set, so these can't be full
+        match (roles.get(reader), kind) {
+            (Some(ReaderRole::PolicySlot(Team::Liberal)), CardKind::Policy(card)) => {
+                let _ = interpretation.policy_cards.liberal.insert(card);
+            }
+            (Some(ReaderRole::PolicySlot(Team::Fascist)), CardKind::Policy(card)) => {
+                let _ = interpretation.policy_cards.fascist.insert(card);
+            }
+            (Some(ReaderRole::DeadCharacter), CardKind::Character(character)) => {
+                interpretation.dead_character = Some(character);
+            }
+            (role, kind) => {
+                warn!(
+                    "Ignoring {} on NFC reader {} with role {}",
+                    Debug2Format(&kind),
+                    reader,
+                    role
+                );
+            }
+        }
+    }
+    interpretation
+}
+
+/// `None` if `event` isn't an [`Event::Nfc`]
+pub fn interpret_nfc_event(
+    event: &Event,
+    registry: &CardRegistry,
+    roles: &SlotMap,
+) -> Option<ScanInterpretation> {
+    match event {
+        Event::Nfc(uids) => Some(interpret_scan(&scan_result(uids), registry, roles)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game_pure::{PolicyCardId, SecretRole};
+
+    use super::*;
+
+    const LIBERAL_0: CardKind = CardKind::Policy(PolicyCardId {
+        team: Team::Liberal,
+        id: 0,
+    });
+    const FASCIST_3: CardKind = CardKind::Policy(PolicyCardId {
+        team: Team::Fascist,
+        id: 3,
+    });
+    const HITLER: CardKind = CardKind::Character(CharacterCardId {
+        secret_role: SecretRole::Hitler,
+        id: 0,
+    });
+
+    fn registry() -> CardRegistry {
+        let mut registry = CardRegistry::new();
+        registry.register(CardUid::new(&[1]), LIBERAL_0).unwrap();
+        registry.register(CardUid::new(&[2]), FASCIST_3).unwrap();
+        registry.register(CardUid::new(&[3]), HITLER).unwrap();
+        registry
+    }
+
+    /// A fascist board with 3 policy slots and the dead character area
+    fn roles() -> SlotMap {
+        Vec::from_iter([
+            ReaderRole::PolicySlot(Team::Fascist),
+            ReaderRole::PolicySlot(Team::Fascist),
+            ReaderRole::PolicySlot(Team::Fascist),
+            ReaderRole::DeadCharacter,
+        ])
+    }
+
+    fn scan(cards: &[Option<u8>]) -> ScanResult {
+        cards
+            .iter()
+            .map(|card| card.map(|byte| CardUid::new(&[byte])))
+            .collect()
+    }
+
+    fn policies(kinds: &[CardKind]) -> impl Iterator<Item = PolicyCardId> + '_ {
+        kinds.iter().map(|kind| match kind {
+            CardKind::Policy(card) => *card,
+            CardKind::Character(_) => unreachable!(),
+        })
+    }
+
+    #[test]
+    fn mixed() {
+        let scan = scan(&[Some(2), None, Some(1), Some(3)]);
+        let interpretation = interpret_scan(&scan, &registry(), &roles());
+        assert_eq!(
+            interpretation.policy_cards,
+            DetectedPolicyCards {
+                liberal: Default::default(),
+                // The liberal policy was misplaced, which the game state warns about
+                fascist: policies(&[FASCIST_3, LIBERAL_0]).collect(),
+            }
+        );
+        assert_eq!(
+            interpretation.dead_character,
+            Some(CharacterCardId {
+                secret_role: SecretRole::Hitler,
+                id: 0
+            })
+        );
+        assert!(interpretation.unknown.is_empty());
+        assert_eq!(interpretation.raw, scan);
+    }
+
+    #[test]
+    fn unknown() {
+        let scan = scan(&[Some(9), Some(2), None, Some(8)]);
+        let interpretation = interpret_scan(&scan, &registry(), &roles());
+        assert_eq!(
+            interpretation.unknown,
+            [CardUid::new(&[9]), CardUid::new(&[8])]
+        );
+        assert!(
+            interpretation
+                .policy_cards
+                .fascist
+                .iter()
+                .copied()
+                .eq(policies(&[FASCIST_3]))
+        );
+        assert_eq!(interpretation.dead_character, None);
+    }
+
+    #[test]
+    fn empty() {
+        let interpretation = interpret_scan(&scan(&[None; 4]), &registry(), &roles());
+        assert_eq!(interpretation.policy_cards, DetectedPolicyCards::default());
+        assert_eq!(interpretation.dead_character, None);
+        assert!(interpretation.unknown.is_empty());
+        assert_eq!(interpretation.raw, scan(&[None; 4]));
+
+        let interpretation =
+            interpret_nfc_event(&Event::Nfc(Vec::new()), &registry(), &roles()).unwrap();
+        assert_eq!(interpretation.raw, ScanResult::new());
+        assert!(interpret_nfc_event(&Event::NfcAlive, &registry(), &roles()).is_none());
+    }
+
+    #[test]
+    fn outside_slot_map() {
+        // Readers 4 and 5 aren't in the slot map
+        let scan = scan(&[None, None, None, None, Some(2), Some(3)]);
+        let interpretation = interpret_scan(&scan, &registry(), &roles());
+        assert_eq!(interpretation.policy_cards, DetectedPolicyCards::default());
+        assert_eq!(interpretation.dead_character, None);
+        // Still shown for debugging
+        assert_eq!(interpretation.raw, scan);
+    }
+
+    #[test]
+    fn wrong_kind() {
+        // A character card on a policy slot, and a policy card in the dead character area
+        let scan = scan(&[Some(3), None, None, Some(2)]);
+        let interpretation = interpret_scan(&scan, &registry(), &roles());
+        assert_eq!(interpretation.policy_cards, DetectedPolicyCards::default());
+        assert_eq!(interpretation.dead_character, None);
+        assert!(interpretation.unknown.is_empty());
+    }
+}
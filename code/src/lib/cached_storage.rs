@@ -0,0 +1,393 @@
+use defmt::warn;
+use embedded_storage_async::nor_flash::NorFlash;
+use game_pure::ShutdownStorage;
+use heapless::Vec;
+use sequential_storage::{
+    cache::KeyCacheImpl,
+    map::{Key, MapStorage},
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{Error, PostcardValue};
+
+struct CacheEntry<K, V> {
+    key: K,
+    /// `None` if nothing is stored with this key
+    value: Option<V>,
+    /// Changed since it was last written to flash
+    dirty: bool,
+}
+
+/// A write-behind cache in front of a [`MapStorage`], so that frequent changes don't block the executor
+/// with flash operations or wear out the flash.
+///
+/// Values are read from flash the first time they are needed. Writes only change the cache,
+/// and all of the changed values are written together at most once every `flush_interval`.
+/// Call [`CachedStorage::flush_now`] before resetting on purpose, since anything that wasn't flushed is lost.
+///
+/// Up to `N` keys are cached. Values are stored as [`PostcardValue`]s.
+pub struct CachedStorage<'a, K: Key, V, S: NorFlash, C: KeyCacheImpl<K>, const N: usize> {
+    map_storage: MapStorage<K, S, C>,
+    buffer: &'a mut [u8],
+    entries: Vec<CacheEntry<K, V>, N>,
+    /// In ms
+    flush_interval: u64,
+    /// When the oldest change that wasn't flushed happened, in ms
+    dirty_since: Option<u64>,
+}
+
+impl<'a, K, V, S, C, const N: usize> CachedStorage<'a, K, V, S, C, N>
+where
+    K: Key,
+    V: Serialize + DeserializeOwned + Clone,
+    S: NorFlash,
+    C: KeyCacheImpl<K>,
+{
+    /// `buffer` has to fit the biggest serialized value. `flush_interval` is in ms.
+    pub fn new(
+        map_storage: MapStorage<K, S, C>,
+        buffer: &'a mut [u8],
+        flush_interval: u64,
+    ) -> Self {
+        Self {
+            map_storage,
+            buffer,
+            entries: Vec::new(),
+            flush_interval,
+            dirty_since: None,
+        }
+    }
+
+    /// The value stored with `key`, including changes that weren't flushed yet
+    pub async fn get(&mut self, key: &K) -> Result<Option<&V>, Error> {
+        let index = match self.entries.iter().position(|entry| entry.key == *key) {
+            Some(index) => index,
+            None => {
+                let value = self
+                    .map_storage
+                    .fetch_item::<PostcardValue<V>>(self.buffer, key)
+                    .await?
+                    .map(|value| value.0);
+                self.insert(key.clone(), value, false).await?
+            }
+        };
+        Ok(self.entries[index].value.as_ref())
+    }
+
+    /// Changes the value stored with `key`, which is written to flash at [`CachedStorage::deadline`].
+    /// This only touches the flash if all `N` cached keys have changes that weren't flushed yet. `now` is in ms.
+    pub async fn set(&mut self, key: K, value: V, now: u64) -> Result<(), Error> {
+        match self.entries.iter_mut().find(|entry| entry.key == key) {
+            Some(entry) => {
+                entry.value = Some(value);
+                entry.dirty = true;
+            }
+            None => {
+                self.insert(key, Some(value), true).await?;
+            }
+        }
+        self.dirty_since.get_or_insert(now);
+        Ok(())
+    }
+
+    /// Returns the index of the new entry
+    async fn insert(&mut self, key: K, value: Option<V>, dirty: bool) -> Result<usize, Error> {
+        if self.entries.is_full() {
+            // Forgetting a value that was already flushed is free, since it can be read again
+            let index = match self.entries.iter().position(|entry| !entry.dirty) {
+                Some(index) => index,
+                None => {
+                    self.flush_now().await?;
+                    0
+                }
+            };
+            self.entries.swap_remove(index);
+        }
+        // There is room after removing an entry
+        let _ = self.entries.push(CacheEntry { key, value, dirty });
+        Ok(self.entries.len() - 1)
+    }
+
+    /// When [`CachedStorage::flush_due`] should be called next, in ms. `None` if nothing changed.
+    pub fn deadline(&self) -> Option<u64> {
+        self.dirty_since
+            .map(|dirty_since| dirty_since + self.flush_interval)
+    }
+
+    /// Flushes if the oldest change is at least `flush_interval` old. `now` is in ms.
+    /// If it fails, it is tried again after another `flush_interval`.
+    pub async fn flush_due(&mut self, now: u64) -> Result<(), Error> {
+        if self.deadline().is_none_or(|deadline| deadline > now) {
+            return Ok(());
+        }
+        let result = self.flush_now().await;
+        if result.is_err() {
+            self.dirty_since = Some(now);
+        }
+        result
+    }
+
+    /// Writes every changed value to flash
+    pub async fn flush_now(&mut self) -> Result<(), Error> {
+        for entry in self.entries.iter_mut().filter(|entry| entry.dirty) {
+            // Only values that were set are dirty
+            if let Some(value) = &entry.value {
+                self.map_storage
+                    .store_item(self.buffer, &entry.key, &PostcardValue(value.clone()))
+                    .await?;
+            }
+            entry.dirty = false;
+        }
+        self.dirty_since = None;
+        Ok(())
+    }
+
+    /// Some values have changes that weren't flushed yet
+    pub fn is_dirty(&self) -> bool {
+        self.dirty_since.is_some()
+    }
+
+    /// For storing values of a different type with other keys, without caching them
+    pub fn map_storage(&mut self) -> &mut MapStorage<K, S, C> {
+        &mut self.map_storage
+    }
+}
+
+impl<K, V, S, C, const N: usize> ShutdownStorage for CachedStorage<'_, K, V, S, C, N>
+where
+    K: Key,
+    V: Serialize + DeserializeOwned + Clone,
+    S: NorFlash,
+    C: KeyCacheImpl<K>,
+{
+    async fn flush_before_reset(&mut self) {
+        if let Err(e) = self.flush_now().await {
+            warn!("Failed to save before resetting: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::cell::RefCell;
+    use std::{vec, vec::Vec};
+
+    use embassy_futures::block_on;
+    use embedded_storage_async::nor_flash::{ErrorType, NorFlashErrorKind, ReadNorFlash};
+    use sequential_storage::{cache::NoCache, map::MapConfig};
+
+    use super::*;
+
+    const ERASE_SIZE: usize = 4096;
+    const PAGES: usize = 4;
+    const FLUSH_INTERVAL: u64 = 5_000;
+
+    #[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+    struct FlashCounts {
+        erases: usize,
+        writes: usize,
+    }
+
+    struct FlashState {
+        bytes: Vec<u8>,
+        counts: FlashCounts,
+        /// Erasing and writing fail, like when the flash is worn out
+        failing: bool,
+    }
+
+    impl FlashState {
+        fn new() -> RefCell<Self> {
+            RefCell::new(Self {
+                bytes: vec![0xFF; ERASE_SIZE * PAGES],
+                counts: Default::default(),
+                failing: false,
+            })
+        }
+    }
+
+    /// NOR flash in RAM, which counts how many times it was erased and written.
+    /// Writing can only clear bits, like real flash.
+    struct MockFlash<'a>(&'a RefCell<FlashState>);
+
+    impl ErrorType for MockFlash<'_> {
+        type Error = NorFlashErrorKind;
+    }
+
+    fn range(offset: u32, len: usize) -> Result<core::ops::Range<usize>, NorFlashErrorKind> {
+        let start = offset as usize;
+        if start + len > ERASE_SIZE * PAGES {
+            return Err(NorFlashErrorKind::OutOfBounds);
+        }
+        Ok(start..start + len)
+    }
+
+    impl ReadNorFlash for MockFlash<'_> {
+        const READ_SIZE: usize = 1;
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+            let range = range(offset, bytes.len())?;
+            bytes.copy_from_slice(&self.0.borrow().bytes[range]);
+            Ok(())
+        }
+
+        fn capacity(&self) -> usize {
+            ERASE_SIZE * PAGES
+        }
+    }
+
+    impl NorFlash for MockFlash<'_> {
+        const WRITE_SIZE: usize = 4;
+        const ERASE_SIZE: usize = ERASE_SIZE;
+
+        async fn erase(&mut self, from: u32, to: u32) -> Result<(), Self::Error> {
+            let mut state = self.0.borrow_mut();
+            if state.failing {
+                return Err(NorFlashErrorKind::Other);
+            }
+            state.bytes[range(from, (to - from) as usize)?].fill(0xFF);
+            state.counts.erases += 1;
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+            let mut state = self.0.borrow_mut();
+            if state.failing {
+                return Err(NorFlashErrorKind::Other);
+            }
+            for (stored, byte) in state.bytes[range(offset, bytes.len())?]
+                .iter_mut()
+                .zip(bytes)
+            {
+                *stored &= byte;
+            }
+            state.counts.writes += 1;
+            Ok(())
+        }
+    }
+
+    fn map_storage(flash: &RefCell<FlashState>) -> MapStorage<u8, MockFlash<'_>, NoCache> {
+        MapStorage::new(
+            MockFlash(flash),
+            MapConfig::new(0..(ERASE_SIZE * PAGES) as u32),
+            NoCache::new(),
+        )
+    }
+
+    type Storage<'a, 'b> = CachedStorage<'a, u8, u32, MockFlash<'b>, NoCache, 2>;
+
+    #[test]
+    fn burst_of_updates() {
+        let flash = FlashState::new();
+        let mut buffer = [0; 32];
+        let mut storage = Storage::new(map_storage(&flash), &mut buffer, FLUSH_INTERVAL);
+        block_on(async {
+            assert_eq!(storage.get(&0).await.unwrap(), None);
+            for i in 0..100 {
+                storage.set(0, i, 1_000 + i as u64).await.unwrap();
+                // Read your writes
+                assert_eq!(storage.get(&0).await.unwrap(), Some(&i));
+            }
+            assert_eq!(flash.borrow().counts, FlashCounts::default());
+            assert_eq!(storage.deadline(), Some(1_000 + FLUSH_INTERVAL));
+            storage.flush_due(1_000 + FLUSH_INTERVAL - 1).await.unwrap();
+            assert_eq!(flash.borrow().counts, FlashCounts::default());
+            storage.flush_due(1_000 + FLUSH_INTERVAL).await.unwrap();
+            assert!(!storage.is_dirty());
+            assert_eq!(storage.deadline(), None);
+        });
+
+        // 100 updates are written like 1
+        let single = FlashState::new();
+        let mut buffer = [0; 32];
+        block_on(map_storage(&single).store_item(&mut buffer, &0, &PostcardValue(99_u32))).unwrap();
+        assert_eq!(flash.borrow().counts, single.borrow().counts);
+
+        // Nothing more to write
+        let counts = flash.borrow().counts;
+        block_on(storage.flush_now()).unwrap();
+        assert_eq!(flash.borrow().counts, counts);
+    }
+
+    #[test]
+    fn persisted() {
+        let flash = FlashState::new();
+        let mut buffer = [0; 32];
+        let mut storage = Storage::new(map_storage(&flash), &mut buffer, FLUSH_INTERVAL);
+        block_on(async {
+            storage.set(0, 10, 0).await.unwrap();
+            storage.set(1, 11, 0).await.unwrap();
+            storage.flush_now().await.unwrap();
+            storage.set(1, 12, 0).await.unwrap();
+        });
+        // Like resetting before the change to key 1 was flushed
+        drop(storage);
+
+        let mut buffer = [0; 32];
+        let mut storage = Storage::new(map_storage(&flash), &mut buffer, FLUSH_INTERVAL);
+        block_on(async {
+            assert_eq!(storage.get(&0).await.unwrap(), Some(&10));
+            assert_eq!(storage.get(&1).await.unwrap(), Some(&11));
+            assert_eq!(storage.get(&2).await.unwrap(), None);
+        });
+    }
+
+    #[test]
+    fn flushed_before_reset() {
+        let flash = FlashState::new();
+        let mut buffer = [0; 32];
+        let mut storage = Storage::new(map_storage(&flash), &mut buffer, FLUSH_INTERVAL);
+        block_on(async {
+            storage.set(0, 10, 0).await.unwrap();
+            storage.flush_before_reset().await;
+        });
+        assert!(!storage.is_dirty());
+        drop(storage);
+
+        let mut buffer = [0; 32];
+        let mut storage = Storage::new(map_storage(&flash), &mut buffer, FLUSH_INTERVAL);
+        assert_eq!(block_on(storage.get(&0)).unwrap(), Some(&10));
+    }
+
+    #[test]
+    fn evicted() {
+        let flash = FlashState::new();
+        let mut buffer = [0; 32];
+        let mut storage = Storage::new(map_storage(&flash), &mut buffer, FLUSH_INTERVAL);
+        block_on(async {
+            storage.set(0, 10, 0).await.unwrap();
+            storage.set(1, 11, 0).await.unwrap();
+            assert_eq!(flash.borrow().counts.writes, 0);
+            // Both cached keys are dirty, so they are flushed to make room
+            storage.set(2, 12, 0).await.unwrap();
+            assert_ne!(flash.borrow().counts.writes, 0);
+            assert!(storage.is_dirty());
+            // Evicted values are read again
+            assert_eq!(storage.get(&0).await.unwrap(), Some(&10));
+            assert_eq!(storage.get(&1).await.unwrap(), Some(&11));
+            assert_eq!(storage.get(&2).await.unwrap(), Some(&12));
+        });
+    }
+
+    #[test]
+    fn flash_failure() {
+        let flash = FlashState::new();
+        let mut buffer = [0; 32];
+        let mut storage = Storage::new(map_storage(&flash), &mut buffer, FLUSH_INTERVAL);
+        flash.borrow_mut().failing = true;
+        block_on(async {
+            // Only the cache changes
+            storage.set(0, 10, 0).await.unwrap();
+            let error = storage.flush_due(FLUSH_INTERVAL).await.unwrap_err();
+            assert!(matches!(error, Error::Storage));
+            // The change isn't lost, and is tried again after another interval
+            assert!(storage.is_dirty());
+            assert_eq!(storage.deadline(), Some(2 * FLUSH_INTERVAL));
+            assert_eq!(storage.get(&0).await.unwrap(), Some(&10));
+            flash.borrow_mut().failing = false;
+            storage.flush_due(2 * FLUSH_INTERVAL).await.unwrap();
+            assert!(!storage.is_dirty());
+        });
+    }
+}
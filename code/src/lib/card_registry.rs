@@ -0,0 +1,447 @@
+use defmt::{Debug2Format, Format, warn};
+use embedded_storage_async::nor_flash::NorFlash;
+use game_pure::{
+    CharacterCardId, FASCIST_POLICY_CARDS, LIBERAL_POLICY_CARDS, PolicyCardId, SecretRole, Team,
+};
+use heapless::Vec;
+use sequential_storage::{
+    cache::KeyCacheImpl,
+    map::{Key, MapStorage},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{CardUid, Error, POSTCARD_VALUE_OVERHEAD, PostcardValue};
+
+pub const LIBERAL_CHARACTER_CARDS: usize = 6;
+pub const FASCIST_CHARACTER_CARDS: usize = 3;
+/// 6 liberals, 3 fascists, and Hitler
+pub const CHARACTER_CARDS: usize = LIBERAL_CHARACTER_CARDS + FASCIST_CHARACTER_CARDS + 1;
+/// Every card in the game can be registered
+pub const CARD_REGISTRY_LEN: usize = LIBERAL_POLICY_CARDS + FASCIST_POLICY_CARDS + CHARACTER_CARDS;
+
+/// The card that an NFC tag is stuck to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CardKind {
+    Policy(PolicyCardId),
+    Character(CharacterCardId),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RegistryFull;
+
+/// How a [`CardKind`] is stored, since game_pure's ids aren't serializable
+#[derive(Debug, Format, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum StoredCardKind {
+    LiberalPolicy(u8),
+    FascistPolicy(u8),
+    LiberalCharacter(u8),
+    FascistCharacter(u8),
+    Hitler,
+}
+
+impl From<CardKind> for StoredCardKind {
+    fn from(value: CardKind) -> Self {
+        match value {
+            CardKind::Policy(PolicyCardId {
+                team: Team::Liberal,
+                id,
+            }) => Self::LiberalPolicy(id as u8),
+            CardKind::Policy(PolicyCardId {
+                team: Team::Fascist,
+                id,
+            }) => Self::FascistPolicy(id as u8),
+            CardKind::Character(CharacterCardId {
+                secret_role: SecretRole::Liberal,
+                id,
+            }) => Self::LiberalCharacter(id as u8),
+            CardKind::Character(CharacterCardId {
+                secret_role: SecretRole::Fascist,
+                id,
+            }) => Self::FascistCharacter(id as u8),
+            CardKind::Character(CharacterCardId {
+                secret_role: SecretRole::Hitler,
+                ..
+            }) => Self::Hitler,
+        }
+    }
+}
+
+impl TryFrom<StoredCardKind> for CardKind {
+    type Error = InvalidCardRegistry;
+
+    /// Cards past the number of cards in the game are corrupt flash
+    fn try_from(value: StoredCardKind) -> Result<Self, Self::Error> {
+        let (kind, id, count) = match value {
+            StoredCardKind::LiberalPolicy(id) => (
+                CardKind::Policy(PolicyCardId {
+                    team: Team::Liberal,
+                    id: id.into(),
+                }),
+                id,
+                LIBERAL_POLICY_CARDS,
+            ),
+            StoredCardKind::FascistPolicy(id) => (
+                CardKind::Policy(PolicyCardId {
+                    team: Team::Fascist,
+                    id: id.into(),
+                }),
+                id,
+                FASCIST_POLICY_CARDS,
+            ),
+            StoredCardKind::LiberalCharacter(id) => (
+                CardKind::Character(CharacterCardId {
+                    secret_role: SecretRole::Liberal,
+                    id: id.into(),
+                }),
+                id,
+                LIBERAL_CHARACTER_CARDS,
+            ),
+            StoredCardKind::FascistCharacter(id) => (
+                CardKind::Character(CharacterCardId {
+                    secret_role: SecretRole::Fascist,
+                    id: id.into(),
+                }),
+                id,
+                FASCIST_CHARACTER_CARDS,
+            ),
+            StoredCardKind::Hitler => (
+                CardKind::Character(CharacterCardId {
+                    secret_role: SecretRole::Hitler,
+                    id: 0,
+                }),
+                0,
+                1,
+            ),
+        };
+        if usize::from(id) < count {
+            Ok(kind)
+        } else {
+            Err(InvalidCardRegistry::UnknownCard(value))
+        }
+    }
+}
+
+/// How a [`CardRegistry`] is stored in flash. It is stored in a [`PostcardValue`], which adds the CRC,
+/// and postcard stores the number of entries before them.
+#[derive(Debug, Format, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub enum CardRegistryBlob {
+    V0 {
+        entries: Vec<(CardUid, StoredCardKind), CARD_REGISTRY_LEN>,
+    },
+}
+
+/// Enough for every card with a 10 byte UID. This is an estimate, since each entry is smaller in postcard than in memory.
+pub const CARD_REGISTRY_BUFFER_LEN: usize =
+    // The variant and the number of entries
+    2 + CARD_REGISTRY_LEN * size_of::<(CardUid, StoredCardKind)>() + POSTCARD_VALUE_OVERHEAD;
+
+/// Why the stored registry can't be used, so the cards have to be enrolled again
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidCardRegistry {
+    /// The CRC didn't match, the data was truncated, or reading the flash failed
+    Unreadable,
+    /// Two cards have the same tag
+    DuplicateUid,
+    /// A card has two tags.
+    /// Together with [`InvalidCardRegistry::UnknownCard`], this means that there can't be more than 17 policies.
+    DuplicateCard(StoredCardKind),
+    UnknownCard(StoredCardKind),
+}
+
+impl From<&CardRegistry> for CardRegistryBlob {
+    fn from(value: &CardRegistry) -> Self {
+        Self::V0 {
+            entries: value
+                .entries
+                .iter()
+                .map(|(uid, kind)| (uid.clone(), (*kind).into()))
+                .collect(),
+        }
+    }
+}
+
+impl TryFrom<CardRegistryBlob> for CardRegistry {
+    type Error = InvalidCardRegistry;
+
+    fn try_from(value: CardRegistryBlob) -> Result<Self, Self::Error> {
+        let CardRegistryBlob::V0 { entries } = value;
+        let mut registry = Self::new();
+        for (uid, stored_kind) in entries {
+            let kind = CardKind::try_from(stored_kind)?;
+            if registry.get(&uid).is_some() {
+                return Err(InvalidCardRegistry::DuplicateUid);
+            }
+            if registry
+                .entries
+                .iter()
+                .any(|(_, registered_kind)| *registered_kind == kind)
+            {
+                return Err(InvalidCardRegistry::DuplicateCard(stored_kind));
+            }
+            // Every entry was a different card, so there are at most as many entries as cards
+            let _ = registry.entries.push((uid, kind));
+        }
+        Ok(registry)
+    }
+}
+
+/// Which card each NFC tag belongs to. Each tag and each card is registered at most once.
+#[derive(Debug, Clone, Default)]
+pub struct CardRegistry {
+    entries: Vec<(CardUid, CardKind), CARD_REGISTRY_LEN>,
+}
+
+impl CardRegistry {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    /// Replaces whatever `uid` or `kind` were registered as before, so that a card can be registered again with a new tag
+    pub fn register(&mut self, uid: CardUid, kind: CardKind) -> Result<(), RegistryFull> {
+        self.entries.retain(|(registered_uid, registered_kind)| {
+            *registered_uid != uid && *registered_kind != kind
+        });
+        self.entries.push((uid, kind)).map_err(|_| RegistryFull)
+    }
+
+    pub fn get(&self, uid: &CardUid) -> Option<CardKind> {
+        self.entries
+            .iter()
+            .find(|(registered_uid, _)| registered_uid == uid)
+            .map(|(_, kind)| *kind)
+    }
+
+    /// Unregisters a tag, like when its card was damaged, so that only that card has to be enrolled again
+    pub fn remove_card(&mut self, uid: &CardUid) -> Option<CardKind> {
+        let index = self
+            .entries
+            .iter()
+            .position(|(registered_uid, _)| registered_uid == uid)?;
+        Some(self.entries.swap_remove(index).1)
+    }
+
+    /// Every card in the game has a tag, so the game can be played
+    pub fn is_complete(&self) -> bool {
+        self.entries.len() == CARD_REGISTRY_LEN
+    }
+
+    /// Loads the registry stored with `key`. Nothing stored is an empty registry, which isn't [`complete`](Self::is_complete).
+    /// `key` must be different from the key of the settings.
+    pub async fn load<K: Key, S: NorFlash, C: KeyCacheImpl<K>>(
+        map_storage: &mut MapStorage<K, S, C>,
+        key: &K,
+        buffer: &mut [u8],
+    ) -> Result<Self, InvalidCardRegistry> {
+        match map_storage
+            .fetch_item::<PostcardValue<CardRegistryBlob>>(buffer, key)
+            .await
+        {
+            Ok(Some(blob)) => blob.0.try_into(),
+            Ok(None) => Ok(Self::new()),
+            Err(e) => {
+                warn!("Failed to load the card registry: {}", Debug2Format(&e));
+                Err(InvalidCardRegistry::Unreadable)
+            }
+        }
+    }
+
+    pub async fn save<K: Key, S: NorFlash, C: KeyCacheImpl<K>>(
+        &self,
+        map_storage: &mut MapStorage<K, S, C>,
+        key: &K,
+        buffer: &mut [u8],
+    ) -> Result<(), Error> {
+        map_storage
+            .store_item(buffer, key, &PostcardValue(CardRegistryBlob::from(self)))
+            .await?;
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use sequential_storage::map::Value;
+
+    use super::*;
+
+    /// The longest kind of UID
+    fn long_uid(i: u8) -> CardUid {
+        CardUid::new(&[i, 0x04, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF])
+    }
+
+    /// Every card, with the UID being the index
+    fn complete_registry() -> CardRegistry {
+        let policies = (0..LIBERAL_POLICY_CARDS)
+            .map(|id| StoredCardKind::LiberalPolicy(id as u8))
+            .chain((0..FASCIST_POLICY_CARDS).map(|id| StoredCardKind::FascistPolicy(id as u8)));
+        let characters = (0..LIBERAL_CHARACTER_CARDS)
+            .map(|id| StoredCardKind::LiberalCharacter(id as u8))
+            .chain(
+                (0..FASCIST_CHARACTER_CARDS).map(|id| StoredCardKind::FascistCharacter(id as u8)),
+            )
+            .chain([StoredCardKind::Hitler]);
+        let mut registry = CardRegistry::new();
+        for (i, kind) in policies.chain(characters).enumerate() {
+            registry
+                .register(long_uid(i as u8), kind.try_into().unwrap())
+                .unwrap();
+        }
+        registry
+    }
+
+    fn serialize(blob: CardRegistryBlob, buffer: &mut [u8]) -> usize {
+        PostcardValue(blob).serialize_into(buffer).unwrap()
+    }
+
+    fn deserialize(buffer: &[u8]) -> Result<CardRegistry, InvalidCardRegistry> {
+        PostcardValue::<CardRegistryBlob>::deserialize_from(buffer)
+            .map_err(|_| InvalidCardRegistry::Unreadable)
+            .and_then(|(blob, _)| blob.0.try_into())
+    }
+
+    #[test]
+    fn round_trip() {
+        let registry = complete_registry();
+        assert!(registry.is_complete());
+        let mut buffer = [0; CARD_REGISTRY_BUFFER_LEN];
+        let len = serialize((&registry).into(), &mut buffer);
+        let loaded = deserialize(&buffer[..len]).unwrap();
+        assert!(loaded.is_complete());
+        assert_eq!(loaded.entries, registry.entries);
+
+        let len = serialize((&CardRegistry::new()).into(), &mut buffer);
+        assert!(deserialize(&buffer[..len]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn corruption() {
+        let mut buffer = [0; CARD_REGISTRY_BUFFER_LEN];
+        let len = serialize((&complete_registry()).into(), &mut buffer);
+        for bit in 0..len * 8 {
+            let mut corrupted = buffer;
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            assert!(
+                deserialize(&corrupted[..len]).is_err(),
+                "flipping bit {bit}"
+            );
+        }
+        // A write that was cut off
+        assert_eq!(
+            deserialize(&buffer[..len / 2]).unwrap_err(),
+            InvalidCardRegistry::Unreadable
+        );
+
+        // Impossible contents with a valid CRC
+        let blob = |entries: &[(u8, StoredCardKind)]| CardRegistryBlob::V0 {
+            entries: entries
+                .iter()
+                .map(|(uid, kind)| (CardUid::new(&[*uid]), *kind))
+                .collect(),
+        };
+        let len = serialize(
+            blob(&[
+                (1, StoredCardKind::LiberalPolicy(0)),
+                (1, StoredCardKind::Hitler),
+            ]),
+            &mut buffer,
+        );
+        assert_eq!(
+            deserialize(&buffer[..len]).unwrap_err(),
+            InvalidCardRegistry::DuplicateUid
+        );
+        let len = serialize(
+            blob(&[(1, StoredCardKind::Hitler), (2, StoredCardKind::Hitler)]),
+            &mut buffer,
+        );
+        assert_eq!(
+            deserialize(&buffer[..len]).unwrap_err(),
+            InvalidCardRegistry::DuplicateCard(StoredCardKind::Hitler)
+        );
+        // An 18th policy
+        let len = serialize(
+            blob(&[(1, StoredCardKind::FascistPolicy(FASCIST_POLICY_CARDS as u8))]),
+            &mut buffer,
+        );
+        assert_eq!(
+            deserialize(&buffer[..len]).unwrap_err(),
+            InvalidCardRegistry::UnknownCard(StoredCardKind::FascistPolicy(
+                FASCIST_POLICY_CARDS as u8
+            ))
+        );
+    }
+
+    #[test]
+    fn partial_re_enrollment() {
+        let mut registry = complete_registry();
+        let damaged = long_uid(3);
+        let kind = registry.remove_card(&damaged).unwrap();
+        assert_eq!(registry.remove_card(&damaged), None);
+        assert!(!registry.is_complete());
+
+        let mut buffer = [0; CARD_REGISTRY_BUFFER_LEN];
+        let len = serialize((&registry).into(), &mut buffer);
+        let mut loaded = deserialize(&buffer[..len]).unwrap();
+        assert_eq!(loaded.len(), CARD_REGISTRY_LEN - 1);
+        loaded.register(CardUid::new(&[0xAB]), kind).unwrap();
+        assert!(loaded.is_complete());
+        assert_eq!(loaded.get(&CardUid::new(&[0xAB])), Some(kind));
+        assert_eq!(loaded.get(&damaged), None);
+    }
+
+    #[test]
+    fn register_again() {
+        let mut registry = CardRegistry::new();
+        let policy = CardKind::Policy(PolicyCardId {
+            team: Team::Liberal,
+            id: 0,
+        });
+        let hitler = CardKind::Character(CharacterCardId {
+            secret_role: SecretRole::Hitler,
+            id: 0,
+        });
+        registry.register(CardUid::new(&[1]), policy).unwrap();
+        registry.register(CardUid::new(&[2]), hitler).unwrap();
+        assert_eq!(registry.get(&CardUid::new(&[1])), Some(policy));
+        assert_eq!(registry.get(&CardUid::new(&[3])), None);
+
+        // The tag was replaced
+        registry.register(CardUid::new(&[3]), policy).unwrap();
+        assert_eq!(registry.get(&CardUid::new(&[1])), None);
+        assert_eq!(registry.get(&CardUid::new(&[3])), Some(policy));
+        // The tag was stuck to a different card
+        registry.register(CardUid::new(&[3]), hitler).unwrap();
+        assert_eq!(registry.get(&CardUid::new(&[2])), None);
+        assert_eq!(registry.get(&CardUid::new(&[3])), Some(hitler));
+        assert_eq!(registry.len(), 1);
+    }
+
+    #[test]
+    fn full() {
+        let mut registry = CardRegistry::new();
+        for id in 0..CARD_REGISTRY_LEN {
+            let kind = CardKind::Policy(PolicyCardId {
+                team: Team::Fascist,
+                id,
+            });
+            registry.register(CardUid::new(&[id as u8]), kind).unwrap();
+        }
+        let kind = CardKind::Policy(PolicyCardId {
+            team: Team::Fascist,
+            id: CARD_REGISTRY_LEN,
+        });
+        assert_eq!(
+            registry.register(CardUid::new(&[0xFF]), kind),
+            Err(RegistryFull)
+        );
+    }
+}
@@ -0,0 +1,172 @@
+use common::MAX_NFC_READERS;
+use defmt::Format;
+use embassy_sync::{blocking_mutex::raw::RawMutex, signal::Signal};
+use heapless::Vec;
+use mfrc522::Uid;
+use serde::{Deserialize, Serialize};
+
+/// The UID of an NFC card. Unlike [`Uid`], this can be compared.
+#[derive(Debug, Format, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct CardUid(Vec<u8, 10>);
+
+impl CardUid {
+    /// UIDs longer than 10 bytes are truncated
+    pub fn new(bytes: &[u8]) -> Self {
+        Self(bytes.iter().copied().take(10).collect())
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<&Uid> for CardUid {
+    fn from(uid: &Uid) -> Self {
+        Self::new(uid.as_bytes())
+    }
+}
+
+/// The card on each reader, in the order of the readers
+pub type ScanResult = Vec<Option<CardUid>, MAX_NFC_READERS>;
+
+/// The cards in an [`common::Event::Nfc`]
+pub fn scan_result(uids: &[Option<Uid>]) -> ScanResult {
+    uids.iter()
+        .map(|uid| uid.as_ref().map(CardUid::from))
+        .collect()
+}
+
+/// Where the cards on the NFC readers come from, so that the game doesn't care whether the readers
+/// are connected directly or through the STM32
+#[allow(async_fn_in_trait)]
+pub trait CardScanner {
+    /// Waits until the cards on the readers change
+    async fn next_scan(&mut self) -> ScanResult;
+}
+
+/// Cards scanned by the STM32 and received as [`common::Event::Nfc`] over UART
+pub struct UartCardScanner<'a, M: RawMutex> {
+    signal: &'a Signal<M, Vec<Option<Uid>, MAX_NFC_READERS>>,
+}
+
+impl<'a, M: RawMutex> UartCardScanner<'a, M> {
+    /// `signal` is signaled with every [`common::Event::Nfc`]
+    pub fn new(signal: &'a Signal<M, Vec<Option<Uid>, MAX_NFC_READERS>>) -> Self {
+        Self { signal }
+    }
+}
+
+impl<M: RawMutex> CardScanner for UartCardScanner<'_, M> {
+    async fn next_scan(&mut self) -> ScanResult {
+        scan_result(&self.signal.wait().await)
+    }
+}
+
+/// Replays scripted scans, for the simulator and tests.
+/// After the last scan, no more scans happen.
+pub struct FakeCardScanner<I> {
+    scans: I,
+}
+
+impl<I: Iterator<Item = ScanResult>> FakeCardScanner<I> {
+    pub fn new(scans: impl IntoIterator<IntoIter = I>) -> Self {
+        Self {
+            scans: scans.into_iter(),
+        }
+    }
+}
+
+impl<I: Iterator<Item = ScanResult>> CardScanner for FakeCardScanner<I> {
+    async fn next_scan(&mut self) -> ScanResult {
+        match self.scans.next() {
+            Some(scan) => scan,
+            None => core::future::pending().await,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::pin::pin;
+
+    use embassy_futures::{block_on, poll_once};
+    use embassy_sync::blocking_mutex::raw::NoopRawMutex;
+
+    use super::*;
+
+    fn scan(cards: &[Option<&[u8]>]) -> ScanResult {
+        cards.iter().map(|card| card.map(CardUid::new)).collect()
+    }
+
+    #[test]
+    fn fake() {
+        let scans = [scan(&[None, Some(&[1, 2, 3, 4])]), scan(&[None, None])];
+        let mut scanner = FakeCardScanner::new(scans.clone());
+        assert_eq!(block_on(scanner.next_scan()), scans[0]);
+        assert_eq!(block_on(scanner.next_scan()), scans[1]);
+        assert!(poll_once(pin!(scanner.next_scan())).is_pending());
+    }
+
+    #[test]
+    fn uart() {
+        let signal = Signal::<NoopRawMutex, _>::new();
+        let mut scanner = UartCardScanner::new(&signal);
+        assert!(poll_once(pin!(scanner.next_scan())).is_pending());
+        signal.signal(Vec::from_iter([None, None]));
+        assert_eq!(block_on(scanner.next_scan()), scan(&[None, None]));
+    }
+
+    /// [`Uid`] can only be made by mfrc522, so this deserializes one with a SAK of 0
+    fn uid(bytes: &[u8]) -> Uid {
+        let variant = match bytes.len() {
+            4 => 0,
+            7 => 1,
+            10 => 2,
+            len => panic!("UIDs can't be {len} bytes"),
+        };
+        let mut buffer = Vec::<u8, 12>::new();
+        buffer.push(variant).unwrap();
+        buffer.extend_from_slice(bytes).unwrap();
+        buffer.push(0).unwrap();
+        postcard::from_bytes(&buffer).unwrap()
+    }
+
+    #[test]
+    fn from_uid() {
+        for bytes in [&[1, 2, 3, 4][..], &[1, 2, 3, 4, 5, 6, 7], &[0xAB; 10]] {
+            let card = CardUid::from(&uid(bytes));
+            assert_eq!(card.as_bytes(), bytes);
+            assert_eq!(card, CardUid::new(bytes));
+        }
+    }
+
+    #[test]
+    fn equality() {
+        // Separately read UIDs of the same card
+        assert_eq!(
+            CardUid::from(&uid(&[1, 2, 3, 4])),
+            CardUid::from(&uid(&[1, 2, 3, 4]))
+        );
+        assert_ne!(
+            CardUid::from(&uid(&[1, 2, 3, 4])),
+            CardUid::from(&uid(&[1, 2, 3, 5]))
+        );
+        assert_ne!(
+            CardUid::from(&uid(&[1, 2, 3, 4])),
+            CardUid::from(&uid(&[1, 2, 3, 4, 5, 6, 7]))
+        );
+    }
+
+    #[test]
+    fn scan_result_from_uids() {
+        assert_eq!(
+            scan_result(&[None, Some(uid(&[1, 2, 3, 4]))]),
+            scan(&[None, Some(&[1, 2, 3, 4])])
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        assert_eq!(CardUid::new(&[0xAB; 12]).as_bytes(), [0xAB; 10]);
+    }
+}
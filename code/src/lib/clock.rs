@@ -0,0 +1,48 @@
+use core::cell::Cell;
+
+use embassy_time::{Duration, Instant};
+
+/// Where the time comes from, so that types that measure time can be tested without the embassy-time driver
+pub trait Clock {
+    /// In embassy-time ticks
+    fn now(&self) -> u64;
+}
+
+impl<C: Clock> Clock for &C {
+    fn now(&self) -> u64 {
+        C::now(self)
+    }
+}
+
+/// The embassy-time driver, which is the default on the boards
+#[derive(Debug, Clone, Copy, Default)]
+pub struct EmbassyClock;
+
+impl Clock for EmbassyClock {
+    fn now(&self) -> u64 {
+        Instant::now().as_ticks()
+    }
+}
+
+/// A clock that only moves when it is told to, for tests.
+/// Pass it by reference so that the test can keep advancing it.
+#[derive(Debug, Default)]
+pub struct TestClock {
+    now: Cell<u64>,
+}
+
+impl TestClock {
+    pub const fn new() -> Self {
+        Self { now: Cell::new(0) }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration.as_ticks());
+    }
+}
+
+impl Clock for TestClock {
+    fn now(&self) -> u64 {
+        self.now.get()
+    }
+}
@@ -1,4 +1,12 @@
+use common::DEFAULT_DOUBLE_CLICK_WINDOW_MS;
+use defmt::Format;
 use embassy_time::Duration;
+use game_pure::{
+    ELECTION_FAILS_FOR_CHAOS, FASCIST_BOARD_SLOTS, LIBERAL_BOARD_SLOTS, ScanActivity, ScanPreset,
+    SupplyThresholds,
+};
+
+use self::led_grid_index as i;
 
 /// Auto-connect to the last paired peripheral
 pub const AUTO_CONNECT: bool = true;
@@ -6,8 +14,345 @@ pub const AUTO_CONNECT: bool = true;
 /// but the peripheral does not have the previously saved bond info
 /// (which could indicate a man in the middle attack).
 pub const SAVE_BOND_INFO: bool = false;
-/// Invert the display every once in a while to reduce burn in.
-/// I'm not sure whether this actually reduces burn-in
-/// or if it just makes all pixels burned in more evenly.
-/// Either way it preserves the screen quality over time
-pub const INVERT_SCREEN_INTERVAL: Duration = Duration::from_secs(2 * 60);
+/// How long the aura LEDs stay on/off while blinking
+pub const AURA_BLINK_INTERVAL: Duration = Duration::from_millis(500);
+/// How long LEDs take to fade to a new color, like when a policy is placed
+pub const LED_FADE: Duration = Duration::from_millis(300);
+/// How often LED frames are written while fading
+pub const LED_FADE_FRAME_INTERVAL: Duration = Duration::from_millis(20);
+/// The length of one game state tick, which is used for things like confirmation deadlines
+pub const TICK_INTERVAL: Duration = Duration::from_secs(1);
+/// Changes to the stored data are written to flash at most this often, so that changing settings quickly doesn't wear out the flash
+pub const STORAGE_FLUSH_INTERVAL: Duration = Duration::from_secs(5);
+/// How long the fascist board waits for the liberal board to open the L2CAP channel after connecting.
+/// Anything else that connects, like a spectator's phone, is disconnected after this so that the liberal board can connect.
+pub const L2CAP_ACCEPT_TIMEOUT: Duration = Duration::from_secs(5);
+/// How often heap usage is logged
+pub const HEAP_LOG_INTERVAL: Duration = Duration::from_secs(30);
+/// How often to try initializing the display again if it didn't respond, in case it is plugged in later
+pub const DISPLAY_INIT_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+/// The number of frames that render times are averaged over
+pub const FRAME_STATS_WINDOW: usize = 16;
+/// Render times are logged once every this many frames
+pub const FRAME_STATS_LOG_INTERVAL: u32 = 64;
+/// How many bytes of recent events are kept in RAM, so that they can be dumped and replayed on the host
+pub const EVENT_RECORD_LEN: usize = 4096;
+/// The label of the flash partition that keeps the newest events across resets, from `partitions.csv`
+pub const RECORD_LOG_PARTITION: &str = "eventlog";
+/// Recorded events are appended to the flash record log once there are this many, so a reset loses fewer than this many events
+pub const RECORD_LOG_PERSIST_EVENTS: usize = 8;
+/// Two clicks within this long are a double click, which dismisses the hint while playing.
+/// Single clicks are only sent to the game once this passes without a second click, so a longer window makes the menus feel slower.
+pub const DOUBLE_CLICK_WINDOW: Duration = Duration::from_millis(DEFAULT_DOUBLE_CLICK_WINDOW_MS);
+/// Game states are rendered at most this often, so that fast rotary movement doesn't make the display fall behind
+pub const UI_MIN_FRAME_GAP: Duration = Duration::from_millis(33);
+/// How often the supply voltage is sampled with the `supply-sense` feature
+pub const SUPPLY_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+/// The supply voltage is averaged over this many samples, so that a burst of current from the LEDs doesn't warn
+pub const SUPPLY_AVERAGE_SAMPLES: usize = 6;
+/// The voltage divider between the supply and the sense pin, as (supply, sense pin).
+/// The ADC only reads up to about 2.5 V, so 5 V is halved by two equal resistors.
+pub const SUPPLY_DIVIDER: (u32, u32) = (2, 1);
+pub const SUPPLY_THRESHOLDS: SupplyThresholds = SupplyThresholds::USB;
+/// How often and how long the BLE radio listens while scanning
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanParams {
+    pub interval: Duration,
+    pub window: Duration,
+    /// Active scanning asks peripherals for scan responses
+    pub active: bool,
+}
+
+/// Listens all of the time, so that peripherals show up as soon as possible
+pub const AGGRESSIVE_SCAN: ScanParams = ScanParams {
+    interval: Duration::from_secs(1),
+    window: Duration::from_secs(1),
+    active: true,
+};
+/// Listens about 5% of the time, which still finds peripherals within a few seconds
+pub const LOW_POWER_SCAN: ScanParams = ScanParams {
+    interval: Duration::from_millis(1280),
+    window: Duration::from_millis(64),
+    active: false,
+};
+
+pub const fn scan_params(preset: ScanPreset) -> ScanParams {
+    match preset {
+        ScanPreset::Aggressive => AGGRESSIVE_SCAN,
+        ScanPreset::LowPower => LOW_POWER_SCAN,
+    }
+}
+
+/// How often the NFC readers are polled for each [`ScanActivity`].
+/// Even at the slowest, a placed card shows up well before the players look at the board.
+pub const fn nfc_poll_interval(activity: ScanActivity) -> Duration {
+    match activity {
+        ScanActivity::High => Duration::from_millis(100),
+        ScanActivity::Normal => Duration::from_millis(400),
+        ScanActivity::Low => Duration::from_secs(2),
+    }
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum LedLayoutError {
+    /// The LED at this index is past the end of the strip
+    OutOfRange(usize),
+    /// The LED at this index is used for more than one thing
+    Duplicate(usize),
+}
+
+/// The LEDs are on a square grid with this many LEDs on each side
+pub const LED_GRID_SIZE: usize = 8;
+
+/// The index of an LED on the [`LED_GRID_SIZE`] grid
+pub const fn led_grid_index(x: usize, y: usize) -> usize {
+    y * LED_GRID_SIZE + x
+}
+
+/// Which LEDs on a board's strip show what.
+/// Each preset is for a revision of the PCB, so a new PCB gets a new preset instead of changing an old one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedLayout {
+    /// Some LEDs may be connected but not used
+    pub total_leds: usize,
+    /// No particular order to this as of now
+    pub aura: &'static [usize],
+    /// The LEDs for each policy slot, in the order that policies are placed
+    pub policies: &'static [[usize; 2]],
+    /// Order matters here. `None` if the PCB doesn't have election tracker LEDs.
+    pub election_tracker: Option<[usize; ELECTION_FAILS_FOR_CHAOS]>,
+}
+
+const GRID_TOTAL_LEDS: usize = LED_GRID_SIZE * LED_GRID_SIZE;
+const LIBERAL_V1_AURA: [usize; 6] = [i(0, 0), i(6, 0), i(0, 2), i(6, 2), i(0, 4), i(6, 4)];
+/// The fascist board's extra policy slot is where the liberal board's right aura LEDs are,
+/// so its right aura LEDs move over to the last column
+const FASCIST_V1_AURA: [usize; 6] = [i(0, 0), i(7, 0), i(0, 2), i(7, 2), i(0, 4), i(7, 4)];
+/// The liberal board uses the first [`LIBERAL_BOARD_SLOTS`]
+const V1_POLICIES: [[usize; 2]; FASCIST_BOARD_SLOTS] = [
+    [i(1, 1), i(1, 3)],
+    [i(2, 1), i(2, 3)],
+    [i(3, 1), i(3, 3)],
+    [i(4, 1), i(4, 3)],
+    [i(5, 1), i(5, 3)],
+    [i(6, 1), i(6, 3)],
+];
+/// The fascist board v2 has them in the same place
+const V1_ELECTION_TRACKER: [usize; ELECTION_FAILS_FOR_CHAOS] = [i(1, 6), i(2, 6), i(3, 6)];
+
+impl LedLayout {
+    pub const fn liberal_v1() -> Self {
+        Self {
+            total_leds: GRID_TOTAL_LEDS,
+            aura: &LIBERAL_V1_AURA,
+            policies: V1_POLICIES.split_at(LIBERAL_BOARD_SLOTS).0,
+            election_tracker: Some(V1_ELECTION_TRACKER),
+        }
+    }
+
+    pub const fn fascist_v1() -> Self {
+        Self {
+            total_leds: GRID_TOTAL_LEDS,
+            aura: &FASCIST_V1_AURA,
+            policies: &V1_POLICIES,
+            election_tracker: None,
+        }
+    }
+
+    /// The fascist board v1 with the election tracker LEDs, for showing it on the fascist board
+    pub const fn fascist_v2() -> Self {
+        Self {
+            election_tracker: Some(V1_ELECTION_TRACKER),
+            ..Self::fascist_v1()
+        }
+    }
+
+    /// A bare grid, like the one on the dev board, which doesn't show a game
+    pub const fn dev_grid() -> Self {
+        Self {
+            total_leds: GRID_TOTAL_LEDS,
+            aura: &[],
+            policies: &[],
+            election_tracker: None,
+        }
+    }
+
+    /// The election tracker LEDs, which are empty if the PCB doesn't have them
+    pub const fn election_tracker_leds(&self) -> &[usize] {
+        match &self.election_tracker {
+            Some(leds) => leds,
+            None => &[],
+        }
+    }
+
+    /// See [`validate_led_layout`]
+    pub const fn validate(&self) -> Result<(), LedLayoutError> {
+        validate_led_layout(
+            &[
+                self.aura,
+                self.policies.as_flattened(),
+                self.election_tracker_leds(),
+            ],
+            self.total_leds,
+        )
+    }
+}
+
+/// Checks that every LED of a board's layout is on the strip, and that no LED is used twice.
+/// A board's layout is made of groups of LEDs, like the aura LEDs and the policy LEDs.
+/// This is `const` so that layouts are checked at compile time.
+pub const fn validate_led_layout(
+    groups: &[&[usize]],
+    total_leds: usize,
+) -> Result<(), LedLayoutError> {
+    let mut group = 0;
+    while group < groups.len() {
+        let mut i = 0;
+        while i < groups[group].len() {
+            let led = groups[group][i];
+            if led >= total_leds {
+                return Err(LedLayoutError::OutOfRange(led));
+            }
+            if used_before(groups, group, i, led) {
+                return Err(LedLayoutError::Duplicate(led));
+            }
+            i += 1;
+        }
+        group += 1;
+    }
+    Ok(())
+}
+
+/// `led` comes before `groups[group][i]`
+const fn used_before(groups: &[&[usize]], group: usize, i: usize, led: usize) -> bool {
+    let mut g = 0;
+    while g <= group {
+        let end = if g == group { i } else { groups[g].len() };
+        let mut j = 0;
+        while j < end {
+            if groups[g][j] == led {
+                return true;
+            }
+            j += 1;
+        }
+        g += 1;
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn valid_layouts() {
+        assert_eq!(validate_led_layout(&[], 0), Ok(()));
+        assert_eq!(validate_led_layout(&[&[], &[]], 0), Ok(()));
+        assert_eq!(validate_led_layout(&[&[0, 5, 2], &[3], &[1, 4]], 6), Ok(()));
+        // LEDs that aren't used are fine
+        assert_eq!(validate_led_layout(&[&[63], &[0]], 64), Ok(()));
+    }
+
+    #[test]
+    fn out_of_range() {
+        assert_eq!(
+            validate_led_layout(&[&[0, 1], &[64]], 64),
+            Err(LedLayoutError::OutOfRange(64))
+        );
+        assert_eq!(
+            validate_led_layout(&[&[usize::MAX]], 64),
+            Err(LedLayoutError::OutOfRange(usize::MAX))
+        );
+        // A shorter strip
+        assert_eq!(
+            validate_led_layout(&[&[10, 40]], 32),
+            Err(LedLayoutError::OutOfRange(40))
+        );
+    }
+
+    #[test]
+    fn presets_valid() {
+        for layout in [
+            LedLayout::liberal_v1(),
+            LedLayout::fascist_v1(),
+            LedLayout::fascist_v2(),
+            LedLayout::dev_grid(),
+        ] {
+            assert_eq!(layout.validate(), Ok(()), "{layout:?}");
+        }
+        let liberal = LedLayout::liberal_v1();
+        assert_eq!(liberal.policies.len(), LIBERAL_BOARD_SLOTS);
+        assert!(liberal.election_tracker.is_some());
+        assert_eq!(LedLayout::fascist_v1().policies.len(), FASCIST_BOARD_SLOTS);
+    }
+
+    #[test]
+    fn liberal_fascist_differences() {
+        let liberal = LedLayout::liberal_v1();
+        let fascist = LedLayout::fascist_v1();
+        assert_eq!(liberal.total_leds, fascist.total_leds);
+        // The same slots are in the same place
+        assert_eq!(fascist.policies[..LIBERAL_BOARD_SLOTS], *liberal.policies);
+        // The extra slot uses the column that has the liberal board's right aura LEDs
+        let extra_slot = fascist.policies[LIBERAL_BOARD_SLOTS];
+        assert!(extra_slot.iter().all(|led| led % LED_GRID_SIZE == 6));
+        // So the right aura LEDs move over by one
+        assert_eq!(liberal.aura.len(), fascist.aura.len());
+        for (&liberal_led, &fascist_led) in liberal.aura.iter().zip(fascist.aura) {
+            if liberal_led % LED_GRID_SIZE == 0 {
+                assert_eq!(liberal_led, fascist_led);
+            } else {
+                assert_eq!(liberal_led % LED_GRID_SIZE, 6);
+                assert_eq!(fascist_led, liberal_led + 1);
+            }
+        }
+        // Only the liberal board has the election tracker
+        assert_eq!(fascist.election_tracker, None);
+    }
+
+    #[test]
+    fn election_tracker_on_both() {
+        let liberal = LedLayout::liberal_v1();
+        let fascist = LedLayout::fascist_v2();
+        // Everything else is the same as v1
+        assert_eq!(
+            LedLayout {
+                election_tracker: None,
+                ..fascist
+            },
+            LedLayout::fascist_v1()
+        );
+        // The tracker is in the same place on both boards, and doesn't overlap the fascist board's extra slot
+        assert_eq!(fascist.election_tracker, liberal.election_tracker);
+        assert_eq!(
+            validate_led_layout(
+                &[
+                    fascist.aura,
+                    fascist.policies.as_flattened(),
+                    fascist.election_tracker_leds()
+                ],
+                fascist.total_leds
+            ),
+            Ok(())
+        );
+        assert_eq!(LedLayout::dev_grid().election_tracker_leds(), &[]);
+    }
+
+    #[test]
+    fn duplicates() {
+        // In the same group
+        assert_eq!(
+            validate_led_layout(&[&[1, 2, 1]], 64),
+            Err(LedLayoutError::Duplicate(1))
+        );
+        // In different groups
+        assert_eq!(
+            validate_led_layout(&[&[1, 2], &[3], &[4, 2]], 64),
+            Err(LedLayoutError::Duplicate(2))
+        );
+        assert_eq!(
+            validate_led_layout(&[&[7], &[], &[7]], 64),
+            Err(LedLayoutError::Duplicate(7))
+        );
+    }
+}
@@ -2,30 +2,41 @@ use core::future;
 
 use embassy_time::{Duration, Instant, Timer};
 
-pub struct Debouncer<T> {
+use crate::{Clock, EmbassyClock};
+
+pub struct Debouncer<T, C = EmbassyClock> {
     value: T,
-    pending_value: Option<(T, Instant)>,
+    /// The value and when it was first seen, in ticks of `clock`
+    pending_value: Option<(T, u64)>,
     debounce_time: Duration,
+    clock: C,
 }
 
 impl<T: PartialEq + Copy> Debouncer<T> {
     pub fn new(initial_value: T, debounce_time: Duration) -> Self {
+        Self::with_clock(initial_value, debounce_time, EmbassyClock)
+    }
+}
+
+impl<T: PartialEq + Copy, C: Clock> Debouncer<T, C> {
+    pub fn with_clock(initial_value: T, debounce_time: Duration, clock: C) -> Self {
         Self {
             value: initial_value,
             pending_value: None,
             debounce_time,
+            clock,
         }
     }
 
     /// Returns if the debounced value changed.
-    pub fn process_data(&mut self, latest_data: T, now: Instant) -> bool {
+    pub fn process_data(&mut self, latest_data: T) -> bool {
         if latest_data == self.value {
             self.pending_value = None;
             false
-        } else if let Some((pending_value, instant)) = self.pending_value
+        } else if let Some((pending_value, since)) = self.pending_value
             && pending_value == latest_data
         {
-            if (now - instant) > self.debounce_time {
+            if Duration::from_ticks(self.clock.now().saturating_sub(since)) > self.debounce_time {
                 self.value = pending_value;
                 self.pending_value = None;
                 true
@@ -33,15 +44,16 @@ impl<T: PartialEq + Copy> Debouncer<T> {
                 false
             }
         } else {
-            self.pending_value = Some((latest_data, now));
+            self.pending_value = Some((latest_data, self.clock.now()));
             false
         }
     }
 
     /// Call this function along with the function you use to detect changes in the source that you're getting the value from.
+    /// This waits on the embassy-time driver, so it only makes sense with [`EmbassyClock`].
     pub async fn wait(&mut self) {
-        if let Some((_pending_value, instant)) = &self.pending_value {
-            Timer::at(*instant + self.debounce_time).await;
+        if let Some((_pending_value, since)) = &self.pending_value {
+            Timer::at(Instant::from_ticks(*since) + self.debounce_time).await;
         } else {
             future::pending::<()>().await;
         }
@@ -51,3 +63,43 @@ impl<T: PartialEq + Copy> Debouncer<T> {
         self.value
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestClock;
+
+    const DEBOUNCE_TIME: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn stable_for_debounce_time() {
+        let clock = TestClock::new();
+        let mut debouncer = Debouncer::with_clock(false, DEBOUNCE_TIME, &clock);
+        assert!(!debouncer.process_data(true));
+        clock.advance(DEBOUNCE_TIME);
+        // It has to be stable for longer than the debounce time
+        assert!(!debouncer.process_data(true));
+        assert!(!debouncer.value());
+        clock.advance(Duration::from_micros(1));
+        assert!(debouncer.process_data(true));
+        assert!(debouncer.value());
+        // Already the value
+        assert!(!debouncer.process_data(true));
+    }
+
+    #[test]
+    fn bounce_restarts() {
+        let clock = TestClock::new();
+        let mut debouncer = Debouncer::with_clock(false, DEBOUNCE_TIME, &clock);
+        assert!(!debouncer.process_data(true));
+        clock.advance(Duration::from_micros(600));
+        assert!(!debouncer.process_data(false));
+        clock.advance(Duration::from_micros(600));
+        // Only 600 µs since it bounced back
+        assert!(!debouncer.process_data(true));
+        clock.advance(Duration::from_micros(600));
+        assert!(!debouncer.process_data(true));
+        clock.advance(Duration::from_micros(600));
+        assert!(debouncer.process_data(true));
+    }
+}
@@ -0,0 +1,176 @@
+use core::fmt::Debug;
+
+use display_interface::AsyncWriteOnlyDataCommand;
+use embedded_graphics::{pixelcolor::BinaryColor, prelude::DrawTarget};
+use ssd1306::{
+    Ssd1306Async,
+    mode::{BufferedGraphicsModeAsync, DisplayConfigAsync},
+    prelude::{Brightness, DisplayRotation},
+    size::DisplaySizeAsync,
+};
+
+use crate::Error;
+
+/// A monochrome display with a buffer that the UI is drawn to, and then flushed.
+/// This lets the render loops work with any display, including a mock display in tests.
+#[allow(async_fn_in_trait)]
+pub trait Display: DrawTarget<Color = BinaryColor, Error: Debug + Into<Error>> {
+    async fn init(&mut self) -> Result<(), Self::Error>;
+
+    /// Clears the buffer without flushing it
+    fn clear_buffer(&mut self);
+
+    /// Sends the buffer to the display
+    async fn flush(&mut self) -> Result<(), Self::Error>;
+
+    async fn set_invert(&mut self, invert: bool) -> Result<(), Self::Error>;
+
+    /// 0 is the dimmest, 255 is the brightest
+    async fn set_brightness(&mut self, brightness: u8) -> Result<(), Self::Error>;
+
+    /// Rotates what is shown by 180°. The buffer is drawn to the same way either way.
+    async fn set_rotated(&mut self, rotated: bool) -> Result<(), Self::Error>;
+}
+
+/// The SSD1306's rotation for [`Settings::rotate_display`](game_pure::Settings::rotate_display)
+pub fn ssd1306_rotation(rotated: bool) -> DisplayRotation {
+    if rotated {
+        DisplayRotation::Rotate180
+    } else {
+        DisplayRotation::Rotate0
+    }
+}
+
+impl<DI, SIZE> Display for Ssd1306Async<DI, SIZE, BufferedGraphicsModeAsync<SIZE>>
+where
+    DI: AsyncWriteOnlyDataCommand,
+    SIZE: DisplaySizeAsync,
+{
+    async fn init(&mut self) -> Result<(), Self::Error> {
+        DisplayConfigAsync::init(self).await
+    }
+
+    fn clear_buffer(&mut self) {
+        Ssd1306Async::clear_buffer(self);
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        Ssd1306Async::flush(self).await
+    }
+
+    async fn set_invert(&mut self, invert: bool) -> Result<(), Self::Error> {
+        Ssd1306Async::set_invert(self, invert).await
+    }
+
+    async fn set_brightness(&mut self, brightness: u8) -> Result<(), Self::Error> {
+        // The precharge period must be at least 1
+        Ssd1306Async::set_brightness(self, Brightness::custom(1, brightness)).await
+    }
+
+    async fn set_rotated(&mut self, rotated: bool) -> Result<(), Self::Error> {
+        // Also used when the display is initialized again
+        Ssd1306Async::set_rotation(self, ssd1306_rotation(rotated)).await
+    }
+}
+
+#[cfg(feature = "mock-display")]
+mod mock {
+    use core::convert::Infallible;
+
+    use embedded_graphics::{
+        Pixel,
+        mock_display::MockDisplay,
+        pixelcolor::BinaryColor,
+        prelude::{Dimensions, DrawTarget},
+        primitives::Rectangle,
+    };
+
+    use super::Display;
+
+    /// A [`Display`] that records what would have been shown, for host tests.
+    /// Note that [`MockDisplay`] is only 64x64, so anything drawn past that is ignored.
+    pub struct MockDisplayDriver {
+        /// What is currently in the buffer
+        pub buffer: MockDisplay<BinaryColor>,
+        /// What was shown on the display the last time the buffer was flushed
+        pub shown: MockDisplay<BinaryColor>,
+        pub flushes: usize,
+        pub inverted: bool,
+        pub brightness: u8,
+        pub rotated: bool,
+    }
+
+    impl MockDisplayDriver {
+        pub fn new() -> Self {
+            let mut buffer = MockDisplay::new();
+            buffer.set_allow_out_of_bounds_drawing(true);
+            buffer.set_allow_overdraw(true);
+            Self {
+                shown: buffer.clone(),
+                buffer,
+                flushes: 0,
+                inverted: false,
+                brightness: u8::MAX,
+                rotated: false,
+            }
+        }
+    }
+
+    impl Default for MockDisplayDriver {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+
+    impl Dimensions for MockDisplayDriver {
+        fn bounding_box(&self) -> Rectangle {
+            self.buffer.bounding_box()
+        }
+    }
+
+    impl DrawTarget for MockDisplayDriver {
+        type Color = BinaryColor;
+        type Error = Infallible;
+
+        fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+        where
+            I: IntoIterator<Item = Pixel<Self::Color>>,
+        {
+            self.buffer.draw_iter(pixels)
+        }
+    }
+
+    impl Display for MockDisplayDriver {
+        async fn init(&mut self) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        fn clear_buffer(&mut self) {
+            let _ = self.buffer.clear(BinaryColor::Off);
+        }
+
+        async fn flush(&mut self) -> Result<(), Self::Error> {
+            self.shown = self.buffer.clone();
+            self.flushes += 1;
+            Ok(())
+        }
+
+        async fn set_invert(&mut self, invert: bool) -> Result<(), Self::Error> {
+            self.inverted = invert;
+            Ok(())
+        }
+
+        async fn set_brightness(&mut self, brightness: u8) -> Result<(), Self::Error> {
+            self.brightness = brightness;
+            Ok(())
+        }
+
+        async fn set_rotated(&mut self, rotated: bool) -> Result<(), Self::Error> {
+            self.rotated = rotated;
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "mock-display")]
+pub use mock::*;
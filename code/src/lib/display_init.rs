@@ -0,0 +1,274 @@
+use core::sync::atomic::Ordering;
+
+use defmt::{Debug2Format, debug, info, warn};
+use embassy_time::Instant;
+
+use crate::{DISPLAY_MISSING, Display};
+
+/// Tracks whether the display was initialized, and when to try again if it wasn't.
+/// Boards can be built without the OLED, so a display that doesn't respond shouldn't stop the LEDs and BLE from working.
+/// It is tried again occasionally in case it gets plugged in later, or after it stops responding in the middle of a game.
+#[derive(Debug, Clone)]
+pub struct DisplayInitRetry {
+    /// How long to wait after a failed attempt, in ms
+    retry_interval: u64,
+    /// `None` once the display is initialized
+    next_attempt_at: Option<u64>,
+    failures: u32,
+}
+
+impl DisplayInitRetry {
+    /// `retry_interval` is in ms. The first attempt can happen right away.
+    pub const fn new(retry_interval: u64) -> Self {
+        Self {
+            retry_interval,
+            next_attempt_at: Some(0),
+            failures: 0,
+        }
+    }
+
+    /// When to try initializing the display next, in ms. `None` once it is initialized.
+    pub fn next_attempt_at(&self) -> Option<u64> {
+        self.next_attempt_at
+    }
+
+    /// `now` is in ms
+    pub fn record_attempt(&mut self, ok: bool, now: u64) {
+        self.next_attempt_at = if ok {
+            None
+        } else {
+            self.failures = self.failures.saturating_add(1);
+            Some(now + self.retry_interval)
+        };
+    }
+
+    /// The number of failed attempts so far
+    pub fn failures(&self) -> u32 {
+        self.failures
+    }
+
+    /// At least one attempt failed, and no attempt worked since then
+    pub fn is_missing(&self) -> bool {
+        self.failures > 0 && self.next_attempt_at.is_some()
+    }
+}
+
+/// Initializes the display and records the attempt in `retry`, logging the first failure and recovering later.
+/// Also updates [`DISPLAY_MISSING`]. Returns `true` if it worked.
+pub async fn try_init_display(display: &mut impl Display, retry: &mut DisplayInitRetry) -> bool {
+    let result = display.init().await;
+    match &result {
+        Ok(()) if retry.failures() > 0 => {
+            info!("Display found after {} failed attempts", retry.failures());
+        }
+        Ok(()) => {}
+        // Boards without a display would log this every retry otherwise
+        Err(e) if retry.failures() > 0 => debug!("Display still missing: {}", Debug2Format(e)),
+        Err(e) => warn!(
+            "Failed to initialize the display: {}. Continuing without it.",
+            Debug2Format(e)
+        ),
+    }
+    retry.record_attempt(result.is_ok(), Instant::now().as_millis());
+    DISPLAY_MISSING.store(retry.is_missing(), Ordering::Relaxed);
+    result.is_ok()
+}
+
+/// Call this after flushing failed with `error`, like from an I2C NACK while the bus is busy.
+/// `redraw` draws the frame again and flushes it. It has to draw the whole frame, because the display only sends what changed.
+/// It is called once, and if that also fails, the display is initialized again and it is called one more time.
+///
+/// Returns `false` if the display still doesn't work. That is recorded in `retry` like a failed attempt to initialize it,
+/// so the caller should stop rendering and start over at [`DisplayInitRetry::next_attempt_at`].
+pub async fn recover_flush<D: Display>(
+    display: &mut D,
+    error: D::Error,
+    retry: &mut DisplayInitRetry,
+    mut redraw: impl AsyncFnMut(&mut D) -> Result<(), D::Error>,
+) -> bool {
+    warn!(
+        "Failed to flush the display: {}. Trying again.",
+        Debug2Format(&error)
+    );
+    if redraw(display).await.is_ok() {
+        return true;
+    }
+    warn!("Failed to flush the display again. Initializing it again.");
+    if try_init_display(display, retry).await {
+        if redraw(display).await.is_ok() {
+            return true;
+        }
+        retry.record_attempt(false, Instant::now().as_millis());
+        DISPLAY_MISSING.store(retry.is_missing(), Ordering::Relaxed);
+    }
+    warn!("The display stopped responding. Continuing without it.");
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{future::pending, pin::pin};
+
+    use display_interface::DisplayError;
+    use embassy_embedded_hal::{SetConfig, shared_bus::asynch::i2c::I2cDeviceWithConfig};
+    use embassy_futures::{block_on, poll_once};
+    use embassy_sync::{blocking_mutex::raw::NoopRawMutex, mutex::Mutex};
+    use embedded_graphics::{pixelcolor::BinaryColor, prelude::*};
+    use embedded_hal_async::i2c::{ErrorKind, ErrorType, I2c, NoAcknowledgeSource, Operation};
+    use ssd1306::{
+        I2CDisplayInterface, Ssd1306Async, prelude::DisplayRotation, size::DisplaySize128x64,
+    };
+
+    use super::*;
+    use crate::Error;
+
+    #[test]
+    fn retry_interval() {
+        let mut retry = DisplayInitRetry::new(60_000);
+        assert_eq!(retry.next_attempt_at(), Some(0));
+        assert!(!retry.is_missing());
+        retry.record_attempt(false, 100);
+        assert_eq!(retry.next_attempt_at(), Some(60_100));
+        assert!(retry.is_missing());
+        retry.record_attempt(false, 60_100);
+        assert_eq!(retry.next_attempt_at(), Some(120_100));
+        assert_eq!(retry.failures(), 2);
+        // Hot-plugged
+        retry.record_attempt(true, 120_100);
+        assert_eq!(retry.next_attempt_at(), None);
+        assert!(!retry.is_missing());
+    }
+
+    /// An I2C bus that fails the next `fail_next` transactions, or that never finishes a transaction
+    #[derive(Default)]
+    struct MockBus {
+        fail_next: usize,
+        hang: bool,
+    }
+
+    impl ErrorType for MockBus {
+        type Error = ErrorKind;
+    }
+
+    impl I2c for MockBus {
+        async fn transaction(
+            &mut self,
+            _address: u8,
+            _operations: &mut [Operation<'_>],
+        ) -> Result<(), Self::Error> {
+            if self.hang {
+                pending::<()>().await;
+            }
+            if self.fail_next > 0 {
+                self.fail_next -= 1;
+                Err(ErrorKind::NoAcknowledge(NoAcknowledgeSource::Address))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    impl SetConfig for MockBus {
+        type Config = ();
+        type ConfigError = ();
+
+        fn set_config(&mut self, _config: &Self::Config) -> Result<(), Self::ConfigError> {
+            Ok(())
+        }
+    }
+
+    fn display(bus: &Mutex<NoopRawMutex, MockBus>) -> impl Display {
+        Ssd1306Async::new(
+            I2CDisplayInterface::new(I2cDeviceWithConfig::new(bus, ())),
+            DisplaySize128x64,
+            DisplayRotation::Rotate0,
+        )
+        .into_buffered_graphics_mode()
+    }
+
+    #[test]
+    fn bus_unlocked_after_failure() {
+        // Nothing is connected for the first transaction
+        let bus = Mutex::<NoopRawMutex, _>::new(MockBus {
+            fail_next: 1,
+            ..Default::default()
+        });
+        let mut display = display(&bus);
+        let mut retry = DisplayInitRetry::new(60_000);
+        assert!(!block_on(try_init_display(&mut display, &mut retry)));
+        // Other devices on the bus, like the GPIO expander, can still use it
+        assert!(bus.try_lock().is_ok());
+        assert!(block_on(try_init_display(&mut display, &mut retry)));
+        assert_eq!(retry.next_attempt_at(), None);
+
+        // Dropping the render future in the middle of a transaction unlocks the bus
+        bus.try_lock().unwrap().hang = true;
+        {
+            let init = pin!(display.init());
+            assert!(poll_once(init).is_pending());
+            assert!(bus.try_lock().is_err());
+        }
+        assert!(bus.try_lock().is_ok());
+    }
+
+    /// Changes a pixel, so that flushing sends something
+    async fn redraw<D: Display>(display: &mut D) -> Result<(), D::Error> {
+        Pixel(Point::zero(), BinaryColor::On).draw(display)?;
+        display.flush().await
+    }
+
+    #[test]
+    fn flush_recovery() {
+        let bus = Mutex::<NoopRawMutex, _>::new(MockBus::default());
+        let mut display = display(&bus);
+        let mut retry = DisplayInitRetry::new(60_000);
+        assert!(block_on(try_init_display(&mut display, &mut retry)));
+
+        // A NACK in the middle of a game, which works when flushing again
+        bus.try_lock().unwrap().fail_next = 1;
+        let error = block_on(redraw(&mut display)).unwrap_err();
+        assert!(block_on(recover_flush(
+            &mut display,
+            error,
+            &mut retry,
+            redraw
+        )));
+        assert_eq!(retry.next_attempt_at(), None);
+
+        // Works after initializing the display again
+        bus.try_lock().unwrap().fail_next = 2;
+        let error = block_on(redraw(&mut display)).unwrap_err();
+        assert!(block_on(recover_flush(
+            &mut display,
+            error,
+            &mut retry,
+            redraw
+        )));
+        assert_eq!(retry.next_attempt_at(), None);
+        assert!(!retry.is_missing());
+
+        // Unplugged
+        bus.try_lock().unwrap().fail_next = usize::MAX;
+        let error = block_on(redraw(&mut display)).unwrap_err();
+        assert!(!block_on(recover_flush(
+            &mut display,
+            error,
+            &mut retry,
+            redraw
+        )));
+        assert!(retry.is_missing());
+        assert!(DISPLAY_MISSING.load(Ordering::Relaxed));
+        assert!(bus.try_lock().is_ok());
+    }
+
+    #[test]
+    fn display_error() {
+        let bus = Mutex::<NoopRawMutex, _>::new(MockBus::default());
+        let mut display = display(&bus);
+        let mut retry = DisplayInitRetry::new(60_000);
+        assert!(block_on(try_init_display(&mut display, &mut retry)));
+        bus.try_lock().unwrap().fail_next = 1;
+        let error: Error = block_on(redraw(&mut display)).unwrap_err().into();
+        assert!(matches!(error, Error::Display(DisplayError::BusWriteError)));
+    }
+}
@@ -0,0 +1,141 @@
+use embassy_time::Duration;
+use rand_core::{RngCore, impls};
+
+/// Randomness for choices that should be fair, such as retry jitter.
+/// On hardware, this wraps the ESP's `Trng`. In tests, use [`Pcg32`] so that the output is reproducible.
+#[derive(Debug, Clone)]
+pub struct Entropy<R> {
+    rng: R,
+}
+
+impl<R: RngCore> Entropy<R> {
+    pub const fn new(rng: R) -> Self {
+        Self { rng }
+    }
+
+    /// Returns a number in `0..n`, or `0` if `n` is `0`
+    pub fn below(&mut self, n: u32) -> u32 {
+        // Multiplying instead of using `%` avoids favoring small numbers
+        ((self.rng.next_u32() as u64 * n as u64) >> 32) as u32
+    }
+
+    /// Returns `None` if `items` is empty
+    pub fn pick<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+        items.get(self.below(items.len().try_into().ok()?) as usize)
+    }
+
+    /// Returns a duration between zero and `max`
+    pub fn jitter(&mut self, max: Duration) -> Duration {
+        // Multiplying like in `below`, with 128 bits so that `max` can be any duration
+        Duration::from_ticks(
+            ((self.rng.next_u64() as u128 * (max.as_ticks() as u128 + 1)) >> 64) as u64,
+        )
+    }
+}
+
+/// A small, seeded RNG for tests and simulators. This is not cryptographically secure.
+/// See <https://www.pcg-random.org/>
+#[derive(Debug, Clone)]
+pub struct Pcg32 {
+    state: u64,
+    increment: u64,
+}
+
+impl Pcg32 {
+    const MULTIPLIER: u64 = 6364136223846793005;
+
+    pub fn new(seed: u64) -> Self {
+        let mut pcg = Self {
+            state: 0,
+            increment: (0xda3e39cb94b95bdb << 1) | 1,
+        };
+        pcg.step();
+        pcg.state = pcg.state.wrapping_add(seed);
+        pcg.step();
+        pcg
+    }
+
+    fn step(&mut self) {
+        self.state = self
+            .state
+            .wrapping_mul(Self::MULTIPLIER)
+            .wrapping_add(self.increment);
+    }
+}
+
+impl RngCore for Pcg32 {
+    fn next_u32(&mut self) -> u32 {
+        let state = self.state;
+        self.step();
+        let xorshifted = (((state >> 18) ^ state) >> 27) as u32;
+        xorshifted.rotate_right((state >> 59) as u32)
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        impls::next_u64_via_u32(self)
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        impls::fill_bytes_via_next(self, dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reproducible() {
+        let mut a = Entropy::new(Pcg32::new(42));
+        let mut b = Entropy::new(Pcg32::new(42));
+        for _ in 0..100 {
+            assert_eq!(a.below(1000), b.below(1000));
+        }
+        let mut c = Entropy::new(Pcg32::new(43));
+        assert!((0..100).any(|_| a.below(1000) != c.below(1000)));
+    }
+
+    #[test]
+    fn pick_is_fair() {
+        let mut entropy = Entropy::new(Pcg32::new(1));
+        let items = [0, 1, 2, 3];
+        let mut counts = [0; 4];
+        for _ in 0..4000 {
+            counts[*entropy.pick(&items).unwrap()] += 1;
+        }
+        // Every item is picked about 1000 times
+        for count in counts {
+            assert!((900..1100).contains(&count), "{counts:?}");
+        }
+        assert_eq!(entropy.pick::<u8>(&[]), None);
+    }
+
+    #[test]
+    fn jitter_in_range() {
+        let mut entropy = Entropy::new(Pcg32::new(7));
+        let max = Duration::from_millis(200);
+        let jitters: [_; 100] = core::array::from_fn(|_| entropy.jitter(max));
+        assert!(jitters.iter().all(|jitter| *jitter <= max));
+        // Not always the same
+        assert!(jitters.iter().any(|jitter| *jitter != jitters[0]));
+    }
+
+    #[test]
+    fn jitter_is_fair() {
+        let mut entropy = Entropy::new(Pcg32::new(3));
+        let mut counts = [0; 4];
+        for _ in 0..4000 {
+            counts[entropy.jitter(Duration::from_ticks(3)).as_ticks() as usize] += 1;
+        }
+        for count in counts {
+            assert!((900..1100).contains(&count), "{counts:?}");
+        }
+        // Doesn't overflow
+        entropy.jitter(Duration::MAX);
+    }
+}
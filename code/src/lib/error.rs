@@ -0,0 +1,81 @@
+use core::convert::Infallible;
+
+use common::PacketError;
+use defmt::{Debug2Format, Format, Formatter, write};
+use display_interface::DisplayError;
+use trouble_host::BleHostError;
+
+/// An error from this library.
+/// The library doesn't decide how to recover, so the bins can retry, show a blink code, or give up.
+#[derive(Debug)]
+pub enum Error {
+    /// Drawing to or flushing the display failed
+    Display(DisplayError),
+    /// Reading or writing the stored data failed.
+    /// sequential-storage's error depends on the flash driver, so it is only logged.
+    Storage,
+    Ble(BleError),
+    Spi(embedded_hal::spi::ErrorKind),
+    /// A packet from the other chip couldn't be read
+    Protocol(PacketError),
+}
+
+#[derive(Debug, Format)]
+pub enum BleError {
+    /// The BLE controller failed. Its error type depends on the controller, so it is only logged.
+    Controller,
+    Host(trouble_host::Error),
+}
+
+impl Format for Error {
+    fn format(&self, f: Formatter) {
+        match self {
+            Self::Display(e) => write!(f, "Display({})", Debug2Format(e)),
+            Self::Storage => write!(f, "Storage"),
+            Self::Ble(e) => write!(f, "Ble({})", e),
+            Self::Spi(e) => write!(f, "Spi({})", Debug2Format(e)),
+            Self::Protocol(e) => write!(f, "Protocol({})", e),
+        }
+    }
+}
+
+impl From<DisplayError> for Error {
+    fn from(e: DisplayError) -> Self {
+        Self::Display(e)
+    }
+}
+
+/// For displays that can't fail, like the mock display
+impl From<Infallible> for Error {
+    fn from(e: Infallible) -> Self {
+        match e {}
+    }
+}
+
+impl<E> From<sequential_storage::Error<E>> for Error
+where
+    sequential_storage::Error<E>: core::fmt::Debug,
+{
+    fn from(e: sequential_storage::Error<E>) -> Self {
+        defmt::warn!("Storage error: {}", Debug2Format(&e));
+        Self::Storage
+    }
+}
+
+impl<E: core::fmt::Debug> From<BleHostError<E>> for Error {
+    fn from(e: BleHostError<E>) -> Self {
+        Self::Ble(match e {
+            BleHostError::Controller(e) => {
+                defmt::warn!("BLE controller error: {}", Debug2Format(&e));
+                BleError::Controller
+            }
+            BleHostError::BleHost(e) => BleError::Host(e),
+        })
+    }
+}
+
+impl From<PacketError> for Error {
+    fn from(e: PacketError) -> Self {
+        Self::Protocol(e)
+    }
+}
@@ -0,0 +1,142 @@
+use core::mem;
+
+use game_pure::record::{RECORD_ENTRY_LEN, RecordDecodeError, RecordEntry, RecordedEvent};
+
+/// Keeps the latest `N` events that changed the game state, so that they can be dumped and replayed on the host.
+/// Older events are overwritten once it is full.
+#[derive(Debug, Clone)]
+pub struct EventRecorder<const N: usize> {
+    /// Starts out like erased flash, so unused entries are skipped when reading the dump
+    entries: [[u8; RECORD_ENTRY_LEN]; N],
+    next_sequence: u32,
+    /// How many of the newest entries haven't been taken with [`EventRecorder::take_unpersisted`]
+    unpersisted: usize,
+}
+
+impl<const N: usize> EventRecorder<N> {
+    pub const fn new() -> Self {
+        Self {
+            entries: [[0xFF; RECORD_ENTRY_LEN]; N],
+            next_sequence: 0,
+            unpersisted: 0,
+        }
+    }
+
+    /// The timestamp is stored as a `u32`, so it wraps after about 49 days
+    pub fn record(&mut self, now_ms: u64, event: RecordedEvent) {
+        let sequence = self.next_sequence;
+        self.entries[sequence as usize % N] =
+            RecordEntry::new(sequence, now_ms as u32, event).to_bytes();
+        self.next_sequence = sequence.wrapping_add(1);
+        self.unpersisted = (self.unpersisted + 1).min(N);
+    }
+
+    pub fn unpersisted(&self) -> usize {
+        self.unpersisted
+    }
+
+    /// The entries recorded since the last call, oldest first, for appending to the flash record log.
+    /// Entries that were overwritten before being taken are left out.
+    pub fn take_unpersisted(&mut self) -> impl Iterator<Item = RecordEntry> + '_ {
+        let count = mem::take(&mut self.unpersisted) as u32;
+        let next_sequence = self.next_sequence;
+        (0..count).rev().map(move |age| {
+            RecordEntry::from_bytes(&self.entries[next_sequence.wrapping_sub(age + 1) as usize % N])
+        })
+    }
+
+    /// Up to `count` of the newest entries, oldest first, for [`game_pure::GameState::debug_dump`]
+    pub fn latest(&self, count: usize) -> impl Iterator<Item = RecordEntry> + '_ {
+        let count = count.min(N) as u32;
+        let next_sequence = self.next_sequence;
+        (0..count)
+            .rev()
+            .map(move |age| {
+                RecordEntry::from_bytes(
+                    &self.entries[next_sequence.wrapping_sub(age + 1) as usize % N],
+                )
+            })
+            // Entries that were never written
+            .filter(|entry| entry.event() != Err(RecordDecodeError::Empty))
+    }
+
+    /// The whole buffer, which can be read with [`game_pure::record::read_record`]
+    pub fn as_bytes(&self) -> &[u8] {
+        self.entries.as_flattened()
+    }
+}
+
+impl<const N: usize> Default for EventRecorder<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game_pure::{Input, record::read_record};
+
+    use super::*;
+
+    #[test]
+    fn keeps_latest() {
+        let mut recorder = EventRecorder::<4>::new();
+        assert!(read_record(recorder.as_bytes()).is_empty());
+        for i in 0..6 {
+            recorder.record(i * 100, RecordedEvent::Input(Input::Down));
+        }
+        let entries = read_record(recorder.as_bytes());
+        let sequences = entries
+            .iter()
+            .map(|entry| entry.sequence)
+            .collect::<heapless::Vec<_, 4>>();
+        assert_eq!(sequences, [2, 3, 4, 5]);
+        assert_eq!(entries[0].timestamp_ms, 200);
+        assert_eq!(entries[3].event(), Ok(RecordedEvent::Input(Input::Down)));
+    }
+
+    #[test]
+    fn take_unpersisted() {
+        let mut recorder = EventRecorder::<4>::new();
+        assert_eq!(recorder.take_unpersisted().count(), 0);
+        for i in 0..3 {
+            recorder.record(i * 100, RecordedEvent::Input(Input::Up));
+        }
+        assert_eq!(recorder.unpersisted(), 3);
+        let sequences = |recorder: &mut EventRecorder<4>| {
+            recorder
+                .take_unpersisted()
+                .map(|entry| entry.sequence)
+                .collect::<heapless::Vec<_, 4>>()
+        };
+        assert_eq!(sequences(&mut recorder), [0, 1, 2]);
+        assert_eq!(recorder.unpersisted(), 0);
+        // More than fit, so the oldest ones were overwritten before being taken
+        for i in 3..9 {
+            recorder.record(i * 100, RecordedEvent::Input(Input::Up));
+        }
+        assert_eq!(sequences(&mut recorder), [5, 6, 7, 8]);
+    }
+
+    #[test]
+    fn latest() {
+        let mut recorder = EventRecorder::<4>::new();
+        let sequences = |recorder: &EventRecorder<4>, count| {
+            recorder
+                .latest(count)
+                .map(|entry| entry.sequence)
+                .collect::<heapless::Vec<_, 4>>()
+        };
+        // Entries that were never written are left out
+        recorder.record(0, RecordedEvent::Input(Input::Up));
+        assert_eq!(sequences(&recorder, 3), [0]);
+        for i in 1..6 {
+            recorder.record(i * 100, RecordedEvent::Input(Input::Up));
+        }
+        assert_eq!(sequences(&recorder, 2), [4, 5]);
+        assert_eq!(sequences(&recorder, 8), [2, 3, 4, 5]);
+        // Taking the unpersisted entries doesn't change what's latest
+        recorder.take_unpersisted().for_each(drop);
+        assert_eq!(sequences(&recorder, 2), [4, 5]);
+    }
+}
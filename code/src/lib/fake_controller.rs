@@ -0,0 +1,175 @@
+//! A BLE controller without a radio, so that [`Ble2`](crate::ble_2::Ble2) can be tested on the host
+
+use core::{cell::RefCell, convert::Infallible};
+
+use bt_hci::{
+    ControllerToHostPacket, FromHciBytes, PacketKind, WriteHci,
+    cmd::{
+        self, AsyncCmd, Cmd, CmdReturnBuf, Opcode, SyncCmd,
+        le::{
+            LeAddDeviceToFilterAcceptList, LeClearFilterAcceptList, LeCreateConn, LeSetScanEnable,
+        },
+    },
+    controller::{Controller, ControllerCmdAsync, ControllerCmdSync},
+    data::{AclPacket, IsoPacket, SyncPacket},
+    param::{AddrKind, BdAddr},
+};
+use embassy_sync::{blocking_mutex::raw::NoopRawMutex, channel::Channel};
+use trouble_host::{Address, prelude::AdStructure};
+
+use crate::SERVICE_UUID;
+
+/// HCI event packets, with the event code and the length
+type EventBytes = heapless::Vec<u8, 64>;
+
+/// Answers every command right away with zeroed return parameters.
+/// Each of `peripherals` is reported once when scanning starts,
+/// and creating a connection connects to the first one that is on the filter accept list.
+pub struct FakeController<'a> {
+    peripherals: &'a [Address],
+    filter_accept_list: RefCell<heapless::Vec<Address, 4>>,
+    events: Channel<NoopRawMutex, EventBytes, 4>,
+    /// Each connection gets the next handle
+    next_handle: RefCell<u16>,
+}
+
+impl<'a> FakeController<'a> {
+    pub fn new(peripherals: &'a [Address]) -> Self {
+        Self {
+            peripherals,
+            filter_accept_list: Default::default(),
+            events: Channel::new(),
+            next_handle: RefCell::new(1),
+        }
+    }
+
+    fn command(&self, opcode: Opcode, params: &impl WriteHci) {
+        let mut bytes = [0; 255];
+        params.write_hci(&mut bytes[..]).unwrap();
+        if opcode == LeClearFilterAcceptList::OPCODE {
+            self.filter_accept_list.borrow_mut().clear();
+        } else if opcode == LeAddDeviceToFilterAcceptList::OPCODE {
+            let (kind, rest) = AddrKind::from_hci_bytes(&bytes).unwrap();
+            let (addr, _) = BdAddr::from_hci_bytes(rest).unwrap();
+            self.filter_accept_list
+                .borrow_mut()
+                .push(Address { kind, addr })
+                .unwrap();
+        } else if opcode == LeSetScanEnable::OPCODE && bytes[0] == 1 {
+            for &peripheral in self.peripherals {
+                self.advertise(peripheral);
+            }
+        } else if opcode == LeCreateConn::OPCODE {
+            let peripheral = self
+                .peripherals
+                .iter()
+                .find(|peripheral| self.filter_accept_list.borrow().contains(peripheral));
+            if let Some(&peripheral) = peripheral {
+                self.connect(peripheral);
+            }
+        }
+    }
+
+    /// An LE Advertising Report with our service UUID
+    fn advertise(&self, peripheral: Address) {
+        let mut data = [0; 31];
+        let len = AdStructure::encode_slice(
+            &[AdStructure::ServiceUuids128(&[SERVICE_UUID
+                .as_raw()
+                .try_into()
+                .unwrap()])],
+            &mut data,
+        )
+        .unwrap();
+        let mut params = EventBytes::new();
+        // The subevent code, one report, and connectable undirected advertising
+        params.extend_from_slice(&[0x02, 1, 0]).unwrap();
+        push_address(&mut params, peripheral);
+        params.push(len as u8).unwrap();
+        params.extend_from_slice(&data[..len]).unwrap();
+        // The RSSI
+        params.push(-50i8 as u8).unwrap();
+        self.le_meta_event(&params);
+    }
+
+    /// An LE Connection Complete event for a successful connection as the central
+    fn connect(&self, peripheral: Address) {
+        let mut next_handle = self.next_handle.borrow_mut();
+        let handle = *next_handle;
+        *next_handle += 1;
+        let mut params = EventBytes::new();
+        // The subevent code and a successful status
+        params.extend_from_slice(&[0x01, 0]).unwrap();
+        params.extend_from_slice(&handle.to_le_bytes()).unwrap();
+        // The central role
+        params.push(0).unwrap();
+        push_address(&mut params, peripheral);
+        // A 50 ms connection interval, no peripheral latency, a 4 s supervision timeout, and the clock accuracy
+        params.extend_from_slice(&40u16.to_le_bytes()).unwrap();
+        params.extend_from_slice(&0u16.to_le_bytes()).unwrap();
+        params.extend_from_slice(&400u16.to_le_bytes()).unwrap();
+        params.push(0).unwrap();
+        self.le_meta_event(&params);
+    }
+
+    fn le_meta_event(&self, params: &[u8]) {
+        let mut event = EventBytes::new();
+        event
+            .extend_from_slice(&[0x3e, params.len() as u8])
+            .unwrap();
+        event.extend_from_slice(params).unwrap();
+        self.events.try_send(event).unwrap();
+    }
+}
+
+fn push_address(bytes: &mut EventBytes, address: Address) {
+    let mut kind = [0];
+    address.kind.write_hci(&mut kind[..]).unwrap();
+    bytes.extend_from_slice(&kind).unwrap();
+    bytes.extend_from_slice(address.addr.raw()).unwrap();
+}
+
+impl embedded_io::ErrorType for FakeController<'_> {
+    type Error = Infallible;
+}
+
+impl Controller for FakeController<'_> {
+    async fn write_acl_data(&self, _packet: &AclPacket<'_>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn write_sync_data(&self, _packet: &SyncPacket<'_>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn write_iso_data(&self, _packet: &IsoPacket<'_>) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn read<'a>(&self, buf: &'a mut [u8]) -> Result<ControllerToHostPacket<'a>, Self::Error> {
+        let event = self.events.receive().await;
+        let buf = &mut buf[..event.len()];
+        buf.copy_from_slice(&event);
+        Ok(
+            ControllerToHostPacket::from_hci_bytes_with_kind(PacketKind::Event, buf)
+                .unwrap()
+                .0,
+        )
+    }
+}
+
+impl<C: SyncCmd> ControllerCmdSync<C> for FakeController<'_> {
+    async fn exec(&self, cmd: &C) -> Result<C::Return, cmd::Error<Self::Error>> {
+        self.command(C::OPCODE, cmd.params());
+        Ok(C::Return::from_hci_bytes(C::ReturnBuf::new().as_ref())
+            .unwrap()
+            .0)
+    }
+}
+
+impl<C: AsyncCmd> ControllerCmdAsync<C> for FakeController<'_> {
+    async fn exec(&self, cmd: &C) -> Result<(), cmd::Error<Self::Error>> {
+        self.command(C::OPCODE, cmd.params());
+        Ok(())
+    }
+}
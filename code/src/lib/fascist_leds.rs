@@ -0,0 +1,126 @@
+use common::correct;
+use game_pure::{AuraLedColor, LedsDisplay, Team};
+use smart_leds::RGB8;
+
+use crate::{BoardLeds, config::LedLayout};
+
+pub const FASCIST_LED_LAYOUT: LedLayout = if cfg!(feature = "fascist-v2") {
+    LedLayout::fascist_v2()
+} else {
+    LedLayout::fascist_v1()
+};
+pub const FASCIST_TOTAL_LEDS: usize = FASCIST_LED_LAYOUT.total_leds;
+const _: () = assert!(
+    FASCIST_LED_LAYOUT.validate().is_ok(),
+    "an LED in the layout is past FASCIST_TOTAL_LEDS or used more than once"
+);
+pub const FASCIST_BOARD_LEDS: BoardLeds = BoardLeds {
+    layout: FASCIST_LED_LAYOUT,
+    board: Team::Fascist,
+};
+
+const AURA_COLOR: RGB8 = RGB8::new(255, 50, 50);
+const POLICY_COLOR: RGB8 = RGB8::new(255, 0, 0);
+/// The same blue as the liberal board's policies
+const LIBERAL_WIN_COLOR: RGB8 = RGB8::new(0, 127, 255);
+
+/// The steady colors of the fascist board's LEDs, before fading and blinking.
+/// `leds` is `None` until the liberal board synced, which shows only a dim aura to show that the board is waiting.
+pub fn fascist_leds_frame(
+    leds: Option<&LedsDisplay>,
+    led_brightness: u8,
+) -> [RGB8; FASCIST_TOTAL_LEDS] {
+    let mut led_colors = [Default::default(); FASCIST_TOTAL_LEDS];
+    let Some(leds) = leds else {
+        // Same as a paused game
+        let brightness = (led_brightness / 4).max(led_brightness.min(1));
+        for &aura_led_index in FASCIST_LED_LAYOUT.aura {
+            led_colors[aura_led_index] = correct(AURA_COLOR, brightness);
+        }
+        return led_colors;
+    };
+    let brightness = leds.brightness(led_brightness);
+    let aura_color = match leds.aura_led_color {
+        AuraLedColor::BoardSpecific => AURA_COLOR,
+        AuraLedColor::LiberalWin => LIBERAL_WIN_COLOR,
+        AuraLedColor::FascistWin => POLICY_COLOR,
+    };
+    for &aura_led_index in FASCIST_LED_LAYOUT.aura {
+        led_colors[aura_led_index] = correct(aura_color, brightness);
+    }
+    for policy in FASCIST_LED_LAYOUT
+        .policies
+        .iter()
+        .take(leds.fascist_policy_leds)
+    {
+        for &led_index in policy {
+            led_colors[led_index] = correct(POLICY_COLOR, brightness);
+        }
+    }
+    FASCIST_BOARD_LEDS.draw_election_tracker(leds, &mut led_colors, brightness);
+    led_colors
+}
+
+/// A liberal policy card was just placed on the fascist board, so the aura blinks for [`game_pure::MISPLACED_BLINK_TICKS`]
+pub fn fascist_aura_blinks(leds: Option<&LedsDisplay>) -> bool {
+    leds.is_some_and(|leds| leds.misplaced_board == Some(Team::Fascist))
+}
+
+#[cfg(test)]
+mod tests {
+    use game_pure::sync::GamePhase;
+
+    use super::*;
+
+    fn lit(frame: &[RGB8]) -> impl Iterator<Item = usize> + '_ {
+        frame
+            .iter()
+            .enumerate()
+            .filter(|(_, color)| **color != RGB8::default())
+            .map(|(i, _)| i)
+    }
+
+    #[test]
+    fn liberal_win() {
+        let leds = LedsDisplay {
+            aura_led_color: AuraLedColor::LiberalWin,
+            liberal_policy_leds: 5,
+            fascist_policy_leds: 4,
+            election_tracker_leds: 0,
+            election_tracker_warning: false,
+            blink_aura: false,
+            misplaced_board: None,
+            dimmed: false,
+            election_tracker_placement: Default::default(),
+            phase: GamePhase::LiberalWin,
+        };
+        let frame = fascist_leds_frame(Some(&leds), 255);
+        let mut expected = FASCIST_LED_LAYOUT
+            .aura
+            .iter()
+            .chain(FASCIST_LED_LAYOUT.policies[..4].as_flattened())
+            .copied()
+            .collect::<heapless::Vec<_, FASCIST_TOTAL_LEDS>>();
+        expected.sort_unstable();
+        assert!(lit(&frame).eq(expected));
+        for &aura_led_index in FASCIST_LED_LAYOUT.aura {
+            assert_eq!(frame[aura_led_index], correct(LIBERAL_WIN_COLOR, 255));
+        }
+        for &led_index in FASCIST_LED_LAYOUT.policies[..4].as_flattened() {
+            assert_eq!(frame[led_index], correct(POLICY_COLOR, 255));
+        }
+        assert!(!fascist_aura_blinks(Some(&leds)));
+    }
+
+    #[test]
+    fn waiting() {
+        let frame = fascist_leds_frame(None, 200);
+        let mut expected =
+            heapless::Vec::<_, FASCIST_TOTAL_LEDS>::from_slice(FASCIST_LED_LAYOUT.aura).unwrap();
+        expected.sort_unstable();
+        assert!(lit(&frame).eq(expected));
+        // Dimmer than while playing
+        assert_eq!(frame[FASCIST_LED_LAYOUT.aura[0]], correct(AURA_COLOR, 50));
+        assert!(!fascist_aura_blinks(None));
+    }
+}
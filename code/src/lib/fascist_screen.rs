@@ -0,0 +1,157 @@
+use core::fmt::Write;
+
+use defmt::Format;
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, ascii::FONT_10X20, iso_8859_16::FONT_7X14},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::Rectangle,
+};
+use game_pure::fmt_bd_addr;
+use trouble_host::prelude::BdAddr;
+
+use crate::{
+    CenteredElement, Display, DisplayInitRetry, DrawWriter, Element, PaddedElement, TextElement,
+    recover_flush,
+};
+
+/// What the fascist board's display shows
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum FascistScreen {
+    /// Our address, so that the fascist board can be told apart when scanning from the liberal board
+    Address,
+    /// The passkey that has to be entered on the liberal board to finish pairing
+    Passkey(u32),
+}
+
+/// The connection events that change [`FascistScreen`].
+/// The pairing events only happen with trouble-host's `security` feature.
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum PairingEvent {
+    PassKeyDisplay(u32),
+    PairingComplete,
+    PairingFailed,
+    Disconnected,
+}
+
+impl FascistScreen {
+    /// The passkey is shown until pairing is done, whether it worked or not
+    pub fn after(self, event: PairingEvent) -> Self {
+        match event {
+            PairingEvent::PassKeyDisplay(passkey) => Self::Passkey(passkey),
+            PairingEvent::PairingComplete
+            | PairingEvent::PairingFailed
+            | PairingEvent::Disconnected => Self::Address,
+        }
+    }
+}
+
+/// Always 6 digits, like `000123`, so that leading zeros aren't missed when typing it in
+pub fn passkey_text(passkey: u32) -> heapless::String<6> {
+    let mut text = heapless::String::new();
+    // Passkeys only go up to 999999
+    let _ = write!(text, "{:06}", passkey % 1_000_000);
+    text
+}
+
+/// Clears the display and draws `screen`. The display still needs to be flushed.
+pub fn draw_fascist_screen<D: DrawTarget<Color = BinaryColor>>(
+    display: &mut D,
+    screen: FascistScreen,
+    address: BdAddr,
+) {
+    let _ = display.clear(BinaryColor::Off);
+    let small = MonoTextStyle::new(&FONT_7X14, BinaryColor::On);
+    match screen {
+        FascistScreen::Address => {
+            let _ = write!(
+                DrawWriter::new(display, Point::zero(), small),
+                "{}",
+                fmt_bd_addr(&address)
+            );
+        }
+        FascistScreen::Passkey(passkey) => {
+            let bounding_box = display.bounding_box();
+            let Ok(title) = PaddedElement {
+                element: CenteredElement {
+                    element: TextElement {
+                        text: "Passkey",
+                        character_style: small,
+                    },
+                    vertical: false,
+                },
+                top: 4,
+                bottom: 0,
+                left: 0,
+                right: 0,
+            }
+            .draw(display, bounding_box) else {
+                return;
+            };
+            // The digits are big and in the middle of the rest of the display, so that they're easy to type in
+            let _ = CenteredElement {
+                element: TextElement {
+                    text: passkey_text(passkey),
+                    character_style: MonoTextStyle::new(&FONT_10X20, BinaryColor::On),
+                },
+                vertical: true,
+            }
+            .draw(
+                display,
+                Rectangle::new(
+                    Point::new(0, title.size.height as i32),
+                    Size::new(
+                        bounding_box.size.width,
+                        bounding_box.size.height.saturating_sub(title.size.height),
+                    ),
+                ),
+            );
+        }
+    }
+}
+
+/// Draws `screen` and flushes it, recovering from a failed flush with [`recover_flush`].
+/// Returns `false` if the display stopped responding.
+pub async fn show_fascist_screen<D: Display>(
+    display: &mut D,
+    screen: FascistScreen,
+    address: BdAddr,
+    init_retry: &mut DisplayInitRetry,
+) -> bool {
+    draw_fascist_screen(display, screen, address);
+    match display.flush().await {
+        Ok(()) => true,
+        Err(e) => {
+            recover_flush(display, e, init_retry, async |display| {
+                draw_fascist_screen(display, screen, address);
+                display.flush().await
+            })
+            .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn passkey_zero_padded() {
+        assert_eq!(passkey_text(0), "000000");
+        assert_eq!(passkey_text(123), "000123");
+        assert_eq!(passkey_text(999_999), "999999");
+    }
+
+    #[test]
+    fn passkey_until_paired() {
+        let screen = FascistScreen::Address.after(PairingEvent::PassKeyDisplay(42));
+        assert_eq!(screen, FascistScreen::Passkey(42));
+        for event in [
+            PairingEvent::PairingComplete,
+            PairingEvent::PairingFailed,
+            PairingEvent::Disconnected,
+        ] {
+            assert_eq!(screen.after(event), FascistScreen::Address);
+        }
+    }
+}
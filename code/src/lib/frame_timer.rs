@@ -0,0 +1,149 @@
+use defmt::Format;
+use embassy_time::Instant;
+use heapless::HistoryBuf;
+use strum::VariantArray;
+
+/// The parts of rendering a frame that are timed
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq, VariantArray)]
+pub enum FrameSection {
+    /// Clearing the buffer and figuring out what to show
+    Layout,
+    /// Drawing the elements into the display buffer
+    Draw,
+    /// Sending the buffer to the display
+    Flush,
+}
+
+/// All durations are in µs
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct SectionStats {
+    pub min: u32,
+    pub avg: u32,
+    pub max: u32,
+}
+
+/// Times each [`FrameSection`] over the last `N` frames, to find out where rendering is slow.
+pub struct FrameTimer<const N: usize> {
+    /// The most recent durations of each section, in µs
+    samples: [HistoryBuf<u32, N>; FrameSection::VARIANTS.len()],
+    /// When the current section started
+    lap_start: Instant,
+    frames: u32,
+    /// [`FrameTimer::end_frame`] returns `true` once every this many frames
+    dump_interval: u32,
+}
+
+impl<const N: usize> FrameTimer<N> {
+    pub const fn new(dump_interval: u32) -> Self {
+        Self {
+            samples: [const { HistoryBuf::new() }; FrameSection::VARIANTS.len()],
+            lap_start: Instant::from_ticks(0),
+            frames: 0,
+            dump_interval,
+        }
+    }
+
+    /// Call this at the start of each frame, before the first [`FrameTimer::lap`]
+    pub fn start_frame(&mut self) {
+        self.lap_start = Instant::now();
+    }
+
+    /// Records the time since the previous lap (or the start of the frame) as `section`
+    pub fn lap(&mut self, section: FrameSection) {
+        let now = Instant::now();
+        self.record(
+            section,
+            (now - self.lap_start)
+                .as_micros()
+                .try_into()
+                .unwrap_or(u32::MAX),
+        );
+        self.lap_start = now;
+    }
+
+    /// Adds a duration in µs to the rolling window of `section`, forgetting the oldest one if the window is full
+    pub fn record(&mut self, section: FrameSection, duration: u32) {
+        self.samples[section as usize].write(duration);
+    }
+
+    /// Returns `true` if the stats should be logged for this frame
+    pub fn end_frame(&mut self) -> bool {
+        self.frames = self.frames.wrapping_add(1);
+        self.dump_interval != 0 && self.frames.is_multiple_of(self.dump_interval)
+    }
+
+    /// `None` if nothing was recorded for this section yet
+    pub fn stats(&self, section: FrameSection) -> Option<SectionStats> {
+        let samples = self.samples[section as usize].as_slice();
+        Some(SectionStats {
+            min: *samples.iter().min()?,
+            avg: (samples.iter().map(|&sample| u64::from(sample)).sum::<u64>()
+                / samples.len() as u64) as u32,
+            max: *samples.iter().max()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_avg_max() {
+        let mut timer = FrameTimer::<4>::new(0);
+        assert_eq!(timer.stats(FrameSection::Draw), None);
+        for duration in [300, 100, 200] {
+            timer.record(FrameSection::Draw, duration);
+        }
+        timer.record(FrameSection::Flush, 20_000);
+        assert_eq!(
+            timer.stats(FrameSection::Draw),
+            Some(SectionStats {
+                min: 100,
+                avg: 200,
+                max: 300
+            })
+        );
+        assert_eq!(
+            timer.stats(FrameSection::Flush),
+            Some(SectionStats {
+                min: 20_000,
+                avg: 20_000,
+                max: 20_000
+            })
+        );
+        assert_eq!(timer.stats(FrameSection::Layout), None);
+    }
+
+    #[test]
+    fn rolling_window() {
+        let mut timer = FrameTimer::<3>::new(0);
+        for duration in [1_000, 10, 20, 30] {
+            timer.record(FrameSection::Layout, duration);
+        }
+        // The oldest duration was forgotten
+        assert_eq!(
+            timer.stats(FrameSection::Layout),
+            Some(SectionStats {
+                min: 10,
+                avg: 20,
+                max: 30
+            })
+        );
+        // The average doesn't overflow
+        for _ in 0..3 {
+            timer.record(FrameSection::Layout, u32::MAX);
+        }
+        assert_eq!(timer.stats(FrameSection::Layout).unwrap().avg, u32::MAX);
+    }
+
+    #[test]
+    fn dump_interval() {
+        let mut timer = FrameTimer::<3>::new(3);
+        let dumps: [_; 7] = core::array::from_fn(|_| timer.end_frame());
+        assert_eq!(dumps, [false, false, true, false, false, true, false]);
+        // 0 never dumps
+        let mut timer = FrameTimer::<3>::new(0);
+        assert!((0..10).all(|_| !timer.end_frame()));
+    }
+}
@@ -0,0 +1,120 @@
+//! The GATT service that lets a spectator phone app follow the game on the fascist board.
+//!
+//! It is read only and doesn't need pairing. Everything in it is already shown on the boards,
+//! and the fascist board has no buttons to confirm pairing with anyway.
+
+use defmt::{info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
+use game_pure::sync::SpectatorState;
+use trouble_host::prelude::*;
+
+use crate::{CONNECTIONS_MAX, SERVICE_UUID};
+
+#[gatt_server(connections_max = CONNECTIONS_MAX)]
+pub struct SpectatorServer {
+    pub spectator: SpectatorService,
+}
+
+/// Each characteristic is one byte of [`SpectatorState::encode`]
+#[gatt_service(uuid = SERVICE_UUID)]
+pub struct SpectatorService {
+    #[characteristic(uuid = "85d47ecb-91e5-4ddb-9c23-0579415f46af", read, notify)]
+    pub liberal_policies: u8,
+    #[characteristic(uuid = "85d47ecc-91e5-4ddb-9c23-0579415f46af", read, notify)]
+    pub fascist_policies: u8,
+    #[characteristic(uuid = "85d47ecd-91e5-4ddb-9c23-0579415f46af", read, notify)]
+    pub election_tracker: u8,
+    #[characteristic(uuid = "85d47ece-91e5-4ddb-9c23-0579415f46af", read, notify)]
+    pub phase: u8,
+    #[characteristic(uuid = "85d47ecf-91e5-4ddb-9c23-0579415f46af", read, notify)]
+    pub winner: u8,
+}
+
+impl SpectatorService {
+    /// In the same order as [`SpectatorState::encode`]
+    fn characteristics(&self) -> [&Characteristic<u8>; 5] {
+        [
+            &self.liberal_policies,
+            &self.fascist_policies,
+            &self.election_tracker,
+            &self.phase,
+            &self.winner,
+        ]
+    }
+}
+
+impl SpectatorServer<'_> {
+    /// Changes what spectators read, without notifying anyone
+    pub fn set_state(&self, state: SpectatorState) {
+        for (characteristic, value) in self
+            .spectator
+            .characteristics()
+            .into_iter()
+            .zip(state.encode())
+        {
+            if let Err(e) = characteristic.set(self, &value) {
+                warn!("Failed to set spectator characteristic: {}", e);
+            }
+        }
+    }
+
+    /// Changes what spectators read, and notifies `conn` if it subscribed
+    pub async fn notify_state(
+        &self,
+        conn: &GattConnection<'_, '_, DefaultPacketPool>,
+        state: SpectatorState,
+    ) -> Result<(), Error> {
+        for (characteristic, value) in self
+            .spectator
+            .characteristics()
+            .into_iter()
+            .zip(state.encode())
+        {
+            characteristic.notify(conn, &value).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Answers a read or a subscription. The values are read from the server's table.
+pub async fn reply_gatt(event: GattEvent<'_, '_, DefaultPacketPool>) {
+    match event.accept() {
+        Ok(reply) => reply.send().await,
+        Err(e) => warn!("Failed to accept GATT event: {}", e),
+    }
+}
+
+/// Answers GATT requests until `conn` disconnects, while waiting for something else on the connection
+pub async fn serve_gatt(conn: &GattConnection<'_, '_, DefaultPacketPool>) {
+    loop {
+        match conn.next().await {
+            GattConnectionEvent::Disconnected { .. } => break,
+            GattConnectionEvent::Gatt { event } => reply_gatt(event).await,
+            _ => {}
+        }
+    }
+}
+
+/// Serves a spectator until they disconnect, notifying them whenever `state` is signaled
+pub async fn serve_spectator(
+    server: &SpectatorServer<'_>,
+    conn: &GattConnection<'_, '_, DefaultPacketPool>,
+    state: &Signal<CriticalSectionRawMutex, SpectatorState>,
+) {
+    loop {
+        match select(conn.next(), state.wait()).await {
+            Either::First(GattConnectionEvent::Disconnected { reason }) => {
+                info!("Spectator disconnected. reason: {}", reason);
+                break;
+            }
+            Either::First(GattConnectionEvent::Gatt { event }) => reply_gatt(event).await,
+            Either::First(_) => {}
+            Either::Second(state) => {
+                if let Err(e) = server.notify_state(conn, state).await {
+                    warn!("Failed to notify spectator: {}", e);
+                }
+            }
+        }
+    }
+}
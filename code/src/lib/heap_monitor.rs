@@ -0,0 +1,74 @@
+use core::cell::Cell;
+
+use defmt::{Format, info};
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embassy_time::Timer;
+
+use crate::config::HEAP_LOG_INTERVAL;
+
+#[cfg(feature = "esp")]
+fn heap_used_free() -> (usize, usize) {
+    (esp_alloc::HEAP.used(), esp_alloc::HEAP.free())
+}
+
+/// There is no ESP heap when rendering on the host
+#[cfg(not(feature = "esp"))]
+fn heap_used_free() -> (usize, usize) {
+    (0, 0)
+}
+
+/// All sizes are in bytes
+#[derive(Debug, Format, Clone, Copy)]
+pub struct HeapStats {
+    pub used: usize,
+    pub free: usize,
+    /// The most heap that was used at once since booting
+    pub peak_used: usize,
+}
+
+/// Keeps track of how much heap is used.
+///
+/// Running out of memory is a panic, so there is nothing to count when an allocation fails.
+/// Instead, the peak usage shows how close we get to running out.
+/// The peak is only as accurate as how often [`HeapMonitor::sample`] is called.
+pub struct HeapMonitor {
+    peak_used: Mutex<CriticalSectionRawMutex, Cell<usize>>,
+}
+
+impl HeapMonitor {
+    pub const fn new() -> Self {
+        Self {
+            peak_used: Mutex::new(Cell::new(0)),
+        }
+    }
+
+    /// Call this after doing something that allocates, so that the peak includes it
+    pub fn sample(&self) -> HeapStats {
+        let (used, free) = heap_used_free();
+        let peak_used = self.peak_used.lock(|peak_used| {
+            peak_used.set(peak_used.get().max(used));
+            peak_used.get()
+        });
+        HeapStats {
+            used,
+            free,
+            peak_used,
+        }
+    }
+
+    /// Logs the heap usage every [`HEAP_LOG_INTERVAL`]
+    pub async fn run(&self) -> ! {
+        loop {
+            info!("Heap: {}", self.sample());
+            Timer::after(HEAP_LOG_INTERVAL).await;
+        }
+    }
+}
+
+impl Default for HeapMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub static HEAP_MONITOR: HeapMonitor = HeapMonitor::new();
@@ -0,0 +1,218 @@
+use core::any::Any;
+
+use common::{Click, ClickClassifier};
+use embassy_futures::select::{Either, select};
+use embassy_time::{Duration, Instant, Timer};
+use game_pure::Input;
+use mcp23017_controller::Pin;
+
+use crate::{Direction, RotaryButton, RotaryInput};
+
+/// Where the rotary encoder's inputs come from, so that the game doesn't care whether the encoder
+/// is connected directly or through the GPIO expander
+#[allow(async_fn_in_trait)]
+pub trait InputSource {
+    /// Waits until the encoder is turned or its button is pressed
+    async fn next_input(&mut self) -> Input;
+}
+
+fn direction_input(direction: Direction) -> Input {
+    match direction {
+        Direction::Clockwise => Input::Down,
+        Direction::CounterClockwise => Input::Up,
+    }
+}
+
+/// The encoder connected to the MCP23017.
+///
+/// The expander shares the I2C bus with the display. Both lock the bus for one transaction at a time,
+/// and neither waits on the other while holding it, so a watch interrupt during a display flush only
+/// waits for the current transaction to finish.
+pub struct ExpanderInput<'a> {
+    rotary_input: RotaryInput<'a>,
+    rotary_button: RotaryButton<'a>,
+}
+
+impl<'a> ExpanderInput<'a> {
+    pub async fn new(
+        dt: Pin<'a, impl Any>,
+        clk: Pin<'a, impl Any>,
+        switch: Pin<'a, impl Any>,
+    ) -> Self {
+        Self {
+            rotary_input: RotaryInput::new(dt, clk).await,
+            rotary_button: RotaryButton::new(switch).await,
+        }
+    }
+}
+
+impl InputSource for ExpanderInput<'_> {
+    async fn next_input(&mut self) -> Input {
+        match select(
+            self.rotary_input.next(),
+            self.rotary_button.wait_until_press(),
+        )
+        .await
+        {
+            Either::First(direction) => direction_input(direction),
+            Either::Second(()) => Input::Click,
+        }
+    }
+}
+
+/// Turns two clicks close together from `I` into [`Input::DoubleClick`].
+/// Single clicks are delayed by the double click window, but turning the encoder isn't.
+pub struct DoubleClickInput<I> {
+    inner: I,
+    clicks: ClickClassifier,
+    /// An input that came while waiting for a second click, which goes after the single click
+    next: Option<Input>,
+}
+
+impl<I> DoubleClickInput<I> {
+    pub fn new(inner: I, window: Duration) -> Self {
+        Self {
+            inner,
+            clicks: ClickClassifier::new(window.as_millis()),
+            next: None,
+        }
+    }
+}
+
+fn click_input(click: Click) -> Input {
+    match click {
+        Click::Single => Input::Click,
+        Click::Double => Input::DoubleClick,
+    }
+}
+
+impl<I: InputSource> InputSource for DoubleClickInput<I> {
+    async fn next_input(&mut self) -> Input {
+        if let Some(input) = self.next.take() {
+            return input;
+        }
+        loop {
+            let input = match self.clicks.deadline_ms() {
+                Some(deadline_ms) => match select(
+                    self.inner.next_input(),
+                    // The deadline is the last ms that a second click counts
+                    Timer::at(Instant::from_millis(deadline_ms + 1)),
+                )
+                .await
+                {
+                    Either::First(input) => input,
+                    Either::Second(()) => match self.clicks.poll(Instant::now().as_millis()) {
+                        Some(click) => return click_input(click),
+                        None => continue,
+                    },
+                },
+                None => self.inner.next_input().await,
+            };
+            match input {
+                Input::Click => {
+                    if let Some(click) = self.clicks.press(Instant::now().as_millis()) {
+                        return click_input(click);
+                    }
+                }
+                input => match self.clicks.flush() {
+                    Some(click) => {
+                        self.next = Some(input);
+                        return click_input(click);
+                    }
+                    None => return input,
+                },
+            }
+        }
+    }
+}
+
+#[cfg(feature = "esp")]
+pub use direct_gpio::*;
+
+#[cfg(feature = "esp")]
+mod direct_gpio {
+    use core::sync::atomic::Ordering;
+
+    use embassy_futures::select::{select, select4};
+    use embassy_time::Duration;
+    use embedded_hal::digital::PinState;
+    use esp_hal::gpio::Input as GpioInput;
+    use game_pure::Input;
+
+    use super::{InputSource, direction_input};
+    use crate::{Debouncer, ROTARY_RESYNCS, RotaryEncoder, RotaryPinsState};
+
+    fn pin_state(pin: &GpioInput) -> PinState {
+        if pin.is_low() {
+            PinState::Low
+        } else {
+            PinState::High
+        }
+    }
+
+    /// The encoder connected straight to the ESP's GPIOs, for boards without the GPIO expander.
+    /// The pins should be configured with pull ups.
+    pub struct DirectGpioInput<'a> {
+        dt: GpioInput<'a>,
+        dt_debounce: Debouncer<PinState>,
+        clk: GpioInput<'a>,
+        clk_debounce: Debouncer<PinState>,
+        switch: GpioInput<'a>,
+        switch_debounce: Debouncer<PinState>,
+        rotary_encoder: RotaryEncoder,
+    }
+
+    impl<'a> DirectGpioInput<'a> {
+        pub fn new(dt: GpioInput<'a>, clk: GpioInput<'a>, switch: GpioInput<'a>) -> Self {
+            let debounce_time = Duration::from_millis(1);
+            let dt_debounce = Debouncer::new(pin_state(&dt), debounce_time);
+            let clk_debounce = Debouncer::new(pin_state(&clk), debounce_time);
+            let switch_debounce = Debouncer::new(pin_state(&switch), debounce_time);
+            let rotary_encoder = RotaryEncoder::new(RotaryPinsState {
+                dt: dt_debounce.value() == PinState::Low,
+                clk: clk_debounce.value() == PinState::Low,
+            });
+            Self {
+                dt,
+                dt_debounce,
+                clk,
+                clk_debounce,
+                switch,
+                switch_debounce,
+                rotary_encoder,
+            }
+        }
+    }
+
+    impl InputSource for DirectGpioInput<'_> {
+        async fn next_input(&mut self) -> Input {
+            loop {
+                select(
+                    select4(
+                        self.dt.wait_for_any_edge(),
+                        self.dt_debounce.wait(),
+                        self.clk.wait_for_any_edge(),
+                        self.clk_debounce.wait(),
+                    ),
+                    select(self.switch.wait_for_any_edge(), self.switch_debounce.wait()),
+                )
+                .await;
+                self.dt_debounce.process_data(pin_state(&self.dt));
+                self.clk_debounce.process_data(pin_state(&self.clk));
+                let pressed = self.switch_debounce.process_data(pin_state(&self.switch))
+                    && self.switch_debounce.value() == PinState::Low;
+                if pressed {
+                    break Input::Click;
+                }
+                let direction = self.rotary_encoder.process_data(RotaryPinsState {
+                    dt: self.dt_debounce.value() == PinState::Low,
+                    clk: self.clk_debounce.value() == PinState::Low,
+                });
+                ROTARY_RESYNCS.store(self.rotary_encoder.resyncs(), Ordering::Relaxed);
+                if let Some(direction) = direction {
+                    break direction_input(direction);
+                }
+            }
+        }
+    }
+}
@@ -92,6 +92,17 @@ where
     }
 }
 
+impl<S, C> From<Error<S, C>> for crate::Error
+where
+    S: SpiBus + SetConfig,
+    <S as SetConfig>::ConfigError: Debug,
+    C: OutputPin,
+{
+    fn from(e: Error<S, C>) -> Self {
+        Self::Spi(e.kind())
+    }
+}
+
 impl<S, C> embedded_hal::spi::Error for Error<S, C>
 where
     S: SpiBus + SetConfig,
@@ -91,6 +91,17 @@ where
     }
 }
 
+impl<S, C> From<Error2<S, C>> for crate::Error
+where
+    S: SpiBus + SetConfig,
+    <S as SetConfig>::ConfigError: Debug,
+    C: OutputPin,
+{
+    fn from(e: Error2<S, C>) -> Self {
+        Self::Spi(e.kind())
+    }
+}
+
 impl<S, C> embedded_hal::spi::Error for Error2<S, C>
 where
     S: SpiBus + SetConfig,
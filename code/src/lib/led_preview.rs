@@ -0,0 +1,219 @@
+use embedded_graphics::{
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, PrimitiveStyleBuilder, Rectangle, StrokeAlignment},
+};
+use game_pure::{AuraLedColor, ElectionTrackerPlacement, LedsDisplay, sync::GamePhase};
+use smart_leds::RGB8;
+
+use crate::{
+    Element, ElementHeight, LIBERAL_LED_LAYOUT,
+    config::{LED_GRID_SIZE, LedLayout},
+    liberal_leds_frame,
+};
+
+const GRID_LEDS: usize = LED_GRID_SIZE * LED_GRID_SIZE;
+
+/// An LED with a channel at least this bright, after gamma correction and brightness, is drawn as on
+pub const PREVIEW_THRESHOLD: u8 = 8;
+
+/// A game with 3 liberal and 2 fascist policies and one failed election, so that every kind of LED is lit
+pub fn preview_leds() -> LedsDisplay {
+    LedsDisplay {
+        aura_led_color: AuraLedColor::BoardSpecific,
+        liberal_policy_leds: 3,
+        fascist_policy_leds: 2,
+        election_tracker_leds: 1,
+        election_tracker_warning: false,
+        blink_aura: false,
+        misplaced_board: None,
+        dimmed: false,
+        election_tracker_placement: ElectionTrackerPlacement::Liberal,
+        phase: GamePhase::Playing,
+    }
+}
+
+/// Which LEDs of `frame` are bright enough to be seen, since the display can only show on or off.
+/// LEDs past the end of `frame` are off.
+pub fn threshold_frame(frame: &[RGB8]) -> [bool; GRID_LEDS] {
+    core::array::from_fn(|i| {
+        frame
+            .get(i)
+            .is_some_and(|color| color.r.max(color.g).max(color.b) >= PREVIEW_THRESHOLD)
+    })
+}
+
+/// An LED frame reduced to what the display can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LedPreview {
+    /// See [`threshold_frame`]
+    pub lit: [bool; GRID_LEDS],
+    /// Lit aura LEDs are outlined as a ring around the grid
+    pub aura: &'static [usize],
+}
+
+impl LedPreview {
+    pub fn new(frame: &[RGB8], layout: &LedLayout) -> Self {
+        Self {
+            lit: threshold_frame(frame),
+            aura: layout.aura,
+        }
+    }
+
+    /// The liberal board's LEDs for [`preview_leds`] at `led_brightness`
+    pub fn liberal(led_brightness: u8) -> Self {
+        Self::new(
+            &liberal_leds_frame(&preview_leds(), led_brightness),
+            &LIBERAL_LED_LAYOUT,
+        )
+    }
+
+    fn aura_lit(&self) -> bool {
+        self.aura.iter().any(|&led| self.lit[led])
+    }
+}
+
+/// Draws a [`LedPreview`] as a grid of `block_size` px squares, with a 1 px outline for the aura ring
+/// and a 1 px gap between the outline and the grid
+pub struct LedPreviewElement {
+    pub preview: LedPreview,
+    /// 1 or 2 px, so that the preview fits next to a menu
+    pub block_size: u32,
+}
+
+impl LedPreviewElement {
+    fn side(&self) -> u32 {
+        LED_GRID_SIZE as u32 * self.block_size + 4
+    }
+}
+
+impl<D: DrawTarget<Color = BinaryColor>> Element<D> for LedPreviewElement {
+    fn draw(&self, display: &mut D, bounding_box: Rectangle) -> Result<Rectangle, D::Error> {
+        let size = Size::new_equal(self.side());
+        if self.preview.aura_lit() {
+            Rectangle::new(bounding_box.top_left, size)
+                .into_styled(
+                    PrimitiveStyleBuilder::new()
+                        .stroke_color(BinaryColor::On)
+                        .stroke_width(1)
+                        .stroke_alignment(StrokeAlignment::Inside)
+                        .build(),
+                )
+                .draw(display)?;
+        }
+        let grid = bounding_box.top_left + Point::new_equal(2);
+        for (led, _) in self.preview.lit.iter().enumerate().filter(|(_, lit)| **lit) {
+            let x = (led % LED_GRID_SIZE) as u32 * self.block_size;
+            let y = (led / LED_GRID_SIZE) as u32 * self.block_size;
+            Rectangle::new(
+                grid + Point::new(x as i32, y as i32),
+                Size::new_equal(self.block_size),
+            )
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(display)?;
+        }
+        Ok(Rectangle::new(Point::zero(), size))
+    }
+
+    fn height(&self, _width: u32) -> ElementHeight {
+        ElementHeight::Fixed(self.side())
+    }
+
+    fn width(&self) -> Option<u32> {
+        Some(self.side())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::mock_display::MockDisplay;
+
+    use super::*;
+    use crate::config::led_grid_index;
+
+    #[test]
+    fn threshold() {
+        let frame = [
+            RGB8::new(0, 0, 0),
+            RGB8::new(PREVIEW_THRESHOLD - 1, 0, PREVIEW_THRESHOLD - 1),
+            RGB8::new(0, PREVIEW_THRESHOLD, 0),
+            RGB8::new(255, 0, 255),
+        ];
+        let lit = threshold_frame(&frame);
+        assert_eq!(lit[..4], [false, false, true, true]);
+        // The rest of the grid isn't in the frame
+        assert!(lit[4..].iter().all(|lit| !lit));
+    }
+
+    #[test]
+    fn liberal_preview() {
+        let preview = LedPreview::liberal(255);
+        for &led in LIBERAL_LED_LAYOUT.aura {
+            assert!(preview.lit[led]);
+        }
+        assert!(preview.lit[led_grid_index(1, 6)]);
+        assert!(!preview.lit[led_grid_index(2, 6)]);
+        // Too dim to tell apart from off on the display
+        let off = LedPreview::liberal(0);
+        assert!(off.lit.iter().all(|lit| !lit));
+        assert!(!off.aura_lit());
+    }
+
+    #[test]
+    fn golden_image() {
+        let mut display = MockDisplay::new();
+        let element = LedPreviewElement {
+            preview: LedPreview::liberal(255),
+            block_size: 1,
+        };
+        let bounding_box = display.bounding_box();
+        let used = element.draw(&mut display, bounding_box).unwrap();
+        assert_eq!(used.size, Size::new_equal(12));
+        display.assert_pattern(&[
+            "############",
+            "#          #",
+            "# #     #  #",
+            "#  #####   #",
+            "# #     #  #",
+            "#  #####   #",
+            "# #     #  #",
+            "#          #",
+            "#  #       #",
+            "#          #",
+            "#          #",
+            "############",
+        ]);
+
+        let mut display = MockDisplay::new();
+        let bounding_box = display.bounding_box();
+        LedPreviewElement {
+            preview: LedPreview::new(&[RGB8::new(255, 255, 255)], &LedLayout::liberal_v1()),
+            block_size: 2,
+        }
+        .draw(&mut display, bounding_box)
+        .unwrap();
+        // Only the first LED, which is an aura LED
+        display.assert_pattern(&[
+            "####################",
+            "#                  #",
+            "# ##               #",
+            "# ##               #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "#                  #",
+            "####################",
+        ]);
+    }
+}
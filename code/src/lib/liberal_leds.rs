@@ -0,0 +1,37 @@
+use common::correct;
+use game_pure::{LedsDisplay, Team};
+use smart_leds::RGB8;
+
+use crate::{BoardLeds, config::LedLayout};
+
+pub const LIBERAL_LED_LAYOUT: LedLayout = LedLayout::liberal_v1();
+pub const LIBERAL_TOTAL_LEDS: usize = LIBERAL_LED_LAYOUT.total_leds;
+const _: () = assert!(
+    LIBERAL_LED_LAYOUT.validate().is_ok(),
+    "an LED in the layout is past LIBERAL_TOTAL_LEDS or used more than once"
+);
+pub const LIBERAL_BOARD_LEDS: BoardLeds = BoardLeds {
+    layout: LIBERAL_LED_LAYOUT,
+    board: Team::Liberal,
+};
+
+const AURA_COLOR: RGB8 = RGB8::new(255, 0, 255);
+const POLICY_COLOR: RGB8 = RGB8::new(0, 127, 255);
+
+/// The steady colors of the liberal board's LEDs, before fading and blinking
+pub fn liberal_leds_frame(leds: &LedsDisplay, led_brightness: u8) -> [RGB8; LIBERAL_TOTAL_LEDS] {
+    // Dimmed while the game is paused or the supply voltage is critical
+    let brightness = leds.brightness(led_brightness);
+    let mut led_colors = [Default::default(); LIBERAL_TOTAL_LEDS];
+    for &aura_led_index in LIBERAL_LED_LAYOUT.aura {
+        led_colors[aura_led_index] = correct(AURA_COLOR, brightness);
+    }
+    for policy in LIBERAL_LED_LAYOUT.policies {
+        for &led_index in policy {
+            led_colors[led_index] = correct(POLICY_COLOR, brightness);
+        }
+    }
+    // Unless the fascist board shows it
+    LIBERAL_BOARD_LEDS.draw_election_tracker(leds, &mut led_colors, brightness);
+    led_colors
+}
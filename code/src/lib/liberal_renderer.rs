@@ -1,31 +1,51 @@
-use bt_hci::param::{AddrKind, BdAddr};
-use core::fmt::{Debug, Write};
-use defmt::{Format, info};
+use bt_hci::param::BdAddr;
+use core::{
+    cell::Cell,
+    fmt::{self, Debug, Write},
+    future::pending,
+    sync::atomic::Ordering,
+};
+use defmt::{Debug2Format, Format, info, warn};
+#[cfg(feature = "esp")]
 use embassy_embedded_hal::{SetConfig, shared_bus::asynch::i2c::I2cDeviceWithConfig};
 use embassy_futures::select::{Either, select};
-use embassy_sync::{blocking_mutex::raw::RawMutex, mutex::Mutex, signal::Signal};
-use embassy_time::{Instant, Timer};
+use embassy_sync::blocking_mutex::raw::RawMutex;
+#[cfg(feature = "esp")]
+use embassy_sync::mutex::Mutex;
+use embassy_time::{Duration, Instant, Timer};
 use embedded_graphics::{
-    mono_font::{MonoFont, MonoTextStyleBuilder, iso_8859_16::FONT_7X14},
+    mono_font::{
+        MonoFont, MonoTextStyle, MonoTextStyleBuilder,
+        ascii::{FONT_4X6, FONT_10X20},
+        iso_8859_16::FONT_7X14,
+    },
     pixelcolor::BinaryColor,
     prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
     text::{Baseline, Text},
 };
+#[cfg(feature = "esp")]
 use embedded_hal_async::i2c::I2c;
-use esp_hal::{gpio::Flex, i2c, time::Rate};
+#[cfg(feature = "esp")]
+use esp_hal::{efuse::Efuse, i2c, time::Rate};
 use game_pure::{
-    BluetoothScreen, ConnectState, ConnectionAction, GameScreen, GameState, MainMenuScreen,
-    MainMenuSelectedItem, ScanningSelectedItem,
-};
-use ssd1306::{
-    I2CDisplayInterface, Ssd1306Async, mode::DisplayConfigAsync, prelude::*,
-    size::DisplaySize128x64,
+    AboutScreen, BluetoothScreen, ConnectState, ConnectingConnectedSelectedItem, ConnectionAction,
+    EndGameSelectedItem, GameScreen, GameState, MainMenuScreen, MainMenuSelectedItem,
+    NoteEntrySelectedItem, PeripheralRole, PlayingScreen, RuntimeInfo, ScanningSelectedItem,
+    SupplyLevel, Team, TextEntryChoice, TextEntryPurpose, fmt_bd_addr, investigation_text, labels,
+    players_text, screen_text,
 };
+#[cfg(feature = "esp")]
+use ssd1306::{I2CDisplayInterface, Ssd1306Async, prelude::*, size::DisplaySize128x64};
 use strum::{EnumIter, VariantArray};
-use trouble_host::Address;
 
 use crate::{
-    Element, FlexElement, ListElement, ScrollYElement, TextElement, config::INVERT_SCREEN_INTERVAL,
+    BLE_UNAVAILABLE, CLOCK_SYNC, CenteredElement, Display, DisplayInitRetry, Element,
+    FIRMWARE_VERSION, FlexElement, FrameSection, FrameTimer, GIT_SHORT_HASH, HEAP_MONITOR,
+    LEDS_DISABLED, ListElement, READER_DEBUG, ROTARY_RESYNCS, ReaderDebugElement, ScrollYElement,
+    SkipUnchanged, TextElement, UiSignal,
+    config::{FRAME_STATS_LOG_INTERVAL, FRAME_STATS_WINDOW, TICK_INTERVAL},
+    recover_flush, try_init_display,
 };
 
 pub const FONT: &MonoFont = &FONT_7X14;
@@ -33,16 +53,119 @@ pub const FONT: &MonoFont = &FONT_7X14;
 pub const DISPLAY_WIDTH: u32 = 128;
 pub const DISPLAY_HEIGHT: u32 = 64;
 
-type D<'a, I2c> = Ssd1306Async<
-    I2CInterface<I2c>,
-    DisplaySize128x64,
-    ssd1306::mode::BufferedGraphicsModeAsync<DisplaySize128x64>,
->;
+/// Draws the average time of each [`FrameSection`] in a tiny font at the bottom of the display
+fn draw_frame_stats<D: Display>(
+    display: &mut D,
+    frame_timer: &FrameTimer<FRAME_STATS_WINDOW>,
+) -> Result<(), D::Error> {
+    let character_style = MonoTextStyle::new(&FONT_4X6, BinaryColor::On);
+    let height = FONT_4X6.character_size.height;
+    // Make the text readable over whatever was drawn there
+    Rectangle::new(
+        Point::new(0, (DISPLAY_HEIGHT - height) as i32),
+        Size::new(DISPLAY_WIDTH, height),
+    )
+    .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+    .draw(display)?;
+    let mut text = heapless::String::<32>::new();
+    for section in FrameSection::VARIANTS {
+        let avg = frame_timer.stats(*section).map_or(0, |stats| stats.avg);
+        let label = match section {
+            FrameSection::Layout => 'L',
+            FrameSection::Draw => 'D',
+            FrameSection::Flush => 'F',
+        };
+        let _ = write!(text, "{label}{avg} ");
+    }
+    let _ = text.push_str("us");
+    Text::with_baseline(
+        &text,
+        Point::new(0, DISPLAY_HEIGHT as i32),
+        character_style,
+        Baseline::Bottom,
+    )
+    .draw(display)?;
+    Ok(())
+}
+
+/// A small battery in the top right corner while the supply voltage is low. It is empty when it is critical.
+fn draw_supply_icon<D: Display>(display: &mut D, level: SupplyLevel) -> Result<(), D::Error> {
+    let top_left = Point::new(DISPLAY_WIDTH as i32 - 13, 0);
+    // Make the icon visible over whatever was drawn there
+    Rectangle::new(top_left, Size::new(13, 8))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+        .draw(display)?;
+    Rectangle::new(top_left + Point::new(1, 1), Size::new(10, 6))
+        .into_styled(PrimitiveStyle::with_stroke(BinaryColor::On, 1))
+        .draw(display)?;
+    // The positive terminal
+    Rectangle::new(top_left + Point::new(11, 3), Size::new(1, 2))
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+        .draw(display)?;
+    if level == SupplyLevel::Low {
+        Rectangle::new(top_left + Point::new(3, 3), Size::new(2, 2))
+            .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+            .draw(display)?;
+    }
+    Ok(())
+}
+
+/// A title followed by items, with the selected item highlighted
+fn draw_menu<'a, D: Display>(
+    display: &mut D,
+    title: &'a str,
+    items: impl Iterator<Item = &'a str> + Clone,
+    selected_item: usize,
+) -> Result<(), D::Error> {
+    ListElement {
+        elements: [title]
+            .into_iter()
+            .chain(items)
+            .enumerate()
+            .map(|(index, text)| {
+                // The title is the first element
+                let is_selected = index == selected_item + 1;
+                TextElement {
+                    text,
+                    character_style: MonoTextStyleBuilder::new()
+                        .font(FONT)
+                        .text_color(if is_selected {
+                            BinaryColor::Off
+                        } else {
+                            BinaryColor::On
+                        })
+                        .background_color(if is_selected {
+                            BinaryColor::On
+                        } else {
+                            BinaryColor::Off
+                        })
+                        .build(),
+                }
+            }),
+    }
+    .draw(display, display.bounding_box())?;
+    Ok(())
+}
 
-async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
-    display.clear(BinaryColor::Off).unwrap();
+/// Renders the game state and times how long each part takes with `frame_timer`.
+/// Returns the error if flushing failed, which [`recover_flush`] can recover from.
+pub async fn render_ui_2<D: Display>(
+    display: &mut D,
+    game_state: GameState,
+    frame_timer: &mut FrameTimer<FRAME_STATS_WINDOW>,
+) -> Result<(), D::Error> {
+    frame_timer.start_frame();
+    display.clear(BinaryColor::Off)?;
+    let action_hint = game_state.action_hint();
+    let tick = Instant::now().as_ticks() / TICK_INTERVAL.as_ticks();
+    let connection_elapsed = game_state.connection_elapsed(tick);
+    let hint_age = game_state.hint_age(tick);
+    let hint_auto_dismiss_ticks = game_state.hint_auto_dismiss_ticks();
+    let show_frame_stats = game_state.settings().show_frame_stats;
+    let supply = game_state.supply();
+    frame_timer.lap(FrameSection::Layout);
     match game_state {
-        GameState::SettingUp(state) => match state.screen {
+        GameState::SettingUp(state) => match state.screen.clone() {
             GameScreen::MainMenu(MainMenuScreen {
                 scroll_y,
                 selected_item,
@@ -56,6 +179,10 @@ async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
                                     text: match item {
                                         MainMenuSelectedItem::StartGame => "Start Game",
                                         MainMenuSelectedItem::Bluetooth => "Bluetooth",
+                                        MainMenuSelectedItem::RestartBoards => {
+                                            labels::RESTART_BOARDS
+                                        }
+                                        MainMenuSelectedItem::About => labels::ABOUT,
                                     },
                                     character_style: MonoTextStyleBuilder::new()
                                         .font(FONT)
@@ -78,8 +205,7 @@ async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
                     scrollbar_color: BinaryColor::On,
                     scrollbar_width: 1,
                 }
-                .draw(display, display.bounding_box())
-                .unwrap();
+                .draw(display, display.bounding_box())?;
             }
             GameScreen::Bluetooth(BluetoothScreen::Scanning {
                 scroll_y,
@@ -95,7 +221,10 @@ async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
                                         TextElement {
                                             text: match item {
                                                 ScanningSelectedItem::Back => "Back",
-                                                ScanningSelectedItem::Title => "Bluetooth",
+                                                ScanningSelectedItem::Title => {
+                                                    state.connection_action.scan_title()
+                                                }
+                                                ScanningSelectedItem::Connect => "Connect",
                                             },
                                             character_style: MonoTextStyleBuilder::new()
                                                 .font(FONT)
@@ -113,10 +242,10 @@ async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
                                         }
                                     },
                                 ),
-                            } as &dyn Element<D<'_, _>>,
+                            } as &dyn Element<D>,
                             &ListElement {
-                                elements: match state.connection_action {
-                                    ConnectionAction::Scan { peripherals } => peripherals,
+                                elements: match &state.connection_action {
+                                    ConnectionAction::Scan { peripherals, .. } => peripherals,
                                     _ => unreachable!(),
                                 }
                                 .iter()
@@ -124,11 +253,29 @@ async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
                                 .map(|(i, item)| {
                                     let is_selected =
                                         selected_item == ScanningSelectedItem::VARIANTS.len() + i;
+                                    let mut text = heapless::String::<48>::new();
+                                    let _ = write!(
+                                        text,
+                                        "{}",
+                                        match item.role {
+                                            Some(PeripheralRole::FascistBoard) => "F ",
+                                            Some(PeripheralRole::TrackerBoard) => "T ",
+                                            None => "",
+                                        }
+                                    );
+                                    // Show the name that the user gave instead of the address
+                                    let _ = match state.peripheral_name(item.address.addr) {
+                                        Some(name) => write!(text, "{name}"),
+                                        None => write!(text, "{}", fmt_bd_addr(&item.address.addr)),
+                                    };
+                                    if item.known {
+                                        let _ = write!(text, " {}", labels::KNOWN_PERIPHERAL);
+                                    }
+                                    if !item.in_range {
+                                        let _ = write!(text, " {}", labels::SAVED_PERIPHERAL);
+                                    }
                                     TextElement {
-                                        text: Address {
-                                            addr: *item,
-                                            kind: AddrKind::RANDOM,
-                                        },
+                                        text,
                                         character_style: MonoTextStyleBuilder::new()
                                             .font(FONT)
                                             .text_color(if is_selected {
@@ -144,7 +291,7 @@ async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
                                             .build(),
                                     }
                                 }),
-                            } as &dyn Element<D<'_, _>>,
+                            } as &dyn Element<D>,
                         ],
                         dynamic_element: None,
                     },
@@ -152,50 +299,433 @@ async fn render_ui_2<I: I2c>(display: &mut D<'_, I>, game_state: GameState) {
                     scrollbar_color: BinaryColor::On,
                     scrollbar_width: 1,
                 }
-                .draw(display, display.bounding_box())
-                .unwrap();
+                .draw(display, display.bounding_box())?;
             }
             GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected {
                 scroll_y,
                 selected_item,
             }) => {
-                TextElement {
-                    text: match match state.connection_action {
-                        ConnectionAction::Connect(status) => status,
-                        _ => unreachable!(),
+                let statuses = match &state.connection_action {
+                    ConnectionAction::Connect(statuses) => statuses,
+                    _ => unreachable!(),
+                };
+                let mut title = heapless::String::<18>::new();
+                match connection_elapsed {
+                    Some(elapsed) => {
+                        let _ = write!(title, "{} ({elapsed}s)", labels::RETRY_CONNECTING);
                     }
-                    .state
-                    {
-                        ConnectState::Connecting => "Connecting",
-                        ConnectState::Connected => "Connected",
+                    None => {
+                        let _ = title.push_str(labels::CONNECTED);
+                    }
+                }
+                ScrollYElement {
+                    element: &FlexElement {
+                        elements: &[
+                            &ListElement {
+                                elements: ConnectingConnectedSelectedItem::VARIANTS
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(i, item)| {
+                                        let is_selected = selected_item == i;
+                                        TextElement {
+                                            text: match item {
+                                                ConnectingConnectedSelectedItem::Back => "Back",
+                                                ConnectingConnectedSelectedItem::Title => {
+                                                    title.as_str()
+                                                }
+                                                ConnectingConnectedSelectedItem::Cancel => {
+                                                    ConnectingConnectedSelectedItem::cancel_label(
+                                                        statuses,
+                                                    )
+                                                }
+                                            },
+                                            character_style: MonoTextStyleBuilder::new()
+                                                .font(FONT)
+                                                .text_color(if is_selected {
+                                                    BinaryColor::Off
+                                                } else {
+                                                    BinaryColor::On
+                                                })
+                                                .background_color(if is_selected {
+                                                    BinaryColor::On
+                                                } else {
+                                                    BinaryColor::Off
+                                                })
+                                                .build(),
+                                        }
+                                    }),
+                            } as &dyn Element<D>,
+                            &ListElement {
+                                elements: statuses.iter().enumerate().map(|(i, status)| {
+                                    let is_selected = selected_item
+                                        == ConnectingConnectedSelectedItem::VARIANTS.len() + i;
+                                    let mut text = heapless::String::<24>::new();
+                                    let _ = write!(
+                                        text,
+                                        "{}: {}",
+                                        match status.role {
+                                            PeripheralRole::FascistBoard => "Fascist",
+                                            PeripheralRole::TrackerBoard => "Tracker",
+                                        },
+                                        match status.state {
+                                            ConnectState::Connecting => "Connecting",
+                                            ConnectState::Connected => "Connected",
+                                        }
+                                    );
+                                    TextElement {
+                                        text,
+                                        character_style: MonoTextStyleBuilder::new()
+                                            .font(FONT)
+                                            .text_color(if is_selected {
+                                                BinaryColor::Off
+                                            } else {
+                                                BinaryColor::On
+                                            })
+                                            .background_color(if is_selected {
+                                                BinaryColor::On
+                                            } else {
+                                                BinaryColor::Off
+                                            })
+                                            .build(),
+                                    }
+                                }),
+                            } as &dyn Element<D>,
+                        ],
+                        dynamic_element: None,
                     },
-                    character_style: MonoTextStyleBuilder::new()
-                        .font(FONT)
-                        .text_color(BinaryColor::On)
-                        .build(),
+                    scroll_y,
+                    scrollbar_color: BinaryColor::On,
+                    scrollbar_width: 1,
                 }
-                .draw(display, display.bounding_box())
-                .unwrap();
+                .draw(display, display.bounding_box())?;
+            }
+            GameScreen::Bluetooth(BluetoothScreen::Unavailable) => {
+                draw_menu(display, labels::BLE_UNAVAILABLE, ["Back"].into_iter(), 0)?;
+            }
+            GameScreen::Bluetooth(BluetoothScreen::ConnectionDetails) => {
+                let lines = state.connection_details(tick);
+                draw_menu(
+                    display,
+                    labels::CONNECTION_DETAILS,
+                    ["Back"]
+                        .into_iter()
+                        .chain(lines.iter().map(|line| line.as_str())),
+                    0,
+                )?;
+            }
+            GameScreen::TextEntry(screen) => {
+                let mut title = heapless::String::<16>::new();
+                let _ = write!(
+                    title,
+                    "{}",
+                    match screen.purpose {
+                        TextEntryPurpose::RenamePeripheral(_) => "Name:",
+                    }
+                );
+                // Show a cursor at the end of the text
+                let mut text = heapless::String::<16>::new();
+                let _ = write!(text, "{}_", screen.text);
+                let mut choice = heapless::String::<16>::new();
+                let _ = match TextEntryChoice::get(screen.selected_choice) {
+                    TextEntryChoice::Character(' ') => write!(choice, "< Space >"),
+                    TextEntryChoice::Character(character) => write!(choice, "< {character} >"),
+                    TextEntryChoice::Backspace => write!(choice, "< Backspace >"),
+                    TextEntryChoice::Done => write!(choice, "< Done >"),
+                };
+                ListElement {
+                    elements: [(title, false), (text, false), (choice, true)].map(
+                        |(text, is_selected)| TextElement {
+                            text,
+                            character_style: MonoTextStyleBuilder::new()
+                                .font(FONT)
+                                .text_color(if is_selected {
+                                    BinaryColor::Off
+                                } else {
+                                    BinaryColor::On
+                                })
+                                .background_color(if is_selected {
+                                    BinaryColor::On
+                                } else {
+                                    BinaryColor::Off
+                                })
+                                .build(),
+                        },
+                    ),
+                }
+                .draw(display, display.bounding_box())?;
+            }
+            GameScreen::About(AboutScreen {
+                scroll_y,
+                selected_item,
+            }) => {
+                let heap_stats = HEAP_MONITOR.sample();
+                let runtime_info = RuntimeInfo {
+                    firmware_version: FIRMWARE_VERSION,
+                    git_hash: GIT_SHORT_HASH,
+                    protocol_version: common::PROTOCOL_VERSION,
+                    our_address: our_address(),
+                    free_heap: heap_stats.free,
+                    peak_heap: heap_stats.peak_used,
+                    uptime_secs: Instant::now().as_secs(),
+                    leds_disabled: LEDS_DISABLED.load(Ordering::Relaxed),
+                    ble_unavailable: BLE_UNAVAILABLE.load(Ordering::Relaxed),
+                    rotary_resyncs: ROTARY_RESYNCS.load(Ordering::Relaxed),
+                    clock_sync: CLOCK_SYNC.lock(Cell::get),
+                };
+                let lines = runtime_info.about_lines();
+                let list = ListElement {
+                    elements: ["Back"]
+                        .into_iter()
+                        .chain(lines.iter().map(|line| line.as_str()))
+                        .enumerate()
+                        .map(|(i, text)| {
+                            let is_selected = selected_item == i;
+                            TextElement {
+                                text,
+                                character_style: MonoTextStyleBuilder::new()
+                                    .font(FONT)
+                                    .text_color(if is_selected {
+                                        BinaryColor::Off
+                                    } else {
+                                        BinaryColor::On
+                                    })
+                                    .background_color(if is_selected {
+                                        BinaryColor::On
+                                    } else {
+                                        BinaryColor::Off
+                                    })
+                                    .build(),
+                            }
+                        }),
+                };
+                let mut scroll_y_element = ScrollYElement {
+                    element: &list,
+                    scroll_y,
+                    scrollbar_color: BinaryColor::On,
+                    scrollbar_width: 1,
+                };
+                let size = display.bounding_box().size;
+                scroll_y_element.scroll_y = scroll_y_element.scroll_into_view(
+                    size,
+                    list.bounding_box_of_element::<D, _>(
+                        size.width - scroll_y_element.scrollbar_width,
+                        selected_item,
+                    ),
+                );
+                scroll_y_element.draw(display, display.bounding_box())?;
+            }
+            GameScreen::AutoStartCountdown { remaining_ticks } => {
+                let text = state.auto_start_text(remaining_ticks);
+                draw_menu(
+                    display,
+                    labels::AUTO_START,
+                    [text.as_str(), labels::CANCEL_AUTO_START].into_iter(),
+                    1,
+                )?;
             }
         },
         GameState::Playing(state) => {
-            TextElement {
-                text: "Playing Game",
-                character_style: MonoTextStyleBuilder::new()
-                    .font(FONT)
-                    .text_color(BinaryColor::On)
-                    .build(),
+            let character_style = MonoTextStyleBuilder::new()
+                .font(FONT)
+                .text_color(BinaryColor::On)
+                .build();
+            // The reconnecting message takes the place of the board, but the menus still work
+            if state.link_degraded() && state.screen() == PlayingScreen::Board {
+                ListElement {
+                    elements: ["Fascist board", "disconnected.", "Reconnecting..."].map(|text| {
+                        TextElement {
+                            text,
+                            character_style,
+                        }
+                    }),
+                }
+                .draw(display, display.bounding_box())?;
+            } else if let PlayingScreen::NoteEntry {
+                policy_index: _,
+                selected_item,
+            } = state.screen()
+            {
+                draw_menu(
+                    display,
+                    labels::NOTE_ENTRY_TITLE,
+                    NoteEntrySelectedItem::VARIANTS
+                        .iter()
+                        .map(|item| item.label()),
+                    selected_item,
+                )?;
+            } else if let PlayingScreen::Menu { selected_item } = state.screen() {
+                draw_menu(
+                    display,
+                    labels::GAME_MENU_TITLE,
+                    state.menu_items().iter().map(|item| item.label()),
+                    selected_item,
+                )?;
+            } else if let PlayingScreen::AdjustPlayers { players } = state.screen() {
+                let text = players_text(players);
+                draw_menu(
+                    display,
+                    labels::ADJUST_PLAYERS_TITLE,
+                    [text.as_str()].into_iter(),
+                    0,
+                )?;
+            } else if let PlayingScreen::ConfirmEndGame { selected_item } = state.screen() {
+                draw_menu(
+                    display,
+                    labels::END_GAME_TITLE,
+                    EndGameSelectedItem::VARIANTS
+                        .iter()
+                        .map(|item| item.label()),
+                    selected_item,
+                )?;
+            } else if state.paused() {
+                // Big enough to see from across the table
+                ListElement {
+                    elements: [
+                        TextElement {
+                            text: labels::PAUSED,
+                            character_style: MonoTextStyle::new(&FONT_10X20, BinaryColor::On),
+                        },
+                        TextElement {
+                            text: labels::RESUME_HINT,
+                            character_style,
+                        },
+                    ],
+                }
+                .draw(display, display.bounding_box())?;
+            } else if state.screen() == PlayingScreen::Notes {
+                ListElement {
+                    elements: [screen_text(labels::PRESIDENT_NOTES)]
+                        .into_iter()
+                        .chain(
+                            state.investigations().iter().map(|&(policy_index, team)| {
+                                investigation_text(policy_index, team)
+                            }),
+                        )
+                        .map(|text| TextElement {
+                            text,
+                            character_style,
+                        }),
+                }
+                .draw(display, display.bounding_box())?;
+            } else if let Some(winner) = state.winner() {
+                // Big and in the middle, so that everyone at the table can see it
+                CenteredElement {
+                    element: TextElement {
+                        text: match winner {
+                            Team::Liberal => labels::LIBERALS_WIN,
+                            Team::Fascist => labels::FASCISTS_WIN,
+                        },
+                        character_style: MonoTextStyle::new(&FONT_10X20, BinaryColor::On),
+                    },
+                    vertical: true,
+                }
+                .draw(display, display.bounding_box())?;
+            } else {
+                let chaos_warning =
+                    (state.failures_until_chaos() == 1).then_some(labels::CHAOS_WARNING);
+                let misplacement_warning = state
+                    .misplacement()
+                    .map(|misplacement| misplacement.warning());
+                let used = ListElement {
+                    elements: ["Playing Game"]
+                        .iter()
+                        .chain(&chaos_warning)
+                        .chain(&misplacement_warning)
+                        .map(|text| text as &dyn fmt::Display)
+                        .chain(
+                            action_hint
+                                .as_ref()
+                                .map(|action_hint| action_hint as &dyn fmt::Display),
+                        )
+                        .map(|text| TextElement {
+                            text,
+                            character_style,
+                        }),
+                }
+                .draw(display, display.bounding_box())?;
+                // The hint is the last thing in the list, so the bar is right under it
+                if let (Some(age), Some(timeout)) = (hint_age, hint_auto_dismiss_ticks) {
+                    let remaining = timeout.saturating_sub(age);
+                    Rectangle::new(
+                        Point::new(0, used.size.height as i32 + 1),
+                        Size::new((DISPLAY_WIDTH as u64 * remaining / timeout) as u32, 2),
+                    )
+                    .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                    .draw(display)?;
+                }
+                if state.reader_debug() {
+                    let height = FONT.character_size.height;
+                    READER_DEBUG.lock(|reader_debug| -> Result<(), D::Error> {
+                        ReaderDebugElement {
+                            state: &reader_debug.borrow(),
+                        }
+                        .draw(
+                            display,
+                            Rectangle::new(
+                                Point::new(0, (DISPLAY_HEIGHT - height) as i32),
+                                Size::new(DISPLAY_WIDTH, height),
+                            ),
+                        )?;
+                        Ok(())
+                    })?;
+                }
+            }
+        }
+    }
+    // The popup covers the whole screen until any input dismisses it
+    if supply.popup {
+        display.clear(BinaryColor::Off)?;
+        let character_style = MonoTextStyle::new(FONT, BinaryColor::On);
+        ListElement {
+            elements: [
+                labels::SUPPLY_LOW_TITLE,
+                labels::SUPPLY_LOW_HINT,
+                labels::DISMISS_HINT,
+            ]
+            .map(|text| TextElement {
+                text,
+                character_style,
+            }),
+        }
+        .draw(display, display.bounding_box())?;
+    } else if supply.level != SupplyLevel::Normal {
+        draw_supply_icon(display, supply.level)?;
+    }
+    // These are the stats of the previous frames, since this frame isn't done yet
+    if show_frame_stats {
+        draw_frame_stats(display, frame_timer)?;
+    }
+    frame_timer.lap(FrameSection::Draw);
+    display.flush().await?;
+    frame_timer.lap(FrameSection::Flush);
+    if frame_timer.end_frame() {
+        for section in FrameSection::VARIANTS {
+            if let Some(stats) = frame_timer.stats(*section) {
+                info!("{} time (us): {}", section, stats);
             }
-            .draw(display, display.bounding_box())
-            .unwrap();
         }
     }
-    display.flush().await.unwrap();
+    Ok(())
 }
 
+#[cfg(feature = "esp")]
+fn our_address() -> BdAddr {
+    BdAddr::new(Efuse::mac_address())
+}
+
+/// There is no eFuse when rendering on the host
+#[cfg(not(feature = "esp"))]
+fn our_address() -> BdAddr {
+    BdAddr::new([0; 6])
+}
+
+/// Renders to the SSD1306 on the shared I2C bus.
+#[cfg(feature = "esp")]
+/// Returns (after logging) if the display couldn't be initialized or stopped responding, so that the caller can try again after [`DisplayInitRetry::next_attempt_at`].
 pub async fn render_display_2<'a, Bus>(
     i2c: &Mutex<impl RawMutex, Bus>,
-    signal: &Signal<impl RawMutex, GameState>,
+    signal: &UiSignal<impl RawMutex, GameState>,
+    init_retry: &mut DisplayInitRetry,
 ) where
     Bus: I2c + SetConfig<Config = i2c::master::Config>,
 {
@@ -203,30 +733,78 @@ pub async fn render_display_2<'a, Bus>(
         i2c,
         i2c::master::Config::default().with_frequency(Rate::from_khz(400)),
     );
-    let mut display = Ssd1306Async::new(
+    let display = Ssd1306Async::new(
         I2CDisplayInterface::new(i2c),
         DisplaySize128x64,
         DisplayRotation::Rotate0,
     )
     .into_buffered_graphics_mode();
-    display.init().await.unwrap();
+    run_display(display, signal, init_retry).await;
+}
+
+/// Renders the game state whenever it changes, paced by the [`UiSignal`], and inverts the display every
+/// [`Settings::invert_screen_interval_secs`](game_pure::Settings::invert_screen_interval_secs) to prevent burn-in.
+/// Only returns if the display couldn't be initialized, or stopped responding even after [`recover_flush`].
+pub async fn run_display(
+    mut display: impl Display,
+    signal: &UiSignal<impl RawMutex, GameState>,
+    init_retry: &mut DisplayInitRetry,
+) {
+    if !try_init_display(&mut display, init_retry).await {
+        return;
+    }
 
     let mut invert = false;
     let mut last_inverted = Instant::now();
+    // Known once we get the first game state
+    let mut invert_interval = None;
+    let mut rotated = None;
+    let mut frame_timer = FrameTimer::new(FRAME_STATS_LOG_INTERVAL);
+    let mut last_rendered = SkipUnchanged::new();
     loop {
         match select(
-            Timer::at(last_inverted + INVERT_SCREEN_INTERVAL),
+            async {
+                match invert_interval {
+                    Some(invert_interval) => Timer::at(last_inverted + invert_interval).await,
+                    None => pending().await,
+                }
+            },
             signal.wait(),
         )
         .await
         {
             Either::First(()) => {
                 invert = !invert;
-                display.set_invert(invert).await.unwrap();
+                // Not worth stopping for, since it is tried again next time
+                if let Err(e) = display.set_invert(invert).await {
+                    warn!("Failed to invert the display: {}", Debug2Format(&e));
+                }
                 last_inverted = Instant::now();
             }
             Either::Second(game_state) => {
-                render_ui_2(&mut display, game_state).await;
+                invert_interval = Some(Duration::from_secs(
+                    game_state.settings().invert_screen_interval_secs.into(),
+                ));
+                let rotate = game_state.settings().rotate_display;
+                if rotated != Some(rotate) {
+                    match display.set_rotated(rotate).await {
+                        Ok(()) => rotated = Some(rotate),
+                        // Tried again with the next game state
+                        Err(e) => warn!("Failed to rotate the display: {}", Debug2Format(&e)),
+                    }
+                }
+                if last_rendered.changed(&game_state) {
+                    if let Err(e) =
+                        render_ui_2(&mut display, game_state.clone(), &mut frame_timer).await
+                        && !recover_flush(&mut display, e, init_retry, async |display| {
+                            render_ui_2(display, game_state.clone(), &mut frame_timer).await
+                        })
+                        .await
+                    {
+                        return;
+                    }
+                    HEAP_MONITOR.sample();
+                }
             }
         }
     }
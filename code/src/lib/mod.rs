@@ -1,42 +1,122 @@
 #![no_std]
+// Errors are returned so that the bins can decide how to recover.
+// Unwraps that can't fail are allowed where they are.
+#![deny(clippy::unwrap_used)]
 pub mod ble_2;
+mod ble_controller;
+mod board_leds;
+mod bridge;
+mod cached_storage;
+mod card_registry;
+mod card_scanner;
+mod clock;
 pub mod config;
 mod debouncer;
+mod display;
+mod display_init;
 mod draw_writer;
+mod entropy;
+mod error;
+mod event_recorder;
+#[cfg(test)]
+mod fake_controller;
+mod fascist_leds;
+mod fascist_screen;
+mod gatt;
+mod frame_timer;
+mod heap_monitor;
+mod input_source;
+mod led_preview;
 pub mod liberal_renderer;
 mod on_drop;
 mod postcard_value;
+mod reader_debug;
+mod record_log_flash;
 mod render;
 mod rotary_encoder;
 mod rotary_input;
-mod scale_rgb;
+mod rpa;
 // mod scan_and_choose;
 pub mod lazy_shared_spi;
 pub mod lazy_shared_spi_2;
+mod liberal_leds;
 mod scanning_event_handler;
+mod skip_unchanged;
 mod storage;
+#[cfg(feature = "supply-sense")]
+mod supply_sense;
+mod ui_signal;
 
+pub use ble_controller::*;
+pub use board_leds::*;
+pub use bridge::*;
+pub use cached_storage::*;
+pub use card_registry::*;
+pub use card_scanner::*;
+pub use clock::*;
 pub use debouncer::*;
+pub use display::*;
+pub use display_init::*;
 pub use draw_writer::*;
+pub use entropy::*;
+pub use error::*;
+pub use event_recorder::*;
+pub use fascist_leds::*;
+pub use fascist_screen::*;
+pub use gatt::*;
+pub use frame_timer::*;
+pub use heap_monitor::*;
+pub use input_source::*;
+pub use led_preview::*;
+pub use liberal_leds::*;
 pub use on_drop::*;
 pub use postcard_value::*;
+pub use reader_debug::*;
+pub use record_log_flash::*;
 pub use render::*;
 pub use rotary_encoder::*;
 pub use rotary_input::*;
-pub use scale_rgb::*;
+pub use rpa::*;
 // pub use scan_and_choose::*;
+use core::{
+    cell::Cell,
+    sync::atomic::{AtomicBool, AtomicU32},
+};
 pub use scanning_event_handler::*;
+pub use skip_unchanged::*;
 pub use storage::*;
+#[cfg(feature = "supply-sense")]
+pub use supply_sense::*;
+pub use ui_signal::*;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use game_pure::ClockSync;
 use trouble_host::prelude::{Uuid, uuid};
 
-pub const LED_BRIGHTNESS: f64 = 0.05;
+pub const FIRMWARE_VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Set by the build script
+pub const GIT_SHORT_HASH: &str = match option_env!("GIT_SHORT_HASH") {
+    Some(hash) => hash,
+    None => "unknown",
+};
+/// Set when writing to the LEDs kept failing and was disabled, so that the About screen can show it
+pub static LEDS_DISABLED: AtomicBool = AtomicBool::new(false);
+/// Set when the BLE controller couldn't be initialized, so that the About screen can show it
+pub static BLE_UNAVAILABLE: AtomicBool = AtomicBool::new(false);
+/// Set while the display isn't responding, so that the LEDs can show [`common::BlinkCode::DisplayMissing`]
+pub static DISPLAY_MISSING: AtomicBool = AtomicBool::new(false);
+/// How many times the rotary encoder missed a step and had to resync, so that the About screen can show it
+pub static ROTARY_RESYNCS: AtomicU32 = AtomicU32::new(0);
+/// Set by whichever board runs the sync once the clocks are synced, so that the About screen can show it.
+/// There are no 64-bit atomics on the ESP32-C3.
+pub static CLOCK_SYNC: Mutex<CriticalSectionRawMutex, Cell<Option<ClockSync>>> =
+    Mutex::new(Cell::new(None));
 pub const SERVICE_UUID: Uuid = uuid!("85d47eca-91e5-4ddb-9c23-0579415f46af");
 
 /// Max number of connections
-pub const CONNECTIONS_MAX: usize = 1;
+pub const CONNECTIONS_MAX: usize = game_pure::MAX_PERIPHERALS;
 
 /// Max number of L2CAP channels.
-pub const L2CAP_CHANNELS_MAX: usize = 3; // Signal + att + CoC
+pub const L2CAP_CHANNELS_MAX: usize = 3 * CONNECTIONS_MAX; // Signal + att + CoC for each connection
 
 // PSM from the dynamic range (0x0080-0x00FF) according to the Bluetooth
 // Specification for L2CAP channels using LE Credit Based Flow Control mode.
@@ -46,3 +126,27 @@ pub const L2CAP_CHANNELS_MAX: usize = 3; // Signal + att + CoC
 pub const PSM_L2CAP_EXAMPLES: u16 = 0x0081;
 
 pub const BLE_SLOTS: usize = 20;
+
+/// The library logs with defmt, which needs a logger and a timestamp to link on the host. The logs are dropped.
+#[cfg(test)]
+mod test_logger {
+    #[defmt::global_logger]
+    struct NoopLogger;
+
+    unsafe impl defmt::Logger for NoopLogger {
+        fn acquire() {}
+
+        unsafe fn flush() {}
+
+        unsafe fn release() {}
+
+        unsafe fn write(_bytes: &[u8]) {}
+    }
+
+    defmt::timestamp!("");
+
+    #[defmt::panic_handler]
+    fn defmt_panic() -> ! {
+        panic!("defmt panic")
+    }
+}
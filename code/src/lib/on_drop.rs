@@ -12,6 +12,8 @@ impl<F: FnOnce()> OnDrop<F> {
 
 impl<F: FnOnce()> Drop for OnDrop<F> {
     fn drop(&mut self) {
-        self.on_drop.take().unwrap()()
+        if let Some(on_drop) = self.on_drop.take() {
+            on_drop()
+        }
     }
 }
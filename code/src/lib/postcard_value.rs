@@ -21,27 +21,85 @@ impl<T> DerefMut for PostcardValue<T> {
     }
 }
 
+/// The length of the value before it
+const LEN_LEN: usize = size_of::<u16>();
+/// The CRC of the length and the value after it
+const CRC_LEN: usize = size_of::<u32>();
+/// How many more bytes a [`PostcardValue`] takes in flash than the postcard bytes of its value
+pub const POSTCARD_VALUE_OVERHEAD: usize = LEN_LEN + CRC_LEN;
+
+/// CRC-32 (IEEE). Computed bit by bit, since the stored values are small and only read at boot or saved occasionally.
+fn crc32(bytes: &[u8]) -> u32 {
+    !bytes.iter().fold(!0, |crc, &byte| {
+        (0..8).fold(crc ^ u32::from(byte), |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg())
+        })
+    })
+}
+
+/// Stored as the length of the value, the value, and then a CRC,
+/// so that flash corruption and truncated values are an error instead of different settings
 impl<'a, T: Serialize + Deserialize<'a>> Value<'a> for PostcardValue<T> {
     fn serialize_into(
         &self,
         buffer: &mut [u8],
     ) -> Result<usize, sequential_storage::map::SerializationError> {
-        Ok(postcard::to_slice(self, buffer)
-            .map_err(|e| match e {
-                postcard::Error::SerializeBufferFull => SerializationError::BufferTooSmall,
-                _ => SerializationError::InvalidData,
-            })?
-            .len())
+        let value_end = buffer
+            .len()
+            .checked_sub(CRC_LEN)
+            .ok_or(SerializationError::BufferTooSmall)?;
+        let value_len = postcard::to_slice(
+            &self.0,
+            buffer
+                .get_mut(LEN_LEN..value_end)
+                .ok_or(SerializationError::BufferTooSmall)?,
+        )
+        .map_err(|e| match e {
+            postcard::Error::SerializeBufferFull => SerializationError::BufferTooSmall,
+            _ => SerializationError::InvalidData,
+        })?
+        .len();
+        buffer[..LEN_LEN].copy_from_slice(
+            &u16::try_from(value_len)
+                .map_err(|_| SerializationError::InvalidData)?
+                .to_le_bytes(),
+        );
+        let crc_start = LEN_LEN + value_len;
+        let crc = crc32(&buffer[..crc_start]);
+        buffer[crc_start..crc_start + CRC_LEN].copy_from_slice(&crc.to_le_bytes());
+        Ok(crc_start + CRC_LEN)
     }
 
     fn deserialize_from(buffer: &'a [u8]) -> Result<(Self, usize), SerializationError>
     where
         Self: Sized,
     {
-        let (value, unused_bytes) = postcard::take_from_bytes(buffer).map_err(|e| match e {
-            postcard::Error::DeserializeUnexpectedEnd => SerializationError::BufferTooSmall,
-            _ => SerializationError::InvalidFormat,
-        })?;
-        Ok((value, buffer.len() - unused_bytes.len()))
+        let (&value_len, _) = buffer
+            .split_first_chunk::<LEN_LEN>()
+            .ok_or(SerializationError::BufferTooSmall)?;
+        let crc_start = LEN_LEN + usize::from(u16::from_le_bytes(value_len));
+        let crc = buffer
+            .get(crc_start..crc_start + CRC_LEN)
+            .ok_or(SerializationError::BufferTooSmall)?;
+        if crc32(&buffer[..crc_start]).to_le_bytes() != crc {
+            return Err(SerializationError::InvalidData);
+        }
+        let (value, unused_bytes) = postcard::take_from_bytes(&buffer[LEN_LEN..crc_start])
+            .map_err(|_| SerializationError::InvalidFormat)?;
+        if !unused_bytes.is_empty() {
+            return Err(SerializationError::InvalidFormat);
+        }
+        Ok((Self(value), crc_start + CRC_LEN))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(&[]), 0);
     }
 }
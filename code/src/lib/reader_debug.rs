@@ -0,0 +1,186 @@
+use core::cell::RefCell;
+
+use common::MAX_NFC_READERS;
+use defmt::Format;
+use embassy_sync::blocking_mutex::{Mutex, raw::CriticalSectionRawMutex};
+use embedded_graphics::{
+    mono_font::{MonoTextStyle, iso_8859_16::FONT_7X14},
+    pixelcolor::BinaryColor,
+    prelude::*,
+    primitives::{PrimitiveStyle, Rectangle},
+};
+use game_pure::Team;
+use heapless::{String, Vec};
+
+use crate::{
+    CardKind, CardRegistry, Element, ElementHeight, ScanInterpretation, SlotMap, TextElement,
+};
+
+/// What one NFC reader saw in the latest scan
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum ReaderStatus {
+    Empty,
+    Policy(Team),
+    Character,
+    /// A card that isn't in the registry
+    Unknown,
+    /// The reader is in the slot map, but wasn't in the scan
+    Error,
+}
+
+impl ReaderStatus {
+    pub fn char(self) -> char {
+        match self {
+            Self::Empty => '.',
+            Self::Policy(Team::Liberal) => 'L',
+            Self::Policy(Team::Fascist) => 'F',
+            Self::Character => 'C',
+            Self::Unknown => '?',
+            Self::Error => 'X',
+        }
+    }
+}
+
+/// The status of each NFC reader, for the overlay that is turned on with the game menu's NFC debug item
+#[derive(Debug, Format, Clone, Default, PartialEq, Eq)]
+pub struct ReaderDebugState {
+    readers: Vec<ReaderStatus, MAX_NFC_READERS>,
+}
+
+impl ReaderDebugState {
+    pub const fn new() -> Self {
+        Self {
+            readers: Vec::new(),
+        }
+    }
+
+    /// Replaces the statuses with what `interpretation` scanned.
+    /// Unlike the interpretation, this shows a card even if it is on the wrong reader.
+    pub fn update(
+        &mut self,
+        interpretation: &ScanInterpretation,
+        registry: &CardRegistry,
+        roles: &SlotMap,
+    ) {
+        let readers = interpretation.raw.len().max(roles.len());
+        self.readers = (0..readers)
+            .map(|reader| match interpretation.raw.get(reader) {
+                None => ReaderStatus::Error,
+                Some(None) => ReaderStatus::Empty,
+                Some(Some(uid)) => match registry.get(uid) {
+                    Some(CardKind::Policy(card)) => ReaderStatus::Policy(card.team),
+                    Some(CardKind::Character(_)) => ReaderStatus::Character,
+                    None => ReaderStatus::Unknown,
+                },
+            })
+            .collect();
+    }
+
+    pub fn readers(&self) -> &[ReaderStatus] {
+        &self.readers
+    }
+
+    /// One character for each reader, like `FF.?LX`
+    pub fn text(&self) -> String<MAX_NFC_READERS> {
+        self.readers.iter().map(|status| status.char()).collect()
+    }
+}
+
+/// Updated with every scan, and drawn by the renderer while [`game_pure::GameStatePlaying::reader_debug`] is on
+pub static READER_DEBUG: Mutex<CriticalSectionRawMutex, RefCell<ReaderDebugState>> =
+    Mutex::new(RefCell::new(ReaderDebugState::new()));
+
+/// Draws [`ReaderDebugState::text`] after `NFC`, covering whatever was drawn behind it
+pub struct ReaderDebugElement<'a> {
+    pub state: &'a ReaderDebugState,
+}
+
+impl<D: DrawTarget<Color = BinaryColor>> Element<D> for ReaderDebugElement<'_> {
+    fn draw(&self, display: &mut D, bounding_box: Rectangle) -> Result<Rectangle, D::Error> {
+        let height = FONT_7X14.character_size.height;
+        Rectangle::new(
+            bounding_box.top_left,
+            Size::new(bounding_box.size.width, height),
+        )
+        .into_styled(PrimitiveStyle::with_fill(BinaryColor::Off))
+        .draw(display)?;
+        let mut text = String::<{ MAX_NFC_READERS + 4 }>::new();
+        let _ = text.push_str("NFC ");
+        let _ = text.push_str(&self.state.text());
+        TextElement {
+            text,
+            character_style: MonoTextStyle::new(&FONT_7X14, BinaryColor::On),
+        }
+        .draw(display, bounding_box)
+    }
+
+    fn height(&self, _width: u32) -> ElementHeight {
+        ElementHeight::Fixed(FONT_7X14.character_size.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use game_pure::{CharacterCardId, PolicyCardId, SecretRole};
+
+    use super::*;
+    use crate::{CardUid, ReaderRole, interpret_scan};
+
+    #[test]
+    fn mixed() {
+        let mut registry = CardRegistry::new();
+        registry
+            .register(
+                CardUid::new(&[1]),
+                CardKind::Policy(PolicyCardId {
+                    team: Team::Liberal,
+                    id: 0,
+                }),
+            )
+            .unwrap();
+        registry
+            .register(
+                CardUid::new(&[2]),
+                CardKind::Policy(PolicyCardId {
+                    team: Team::Fascist,
+                    id: 0,
+                }),
+            )
+            .unwrap();
+        registry
+            .register(
+                CardUid::new(&[3]),
+                CardKind::Character(CharacterCardId {
+                    secret_role: SecretRole::Hitler,
+                    id: 0,
+                }),
+            )
+            .unwrap();
+        let roles: SlotMap = [ReaderRole::PolicySlot(Team::Fascist); 5]
+            .into_iter()
+            .chain([ReaderRole::DeadCharacter])
+            .collect();
+        // The last reader didn't report
+        let scan = [Some(2), None, Some(9), Some(1), Some(3)]
+            .into_iter()
+            .map(|card| card.map(|byte| CardUid::new(&[byte])))
+            .collect();
+        let mut state = ReaderDebugState::new();
+        assert_eq!(state.text(), "");
+        state.update(&interpret_scan(&scan, &registry, &roles), &registry, &roles);
+        assert_eq!(
+            state.readers(),
+            [
+                ReaderStatus::Policy(Team::Fascist),
+                ReaderStatus::Empty,
+                ReaderStatus::Unknown,
+                // On the wrong board, but still shown
+                ReaderStatus::Policy(Team::Liberal),
+                // Not in the dead character area
+                ReaderStatus::Character,
+                ReaderStatus::Error,
+            ]
+        );
+        assert_eq!(state.text(), "F.?LCX");
+    }
+}
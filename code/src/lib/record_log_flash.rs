@@ -0,0 +1,52 @@
+use defmt::{Debug2Format, info, warn};
+use embedded_storage_async::nor_flash::NorFlash;
+use game_pure::record_log::{RECORD_LOG_SECTOR_LEN, RecordLogFlash, read_last_session};
+
+/// A flash partition of at least [`game_pure::record_log::RECORD_LOG_LEN`] bytes, for [`game_pure::record_log::RecordLog`]
+pub struct FlashRecordLog<F>(pub F);
+
+impl<F: NorFlash> RecordLogFlash for FlashRecordLog<F> {
+    type Error = F::Error;
+
+    async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), Self::Error> {
+        self.0.read(offset, bytes).await
+    }
+
+    async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), Self::Error> {
+        self.0.write(offset, bytes).await
+    }
+
+    async fn erase_sector(&mut self, offset: u32) -> Result<(), Self::Error> {
+        self.0.erase(offset, offset + RECORD_LOG_SECTOR_LEN).await
+    }
+}
+
+/// Logs the events that the last session appended to the flash record log, so that what led up to a reset or crash can be seen.
+/// Call this before starting a new session, which erases the oldest sector.
+pub async fn dump_last_session<F: NorFlash>(flash: &mut FlashRecordLog<F>) {
+    match read_last_session(flash).await {
+        Ok(log) => match log.session {
+            Some(session) => {
+                info!(
+                    "The last session ({}) recorded {} events before resetting",
+                    session,
+                    log.entries.len()
+                );
+                for entry in &log.entries {
+                    info!(
+                        "#{} at {}ms: {}",
+                        entry.sequence,
+                        entry.timestamp_ms,
+                        Debug2Format(&entry.event())
+                    );
+                }
+                if log.torn > 0 {
+                    // Expected for the last write if the board reset while writing it
+                    warn!("{} events were cut off while being written", log.torn);
+                }
+            }
+            None => info!("No events were recorded in flash before this session"),
+        },
+        Err(e) => warn!("Failed to read the flash record log: {}", Debug2Format(&e)),
+    }
+}
@@ -5,7 +5,7 @@ use embedded_graphics::{
     geometry::{AnchorX, AnchorY},
     prelude::*,
     primitives::{PrimitiveStyleBuilder, Rectangle},
-    text::renderer::TextRenderer,
+    text::{Baseline, renderer::TextRenderer},
 };
 
 use crate::DrawWriter;
@@ -40,9 +40,15 @@ pub trait Element<D: DrawTarget> {
 
     /// The height that this element needs in order to be fully in view
     fn height(&self, width: u32) -> ElementHeight;
+
+    /// The width that this element needs, if it doesn't use the entire width it receives
+    fn width(&self) -> Option<u32> {
+        None
+    }
 }
 
 /// Currently only supports 1-byte UTF-8 characters
+#[derive(Clone)]
 pub struct TextElement<T, S> {
     pub text: T,
     pub character_style: S,
@@ -71,6 +77,142 @@ where
         // TODO: Multi-line text and text wrapping
         ElementHeight::Fixed(self.character_style.line_height())
     }
+
+    fn width(&self) -> Option<u32> {
+        let mut measure_writer = MeasureWriter {
+            character_style: &self.character_style,
+            width: 0,
+        };
+        let _ = write!(measure_writer, "{}", self.text);
+        Some(measure_writer.width)
+    }
+}
+
+/// Adds up the width of text without drawing it
+struct MeasureWriter<'a, S> {
+    character_style: &'a S,
+    width: u32,
+}
+
+impl<S: TextRenderer> Write for MeasureWriter<'_, S> {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.width += self
+            .character_style
+            .measure_string(s, Point::zero(), Baseline::Top)
+            .next_position
+            .x as u32;
+        Ok(())
+    }
+}
+
+/// Similar to CSS padding. The padding is empty space around the element.
+pub struct PaddedElement<E> {
+    pub element: E,
+    pub top: u32,
+    pub bottom: u32,
+    pub left: u32,
+    pub right: u32,
+}
+
+impl<E> PaddedElement<E> {
+    /// The same padding on all sides
+    pub fn uniform(element: E, padding: u32) -> Self {
+        Self {
+            element,
+            top: padding,
+            bottom: padding,
+            left: padding,
+            right: padding,
+        }
+    }
+}
+
+impl<D: DrawTarget, E: Element<D>> Element<D> for PaddedElement<E> {
+    fn draw(&self, display: &mut D, bounding_box: Rectangle) -> Result<Rectangle, D::Error> {
+        let used = self.element.draw(
+            display,
+            Rectangle::new(
+                bounding_box.top_left + Point::new(self.left as i32, self.top as i32),
+                Size::new(
+                    bounding_box
+                        .size
+                        .width
+                        .saturating_sub(self.left + self.right),
+                    bounding_box
+                        .size
+                        .height
+                        .saturating_sub(self.top + self.bottom),
+                ),
+            ),
+        )?;
+        Ok(Rectangle::new(
+            used.top_left,
+            used.size + Size::new(self.left + self.right, self.top + self.bottom),
+        ))
+    }
+
+    fn height(&self, width: u32) -> ElementHeight {
+        match self
+            .element
+            .height(width.saturating_sub(self.left + self.right))
+        {
+            ElementHeight::Fixed(height) => ElementHeight::Fixed(height + self.top + self.bottom),
+            ElementHeight::Dynamic => ElementHeight::Dynamic,
+        }
+    }
+
+    fn width(&self) -> Option<u32> {
+        self.element
+            .width()
+            .map(|width| width + self.left + self.right)
+    }
+}
+
+/// Centers an element horizontally if it has a [`width`](Element::width),
+/// and vertically if `vertical` is set and it has a fixed height.
+pub struct CenteredElement<E> {
+    pub element: E,
+    /// Use the entire height that this element receives and center the element within it
+    pub vertical: bool,
+}
+
+impl<D: DrawTarget, E: Element<D>> Element<D> for CenteredElement<E> {
+    fn draw(&self, display: &mut D, bounding_box: Rectangle) -> Result<Rectangle, D::Error> {
+        let width = self
+            .element
+            .width()
+            .map_or(bounding_box.size.width, |width| {
+                width.min(bounding_box.size.width)
+            });
+        let height = match self.element.height(width) {
+            ElementHeight::Fixed(height) if self.vertical => height.min(bounding_box.size.height),
+            _ => bounding_box.size.height,
+        };
+        let offset = Point::new(
+            ((bounding_box.size.width - width) / 2) as i32,
+            ((bounding_box.size.height - height) / 2) as i32,
+        );
+        let used = self.element.draw(
+            display,
+            Rectangle::new(
+                bounding_box.top_left + offset,
+                Size::new(width, bounding_box.size.height - offset.y as u32),
+            ),
+        )?;
+        Ok(if self.vertical {
+            Rectangle::new(Point::zero(), bounding_box.size)
+        } else {
+            used
+        })
+    }
+
+    fn height(&self, width: u32) -> ElementHeight {
+        if self.vertical {
+            ElementHeight::Dynamic
+        } else {
+            self.element.height(width)
+        }
+    }
 }
 
 /// Similar to a vertical CSS Flexbox
@@ -81,6 +223,8 @@ pub struct FlexElement<'a, E> {
     pub dynamic_element: Option<usize>,
 }
 
+// The elements other than the dynamic one have a fixed height, and the coordinates fit on the display
+#[allow(clippy::unwrap_used)]
 impl<D: DrawTarget> Element<D> for FlexElement<'_, &dyn Element<D>> {
     fn draw(
         &self,
@@ -152,10 +296,18 @@ impl<D: DrawTarget, E> ScrollYElement<'_, D, E> {
     /// Returns the new `scroll_y` to do just enough scrolling for the entire element to be seen.
     /// `size` is the size of the bounding box this element will be drawn with.
     pub fn scroll_into_view(&self, size: Size, element: BoundingHeight) -> u32 {
-        todo!()
+        if element.y < self.scroll_y {
+            element.y
+        } else if element.y + element.height > self.scroll_y + size.height {
+            element.y + element.height - size.height
+        } else {
+            self.scroll_y
+        }
     }
 }
 
+// The scrolled element has a fixed height
+#[allow(clippy::unwrap_used)]
 impl<D: DrawTarget, E: Element<D>> Element<D> for ScrollYElement<'_, D, E> {
     fn draw(
         &self,
@@ -163,9 +315,13 @@ impl<D: DrawTarget, E: Element<D>> Element<D> for ScrollYElement<'_, D, E> {
         bounding_box: Rectangle,
     ) -> Result<Rectangle, <D as DrawTarget>::Error> {
         if let Some(element_width) = bounding_box.size.width.checked_sub(self.scrollbar_width) {
+            // Parts of the element that are scrolled out of view are drawn outside of the display
             self.element.draw(
                 display,
-                bounding_box.resized_width(element_width, AnchorX::Left),
+                Rectangle::new(
+                    bounding_box.top_left - Point::new(0, self.scroll_y as i32),
+                    Size::new(element_width, bounding_box.size.height + self.scroll_y),
+                ),
             )?;
             // Draw the scrollbar
             let total_height = u32::try_from(self.element.height(element_width)).unwrap() as f64;
@@ -205,6 +361,8 @@ pub struct ListElement<I> {
     pub elements: I,
 }
 
+// The list's elements have a fixed height, and the coordinates fit on the display
+#[allow(clippy::unwrap_used)]
 impl<D, E, I> Element<D> for ListElement<I>
 where
     D: DrawTarget,
@@ -250,6 +408,8 @@ pub struct BoundingHeight {
 }
 
 impl<I> ListElement<I> {
+    /// Panics if `index` is out of bounds
+    #[allow(clippy::unwrap_used)]
     pub fn bounding_box_of_element<D, E>(&self, width: u32, index: usize) -> BoundingHeight
     where
         D: DrawTarget,
@@ -265,3 +425,113 @@ impl<I> ListElement<I> {
         BoundingHeight { y, height }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use embedded_graphics::{
+        mock_display::MockDisplay,
+        mono_font::{MonoTextStyle, ascii::FONT_6X10},
+        pixelcolor::BinaryColor,
+        primitives::PrimitiveStyle,
+    };
+
+    use super::*;
+
+    /// A filled rectangle of a fixed size
+    struct BoxElement(Size);
+
+    impl<D: DrawTarget<Color = BinaryColor>> Element<D> for BoxElement {
+        fn draw(&self, display: &mut D, bounding_box: Rectangle) -> Result<Rectangle, D::Error> {
+            Rectangle::new(bounding_box.top_left, self.0)
+                .into_styled(PrimitiveStyle::with_fill(BinaryColor::On))
+                .draw(display)?;
+            Ok(Rectangle::new(Point::zero(), self.0))
+        }
+
+        fn height(&self, _width: u32) -> ElementHeight {
+            ElementHeight::Fixed(self.0.height)
+        }
+
+        fn width(&self) -> Option<u32> {
+            Some(self.0.width)
+        }
+    }
+
+    fn draw(element: &impl Element<MockDisplay<BinaryColor>>) -> MockDisplay<BinaryColor> {
+        let mut display = MockDisplay::new();
+        let bounding_box = display.bounding_box();
+        element.draw(&mut display, bounding_box).unwrap();
+        display
+    }
+
+    #[test]
+    fn padding() {
+        let element = PaddedElement {
+            element: BoxElement(Size::new(2, 3)),
+            top: 1,
+            bottom: 4,
+            left: 5,
+            right: 6,
+        };
+        assert_eq!(
+            draw(&element).affected_area(),
+            Rectangle::new(Point::new(5, 1), Size::new(2, 3))
+        );
+        assert!(matches!(
+            Element::<MockDisplay<BinaryColor>>::height(&element, 64),
+            ElementHeight::Fixed(8)
+        ));
+        assert_eq!(
+            Element::<MockDisplay<BinaryColor>>::width(&element),
+            Some(13)
+        );
+    }
+
+    #[test]
+    fn centered() {
+        let element = CenteredElement {
+            element: BoxElement(Size::new(10, 4)),
+            vertical: false,
+        };
+        assert_eq!(
+            draw(&element).affected_area(),
+            Rectangle::new(Point::new(27, 0), Size::new(10, 4))
+        );
+        let element = CenteredElement {
+            element: BoxElement(Size::new(10, 4)),
+            vertical: true,
+        };
+        assert_eq!(
+            draw(&element).affected_area(),
+            Rectangle::new(Point::new(27, 30), Size::new(10, 4))
+        );
+        assert!(matches!(
+            Element::<MockDisplay<BinaryColor>>::height(&element, 64),
+            ElementHeight::Dynamic
+        ));
+    }
+
+    #[test]
+    fn centered_padded_text() {
+        let character_style = MonoTextStyle::new(&FONT_6X10, BinaryColor::On);
+        let element = CenteredElement {
+            element: PaddedElement::uniform(
+                TextElement {
+                    text: "1234",
+                    character_style,
+                },
+                2,
+            ),
+            vertical: false,
+        };
+        // 4 characters that are 6 px wide, plus the padding
+        assert_eq!(
+            Element::<MockDisplay<BinaryColor>>::width(&element.element),
+            Some(28)
+        );
+        // The text is drawn within the padding
+        let area = draw(&element).affected_area();
+        assert!(area.top_left.x >= 18 + 2 && area.top_left.y >= 2);
+        assert!(area.bottom_right().unwrap().x < 18 + 2 + 24);
+    }
+}
@@ -1,7 +1,9 @@
-use core::{mem, ops::Not};
+use core::ops::Not;
 
-use defmt::*;
-use embassy_time::Instant;
+use defmt::{Format, trace, warn};
+use embassy_time::Duration;
+
+use crate::{Clock, EmbassyClock};
 
 #[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
 pub struct RotaryPinsState {
@@ -42,28 +44,59 @@ impl RotaryPin {
     }
 }
 
-pub struct RotaryEncoder {
+/// Between detents, exactly one pin has changed, so whether a pin is leading only depends on the state of the pins.
+/// Either pin can be the leading pin, since the next change is decoded the same way.
+fn leading_pin(state: RotaryPinsState) -> Option<RotaryPin> {
+    (state.clk != state.dt).then_some(RotaryPin::Clock)
+}
+
+pub struct RotaryEncoder<C = EmbassyClock> {
     state: RotaryPinsState,
     leading_pin: Option<RotaryPin>,
-    last_changed: Instant,
+    /// In ticks of `clock`
+    last_changed: u64,
+    /// The time between the last two pin changes
+    change_interval: Duration,
+    resyncs: u32,
+    clock: C,
 }
 
 impl RotaryEncoder {
     pub fn new(state: RotaryPinsState) -> Self {
+        Self::with_clock(state, EmbassyClock)
+    }
+}
+
+impl<C: Clock> RotaryEncoder<C> {
+    pub fn with_clock(state: RotaryPinsState, clock: C) -> Self {
         Self {
             state,
-            leading_pin: None,
-            last_changed: Instant::now(),
+            leading_pin: leading_pin(state),
+            last_changed: 0,
+            change_interval: Duration::from_ticks(0),
+            resyncs: 0,
+            clock,
         }
     }
 
+    /// The time between the last two pin changes, which is useful for tuning the debounce time
+    pub fn change_interval(&self) -> Duration {
+        self.change_interval
+    }
+
+    /// How many times both pins changed in one sample, which loses a step
+    pub fn resyncs(&self) -> u32 {
+        self.resyncs
+    }
+
     pub fn process_data(&mut self, new_state: RotaryPinsState) -> Option<Direction> {
         let direction = if new_state != self.state {
-            let now = Instant::now();
-            let last_changed = mem::replace(&mut self.last_changed, now);
+            let now = self.clock.now();
+            self.change_interval = Duration::from_ticks(now.saturating_sub(self.last_changed));
+            self.last_changed = now;
             trace!(
                 "time between change: {} us",
-                (now - last_changed).as_micros()
+                self.change_interval.as_micros()
             );
             let clk_changed = new_state.clk != self.state.clk;
             let dt_changed = new_state.dt != self.state.dt;
@@ -91,8 +124,11 @@ impl RotaryEncoder {
                     changed_pin.leading_direction()
                 })
             } else {
-                // Since both pins changed, we know that it moved, but we don't know which direction
-                trace!("both changed");
+                // Since both pins changed, we know that it moved, but we don't know which direction.
+                // A step was missed, so start over from the new state.
+                warn!("both rotary encoder pins changed, resyncing");
+                self.leading_pin = leading_pin(new_state);
+                self.resyncs = self.resyncs.wrapping_add(1);
                 None
             }
         } else {
@@ -102,3 +138,117 @@ impl RotaryEncoder {
         direction
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::TestClock;
+
+    /// The states of the pins while turning clockwise, starting from a detent
+    const CLOCKWISE: [RotaryPinsState; 4] = [
+        RotaryPinsState {
+            clk: true,
+            dt: false,
+        },
+        RotaryPinsState {
+            clk: true,
+            dt: true,
+        },
+        RotaryPinsState {
+            clk: false,
+            dt: true,
+        },
+        RotaryPinsState {
+            clk: false,
+            dt: false,
+        },
+    ];
+
+    /// The time between each state in [`turn`]
+    const STEP: Duration = Duration::from_millis(2);
+
+    fn turn(
+        encoder: &mut RotaryEncoder<&TestClock>,
+        states: impl IntoIterator<Item = RotaryPinsState>,
+    ) -> heapless::Vec<Option<Direction>, 8> {
+        states
+            .into_iter()
+            .map(|state| {
+                encoder.clock.advance(STEP);
+                encoder.process_data(state)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn full_cycles() {
+        let clock = TestClock::new();
+        let mut encoder = RotaryEncoder::with_clock(CLOCKWISE[3], &clock);
+        assert_eq!(
+            turn(&mut encoder, CLOCKWISE),
+            [Some(Direction::Clockwise); 4]
+        );
+        assert_eq!(
+            turn(
+                &mut encoder,
+                CLOCKWISE.into_iter().rev().skip(1).chain([CLOCKWISE[3]])
+            ),
+            [Some(Direction::CounterClockwise); 4]
+        );
+        assert_eq!(encoder.resyncs(), 0);
+    }
+
+    #[test]
+    fn both_changed() {
+        let clock = TestClock::new();
+        for direction in [Direction::Clockwise, Direction::CounterClockwise] {
+            let mut states = CLOCKWISE;
+            if direction == Direction::CounterClockwise {
+                states[..3].reverse();
+            }
+            let mut encoder = RotaryEncoder::with_clock(states[3], &clock);
+            // The first step is seen, and then both pins change mid-cycle
+            assert_eq!(turn(&mut encoder, [states[0]]), [Some(direction)]);
+            assert_eq!(turn(&mut encoder, [states[2]]), [None]);
+            assert_eq!(encoder.resyncs(), 1);
+            assert_eq!(
+                turn(
+                    &mut encoder,
+                    [states[3], states[0], states[1], states[2], states[3]]
+                ),
+                [Some(direction); 5]
+            );
+        }
+    }
+
+    #[test]
+    fn starts_between_detents() {
+        let clock = TestClock::new();
+        let mut encoder = RotaryEncoder::with_clock(CLOCKWISE[0], &clock);
+        assert_eq!(
+            turn(&mut encoder, [CLOCKWISE[1], CLOCKWISE[2], CLOCKWISE[3]]),
+            [Some(Direction::Clockwise); 3]
+        );
+        let mut encoder = RotaryEncoder::with_clock(CLOCKWISE[0], &clock);
+        assert_eq!(
+            turn(&mut encoder, [CLOCKWISE[3]]),
+            [Some(Direction::CounterClockwise)]
+        );
+    }
+
+    #[test]
+    fn change_interval() {
+        let clock = TestClock::new();
+        let mut encoder = RotaryEncoder::with_clock(CLOCKWISE[3], &clock);
+        turn(&mut encoder, [CLOCKWISE[0], CLOCKWISE[1]]);
+        assert_eq!(encoder.change_interval(), STEP);
+        // Not a change
+        turn(&mut encoder, [CLOCKWISE[1]]);
+        clock.advance(Duration::from_millis(5));
+        turn(&mut encoder, [CLOCKWISE[2]]);
+        assert_eq!(
+            encoder.change_interval(),
+            STEP * 2 + Duration::from_millis(5)
+        );
+    }
+}
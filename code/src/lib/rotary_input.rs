@@ -1,12 +1,12 @@
-use core::any::Any;
+use core::{any::Any, sync::atomic::Ordering};
 
 use embassy_futures::select::{select, select4};
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, signal::Signal};
-use embassy_time::{Duration, Instant};
+use embassy_time::Duration;
 use embedded_hal::digital::PinState;
 use mcp23017_controller::{Pin, mode::Watch};
 
-use crate::{Debouncer, Direction, RotaryEncoder, RotaryPinsState, rotary_encoder};
+use crate::{Debouncer, Direction, ROTARY_RESYNCS, RotaryEncoder, RotaryPinsState, rotary_encoder};
 
 pub struct RotaryInput<'a> {
     dt: Pin<'a, Watch>,
@@ -45,14 +45,14 @@ impl<'a> RotaryInput<'a> {
                 self.clk_debounce.wait(),
             )
             .await;
-            self.dt_debounce
-                .process_data(self.dt.state().await, Instant::now());
-            self.clk_debounce
-                .process_data(self.clk.state().await, Instant::now());
-            if let Some(direction) = self.rotary_encoder.process_data(RotaryPinsState {
+            self.dt_debounce.process_data(self.dt.state().await);
+            self.clk_debounce.process_data(self.clk.state().await);
+            let direction = self.rotary_encoder.process_data(RotaryPinsState {
                 dt: self.dt_debounce.value() == PinState::Low,
                 clk: self.clk_debounce.value() == PinState::Low,
-            }) {
+            });
+            ROTARY_RESYNCS.store(self.rotary_encoder.resyncs(), Ordering::Relaxed);
+            if let Some(direction) = direction {
                 break direction;
             }
         }
@@ -95,12 +95,14 @@ impl RotaryInput2 {
                         clk_debounce.wait(),
                     )
                     .await;
-                    dt_debounce.process_data(dt.state().await, Instant::now());
-                    clk_debounce.process_data(clk.state().await, Instant::now());
-                    if let Some(direction) = rotary_encoder.process_data(RotaryPinsState {
+                    dt_debounce.process_data(dt.state().await);
+                    clk_debounce.process_data(clk.state().await);
+                    let direction = rotary_encoder.process_data(RotaryPinsState {
                         dt: dt_debounce.value() == PinState::Low,
                         clk: clk_debounce.value() == PinState::Low,
-                    }) {
+                    });
+                    ROTARY_RESYNCS.store(rotary_encoder.resyncs(), Ordering::Relaxed);
+                    if let Some(direction) = direction {
                         value += match direction {
                             Direction::Clockwise => 1,
                             Direction::CounterClockwise => -1,
@@ -149,9 +151,7 @@ impl<'a> RotaryButton<'a> {
     pub async fn wait_until_press(&mut self) {
         loop {
             select(self.switch.watch(), self.debouncer.wait()).await;
-            let level_changed = self
-                .debouncer
-                .process_data(self.switch.state().await, Instant::now());
+            let level_changed = self.debouncer.process_data(self.switch.state().await);
             if level_changed && self.debouncer.value() == PinState::Low {
                 break;
             }
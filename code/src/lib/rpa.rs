@@ -0,0 +1,127 @@
+//! Resolving private addresses with a bond's identity resolving key (IRK), so that a peripheral that
+//! advertises with a resolvable private address (RPA) is listed with its identity address.
+//! The AES-128 block cipher that `ah` uses is RustCrypto's, which is also what trouble-host uses with its `security` feature.
+
+use aes::{
+    Aes128,
+    cipher::{BlockEncrypt, KeyInit},
+};
+use trouble_host::{
+    Address,
+    prelude::{AddrKind, BdAddr},
+};
+
+/// A peripheral that we bonded with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BondedIdentity {
+    /// The address that the peripheral shared while bonding
+    pub address: Address,
+    pub irk: u128,
+}
+
+/// The random address hash function `ah` from the Bluetooth Core Specification, Vol 3, Part H, Section 2.2.2.
+/// `prand` and the hash are most significant byte first, like in the specification.
+pub fn ah(irk: u128, prand: [u8; 3]) -> [u8; 3] {
+    let mut block = [0; 16];
+    block[13..].copy_from_slice(&prand);
+    Aes128::new(&irk.to_be_bytes().into()).encrypt_block((&mut block).into());
+    [block[13], block[14], block[15]]
+}
+
+/// `addr` is a resolvable private address that was made with `irk`
+pub fn resolves_rpa(irk: u128, addr: &BdAddr) -> bool {
+    // BdAddr is least significant byte first: the hash, and then prand
+    let [hash @ .., _, _, _] = addr.into_inner();
+    let [_, _, _, prand @ ..] = addr.into_inner();
+    // The 2 most significant bits of an RPA are 0b01
+    if prand[2] >> 6 != 0b01 {
+        return false;
+    }
+    let mut expected = ah(irk, [prand[2], prand[1], prand[0]]);
+    expected.reverse();
+    hash == expected
+}
+
+/// The identity address of the bonded peripheral that `address` belongs to.
+/// This is either the identity address itself, or a resolvable private address made with the peripheral's IRK.
+pub fn resolve_identity(address: Address, identities: &[BondedIdentity]) -> Option<Address> {
+    identities
+        .iter()
+        .find(|identity| {
+            identity.address == address
+                || (address.kind == AddrKind::RANDOM && resolves_rpa(identity.irk, &address.addr))
+        })
+        .map(|identity| identity.address)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Makes the resolvable private address that a peripheral with `irk` would advertise with
+    fn rpa(irk: u128, prand: [u8; 3]) -> Address {
+        let prand = [prand[0] & 0b0011_1111 | 0b0100_0000, prand[1], prand[2]];
+        let hash = ah(irk, prand);
+        Address {
+            kind: AddrKind::RANDOM,
+            addr: BdAddr::new([hash[2], hash[1], hash[0], prand[2], prand[1], prand[0]]),
+        }
+    }
+
+    /// The sample data from the Bluetooth Core Specification, Vol 3, Part H, Appendix D.7
+    #[test]
+    fn ah_sample_data() {
+        assert_eq!(
+            ah(0xec0234a3_57c8ad05_341010a6_0a397d9b, [0x70, 0x81, 0x94]),
+            [0x0d, 0xfb, 0xaa]
+        );
+    }
+
+    #[test]
+    fn resolve() {
+        // The same RPA that trouble-host tests with
+        let irk = 0x8b3958c1_58ed6446_7bd27bc9_0d3cf54d;
+        assert!(resolves_rpa(
+            irk,
+            &BdAddr::new([0x92, 0xF2, 0x8F, 0x84, 0x72, 0x4F])
+        ));
+        assert!(!resolves_rpa(
+            irk,
+            &BdAddr::new([0x93, 0xF2, 0x8F, 0x84, 0x72, 0x4F])
+        ));
+        // Not marked as an RPA
+        assert!(!resolves_rpa(
+            irk,
+            &BdAddr::new([0x92, 0xF2, 0x8F, 0x84, 0x72, 0xCF])
+        ));
+
+        let identity = BondedIdentity {
+            address: Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0xC5]),
+            irk,
+        };
+        let other = BondedIdentity {
+            address: Address::random([0x10, 0x11, 0x12, 0x13, 0x14, 0xD5]),
+            irk: 1,
+        };
+        let identities = [other, identity];
+        let private = rpa(irk, [0x12, 0x34, 0x56]);
+        assert_eq!(
+            resolve_identity(private, &identities),
+            Some(identity.address)
+        );
+        assert_eq!(
+            resolve_identity(identity.address, &identities),
+            Some(identity.address)
+        );
+        // A public address with the same bytes can't be an RPA
+        let public = Address {
+            kind: AddrKind::PUBLIC,
+            addr: private.addr,
+        };
+        assert_eq!(resolve_identity(public, &identities), None);
+        assert_eq!(
+            resolve_identity(rpa(2, [0x12, 0x34, 0x56]), &identities),
+            None
+        );
+    }
+}
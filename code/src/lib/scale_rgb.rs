@@ -1,16 +0,0 @@
-use smart_leds::RGB8;
-
-pub trait ScaleRgb {
-    fn scale(self, factor: f64) -> Self;
-}
-
-impl ScaleRgb for RGB8 {
-    fn scale(self, factor: f64) -> Self {
-        let Self { r, g, b } = self;
-        Self::new(
-            (r as f64 * factor) as u8,
-            (g as f64 * factor) as u8,
-            (b as f64 * factor) as u8,
-        )
-    }
-}
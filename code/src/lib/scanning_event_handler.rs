@@ -1,6 +1,9 @@
+use core::cell::RefCell;
+
 use bt_hci::param::LeAdvReportsIter;
 use defmt::warn;
 use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, channel::Channel};
+use game_pure::SCAN_LIST_SIZE;
 use trouble_host::{
     Address,
     prelude::{AdStructure, EventHandler},
@@ -8,12 +11,27 @@ use trouble_host::{
 
 use crate::SERVICE_UUID;
 
+/// Carries the kind of address too, since connecting to a public address as a random address doesn't work
 pub type ScanChannel = Channel<CriticalSectionRawMutex, Address, 1>;
 
 pub struct ScanningEventHandler<'a> {
-    pub channel: &'a ScanChannel,
+    channel: &'a ScanChannel,
+    /// Peripherals advertise many times per second, so each address is only sent once
+    sent: RefCell<heapless::Vec<Address, SCAN_LIST_SIZE>>,
+}
+
+impl<'a> ScanningEventHandler<'a> {
+    pub fn new(channel: &'a ScanChannel) -> Self {
+        Self {
+            channel,
+            sent: Default::default(),
+        }
+    }
 }
+
 impl EventHandler for ScanningEventHandler<'_> {
+    // The service UUID is 128 bits
+    #[allow(clippy::unwrap_used)]
     fn on_adv_reports(&self, reports: LeAdvReportsIter) {
         reports
             .filter_map(Result::ok)
@@ -28,13 +46,23 @@ impl EventHandler for ScanningEventHandler<'_> {
                         }
                     })
             })
-            .for_each(|report| {
-                if let Err(e) = self.channel.try_send(Address {
-                    addr: report.addr,
-                    kind: report.addr_kind,
-                }) {
-                    warn!("error sending: {}", e);
-                };
+            .map(|report| Address {
+                addr: report.addr,
+                kind: report.addr_kind,
+            })
+            .for_each(|address| {
+                let mut sent = self.sent.borrow_mut();
+                if sent.contains(&address) {
+                    return;
+                }
+                match self.channel.try_send(address) {
+                    // If there are more peripherals than fit, the extra ones are sent every time they advertise
+                    Ok(()) => {
+                        let _ = sent.push(address);
+                    }
+                    // It will be sent again the next time it advertises
+                    Err(e) => warn!("error sending: {}", e),
+                }
             });
     }
 }
@@ -0,0 +1,43 @@
+/// Remembers the last value that was rendered, so that rendering an identical value again can be skipped
+#[derive(Debug, Clone)]
+pub struct SkipUnchanged<T> {
+    last: Option<T>,
+}
+
+impl<T: PartialEq + Clone> SkipUnchanged<T> {
+    pub const fn new() -> Self {
+        Self { last: None }
+    }
+
+    /// Returns `false` if `value` is the same as the last value, so it doesn't need to be rendered again.
+    /// Otherwise `value` is remembered as the last value.
+    pub fn changed(&mut self, value: &T) -> bool {
+        if self.last.as_ref() == Some(value) {
+            false
+        } else {
+            self.last = Some(value.clone());
+            true
+        }
+    }
+}
+
+impl<T: PartialEq + Clone> Default for SkipUnchanged<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_identical() {
+        let mut skip = SkipUnchanged::new();
+        assert!(skip.changed(&[1, 2, 3]));
+        assert!(!skip.changed(&[1, 2, 3]));
+        assert!(skip.changed(&[1, 2, 4]));
+        // Going back to an older value isn't skipped
+        assert!(skip.changed(&[1, 2, 3]));
+    }
+}
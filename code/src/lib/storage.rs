@@ -1,21 +1,33 @@
-use bt_hci::param::BdAddr;
-use defmt::Format;
-use serde::{Deserialize, Serialize};
+use bt_hci::param::{AddrKind, BdAddr};
+use defmt::{Format, info, warn};
+use embedded_storage_async::nor_flash::NorFlash;
+use game_pure::{
+    ElectionTrackerPlacement, KNOWN_PERIPHERALS_SIZE, KnownPeripheral, PERIPHERAL_NAME_LEN,
+    PLAYERS, Settings,
+};
+use sequential_storage::{
+    cache::KeyCacheImpl,
+    map::{MapStorage, SerializationError, Value},
+};
+use serde::{
+    Deserialize, Deserializer, Serialize,
+    de::{Error as _, Unexpected},
+};
+
+use trouble_host::Address;
+
+use crate::{BondedIdentity, Error, POSTCARD_VALUE_OVERHEAD, PostcardValue};
 // use trouble_host::{
 //     BondInformation, Identity, IdentityResolvingKey, LongTermKey, prelude::SecurityLevel,
 // };
 
-// #[derive(Debug, Format, Serialize, Deserialize, Clone)]
-// pub struct StoredBondInformation {
-//     pub ltk: u128,
-//     pub bd_addr: [u8; 6],
-//     pub irk: Option<u128>,
-//     pub security_level: u8,
-// }
-
-// impl From<StoredBondInformation> for BondInformation {
-//     fn from(value: StoredBondInformation) -> Self {
-//         Self {
+// Restoring bonds needs trouble-host's `security` feature
+// impl TryFrom<StoredBondInformation> for BondInformation {
+//     type Error = InvalidBond;
+//
+//     fn try_from(value: StoredBondInformation) -> Result<Self, Self::Error> {
+//         value.validate()?;
+//         Ok(Self {
 //             ltk: LongTermKey::new(value.ltk),
 //             identity: Identity {
 //                 bd_addr: BdAddr::new(value.bd_addr),
@@ -23,12 +35,11 @@ use serde::{Deserialize, Serialize};
 //             },
 //             is_bonded: true,
 //             security_level: match value.security_level {
-//                 0 => SecurityLevel::NoEncryption,
 //                 1 => SecurityLevel::Encrypted,
 //                 2 => SecurityLevel::EncryptedAuthenticated,
-//                 _ => unreachable!(),
+//                 level => return Err(InvalidBond::UnknownSecurityLevel(level)),
 //             },
-//         }
+//         })
 //     }
 // }
 
@@ -47,20 +58,772 @@ use serde::{Deserialize, Serialize};
 //     }
 // }
 
+#[derive(Debug, Format, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StoredBondInformation {
+    pub ltk: u128,
+    pub bd_addr: [u8; 6],
+    pub irk: Option<u128>,
+    /// 0 is no encryption, 1 is encrypted, and 2 is encrypted and authenticated
+    pub security_level: u8,
+}
+
+/// Why a stored bond can't be restored
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidBond {
+    /// The connection would never be encrypted, so the bond is useless
+    NoEncryption,
+    /// An all-zero LTK is erased flash, not a key that was negotiated
+    ZeroLtk,
+    /// Corrupt flash, since only 0 to 2 are stored
+    UnknownSecurityLevel(u8),
+}
+
+impl StoredBondInformation {
+    /// For listing the bonded peripheral by its identity address when it advertises with a resolvable private address.
+    /// Our boards' identity addresses are random static addresses.
+    pub fn identity(&self) -> Option<BondedIdentity> {
+        self.irk.map(|irk| BondedIdentity {
+            address: Address::random(self.bd_addr),
+            irk,
+        })
+    }
+
+    /// Checks that the bond can encrypt a connection, so that connecting doesn't expect encryption that never comes up
+    pub fn validate(&self) -> Result<(), InvalidBond> {
+        match self.security_level {
+            0 => Err(InvalidBond::NoEncryption),
+            1 | 2 if self.ltk == 0 => Err(InvalidBond::ZeroLtk),
+            1 | 2 => Ok(()),
+            level => Err(InvalidBond::UnknownSecurityLevel(level)),
+        }
+    }
+}
+
+#[derive(Debug, Format, Clone, Copy, Default, PartialEq, Eq)]
+pub struct BondRestoreCounts {
+    pub restored: usize,
+    pub pruned: usize,
+}
+
+/// Removes the bonds that can't be restored, so that they are deleted from flash the next time the storage is saved
+pub fn prune_bonds(
+    bonds: &mut heapless::Vec<StoredBondInformation, STORED_BONDS_LEN>,
+) -> BondRestoreCounts {
+    let len = bonds.len();
+    bonds.retain(|bond| match bond.validate() {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Pruning stored bond for {:02X}: {}", bond.bd_addr, e);
+            false
+        }
+    });
+    BondRestoreCounts {
+        restored: bonds.len(),
+        pruned: len - bonds.len(),
+    }
+}
+
 pub const STORED_BONDS_LEN: usize = 10;
 
+#[derive(Debug, Format, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct StoredKnownPeripheral {
+    pub address: [u8; 6],
+    pub name: heapless::String<PERIPHERAL_NAME_LEN>,
+}
+
+impl From<StoredKnownPeripheral> for KnownPeripheral {
+    fn from(value: StoredKnownPeripheral) -> Self {
+        Self {
+            address: BdAddr::new(value.address),
+            name: value.name,
+        }
+    }
+}
+
+impl From<KnownPeripheral> for StoredKnownPeripheral {
+    fn from(value: KnownPeripheral) -> Self {
+        Self {
+            address: value.address.into_inner(),
+            name: value.name,
+        }
+    }
+}
+
+/// The kinds of addresses that can be connected to
+const ADDR_KINDS: [AddrKind; 4] = [
+    AddrKind::PUBLIC,
+    AddrKind::RANDOM,
+    AddrKind::RESOLVABLE_PRIVATE_OR_PUBLIC,
+    AddrKind::RESOLVABLE_PRIVATE_OR_RANDOM,
+];
+
+#[derive(Debug, Format, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct StoredAddress {
+    /// One of [`ADDR_KINDS`]
+    #[serde(deserialize_with = "deserialize_addr_kind")]
+    pub kind: u8,
+    pub addr: [u8; 6],
+}
+
+fn deserialize_addr_kind<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let kind = u8::deserialize(deserializer)?;
+    if ADDR_KINDS
+        .iter()
+        .any(|addr_kind| addr_kind.into_inner() == kind)
+    {
+        Ok(kind)
+    } else {
+        Err(D::Error::invalid_value(
+            Unexpected::Unsigned(kind.into()),
+            &"a kind of BLE address",
+        ))
+    }
+}
+
+impl From<StoredAddress> for Address {
+    fn from(value: StoredAddress) -> Self {
+        Self {
+            kind: ADDR_KINDS
+                .into_iter()
+                .find(|kind| kind.into_inner() == value.kind)
+                // Only valid kinds are deserialized
+                .unwrap_or(AddrKind::RANDOM),
+            addr: BdAddr::new(value.addr),
+        }
+    }
+}
+
+impl From<Address> for StoredAddress {
+    fn from(value: Address) -> Self {
+        Self {
+            kind: value.kind.into_inner(),
+            addr: value.addr.into_inner(),
+        }
+    }
+}
+
+/// The settings as [`VersionedLiberalStorage::V0None`], [`VersionedLiberalStorage::V0Some`] and [`VersionedLiberalStorage::V1`] stored them.
+/// Frozen, since changing it would change how those versions are read.
+#[derive(Debug, Format, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct StoredSettingsV0 {
+    pub led_brightness: u8,
+    pub invert_screen_interval_secs: u16,
+    #[serde(deserialize_with = "deserialize_players")]
+    pub default_players: u8,
+    pub connect_timeout_ticks: u16,
+    pub show_frame_stats: bool,
+    pub president_notes: bool,
+}
+
+/// The settings that didn't exist yet get their default values
+impl From<StoredSettingsV0> for StoredSettings {
+    fn from(value: StoredSettingsV0) -> Self {
+        Self {
+            led_brightness: value.led_brightness,
+            invert_screen_interval_secs: value.invert_screen_interval_secs,
+            default_players: value.default_players,
+            connect_timeout_ticks: value.connect_timeout_ticks,
+            show_frame_stats: value.show_frame_stats,
+            president_notes: value.president_notes,
+            ..Default::default()
+        }
+    }
+}
+
+/// The settings as [`VersionedLiberalStorage::V2`] and [`FascistStorage`] store them.
+/// Don't add or change fields: freeze this struct like [`StoredSettingsV0`] and add a new version of [`VersionedLiberalStorage`] with a new struct.
+#[derive(Debug, Format, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub struct StoredSettings {
+    pub led_brightness: u8,
+    pub invert_screen_interval_secs: u16,
+    #[serde(deserialize_with = "deserialize_players")]
+    pub default_players: u8,
+    pub connect_timeout_ticks: u16,
+    pub show_frame_stats: bool,
+    pub president_notes: bool,
+    pub auto_start: bool,
+    pub hint_auto_dismiss_ticks: u16,
+    pub rotate_display: bool,
+    pub election_tracker_placement: StoredElectionTrackerPlacement,
+}
+
+/// See [`ElectionTrackerPlacement`]
+#[derive(Debug, Format, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+pub enum StoredElectionTrackerPlacement {
+    Liberal,
+    Fascist,
+    Both,
+}
+
+impl From<StoredElectionTrackerPlacement> for ElectionTrackerPlacement {
+    fn from(value: StoredElectionTrackerPlacement) -> Self {
+        match value {
+            StoredElectionTrackerPlacement::Liberal => Self::Liberal,
+            StoredElectionTrackerPlacement::Fascist => Self::Fascist,
+            StoredElectionTrackerPlacement::Both => Self::Both,
+        }
+    }
+}
+
+impl From<ElectionTrackerPlacement> for StoredElectionTrackerPlacement {
+    fn from(value: ElectionTrackerPlacement) -> Self {
+        match value {
+            ElectionTrackerPlacement::Liberal => Self::Liberal,
+            ElectionTrackerPlacement::Fascist => Self::Fascist,
+            ElectionTrackerPlacement::Both => Self::Both,
+        }
+    }
+}
+
+/// Starting a game with a number of players that the rules don't cover would panic
+fn deserialize_players<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u8, D::Error> {
+    let players = u8::deserialize(deserializer)?;
+    if PLAYERS.contains(&players) {
+        Ok(players)
+    } else {
+        Err(D::Error::invalid_value(
+            Unexpected::Unsigned(players.into()),
+            &"5 to 10 players",
+        ))
+    }
+}
+
+impl Default for StoredSettings {
+    fn default() -> Self {
+        Settings::default().into()
+    }
+}
+
+impl From<StoredSettings> for Settings {
+    fn from(value: StoredSettings) -> Self {
+        Self {
+            led_brightness: value.led_brightness,
+            invert_screen_interval_secs: value.invert_screen_interval_secs,
+            default_players: value.default_players,
+            connect_timeout_ticks: value.connect_timeout_ticks,
+            show_frame_stats: value.show_frame_stats,
+            president_notes: value.president_notes,
+            auto_start: value.auto_start,
+            hint_auto_dismiss_ticks: value.hint_auto_dismiss_ticks,
+            rotate_display: value.rotate_display,
+            election_tracker_placement: value.election_tracker_placement.into(),
+        }
+    }
+}
+
+impl From<Settings> for StoredSettings {
+    fn from(value: Settings) -> Self {
+        Self {
+            led_brightness: value.led_brightness,
+            invert_screen_interval_secs: value.invert_screen_interval_secs,
+            default_players: value.default_players,
+            connect_timeout_ticks: value.connect_timeout_ticks,
+            show_frame_stats: value.show_frame_stats,
+            president_notes: value.president_notes,
+            auto_start: value.auto_start,
+            hint_auto_dismiss_ticks: value.hint_auto_dismiss_ticks,
+            rotate_display: value.rotate_display,
+            election_tracker_placement: value.election_tracker_placement.into(),
+        }
+    }
+}
+
 // Everything that's stored
-#[derive(Debug, Format, Default, Serialize, Deserialize)]
+#[derive(Debug, Format, Default, Serialize, Deserialize, Clone, PartialEq, Eq)]
+#[serde(from = "VersionedLiberalStorage", into = "VersionedLiberalStorage")]
 pub struct LiberalStorage {
-    pub last_connected_peripheral: Option<[u8; 6]>,
+    pub last_connected_peripheral: Option<StoredAddress>,
+    /// Peripherals that the user gave names to
+    pub known_peripherals: heapless::Vec<StoredKnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+    pub settings: StoredSettings,
     // pub saved_bonds: heapless::Vec<StoredBondInformation, STORED_BONDS_LEN>,
 }
 
-#[derive(Debug, Format, Default, Serialize, Deserialize)]
+/// How [`LiberalStorage`] is serialized, so that data from older versions can still be read.
+/// Each version's layout is frozen, so changing what is stored means adding a version.
+/// Postcard serializes an `Option`'s tag like an enum's variant index,
+/// so the first framed version, which started with `last_connected_peripheral: Option<[u8; 6]>`, is read as `V0None` or `V0Some`.
+#[derive(Serialize, Deserialize)]
+enum VersionedLiberalStorage {
+    V0None(
+        heapless::Vec<StoredKnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+        StoredSettingsV0,
+    ),
+    /// The address was always connected to as a random address
+    V0Some(
+        [u8; 6],
+        heapless::Vec<StoredKnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+        StoredSettingsV0,
+    ),
+    /// Stores the kind of address
+    V1 {
+        last_connected_peripheral: Option<StoredAddress>,
+        known_peripherals: heapless::Vec<StoredKnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+        settings: StoredSettingsV0,
+    },
+    /// Adds the auto start, hint, display rotation and election tracker settings
+    V2 {
+        last_connected_peripheral: Option<StoredAddress>,
+        known_peripherals: heapless::Vec<StoredKnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+        settings: StoredSettings,
+    },
+}
+
+impl From<VersionedLiberalStorage> for LiberalStorage {
+    fn from(value: VersionedLiberalStorage) -> Self {
+        match value {
+            VersionedLiberalStorage::V0None(known_peripherals, settings) => Self {
+                last_connected_peripheral: None,
+                known_peripherals,
+                settings: settings.into(),
+            },
+            VersionedLiberalStorage::V0Some(addr, known_peripherals, settings) => Self {
+                last_connected_peripheral: Some(StoredAddress {
+                    kind: AddrKind::RANDOM.into_inner(),
+                    addr,
+                }),
+                known_peripherals,
+                settings: settings.into(),
+            },
+            VersionedLiberalStorage::V1 {
+                last_connected_peripheral,
+                known_peripherals,
+                settings,
+            } => Self {
+                last_connected_peripheral,
+                known_peripherals,
+                settings: settings.into(),
+            },
+            VersionedLiberalStorage::V2 {
+                last_connected_peripheral,
+                known_peripherals,
+                settings,
+            } => Self {
+                last_connected_peripheral,
+                known_peripherals,
+                settings,
+            },
+        }
+    }
+}
+
+impl From<LiberalStorage> for VersionedLiberalStorage {
+    fn from(value: LiberalStorage) -> Self {
+        Self::V2 {
+            last_connected_peripheral: value.last_connected_peripheral,
+            known_peripherals: value.known_peripherals,
+            settings: value.settings,
+        }
+    }
+}
+
+/// How [`LiberalStorage`] was stored before stored values were framed with a length and CRC (see [`PostcardValue`]).
+/// [`migrate_unframed_liberal_storage`] saves it again framed.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct UnframedLiberalStorage {
+    pub last_connected_peripheral: Option<[u8; 6]>,
+}
+
+/// Only reads exactly 1 or 7 bytes, which a framed [`LiberalStorage`] never is
+impl<'a> Value<'a> for UnframedLiberalStorage {
+    fn serialize_into(&self, buffer: &mut [u8]) -> Result<usize, SerializationError> {
+        postcard::to_slice(self, buffer)
+            .map(|bytes| bytes.len())
+            .map_err(|_| SerializationError::BufferTooSmall)
+    }
+
+    fn deserialize_from(buffer: &'a [u8]) -> Result<(Self, usize), SerializationError>
+    where
+        Self: Sized,
+    {
+        let (value, unused_bytes) =
+            postcard::take_from_bytes(buffer).map_err(|_| SerializationError::InvalidFormat)?;
+        if !unused_bytes.is_empty() {
+            return Err(SerializationError::InvalidFormat);
+        }
+        Ok((value, buffer.len()))
+    }
+}
+
+/// The address was always connected to as a random address
+impl From<UnframedLiberalStorage> for LiberalStorage {
+    fn from(value: UnframedLiberalStorage) -> Self {
+        Self {
+            last_connected_peripheral: value.last_connected_peripheral.map(|addr| StoredAddress {
+                kind: AddrKind::RANDOM.into_inner(),
+                addr,
+            }),
+            ..Default::default()
+        }
+    }
+}
+
+/// If the [`LiberalStorage`] can't be read but an [`UnframedLiberalStorage`] can, saves it again framed.
+/// Call it at boot before reading the storage.
+pub async fn migrate_unframed_liberal_storage<S: NorFlash, C: KeyCacheImpl<()>>(
+    map_storage: &mut MapStorage<(), S, C>,
+    buffer: &mut [u8],
+) -> Result<(), Error> {
+    if map_storage
+        .fetch_item::<PostcardValue<LiberalStorage>>(buffer, &())
+        .await
+        .is_ok()
+    {
+        return Ok(());
+    }
+    if let Ok(Some(unframed)) = map_storage
+        .fetch_item::<UnframedLiberalStorage>(buffer, &())
+        .await
+    {
+        info!("Saving the storage from before it was framed again framed");
+        map_storage
+            .store_item(buffer, &(), &PostcardValue(LiberalStorage::from(unframed)))
+            .await?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Format, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct FascistStorage {
+    pub settings: StoredSettings,
     // pub saved_bonds: heapless::Vec<StoredBondInformation, STORED_BONDS_LEN>,
 }
 
 /// This is an estimate
-pub const LIBERAL_DATA_BUFFER_LEN: usize = size_of::<LiberalStorage>();
-pub const FASCIST_DATA_BUFFER_LEN: usize = size_of::<LiberalStorage>();
+pub const LIBERAL_DATA_BUFFER_LEN: usize = size_of::<LiberalStorage>() + POSTCARD_VALUE_OVERHEAD;
+pub const FASCIST_DATA_BUFFER_LEN: usize = size_of::<LiberalStorage>() + POSTCARD_VALUE_OVERHEAD;
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use core::fmt::Debug;
+    use std::panic::catch_unwind;
+
+    use sequential_storage::map::{SerializationError, Value};
+    use serde::de::DeserializeOwned;
+
+    use super::*;
+    use crate::PostcardValue;
+
+    const BUFFER_LEN: usize = 256;
+
+    /// Serializes `value`, and then checks that flipping any single bit and truncating it are errors instead of a panic or a different value.
+    /// Returns the serialized length.
+    fn audit<T: Serialize + DeserializeOwned + PartialEq + Debug>(value: T) -> usize {
+        let mut buffer = [0; BUFFER_LEN];
+        let value = PostcardValue(value);
+        let len = value.serialize_into(&mut buffer).unwrap();
+        let (deserialized, used) = PostcardValue::<T>::deserialize_from(&buffer[..len]).unwrap();
+        assert_eq!(deserialized.0, value.0);
+        assert_eq!(used, len);
+        for bit in 0..len * 8 {
+            let mut corrupted = buffer;
+            corrupted[bit / 8] ^= 1 << (bit % 8);
+            let result = catch_unwind(|| PostcardValue::<T>::deserialize_from(&corrupted[..len]))
+                .unwrap_or_else(|_| panic!("flipping bit {bit} of {value:?} panicked"));
+            assert!(
+                result.is_err(),
+                "flipping bit {bit} of {value:?} gave {:?}",
+                result.map(|(value, _)| value)
+            );
+        }
+        for truncated_len in 0..len {
+            assert!(
+                matches!(
+                    PostcardValue::<T>::deserialize_from(&buffer[..truncated_len]),
+                    Err(SerializationError::BufferTooSmall)
+                ),
+                "truncated to {truncated_len} bytes"
+            );
+        }
+        len
+    }
+
+    /// The biggest value that can be stored
+    fn full_liberal_storage() -> LiberalStorage {
+        LiberalStorage {
+            last_connected_peripheral: Some(StoredAddress {
+                kind: AddrKind::RESOLVABLE_PRIVATE_OR_RANDOM.into_inner(),
+                addr: [0xFF; 6],
+            }),
+            known_peripherals: (0..KNOWN_PERIPHERALS_SIZE)
+                .map(|i| StoredKnownPeripheral {
+                    address: [i as u8; 6],
+                    name: (0..PERIPHERAL_NAME_LEN).map(|_| 'W').collect(),
+                })
+                .collect(),
+            settings: StoredSettings {
+                led_brightness: u8::MAX,
+                invert_screen_interval_secs: u16::MAX,
+                default_players: *PLAYERS.end(),
+                connect_timeout_ticks: u16::MAX,
+                show_frame_stats: true,
+                president_notes: true,
+                auto_start: true,
+                hint_auto_dismiss_ticks: u16::MAX,
+                rotate_display: true,
+                election_tracker_placement: StoredElectionTrackerPlacement::Both,
+            },
+        }
+    }
+
+    #[test]
+    fn bit_flips_and_truncation() {
+        audit(LiberalStorage::default());
+        let len = audit(full_liberal_storage());
+        assert!(len <= LIBERAL_DATA_BUFFER_LEN);
+        audit(FascistStorage::default());
+        audit(FascistStorage {
+            settings: full_liberal_storage().settings,
+        });
+        audit(StoredSettings::default());
+    }
+
+    fn known_peripheral() -> StoredKnownPeripheral {
+        StoredKnownPeripheral {
+            address: [7; 6],
+            name: "Hi".try_into().unwrap(),
+        }
+    }
+
+    fn settings_v0() -> StoredSettingsV0 {
+        StoredSettingsV0 {
+            led_brightness: 50,
+            invert_screen_interval_secs: 300,
+            default_players: 7,
+            connect_timeout_ticks: 10,
+            show_frame_stats: true,
+            president_notes: false,
+        }
+    }
+
+    fn read_liberal(bytes: &[u8]) -> LiberalStorage {
+        let (storage, used) = PostcardValue::<LiberalStorage>::deserialize_from(bytes).unwrap();
+        assert_eq!(used, bytes.len());
+        storage.0
+    }
+
+    /// Saved by the firmware before values were framed
+    #[test]
+    fn migrate_unframed() {
+        let read = |bytes: &[u8]| {
+            assert!(PostcardValue::<LiberalStorage>::deserialize_from(bytes).is_err());
+            let (unframed, used) = UnframedLiberalStorage::deserialize_from(bytes).unwrap();
+            assert_eq!(used, bytes.len());
+            LiberalStorage::from(unframed)
+        };
+        assert_eq!(read(&[0x00]), LiberalStorage::default());
+        assert_eq!(
+            read(&[0x01, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]),
+            LiberalStorage {
+                last_connected_peripheral: Some(StoredAddress {
+                    kind: AddrKind::RANDOM.into_inner(),
+                    addr: [1, 2, 3, 4, 5, 6],
+                }),
+                ..Default::default()
+            }
+        );
+        // Trailing bytes aren't the old layout
+        assert!(UnframedLiberalStorage::deserialize_from(&[0x00, 0x00]).is_err());
+    }
+
+    /// Before the kind of address was stored
+    #[test]
+    fn migrate_v0() {
+        #[rustfmt::skip]
+        let bytes = [
+            0x18, 0x00,
+            // V0Some
+            0x01, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+            0x01, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x02, 0x48, 0x69,
+            0x32, 0xAC, 0x02, 0x07, 0x0A, 0x01, 0x00,
+            0xAA, 0xCA, 0x75, 0xC9,
+        ];
+        assert_eq!(
+            read_liberal(&bytes),
+            LiberalStorage {
+                last_connected_peripheral: Some(StoredAddress {
+                    kind: AddrKind::RANDOM.into_inner(),
+                    addr: [1, 2, 3, 4, 5, 6],
+                }),
+                known_peripherals: [known_peripheral()].into_iter().collect(),
+                settings: settings_v0().into(),
+            }
+        );
+    }
+
+    /// Before the auto start, hint, display rotation and election tracker settings
+    #[test]
+    fn migrate_v1() {
+        #[rustfmt::skip]
+        let bytes = [
+            0x11, 0x00,
+            // V1
+            0x02, 0x01, 0x00, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+            0x00,
+            0x32, 0xAC, 0x02, 0x07, 0x0A, 0x01, 0x00,
+            0x4A, 0x8E, 0x62, 0x69,
+        ];
+        assert_eq!(
+            read_liberal(&bytes),
+            LiberalStorage {
+                last_connected_peripheral: Some(StoredAddress {
+                    kind: AddrKind::PUBLIC.into_inner(),
+                    addr: [1, 2, 3, 4, 5, 6],
+                }),
+                known_peripherals: Default::default(),
+                settings: StoredSettings {
+                    led_brightness: 50,
+                    invert_screen_interval_secs: 300,
+                    default_players: 7,
+                    connect_timeout_ticks: 10,
+                    show_frame_stats: true,
+                    president_notes: false,
+                    ..Default::default()
+                },
+            }
+        );
+    }
+
+    /// The current version, which must keep being read and written the same way
+    #[test]
+    fn v2_layout() {
+        #[rustfmt::skip]
+        let bytes = [
+            0x1F, 0x00,
+            // V2
+            0x03, 0x01, 0x03, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06,
+            0x01, 0x07, 0x07, 0x07, 0x07, 0x07, 0x07, 0x02, 0x48, 0x69,
+            0x32, 0xAC, 0x02, 0x07, 0x0A, 0x01, 0x00, 0x01, 0xD8, 0x04, 0x01, 0x02,
+            0xA7, 0xBD, 0x5B, 0xD5,
+        ];
+        let storage = LiberalStorage {
+            last_connected_peripheral: Some(StoredAddress {
+                kind: AddrKind::RESOLVABLE_PRIVATE_OR_RANDOM.into_inner(),
+                addr: [1, 2, 3, 4, 5, 6],
+            }),
+            known_peripherals: [known_peripheral()].into_iter().collect(),
+            settings: StoredSettings {
+                auto_start: true,
+                hint_auto_dismiss_ticks: 600,
+                rotate_display: true,
+                election_tracker_placement: StoredElectionTrackerPlacement::Both,
+                ..StoredSettings::from(settings_v0())
+            },
+        };
+        assert_eq!(read_liberal(&bytes), storage);
+        let mut buffer = [0; BUFFER_LEN];
+        let len = PostcardValue(storage).serialize_into(&mut buffer).unwrap();
+        assert_eq!(buffer[..len], bytes);
+    }
+
+    #[test]
+    fn public_address() {
+        let address = Address {
+            kind: AddrKind::PUBLIC,
+            addr: BdAddr::new([1, 2, 3, 4, 5, 6]),
+        };
+        let storage = LiberalStorage {
+            last_connected_peripheral: Some(address.into()),
+            ..Default::default()
+        };
+        let mut buffer = [0; BUFFER_LEN];
+        let len = PostcardValue(storage.clone())
+            .serialize_into(&mut buffer)
+            .unwrap();
+        let (deserialized, _) =
+            PostcardValue::<LiberalStorage>::deserialize_from(&buffer[..len]).unwrap();
+        assert_eq!(deserialized.0, storage);
+        assert_eq!(
+            Address::from(deserialized.0.last_connected_peripheral.unwrap()),
+            address
+        );
+    }
+
+    #[test]
+    fn prune_invalid_bonds() {
+        let bond = |ltk, security_level| StoredBondInformation {
+            ltk,
+            bd_addr: [security_level; 6],
+            irk: None,
+            security_level,
+        };
+        assert_eq!(bond(1, 0).validate(), Err(InvalidBond::NoEncryption));
+        assert_eq!(bond(0, 1).validate(), Err(InvalidBond::ZeroLtk));
+        assert_eq!(bond(0, 2).validate(), Err(InvalidBond::ZeroLtk));
+        assert_eq!(
+            bond(1, 3).validate(),
+            Err(InvalidBond::UnknownSecurityLevel(3))
+        );
+
+        // Corrupt flash with a valid CRC is still read, and then pruned
+        let mut buffer = [0; BUFFER_LEN];
+        let stored = [
+            bond(1, 1),
+            bond(1, 0),
+            bond(2, 2),
+            bond(0, 2),
+            bond(1, 3),
+            bond(1, u8::MAX),
+        ]
+        .into_iter()
+        .collect::<heapless::Vec<_, STORED_BONDS_LEN>>();
+        let len = PostcardValue(stored).serialize_into(&mut buffer).unwrap();
+        let (mut bonds, _) = PostcardValue::<heapless::Vec<StoredBondInformation, STORED_BONDS_LEN>>::deserialize_from(&buffer[..len]).unwrap();
+        assert_eq!(
+            prune_bonds(&mut bonds.0),
+            BondRestoreCounts {
+                restored: 2,
+                pruned: 4
+            }
+        );
+        assert_eq!(bonds.0, [bond(1, 1), bond(2, 2)]);
+
+        let mut bonds = heapless::Vec::new();
+        assert_eq!(prune_bonds(&mut bonds), BondRestoreCounts::default());
+    }
+
+    /// Valid postcard data with a valid CRC, like from a different firmware version, must not panic later
+    #[test]
+    fn out_of_range_values() {
+        let mut buffer = [0; BUFFER_LEN];
+        for default_players in [0, *PLAYERS.start() - 1, *PLAYERS.end() + 1, u8::MAX] {
+            let settings = StoredSettings {
+                default_players,
+                ..Default::default()
+            };
+            let len = PostcardValue(settings).serialize_into(&mut buffer).unwrap();
+            assert!(PostcardValue::<StoredSettings>::deserialize_from(&buffer[..len]).is_err());
+        }
+
+        // More known peripherals than fit
+        let too_many_peripherals = PostcardValue((
+            // V2
+            3_u8,
+            None::<StoredAddress>,
+            (0..=KNOWN_PERIPHERALS_SIZE)
+                .map(|i| StoredKnownPeripheral {
+                    address: [i as u8; 6],
+                    name: Default::default(),
+                })
+                .collect::<heapless::Vec<_, { KNOWN_PERIPHERALS_SIZE + 1 }>>(),
+            StoredSettings::default(),
+        ));
+        let len = too_many_peripherals.serialize_into(&mut buffer).unwrap();
+        assert!(matches!(
+            PostcardValue::<LiberalStorage>::deserialize_from(&buffer[..len]),
+            Err(SerializationError::InvalidFormat)
+        ));
+
+        // Not a kind of address that can be connected to
+        let invalid_kind = StoredAddress {
+            kind: AddrKind::ANONYMOUS_ADV.into_inner(),
+            addr: [0; 6],
+        };
+        let len = PostcardValue(invalid_kind)
+            .serialize_into(&mut buffer)
+            .unwrap();
+        assert!(PostcardValue::<StoredAddress>::deserialize_from(&buffer[..len]).is_err());
+    }
+}
@@ -0,0 +1,47 @@
+use defmt::info;
+use embassy_sync::{blocking_mutex::raw::RawMutex, signal::Signal};
+use embassy_time::Ticker;
+use esp_hal::{
+    analog::adc::{Adc, AdcCalCurve, AdcChannel, AdcConfig, Attenuation},
+    gpio::AnalogPin,
+    peripherals::ADC1,
+};
+use game_pure::{SupplyLevel, SupplyMonitor};
+
+use crate::config::{
+    SUPPLY_AVERAGE_SAMPLES, SUPPLY_DIVIDER, SUPPLY_SAMPLE_INTERVAL, SUPPLY_THRESHOLDS,
+};
+
+/// The supply voltage for a reading of the sense pin, both in mV
+pub fn supply_mv(pin_mv: u16) -> u16 {
+    let (supply, pin) = SUPPLY_DIVIDER;
+    (u32::from(pin_mv) * supply / pin).min(u16::MAX.into()) as u16
+}
+
+/// Samples the supply voltage through the voltage divider on `pin` every [`SUPPLY_SAMPLE_INTERVAL`],
+/// and signals the level whenever [`SupplyMonitor`] says that it changed
+pub async fn run_supply_sense(
+    adc1: ADC1<'static>,
+    pin: impl AdcChannel + AnalogPin,
+    signal: &Signal<impl RawMutex, SupplyLevel>,
+) -> ! {
+    let mut config = AdcConfig::new();
+    // The calibration curve makes readings in mV
+    let mut pin =
+        config.enable_pin_with_cal::<_, AdcCalCurve<ADC1<'static>>>(pin, Attenuation::_11dB);
+    let mut adc = Adc::new(adc1, config).into_async();
+    let mut monitor = SupplyMonitor::<SUPPLY_AVERAGE_SAMPLES>::new(SUPPLY_THRESHOLDS);
+    let mut ticker = Ticker::every(SUPPLY_SAMPLE_INTERVAL);
+    loop {
+        let mv = supply_mv(adc.read_oneshot(&mut pin).await);
+        if let Some(level) = monitor.record(mv) {
+            info!(
+                "Supply voltage averaged {} mV, which is {}",
+                monitor.average(),
+                level
+            );
+            signal.signal(level);
+        }
+        ticker.next().await;
+    }
+}
@@ -0,0 +1,225 @@
+use core::cell::RefCell;
+
+use embassy_futures::select::select;
+use embassy_sync::{
+    blocking_mutex::{self, raw::RawMutex},
+    signal::Signal,
+};
+use embassy_time::{Instant, Timer};
+
+/// Decides which state the display task renders next, and when.
+/// Only the newest state is kept, and frames are at least `min_frame_gap` apart,
+/// so that fast rotary movement doesn't make the display fall behind.
+/// The newest state is always rendered eventually.
+#[derive(Debug, Clone)]
+pub struct UiPacer<T> {
+    /// In ms
+    min_frame_gap: u64,
+    pending: Option<T>,
+    /// Set until the pending state is taken, even if it was overwritten by a state that isn't urgent
+    urgent: bool,
+    /// When the last frame was taken, in ms
+    last_frame_at: Option<u64>,
+}
+
+impl<T> UiPacer<T> {
+    /// `min_frame_gap` is in ms
+    pub const fn new(min_frame_gap: u64) -> Self {
+        Self {
+            min_frame_gap,
+            pending: None,
+            urgent: false,
+            last_frame_at: None,
+        }
+    }
+
+    /// Replaces the pending state. An urgent state is rendered without waiting for the frame gap.
+    pub fn push(&mut self, state: T, urgent: bool) {
+        self.pending = Some(state);
+        self.urgent |= urgent;
+    }
+
+    /// When the pending state can be rendered, in ms. `None` if there is nothing to render.
+    pub fn ready_at(&self) -> Option<u64> {
+        self.pending.as_ref()?;
+        Some(match self.last_frame_at {
+            Some(last_frame_at) if !self.urgent => last_frame_at + self.min_frame_gap,
+            _ => 0,
+        })
+    }
+
+    /// Takes the pending state if it can be rendered at `now` (in ms), and counts it as a frame
+    pub fn take(&mut self, now: u64) -> Option<T> {
+        if now < self.ready_at()? {
+            return None;
+        }
+        self.urgent = false;
+        self.last_frame_at = Some(now);
+        self.pending.take()
+    }
+}
+
+/// Like a [`Signal`], but paced by a [`UiPacer`]
+pub struct UiSignal<M: RawMutex, T> {
+    pacer: blocking_mutex::Mutex<M, RefCell<UiPacer<T>>>,
+    /// Wakes up [`UiSignal::wait`] when there is a new state
+    new_state: Signal<M, ()>,
+}
+
+impl<M: RawMutex, T> UiSignal<M, T> {
+    /// `min_frame_gap` is in ms
+    pub const fn new(min_frame_gap: u64) -> Self {
+        Self {
+            pacer: blocking_mutex::Mutex::new(RefCell::new(UiPacer::new(min_frame_gap))),
+            new_state: Signal::new(),
+        }
+    }
+
+    /// Replaces the state that will be rendered next
+    pub fn signal(&self, state: T) {
+        self.push(state, false);
+    }
+
+    /// Replaces the state that will be rendered next, and renders it without waiting for the frame gap
+    pub fn signal_urgent(&self, state: T) {
+        self.push(state, true);
+    }
+
+    fn push(&self, state: T, urgent: bool) {
+        self.pacer
+            .lock(|pacer| pacer.borrow_mut().push(state, urgent));
+        self.new_state.signal(());
+    }
+
+    /// Waits until the newest state can be rendered. Cancel safe.
+    pub async fn wait(&self) -> T {
+        loop {
+            let ready_at = self.pacer.lock(|pacer| {
+                let mut pacer = pacer.borrow_mut();
+                match pacer.take(Instant::now().as_millis()) {
+                    Some(state) => Ok(state),
+                    None => Err(pacer.ready_at()),
+                }
+            });
+            match ready_at {
+                Ok(state) => return state,
+                Err(Some(ready_at)) => {
+                    // A new state could be urgent
+                    select(
+                        Timer::at(Instant::from_millis(ready_at)),
+                        self.new_state.wait(),
+                    )
+                    .await;
+                }
+                Err(None) => self.new_state.wait().await,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use heapless::Vec;
+
+    use super::*;
+
+    const MIN_FRAME_GAP: u64 = 33;
+    /// How long drawing and flushing a frame takes
+    const RENDER_TIME: u64 = 15;
+
+    /// Simulates the display task with a mock clock in ms.
+    /// `updates` are `(time, state, urgent)`, sorted by time. Returns the rendered frames as `(time, state)`.
+    fn simulate(updates: &[(u64, u32, bool)]) -> Vec<(u64, u32), 256> {
+        let mut pacer = UiPacer::new(MIN_FRAME_GAP);
+        let mut updates = updates.iter().peekable();
+        let mut frames = Vec::new();
+        let mut now = 0;
+        loop {
+            while let Some(&(_, state, urgent)) = updates.next_if(|(at, ..)| *at <= now) {
+                pacer.push(state, urgent);
+            }
+            if let Some(state) = pacer.take(now) {
+                frames.push((now, state)).unwrap();
+                now += RENDER_TIME;
+                continue;
+            }
+            // Sleep until the next update or the next frame
+            match [pacer.ready_at(), updates.peek().map(|(at, ..)| *at)]
+                .into_iter()
+                .flatten()
+                .min()
+            {
+                Some(wake_at) => now = now.max(wake_at),
+                None => return frames,
+            }
+        }
+    }
+
+    /// One update every ms
+    fn burst(start: u64, count: u32) -> impl Iterator<Item = (u64, u32, bool)> {
+        (0..count).map(move |i| (start + u64::from(i), i, false))
+    }
+
+    #[test]
+    fn burst_of_100() {
+        let frames = simulate(&burst(1_000, 100).collect::<Vec<_, 100>>());
+        // The first update is shown right away
+        assert_eq!(frames[0], (1_000, 0));
+        for pair in frames.windows(2) {
+            assert!(pair[1].0 - pair[0].0 >= MIN_FRAME_GAP, "{frames:?}");
+        }
+        // Instead of 100 frames that fall further and further behind
+        assert!(
+            frames.len() <= 100 / MIN_FRAME_GAP as usize + 2,
+            "{frames:?}"
+        );
+        // The final state is shown no later than one frame gap after it was signaled
+        let &(last_at, last_state) = frames.last().unwrap();
+        assert_eq!(last_state, 99);
+        assert!(last_at <= 1_099 + MIN_FRAME_GAP, "{frames:?}");
+    }
+
+    #[test]
+    fn bursts_with_pauses() {
+        let updates = burst(0, 100)
+            .chain(burst(500, 100).map(|(at, state, urgent)| (at, state + 100, urgent)))
+            .collect::<Vec<_, 200>>();
+        let frames = simulate(&updates);
+        // The second burst isn't delayed by the first one
+        assert!(frames.contains(&(500, 100)), "{frames:?}");
+        assert!(frames.iter().any(|&(_, state)| state == 99));
+        assert_eq!(frames.last().unwrap().1, 199);
+    }
+
+    #[test]
+    fn urgent_bypasses_frame_gap() {
+        let mut updates = burst(0, 100).collect::<Vec<_, 100>>();
+        // Like a new screen in the middle of scrolling
+        updates[50] = (50, 1_000, true);
+        let frames = simulate(&updates);
+        let &(urgent_at, _) = frames
+            .iter()
+            .find(|(_, state)| *state == 1_000)
+            .expect("urgent state was dropped");
+        // Only waits for the frame that was being drawn
+        assert!(urgent_at < 50 + RENDER_TIME, "{frames:?}");
+        assert_eq!(frames.last().unwrap().1, 99);
+    }
+
+    #[test]
+    fn urgent_is_sticky() {
+        let mut pacer = UiPacer::new(MIN_FRAME_GAP);
+        pacer.push(1, false);
+        assert_eq!(pacer.take(0), Some(1));
+        assert_eq!(pacer.ready_at(), None);
+        pacer.push(2, true);
+        // Overwriting an urgent state keeps it urgent, so the newer state isn't delayed
+        pacer.push(3, false);
+        assert_eq!(pacer.ready_at(), Some(0));
+        assert_eq!(pacer.take(1), Some(3));
+        pacer.push(4, false);
+        assert_eq!(pacer.ready_at(), Some(1 + MIN_FRAME_GAP));
+        assert_eq!(pacer.take(MIN_FRAME_GAP), None);
+        assert_eq!(pacer.take(1 + MIN_FRAME_GAP), Some(4));
+    }
+}
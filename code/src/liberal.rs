@@ -1,48 +1,94 @@
 #![no_std]
 #![no_main]
 
-use core::iter::repeat;
+use core::{cell::RefCell, future::pending, sync::atomic::Ordering};
 
-use defmt::info;
-use embassy_embedded_hal::{adapter::BlockingAsync, shared_bus::asynch::i2c::I2cDeviceWithConfig};
+use common::{AMBER, BLINK_CODE_STEP_MS, BlinkCode, LedAnimator, LedWriter, correct};
+use defmt::{Debug2Format, info, warn};
+use embassy_embedded_hal::{
+    adapter::BlockingAsync, flash::partition::BlockingPartition,
+    shared_bus::asynch::i2c::I2cDeviceWithConfig,
+};
 use embassy_executor::Spawner;
 use embassy_futures::join::*;
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
-use embassy_time::Delay;
+use embassy_sync::{
+    blocking_mutex::{NoopMutex, raw::CriticalSectionRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
+use embassy_time::{Delay, Duration, Instant, Timer};
 use esp_backtrace as _;
 use esp_bootloader_esp_idf::partitions::{
     DataPartitionSubType, PARTITION_TABLE_MAX_LEN, PartitionType, read_partition_table,
 };
+#[cfg(feature = "supply-sense")]
+use esp_hal::rng::Rng;
+#[cfg(not(feature = "supply-sense"))]
+use esp_hal::rng::{Trng, TrngSource};
 use esp_hal::{
     gpio::{Input, InputConfig, Level, Output, OutputConfig, Pull},
     i2c::{self, master::I2c},
     interrupt::software::SoftwareInterruptControl,
     rmt::Rmt,
+    system::software_reset,
     time::Rate,
     timer::timg::TimerGroup,
 };
 use esp_hal_smartled::{SmartLedsAdapterAsync, buffer_size_async, smart_led_buffer};
 use esp_println as _;
 use esp_storage::FlashStorage;
-use game_pure::{BleAction, ConnectState, GameState};
+use game_pure::{
+    BleAction, CommandTransport, ConnectState, DEBUG_DUMP_EVENTS, DEBUG_DUMP_LEN,
+    DetectedPolicyCards, GameEffect, GameState, SupplyLevel, Team,
+    record::{RECORD_ENTRY_LEN, RecordedEvent},
+    record_log::{RECORD_LOG_LEN, RecordLog},
+    shutdown_central,
+    sync::BoardCommand,
+};
 use mcp23017_controller::Mcp23017;
 use sequential_storage::{
     cache::NoCache,
     map::{MapConfig, MapStorage},
 };
-use smart_leds::{RGB8, SmartLedsWriteAsync};
+use smart_leds::RGB8;
 use trouble_host::prelude::*;
 
+#[cfg(feature = "supply-sense")]
+use lib::run_supply_sense;
 use lib::{
-    Direction, LED_BRIGHTNESS, LIBERAL_DATA_BUFFER_LEN, LiberalStorage, PostcardValue,
-    RotaryButton, RotaryInput, ScaleRgb,
+    BLE_UNAVAILABLE, CachedStorage, DISPLAY_MISSING, DisplayInitRetry, DoubleClickInput,
+    ELECTION_TRACKER_COLOR, EventRecorder, ExpanderInput, FlashRecordLog, HEAP_MONITOR,
+    InputSource, LEDS_DISABLED, LIBERAL_BOARD_LEDS, LIBERAL_DATA_BUFFER_LEN, LIBERAL_LED_LAYOUT,
+    LIBERAL_TOTAL_LEDS, LiberalStorage, SkipUnchanged, UiSignal,
     ble_2::{Ble2, BleEvent},
-    config::AUTO_CONNECT,
+    config::{
+        AURA_BLINK_INTERVAL, AUTO_CONNECT, DISPLAY_INIT_RETRY_INTERVAL, DOUBLE_CLICK_WINDOW,
+        EVENT_RECORD_LEN, LED_FADE, LED_FADE_FRAME_INTERVAL, RECORD_LOG_PARTITION,
+        RECORD_LOG_PERSIST_EVENTS, STORAGE_FLUSH_INTERVAL, TICK_INTERVAL, UI_MIN_FRAME_GAP,
+    },
+    dump_last_session, liberal_leds_frame,
     liberal_renderer::render_display_2,
+    migrate_unframed_liberal_storage,
 };
 
 esp_bootloader_esp_idf::esp_app_desc!();
 
+// The fascist board's logs are stamped with our time too, once it synced with us
+defmt::timestamp!("{=u64:ms}", Instant::now().as_millis());
+
+/// BLE is unavailable, so the fascist board can't be told to restart and has to be restarted by hand
+struct NoBle;
+
+impl CommandTransport for NoBle {
+    async fn send_command(&mut self, _command: BoardCommand) -> bool {
+        false
+    }
+
+    async fn wait_for_ack(&mut self, _timeout_ms: u64) -> bool {
+        false
+    }
+}
+
 #[esp_rtos::main]
 async fn main(spawner: Spawner) {
     let _ = spawner;
@@ -56,51 +102,31 @@ async fn main(spawner: Spawner) {
 
     info!("Welcome to the electronic board game Secret Hitler. This is the liberal board.");
 
-    // Some LEDS may be connected but not used
-    const TOTAL_LEDS: usize = 64;
-    // Index on a 8x8 grid
-    fn i(x: usize, y: usize) -> usize {
-        y * 8 + x
-    }
-    // No particular order to this as of now
-    let aura_leds = [i(0, 0), i(6, 0), i(0, 2), i(6, 2), i(0, 4), i(6, 4)];
-    // Each group of leds represents the LEDs for that policy slot
-    let policy_leds = [
-        [i(1, 1), i(1, 3)],
-        [i(2, 1), i(2, 3)],
-        [i(3, 1), i(3, 3)],
-        [i(4, 1), i(4, 3)],
-        [i(5, 1), i(5, 3)],
-    ];
-    // Order matters here
-    let election_tracker_leds = [i(1, 6), i(2, 6), i(3, 6)];
-
     let ws2812_gpio = p.GPIO7;
     let i2c_scl_gpio = p.GPIO5;
     let i2c_sda_gpio = p.GPIO6;
     let interrupt_gpio = p.GPIO1;
     let reset_gpio = p.GPIO8;
+    #[cfg(feature = "supply-sense")]
+    let supply_sense_gpio = p.GPIO3;
 
-    let mut buffer = smart_led_buffer!(buffer_size_async(TOTAL_LEDS));
-    let mut leds_adapter = SmartLedsAdapterAsync::new(
+    let mut buffer = smart_led_buffer!(buffer_size_async(LIBERAL_TOTAL_LEDS));
+    let mut leds_adapter = LedWriter::new(SmartLedsAdapterAsync::new(
         Rmt::new(p.RMT, Rate::from_mhz(80))
             .unwrap()
             .into_async()
             .channel0,
         ws2812_gpio,
         &mut buffer,
-    );
+    ));
     leds_adapter
-        .write(repeat(RGB8::default()).take(TOTAL_LEDS))
-        .await
-        .unwrap();
+        .write(&[RGB8::default(); LIBERAL_TOTAL_LEDS])
+        .await;
 
-    // Scaling factor
-    let aura_color = RGB8::new(255, 0, 255);
-    let liberal_color = RGB8::new(0, 127, 255);
-    let election_tracker_color = RGB8::new(0, 255, 0);
-
-    let signal = Signal::<CriticalSectionRawMutex, _>::new();
+    let signal = UiSignal::<CriticalSectionRawMutex, _>::new(UI_MIN_FRAME_GAP.as_millis());
+    // Wakes up the LED loop to show the blink code
+    let display_missing_signal = Signal::<CriticalSectionRawMutex, ()>::new();
+    let supply_signal = Signal::<CriticalSectionRawMutex, SupplyLevel>::new();
 
     let i2c = Mutex::<CriticalSectionRawMutex, _>::new(
         I2c::new(p.I2C0, i2c::master::Config::default())
@@ -132,107 +158,415 @@ async fn main(spawner: Spawner) {
         .find_partition(PartitionType::Data(DataPartitionSubType::Nvs))
         .unwrap()
         .unwrap();
-    let nvs_partition = nvs.as_embedded_storage(&mut flash);
-    let map_config = MapConfig::new(0..nvs_partition.partition_size() as u32);
+    let record_log_partition = pt
+        .iter()
+        .find(|partition| partition.label_as_str() == RECORD_LOG_PARTITION);
+    // NVS and the record log are both in this flash
+    let flash = NoopMutex::new(RefCell::new(flash));
+    let nvs_partition = BlockingPartition::new(&flash, nvs.offset(), nvs.len());
+    let map_config = MapConfig::new(0..nvs.len());
+    let mut data_buffer = [Default::default(); LIBERAL_DATA_BUFFER_LEN];
     let mut map_storage = MapStorage::<(), _, _>::new(
         BlockingAsync::new(nvs_partition),
         map_config,
         NoCache::new(),
     );
-    let mut data_buffer = [Default::default(); LIBERAL_DATA_BUFFER_LEN];
-    let mut stored_data = map_storage
-        .fetch_item::<PostcardValue<LiberalStorage>>(&mut data_buffer, &())
-        .await
-        .unwrap()
-        .unwrap_or_default();
+    if let Err(e) = migrate_unframed_liberal_storage(&mut map_storage, &mut data_buffer).await {
+        warn!("Failed to migrate the storage: {}", e);
+    }
+    // Everything is stored with one key
+    let mut storage = CachedStorage::<_, LiberalStorage, _, _, 1>::new(
+        map_storage,
+        &mut data_buffer,
+        STORAGE_FLUSH_INTERVAL.as_millis(),
+    );
+    // The last session's events are dumped before the new session erases the oldest sector
+    let mut record_log = match record_log_partition {
+        Some(partition) if partition.len() >= RECORD_LOG_LEN => {
+            let mut record_log_flash = FlashRecordLog(BlockingAsync::new(BlockingPartition::new(
+                &flash,
+                partition.offset(),
+                RECORD_LOG_LEN,
+            )));
+            dump_last_session(&mut record_log_flash).await;
+            RecordLog::start_session(record_log_flash)
+                .await
+                .inspect_err(|e| {
+                    warn!(
+                        "Failed to start a flash record log session: {}",
+                        Debug2Format(e)
+                    )
+                })
+                .ok()
+        }
+        _ => {
+            warn!(
+                "There is no {} partition of {} bytes, so recorded events are lost when resetting",
+                RECORD_LOG_PARTITION, RECORD_LOG_LEN
+            );
+            None
+        }
+    };
+    let mut stored_data = match storage.get(&()).await {
+        Ok(stored_data) => stored_data.cloned().unwrap_or_default(),
+        Err(e) => {
+            // The stored data could be from an older version with different fields
+            warn!("Failed to load stored data: {}. Using defaults.", e);
+            Default::default()
+        }
+    };
 
-    let mut game_state = GameState::new(if AUTO_CONNECT {
-        stored_data.last_connected_peripheral.map(BdAddr::new)
-    } else {
-        None
-    });
     let mut ble = Ble2::new();
-    let controller = esp_radio::init().unwrap();
-    let (ble_runner, mut ble) = ble.run(&controller, p.BT);
+    // Without BLE, the game can still be played with only this board
+    let controller = esp_radio::init()
+        .inspect_err(|e| warn!("Failed to initialize the radio: {}", Debug2Format(e)))
+        .ok();
+    // The ADC senses the supply voltage instead
+    #[cfg(feature = "supply-sense")]
+    let rng = Rng::new();
+    #[cfg(not(feature = "supply-sense"))]
+    let _trng_source = TrngSource::new(p.RNG, p.ADC1);
+    #[cfg(not(feature = "supply-sense"))]
+    let rng = Trng::try_new().unwrap();
+    let (ble_runner, mut ble) = match controller
+        .as_ref()
+        .and_then(|controller| ble.run_esp(controller, p.BT, rng))
+    {
+        Some((ble_runner, ble)) => (Some(ble_runner), Some(ble)),
+        None => {
+            warn!("BLE is unavailable, only this board can be used");
+            BLE_UNAVAILABLE.store(true, Ordering::Relaxed);
+            (None, None)
+        }
+    };
+    let known_peripherals = stored_data
+        .known_peripherals
+        .iter()
+        .cloned()
+        .map(Into::into)
+        .collect();
+    let mut game_state = if ble.is_some() {
+        GameState::new(
+            if AUTO_CONNECT {
+                stored_data.last_connected_peripheral.map(Into::into)
+            } else {
+                None
+            },
+            known_peripherals,
+            stored_data.settings.into(),
+        )
+    } else {
+        GameState::new_local_only(known_peripherals, stored_data.settings.into())
+    };
+    // Without auto-connect, the saved fascist board is still listed first while scanning
+    if let Some(address) = stored_data.last_connected_peripheral
+        && !AUTO_CONNECT
+    {
+        game_state.load_last_connected_peripheral(address.into());
+    }
     let (gpio_expander_runner, expander_pins) = mcp23017.run();
-    join4(
-        render_display_2(&i2c, &signal),
-        ble_runner,
+    join5(
+        join(HEAP_MONITOR.run(), async {
+            #[cfg(feature = "supply-sense")]
+            run_supply_sense(p.ADC1, supply_sense_gpio, &supply_signal).await;
+        }),
+        async {
+            let mut init_retry = DisplayInitRetry::new(DISPLAY_INIT_RETRY_INTERVAL.as_millis());
+            // Rendering only stops if the display couldn't be initialized
+            while let Some(next_attempt_at) = init_retry.next_attempt_at() {
+                Timer::at(Instant::from_millis(next_attempt_at)).await;
+                render_display_2(&i2c, &signal, &mut init_retry).await;
+                display_missing_signal.signal(());
+            }
+        },
+        async {
+            if let Some(ble_runner) = ble_runner {
+                ble_runner.await;
+            }
+        },
         gpio_expander_runner,
         async {
-            let mut rotary_input = RotaryInput::new(expander_pins.B2, expander_pins.B3).await;
-            let mut rotary_button = RotaryButton::new(expander_pins.B1).await;
+            let mut input = DoubleClickInput::new(
+                ExpanderInput::new(expander_pins.B2, expander_pins.B3, expander_pins.B1).await,
+                DOUBLE_CLICK_WINDOW,
+            );
 
             signal.signal(game_state.clone());
+            let mut last_leds_frame = SkipUnchanged::new();
+            let mut led_animator = LedAnimator::<LIBERAL_TOTAL_LEDS>::new(LED_FADE.as_millis());
+            // Can be read with a debugger and replayed with game_pure's `replay` tool
+            let mut event_recorder =
+                EventRecorder::<{ EVENT_RECORD_LEN / RECORD_ENTRY_LEN }>::new();
+            // This board's own card readers aren't read yet, so only the fascist board's cards are detected
+            let mut detected_cards = DetectedPolicyCards::default();
 
             loop {
                 use embassy_futures::select::{Either3::*, *};
-                match select3(
-                    rotary_input.next(),
-                    rotary_button.wait_until_press(),
-                    ble.next(),
-                )
-                .await
                 {
-                    First(direction) => {
-                        info!("Direction: {}", direction);
-                        game_state.process_input(match direction {
-                            Direction::Clockwise => game_pure::Input::Down,
-                            Direction::CounterClockwise => game_pure::Input::Up,
-                        });
+                    let leds = game_state.get_leds();
+                    // Dimmed while the game is paused or the supply voltage is critical
+                    let brightness = leds.brightness(game_state.settings().led_brightness);
+                    let now_ms = Instant::now().as_millis();
+                    let led_colors =
+                        liberal_leds_frame(&leds, game_state.settings().led_brightness);
+                    let blink_on = (now_ms / AURA_BLINK_INTERVAL.as_millis()).is_multiple_of(2);
+
+                    // The LEDs above fade to their new colors, and blinking is shown on top right away
+                    led_animator.set_target(&led_colors, now_ms);
+                    let mut led_colors = led_animator.frame(now_ms);
+                    let aura_on = !(leds.blink_aura || leds.misplaced_board == Some(Team::Liberal))
+                        || blink_on;
+                    if !aura_on {
+                        for &aura_led_index in LIBERAL_LED_LAYOUT.aura {
+                            led_colors[aura_led_index] = Default::default();
+                        }
+                    }
+                    // Blink the last election tracker LED to warn that the next failed election causes chaos
+                    if let Some(led_index) = LIBERAL_BOARD_LEDS.election_tracker_warning(&leds)
+                        && blink_on
+                    {
+                        led_colors[led_index] = correct(ELECTION_TRACKER_COLOR, brightness);
                     }
-                    Second(()) => {
-                        info!("Rotary button pressed");
-                        game_state.process_input(game_pure::Input::Click);
+                    // Without a display, the first aura LED shows that it is missing
+                    if DISPLAY_MISSING.load(Ordering::Relaxed) {
+                        led_colors[LIBERAL_LED_LAYOUT.aura[0]] =
+                            if BlinkCode::DisplayMissing.is_on(now_ms) {
+                                correct(AMBER, brightness)
+                            } else {
+                                Default::default()
+                            };
                     }
-                    Third(BleEvent::PeripheralScanned(address)) => {
+                    if last_leds_frame.changed(&led_colors) {
+                        leds_adapter.write(&led_colors).await;
+                        LEDS_DISABLED.store(leds_adapter.disabled(), Ordering::Relaxed);
+                    }
+                }
+                let blink = {
+                    let leds = game_state.get_leds();
+                    leds.blink_aura
+                        || LIBERAL_BOARD_LEDS.election_tracker_warning(&leds).is_some()
+                        || leds.misplaced_board == Some(Team::Liberal)
+                };
+                let next_tick = game_state.next_tick();
+                let fading = !led_animator.is_done(Instant::now().as_millis());
+                let wake_at = [
+                    blink.then(|| Instant::now() + AURA_BLINK_INTERVAL),
+                    fading.then(|| Instant::now() + LED_FADE_FRAME_INTERVAL),
+                    DISPLAY_MISSING
+                        .load(Ordering::Relaxed)
+                        .then(|| Instant::now() + Duration::from_millis(BLINK_CODE_STEP_MS)),
+                    next_tick.map(|tick| Instant::from_ticks(tick * TICK_INTERVAL.as_ticks())),
+                    storage.deadline().map(Instant::from_millis),
+                ]
+                .into_iter()
+                .flatten()
+                .min();
+                let event = select3(
+                    input.next_input(),
+                    async {
+                        match &mut ble {
+                            Some(ble) => ble.next().await,
+                            None => pending().await,
+                        }
+                    },
+                    async {
+                        let timer = async {
+                            match wake_at {
+                                Some(wake_at) => Timer::at(wake_at).await,
+                                None => pending::<()>().await,
+                            }
+                        };
+                        match select3(timer, display_missing_signal.wait(), supply_signal.wait())
+                            .await
+                        {
+                            Third(level) => Some(level),
+                            First(()) | Second(()) => None,
+                        }
+                    },
+                )
+                .await;
+                let now_ms = Instant::now().as_millis();
+                let tick = Instant::now().as_ticks() / TICK_INTERVAL.as_ticks();
+                game_state.tick(tick);
+                match event {
+                    First(input) => {
+                        info!("Input: {}", input);
+                        event_recorder.record(now_ms, RecordedEvent::Input(input));
+                        game_state.process_input(input);
+                    }
+                    Second(BleEvent::PeripheralScanned(address)) => {
                         info!("Address found: {}", address);
-                        game_state.ble_peripheral_found(address.addr);
+                        event_recorder.record(now_ms, RecordedEvent::PeripheralFound(address));
+                        // Bonds aren't saved on the liberal board yet (see `LiberalStorage`), so there is nothing to resolve with.
+                        // Once they are, use `ble_bonded_peripheral_found` with `resolve_identity` and their `StoredBondInformation::identity`.
+                        game_state.ble_peripheral_found(address);
                     }
-                    Third(BleEvent::ConnectionUpdate(state)) => match state {
+                    Second(BleEvent::ConnectionUpdate(address, state)) => match state {
                         ConnectState::Connected => {
-                            info!("BLE connected");
-                            game_state.ble_connected();
+                            info!("BLE connected to {}", address);
+                            event_recorder.record(now_ms, RecordedEvent::Connected(address));
+                            game_state.ble_connected(address, tick);
                         }
                         ConnectState::Connecting => {
-                            info!("BLE disconnected");
-                            game_state.ble_connected();
+                            info!("BLE disconnected from {}", address);
+                            event_recorder.record(now_ms, RecordedEvent::Disconnected(address));
+                            game_state.ble_disconnected(address, tick);
                         }
                     },
-                }
-                signal.signal(game_state.clone());
-                match game_state.ble_action() {
-                    BleAction::Scan => {
-                        ble.scan();
+                    Second(BleEvent::FascistCards(cards)) => {
+                        info!("The fascist board's cards changed");
+                        for (cards, other, present) in [
+                            (&cards, &detected_cards.fascist, true),
+                            (&detected_cards.fascist, &cards, false),
+                        ] {
+                            for &card in cards.difference(other) {
+                                event_recorder.record(
+                                    now_ms,
+                                    RecordedEvent::PolicyCard {
+                                        board: Team::Fascist,
+                                        card,
+                                        present,
+                                    },
+                                );
+                            }
+                        }
+                        detected_cards.fascist = cards;
+                        // The fascist board syncs its cards as soon as it connects, which can be while setting up
+                        if let GameState::Playing(_) = game_state {
+                            game_state.update_scanned_policy_cards(detected_cards.clone());
+                        }
                     }
-                    BleAction::MaintainConnection(address) => {
-                        ble.maintain_connection(Address {
-                            kind: AddrKind::RANDOM,
-                            addr: address,
-                        });
+                    Third(Some(level)) => {
+                        event_recorder.record(now_ms, RecordedEvent::SupplyLevel(level));
+                        game_state.supply_level_changed(level);
+                    }
+                    Third(None) => {
+                        // Only need to update the blinking LEDs, the game state's tick, or save settings
                     }
                 }
+                if let Some(record_log) = &mut record_log
+                    && event_recorder.unpersisted() >= RECORD_LOG_PERSIST_EVENTS
                 {
-                    let leds = game_state.get_leds();
-                    let mut led_colors = [Default::default(); TOTAL_LEDS];
-                    // Turn on Aura LEDs
-                    for aura_led_index in aura_leds {
-                        led_colors[aura_led_index] = aura_color.scale(LED_BRIGHTNESS);
+                    record_log
+                        .append_all(event_recorder.take_unpersisted())
+                        .await;
+                }
+                // A different screen is shown right away, only scrolling within a screen is rate limited
+                let mut new_screen = false;
+                let mut debug_dump = false;
+                for effect in game_state.drain_effects() {
+                    // Like the LEDs, the command is sent once the fascist board is connected
+                    if let Some(command) = effect.board_command()
+                        && let Some(ble) = &mut ble
+                    {
+                        ble.send_command(command);
                     }
-
-                    // Turn on the policy LEDs
-                    for policy in policy_leds {
-                        for led_index in policy {
-                            led_colors[led_index] = liberal_color.scale(LED_BRIGHTNESS);
+                    match effect {
+                        GameEffect::Disconnect(addresses) => {
+                            // Ble2 disconnects gracefully when it stops maintaining the connections
+                            for address in addresses {
+                                info!("Disconnecting from {}", address);
+                            }
+                        }
+                        GameEffect::ConnectTimedOut(addresses) => {
+                            // Ble2 stops connecting when it is told to scan, and disconnects gracefully
+                            warn!("Connecting timed out, going back to scanning");
+                            for address in addresses {
+                                info!("Disconnecting from {}", address);
+                            }
+                        }
+                        GameEffect::SaveKnownPeripherals(known_peripherals) => {
+                            stored_data.known_peripherals =
+                                known_peripherals.into_iter().map(Into::into).collect();
+                            if let Err(e) = storage
+                                .set((), stored_data.clone(), Instant::now().as_millis())
+                                .await
+                            {
+                                warn!("Failed to save known peripherals: {}", e);
+                            }
+                        }
+                        GameEffect::SettingsChanged(settings) => {
+                            stored_data.settings = settings.into();
+                            if let Err(e) = storage
+                                .set((), stored_data.clone(), Instant::now().as_millis())
+                                .await
+                            {
+                                warn!("Failed to save settings: {}", e);
+                            }
+                        }
+                        GameEffect::GameCompleted(team) => {
+                            info!(
+                                "Game over, the {} won",
+                                match team {
+                                    Team::Liberal => "liberals",
+                                    Team::Fascist => "fascists",
+                                }
+                            );
+                        }
+                        GameEffect::GameStarted => {
+                            info!("A new game started");
+                        }
+                        GameEffect::RestartBoards => {
+                            info!("Restarting both boards");
+                            let command = BoardCommand::Shutdown { reboot: true };
+                            let reset = |_: BoardCommand| {
+                                info!("Resetting");
+                                software_reset();
+                            };
+                            match &mut ble {
+                                Some(ble) => {
+                                    shutdown_central(&mut storage, ble, command, reset).await
+                                }
+                                None => {
+                                    shutdown_central(&mut storage, &mut NoBle, command, reset).await
+                                }
+                            };
+                        }
+                        GameEffect::RedrawScreen => {
+                            new_screen = true;
+                        }
+                        GameEffect::DebugDump => {
+                            debug_dump = true;
                         }
                     }
-
-                    // Turn on the election tracker LEDs
-                    for election_tracker_led_index in election_tracker_leds {
-                        led_colors[election_tracker_led_index] =
-                            election_tracker_color.scale(LED_BRIGHTNESS);
+                }
+                // For when the board disagrees with the table
+                if debug_dump {
+                    let mut dump = heapless::String::<DEBUG_DUMP_LEN>::new();
+                    if game_state
+                        .debug_dump(event_recorder.latest(DEBUG_DUMP_EVENTS), &mut dump)
+                        .is_err()
+                    {
+                        warn!("The debug dump was cut off");
                     }
-                    leds_adapter.write(led_colors).await.unwrap();
+                    info!("Game state:\n{}", dump.as_str());
+                }
+                if new_screen {
+                    signal.signal_urgent(game_state.clone());
+                } else {
+                    signal.signal(game_state.clone());
+                }
+                // Changes are written together, so that changing settings quickly doesn't wear out the flash
+                if let Err(e) = storage.flush_due(Instant::now().as_millis()).await {
+                    warn!("Failed to save the stored data: {}", e);
+                }
+                if let Some(ble) = &mut ble {
+                    match game_state.ble_action() {
+                        BleAction::Off => {
+                            ble.off();
+                        }
+                        BleAction::Scan(preset) => {
+                            ble.scan(preset);
+                        }
+                        BleAction::MaintainConnections(addresses) => {
+                            ble.maintain_connections(addresses, game_state.fascist_board_address());
+                        }
+                    }
+                }
+                if let Some(leds) = game_state.take_sync()
+                    && let Some(ble) = &mut ble
+                {
+                    ble.sync(leds);
                 }
             }
         },
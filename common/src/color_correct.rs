@@ -0,0 +1,120 @@
+use smart_leds::RGB8;
+
+/// WS2812 LEDs are linear, but our eyes are not
+const GAMMA: f64 = 2.2;
+
+/// `GAMMA_TABLE[x]` is `x` with gamma correction applied
+pub const GAMMA_TABLE: [u8; 256] = {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < table.len() {
+        table[i] = (powf(i as f64 / 255.0, GAMMA) * 255.0 + 0.5) as u8;
+        i += 1;
+    }
+    table
+};
+
+/// `x.powf(y)` for `0 <= x <= 1`. `powf` isn't available in `const` or in `no_std`.
+const fn powf(x: f64, y: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    exp(y * ln(x))
+}
+
+/// Natural log for `0 < x <= 1`, using `ln(x) = 2 * atanh((x - 1) / (x + 1))`
+const fn ln(x: f64) -> f64 {
+    // Keep the series argument small so that it converges quickly
+    let mut x = x;
+    let mut halvings = 0;
+    while x < 0.5 {
+        x *= 2.0;
+        halvings += 1;
+    }
+    let t = (x - 1.0) / (x + 1.0);
+    let mut sum = 0.0;
+    let mut term = t;
+    let mut n = 1;
+    while n < 64 {
+        sum += term / n as f64;
+        term *= t * t;
+        n += 2;
+    }
+    2.0 * sum - halvings as f64 * core::f64::consts::LN_2
+}
+
+/// `e^x` for `x <= 0`
+const fn exp(x: f64) -> f64 {
+    // Keep the series argument small so that it converges quickly
+    let mut x = x;
+    let mut squarings = 0;
+    while x < -0.5 {
+        x /= 2.0;
+        squarings += 1;
+    }
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let mut n = 1;
+    while n < 32 {
+        term *= x / n as f64;
+        sum += term;
+        n += 1;
+    }
+    while squarings > 0 {
+        sum *= sum;
+        squarings -= 1;
+    }
+    sum
+}
+
+/// Applies gamma correction so that colors keep their hue, and then scales to the global brightness.
+/// A `brightness` of 255 is full brightness.
+pub fn correct(color: RGB8, brightness: u8) -> RGB8 {
+    let channel =
+        |value: u8| ((GAMMA_TABLE[value as usize] as u16 * brightness as u16 + 127) / 255) as u8;
+    RGB8::new(channel(color.r), channel(color.g), channel(color.b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gamma_table() {
+        assert_eq!(GAMMA_TABLE[0], 0);
+        assert_eq!(GAMMA_TABLE[1], 0);
+        assert_eq!(GAMMA_TABLE[64], 12);
+        assert_eq!(GAMMA_TABLE[128], 56);
+        assert_eq!(GAMMA_TABLE[192], 137);
+        assert_eq!(GAMMA_TABLE[255], 255);
+    }
+
+    #[test]
+    fn gamma_table_is_monotonic() {
+        assert!(GAMMA_TABLE.windows(2).all(|pair| pair[0] <= pair[1]));
+    }
+
+    #[test]
+    fn correct_colors() {
+        assert_eq!(correct(RGB8::new(255, 128, 0), 255), RGB8::new(255, 56, 0));
+        assert_eq!(correct(RGB8::new(255, 128, 0), 128), RGB8::new(128, 28, 0));
+        assert_eq!(correct(RGB8::new(255, 255, 255), 0), RGB8::new(0, 0, 0));
+    }
+
+    /// The integer scaling stays within 1 of scaling with floats, for every value and brightness
+    #[test]
+    fn correct_matches_float() {
+        for value in 0..=u8::MAX {
+            for brightness in 0..=u8::MAX {
+                let reference = GAMMA_TABLE[value as usize] as f64 * brightness as f64 / 255.0;
+                let corrected = correct(RGB8::new(value, value, value), brightness);
+                for channel in [corrected.r, corrected.g, corrected.b] {
+                    assert!(
+                        (channel as f64 - reference).abs() < 1.0,
+                        "{value} at brightness {brightness} is {channel} instead of {reference}"
+                    );
+                }
+            }
+        }
+    }
+}
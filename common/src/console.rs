@@ -0,0 +1,251 @@
+use defmt::Format;
+
+/// The max length of a line typed into the STM32's debug console
+pub const CONSOLE_LINE_LEN: usize = 32;
+
+pub const CONSOLE_HELP: &str = "Commands: leds off, nfc probe, nfc dwell <reader>, nfc soak [fail%], nfc stop, nfc report, stats, reset, help";
+
+/// A command typed into the STM32's debug console
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleCommand {
+    /// Turn off all LEDs
+    LedsOff,
+    /// Read the version of every working NFC reader
+    NfcProbe,
+    /// Sweep the antenna dwell of one NFC reader with a card held on it, see [`DwellSweep`](crate::DwellSweep)
+    NfcDwellSweep {
+        reader: u8,
+    },
+    /// Poll every NFC reader with a card held on it until [`ConsoleCommand::NfcSoakStop`], see [`NfcSoakStats`](crate::NfcSoakStats).
+    /// If a reader's error rate goes above `fail_threshold_percent`, its LED turns red.
+    NfcSoak {
+        fail_threshold_percent: Option<u8>,
+    },
+    NfcSoakStop,
+    /// Print the last soak test results that were saved to flash
+    NfcSoakReport,
+    /// Print the uptime and counters
+    Stats,
+    /// Reboot the STM32
+    Reset,
+    Help,
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum ConsoleError {
+    /// Nothing was typed
+    Empty,
+    UnknownCommand,
+    /// The line was longer than [`CONSOLE_LINE_LEN`]
+    TooLong,
+}
+
+impl ConsoleCommand {
+    /// Words can be separated by any whitespace, and case doesn't matter
+    pub fn parse(line: &str) -> Result<Self, ConsoleError> {
+        let mut words = line.split_ascii_whitespace();
+        let first = words.next().ok_or(ConsoleError::Empty)?;
+        let rest = (words.next(), words.next());
+        let is = |word: &str, expected: &str| word.eq_ignore_ascii_case(expected);
+        match rest {
+            (None, None) if is(first, "stats") => Ok(Self::Stats),
+            (None, None) if is(first, "reset") => Ok(Self::Reset),
+            (None, None) if is(first, "help") => Ok(Self::Help),
+            (Some(second), None) if is(first, "leds") && is(second, "off") => Ok(Self::LedsOff),
+            (Some(second), None) if is(first, "nfc") && is(second, "probe") => Ok(Self::NfcProbe),
+            (Some(second), None) if is(first, "nfc") && is(second, "soak") => Ok(Self::NfcSoak {
+                fail_threshold_percent: None,
+            }),
+            (Some(second), None) if is(first, "nfc") && is(second, "stop") => Ok(Self::NfcSoakStop),
+            (Some(second), None) if is(first, "nfc") && is(second, "report") => {
+                Ok(Self::NfcSoakReport)
+            }
+            (Some(second), Some(third)) if is(first, "nfc") && is(second, "dwell") => {
+                match (third.parse(), words.next()) {
+                    (Ok(reader), None) => Ok(Self::NfcDwellSweep { reader }),
+                    _ => Err(ConsoleError::UnknownCommand),
+                }
+            }
+            (Some(second), Some(third)) if is(first, "nfc") && is(second, "soak") => {
+                match (third.trim_end_matches('%').parse(), words.next()) {
+                    (Ok(percent @ 0..=100), None) => Ok(Self::NfcSoak {
+                        fail_threshold_percent: Some(percent),
+                    }),
+                    _ => Err(ConsoleError::UnknownCommand),
+                }
+            }
+            _ => Err(ConsoleError::UnknownCommand),
+        }
+    }
+
+    /// The command changes state that the ESP also controls,
+    /// so it can confuse the ESP or be undone by the ESP if there is an active session
+    pub fn conflicts_with_esp(&self) -> bool {
+        matches!(self, Self::LedsOff | Self::Reset)
+    }
+}
+
+/// Collects typed bytes into lines.
+/// Both `\r` and `\n` end a line, and backspace deletes the last character.
+#[derive(Debug, Clone)]
+pub struct LineBuffer<const N: usize> {
+    line: heapless::String<N>,
+    too_long: bool,
+}
+
+impl<const N: usize> LineBuffer<N> {
+    pub const fn new() -> Self {
+        Self {
+            line: heapless::String::new(),
+            too_long: false,
+        }
+    }
+
+    /// Returns the line once it ends.
+    /// Non-ASCII bytes are ignored.
+    pub fn push(&mut self, byte: u8) -> Option<Result<heapless::String<N>, ConsoleError>> {
+        match byte {
+            b'\r' | b'\n' => {
+                let line = core::mem::take(&mut self.line);
+                Some(if core::mem::take(&mut self.too_long) {
+                    Err(ConsoleError::TooLong)
+                } else {
+                    Ok(line)
+                })
+            }
+            // Backspace and delete
+            0x08 | 0x7F => {
+                self.line.pop();
+                None
+            }
+            byte if byte.is_ascii() && !byte.is_ascii_control() => {
+                if self.line.push(byte.into()).is_err() {
+                    self.too_long = true;
+                }
+                None
+            }
+            _ => None,
+        }
+    }
+}
+
+impl<const N: usize> Default for LineBuffer<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_commands() {
+        assert_eq!(
+            ConsoleCommand::parse("leds off"),
+            Ok(ConsoleCommand::LedsOff)
+        );
+        assert_eq!(
+            ConsoleCommand::parse("  NFC   Probe "),
+            Ok(ConsoleCommand::NfcProbe)
+        );
+        assert_eq!(
+            ConsoleCommand::parse("nfc dwell 2"),
+            Ok(ConsoleCommand::NfcDwellSweep { reader: 2 })
+        );
+        assert_eq!(
+            ConsoleCommand::parse("nfc soak"),
+            Ok(ConsoleCommand::NfcSoak {
+                fail_threshold_percent: None
+            })
+        );
+        assert_eq!(
+            ConsoleCommand::parse("nfc soak 5"),
+            Ok(ConsoleCommand::NfcSoak {
+                fail_threshold_percent: Some(5)
+            })
+        );
+        assert_eq!(
+            ConsoleCommand::parse("nfc soak 10%"),
+            Ok(ConsoleCommand::NfcSoak {
+                fail_threshold_percent: Some(10)
+            })
+        );
+        assert_eq!(
+            ConsoleCommand::parse("nfc stop"),
+            Ok(ConsoleCommand::NfcSoakStop)
+        );
+        assert_eq!(
+            ConsoleCommand::parse("nfc report"),
+            Ok(ConsoleCommand::NfcSoakReport)
+        );
+        assert_eq!(ConsoleCommand::parse("stats"), Ok(ConsoleCommand::Stats));
+        assert_eq!(ConsoleCommand::parse("reset"), Ok(ConsoleCommand::Reset));
+        assert_eq!(ConsoleCommand::parse("help"), Ok(ConsoleCommand::Help));
+        assert_eq!(ConsoleCommand::parse(""), Err(ConsoleError::Empty));
+        assert_eq!(ConsoleCommand::parse(" \t"), Err(ConsoleError::Empty));
+        for line in [
+            "leds",
+            "leds on",
+            "stats now",
+            "nfc probe 1",
+            "nfc dwell",
+            "nfc dwell x",
+            "nfc dwell 1 2",
+            "nfc soak 101",
+            "nfc soak -1",
+            "nfc soak 5 6",
+            "reboot",
+        ] {
+            assert_eq!(
+                ConsoleCommand::parse(line),
+                Err(ConsoleError::UnknownCommand)
+            );
+        }
+    }
+
+    fn push_str<const N: usize>(
+        buffer: &mut LineBuffer<N>,
+        bytes: &[u8],
+    ) -> Option<Result<heapless::String<N>, ConsoleError>> {
+        let mut result = None;
+        for &byte in bytes {
+            if let Some(line) = buffer.push(byte) {
+                assert!(result.is_none(), "more than one line");
+                result = Some(line);
+            }
+        }
+        result
+    }
+
+    #[test]
+    fn line_buffer() {
+        let mut buffer = LineBuffer::<CONSOLE_LINE_LEN>::new();
+        assert_eq!(push_str(&mut buffer, b"stats"), None);
+        assert_eq!(push_str(&mut buffer, b"\r").unwrap().unwrap(), "stats");
+        // The \n after \r is an empty line
+        assert_eq!(push_str(&mut buffer, b"\n").unwrap().unwrap(), "");
+        // Backspace
+        assert_eq!(
+            push_str(&mut buffer, b"resx\x08et\n").unwrap().unwrap(),
+            "reset"
+        );
+        assert_eq!(
+            push_str(&mut buffer, b"\x7F\x7Fhelp\xFF\x1B\n")
+                .unwrap()
+                .unwrap(),
+            "help"
+        );
+    }
+
+    #[test]
+    fn line_too_long() {
+        let mut buffer = LineBuffer::<4>::new();
+        assert_eq!(
+            push_str(&mut buffer, b"stats\n"),
+            Some(Err(ConsoleError::TooLong))
+        );
+        // The next line is fine
+        assert_eq!(push_str(&mut buffer, b"help\n").unwrap().unwrap(), "help");
+    }
+}
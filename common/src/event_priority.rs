@@ -0,0 +1,129 @@
+use defmt::Format;
+
+use crate::Event;
+
+/// The biggest packet that the STM32 sends, COBS encoded. An [`Event::Nfc`] with 6 readers and 10 byte UIDs is about 80 bytes.
+/// At 2.25 Mbaud, this takes about half a millisecond to send, so a rotary event never waits long behind an NFC event.
+pub const MAX_EVENT_PACKET_LEN: usize = 128;
+
+/// Where the STM32 keeps each kind of event until it is sent.
+/// Newer events of the same kind replace the older one, since only the latest one matters.
+///
+/// The slots are in the order that they are sent, so input and responses to requests go before NFC scans.
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum EventSlot {
+    SoftResetComplete,
+    RotarySwitch,
+    RotaryEncoder,
+    Info,
+    Nfc,
+    NfcAlive,
+    LinkQualityWarning,
+}
+
+impl EventSlot {
+    pub const COUNT: usize = 7;
+
+    pub fn of(event: &Event) -> Self {
+        match event {
+            Event::SoftResetComplete => Self::SoftResetComplete,
+            Event::RotarySwitch { .. } => Self::RotarySwitch,
+            Event::RotaryEncoder(_) => Self::RotaryEncoder,
+            Event::Info { .. } => Self::Info,
+            Event::Nfc(_) => Self::Nfc,
+            Event::NfcAlive => Self::NfcAlive,
+            Event::LinkQualityWarning { .. } => Self::LinkQualityWarning,
+        }
+    }
+
+    /// The index of the slot in an array of [`EventSlot::COUNT`] slots
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+const _: () = assert!(EventSlot::LinkQualityWarning.index() + 1 == EventSlot::COUNT);
+
+/// Takes the event that should be sent next from `slots`, which are indexed by [`EventSlot::index`].
+///
+/// Call this again after sending each event, instead of taking every pending event at once,
+/// so that an input that happens while a big NFC event is being sent goes before the other pending NFC events.
+pub fn next_event<S>(
+    slots: &[S; EventSlot::COUNT],
+    try_take: impl FnMut(&S) -> Option<Event>,
+) -> Option<Event> {
+    slots.iter().find_map(try_take)
+}
+
+#[cfg(test)]
+mod tests {
+    use core::cell::RefCell;
+
+    use heapless::Vec;
+
+    use super::*;
+
+    /// Like the STM32's signals, without needing a mutex
+    type FakeSlots = [RefCell<Option<Event>>; EventSlot::COUNT];
+
+    fn put(slots: &FakeSlots, event: Event) {
+        let slot = EventSlot::of(&event);
+        *slots[slot.index()].borrow_mut() = Some(event);
+    }
+
+    fn take(slots: &FakeSlots) -> Option<EventSlot> {
+        next_event(slots, |slot| slot.borrow_mut().take()).map(|event| EventSlot::of(&event))
+    }
+
+    #[test]
+    fn input_first() {
+        let slots = FakeSlots::default();
+        put(&slots, Event::NfcAlive);
+        put(&slots, Event::Nfc(Vec::new()));
+        put(&slots, Event::RotaryEncoder(1));
+        put(
+            &slots,
+            Event::RotarySwitch {
+                pressed: true,
+                duration_ms: None,
+            },
+        );
+        assert_eq!(take(&slots), Some(EventSlot::RotarySwitch));
+        assert_eq!(take(&slots), Some(EventSlot::RotaryEncoder));
+        assert_eq!(take(&slots), Some(EventSlot::Nfc));
+        assert_eq!(take(&slots), Some(EventSlot::NfcAlive));
+        assert_eq!(take(&slots), None);
+    }
+
+    /// A click while the NFC event is being sent goes before the NFC alive event that was already waiting
+    #[test]
+    fn input_during_nfc() {
+        let slots = FakeSlots::default();
+        put(&slots, Event::Nfc(Vec::new()));
+        put(&slots, Event::NfcAlive);
+        assert_eq!(take(&slots), Some(EventSlot::Nfc));
+        put(
+            &slots,
+            Event::RotarySwitch {
+                pressed: false,
+                duration_ms: Some(100),
+            },
+        );
+        assert_eq!(take(&slots), Some(EventSlot::RotarySwitch));
+        assert_eq!(take(&slots), Some(EventSlot::NfcAlive));
+        assert_eq!(take(&slots), None);
+    }
+
+    /// Only the latest event of each kind is sent
+    #[test]
+    fn replaced() {
+        let slots = FakeSlots::default();
+        put(&slots, Event::RotaryEncoder(1));
+        put(&slots, Event::RotaryEncoder(2));
+        assert!(matches!(
+            next_event(&slots, |slot| slot.borrow_mut().take()),
+            Some(Event::RotaryEncoder(2))
+        ));
+        assert_eq!(take(&slots), None);
+    }
+}
@@ -0,0 +1,263 @@
+use core::array;
+
+use defmt::Format;
+use smart_leds::RGB8;
+
+use crate::correct;
+
+/// How long the rainbow sweep takes when the STM32 boots or soft resets
+pub const BOOT_ANIMATION_MS: u64 = 1_500;
+pub const BOOT_ANIMATION_BRIGHTNESS: u8 = 32;
+/// If the STM32 doesn't receive a valid [`crate::Request`] for this long, it shows [`breathing`]
+pub const LINK_DOWN_TIMEOUT_MS: u64 = 5_000;
+/// The time to breathe in and out once
+pub const BREATHING_PERIOD_MS: u64 = 4_000;
+/// The brightness at the top of each breath
+pub const BREATHING_BRIGHTNESS: u8 = 32;
+pub const AMBER: RGB8 = RGB8::new(255, 120, 0);
+/// How long each blink of a [`BlinkCode`] is on, and also how long it is off between blinks
+pub const BLINK_CODE_STEP_MS: u64 = 250;
+/// How long a [`BlinkCode`] is off before it repeats
+pub const BLINK_CODE_PAUSE_MS: u64 = 1_500;
+
+/// Goes from red (0) to green (85) to blue (170) and back to red
+pub fn wheel(position: u8) -> RGB8 {
+    let position = position as u16;
+    let up = |offset: u16| ((position - offset) * 3) as u8;
+    let down = |offset: u16| (255 - (position - offset) * 3) as u8;
+    match position {
+        0..85 => RGB8::new(down(0), up(0), 0),
+        85..170 => RGB8::new(0, down(85), up(85)),
+        _ => RGB8::new(up(170), 0, down(170)),
+    }
+}
+
+/// A rainbow that sweeps along the strip, so that the LEDs don't look dead before the ESP sends anything.
+/// Returns `None` once the animation is done.
+pub fn boot_animation<const N: usize>(elapsed_ms: u64) -> Option<[RGB8; N]> {
+    if elapsed_ms >= BOOT_ANIMATION_MS {
+        return None;
+    }
+    let lit = (elapsed_ms * N as u64 / BOOT_ANIMATION_MS) as usize + 1;
+    Some(array::from_fn(|i| {
+        if i < lit {
+            correct(wheel((i * 256 / N) as u8), BOOT_ANIMATION_BRIGHTNESS)
+        } else {
+            Default::default()
+        }
+    }))
+}
+
+/// A slow amber breathing, which shows that the board is on but isn't receiving requests
+pub fn breathing(elapsed_ms: u64) -> RGB8 {
+    let half_period = BREATHING_PERIOD_MS / 2;
+    let phase = elapsed_ms % BREATHING_PERIOD_MS;
+    let level = if phase < half_period {
+        phase
+    } else {
+        BREATHING_PERIOD_MS - phase
+    };
+    correct(
+        AMBER,
+        (level * BREATHING_BRIGHTNESS as u64 / half_period) as u8,
+    )
+}
+
+/// Problems that are shown by blinking an LED a number of times and then pausing,
+/// for boards that don't have a display to show them on
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum BlinkCode {
+    /// The OLED didn't respond, so it probably isn't connected
+    DisplayMissing,
+}
+
+impl BlinkCode {
+    pub fn blinks(self) -> u8 {
+        match self {
+            Self::DisplayMissing => 2,
+        }
+    }
+
+    /// Whether the LED is on `elapsed_ms` after the code started.
+    /// The LED only changes at multiples of [`BLINK_CODE_STEP_MS`].
+    pub fn is_on(self, elapsed_ms: u64) -> bool {
+        let blinks_ms = 2 * BLINK_CODE_STEP_MS * u64::from(self.blinks());
+        let phase = elapsed_ms % (blinks_ms + BLINK_CODE_PAUSE_MS);
+        phase < blinks_ms && (phase / BLINK_CODE_STEP_MS).is_multiple_of(2)
+    }
+}
+
+/// Fades between LED frames, so that a change like a newly placed policy doesn't snap on.
+/// Frames are colors after [`correct`], so the fade is linear in how much light the LEDs give off.
+#[derive(Debug, Clone)]
+pub struct LedAnimator<const N: usize> {
+    from: [RGB8; N],
+    to: [RGB8; N],
+    started_ms: u64,
+    duration_ms: u64,
+}
+
+impl<const N: usize> LedAnimator<N> {
+    /// Starts with all LEDs off. A `duration_ms` of 0 never fades.
+    pub const fn new(duration_ms: u64) -> Self {
+        Self {
+            from: [RGB8::new(0, 0, 0); N],
+            to: [RGB8::new(0, 0, 0); N],
+            started_ms: 0,
+            duration_ms,
+        }
+    }
+
+    /// Fades from the frame shown at `now_ms` to `target`.
+    /// Setting the same target again doesn't restart the fade.
+    pub fn set_target(&mut self, target: &[RGB8; N], now_ms: u64) {
+        if *target != self.to {
+            self.from = self.frame(now_ms);
+            self.to = *target;
+            self.started_ms = now_ms;
+        }
+    }
+
+    /// Shows `frame` right away, for overrides like blinking that must not lag behind
+    pub fn jump_to(&mut self, frame: &[RGB8; N]) {
+        self.from = *frame;
+        self.to = *frame;
+    }
+
+    pub fn is_done(&self, now_ms: u64) -> bool {
+        now_ms.saturating_sub(self.started_ms) >= self.duration_ms
+    }
+
+    /// The frame to show at `now_ms`
+    pub fn frame(&self, now_ms: u64) -> [RGB8; N] {
+        if self.is_done(now_ms) {
+            return self.to;
+        }
+        let elapsed = (now_ms.saturating_sub(self.started_ms)) as i64;
+        let duration = self.duration_ms as i64;
+        let channel = |from: u8, to: u8| {
+            let difference = to as i64 - from as i64;
+            // Rounded to the nearest value
+            (from as i64
+                + (2 * difference * elapsed + difference.signum() * duration) / (2 * duration))
+                as u8
+        };
+        array::from_fn(|i| {
+            let (from, to) = (self.from[i], self.to[i]);
+            RGB8::new(
+                channel(from.r, to.r),
+                channel(from.g, to.g),
+                channel(from.b, to.b),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wheel_colors() {
+        assert_eq!(wheel(0), RGB8::new(255, 0, 0));
+        assert_eq!(wheel(85), RGB8::new(0, 255, 0));
+        assert_eq!(wheel(170), RGB8::new(0, 0, 255));
+        assert_eq!(wheel(255), RGB8::new(255, 0, 0));
+    }
+
+    #[test]
+    fn boot_animation_sweeps() {
+        let first = boot_animation::<8>(0).unwrap();
+        assert_eq!(first[0], correct(wheel(0), BOOT_ANIMATION_BRIGHTNESS));
+        assert!(first[1..].iter().all(|color| *color == RGB8::default()));
+
+        let halfway = boot_animation::<8>(BOOT_ANIMATION_MS / 2).unwrap();
+        assert_eq!(
+            halfway
+                .iter()
+                .filter(|color| **color != RGB8::default())
+                .count(),
+            5
+        );
+
+        let last = boot_animation::<8>(BOOT_ANIMATION_MS - 1).unwrap();
+        assert!(last.iter().all(|color| *color != RGB8::default()));
+        assert_eq!(boot_animation::<8>(BOOT_ANIMATION_MS), None);
+    }
+
+    #[test]
+    fn blink_code() {
+        let code = BlinkCode::DisplayMissing;
+        let pattern: [bool; 10] = array::from_fn(|i| code.is_on(i as u64 * BLINK_CODE_STEP_MS));
+        assert_eq!(
+            pattern,
+            [
+                true, false, true, false, false, false, false, false, false, false
+            ]
+        );
+        assert!(code.is_on(BLINK_CODE_STEP_MS - 1));
+        // It repeats after the pause
+        assert!(code.is_on(4 * BLINK_CODE_STEP_MS + BLINK_CODE_PAUSE_MS));
+    }
+
+    #[test]
+    fn breathing_frames() {
+        assert_eq!(breathing(0), RGB8::new(0, 0, 0));
+        assert_eq!(breathing(1_000), RGB8::new(16, 3, 0));
+        assert_eq!(breathing(2_000), RGB8::new(32, 6, 0));
+        assert_eq!(breathing(3_000), breathing(1_000));
+        assert_eq!(breathing(BREATHING_PERIOD_MS), breathing(0));
+    }
+
+    #[test]
+    fn fade() {
+        let mut animator = LedAnimator::<2>::new(300);
+        let target = [RGB8::new(200, 0, 255), RGB8::new(10, 20, 30)];
+        animator.set_target(&target, 1_000);
+        assert_eq!(animator.frame(1_000), [RGB8::default(); 2]);
+        assert_eq!(
+            animator.frame(1_150),
+            [RGB8::new(100, 0, 128), RGB8::new(5, 10, 15)]
+        );
+        assert!(!animator.is_done(1_299));
+        assert_ne!(animator.frame(1_299), target);
+        assert!(animator.is_done(1_300));
+        assert_eq!(animator.frame(1_300), target);
+        assert_eq!(animator.frame(5_000), target);
+
+        // Fading out from halfway through a fade starts from what was shown
+        let mut animator = LedAnimator::<1>::new(300);
+        animator.set_target(&[RGB8::new(200, 200, 200)], 0);
+        animator.set_target(&[RGB8::new(0, 0, 0)], 150);
+        // Setting the same target doesn't start over
+        animator.set_target(&[RGB8::new(0, 0, 0)], 200);
+        assert_eq!(animator.frame(150), [RGB8::new(100, 100, 100)]);
+        assert_eq!(animator.frame(300), [RGB8::new(50, 50, 50)]);
+        assert_eq!(animator.frame(450), [RGB8::new(0, 0, 0)]);
+    }
+
+    #[test]
+    fn no_fade() {
+        let target = [RGB8::new(255, 255, 255); 64];
+        let mut animator = LedAnimator::<64>::new(300);
+        animator.jump_to(&target);
+        assert_eq!(animator.frame(0), target);
+        let mut animator = LedAnimator::<64>::new(0);
+        animator.set_target(&target, 10);
+        assert!(animator.is_done(10));
+        assert_eq!(animator.frame(10), target);
+    }
+
+    fn fade_halfway<const N: usize>() {
+        let mut animator = LedAnimator::<N>::new(300);
+        animator.set_target(&[RGB8::new(200, 100, 0); N], 0);
+        assert_eq!(animator.frame(150), [RGB8::new(100, 50, 0); N]);
+        assert!(animator.is_done(300));
+    }
+
+    #[test]
+    fn led_counts() {
+        fade_halfway::<8>();
+        fade_halfway::<64>();
+    }
+}
@@ -0,0 +1,148 @@
+use core::fmt::Debug;
+
+use defmt::{Debug2Format, Format, warn};
+use smart_leds::{RGB, SmartLedsWriteAsync};
+
+/// After this many frames in a row fail to be written, LED writes are disabled until reboot
+pub const LED_MAX_FAILED_FRAMES: u8 = 5;
+
+/// What to do after trying to write a frame
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum LedWriteNext {
+    /// The frame was written, or gave up on
+    Done,
+    /// Try writing the same frame again
+    Retry,
+    /// Too many frames failed in a row, so this frame and all future frames should not be written
+    Disable,
+}
+
+/// Decides when to retry and when to give up on writing LED frames.
+/// LEDs aren't critical, so an LED driver that keeps failing is turned off instead of panicking.
+#[derive(Debug, Clone, Default)]
+pub struct LedWriteHealth {
+    /// The current frame already failed once
+    retrying: bool,
+    consecutive_failed_frames: u8,
+    disabled: bool,
+}
+
+impl LedWriteHealth {
+    pub const fn new() -> Self {
+        Self {
+            retrying: false,
+            consecutive_failed_frames: 0,
+            disabled: false,
+        }
+    }
+
+    /// Call this after each attempt to write a frame. Each frame is retried once.
+    pub fn record_attempt(&mut self, ok: bool) -> LedWriteNext {
+        if ok {
+            self.retrying = false;
+            self.consecutive_failed_frames = 0;
+            LedWriteNext::Done
+        } else if !self.retrying {
+            self.retrying = true;
+            LedWriteNext::Retry
+        } else {
+            self.retrying = false;
+            self.consecutive_failed_frames += 1;
+            if self.consecutive_failed_frames >= LED_MAX_FAILED_FRAMES {
+                self.disabled = true;
+                LedWriteNext::Disable
+            } else {
+                LedWriteNext::Done
+            }
+        }
+    }
+
+    /// Nothing should be written once this is `true`
+    pub fn disabled(&self) -> bool {
+        self.disabled
+    }
+}
+
+/// Writes frames to WS2812 LEDs with the policy of [`LedWriteHealth`], logging errors instead of returning them
+pub struct LedWriter<W> {
+    leds: W,
+    health: LedWriteHealth,
+}
+
+impl<W> LedWriter<W>
+where
+    W: SmartLedsWriteAsync<Color = RGB<u8>>,
+    W::Error: Debug,
+{
+    pub const fn new(leds: W) -> Self {
+        Self {
+            leds,
+            health: LedWriteHealth::new(),
+        }
+    }
+
+    pub async fn write(&mut self, colors: &[RGB<u8>]) {
+        if self.health.disabled() {
+            return;
+        }
+        loop {
+            let result = self.leds.write(colors.iter().copied()).await;
+            if let Err(e) = &result {
+                warn!("Error writing LEDs: {}", Debug2Format(e));
+            }
+            match self.health.record_attempt(result.is_ok()) {
+                LedWriteNext::Done => break,
+                LedWriteNext::Retry => {}
+                LedWriteNext::Disable => {
+                    warn!(
+                        "Disabling LEDs after {} frames in a row failed",
+                        LED_MAX_FAILED_FRAMES
+                    );
+                    break;
+                }
+            }
+        }
+    }
+
+    /// See [`LedWriteHealth::disabled`]
+    pub fn disabled(&self) -> bool {
+        self.health.disabled()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn retry_once() {
+        let mut health = LedWriteHealth::new();
+        assert_eq!(health.record_attempt(true), LedWriteNext::Done);
+        assert_eq!(health.record_attempt(false), LedWriteNext::Retry);
+        assert_eq!(health.record_attempt(true), LedWriteNext::Done);
+        // A frame that failed twice is given up on
+        assert_eq!(health.record_attempt(false), LedWriteNext::Retry);
+        assert_eq!(health.record_attempt(false), LedWriteNext::Done);
+        assert!(!health.disabled());
+    }
+
+    #[test]
+    fn disable_after_consecutive_failures() {
+        let mut health = LedWriteHealth::new();
+        let fail_frame = |health: &mut LedWriteHealth| {
+            assert_eq!(health.record_attempt(false), LedWriteNext::Retry);
+            health.record_attempt(false)
+        };
+        for _ in 1..LED_MAX_FAILED_FRAMES {
+            assert_eq!(fail_frame(&mut health), LedWriteNext::Done);
+        }
+        // A successful frame starts the count over
+        assert_eq!(health.record_attempt(true), LedWriteNext::Done);
+        for _ in 1..LED_MAX_FAILED_FRAMES {
+            assert_eq!(fail_frame(&mut health), LedWriteNext::Done);
+        }
+        assert!(!health.disabled());
+        assert_eq!(fail_frame(&mut health), LedWriteNext::Disable);
+        assert!(health.disabled());
+    }
+}
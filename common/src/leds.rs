@@ -0,0 +1,135 @@
+use defmt::Format;
+use heapless::Vec;
+use smart_leds::RGB;
+
+use crate::Request;
+
+/// The max number of colors in one [`Request::SetLedsFrame`]
+pub const LEDS_PER_PACKET: usize = 32;
+/// The most LEDs that a strip can have, since [`crate::Event::Info`] reports the number of LEDs as a `u8`.
+/// Boards should check their LED count against this at compile time.
+pub const MAX_FRAME_LEDS: usize = u8::MAX as usize;
+
+/// Splits a full frame into [`Request::SetLedsFrame`] packets, followed by [`Request::CommitLeds`].
+/// Frames can have up to [`MAX_FRAME_LEDS`] LEDs.
+pub fn led_frame_requests(colors: &[RGB<u8>]) -> impl Iterator<Item = Request> + '_ {
+    colors
+        .chunks(LEDS_PER_PACKET)
+        .enumerate()
+        .map(|(i, chunk)| Request::SetLedsFrame {
+            offset: (i * LEDS_PER_PACKET).try_into().unwrap(),
+            colors: Vec::from_slice(chunk).unwrap(),
+        })
+        .chain([Request::CommitLeds])
+}
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct LedsOutOfRange;
+
+/// Colors received with [`Request::SetLedsFrame`], which are only shown after [`Request::CommitLeds`].
+/// This way, a partially updated frame is never shown.
+#[derive(Debug, Clone)]
+pub struct LedStaging<const N: usize> {
+    colors: [RGB<u8>; N],
+}
+
+impl<const N: usize> LedStaging<N> {
+    pub const fn new() -> Self {
+        Self {
+            colors: [RGB { r: 0, g: 0, b: 0 }; N],
+        }
+    }
+
+    /// Nothing is staged if any of the colors would go past the end of the strip
+    pub fn stage(&mut self, offset: u8, colors: &[RGB<u8>]) -> Result<(), LedsOutOfRange> {
+        let offset = offset as usize;
+        self.colors
+            .get_mut(offset..offset + colors.len())
+            .ok_or(LedsOutOfRange)?
+            .copy_from_slice(colors);
+        Ok(())
+    }
+
+    /// The frame to show when committing
+    pub fn frame(&self) -> &[RGB<u8>; N] {
+        &self.colors
+    }
+}
+
+impl<const N: usize> Default for LedStaging<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn color(i: usize) -> RGB<u8> {
+        RGB::new(i as u8, 0, 255 - i as u8)
+    }
+
+    /// Sends a frame of `N` LEDs and returns the number of packets that it took
+    fn send_frame<const N: usize>() -> usize {
+        let frame: [_; N] = core::array::from_fn(color);
+        let mut staging = LedStaging::<N>::new();
+        let mut packets = 0;
+        let mut committed = false;
+        for request in led_frame_requests(&frame) {
+            match request {
+                Request::SetLedsFrame { offset, colors } => {
+                    assert!(!committed);
+                    staging.stage(offset, &colors).unwrap();
+                    packets += 1;
+                }
+                Request::CommitLeds => committed = true,
+                _ => unreachable!(),
+            }
+        }
+        assert!(committed);
+        assert_eq!(staging.frame(), &frame);
+        packets
+    }
+
+    #[test]
+    fn split_frame() {
+        assert_eq!(send_frame::<72>(), 3);
+    }
+
+    #[test]
+    fn frame_sizes() {
+        assert_eq!(send_frame::<8>(), 1);
+        assert_eq!(send_frame::<64>(), 2);
+        assert_eq!(send_frame::<MAX_FRAME_LEDS>(), 8);
+    }
+
+    #[test]
+    fn set_leds_frame_round_trip() {
+        let mut buffer = [0; 256];
+        let request = led_frame_requests(&[color(0); 40]).nth(1).unwrap();
+        let bytes = postcard::to_slice_cobs(&request, &mut buffer).unwrap();
+        match postcard::from_bytes_cobs::<Request>(bytes).unwrap() {
+            Request::SetLedsFrame { offset, colors } => {
+                assert_eq!(offset, 32);
+                assert_eq!(colors.as_slice(), &[color(0); 8]);
+            }
+            _ => panic!("wrong request"),
+        }
+        let bytes = postcard::to_slice_cobs(&Request::CommitLeds, &mut buffer).unwrap();
+        assert!(matches!(
+            postcard::from_bytes_cobs::<Request>(bytes).unwrap(),
+            Request::CommitLeds
+        ));
+    }
+
+    #[test]
+    fn staging_out_of_range() {
+        let mut staging = LedStaging::<64>::new();
+        assert_eq!(staging.stage(60, &[color(1); 4]), Ok(()));
+        assert_eq!(staging.stage(60, &[color(2); 5]), Err(LedsOutOfRange));
+        // Nothing was staged from the invalid packet
+        assert_eq!(staging.frame()[60..], [color(1); 4]);
+        assert_eq!(staging.stage(255, &[color(3)]), Err(LedsOutOfRange));
+    }
+}
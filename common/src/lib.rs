@@ -1,4 +1,17 @@
 #![no_std]
+mod color_correct;
+mod console;
+mod event_priority;
+mod led_animations;
+mod led_writer;
+mod leds;
+mod link_quality;
+mod nfc_dwell;
+mod nfc_retry;
+mod nfc_soak;
+mod packets;
+mod press;
+mod soft_reset;
 
 use defmt::Format;
 use heapless::Vec;
@@ -6,23 +19,219 @@ use mfrc522::Uid;
 use serde::{Deserialize, Serialize};
 use smart_leds::RGB;
 
+pub use color_correct::*;
+pub use console::*;
+pub use event_priority::*;
+pub use led_animations::*;
+pub use led_writer::*;
+pub use leds::*;
+pub use link_quality::*;
+pub use nfc_dwell::*;
+pub use nfc_retry::*;
+pub use nfc_soak::*;
+pub use packets::*;
+pub use press::*;
+pub use soft_reset::*;
+
 #[derive(Debug, Serialize, Deserialize)]
 pub enum Request {
     SoftReset,
     SetLed(bool),
-    #[serde(with = "serde_arrays")]
-    SetLeds([RGB<u8>; 64]),
+    /// Stages colors starting at the LED at `offset`. Use [`led_frame_requests`] to send a full frame.
+    SetLedsFrame {
+        offset: u8,
+        colors: Vec<RGB<u8>, LEDS_PER_PACKET>,
+    },
+    /// Shows all of the staged colors at once
+    CommitLeds,
     WatchRotarySwitch(bool),
     WatchRotaryEncoder(bool),
     WatchNfc(bool),
+    /// Sets how long the antenna of the NFC reader at index `reader` is on before looking for a card.
+    /// The dwell is [`DEFAULT_NFC_DWELL_MS`] until this is sent, and after a soft reset.
+    ConfigureNfc {
+        reader: u8,
+        dwell_ms: u8,
+    },
+    /// The STM32 will respond with [`Event::Info`]
+    GetInfo,
 }
 
 pub const MAX_NFC_READERS: usize = 6;
+/// Increase this whenever [`Request`] or [`Event`] change,
+/// so that the ESP can tell if the STM32 is running an incompatible firmware
+pub const PROTOCOL_VERSION: u16 = 6;
+/// While watching NFC, the STM32 sends [`Event::NfcAlive`] at this interval (in ms),
+/// even if the scanned cards didn't change
+pub const NFC_ALIVE_INTERVAL_MS: u64 = 2_000;
 
 #[derive(Debug, Format, Serialize, Deserialize)]
 pub enum Event {
     SoftResetComplete,
-    RotarySwitch(bool),
+    /// Release events have how long the switch was held, if the STM32 saw it being pressed.
+    /// Use [`Press::from_event`] so that a missed press event doesn't matter.
+    RotarySwitch {
+        pressed: bool,
+        duration_ms: Option<u32>,
+    },
     RotaryEncoder(i64),
     Nfc(Vec<Option<Uid>, MAX_NFC_READERS>),
+    Info {
+        fw_version: u32,
+        protocol_version: u16,
+        /// The max number of NFC readers that the firmware supports
+        nfc_readers: u8,
+        total_leds: u8,
+        uptime_ms: u32,
+        /// Requests that the STM32 couldn't read since booting
+        packet_errors: PacketErrorCounts,
+    },
+    /// NFC scanning is still running
+    NfcAlive,
+    /// More than [`LINK_NOISE_COBS_ERRORS`] requests were corrupted within [`LINK_NOISE_WINDOW_MS`],
+    /// so the UART link is noisy
+    LinkQualityWarning {
+        cobs_errors: u32,
+    },
+}
+
+/// `Uid` doesn't implement `PartialEq`, so this compares the bytes of the UIDs
+pub fn nfc_scan_changed(previous: &[Option<Uid>], current: &[Option<Uid>]) -> bool {
+    scan_changed_by(previous, current, |a, b| a.as_bytes() == b.as_bytes())
+}
+
+fn scan_changed_by<T>(
+    previous: &[Option<T>],
+    current: &[Option<T>],
+    eq: impl Fn(&T, &T) -> bool,
+) -> bool {
+    previous.len() != current.len()
+        || previous
+            .iter()
+            .zip(current)
+            .any(|(previous, current)| match (previous, current) {
+                (Some(previous), Some(current)) => !eq(previous, current),
+                (None, None) => false,
+                _ => true,
+            })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes_changed(previous: &[Option<&[u8]>], current: &[Option<&[u8]>]) -> bool {
+        scan_changed_by(previous, current, |a, b| a == b)
+    }
+
+    #[test]
+    fn nfc_scan_unchanged() {
+        assert!(!bytes_changed(&[], &[]));
+        assert!(!bytes_changed(&[None, None], &[None, None]));
+        assert!(!bytes_changed(
+            &[Some(&[1, 2, 3, 4]), None],
+            &[Some(&[1, 2, 3, 4]), None]
+        ));
+    }
+
+    /// [`Uid`] can only be made by mfrc522, so this deserializes one with a SAK of 0
+    fn uid(bytes: &[u8]) -> Uid {
+        let variant = match bytes.len() {
+            4 => 0,
+            7 => 1,
+            10 => 2,
+            len => panic!("UIDs can't be {len} bytes"),
+        };
+        let mut buffer = Vec::<u8, 12>::new();
+        buffer.push(variant).unwrap();
+        buffer.extend_from_slice(bytes).unwrap();
+        buffer.push(0).unwrap();
+        postcard::from_bytes(&buffer).unwrap()
+    }
+
+    #[test]
+    fn uid_bytes() {
+        for bytes in [&[1, 2, 3, 4][..], &[1, 2, 3, 4, 5, 6, 7], &[0xAB; 10]] {
+            assert_eq!(uid(bytes).as_bytes(), bytes);
+        }
+    }
+
+    #[test]
+    fn nfc_scan_compares_uid_bytes() {
+        // Separately read UIDs of the same card
+        assert!(!nfc_scan_changed(
+            &[Some(uid(&[1, 2, 3, 4])), None],
+            &[Some(uid(&[1, 2, 3, 4])), None]
+        ));
+        assert!(nfc_scan_changed(
+            &[Some(uid(&[1, 2, 3, 4]))],
+            &[Some(uid(&[1, 2, 3, 5]))]
+        ));
+        assert!(nfc_scan_changed(
+            &[Some(uid(&[1, 2, 3, 4]))],
+            &[Some(uid(&[1, 2, 3, 4, 5, 6, 7]))]
+        ));
+        assert!(nfc_scan_changed(&[None], &[Some(uid(&[1, 2, 3, 4]))]));
+    }
+
+    #[test]
+    fn nfc_scan_has_changes() {
+        // Card placed
+        assert!(bytes_changed(&[None, None], &[Some(&[1, 2, 3, 4]), None]));
+        // Card removed
+        assert!(bytes_changed(&[None, Some(&[1, 2, 3, 4])], &[None, None]));
+        // Different card
+        assert!(bytes_changed(
+            &[Some(&[1, 2, 3, 4])],
+            &[Some(&[1, 2, 3, 5])]
+        ));
+        // Same prefix but a longer UID
+        assert!(bytes_changed(
+            &[Some(&[1, 2, 3, 4])],
+            &[Some(&[1, 2, 3, 4, 5, 6, 7])]
+        ));
+        // A reader stopped working
+        assert!(bytes_changed(&[None, None], &[None]));
+    }
+
+    #[test]
+    fn get_info_round_trip() {
+        let mut buffer = [0; 64];
+        let bytes = postcard::to_slice_cobs(&Request::GetInfo, &mut buffer).unwrap();
+        assert!(matches!(
+            postcard::from_bytes_cobs::<Request>(bytes).unwrap(),
+            Request::GetInfo
+        ));
+    }
+
+    #[test]
+    fn info_round_trip() {
+        let mut buffer = [0; 64];
+        let bytes = postcard::to_slice_cobs(
+            &Event::Info {
+                fw_version: 3,
+                protocol_version: PROTOCOL_VERSION,
+                nfc_readers: MAX_NFC_READERS as u8,
+                total_leds: 64,
+                uptime_ms: 123_456,
+                packet_errors: PacketErrorCounts {
+                    cobs: 7,
+                    ..Default::default()
+                },
+            },
+            &mut buffer,
+        )
+        .unwrap();
+        assert!(matches!(
+            postcard::from_bytes_cobs::<Event>(bytes).unwrap(),
+            Event::Info {
+                fw_version: 3,
+                protocol_version: PROTOCOL_VERSION,
+                nfc_readers: 6,
+                total_leds: 64,
+                uptime_ms: 123_456,
+                packet_errors: PacketErrorCounts { cobs: 7, .. },
+            }
+        ));
+    }
 }
@@ -0,0 +1,225 @@
+use core::fmt;
+
+use defmt::Format;
+use serde::{Deserialize, Serialize};
+
+use crate::PacketError;
+
+/// How long (in ms) COBS errors are counted for before starting over
+pub const LINK_NOISE_WINDOW_MS: u64 = 10_000;
+/// More COBS errors than this within [`LINK_NOISE_WINDOW_MS`] means that the UART link is noisy
+pub const LINK_NOISE_COBS_ERRORS: u32 = 5;
+
+/// The number of packets that couldn't be read, by why they couldn't be read.
+/// The counters saturate, so they are still meaningful after running for a very long time.
+#[derive(Debug, Format, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PacketErrorCounts {
+    pub too_long: u32,
+    pub empty: u32,
+    pub cobs: u32,
+    pub unknown: u32,
+    pub deserialize: u32,
+}
+
+impl PacketErrorCounts {
+    pub fn record(&mut self, error: &PacketError) {
+        let counter = match error {
+            PacketError::TooLong => &mut self.too_long,
+            PacketError::Empty => &mut self.empty,
+            PacketError::Cobs => &mut self.cobs,
+            PacketError::Unknown => &mut self.unknown,
+            PacketError::Deserialize(_) => &mut self.deserialize,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    pub fn total(&self) -> u64 {
+        u64::from(self.too_long)
+            + u64::from(self.empty)
+            + u64::from(self.cobs)
+            + u64::from(self.unknown)
+            + u64::from(self.deserialize)
+    }
+}
+
+impl fmt::Display for PacketErrorCounts {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (empty: {}, cobs: {}, decode: {}, unknown: {}, too long: {})",
+            self.total(),
+            self.empty,
+            self.cobs,
+            self.deserialize,
+            self.unknown,
+            self.too_long
+        )
+    }
+}
+
+/// Tells when COBS errors happen often enough that the UART link is probably noisy,
+/// rather than the occasional corrupted packet
+#[derive(Debug, Default)]
+pub struct LinkNoiseDetector {
+    window_start_ms: u64,
+    cobs_errors: u32,
+    warned: bool,
+}
+
+impl LinkNoiseDetector {
+    pub const fn new() -> Self {
+        Self {
+            window_start_ms: 0,
+            cobs_errors: 0,
+            warned: false,
+        }
+    }
+
+    /// Returns the number of COBS errors in the current window once it exceeds [`LINK_NOISE_COBS_ERRORS`].
+    /// This only returns `Some` once per window.
+    pub fn record_cobs_error(&mut self, now_ms: u64) -> Option<u32> {
+        if now_ms.saturating_sub(self.window_start_ms) >= LINK_NOISE_WINDOW_MS {
+            *self = Self {
+                window_start_ms: now_ms,
+                ..Self::new()
+            };
+        }
+        self.cobs_errors = self.cobs_errors.saturating_add(1);
+        if self.cobs_errors > LINK_NOISE_COBS_ERRORS && !self.warned {
+            self.warned = true;
+            Some(self.cobs_errors)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+    use crate::{PacketReader, Request};
+
+    /// Reads `bytes` and counts the errors
+    fn count(bytes: &[u8]) -> PacketErrorCounts {
+        let mut reader = PacketReader::<16>::new();
+        let mut counts = PacketErrorCounts::default();
+        for chunk in bytes.chunks(4) {
+            reader.unfilled()[..chunk.len()].copy_from_slice(chunk);
+            reader.received(chunk.len());
+            while let Some(result) = reader.next_packet::<Request>() {
+                if let Err(e) = result {
+                    counts.record(&e);
+                }
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn noise() {
+        assert_eq!(
+            count(&[0, 0, 5, 1, 0, 0, 9, 9, 0, 3, 6, 1, 0]),
+            PacketErrorCounts {
+                empty: 3,
+                cobs: 2,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn protocol_bugs() {
+        assert_eq!(
+            count(&[2, 100, 0, 3, 6, 2, 0, 3, 6, 1, 0]),
+            PacketErrorCounts {
+                unknown: 1,
+                deserialize: 1,
+                ..Default::default()
+            }
+        );
+        assert_eq!(
+            count(&[1; 20]),
+            PacketErrorCounts::default(),
+            "the rest of the packet wasn't received yet"
+        );
+        let mut bytes = [1; 21];
+        bytes[20] = 0;
+        assert_eq!(
+            count(&bytes),
+            PacketErrorCounts {
+                too_long: 1,
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn saturating() {
+        let mut counts = PacketErrorCounts {
+            cobs: u32::MAX,
+            empty: u32::MAX,
+            ..Default::default()
+        };
+        counts.record(&PacketError::Cobs);
+        assert_eq!(counts.cobs, u32::MAX);
+        assert_eq!(counts.total(), 2 * u64::from(u32::MAX));
+    }
+
+    #[test]
+    fn display() {
+        let mut string = heapless::String::<96>::new();
+        write!(
+            string,
+            "{}",
+            PacketErrorCounts {
+                empty: 1,
+                cobs: 2,
+                deserialize: 3,
+                unknown: 4,
+                too_long: 5,
+            }
+        )
+        .unwrap();
+        assert_eq!(
+            string,
+            "15 (empty: 1, cobs: 2, decode: 3, unknown: 4, too long: 5)"
+        );
+    }
+
+    #[test]
+    fn noisy_link() {
+        let mut detector = LinkNoiseDetector::new();
+        for i in 0..LINK_NOISE_COBS_ERRORS {
+            assert_eq!(detector.record_cobs_error(u64::from(i) * 100), None);
+        }
+        assert_eq!(
+            detector.record_cobs_error(1_000),
+            Some(LINK_NOISE_COBS_ERRORS + 1)
+        );
+        // Only warned once per window
+        assert_eq!(detector.record_cobs_error(2_000), None);
+        // The errors were spread out enough
+        let mut detector = LinkNoiseDetector::new();
+        for i in 0..3 * u64::from(LINK_NOISE_COBS_ERRORS) {
+            assert_eq!(
+                detector.record_cobs_error(i * LINK_NOISE_WINDOW_MS / 3),
+                None
+            );
+        }
+        // A new window warns again
+        let mut detector = LinkNoiseDetector::new();
+        for i in 0..=LINK_NOISE_COBS_ERRORS {
+            detector.record_cobs_error(u64::from(i));
+        }
+        let start = LINK_NOISE_WINDOW_MS;
+        for i in 0..LINK_NOISE_COBS_ERRORS {
+            assert_eq!(detector.record_cobs_error(start + u64::from(i)), None);
+        }
+        assert_eq!(
+            detector.record_cobs_error(start + 100),
+            Some(LINK_NOISE_COBS_ERRORS + 1)
+        );
+    }
+}
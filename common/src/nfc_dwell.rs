@@ -0,0 +1,181 @@
+use defmt::Format;
+use heapless::Vec;
+
+/// How long (in ms) a reader's antenna is on before the first WUPA, unless it is configured with [`Request::ConfigureNfc`](crate::Request::ConfigureNfc).
+/// Cards at marginal coupling need some time to power up before they can answer.
+pub const DEFAULT_NFC_DWELL_MS: u8 = 5;
+/// A longer dwell would slow down scanning too much, so configured dwells are clamped to this
+pub const MAX_NFC_DWELL_MS: u8 = 50;
+/// The shortest dwell that is tried by [`DwellSweep`]
+pub const DWELL_SWEEP_MIN_MS: u8 = 1;
+/// The longest dwell that is tried by [`DwellSweep`]
+pub const DWELL_SWEEP_MAX_MS: u8 = 20;
+pub const DWELL_SWEEP_STEPS: usize = (DWELL_SWEEP_MAX_MS - DWELL_SWEEP_MIN_MS + 1) as usize;
+/// How many times a card is read at each dwell
+pub const DWELL_SWEEP_ATTEMPTS: u8 = 10;
+
+/// Tries every dwell from [`DWELL_SWEEP_MIN_MS`] to [`DWELL_SWEEP_MAX_MS`] on one reader,
+/// to find out how long the antenna needs to be on for a card that is held in place.
+///
+/// Read the card with the antenna on for [`DwellSweep::dwell_ms`], and then call [`DwellSweep::record`], until the sweep is done.
+#[derive(Debug, Clone)]
+pub struct DwellSweep {
+    attempts: u8,
+    /// The number of detections at each dwell so far
+    successes: Vec<u8, DWELL_SWEEP_STEPS>,
+    /// The number of attempts at the current dwell
+    current_attempts: u8,
+}
+
+impl DwellSweep {
+    /// `attempts` is how many times the card is read at each dwell, and 0 is treated as 1
+    pub fn new(attempts: u8) -> Self {
+        let mut successes = Vec::new();
+        // It's never full
+        let _ = successes.push(0);
+        Self {
+            attempts: attempts.max(1),
+            successes,
+            current_attempts: 0,
+        }
+    }
+
+    /// The dwell to use for the next read, or `None` if the sweep is done
+    pub fn dwell_ms(&self) -> Option<u8> {
+        if self.current_attempts == self.attempts {
+            None
+        } else {
+            Some(DWELL_SWEEP_MIN_MS + self.successes.len() as u8 - 1)
+        }
+    }
+
+    /// Call this after each read with whether the card was detected.
+    /// Results after the sweep is done are ignored.
+    pub fn record(&mut self, detected: bool) {
+        if self.dwell_ms().is_none() {
+            return;
+        }
+        if detected && let Some(successes) = self.successes.last_mut() {
+            *successes += 1;
+        }
+        self.current_attempts += 1;
+        if self.current_attempts == self.attempts && self.successes.push(0).is_ok() {
+            self.current_attempts = 0;
+        }
+    }
+
+    /// The results of the dwells that were tried so far
+    pub fn results(&self) -> DwellSweepResults {
+        let mut successes = self.successes.clone();
+        if self.current_attempts != self.attempts {
+            // The current dwell isn't done
+            successes.pop();
+        }
+        DwellSweepResults {
+            attempts: self.attempts,
+            successes,
+        }
+    }
+}
+
+#[derive(Debug, Format, Clone, PartialEq, Eq)]
+pub struct DwellSweepResults {
+    /// How many times the card was read at each dwell
+    pub attempts: u8,
+    /// The number of detections at each dwell, starting at [`DWELL_SWEEP_MIN_MS`]
+    pub successes: Vec<u8, DWELL_SWEEP_STEPS>,
+}
+
+impl DwellSweepResults {
+    /// Each dwell with its detection success rate in %
+    pub fn success_rates(&self) -> impl Iterator<Item = (u8, u8)> + '_ {
+        (DWELL_SWEEP_MIN_MS..).zip(
+            self.successes.iter().map(|&successes| {
+                (u16::from(successes) * 100 / u16::from(self.attempts.max(1))) as u8
+            }),
+        )
+    }
+
+    /// The shortest dwell where the card was always detected, and also always detected with every longer dwell
+    pub fn min_reliable_dwell_ms(&self) -> Option<u8> {
+        let reliable_steps = self
+            .successes
+            .iter()
+            .rev()
+            .take_while(|&&successes| successes == self.attempts)
+            .count();
+        (reliable_steps > 0)
+            .then(|| DWELL_SWEEP_MIN_MS + (self.successes.len() - reliable_steps) as u8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A card that is only detected if the antenna is on for at least `threshold_ms`
+    fn sweep(threshold_ms: u8, attempts: u8) -> DwellSweepResults {
+        let mut sweep = DwellSweep::new(attempts);
+        let mut reads = 0;
+        while let Some(dwell_ms) = sweep.dwell_ms() {
+            sweep.record(dwell_ms >= threshold_ms);
+            reads += 1;
+        }
+        assert_eq!(reads, DWELL_SWEEP_STEPS * usize::from(attempts));
+        sweep.results()
+    }
+
+    #[test]
+    fn threshold_reader() {
+        let results = sweep(7, 4);
+        assert_eq!(results.successes.len(), DWELL_SWEEP_STEPS);
+        assert_eq!(results.min_reliable_dwell_ms(), Some(7));
+        let rates = results
+            .success_rates()
+            .collect::<Vec<_, DWELL_SWEEP_STEPS>>();
+        assert_eq!(rates[0], (1, 0));
+        assert_eq!(rates[5], (6, 0));
+        assert_eq!(rates[6], (7, 100));
+        assert_eq!(rates[DWELL_SWEEP_STEPS - 1], (DWELL_SWEEP_MAX_MS, 100));
+
+        assert_eq!(sweep(1, 1).min_reliable_dwell_ms(), Some(1));
+        // The card is never detected
+        assert_eq!(
+            sweep(DWELL_SWEEP_MAX_MS + 1, 2).min_reliable_dwell_ms(),
+            None
+        );
+    }
+
+    #[test]
+    fn flaky_reads() {
+        let mut sweep = DwellSweep::new(4);
+        // The card is missed once per dwell below 10 ms, and once at 15 ms
+        while let Some(dwell_ms) = sweep.dwell_ms() {
+            let attempt = sweep.current_attempts;
+            sweep.record(!(attempt == 0 && (dwell_ms < 10 || dwell_ms == 15)));
+        }
+        // Extra results are ignored
+        sweep.record(false);
+        let results = sweep.results();
+        assert_eq!(results.min_reliable_dwell_ms(), Some(16));
+        assert_eq!(results.success_rates().nth(14), Some((15, 75)));
+        assert_eq!(results.success_rates().nth(13), Some((14, 100)));
+    }
+
+    #[test]
+    fn partial_results() {
+        let mut sweep = DwellSweep::new(3);
+        for _ in 0..4 {
+            sweep.record(true);
+        }
+        assert_eq!(sweep.dwell_ms(), Some(2));
+        // Only the first dwell is done
+        assert_eq!(
+            sweep.results(),
+            DwellSweepResults {
+                attempts: 3,
+                successes: Vec::from_slice(&[3]).unwrap(),
+            }
+        );
+    }
+}
@@ -0,0 +1,145 @@
+use defmt::Format;
+
+use crate::NfcReadError;
+
+/// How many times reading a card is tried again within one polling round.
+///
+/// A card that answers the WUPA but collides during the SELECT is usually there, so reporting it as missing
+/// would make it flicker for one round. Reading it again right away usually works.
+/// A WUPA timeout means that there is no card, so that isn't tried again.
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct NfcRetryPolicy {
+    /// How many more times the WUPA and SELECT are done after a SELECT collision
+    pub select_retries: u8,
+}
+
+impl NfcRetryPolicy {
+    pub const DEFAULT: Self = Self { select_retries: 2 };
+
+    /// Calls `read`, which does a WUPA and a SELECT, until it doesn't end with a SELECT collision or the retries run out.
+    /// The antenna should stay on in between, so that the card doesn't need to power up again.
+    pub async fn read<T>(
+        self,
+        mut read: impl AsyncFnMut() -> Result<T, NfcReadError>,
+    ) -> RetriedRead<T> {
+        let mut collisions = 0;
+        loop {
+            match read().await {
+                Err(NfcReadError::SelectCollision) if collisions < self.select_retries => {
+                    collisions += 1;
+                }
+                result => return RetriedRead { result, collisions },
+            }
+        }
+    }
+}
+
+impl Default for NfcRetryPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The result of [`NfcRetryPolicy::read`]
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub struct RetriedRead<T> {
+    /// The result of the last try
+    pub result: Result<T, NfcReadError>,
+    /// How many tries before the last one ended with a SELECT collision
+    pub collisions: u8,
+}
+
+impl<T> RetriedRead<T> {
+    /// The card was read after colliding at least once
+    pub fn collision_recovered(&self) -> bool {
+        self.result.is_ok() && self.collisions > 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+
+    /// The fake reader never waits, so this only polls once
+    fn block_on<F: Future>(future: F) -> F::Output {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => unreachable!(),
+        }
+    }
+
+    /// Returns the scripted results in order, and counts how many times it was read
+    fn read_scripted(script: &[Result<u8, NfcReadError>]) -> (RetriedRead<u8>, usize) {
+        let mut reads = 0;
+        let read = block_on(NfcRetryPolicy::DEFAULT.read(async || {
+            reads += 1;
+            script[reads - 1]
+        }));
+        (read, reads)
+    }
+
+    #[test]
+    fn collision_then_success() {
+        let (read, reads) = read_scripted(&[Err(NfcReadError::SelectCollision), Ok(7)]);
+        assert_eq!(
+            read,
+            RetriedRead {
+                result: Ok(7),
+                collisions: 1
+            }
+        );
+        assert_eq!(reads, 2);
+        assert!(read.collision_recovered());
+
+        let (read, reads) = read_scripted(&[
+            Err(NfcReadError::SelectCollision),
+            Err(NfcReadError::SelectCollision),
+            Ok(7),
+        ]);
+        assert_eq!(read.result, Ok(7));
+        assert_eq!(reads, 3);
+        assert!(read.collision_recovered());
+    }
+
+    #[test]
+    fn persistent_collision() {
+        let (read, reads) = read_scripted(&[Err(NfcReadError::SelectCollision); 4]);
+        assert_eq!(
+            read,
+            RetriedRead {
+                result: Err(NfcReadError::SelectCollision),
+                collisions: 2
+            }
+        );
+        assert_eq!(reads, 3);
+        assert!(!read.collision_recovered());
+    }
+
+    #[test]
+    fn no_retry_after_timeout() {
+        let (read, reads) = read_scripted(&[Err(NfcReadError::WupaTimeout), Ok(7)]);
+        assert_eq!(read.result, Err(NfcReadError::WupaTimeout));
+        assert_eq!(reads, 1);
+        // The card left while it was being retried
+        let (read, reads) = read_scripted(&[
+            Err(NfcReadError::SelectCollision),
+            Err(NfcReadError::WupaTimeout),
+            Ok(7),
+        ]);
+        assert_eq!(
+            read,
+            RetriedRead {
+                result: Err(NfcReadError::WupaTimeout),
+                collisions: 1
+            }
+        );
+        assert_eq!(reads, 2);
+        let (read, _) = read_scripted(&[Ok(7)]);
+        assert!(!read.collision_recovered());
+    }
+}
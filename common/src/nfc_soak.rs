@@ -0,0 +1,300 @@
+use core::fmt;
+
+use defmt::Format;
+use heapless::Vec;
+use serde::{Deserialize, Serialize};
+
+use crate::{MAX_NFC_READERS, RetriedRead};
+
+/// How long (in s) a soak test runs between summaries
+pub const NFC_SOAK_SUMMARY_INTERVAL_S: u64 = 60;
+
+/// Why reading a card that is held on a reader failed
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum NfcReadError {
+    /// The card didn't answer the WUPA
+    WupaTimeout,
+    /// The card answered the WUPA, but the SELECT failed, usually because of a collision
+    SelectCollision,
+    /// Talking to the reader failed
+    Spi,
+}
+
+/// What happened when one reader was read during a soak test.
+/// The counters saturate, so they are still meaningful after running for a very long time.
+#[derive(Debug, Format, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NfcReaderSoakStats {
+    pub selects: u32,
+    pub wupa_timeouts: u32,
+    /// SELECT collisions that were still there after the retries of [`NfcRetryPolicy`](crate::NfcRetryPolicy)
+    pub select_collisions: u32,
+    pub spi_errors: u32,
+    /// Reads that worked after a SELECT collision, which are also counted in `selects`
+    pub collisions_recovered: u32,
+}
+
+impl NfcReaderSoakStats {
+    pub fn record(&mut self, result: Result<(), NfcReadError>) {
+        let counter = match result {
+            Ok(()) => &mut self.selects,
+            Err(NfcReadError::WupaTimeout) => &mut self.wupa_timeouts,
+            Err(NfcReadError::SelectCollision) => &mut self.select_collisions,
+            Err(NfcReadError::Spi) => &mut self.spi_errors,
+        };
+        *counter = counter.saturating_add(1);
+    }
+
+    pub fn record_retried<T>(&mut self, read: &RetriedRead<T>) {
+        self.record(read.result.as_ref().map(|_| ()).map_err(|&e| e));
+        if read.collision_recovered() {
+            self.collisions_recovered = self.collisions_recovered.saturating_add(1);
+        }
+    }
+
+    pub fn errors(&self) -> u64 {
+        u64::from(self.wupa_timeouts)
+            + u64::from(self.select_collisions)
+            + u64::from(self.spi_errors)
+    }
+
+    pub fn reads(&self) -> u64 {
+        u64::from(self.selects) + self.errors()
+    }
+
+    /// Rounded down, and 0 if nothing was read
+    pub fn error_rate_percent(&self) -> u8 {
+        match self.reads() {
+            0 => 0,
+            reads => (self.errors() * 100 / reads) as u8,
+        }
+    }
+
+    /// More than `threshold_percent` of the reads failed
+    pub fn exceeds(&self, threshold_percent: u8) -> bool {
+        self.errors() * 100 > u64::from(threshold_percent) * self.reads()
+    }
+}
+
+impl fmt::Display for NfcReaderSoakStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "ok: {}, wupa: {}, select: {}, recovered: {}, spi: {}, errors: {}%",
+            self.selects,
+            self.wupa_timeouts,
+            self.select_collisions,
+            self.collisions_recovered,
+            self.spi_errors,
+            self.error_rate_percent()
+        )
+    }
+}
+
+/// The results of polling every reader with a card held on it for a long time,
+/// to find readers that are unreliable
+#[derive(Debug, Format, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NfcSoakStats {
+    /// How long the soak test has been running, in s
+    pub elapsed_s: u32,
+    pub readers: Vec<NfcReaderSoakStats, MAX_NFC_READERS>,
+}
+
+impl NfcSoakStats {
+    /// More than [`MAX_NFC_READERS`] readers are treated as [`MAX_NFC_READERS`]
+    pub fn new(readers: usize) -> Self {
+        Self {
+            elapsed_s: 0,
+            readers: (0..readers.min(MAX_NFC_READERS))
+                .map(|_| Default::default())
+                .collect(),
+        }
+    }
+
+    /// Results for readers that don't exist are ignored
+    pub fn record(&mut self, reader: usize, result: Result<(), NfcReadError>) {
+        if let Some(stats) = self.readers.get_mut(reader) {
+            stats.record(result);
+        }
+    }
+
+    /// Like [`NfcSoakStats::record`], and also counts whether a SELECT collision was recovered from
+    pub fn record_retried<T>(&mut self, reader: usize, read: &RetriedRead<T>) {
+        if let Some(stats) = self.readers.get_mut(reader) {
+            stats.record_retried(read);
+        }
+    }
+
+    /// The indexes of the readers where more than `threshold_percent` of the reads failed
+    pub fn failing(&self, threshold_percent: u8) -> impl Iterator<Item = usize> + '_ {
+        self.readers
+            .iter()
+            .enumerate()
+            .filter(move |(_, stats)| stats.exceeds(threshold_percent))
+            .map(|(i, _)| i)
+    }
+
+    /// The first line of a summary, followed by a line for each reader
+    pub fn header(&self) -> impl fmt::Display + '_ {
+        SoakHeader(self)
+    }
+}
+
+struct SoakHeader<'a>(&'a NfcSoakStats);
+
+impl fmt::Display for SoakHeader<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let elapsed_s = self.0.elapsed_s;
+        let reads = self
+            .0
+            .readers
+            .iter()
+            .map(NfcReaderSoakStats::reads)
+            .sum::<u64>();
+        write!(
+            f,
+            "NFC soak: {}h{}m{}s, {} readers, {} reads",
+            elapsed_s / 3600,
+            elapsed_s / 60 % 60,
+            elapsed_s % 60,
+            self.0.readers.len(),
+            reads
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::fmt::Write;
+
+    use super::*;
+
+    fn to_string(value: impl fmt::Display) -> heapless::String<96> {
+        let mut string = heapless::String::new();
+        write!(string, "{value}").unwrap();
+        string
+    }
+
+    #[test]
+    fn counters() {
+        let mut stats = NfcSoakStats::new(2);
+        for _ in 0..97 {
+            stats.record(0, Ok(()));
+        }
+        stats.record(0, Err(NfcReadError::WupaTimeout));
+        stats.record(0, Err(NfcReadError::SelectCollision));
+        stats.record(0, Err(NfcReadError::Spi));
+        // Doesn't exist
+        stats.record(2, Err(NfcReadError::Spi));
+        assert_eq!(
+            stats.readers[0],
+            NfcReaderSoakStats {
+                selects: 97,
+                wupa_timeouts: 1,
+                select_collisions: 1,
+                spi_errors: 1,
+                collisions_recovered: 0,
+            }
+        );
+        assert_eq!(stats.readers[0].reads(), 100);
+        assert_eq!(stats.readers[0].error_rate_percent(), 3);
+        assert_eq!(stats.readers[1], NfcReaderSoakStats::default());
+        assert_eq!(stats.readers[1].error_rate_percent(), 0);
+        assert_eq!(
+            NfcSoakStats::new(MAX_NFC_READERS + 1).readers.len(),
+            MAX_NFC_READERS
+        );
+    }
+
+    #[test]
+    fn saturating() {
+        let mut stats = NfcReaderSoakStats {
+            selects: u32::MAX,
+            wupa_timeouts: u32::MAX,
+            select_collisions: u32::MAX - 1,
+            spi_errors: 0,
+            collisions_recovered: u32::MAX,
+        };
+        stats.record(Ok(()));
+        stats.record(Err(NfcReadError::WupaTimeout));
+        stats.record(Err(NfcReadError::SelectCollision));
+        stats.record(Err(NfcReadError::SelectCollision));
+        assert_eq!(stats.selects, u32::MAX);
+        assert_eq!(stats.wupa_timeouts, u32::MAX);
+        assert_eq!(stats.select_collisions, u32::MAX);
+        stats.record_retried(&RetriedRead {
+            result: Ok(()),
+            collisions: 1,
+        });
+        assert_eq!(stats.collisions_recovered, u32::MAX);
+        // The totals don't overflow
+        assert_eq!(stats.reads(), 3 * u64::from(u32::MAX));
+        assert_eq!(stats.error_rate_percent(), 66);
+    }
+
+    #[test]
+    fn fail_threshold() {
+        let mut stats = NfcSoakStats::new(3);
+        for i in 0..100 {
+            // 5% of reads fail on reader 1, and 6% on reader 2
+            stats.record(0, Ok(()));
+            stats.record(
+                1,
+                if i < 5 {
+                    Err(NfcReadError::WupaTimeout)
+                } else {
+                    Ok(())
+                },
+            );
+            stats.record(
+                2,
+                if i < 6 {
+                    Err(NfcReadError::Spi)
+                } else {
+                    Ok(())
+                },
+            );
+        }
+        assert!(stats.failing(5).eq([2]));
+        assert!(stats.failing(4).eq([1, 2]));
+        assert!(stats.failing(0).eq([1, 2]));
+        assert!(stats.failing(100).eq([]));
+        // A reader that wasn't read yet isn't failing
+        assert!(NfcSoakStats::new(1).failing(0).eq([]));
+    }
+
+    #[test]
+    fn summary() {
+        let mut stats = NfcSoakStats::new(2);
+        stats.elapsed_s = 2 * 3600 + 3 * 60 + 4;
+        for _ in 0..9 {
+            stats.record(1, Ok(()));
+        }
+        stats.record(1, Err(NfcReadError::SelectCollision));
+        stats.record_retried(
+            1,
+            &RetriedRead {
+                result: Ok(()),
+                collisions: 2,
+            },
+        );
+        assert_eq!(
+            to_string(stats.header()),
+            "NFC soak: 2h3m4s, 2 readers, 11 reads"
+        );
+        assert_eq!(
+            to_string(stats.readers[1]),
+            "ok: 10, wupa: 0, select: 1, recovered: 1, spi: 0, errors: 9%"
+        );
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut stats = NfcSoakStats::new(MAX_NFC_READERS);
+        stats.elapsed_s = u32::MAX;
+        stats.readers[5].spi_errors = u32::MAX;
+        stats.readers[5].collisions_recovered = u32::MAX;
+        let mut buffer = [0; 128];
+        let bytes = postcard::to_slice(&stats, &mut buffer).unwrap();
+        assert_eq!(postcard::from_bytes::<NfcSoakStats>(bytes).unwrap(), stats);
+    }
+}
@@ -0,0 +1,174 @@
+use defmt::Format;
+use serde::de::DeserializeOwned;
+
+/// A packet that could not be read. Its bytes are dropped, so the packets after it can still be read.
+#[derive(Debug, Format)]
+pub enum PacketError {
+    /// The packet didn't fit in the buffer
+    TooLong,
+    /// There was nothing before the `0`, which is usually noise on the line
+    Empty,
+    /// The bytes aren't valid COBS, so bytes were corrupted or lost on the line, such as from a baud mismatch
+    Cobs,
+    /// The message is from a newer protocol version
+    Unknown,
+    /// The bytes arrived intact but aren't a valid message, which is usually a protocol bug
+    Deserialize(postcard::Error),
+}
+
+impl PacketError {
+    fn from_postcard(e: postcard::Error) -> Self {
+        match e {
+            // `postcard::from_bytes_cobs` only returns this if the COBS decoding fails
+            postcard::Error::DeserializeBadEncoding => Self::Cobs,
+            postcard::Error::DeserializeBadEnum => Self::Unknown,
+            e => Self::Deserialize(e),
+        }
+    }
+}
+
+/// Splits a stream of bytes into COBS-encoded postcard packets, which are separated by `0` bytes.
+/// Bytes can be read in chunks of any size, so a packet can be split across multiple reads.
+///
+/// Packets are encoded with [`postcard::to_slice_cobs`].
+#[derive(Debug)]
+pub struct PacketReader<const N: usize> {
+    buffer: [u8; N],
+    len: usize,
+    /// The bytes until the next `0` are the rest of a packet that didn't fit in the buffer
+    discarding: bool,
+}
+
+impl<const N: usize> PacketReader<N> {
+    pub const fn new() -> Self {
+        Self {
+            buffer: [0; N],
+            len: 0,
+            discarding: false,
+        }
+    }
+
+    /// Read new bytes into this, and then call [`Self::received`]
+    pub fn unfilled(&mut self) -> &mut [u8] {
+        &mut self.buffer[self.len..]
+    }
+
+    /// `bytes_read` is the number of bytes that were read into [`Self::unfilled`]
+    pub fn received(&mut self, bytes_read: usize) {
+        self.len += bytes_read;
+    }
+
+    /// Returns `None` if more bytes need to be read to get the next packet.
+    /// Call this until it returns `None` after every read.
+    pub fn next_packet<T: DeserializeOwned>(&mut self) -> Option<Result<T, PacketError>> {
+        let Some(zero_index) = self.buffer[..self.len].iter().position(|&byte| byte == 0) else {
+            if self.len == N {
+                // Make space to read the rest of the packet, which will be dropped
+                self.len = 0;
+                self.discarding = true;
+            }
+            return None;
+        };
+        let packet_len = zero_index + 1;
+        let result = if self.discarding {
+            self.discarding = false;
+            Err(PacketError::TooLong)
+        } else if packet_len == 1 {
+            Err(PacketError::Empty)
+        } else {
+            postcard::from_bytes_cobs(&mut self.buffer[..packet_len])
+                .map_err(PacketError::from_postcard)
+        };
+        self.buffer.copy_within(packet_len..self.len, 0);
+        self.len -= packet_len;
+        Some(result)
+    }
+}
+
+impl<const N: usize> Default for PacketReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Request;
+
+    fn read(reader: &mut PacketReader<16>, bytes: &[u8]) {
+        reader.unfilled()[..bytes.len()].copy_from_slice(bytes);
+        reader.received(bytes.len());
+    }
+
+    #[test]
+    fn split_across_reads() {
+        let mut buffer = [0; 16];
+        let bytes = postcard::to_slice_cobs(&Request::WatchNfc(true), &mut buffer).unwrap();
+        let mut reader = PacketReader::<16>::new();
+        let (first, second) = bytes.split_at(2);
+        read(&mut reader, first);
+        assert!(reader.next_packet::<Request>().is_none());
+        read(&mut reader, second);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Ok(Request::WatchNfc(true)))
+        ));
+        assert!(reader.next_packet::<Request>().is_none());
+    }
+
+    #[test]
+    fn too_long() {
+        let mut reader = PacketReader::<16>::new();
+        read(&mut reader, &[1; 16]);
+        assert!(reader.next_packet::<Request>().is_none());
+        read(&mut reader, &[1, 1, 0]);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Err(PacketError::TooLong))
+        ));
+        // The next packet is still read
+        let mut buffer = [0; 16];
+        let bytes = postcard::to_slice_cobs(&Request::GetInfo, &mut buffer).unwrap();
+        read(&mut reader, bytes);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Ok(Request::GetInfo))
+        ));
+    }
+
+    #[test]
+    fn classified() {
+        let mut reader = PacketReader::<16>::new();
+        read(&mut reader, &[0]);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Err(PacketError::Empty))
+        ));
+        // The first code says that 4 bytes follow, but the packet ends after 1
+        read(&mut reader, &[5, 1, 0]);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Err(PacketError::Cobs))
+        ));
+        // There is no request with index 100
+        read(&mut reader, &[2, 100, 0]);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Err(PacketError::Unknown))
+        ));
+        // `WatchNfc` with 2 as the bool
+        read(&mut reader, &[3, 6, 2, 0]);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Err(PacketError::Deserialize(
+                postcard::Error::DeserializeBadBool
+            )))
+        ));
+        read(&mut reader, &[3, 6, 1, 0]);
+        assert!(matches!(
+            reader.next_packet::<Request>(),
+            Some(Ok(Request::WatchNfc(true)))
+        ));
+    }
+}
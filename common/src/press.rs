@@ -0,0 +1,168 @@
+use defmt::Format;
+
+/// Presses that are held at least this long are long presses
+pub const LONG_PRESS_MS: u32 = 500;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Press {
+    Short,
+    Long,
+}
+
+impl Press {
+    /// Classifies a press from the [`crate::Event::RotarySwitch`] release event alone, so a missed press event doesn't matter.
+    /// Returns `None` for press events.
+    ///
+    /// If the STM32 didn't see the switch being pressed, for example because it started watching while the switch was held,
+    /// the duration is unknown and it counts as a short press.
+    pub fn from_event(pressed: bool, duration_ms: Option<u32>) -> Option<Self> {
+        if pressed {
+            return None;
+        }
+        Some(match duration_ms {
+            Some(duration_ms) if duration_ms >= LONG_PRESS_MS => Self::Long,
+            _ => Self::Short,
+        })
+    }
+}
+
+/// Two presses of the rotary button within this long (in ms) are a double click.
+/// A single click is only known once this passes without a second press, so single clicks are delayed by this much.
+pub const DEFAULT_DOUBLE_CLICK_WINDOW_MS: u64 = 250;
+
+#[derive(Debug, Format, Clone, Copy, PartialEq, Eq)]
+pub enum Click {
+    Single,
+    Double,
+}
+
+/// Tells single clicks from double clicks by when the button was pressed.
+/// How long the button was held doesn't matter.
+#[derive(Debug)]
+pub struct ClickClassifier {
+    window_ms: u64,
+    /// A press that could still become a double click
+    first_press_ms: Option<u64>,
+}
+
+impl ClickClassifier {
+    pub const fn new(window_ms: u64) -> Self {
+        Self {
+            window_ms,
+            first_press_ms: None,
+        }
+    }
+
+    /// Call this when the button is pressed.
+    /// Returns a single click if the previous press wasn't classified yet, even though its window already passed.
+    pub fn press(&mut self, now_ms: u64) -> Option<Click> {
+        match self.first_press_ms.take() {
+            Some(first_press_ms) if now_ms.saturating_sub(first_press_ms) <= self.window_ms => {
+                Some(Click::Double)
+            }
+            previous => {
+                self.first_press_ms = Some(now_ms);
+                previous.map(|_| Click::Single)
+            }
+        }
+    }
+
+    /// When [`Self::poll`] will return a single click, if nothing else happens before then
+    pub fn deadline_ms(&self) -> Option<u64> {
+        self.first_press_ms
+            .map(|first_press_ms| first_press_ms + self.window_ms)
+    }
+
+    /// Returns a single click once the window after a press passed without a second press
+    pub fn poll(&mut self, now_ms: u64) -> Option<Click> {
+        if self.deadline_ms()? < now_ms {
+            self.first_press_ms = None;
+            Some(Click::Single)
+        } else {
+            None
+        }
+    }
+
+    /// Something other than a press happened, so a press that is waiting for a second press is a single click now
+    pub fn flush(&mut self) -> Option<Click> {
+        self.first_press_ms.take().map(|_| Click::Single)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn thresholds() {
+        assert_eq!(Press::from_event(false, Some(0)), Some(Press::Short));
+        assert_eq!(
+            Press::from_event(false, Some(LONG_PRESS_MS - 1)),
+            Some(Press::Short)
+        );
+        assert_eq!(
+            Press::from_event(false, Some(LONG_PRESS_MS)),
+            Some(Press::Long)
+        );
+        assert_eq!(Press::from_event(false, Some(10_000)), Some(Press::Long));
+    }
+
+    #[test]
+    fn press_events_are_not_classified() {
+        assert_eq!(Press::from_event(true, None), None);
+    }
+
+    #[test]
+    fn missed_press() {
+        // The release event is enough, even if the press event before it was dropped
+        assert_eq!(
+            Press::from_event(false, Some(LONG_PRESS_MS * 2)),
+            Some(Press::Long)
+        );
+        // The STM32 never saw the press
+        assert_eq!(Press::from_event(false, None), Some(Press::Short));
+    }
+
+    #[test]
+    fn single_and_double() {
+        let mut clicks = ClickClassifier::new(250);
+        assert_eq!(clicks.press(1_000), None);
+        assert_eq!(clicks.deadline_ms(), Some(1_250));
+        assert_eq!(clicks.poll(1_250), None);
+        assert_eq!(clicks.poll(1_251), Some(Click::Single));
+        assert_eq!(clicks.poll(2_000), None);
+
+        assert_eq!(clicks.press(2_000), None);
+        assert_eq!(clicks.press(2_250), Some(Click::Double));
+        assert_eq!(clicks.deadline_ms(), None);
+        // A third press starts over
+        assert_eq!(clicks.press(2_300), None);
+        assert_eq!(clicks.flush(), Some(Click::Single));
+        assert_eq!(clicks.flush(), None);
+    }
+
+    /// Holding the button between presses doesn't matter, only when it was pressed
+    #[test]
+    fn press_hold_press() {
+        let mut clicks = ClickClassifier::new(250);
+        // Pressed, held for 200 ms, released, and pressed again
+        assert_eq!(clicks.press(0), None);
+        assert_eq!(clicks.press(240), Some(Click::Double));
+
+        // Held for longer than the window
+        assert_eq!(clicks.press(1_000), None);
+        assert_eq!(clicks.poll(1_400), Some(Click::Single));
+        assert_eq!(clicks.press(1_450), None);
+        assert_eq!(clicks.poll(1_701), Some(Click::Single));
+    }
+
+    /// The second press came after the window, but nothing polled in between
+    #[test]
+    fn late_second_press() {
+        let mut clicks = ClickClassifier::new(250);
+        assert_eq!(clicks.press(0), None);
+        assert_eq!(clicks.press(300), Some(Click::Single));
+        assert_eq!(clicks.deadline_ms(), Some(550));
+        assert_eq!(clicks.poll(551), Some(Click::Single));
+    }
+}
@@ -0,0 +1,92 @@
+/// Keeps track of which tasks have reset their state after a soft reset.
+///
+/// Every soft reset starts a new generation.
+/// Each task acknowledges the generation after dropping its state,
+/// and the soft reset is complete once every task has acknowledged the latest generation.
+#[derive(Debug)]
+pub struct SoftResetBarrier<const N: usize> {
+    generation: u32,
+    acknowledged: [u32; N],
+}
+
+impl<const N: usize> SoftResetBarrier<N> {
+    pub const fn new() -> Self {
+        Self {
+            generation: 0,
+            acknowledged: [0; N],
+        }
+    }
+
+    /// Starts a new generation, which every task needs to acknowledge. Returns the new generation.
+    pub fn reset(&mut self) -> u32 {
+        self.generation = self.generation.wrapping_add(1);
+        self.generation
+    }
+
+    pub fn generation(&self) -> u32 {
+        self.generation
+    }
+
+    pub fn is_complete(&self) -> bool {
+        self.acknowledged
+            .iter()
+            .all(|&generation| generation == self.generation)
+    }
+
+    /// Acknowledges that `task` reset its state for `generation`.
+    /// Acknowledging an old generation does nothing.
+    /// Returns `true` if this was the last task that needed to acknowledge the current generation.
+    pub fn acknowledge(&mut self, task: usize, generation: u32) -> bool {
+        if generation != self.generation || self.acknowledged[task] == generation {
+            return false;
+        }
+        self.acknowledged[task] = generation;
+        self.is_complete()
+    }
+}
+
+impl<const N: usize> Default for SoftResetBarrier<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_complete() {
+        let barrier = SoftResetBarrier::<3>::new();
+        assert_eq!(barrier.generation(), 0);
+        assert!(barrier.is_complete());
+    }
+
+    #[test]
+    fn completes_after_all_tasks_acknowledge() {
+        let mut barrier = SoftResetBarrier::<3>::new();
+        let generation = barrier.reset();
+        assert!(!barrier.is_complete());
+        assert!(!barrier.acknowledge(0, generation));
+        assert!(!barrier.acknowledge(2, generation));
+        // Acknowledging twice doesn't count as another task
+        assert!(!barrier.acknowledge(2, generation));
+        assert!(barrier.acknowledge(1, generation));
+        assert!(barrier.is_complete());
+        // Only the last acknowledgement completes the barrier
+        assert!(!barrier.acknowledge(1, generation));
+    }
+
+    #[test]
+    fn reset_while_resetting() {
+        let mut barrier = SoftResetBarrier::<2>::new();
+        let old_generation = barrier.reset();
+        assert!(!barrier.acknowledge(0, old_generation));
+        let generation = barrier.reset();
+        // Late acknowledgements of the old generation are ignored
+        assert!(!barrier.acknowledge(1, old_generation));
+        assert!(!barrier.is_complete());
+        assert!(!barrier.acknowledge(0, generation));
+        assert!(barrier.acknowledge(1, generation));
+    }
+}
@@ -0,0 +1,30 @@
+//! Replays an event record dumped from the liberal board and prints the game state that it ends with.
+//! Run with `cargo run --features std --bin replay <dump path>`.
+//!
+//! The replay starts from a new game state with the default settings,
+//! so settings and known peripherals that were stored on the board aren't included.
+
+use std::{env, fs};
+
+use game_pure::{DEBUG_DUMP_EVENTS, GameState, record::read_record, sim::replay};
+
+/// The length of a tick on the boards, in ms
+const TICK_MS: u64 = 1_000;
+
+fn main() {
+    let path = env::args().nth(1).expect("the path of the dumped record");
+    let record = fs::read(&path).unwrap();
+    let state = GameState::new(None, Default::default(), Default::default());
+    match replay(state, &record, TICK_MS) {
+        Ok(state) => {
+            let entries = read_record(&record);
+            let recent_events = &entries[entries.len().saturating_sub(DEBUG_DUMP_EVENTS)..];
+            let mut dump = String::new();
+            state
+                .debug_dump(recent_events.iter().copied(), &mut dump)
+                .unwrap();
+            print!("{dump}");
+        }
+        Err(e) => eprintln!("Failed to replay {path}: {e:?}"),
+    }
+}
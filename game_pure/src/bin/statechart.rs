@@ -0,0 +1,16 @@
+//! Writes the transitions between screens as a Graphviz graph.
+//! Run with `cargo run --features statechart --bin statechart [output path]`,
+//! and render it with `dot -Tsvg game_states.dot -o game_states.svg`.
+
+use std::{env, fs};
+
+use game_pure::statechart::{to_dot, transitions};
+
+fn main() {
+    let path = env::args()
+        .nth(1)
+        .unwrap_or_else(|| "game_states.dot".into());
+    let transitions = transitions();
+    fs::write(&path, to_dot(&transitions)).unwrap();
+    println!("Wrote {} transitions to {path}", transitions.len());
+}
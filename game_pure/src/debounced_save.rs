@@ -0,0 +1,79 @@
+/// How long changes are coalesced before they are saved, in ms
+pub const SAVE_DELAY_MS: u64 = 5_000;
+
+/// Coalesces rapid changes so that flash isn't written more than once per `delay`.
+/// The first change starts the delay, and the latest value is saved when it is over.
+#[derive(Debug, Clone)]
+pub struct DebouncedSave<T> {
+    /// The value to save and when to save it
+    pending: Option<(T, u64)>,
+    delay: u64,
+}
+
+impl<T> DebouncedSave<T> {
+    /// `delay` is in ms
+    pub const fn new(delay: u64) -> Self {
+        Self {
+            pending: None,
+            delay,
+        }
+    }
+
+    /// `now` is in ms
+    pub fn changed(&mut self, value: T, now: u64) {
+        let deadline = match &self.pending {
+            Some((_, deadline)) => *deadline,
+            None => now + self.delay,
+        };
+        self.pending = Some((value, deadline));
+    }
+
+    /// When [`DebouncedSave::take_due`] should be called next, in ms
+    pub fn deadline(&self) -> Option<u64> {
+        self.pending.as_ref().map(|(_, deadline)| *deadline)
+    }
+
+    /// Returns the value to save if it's time to save it
+    pub fn take_due(&mut self, now: u64) -> Option<T> {
+        if self.deadline()? <= now {
+            self.pending.take().map(|(value, _)| value)
+        } else {
+            None
+        }
+    }
+}
+
+impl<T> Default for DebouncedSave<T> {
+    fn default() -> Self {
+        Self::new(SAVE_DELAY_MS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn coalesce_rapid_changes() {
+        let mut save = DebouncedSave::new(5_000);
+        assert_eq!(save.take_due(0), None);
+        save.changed(1, 1_000);
+        save.changed(2, 2_000);
+        save.changed(3, 5_999);
+        assert_eq!(save.deadline(), Some(6_000));
+        assert_eq!(save.take_due(5_999), None);
+        assert_eq!(save.take_due(6_000), Some(3));
+        assert_eq!(save.take_due(7_000), None);
+        assert_eq!(save.deadline(), None);
+    }
+
+    #[test]
+    fn new_delay_after_saving() {
+        let mut save = DebouncedSave::new(5_000);
+        save.changed(1, 0);
+        assert_eq!(save.take_due(5_000), Some(1));
+        save.changed(2, 5_500);
+        assert_eq!(save.take_due(6_000), None);
+        assert_eq!(save.take_due(10_500), Some(2));
+    }
+}
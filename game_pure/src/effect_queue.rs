@@ -0,0 +1,138 @@
+use heapless::Deque;
+use strum::EnumCount;
+
+use crate::{GameEffect, log::log_warn};
+
+/// The max number of effects that can be waiting to be drained.
+/// A call that mutates the game state pushes at most one effect of each kind, and the caller drains them after each call,
+/// so there is room for one of every kind and critical effects never overflow.
+pub const EFFECT_QUEUE_SIZE: usize = GameEffect::COUNT;
+
+/// Effects that the game state pushes while it is mutated, in the order that they happened.
+/// The caller drains them with [`GameState::drain_effects`](crate::GameState::drain_effects) after each call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EffectQueue {
+    effects: Deque<GameEffect, EFFECT_QUEUE_SIZE>,
+}
+
+impl EffectQueue {
+    /// A non-critical effect that is already queued isn't queued again.
+    /// If the queue is full, the oldest non-critical effect is dropped to make space.
+    /// Critical effects are never dropped to make space, so the queue only fills up with them
+    /// if the effects weren't drained after a call, and then the new effect is dropped.
+    pub fn push(&mut self, effect: GameEffect) {
+        if !effect.is_critical() && self.effects.iter().any(|queued| *queued == effect) {
+            return;
+        }
+        if self.effects.is_full() {
+            let Some(index) = self.effects.iter().position(|queued| !queued.is_critical()) else {
+                log_warn!(
+                    "Effect queue is full of critical effects, dropping the new effect. Drain it after every call."
+                );
+                return;
+            };
+            log_warn!("Effect queue is full, dropping the oldest non-critical effect");
+            let mut i = 0;
+            self.effects.retain(|_| {
+                i += 1;
+                i - 1 != index
+            });
+        }
+        // We made space
+        let _ = self.effects.push_back(effect);
+    }
+
+    /// Removes the effects, oldest first
+    pub fn drain(&mut self) -> impl Iterator<Item = GameEffect> + '_ {
+        core::iter::from_fn(|| self.effects.pop_front())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.effects.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use std::vec::Vec;
+
+    use trouble_host::prelude::BdAddr;
+
+    use super::*;
+    use crate::{Settings, Team, log};
+
+    fn disconnect(i: u8) -> GameEffect {
+        GameEffect::Disconnect([BdAddr::new([i; 6])].into_iter().collect())
+    }
+
+    /// One of each kind of effect
+    fn every_effect() -> [GameEffect; GameEffect::COUNT] {
+        [
+            disconnect(0),
+            GameEffect::SaveKnownPeripherals(Default::default()),
+            GameEffect::SettingsChanged(Settings::default()),
+            GameEffect::ConnectTimedOut(Default::default()),
+            GameEffect::GameCompleted(Team::Liberal),
+            GameEffect::GameStarted,
+            GameEffect::RestartBoards,
+            GameEffect::RedrawScreen,
+            GameEffect::DebugDump,
+        ]
+    }
+
+    #[test]
+    fn coalesce_non_critical() {
+        let mut queue = EffectQueue::default();
+        queue.push(GameEffect::RedrawScreen);
+        queue.push(GameEffect::SettingsChanged(Settings::default()));
+        queue.push(GameEffect::RedrawScreen);
+        assert_eq!(
+            queue.drain().collect::<Vec<_>>(),
+            [
+                GameEffect::RedrawScreen,
+                GameEffect::SettingsChanged(Settings::default()),
+            ]
+        );
+        assert!(queue.is_empty());
+    }
+
+    #[test]
+    fn one_of_each_fits() {
+        let mut queue = EffectQueue::default();
+        log::take_warnings();
+        for effect in every_effect() {
+            queue.push(effect);
+        }
+        assert!(log::take_warnings().is_empty());
+        assert_eq!(queue.drain().collect::<Vec<_>>(), every_effect());
+    }
+
+    #[test]
+    fn overflow() {
+        let mut queue = EffectQueue::default();
+        log::take_warnings();
+        queue.push(disconnect(0));
+        queue.push(GameEffect::RedrawScreen);
+        for i in 1..EFFECT_QUEUE_SIZE as u8 - 1 {
+            queue.push(disconnect(i));
+        }
+        assert!(log::take_warnings().is_empty());
+
+        // The non-critical effect is dropped first
+        queue.push(disconnect(10));
+        assert_eq!(log::take_warnings().len(), 1);
+        // Then new effects are dropped, so that the queued critical effects aren't lost
+        queue.push(GameEffect::RedrawScreen);
+        queue.push(disconnect(11));
+        assert_eq!(log::take_warnings().len(), 2);
+        assert_eq!(
+            queue.drain().collect::<Vec<_>>(),
+            (0..EFFECT_QUEUE_SIZE as u8 - 1)
+                .chain([10])
+                .map(disconnect)
+                .collect::<Vec<_>>()
+        );
+    }
+}
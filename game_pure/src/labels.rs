@@ -0,0 +1,58 @@
+//! Static text that is shown on the screen
+
+pub const ABOUT: &str = "About";
+pub const RESTART_BOARDS: &str = "Restart boards";
+/// The title of the Bluetooth screen when the BLE controller couldn't be initialized
+pub const BLE_UNAVAILABLE: &str = "BLE unavailable";
+/// The title item of the Bluetooth screen while scanning, which pauses scanning when clicked
+pub const SCANNING: &str = "Scanning (pause)";
+pub const SCAN_PAUSED: &str = "Scan off (resume)";
+/// The title item of the Bluetooth screen while connecting, followed by how long it has been connecting for
+pub const RETRY_CONNECTING: &str = "Retry now";
+/// The title item of the Bluetooth screen once everything is connected
+pub const CONNECTED: &str = "Connected (info)";
+pub const CONNECTION_DETAILS: &str = "Connections";
+/// After a scanned peripheral that we bonded with
+pub const KNOWN_PERIPHERAL: &str = "(known)";
+/// After a saved peripheral that is listed while scanning, but hasn't been found yet
+pub const SAVED_PERIPHERAL: &str = "(saved, not in range)";
+/// The title of the countdown to starting a game when the fascist board reconnects on boot
+pub const AUTO_START: &str = "Starting game";
+pub const CANCEL_AUTO_START: &str = "Cancel";
+/// Shown on the About screen after the build and runtime info.
+/// Each line must fit on the screen.
+pub const ABOUT_LICENSE: [&str; 6] = [
+    "License: AGPL-3.0",
+    "Source code and",
+    "other licenses at",
+    "github.com/",
+    "ChocolateLoverRaj/",
+    "board-game",
+];
+
+pub const CHECK_PARTY_HINT: &str = "President: check a player's party";
+pub const CHOOSE_NEXT_PRESIDENT_HINT: &str = "President: choose the next president";
+pub const KILL_HINT: &str = "President: kill a player";
+pub const EXAMINE_TOP_3_HINT: &str = "President: examine the top 3 cards";
+pub const CONFIRM_EXAMINE_TOP_3_HINT: &str = "Confirm top 3 cards were examined? (click again)";
+/// Shown when writing down the result of a check party action
+pub const NOTE_ENTRY_TITLE: &str = "Checked player is";
+pub const PRESIDENT_NOTES: &str = "President notes";
+/// The menu that is opened by clicking while playing
+pub const GAME_MENU_TITLE: &str = "Game";
+pub const END_GAME_TITLE: &str = "End the game?";
+/// Choosing how many players are left after someone leaves
+pub const ADJUST_PLAYERS_TITLE: &str = "Players left";
+/// The popup that is shown once when the supply voltage gets low, followed by the hint and [`DISMISS_HINT`]
+pub const SUPPLY_LOW_TITLE: &str = "Power low";
+pub const SUPPLY_LOW_HINT: &str = "Check the power bank";
+pub const DISMISS_HINT: &str = "Click to dismiss";
+pub const PAUSED: &str = "Paused";
+pub const RESUME_HINT: &str = "Click to resume";
+pub const CHAOS_WARNING: &str = "Chaos on next fail";
+/// The banner after the game is over, in a big font that only fits 12 characters
+pub const LIBERALS_WIN: &str = "Liberals win";
+pub const FASCISTS_WIN: &str = "Fascists win";
+/// Shown when a policy card is placed on the other team's board
+pub const MISPLACED_LIBERAL_POLICY: &str = "Move liberal card";
+pub const MISPLACED_FASCIST_POLICY: &str = "Move fascist card";
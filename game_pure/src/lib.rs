@@ -1,72 +1,287 @@
 #![cfg_attr(not(feature = "std"), no_std)]
+mod debounced_save;
+mod effect_queue;
+pub mod labels;
+mod log;
+pub mod record;
+pub mod record_log;
+mod scan_list;
+mod shutdown;
+#[cfg(any(test, feature = "std"))]
+pub mod sim;
+#[cfg(any(test, feature = "statechart"))]
+pub mod statechart;
+mod supply;
+pub mod sync;
+mod time_sync;
 pub mod ui;
 
-use core::fmt::Display;
-
-use alloc::{
-    boxed::Box,
-    string::{String, ToString},
-    vec::Vec,
+use core::{
+    fmt::{self, Write},
+    mem,
+    ops::RangeInclusive,
 };
+
 use heapless::index_set::FnvIndexSet;
-use strum::VariantArray;
-use trouble_host::{
-    Address,
-    prelude::{AddrKind, BdAddr},
+use strum::{EnumCount, VariantArray};
+use trouble_host::{Address, prelude::BdAddr};
+
+use crate::{
+    record::{RecordEntry, RecordedEvent},
+    ui::{Screen, SelectedItem},
 };
 
-use crate::ui::{Screen, SelectedItem};
+pub use debounced_save::*;
+pub use effect_queue::*;
+pub use log::BdAddrFmt;
+use log::{log_info, log_warn};
+pub use scan_list::*;
+pub use shutdown::*;
+pub use supply::*;
+pub use time_sync::*;
 
 extern crate alloc;
 
 pub const SCAN_LIST_SIZE: usize = 4;
+/// The max number of screens that can be "behind" the current screen.
+/// The main menu is always at the bottom of the stack.
+pub const NAVIGATION_STACK_SIZE: usize = 4;
+/// The max number of peripherals (boards) that we connect to at the same time
+pub const MAX_PERIPHERALS: usize = 2;
+/// The max number of peripherals that we remember names for
+pub const KNOWN_PERIPHERALS_SIZE: usize = 4;
+/// The max length of a peripheral's name, which needs to fit in a row on the screen
+pub const PERIPHERAL_NAME_LEN: usize = 12;
+/// The rules only cover games with this many players
+pub const PLAYERS: RangeInclusive<u8> = 5..=10;
+/// After this many ticks without input on the Bluetooth scanning screen, scanning switches to [`ScanPreset::LowPower`]
+pub const SCAN_IDLE_TICKS: u64 = 30;
+/// After a policy card changes, more changes are expected for this many ticks, such as during a legislative session
+pub const SCAN_ACTIVITY_HIGH_TICKS: u64 = 30;
+/// After this many ticks without a policy card changing, changes aren't expected for a while
+pub const SCAN_ACTIVITY_LOW_TICKS: u64 = 300;
+
+/// Formats an address the same way as [`trouble_host::Address`]'s `Display` impl (`XX:XX:XX:XX:XX:XX`),
+/// without needing an allocator
+pub fn fmt_bd_addr(addr: &BdAddr) -> heapless::String<17> {
+    let a = addr.into_inner();
+    let mut s = heapless::String::new();
+    // 17 bytes is always exactly enough
+    let _ = write!(
+        s,
+        "{:02X}:{:02X}:{:02X}:{:02X}:{:02X}:{:02X}",
+        a[5], a[4], a[3], a[2], a[1], a[0]
+    );
+    s
+}
+
+/// Only the last 3 bytes of [`fmt_bd_addr`] (`XX:XX:XX`), for places where there isn't space for the full address
+pub fn fmt_bd_addr_short(addr: &BdAddr) -> heapless::String<8> {
+    let a = addr.into_inner();
+    let mut s = heapless::String::new();
+    let _ = write!(s, "{:02X}:{:02X}:{:02X}", a[2], a[1], a[0]);
+    s
+}
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConnectState {
     Connecting,
     Connected,
 }
 
-#[derive(Debug, Clone, Copy)]
+/// What a peripheral is used for
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray)]
+pub enum PeripheralRole {
+    /// The board that fascist policies are placed on. This board is always needed.
+    FascistBoard,
+    /// An optional separate board for the election tracker
+    TrackerBoard,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct ConnectionStatus {
-    pub peripheral_address: BdAddr,
+    /// Connecting needs the kind of address, since a public and a random address can have the same bytes
+    pub peripheral_address: Address,
+    pub role: PeripheralRole,
     pub state: ConnectState,
+    /// The tick at which `state` last changed.
+    /// This is when we started connecting, or when the connection was established.
+    pub since: u64,
+}
+
+impl ConnectionStatus {
+    /// The number of ticks that we have been connecting or connected for
+    pub fn elapsed(&self, now: u64) -> u64 {
+        now.saturating_sub(self.since)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScannedPeripheral {
+    pub address: Address,
+    /// The role that the user assigned to this peripheral.
+    /// Only peripherals with a role will be connected to.
+    pub role: Option<PeripheralRole>,
+    /// `address` is the identity address of a peripheral that we bonded with
+    pub known: bool,
+    /// Found by this scan. Saved peripherals are listed before they are found, so that the user can tell
+    /// that the board remembers them, and connecting to them is tried anyway since they may come in range.
+    pub in_range: bool,
 }
 
-#[derive(Debug, Clone)]
+/// A peripheral that the user gave a name to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownPeripheral {
+    pub address: BdAddr,
+    pub name: heapless::String<PERIPHERAL_NAME_LEN>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ConnectionAction {
     Scan {
-        peripherals: heapless::Vec<BdAddr, SCAN_LIST_SIZE>,
+        peripherals: ScanList,
+        /// Scanning was paused from the Bluetooth screen to save power
+        paused: bool,
     },
-    Connect(ConnectionStatus),
+    /// Always contains exactly one [`PeripheralRole::FascistBoard`]
+    Connect(heapless::Vec<ConnectionStatus, MAX_PERIPHERALS>),
+    /// BLE is unavailable, so the game is played with only this board and the fascist board isn't synced
+    LocalOnly,
+}
+
+impl ConnectionAction {
+    /// The connections that a game would be played with, if a game can be started now
+    fn ready_to_start(&self) -> Option<heapless::Vec<ConnectionStatus, MAX_PERIPHERALS>> {
+        match self {
+            Self::Connect(statuses) if all_connected(statuses) => Some(statuses.clone()),
+            // There is no fascist board to wait for
+            Self::LocalOnly => Some(Default::default()),
+            _ => None,
+        }
+    }
+
+    /// The title item of the Bluetooth screen while scanning, which says what clicking it does
+    pub fn scan_title(&self) -> &'static str {
+        match self {
+            Self::Scan { paused: true, .. } => labels::SCAN_PAUSED,
+            _ => labels::SCANNING,
+        }
+    }
+}
+
+/// The address of the peripheral with the [`PeripheralRole::FascistBoard`] role
+fn fascist_board_address(connection_statuses: &[ConnectionStatus]) -> Option<Address> {
+    connection_statuses
+        .iter()
+        .find(|status| status.role == PeripheralRole::FascistBoard)
+        .map(|status| status.peripheral_address)
+}
+
+fn all_connected(connection_statuses: &[ConnectionStatus]) -> bool {
+    connection_statuses
+        .iter()
+        .all(|status| status.state == ConnectState::Connected)
+}
+
+/// Like indexing `T::VARIANTS`, but an index that is out of range is clamped to the last variant instead of panicking.
+/// This way a stale `selected_item` doesn't crash the board.
+pub fn checked_variant<T: VariantArray>(index: usize) -> &'static T {
+    let max = T::VARIANTS.len() - 1;
+    if index > max {
+        log_warn!(
+            "Selected item {} is out of range, using the last item {} instead",
+            index,
+            max
+        );
+    }
+    &T::VARIANTS[index.min(max)]
+}
+
+/// The role that a scanned peripheral gets when it is clicked.
+/// Cycles through the roles that no other peripheral has, and then goes back to no role.
+fn next_role(peripherals: &[ScannedPeripheral], index: usize) -> Option<PeripheralRole> {
+    let start = peripherals[index].role.map_or(0, |role| role as usize + 1);
+    PeripheralRole::VARIANTS[start..]
+        .iter()
+        .copied()
+        .find(|role| {
+            !peripherals
+                .iter()
+                .any(|peripheral| peripheral.role == Some(*role))
+        })
 }
 
 #[derive(VariantArray)]
 pub enum ConnectingConnectedSelectedItem {
     Back,
     /// Highlight the text that says connecting to ...
+    /// Clicking it retries connecting right away, or shows the connection details once everything is connected.
     Title,
+    /// Stop trying to connect, or disconnect if any peripheral is already connected.
+    /// See [`ConnectingConnectedSelectedItem::cancel_label`].
     Cancel,
 }
 
+impl ConnectingConnectedSelectedItem {
+    /// The cancel item says "Disconnect" instead of "Cancel" if any peripheral is connected
+    pub fn cancel_label(connection_statuses: &[ConnectionStatus]) -> &'static str {
+        if connection_statuses
+            .iter()
+            .any(|status| status.state == ConnectState::Connected)
+        {
+            "Disconnect"
+        } else {
+            "Cancel"
+        }
+    }
+}
+
 #[derive(VariantArray)]
 pub enum ScanningSelectedItem {
     Back,
+    /// Pauses or resumes scanning
     Title,
+    /// Connect to the peripherals that have a role
+    Connect,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum BluetoothScreen {
     Scanning {
         scroll_y: u32,
-        /// See [`ScanningSelectedItem`] for first two items, after that it's one item for each scanned device
+        /// See [`ScanningSelectedItem`] for the first items, after that it's one item for each scanned device.
+        /// Clicking on a scanned device cycles through the roles it can be assigned.
         selected_item: usize,
     },
     ConnectingConnected {
         scroll_y: u32,
-        /// See [`ConnectingConnectedSelectedItem`]
+        /// See [`ConnectingConnectedSelectedItem`] for the first items, after that it's one item for each peripheral.
+        /// Clicking on a peripheral renames it.
         selected_item: usize,
     },
+    /// Shown instead of scanning with [`ConnectionAction::LocalOnly`]. The back item is always selected.
+    Unavailable,
+    /// How long each peripheral has been connected for, opened by clicking the title once everything is connected.
+    /// The back item is always selected.
+    ConnectionDetails,
+}
+
+impl BluetoothScreen {
+    /// The Bluetooth screen that matches what we are currently doing
+    pub fn new(connection_action: &ConnectionAction) -> Self {
+        match connection_action {
+            ConnectionAction::Scan { .. } => Self::Scanning {
+                scroll_y: 0, // TODO: make sure it's visible
+                selected_item: ScanningSelectedItem::Title as usize,
+            },
+            ConnectionAction::Connect(_) => Self::ConnectingConnected {
+                scroll_y: 0, // TODO: make sure it's visible
+                selected_item: ConnectingConnectedSelectedItem::Title as usize,
+            },
+            ConnectionAction::LocalOnly => Self::Unavailable,
+        }
+    }
 }
 
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
@@ -74,28 +289,536 @@ pub enum BluetoothScreen {
 pub enum MainMenuSelectedItem {
     StartGame,
     Bluetooth,
+    /// Saves and restarts both boards, see [`GameEffect::RestartBoards`]
+    RestartBoards,
+    About,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct MainMenuScreen {
     pub scroll_y: u32,
     /// See [`MainMenuSelectedItem`]
     pub selected_item: usize,
 }
 
-#[derive(Debug, Clone)]
+/// The max length of a line on the About screen
+pub const ABOUT_LINE_LEN: usize = 18;
+/// The number of lines on the About screen, not including the back item
+pub const ABOUT_LINES: usize = 13 + labels::ABOUT_LICENSE.len();
+
+/// The max length of a title or item in [`GameState::screen`]
+pub const SCREEN_TEXT_LEN: usize = ABOUT_LINE_LEN;
+/// The max number of items in [`GameState::screen`]. The About screen has the most items.
+pub const MAX_SCREEN_ITEMS: usize = ABOUT_LINES;
+pub type ScreenText = heapless::String<SCREEN_TEXT_LEN>;
+
+/// Text that is too long is cut off
+pub fn screen_text(text: &str) -> ScreenText {
+    let mut screen_text = ScreenText::new();
+    for character in text.chars() {
+        if screen_text.push(character).is_err() {
+            break;
+        }
+    }
+    screen_text
+}
+
+/// Info that the game state doesn't know about, but is shown on the About screen
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RuntimeInfo {
+    pub firmware_version: &'static str,
+    /// The short git commit hash that the firmware was built from
+    pub git_hash: &'static str,
+    /// The protocol version used to talk to the STM32
+    pub protocol_version: u16,
+    /// Our BLE address
+    pub our_address: BdAddr,
+    /// In bytes
+    pub free_heap: usize,
+    /// The most heap that was used at once since booting, in bytes
+    pub peak_heap: usize,
+    pub uptime_secs: u64,
+    /// Writing to the LEDs kept failing, so it was disabled
+    pub leds_disabled: bool,
+    /// The BLE controller couldn't be initialized, see [`ConnectionAction::LocalOnly`]
+    pub ble_unavailable: bool,
+    /// How many times both of the rotary encoder's pins changed at once, which misses a step
+    pub rotary_resyncs: u32,
+    /// How far the fascist board's clock is from the liberal board's. `None` until they synced.
+    pub clock_sync: Option<ClockSync>,
+}
+
+impl RuntimeInfo {
+    /// The lines shown on the About screen
+    pub fn about_lines(&self) -> [heapless::String<ABOUT_LINE_LEN>; ABOUT_LINES] {
+        let mut lines = [const { heapless::String::new() }; ABOUT_LINES];
+        // Lines that are too long are cut off
+        let _ = write!(lines[0], "Version: {}", self.firmware_version);
+        let _ = write!(lines[1], "Commit: {}", self.git_hash);
+        let _ = write!(lines[2], "Protocol: {}", self.protocol_version);
+        let _ = write!(lines[3], "BLE address:");
+        let _ = write!(lines[4], "{}", fmt_bd_addr(&self.our_address));
+        let _ = write!(lines[5], "Free heap: {}B", self.free_heap);
+        let _ = write!(lines[6], "Peak heap: {}B", self.peak_heap);
+        let _ = write!(
+            lines[7],
+            "Uptime: {}h{}m{}s",
+            self.uptime_secs / 3600,
+            self.uptime_secs / 60 % 60,
+            self.uptime_secs % 60
+        );
+        let _ = write!(
+            lines[8],
+            "LEDs: {}",
+            if self.leds_disabled { "disabled" } else { "OK" }
+        );
+        let _ = write!(
+            lines[9],
+            "BLE: {}",
+            if self.ble_unavailable {
+                "unavailable"
+            } else {
+                "OK"
+            }
+        );
+        let _ = write!(lines[10], "Knob resyncs: {}", self.rotary_resyncs);
+        match self.clock_sync {
+            Some(clock_sync) => {
+                let _ = write!(lines[11], "Clock: {:+}ms", clock_sync.offset_ms);
+                let _ = write!(lines[12], "Sync RTT: {}ms", clock_sync.round_trip_ms);
+            }
+            None => {
+                let _ = write!(lines[11], "Clock: not synced");
+                let _ = write!(lines[12], "Sync RTT: -");
+            }
+        }
+        for (line, label) in lines[13..].iter_mut().zip(labels::ABOUT_LICENSE) {
+            let _ = line.push_str(label);
+        }
+        lines
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct AboutScreen {
+    pub scroll_y: u32,
+    /// The first item is the back item, and then one item for each line of [`RuntimeInfo::about_lines`].
+    /// Selecting lines is how the screen is scrolled.
+    pub selected_item: usize,
+}
+
+/// The characters that can be entered on the text entry screen
+pub const TEXT_ENTRY_CHARACTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789 -";
+
+/// What can be selected on the text entry screen.
+/// Turning the rotary encoder cycles through every character, then backspace, then done.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEntryChoice {
+    Character(char),
+    Backspace,
+    Done,
+}
+
+impl TextEntryChoice {
+    pub const COUNT: usize = TEXT_ENTRY_CHARACTERS.len() + 2;
+
+    pub fn get(index: usize) -> Self {
+        match TEXT_ENTRY_CHARACTERS.as_bytes().get(index) {
+            Some(&character) => Self::Character(character.into()),
+            None if index == TEXT_ENTRY_CHARACTERS.len() => Self::Backspace,
+            None => Self::Done,
+        }
+    }
+}
+
+/// What the entered text is for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextEntryPurpose {
+    RenamePeripheral(BdAddr),
+}
+
+/// The result of entering text
+#[derive(Debug, PartialEq, Eq)]
+pub enum TextEntryResult {
+    Done,
+    Cancelled,
+}
+
+/// A simple character picker.
+/// The rotary encoder selects a character, and clicking adds it to the text.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEntryScreen {
+    pub purpose: TextEntryPurpose,
+    pub text: heapless::String<PERIPHERAL_NAME_LEN>,
+    /// See [`TextEntryChoice::get`]
+    pub selected_choice: usize,
+}
+
+impl TextEntryScreen {
+    pub fn new(purpose: TextEntryPurpose, text: heapless::String<PERIPHERAL_NAME_LEN>) -> Self {
+        Self {
+            purpose,
+            text,
+            selected_choice: 0,
+        }
+    }
+
+    /// Returns `Some` once the user is done entering text
+    pub fn process_input(&mut self, input: Input) -> Option<TextEntryResult> {
+        match input {
+            // The list of characters wraps around so that you don't have to turn all the way back
+            Input::Down => {
+                self.selected_choice = (self.selected_choice + 1) % TextEntryChoice::COUNT;
+            }
+            Input::Up => {
+                self.selected_choice = self
+                    .selected_choice
+                    .checked_sub(1)
+                    .unwrap_or(TextEntryChoice::COUNT - 1);
+            }
+            Input::Click => match TextEntryChoice::get(self.selected_choice) {
+                TextEntryChoice::Character(character) => {
+                    if self.text.push(character).is_err() {
+                        log_warn!("Not adding character because the text is at its max length");
+                    }
+                }
+                TextEntryChoice::Backspace => {
+                    self.text.pop();
+                }
+                TextEntryChoice::Done => return Some(TextEntryResult::Done),
+            },
+            Input::Back => return Some(TextEntryResult::Cancelled),
+            Input::DoubleClick => {}
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum GameScreen {
     MainMenu(MainMenuScreen),
     Bluetooth(BluetoothScreen),
+    TextEntry(TextEntryScreen),
+    About(AboutScreen),
+    /// Counting down to starting a game after the fascist board reconnected on boot. Any input cancels it.
+    AutoStartCountdown {
+        remaining_ticks: u8,
+    },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct GameStateSettingUp {
     pub connection_action: ConnectionAction,
     pub screen: GameScreen,
+    /// The screens that we came from, including their `scroll_y` and `selected_item`.
+    /// Going back pops the last screen and restores it.
+    pub back_stack: heapless::Vec<GameScreen, NAVIGATION_STACK_SIZE>,
+    /// The Bluetooth screen as it was when going back from it, so that entering it again keeps the scroll and selection.
+    /// It is forgotten when the connection action changes, since that changes what the screen shows.
+    pub last_bluetooth_screen: Option<BluetoothScreen>,
+    pub known_peripherals: heapless::Vec<KnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+    /// The fascist board that we last connected to or tried to connect to.
+    /// It is listed while scanning, along with the known peripherals, even before it is found.
+    pub last_connected_peripheral: Option<Address>,
+    pub settings: Settings,
+    /// The latest tick given by the caller
+    pub tick: u64,
+    /// The tick of the last input, to tell when nobody is looking at the scanned peripherals
+    pub last_input_tick: u64,
+    /// The auto start countdown starts once everything is connected.
+    /// Only set when booting with [`Settings::auto_start`] and an auto-connect address, and cleared by any input.
+    pub auto_start_pending: bool,
+    /// Kept while playing, since the supply voltage doesn't care about the game
+    pub supply: SupplyStatus,
+    /// The fascist board needs to be sent that the game ended.
+    /// Only set by ending a game, since the fascist board starts out in [`sync::GamePhase::Setup`].
+    pub sync_pending: bool,
+    pub effects: EffectQueue,
+}
+
+impl GameStateSettingUp {
+    /// The name that the user gave to this peripheral, if any
+    pub fn peripheral_name(&self, address: BdAddr) -> Option<&str> {
+        self.known_peripherals
+            .iter()
+            .find(|peripheral| peripheral.address == address)
+            .map(|peripheral| peripheral.name.as_str())
+    }
+
+    /// The scan list before anything is found: the last connected peripheral and then the known peripherals.
+    /// Known peripherals only have the bytes of their address, so they are listed as random static addresses like the boards use.
+    fn saved_peripherals(&self) -> ScanList {
+        let mut peripherals = heapless::Vec::<ScannedPeripheral, SCAN_LIST_SIZE>::new();
+        let addresses = self.last_connected_peripheral.into_iter().chain(
+            self.known_peripherals
+                .iter()
+                .map(|peripheral| Address::random(peripheral.address.into_inner())),
+        );
+        for address in addresses {
+            if !peripherals
+                .iter()
+                .any(|peripheral| peripheral.address.addr == address.addr)
+            {
+                // Saved peripherals that don't fit are listed once they are found
+                let _ = peripherals.push(ScannedPeripheral {
+                    address,
+                    role: None,
+                    known: false,
+                    in_range: false,
+                });
+            }
+        }
+        peripherals.into_iter().collect()
+    }
+
+    /// An empty name forgets the peripheral.
+    /// If we already know the max number of peripherals, the oldest one is forgotten.
+    fn set_peripheral_name(
+        &mut self,
+        address: BdAddr,
+        name: heapless::String<PERIPHERAL_NAME_LEN>,
+    ) {
+        self.known_peripherals
+            .retain(|peripheral| peripheral.address != address);
+        if !name.is_empty() {
+            if self.known_peripherals.is_full() {
+                self.known_peripherals.remove(0);
+            }
+            // We just made space
+            let _ = self
+                .known_peripherals
+                .push(KnownPeripheral { address, name });
+        }
+    }
+
+    /// Enter a new screen, remembering the current screen so that going back restores it.
+    /// If the stack is full, we stay on the current screen.
+    fn navigate_to(&mut self, screen: GameScreen) {
+        if self.back_stack.is_full() {
+            log_warn!(
+                "Not navigating to a new screen because the navigation stack is full. Consider rebuilding with a larger max size."
+            );
+            return;
+        }
+        let previous_screen = mem::replace(&mut self.screen, screen);
+        // We already checked that there is space
+        let _ = self.back_stack.push(previous_screen);
+        self.clamp_selected_item();
+        self.effects.push(GameEffect::RedrawScreen);
+    }
+
+    /// Enter the Bluetooth screen, restoring it if it was left with [`GameStateSettingUp::navigate_back`]
+    fn navigate_to_bluetooth(&mut self) {
+        let screen = self
+            .last_bluetooth_screen
+            .take()
+            .unwrap_or_else(|| BluetoothScreen::new(&self.connection_action));
+        self.navigate_to(GameScreen::Bluetooth(screen));
+    }
+
+    /// Go back to the previous screen. Does nothing on the main menu.
+    fn navigate_back(&mut self) {
+        if let Some(screen) = self.back_stack.pop() {
+            let previous_screen = mem::replace(&mut self.screen, screen);
+            if let GameScreen::Bluetooth(
+                screen @ (BluetoothScreen::Scanning { .. }
+                | BluetoothScreen::ConnectingConnected { .. }),
+            ) = previous_screen
+            {
+                self.last_bluetooth_screen = Some(screen);
+            }
+            self.clamp_selected_item();
+            self.effects.push(GameEffect::RedrawScreen);
+        }
+    }
+
+    /// A restored screen's selected item could be out of range if its list got shorter,
+    /// so it is clamped to the last item of the current screen.
+    fn clamp_selected_item(&mut self) {
+        let (selected_item, max) = match &mut self.screen {
+            GameScreen::MainMenu(screen) => (
+                &mut screen.selected_item,
+                MainMenuSelectedItem::VARIANTS.len() - 1,
+            ),
+            GameScreen::Bluetooth(BluetoothScreen::Scanning { selected_item, .. }) => (
+                selected_item,
+                ScanningSelectedItem::VARIANTS.len()
+                    + match &self.connection_action {
+                        ConnectionAction::Scan { peripherals, .. } => peripherals.len(),
+                        ConnectionAction::Connect(_) | ConnectionAction::LocalOnly => 0,
+                    }
+                    - 1,
+            ),
+            GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected {
+                selected_item, ..
+            }) => (
+                selected_item,
+                ConnectingConnectedSelectedItem::VARIANTS.len()
+                    + match &self.connection_action {
+                        ConnectionAction::Connect(statuses) => statuses.len(),
+                        ConnectionAction::Scan { .. } | ConnectionAction::LocalOnly => 0,
+                    }
+                    - 1,
+            ),
+            GameScreen::TextEntry(screen) => {
+                (&mut screen.selected_choice, TextEntryChoice::COUNT - 1)
+            }
+            GameScreen::About(screen) => (&mut screen.selected_item, ABOUT_LINES),
+            GameScreen::Bluetooth(
+                BluetoothScreen::Unavailable | BluetoothScreen::ConnectionDetails,
+            )
+            | GameScreen::AutoStartCountdown { .. } => return,
+        };
+        if *selected_item > max {
+            log_warn!(
+                "Clamping selected item {} to {} on the new screen",
+                *selected_item,
+                max
+            );
+            *selected_item = max;
+        }
+    }
+
+    /// Goes back to scanning, and returns the peripherals that need to be disconnected from
+    fn stop_connecting(&mut self) -> heapless::Vec<BdAddr, MAX_PERIPHERALS> {
+        let connected_peripherals = match &self.connection_action {
+            ConnectionAction::Connect(statuses) => statuses,
+            ConnectionAction::Scan { .. } | ConnectionAction::LocalOnly => {
+                unreachable!()
+            }
+        }
+        .iter()
+        .filter(|status| status.state == ConnectState::Connected)
+        .map(|status| status.peripheral_address.addr)
+        .collect();
+        if let ConnectionAction::Connect(statuses) = &self.connection_action {
+            self.last_connected_peripheral = fascist_board_address(statuses);
+        }
+        self.connection_action = ConnectionAction::Scan {
+            peripherals: self.saved_peripherals(),
+            paused: false,
+        };
+        self.last_bluetooth_screen = None;
+        // The connecting screens can't be shown while scanning, even after going back to them
+        for screen in [&mut self.screen].into_iter().chain(&mut self.back_stack) {
+            if let GameScreen::Bluetooth(
+                BluetoothScreen::ConnectingConnected { .. } | BluetoothScreen::ConnectionDetails,
+            ) = screen
+            {
+                *screen = GameScreen::Bluetooth(BluetoothScreen::Scanning {
+                    scroll_y: 0,
+                    selected_item: 0,
+                });
+            }
+        }
+        // Someone could be waiting for the list to show up, so it starts out scanning aggressively
+        self.last_input_tick = self.tick;
+        self.effects.push(GameEffect::RedrawScreen);
+        connected_peripherals
+    }
+
+    /// `None` if the connections aren't ready to start a game
+    fn start_game(&mut self) -> Option<GameStatePlaying> {
+        let connection_statuses = self.connection_action.ready_to_start()?;
+        self.effects.push(GameEffect::GameStarted);
+        Some(GameStatePlaying {
+            players: self.settings.default_players,
+            dead_players: 0,
+            settings: self.settings,
+            connection_statuses,
+            liberal_policies_placed: 0,
+            fascist_policies_placed: 0,
+            detected_cards: Default::default(),
+            highest_liberal_policies_placed: 0,
+            highest_fascist_policies_placed: 0,
+            liberal_policies_removed_since: None,
+            fascist_policies_removed_since: None,
+            hitler_state: HitlerState::Secret,
+            election_fail_streak: 0,
+            chaos_policy_pending: false,
+            pending_action: PendingAction::None,
+            hint_since: self.tick,
+            tick: self.tick,
+            // The first legislative session is about to start
+            last_card_change_tick: self.tick,
+            link_degraded: false,
+            misplacement: None,
+            misplaced_since: self.tick,
+            sync_pending: true,
+            local_only: matches!(self.connection_action, ConnectionAction::LocalOnly),
+            screen: PlayingScreen::Board,
+            investigations: heapless::Vec::new(),
+            known_peripherals: self.known_peripherals.clone(),
+            reader_debug: false,
+            supply: self.supply,
+            effects: mem::take(&mut self.effects),
+        })
+    }
+
+    /// Stops the auto start countdown, leaving the players on the main menu
+    fn cancel_auto_start(&mut self) {
+        self.screen = GameScreen::MainMenu(MainMenuScreen {
+            scroll_y: 0,
+            selected_item: 0,
+        });
+        self.effects.push(GameEffect::RedrawScreen);
+    }
+
+    /// The line on the auto start countdown screen, like `10 players in 5s`
+    pub fn auto_start_text(&self, remaining_ticks: u8) -> ScreenText {
+        let mut text = ScreenText::new();
+        let _ = write!(
+            text,
+            "{} players in {}s",
+            self.settings.default_players, remaining_ticks
+        );
+        text
+    }
+
+    /// Restarts the connect timeout of the peripherals that aren't connected yet, as if connecting just started
+    fn retry_connecting(&mut self) {
+        let ConnectionAction::Connect(statuses) = &mut self.connection_action else {
+            unreachable!()
+        };
+        for status in statuses
+            .iter_mut()
+            .filter(|status| status.state == ConnectState::Connecting)
+        {
+            status.since = self.tick;
+        }
+        self.effects.push(GameEffect::RedrawScreen);
+    }
+
+    /// Two lines for each peripheral: its name, and then how long it has been connected for.
+    /// `now` is the same as in [`GameState::tick`].
+    pub fn connection_details(
+        &self,
+        now: u64,
+    ) -> heapless::Vec<ScreenText, { 2 * MAX_PERIPHERALS }> {
+        let statuses: &[ConnectionStatus] = match &self.connection_action {
+            ConnectionAction::Connect(statuses) => statuses,
+            ConnectionAction::Scan { .. } | ConnectionAction::LocalOnly => &[],
+        };
+        statuses
+            .iter()
+            .flat_map(|status| {
+                let address = status.peripheral_address.addr;
+                let name = match self.peripheral_name(address) {
+                    Some(name) => screen_text(name),
+                    None => screen_text(&fmt_bd_addr(&address)),
+                };
+                let mut state = ScreenText::new();
+                let _ = match status.state {
+                    ConnectState::Connecting => write!(state, "Connecting"),
+                    ConnectState::Connected => write!(state, "Up {}s", status.elapsed(now)),
+                };
+                [name, state]
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HitlerState {
     /// It has not been publicly revealed who hitler is.
     Secret,
@@ -148,19 +871,251 @@ impl FascistAction {
             Self::Kill => false,
         }
     }
+
+    /// Players often forget whether they examined the top 3 cards,
+    /// so that hint needs a second click to be dismissed.
+    pub fn needs_confirmation(&self) -> bool {
+        match self {
+            Self::ExamineTop3 => true,
+            Self::CheckParty | Self::ChooseNextPresident | Self::Kill => false,
+        }
+    }
+
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Self::CheckParty => labels::CHECK_PARTY_HINT,
+            Self::ChooseNextPresident => labels::CHOOSE_NEXT_PRESIDENT_HINT,
+            Self::Kill => labels::KILL_HINT,
+            Self::ExamineTop3 => labels::EXAMINE_TOP_3_HINT,
+        }
+    }
+}
+
+/// The rules for presidential powers are different depending on the number of players
+fn players_rule(players: u8) -> &'static str {
+    match players {
+        5 | 6 => "5-6",
+        7 | 8 => "7-8",
+        9 | 10 => "9-10",
+        _ => unreachable!(),
+    }
+}
+
+/// A hint that also says which rule it comes from, so that players can check it against the rules.
+/// Formats as `Fascist policy #2 (9-10 players): President: check a player's party`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ActionHint {
+    pub action: FascistAction,
+    /// The fascist policy that gave the president this action, starting at 1
+    pub policy_index: usize,
+    pub players: u8,
+    /// The same as [`GameState::display_action_hint_text`]
+    pub text: &'static str,
+}
+
+impl fmt::Display for ActionHint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Fascist policy #{} ({} players): {}",
+            self.policy_index,
+            players_rule(self.players),
+            self.text
+        )
+    }
+}
+
+/// How many ticks the players have to click again to confirm that the hint can be dismissed
+pub const CONFIRM_ACTION_TICKS: u64 = 10;
+/// How long the auto start countdown gives the players to cancel before the game starts
+pub const AUTO_START_COUNTDOWN_TICKS: u8 = 5;
+/// After a policy card is placed on the wrong board, that board's aura blinks for this many ticks.
+/// The warning on the screen stays until the card is moved.
+pub const MISPLACED_BLINK_TICKS: u64 = 5;
+/// A policy card that is missing for this many ticks was taken off the board, such as after being placed by accident,
+/// instead of flickering out. Placing a policy card again after that is a new policy.
+pub const POLICY_REMOVED_TICKS: u64 = 5;
+
+/// What a call to [`GameState::tick`] changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct TickOutcome {
+    /// Something on the screen changed
+    pub redraw: bool,
+    /// Effects were pushed, which can be taken with [`GameState::drain_effects`]
+    pub effects: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PendingAction {
+    None,
+    Pending(FascistAction),
+    /// The hint was clicked once, and will be dismissed if it is clicked again by the deadline tick.
+    /// After the deadline, it goes back to [`PendingAction::Pending`].
+    Confirming(FascistAction, u64),
+}
+
+/// The most investigation results that can be written down with president notes.
+/// There are at most 2 check party actions in a game, so this is never full unless something went wrong.
+pub const MAX_INVESTIGATIONS: usize = 3;
+
+/// The items when writing down the result of a check party action. Skip is selected first.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray)]
+pub enum NoteEntrySelectedItem {
+    Skip,
+    Liberal,
+    Fascist,
+}
+
+impl NoteEntrySelectedItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Skip => "Skip",
+            Self::Liberal => "Liberal",
+            Self::Fascist => "Fascist",
+        }
+    }
+}
+
+/// The items of the menu that is opened by clicking while playing. Dismiss hint is selected first.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray)]
+pub enum PlayingMenuSelectedItem {
+    /// Does what clicking did before there was a menu
+    DismissHint,
+    Pause,
+    AdjustPlayers,
+    /// Shows what each NFC reader sees at the bottom of the screen, for when a card isn't being detected
+    ReaderDebug,
+    /// Asks the firmware to log [`GameState::debug_dump`], for when the board disagrees with the table
+    DebugDump,
+    EndGame,
+    /// Opens [`PlayingScreen::Notes`]. Only shown with [`Settings::president_notes`].
+    /// It's last so that the other items are in the same place either way.
+    Notes,
+}
+
+impl PlayingMenuSelectedItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::DismissHint => "Dismiss hint",
+            Self::Pause => "Pause game",
+            Self::AdjustPlayers => "Adjust players",
+            Self::ReaderDebug => "NFC debug",
+            Self::DebugDump => "Log state",
+            Self::EndGame => "End game",
+            Self::Notes => "Notes",
+        }
+    }
+}
+
+/// The items when confirming to end the game. Keep playing is selected first, so that a double click doesn't end the game.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, VariantArray)]
+pub enum EndGameSelectedItem {
+    KeepPlaying,
+    EndGame,
+}
+
+impl EndGameSelectedItem {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::KeepPlaying => "Keep playing",
+            Self::EndGame => "End game",
+        }
+    }
+}
+
+/// A line on the president notes screen, such as `Policy #2: Fascist`
+pub fn investigation_text(policy_index: usize, team: Team) -> ScreenText {
+    let mut text = ScreenText::new();
+    // Text that is too long is cut off
+    let _ = write!(
+        text,
+        "Policy #{}: {}",
+        policy_index,
+        match team {
+            Team::Liberal => NoteEntrySelectedItem::Liberal.label(),
+            Team::Fascist => NoteEntrySelectedItem::Fascist.label(),
+        }
+    );
+    text
+}
+
+/// Why [`GameState::adjust_player_count`] didn't change the number of players
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AdjustPlayersError {
+    /// The rules only cover [`PLAYERS`]
+    NotCovered,
+    /// Fewer than 3 players would still be alive. See [`GameStatePlaying::min_players`].
+    TooFewAlive,
+}
+
+/// The item on the adjust players screen, such as `7 players`
+pub fn players_text(players: u8) -> ScreenText {
+    let mut text = ScreenText::new();
+    let _ = write!(text, "{players} players");
+    text
+}
+
+/// What is shown on the screen while playing
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PlayingScreen {
+    /// The hints and warnings about the game
+    Board,
+    /// Asks the president which team the player they checked was, right after the check party hint was dismissed.
+    /// Only shown with [`Settings::president_notes`].
+    NoteEntry {
+        /// The fascist policy that gave the president the check party action, starting at 1
+        policy_index: usize,
+        /// See [`NoteEntrySelectedItem`]
+        selected_item: usize,
+    },
+    /// Lists what the president wrote down. Opened from the game menu.
+    Notes,
+    /// Opened by clicking on the board
+    Menu {
+        /// See [`PlayingMenuSelectedItem`]
+        selected_item: usize,
+    },
+    ConfirmEndGame {
+        /// See [`EndGameSelectedItem`]
+        selected_item: usize,
+    },
+    /// The group is taking a break. Scanned cards are ignored and the LEDs are dimmed until clicking resumes the game.
+    Paused,
+    /// Choosing the new number of players after someone left. Clicking applies it with [`GameState::adjust_player_count`].
+    AdjustPlayers { players: u8 },
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct GameStatePlaying {
-    /// The game has 5-10 players. Once the game is started, the number of players currently cannot be adjusted.
-    /// However, we could in the future handle changing the number of players mid-game.
-    /// We would need to update the `pending_action` field when this happens.
+    /// The game has 5-10 players. If someone leaves, it can be changed with [`GameState::adjust_player_count`].
     players: u8,
-    connection_status: ConnectionStatus,
+    /// Players that were killed with the kill action, who still count towards [`GameStatePlaying::players`]
+    dead_players: u8,
+    settings: Settings,
+    connection_statuses: heapless::Vec<ConnectionStatus, MAX_PERIPHERALS>,
     liberal_policies_placed: usize,
     fascist_policies_placed: usize,
+    /// From the last [`GameState::update_scanned_policy_cards`], for [`GameState::debug_dump`]
+    detected_cards: DetectedPolicyCards,
+    /// The most liberal policies that were placed at once.
+    /// A card that flickers out for one scan and back in isn't a new policy, so it doesn't reset the election tracker again.
+    highest_liberal_policies_placed: usize,
+    /// Like [`GameStatePlaying::highest_liberal_policies_placed`].
+    /// A policy's action is only triggered the first time that its index is reached, so a flicker doesn't bring back a hint that was acted on.
+    highest_fascist_policies_placed: usize,
+    /// The tick when there were fewer liberal policies than [`GameStatePlaying::highest_liberal_policies_placed`].
+    /// After [`POLICY_REMOVED_TICKS`], the highest count is lowered to the policies that are still placed.
+    liberal_policies_removed_since: Option<u64>,
+    /// Like [`GameStatePlaying::liberal_policies_removed_since`]
+    fascist_policies_removed_since: Option<u64>,
     hitler_state: HitlerState,
     election_fail_streak: usize,
+    /// The top policy was enacted because of chaos, so its presidential power is ignored
+    chaos_policy_pending: bool,
     /// The game can give a tip of what to do next on the screen.
     ///
     /// Most of the time, it will say "place a policy or increment the election fail counter".
@@ -174,10 +1129,51 @@ pub struct GameStatePlaying {
     /// If there is a "the president kills another player" action, the game will dismiss this
     /// action when a dead character card is detected or the fascist policy card is removed.
     /// This hint cannot be manually dismissed.
-    pending_action: bool,
+    ///
+    /// If there is an "examine the top 3 cards" action, the hint needs to be clicked twice to be dismissed.
+    pending_action: PendingAction,
+    /// When [`GameStatePlaying::pending_action`] was last shown, for [`GameState::hint_age`]
+    hint_since: u64,
+    /// The latest tick given by the caller
+    tick: u64,
+    /// When the scanned policy cards last changed, for [`GameState::expected_scan_activity`]
+    last_card_change_tick: u64,
+    /// The connection to the fascist board (or any other peripheral) was lost during the game.
+    /// We keep processing scanned cards on our board, but the fascist board will be out of sync until we reconnect.
+    link_degraded: bool,
+    /// The fascist board needs to be sent the latest state.
+    /// Updates are coalesced while disconnected, so only one up-to-date sync is sent after reconnecting.
+    sync_pending: bool,
+    /// Started with [`ConnectionAction::LocalOnly`], so there is no fascist board to sync
+    local_only: bool,
+    /// A policy card was placed on the other team's board in the latest scan
+    misplacement: Option<Misplacement>,
+    /// When [`GameStatePlaying::misplacement`] last moved to a different board, for [`MISPLACED_BLINK_TICKS`]
+    misplaced_since: u64,
+    screen: PlayingScreen,
+    /// President notes: the team that each checked player was claimed to be, by the policy index that gave the check party action.
+    /// This is only a memory aid for the president, so it doesn't affect the game.
+    /// It starts empty in every game.
+    investigations: heapless::Vec<(usize, Team), MAX_INVESTIGATIONS>,
+    /// Kept from setting up, so that they are still known after the game is ended
+    known_peripherals: heapless::Vec<KnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+    /// Toggled from the menu. It isn't saved, so every game starts without it.
+    reader_debug: bool,
+    supply: SupplyStatus,
+    effects: EffectQueue,
 }
 
 impl GameStatePlaying {
+    pub fn players(&self) -> u8 {
+        self.players
+    }
+
+    /// The fewest players that the game can be changed to.
+    /// Dead players stay at the table, and at least 3 players need to still be alive.
+    pub fn min_players(&self) -> u8 {
+        (self.dead_players + 3).max(*PLAYERS.start())
+    }
+
     pub fn winner(&self) -> Option<Team> {
         match self.hitler_state {
             HitlerState::Secret => {
@@ -194,52 +1190,600 @@ impl GameStatePlaying {
         }
     }
 
-    // pub fn
-}
+    /// The number of failed elections in a row that are still allowed before the top policy is enacted
+    pub fn failures_until_chaos(&self) -> usize {
+        ELECTION_FAILS_FOR_CHAOS - self.election_fail_streak
+    }
 
-#[derive(Debug, Clone)]
-pub enum GameState {
-    SettingUp(GameStateSettingUp),
-    Playing(GameStatePlaying),
-}
+    /// If `true`, the screen should say that the fascist board is disconnected and that we are reconnecting.
+    pub fn link_degraded(&self) -> bool {
+        self.link_degraded
+    }
 
-impl GameState {
-    /// You can load a auto-connect address if you want
-    pub fn new(peripheral_address: Option<BdAddr>) -> Self {
-        Self::SettingUp(GameStateSettingUp {
-            connection_action: match peripheral_address {
-                Some(address) => ConnectionAction::Connect(ConnectionStatus {
-                    peripheral_address: address,
-                    state: ConnectState::Connecting,
-                }),
-                None => ConnectionAction::Scan {
-                    peripherals: Default::default(),
-                },
-            },
-            screen: GameScreen::MainMenu(MainMenuScreen {
-                scroll_y: 0,
-                selected_item: 0,
-            }),
-        })
+    /// If `true`, the board screen should show what each NFC reader sees
+    pub fn reader_debug(&self) -> bool {
+        self.reader_debug
     }
-}
 
-#[derive(Debug, PartialEq, Eq)]
+    /// If this is `Some`, the screen should warn the players to move the policy card to the correct board
+    pub fn misplacement(&self) -> Option<Misplacement> {
+        self.misplacement
+    }
+
+    /// The board with a misplaced policy card, until it blinked for [`MISPLACED_BLINK_TICKS`]
+    fn misplaced_board_blinking(&self) -> Option<Team> {
+        self.misplacement
+            .filter(|_| self.tick < self.misplaced_since + MISPLACED_BLINK_TICKS)
+            .map(|misplacement| misplacement.board)
+    }
+
+    pub fn screen(&self) -> PlayingScreen {
+        self.screen
+    }
+
+    /// The game was started with [`ConnectionAction::LocalOnly`], so there is no fascist board
+    pub fn local_only(&self) -> bool {
+        self.local_only
+    }
+
+    pub fn paused(&self) -> bool {
+        self.screen == PlayingScreen::Paused
+    }
+
+    /// See [`Settings::president_notes`]. Sorted by policy index.
+    pub fn investigations(&self) -> &[(usize, Team)] {
+        &self.investigations
+    }
+
+    /// The items of [`PlayingScreen::Menu`]. The notes are only there with [`Settings::president_notes`].
+    pub fn menu_items(&self) -> &'static [PlayingMenuSelectedItem] {
+        let items = PlayingMenuSelectedItem::VARIANTS;
+        if self.settings.president_notes {
+            items
+        } else {
+            &items[..items.len() - 1]
+        }
+    }
+
+    /// Writing down the same policy index again replaces the previous note
+    fn record_investigation(&mut self, policy_index: usize, team: Team) {
+        if let Some(investigation) = self
+            .investigations
+            .iter_mut()
+            .find(|(index, _)| *index == policy_index)
+        {
+            investigation.1 = team;
+        } else if self.investigations.push((policy_index, team)).is_err() {
+            log_warn!(
+                "Not saving the note for fascist policy #{} because there are already {} notes",
+                policy_index,
+                MAX_INVESTIGATIONS
+            );
+        } else {
+            self.investigations
+                .sort_unstable_by_key(|(index, _)| *index);
+        }
+    }
+
+    fn show_screen(&mut self, screen: PlayingScreen) {
+        self.screen = screen;
+        self.effects.push(GameEffect::RedrawScreen);
+    }
+
+    /// Pausing and resuming changes the LEDs on both boards
+    fn set_paused(&mut self, paused: bool) {
+        self.show_screen(if paused {
+            PlayingScreen::Paused
+        } else {
+            PlayingScreen::Board
+        });
+        self.sync_pending = true;
+    }
+
+    /// How many ticks the hint is shown for before it dismisses itself.
+    /// `None` if there is no hint being shown, or if it stays until it's dismissed.
+    fn hint_auto_dismiss_ticks(&self) -> Option<u64> {
+        let timeout = self.settings.hint_auto_dismiss_ticks;
+        match self.pending_action {
+            PendingAction::Pending(action) | PendingAction::Confirming(action, _)
+                if timeout != 0 && !self.link_degraded && action.can_clear_with_button_press() =>
+            {
+                Some(timeout.into())
+            }
+            _ => None,
+        }
+    }
+
+    /// Dismisses the hint from any screen, without going through the menu
+    fn double_click(&mut self) {
+        self.show_screen(PlayingScreen::Board);
+        self.dismiss_hint();
+    }
+
+    /// What clicking on the board did before there was a menu, and what rotating and double clicking still do.
+    /// With president notes, this opens the note entry after the check party hint.
+    fn dismiss_hint(&mut self) {
+        // The hint isn't shown while the fascist board is disconnected, so it can't be dismissed
+        if self.link_degraded {
+            return;
+        }
+        let previous_action = self.pending_action;
+        self.pending_action = match self.pending_action {
+            PendingAction::Pending(action) if action.needs_confirmation() => {
+                PendingAction::Confirming(action, self.tick + CONFIRM_ACTION_TICKS)
+            }
+            PendingAction::Pending(action) if action.can_clear_with_button_press() => {
+                PendingAction::None
+            }
+            PendingAction::Confirming(_, deadline) if self.tick <= deadline => PendingAction::None,
+            pending_action => pending_action,
+        };
+        if self.settings.president_notes
+            && previous_action == PendingAction::Pending(FascistAction::CheckParty)
+        {
+            self.show_screen(PlayingScreen::NoteEntry {
+                policy_index: self.fascist_policies_placed,
+                selected_item: NoteEntrySelectedItem::Skip as usize,
+            });
+        }
+    }
+
+    /// Goes back to the main menu, staying connected to the fascist board, so that a new game can be started
+    fn end_game(&mut self) -> GameStateSettingUp {
+        let mut effects = mem::take(&mut self.effects);
+        effects.push(GameEffect::RedrawScreen);
+        GameStateSettingUp {
+            connection_action: if self.local_only() {
+                ConnectionAction::LocalOnly
+            } else {
+                ConnectionAction::Connect(self.connection_statuses.clone())
+            },
+            screen: GameScreen::MainMenu(MainMenuScreen {
+                scroll_y: 0,
+                selected_item: 0,
+            }),
+            back_stack: Default::default(),
+            last_bluetooth_screen: None,
+            known_peripherals: mem::take(&mut self.known_peripherals),
+            last_connected_peripheral: fascist_board_address(&self.connection_statuses),
+            settings: self.settings,
+            tick: self.tick,
+            last_input_tick: self.tick,
+            auto_start_pending: false,
+            supply: self.supply,
+            sync_pending: !self.local_only(),
+            effects,
+        }
+    }
+
+    /// `previous_winner` is the winner before the state changed
+    fn push_game_completed(&mut self, previous_winner: Option<Team>) {
+        if previous_winner.is_none()
+            && let Some(team) = self.winner()
+        {
+            self.effects.push(GameEffect::GameCompleted(team));
+        }
+    }
+}
+
+// The game state is only stored in a few places, so it's fine for it to be big instead of using a heap allocation
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameState {
+    SettingUp(GameStateSettingUp),
+    Playing(GameStatePlaying),
+}
+
+/// Settings that are saved and survive a reboot
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settings {
+    /// 255 is full brightness
+    pub led_brightness: u8,
+    /// Invert the display every once in a while to reduce burn in.
+    /// I'm not sure whether this actually reduces burn-in
+    /// or if it just makes all pixels burned in more evenly.
+    /// Either way it preserves the screen quality over time
+    pub invert_screen_interval_secs: u16,
+    /// The number of players when starting a game
+    pub default_players: u8,
+    /// While setting up, give up connecting and go back to scanning after this many ticks.
+    /// 0 means never give up.
+    pub connect_timeout_ticks: u16,
+    /// Show how long rendering takes at the bottom of the screen, for debugging
+    pub show_frame_stats: bool,
+    /// After the check party hint is dismissed, let the president write down which team the player was.
+    /// The notes can be seen again by clicking when there is no hint.
+    pub president_notes: bool,
+    /// When booting reconnects to the saved fascist board, count down and start a game with [`Settings::default_players`]
+    pub auto_start: bool,
+    /// Hints that can be dismissed with a button press dismiss themselves after being shown for this many ticks.
+    /// 0 means never. The kill hint never dismisses itself.
+    pub hint_auto_dismiss_ticks: u16,
+    /// Rotate the display 180°, for enclosures that have it mounted upside down
+    pub rotate_display: bool,
+    /// Which board's LEDs show the election tracker
+    pub election_tracker_placement: ElectionTrackerPlacement,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            led_brightness: 13,
+            invert_screen_interval_secs: 2 * 60,
+            default_players: 10,
+            connect_timeout_ticks: 30,
+            show_frame_stats: false,
+            president_notes: false,
+            auto_start: false,
+            hint_auto_dismiss_ticks: 0,
+            rotate_display: false,
+            election_tracker_placement: Default::default(),
+        }
+    }
+}
+
+impl GameState {
+    /// You can load a auto-connect address for the fascist board if you want,
+    /// and the names of peripherals and settings that were saved
+    pub fn new(
+        peripheral_address: Option<Address>,
+        known_peripherals: heapless::Vec<KnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+        settings: Settings,
+    ) -> Self {
+        let mut state = GameStateSettingUp {
+            connection_action: match peripheral_address {
+                Some(address) => ConnectionAction::Connect(
+                    [ConnectionStatus {
+                        peripheral_address: address,
+                        role: PeripheralRole::FascistBoard,
+                        state: ConnectState::Connecting,
+                        since: 0,
+                    }]
+                    .into_iter()
+                    .collect(),
+                ),
+                None => ConnectionAction::Scan {
+                    peripherals: Default::default(),
+                    paused: false,
+                },
+            },
+            screen: GameScreen::MainMenu(MainMenuScreen {
+                scroll_y: 0,
+                selected_item: 0,
+            }),
+            back_stack: Default::default(),
+            last_bluetooth_screen: None,
+            known_peripherals,
+            last_connected_peripheral: peripheral_address,
+            settings,
+            tick: 0,
+            last_input_tick: 0,
+            auto_start_pending: settings.auto_start && peripheral_address.is_some(),
+            supply: Default::default(),
+            sync_pending: false,
+            effects: Default::default(),
+        };
+        let saved_peripherals = state.saved_peripherals();
+        if let ConnectionAction::Scan { peripherals, .. } = &mut state.connection_action {
+            *peripherals = saved_peripherals;
+        }
+        Self::SettingUp(state)
+    }
+
+    /// The fascist board that was saved, for when it isn't connected to on boot.
+    /// It is listed first while scanning, even before it is found.
+    pub fn load_last_connected_peripheral(&mut self, address: Address) {
+        if let Self::SettingUp(state) = self {
+            state.last_connected_peripheral = Some(address);
+            let saved_peripherals = state.saved_peripherals();
+            // Before anything is found, the list is only the saved peripherals
+            if let ConnectionAction::Scan { peripherals, .. } = &mut state.connection_action
+                && peripherals.iter().all(|peripheral| !peripheral.in_range)
+            {
+                *peripherals = saved_peripherals;
+            }
+        }
+    }
+
+    /// For when BLE couldn't be initialized. A game can still be started, but only with this board.
+    pub fn new_local_only(
+        known_peripherals: heapless::Vec<KnownPeripheral, KNOWN_PERIPHERALS_SIZE>,
+        settings: Settings,
+    ) -> Self {
+        let mut state = Self::new(None, known_peripherals, settings);
+        if let Self::SettingUp(state) = &mut state {
+            state.connection_action = ConnectionAction::LocalOnly;
+        }
+        state
+    }
+
+    /// BLE is unavailable, so the `ble_*` functions do nothing
+    pub fn local_only(&self) -> bool {
+        match self {
+            Self::SettingUp(state) => {
+                matches!(state.connection_action, ConnectionAction::LocalOnly)
+            }
+            Self::Playing(state) => state.local_only(),
+        }
+    }
+
+    pub fn settings(&self) -> &Settings {
+        match self {
+            Self::SettingUp(state) => &state.settings,
+            Self::Playing(state) => &state.settings,
+        }
+    }
+
+    pub fn supply(&self) -> SupplyStatus {
+        match self {
+            Self::SettingUp(state) => state.supply,
+            Self::Playing(state) => state.supply,
+        }
+    }
+
+    fn supply_mut(&mut self) -> &mut SupplyStatus {
+        match self {
+            Self::SettingUp(state) => &mut state.supply,
+            Self::Playing(state) => &mut state.supply,
+        }
+    }
+
+    /// Called with each level from [`SupplyMonitor::record`].
+    /// Getting worse than [`SupplyLevel::Normal`] shows [`SupplyStatus::popup`], and [`SupplyLevel::Critical`] dims the LEDs.
+    pub fn supply_level_changed(&mut self, level: SupplyLevel) {
+        let supply = self.supply_mut();
+        if level == supply.level {
+            return;
+        }
+        log_warn!("Supply voltage is now {}", level);
+        if supply.level == SupplyLevel::Normal {
+            supply.popup = true;
+        } else if level == SupplyLevel::Normal {
+            supply.popup = false;
+        }
+        supply.level = level;
+        self.effects_mut().push(GameEffect::RedrawScreen);
+    }
+
+    fn effects_mut(&mut self) -> &mut EffectQueue {
+        match self {
+            Self::SettingUp(state) => &mut state.effects,
+            Self::Playing(state) => &mut state.effects,
+        }
+    }
+
+    /// Removes the effects that were pushed since the last call, oldest first.
+    /// This should be called after every call that mutates the game state.
+    pub fn drain_effects(&mut self) -> impl Iterator<Item = GameEffect> + '_ {
+        self.effects_mut().drain()
+    }
+
+    /// A multi-line report of everything that the game state knows, for when the board disagrees with the table.
+    /// It ends with `recent_events`, which should be the last [`DEBUG_DUMP_EVENTS`] recorded events, oldest first.
+    /// The report is less than [`DEBUG_DUMP_LEN`] bytes.
+    pub fn debug_dump(
+        &self,
+        recent_events: impl IntoIterator<Item = RecordEntry>,
+        out: &mut impl fmt::Write,
+    ) -> fmt::Result {
+        match self {
+            Self::SettingUp(state) => {
+                writeln!(out, "Setting up, tick {}", state.tick)?;
+                writeln!(out, "screen: {:?}", state.screen)?;
+                writeln!(out, "back stack: {}", state.back_stack.len())?;
+                match &state.connection_action {
+                    ConnectionAction::Scan {
+                        peripherals,
+                        paused,
+                    } => writeln!(
+                        out,
+                        "scanning: {} found, paused: {}",
+                        peripherals.len(),
+                        paused
+                    )?,
+                    ConnectionAction::Connect(statuses) => dump_connections(out, statuses)?,
+                    ConnectionAction::LocalOnly => writeln!(out, "local only")?,
+                }
+            }
+            Self::Playing(state) => {
+                writeln!(
+                    out,
+                    "Playing, {} players, tick {}",
+                    state.players, state.tick
+                )?;
+                writeln!(
+                    out,
+                    "policies: {} liberal, {} fascist",
+                    state.liberal_policies_placed, state.fascist_policies_placed
+                )?;
+                writeln!(out, "hitler: {:?}", state.hitler_state)?;
+                writeln!(
+                    out,
+                    "fail streak: {}, chaos pending: {}",
+                    state.election_fail_streak, state.chaos_policy_pending
+                )?;
+                writeln!(out, "pending: {:?}", state.pending_action)?;
+                writeln!(out, "misplaced: {:?}", state.misplacement)?;
+                writeln!(out, "screen: {:?}", state.screen)?;
+                writeln!(
+                    out,
+                    "link degraded: {}, sync pending: {}, local only: {}",
+                    state.link_degraded, state.sync_pending, state.local_only
+                )?;
+                dump_connections(out, &state.connection_statuses)?;
+                writeln!(out, "notes: {:?}", state.investigations)?;
+                write!(out, "cards: liberal board")?;
+                dump_cards(out, &sorted_policy_cards(&state.detected_cards.liberal))?;
+                write!(out, ", fascist board")?;
+                dump_cards(out, &sorted_policy_cards(&state.detected_cards.fascist))?;
+                writeln!(out)?;
+            }
+        }
+        writeln!(out, "recent events:")?;
+        for entry in recent_events {
+            write!(out, "{}ms ", entry.timestamp_ms)?;
+            match entry.event() {
+                Ok(event) => dump_event(out, event)?,
+                Err(e) => writeln!(out, "{e:?}")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The ids of `cards` like `[L0, F3]`, for [`GameState::debug_dump`]
+fn dump_cards(out: &mut impl fmt::Write, cards: &[PolicyCardId]) -> fmt::Result {
+    write!(out, " [")?;
+    for (i, card) in cards.iter().enumerate() {
+        if i > 0 {
+            write!(out, ", ")?;
+        }
+        let team = match card.team {
+            Team::Liberal => 'L',
+            Team::Fascist => 'F',
+        };
+        write!(out, "{team}{}", card.id)?;
+    }
+    write!(out, "]")
+}
+
+/// One line for a recorded event, for [`GameState::debug_dump`]. Shorter than its `Debug`, so that more events fit.
+fn dump_event(out: &mut impl fmt::Write, event: RecordedEvent) -> fmt::Result {
+    match event {
+        RecordedEvent::Input(input) => writeln!(out, "{input:?}"),
+        RecordedEvent::PolicyCard {
+            board,
+            card,
+            present,
+        } => {
+            write!(
+                out,
+                "{} {:?} board",
+                if present { "placed on" } else { "removed from" },
+                board
+            )?;
+            dump_cards(out, &[card])?;
+            writeln!(out)
+        }
+        RecordedEvent::PeripheralFound(address) => {
+            writeln!(out, "found {}", BdAddrFmt(address.addr))
+        }
+        RecordedEvent::Connected(address) => writeln!(out, "connected {}", BdAddrFmt(address.addr)),
+        RecordedEvent::Disconnected(address) => {
+            writeln!(out, "disconnected {}", BdAddrFmt(address.addr))
+        }
+        RecordedEvent::SupplyLevel(level) => writeln!(out, "supply {level:?}"),
+    }
+}
+
+/// One line for each peripheral, for [`GameState::debug_dump`]
+fn dump_connections(out: &mut impl fmt::Write, statuses: &[ConnectionStatus]) -> fmt::Result {
+    for status in statuses {
+        writeln!(
+            out,
+            "{} {:?}: {:?} since {}",
+            BdAddrFmt(status.peripheral_address.addr),
+            status.role,
+            status.state,
+            status.since
+        )?;
+    }
+    Ok(())
+}
+
+/// Something that the game wants done outside of the game state
+#[derive(Debug, Clone, PartialEq, Eq, EnumCount)]
+pub enum GameEffect {
+    /// These peripherals were connected, and the user chose to disconnect from them.
+    /// The BLE driver must disconnect gracefully instead of just dropping the connection,
+    /// so that the peripherals know that they are not connected anymore.
+    Disconnect(heapless::Vec<BdAddr, MAX_PERIPHERALS>),
+    /// The user renamed a peripheral, so the known peripherals need to be saved to storage
+    SaveKnownPeripherals(heapless::Vec<KnownPeripheral, KNOWN_PERIPHERALS_SIZE>),
+    /// The settings changed and need to be saved to storage.
+    /// Saving should be debounced, like with [`DebouncedSave`].
+    SettingsChanged(Settings),
+    /// Connecting took longer than [`Settings::connect_timeout_ticks`], so we went back to scanning.
+    /// The BLE driver must stop connecting, and gracefully disconnect from these peripherals that did connect.
+    ConnectTimedOut(heapless::Vec<BdAddr, MAX_PERIPHERALS>),
+    /// A team won, so the game is over
+    GameCompleted(Team),
+    /// A new game started
+    GameStarted,
+    /// The user wants to restart both boards.
+    /// Pending writes must be saved, and the fascist board told to restart, before resetting, like with [`shutdown_central`].
+    RestartBoards,
+    /// A different screen is shown
+    RedrawScreen,
+    /// The user wants [`GameState::debug_dump`] to be logged
+    DebugDump,
+}
+
+impl GameEffect {
+    /// Critical effects must not be dropped, or else something would be out of sync or not saved.
+    /// Non-critical effects only tell the caller to update something that it could also check for itself.
+    pub fn is_critical(&self) -> bool {
+        !matches!(self, Self::RedrawScreen)
+    }
+
+    /// The command that tells the fascist board about this effect, for [`sync::SyncEngine::send_command`]
+    pub fn board_command(&self) -> Option<sync::BoardCommand> {
+        match self {
+            Self::GameCompleted(winner) => Some(sync::BoardCommand::GameOver { winner: *winner }),
+            Self::GameStarted => Some(sync::BoardCommand::NewGame),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
 pub enum BleAction {
-    Scan,
-    MaintainConnection(BdAddr),
+    /// BLE is unavailable, or scanning was paused
+    Off,
+    Scan(ScanPreset),
+    MaintainConnections(heapless::Vec<Address, MAX_PERIPHERALS>),
 }
 
+/// How hard to scan. The caller decides what scan interval and window each preset uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanPreset {
+    /// Finds peripherals quickly, for when someone is looking at the scanned peripherals
+    Aggressive,
+    /// Scans less of the time, which saves power and leaves more time for the NFC readers
+    LowPower,
+}
+
+/// How soon the policy cards are expected to change. The caller decides how often the NFC readers are polled for each one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanActivity {
+    /// A pending action or a recent change, so a change should be seen right away
+    High,
+    Normal,
+    /// Nothing changed for [`SCAN_ACTIVITY_LOW_TICKS`], so a change can be seen a bit later
+    Low,
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Input {
     Up,
     Down,
     Click,
+    /// Go back to the previous screen, same as clicking the back item
+    Back,
+    /// Two clicks close together. While playing, this dismisses the hint from any screen. It is ignored everywhere else.
+    DoubleClick,
 }
 
 // https://www.secrethitler.com/assets/Secret_Hitler_Rules.pdf
 pub const LIBERAL_POLICY_CARDS: usize = 6;
 pub const FASCIST_POLICY_CARDS: usize = 11;
 
+/// [`GameState::debug_dump`] writes less than this many bytes, so that it fits in a crash record or a fixed buffer
+pub const DEBUG_DUMP_LEN: usize = 1024;
+/// How many of the latest recorded events [`GameState::debug_dump`] lists
+pub const DEBUG_DUMP_EVENTS: usize = 8;
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum AuraLedColor {
     /// A blueish color for the liberal board and a reddish color for the fascist board, or something else if the theme is different
@@ -250,7 +1794,7 @@ pub enum AuraLedColor {
     FascistWin,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct LedsDisplay {
     pub aura_led_color: AuraLedColor,
     /// The number of liberal policy LEDs that are lit up
@@ -259,8 +1803,68 @@ pub struct LedsDisplay {
     pub fascist_policy_leds: usize,
     /// The number of election tracker LEDs that are lit up
     pub election_tracker_leds: usize,
+    /// The next failed election will cause chaos, so the last election tracker LED should blink
+    pub election_tracker_warning: bool,
+    /// The fascist board is disconnected, so the aura LEDs should blink to show that the game is out of sync
+    pub blink_aura: bool,
+    /// A policy card of the other team was just placed on this board, so its aura LEDs should blink.
+    /// This only lasts for [`MISPLACED_BLINK_TICKS`], even if the card is still there.
+    pub misplaced_board: Option<Team>,
+    /// The game is paused, so all LEDs should be at [`LedsDisplay::brightness`]
+    pub dimmed: bool,
+    /// From [`Settings::election_tracker_placement`], so that the fascist board knows it from the sync
+    pub election_tracker_placement: ElectionTrackerPlacement,
+    /// So that the fascist board can advertise it, see [`sync::fascist_adv_data`]
+    pub phase: sync::GamePhase,
+}
+
+impl LedsDisplay {
+    /// The number of election tracker LEDs that `board` should light up, which is 0 if it doesn't show the election tracker
+    pub fn election_tracker_leds_on(&self, board: Team) -> usize {
+        if self.election_tracker_placement.shows_on(board) {
+            self.election_tracker_leds
+        } else {
+            0
+        }
+    }
+
+    /// Like [`LedsDisplay::election_tracker_warning`], but only for the boards that show the election tracker
+    pub fn election_tracker_warning_on(&self, board: Team) -> bool {
+        self.election_tracker_warning && self.election_tracker_placement.shows_on(board)
+    }
+    /// [`Settings::led_brightness`], or a quarter of it while dimmed
+    pub fn brightness(&self, led_brightness: u8) -> u8 {
+        if self.dimmed {
+            // Stays on, so that it doesn't look like the board turned off
+            (led_brightness / 4).max(led_brightness.min(1))
+        } else {
+            led_brightness
+        }
+    }
+}
+
+/// Which board's LEDs show the election tracker.
+/// The first PCBs only have the election tracker LEDs on the liberal board, and newer fascist board PCBs have them too.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ElectionTrackerPlacement {
+    #[default]
+    Liberal,
+    Fascist,
+    Both,
 }
 
+impl ElectionTrackerPlacement {
+    pub fn shows_on(self, board: Team) -> bool {
+        match self {
+            Self::Liberal => board == Team::Liberal,
+            Self::Fascist => board == Team::Fascist,
+            Self::Both => true,
+        }
+    }
+}
+
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub enum Team {
     Liberal,
@@ -274,22 +1878,81 @@ pub struct PolicyCardId {
     pub id: usize,
 }
 
+/// A policy card that was placed on the other team's board
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Misplacement {
+    pub card: PolicyCardId,
+    /// The board that the card was placed on
+    pub board: Team,
+}
+
+impl Misplacement {
+    pub fn warning(&self) -> &'static str {
+        match self.card.team {
+            Team::Liberal => labels::MISPLACED_LIBERAL_POLICY,
+            Team::Fascist => labels::MISPLACED_FASCIST_POLICY,
+        }
+    }
+}
+
 pub const LIBERAL_BOARD_SLOTS: usize = 5;
+/// The number of failed elections in a row that causes the top policy to be enacted
+pub const ELECTION_FAILS_FOR_CHAOS: usize = 3;
 pub const FASCIST_BOARD_SLOTS: usize = 6;
 
 /// Due to how close the NFC readers are to each other, we do not have 100% confident detection of which slot a policy was placed in.
 /// But we can be sure about exactly which policy cards are placed on each board.  
 ///
 /// Note that players can physically place policy cards on the wrong board, such as placing a liberal policy on the fascist board.
-#[derive(Debug, Clone)]
+///
+/// The order that the cards were detected in doesn't matter when comparing.
+/// Use [`DetectedPolicyCards::iter_sorted`] to show them, since the sets iterate in the order that the cards were inserted.
+#[derive(Clone, Default, PartialEq)]
 pub struct DetectedPolicyCards {
     // FnvIndexSet requires a power of two for the capacity
     pub liberal: FnvIndexSet<PolicyCardId, { LIBERAL_BOARD_SLOTS.next_power_of_two() }>,
     pub fascist: FnvIndexSet<PolicyCardId, { FASCIST_BOARD_SLOTS.next_power_of_two() }>,
 }
 
+// FnvIndexSet compares as a set, but doesn't implement `Eq` itself
+impl Eq for DetectedPolicyCards {}
+
+impl DetectedPolicyCards {
+    /// Every card with the board that it is on, the liberal board first.
+    /// The cards on each board are sorted like [`sorted_policy_cards`].
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (Team, PolicyCardId)> {
+        sorted_policy_cards(&self.liberal)
+            .into_iter()
+            .map(|card| (Team::Liberal, card))
+            .chain(
+                sorted_policy_cards(&self.fascist)
+                    .into_iter()
+                    .map(|card| (Team::Fascist, card)),
+            )
+    }
+}
+
+/// Sorted so that the cards don't move around every time they are scanned in a different order
+impl fmt::Debug for DetectedPolicyCards {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("DetectedPolicyCards")
+            .field("liberal", &sorted_policy_cards(&self.liberal))
+            .field("fascist", &sorted_policy_cards(&self.fascist))
+            .finish()
+    }
+}
+
+/// The cards sorted by team and then id, no matter which order they were inserted in
+pub fn sorted_policy_cards<const N: usize>(
+    cards: &FnvIndexSet<PolicyCardId, N>,
+) -> heapless::Vec<PolicyCardId, N> {
+    let mut sorted = cards.iter().copied().collect::<heapless::Vec<_, N>>();
+    sorted.sort_unstable();
+    sorted
+}
+
 #[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SecretRole {
     /// There are up to 6 liberals
     Liberal,
@@ -299,8 +1962,8 @@ pub enum SecretRole {
     Hitler,
 }
 
-#[cfg_attr(feature = "defmt", derive(defmt::Format))]
-#[derive(Debug)]
+/// Formats as `Hitler#0`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct CharacterCardId {
     pub secret_role: SecretRole,
     pub id: usize,
@@ -308,92 +1971,283 @@ pub struct CharacterCardId {
 
 impl GameState {
     pub fn ble_action(&self) -> BleAction {
+        if self.local_only()
+            || matches!(
+                self,
+                Self::SettingUp(GameStateSettingUp {
+                    connection_action: ConnectionAction::Scan { paused: true, .. },
+                    ..
+                })
+            )
+        {
+            return BleAction::Off;
+        }
+        match self.ble_connection_statuses() {
+            Some(statuses) => BleAction::MaintainConnections(
+                statuses
+                    .iter()
+                    .map(|status| status.peripheral_address)
+                    .collect(),
+            ),
+            None => BleAction::Scan(self.scan_preset()),
+        }
+    }
+
+    /// Scanning uses less power once the scanning screen has been left alone for [`SCAN_IDLE_TICKS`].
+    /// Any input switches back to scanning aggressively.
+    fn scan_preset(&self) -> ScanPreset {
+        match self {
+            Self::SettingUp(state)
+                if matches!(
+                    state.screen,
+                    GameScreen::Bluetooth(BluetoothScreen::Scanning { .. })
+                ) && state.tick.saturating_sub(state.last_input_tick) >= SCAN_IDLE_TICKS =>
+            {
+                ScanPreset::LowPower
+            }
+            _ => ScanPreset::Aggressive,
+        }
+    }
+
+    /// The peripheral that the sync protocol runs with, while connecting or connected to it
+    pub fn fascist_board_address(&self) -> Option<Address> {
+        fascist_board_address(self.ble_connection_statuses()?)
+    }
+
+    fn ble_connection_statuses(&self) -> Option<&[ConnectionStatus]> {
         match self {
             Self::SettingUp(state) => match &state.connection_action {
-                ConnectionAction::Scan { peripherals: _ } => BleAction::Scan,
-                ConnectionAction::Connect(status) => {
-                    BleAction::MaintainConnection(status.peripheral_address)
-                }
+                ConnectionAction::Connect(statuses) => Some(statuses),
+                ConnectionAction::Scan { .. } | ConnectionAction::LocalOnly => None,
             },
-            Self::Playing(state) => {
-                BleAction::MaintainConnection(state.connection_status.peripheral_address)
-            }
+            Self::Playing(state) => Some(&state.connection_statuses),
         }
     }
 
-    fn ble_connection_status_mut(&mut self) -> Option<&mut ConnectionStatus> {
+    /// `None` while scanning, since a connection update can still be queued after timing out or cancelling
+    fn ble_connection_status_mut(&mut self, address: Address) -> Option<&mut ConnectionStatus> {
         match self {
             Self::SettingUp(state) => match &mut state.connection_action {
-                ConnectionAction::Connect(status) => Some(status),
-                ConnectionAction::Scan { peripherals: _ } => None,
+                ConnectionAction::Connect(statuses) => Some(statuses),
+                ConnectionAction::Scan { .. } | ConnectionAction::LocalOnly => None,
             },
-            Self::Playing(state) => Some(&mut state.connection_status),
-        }
+            Self::Playing(state) => Some(&mut state.connection_statuses),
+        }?
+        .iter_mut()
+        .find(|status| status.peripheral_address == address)
     }
 
-    pub fn ble_connected(&mut self) {
-        self.ble_connection_status_mut()
-            .expect("game should be trying to maintain a connection and not be scanning")
-            .state = ConnectState::Connected;
+    /// `address` is the peripheral that connected. `tick` is the same as in [`GameState::tick`].
+    pub fn ble_connected(&mut self, address: Address, tick: u64) {
+        if self.local_only() {
+            log_warn!(
+                "Ignoring connection to {} because BLE is unavailable",
+                BdAddrFmt(address.addr)
+            );
+            return;
+        }
+        match self.ble_connection_status_mut(address) {
+            Some(status) => {
+                status.state = ConnectState::Connected;
+                status.since = tick;
+            }
+            None => {
+                log_warn!(
+                    "Connected to {} which we are not trying to connect to",
+                    BdAddrFmt(address.addr)
+                );
+                return;
+            }
+        }
+        if let Self::SettingUp(state) = self
+            && state.auto_start_pending
+            && state.connection_action.ready_to_start().is_some()
+        {
+            state.auto_start_pending = false;
+            state.back_stack.clear();
+            state.screen = GameScreen::AutoStartCountdown {
+                remaining_ticks: AUTO_START_COUNTDOWN_TICKS,
+            };
+            state.effects.push(GameEffect::RedrawScreen);
+        }
+        if let Self::Playing(state) = self
+            && all_connected(&state.connection_statuses)
+        {
+            if state.link_degraded {
+                // The hint was held back, so it is shown for the full time again
+                state.hint_since = state.tick;
+            }
+            state.link_degraded = false;
+            // The fascist board may have missed updates (or restarted) while it was disconnected
+            state.sync_pending = true;
+        }
     }
 
-    pub fn ble_disconnected(&mut self) {
-        self.ble_connection_status_mut()
-            .expect("game should be trying to maintain a connection and not be scanning")
-            .state = ConnectState::Connecting;
+    /// `address` is the peripheral that disconnected. `tick` is the same as in [`GameState::tick`].
+    pub fn ble_disconnected(&mut self, address: Address, tick: u64) {
+        if self.local_only() {
+            log_warn!(
+                "Ignoring disconnection from {} because BLE is unavailable",
+                BdAddrFmt(address.addr)
+            );
+            return;
+        }
+        match self.ble_connection_status_mut(address) {
+            Some(status) => {
+                status.state = ConnectState::Connecting;
+                status.since = tick;
+            }
+            None => {
+                log_warn!(
+                    "Disconnected from {} which we are not connected to",
+                    BdAddrFmt(address.addr)
+                );
+                return;
+            }
+        }
+        match self {
+            Self::SettingUp(state) => {
+                if matches!(state.screen, GameScreen::AutoStartCountdown { .. }) {
+                    state.cancel_auto_start();
+                }
+            }
+            Self::Playing(state) => state.link_degraded = true,
+        }
     }
 
-    pub fn ble_peripheral_found(&mut self, address: BdAddr) {
+    /// Returns the LEDs that the fascist board should display if the fascist board needs to be updated.
+    /// While the fascist board is disconnected, this returns `None` and updates are held back
+    /// until it reconnects, at which point a single up-to-date sync is returned.
+    pub fn take_sync(&mut self) -> Option<LedsDisplay> {
         match self {
-            Self::SettingUp(state) => match &mut state.connection_action {
-                ConnectionAction::Scan { peripherals } => {
-                    if !peripherals.contains(&address) {
-                        if let Err(address) = peripherals.push(address) {
-                            #[cfg(feature = "defmt")]
-                            defmt::warn!(
-                                "Failed to push address {} to list of scanned peripherals because the list is full. Consider rebuilding with a larger max size.",
-                                address
-                            );
-                        }
-                    }
+            Self::Playing(state) => {
+                if state.sync_pending
+                    && !state.local_only()
+                    && all_connected(&state.connection_statuses)
+                {
+                    state.sync_pending = false;
+                    Some(self.get_leds())
+                } else {
+                    None
                 }
-                ConnectionAction::Connect(_) => {
-                    unreachable!("this function must be called while scanning");
+            }
+            Self::SettingUp(state) => {
+                if state.sync_pending
+                    && matches!(&state.connection_action, ConnectionAction::Connect(statuses) if all_connected(statuses))
+                {
+                    state.sync_pending = false;
+                    Some(self.get_leds())
+                } else {
+                    None
                 }
-            },
-            Self::Playing(_) => unreachable!("this function must be called while scanning"),
+            }
         }
     }
 
-    pub fn process_input(&mut self, input: Input) {
-        match self {
-            Self::SettingUp(state) => match &mut state.screen {
-                GameScreen::MainMenu(screen) => match input {
-                    Input::Click => match MainMenuSelectedItem::VARIANTS[screen.selected_item] {
-                        MainMenuSelectedItem::StartGame => match &state.connection_action {
-                            ConnectionAction::Connect(connection_status) => {
-                                *self = GameState::Playing(GameStatePlaying {
-                                    players: 10, // TODO: Configure this in settings
-                                    connection_status: *connection_status,
-                                    liberal_policies_placed: 0,
-                                    fascist_policies_placed: 0,
-                                    hitler_state: HitlerState::Secret,
-                                    election_fail_streak: 0,
-                                    pending_action: false,
-                                });
-                            }
-                            ConnectionAction::Scan { peripherals: _ } => {
-                                state.screen = GameScreen::Bluetooth(BluetoothScreen::Scanning {
-                                    scroll_y: 0, // TODO: make sure it's visible
-                                    selected_item: ScanningSelectedItem::Title as usize,
-                                });
-                            }
+    /// The same address can be found more than once, and is only listed once.
+    /// A saved peripheral that is listed before it is found is marked as in range instead of being listed again.
+    pub fn ble_peripheral_found(&mut self, address: Address) {
+        self.ble_bonded_peripheral_found(address, |_| None);
+    }
+
+    /// Like [`GameState::ble_peripheral_found`], but a bonded peripheral is listed with its identity address
+    /// and marked as known. `identity` returns the identity address of the bonded peripheral that an address belongs to,
+    /// which resolving a private address needs the peripheral's IRK for.
+    /// Its resolvable private addresses that were listed before are merged into that entry,
+    /// since connecting to an old private address fails. Other addresses stay distinct by their kind and bytes.
+    pub fn ble_bonded_peripheral_found(
+        &mut self,
+        address: Address,
+        identity: impl Fn(Address) -> Option<Address>,
+    ) {
+        if self.local_only() {
+            log_warn!(
+                "Ignoring scanned peripheral {} because BLE is unavailable",
+                BdAddrFmt(address.addr)
+            );
+            return;
+        }
+        match self {
+            Self::SettingUp(state) => match &mut state.connection_action {
+                ConnectionAction::Scan { peripherals, .. } => {
+                    let found_identity = identity(address);
+                    let same = |peripheral: &ScannedPeripheral| {
+                        peripheral.address == address
+                            || found_identity.is_some()
+                                && identity(peripheral.address) == found_identity
+                    };
+                    // The selection follows the selected peripheral
+                    let selected_item = match &mut state.screen {
+                        GameScreen::Bluetooth(BluetoothScreen::Scanning {
+                            selected_item, ..
+                        }) => Some(selected_item),
+                        _ => None,
+                    };
+                    let mut selected = selected_item.as_ref().and_then(|selected_item| {
+                        selected_item.checked_sub(ScanningSelectedItem::VARIANTS.len())
+                    });
+                    peripherals.insert_or_refresh(
+                        ScannedPeripheral {
+                            address: found_identity.unwrap_or(address),
+                            role: None,
+                            known: found_identity.is_some(),
+                            in_range: true,
                         },
-                        MainMenuSelectedItem::Bluetooth => {
-                            state.screen = GameScreen::Bluetooth(BluetoothScreen::Scanning {
-                                scroll_y: 0, // TODO: make sure it's visible
-                                selected_item: ScanningSelectedItem::Title as usize,
-                            });
+                        same,
+                        &mut selected,
+                    );
+                    if let (Some(selected_item), Some(selected)) = (selected_item, selected) {
+                        *selected_item = ScanningSelectedItem::VARIANTS.len() + selected;
+                    }
+                }
+                // A scan result can still be queued after choosing a peripheral
+                ConnectionAction::Connect(_) | ConnectionAction::LocalOnly => log_warn!(
+                    "Ignoring scanned peripheral {} because we are not scanning",
+                    BdAddrFmt(address.addr)
+                ),
+            },
+            Self::Playing(_) => log_warn!(
+                "Ignoring scanned peripheral {} because we are not scanning",
+                BdAddrFmt(address.addr)
+            ),
+        }
+    }
+
+    /// Pushes anything that needs to be done outside of the game state to the effect queue
+    pub fn process_input(&mut self, input: Input) {
+        // The input only dismisses the popup, so that it can't change the game by accident
+        if self.supply().popup {
+            self.supply_mut().popup = false;
+            self.effects_mut().push(GameEffect::RedrawScreen);
+            return;
+        }
+        if let Self::SettingUp(state) = self {
+            state.last_input_tick = state.tick;
+            state.auto_start_pending = false;
+        }
+        match self {
+            Self::SettingUp(state) => match &mut state.screen {
+                GameScreen::MainMenu(screen) => match input {
+                    Input::Click => match checked_variant(screen.selected_item) {
+                        MainMenuSelectedItem::StartGame => {
+                            match state.start_game() {
+                                Some(playing) => {
+                                    *self = GameState::Playing(playing);
+                                    self.effects_mut().push(GameEffect::RedrawScreen);
+                                }
+                                // Show the user what they need to do before they can start the game
+                                None => state.navigate_to_bluetooth(),
+                            }
+                        }
+                        MainMenuSelectedItem::Bluetooth => state.navigate_to_bluetooth(),
+                        MainMenuSelectedItem::RestartBoards => {
+                            state.effects.push(GameEffect::RestartBoards);
+                        }
+                        MainMenuSelectedItem::About => {
+                            state.navigate_to(GameScreen::About(AboutScreen {
+                                scroll_y: 0,
+                                selected_item: 0,
+                            }));
                         }
                     },
                     Input::Down => {
@@ -407,43 +2261,64 @@ impl GameState {
                         screen.selected_item = screen.selected_item.saturating_sub(1);
                         // TODO: Adjust scroll
                     }
+                    Input::Back => state.navigate_back(),
+                    Input::DoubleClick => {}
                 },
                 GameScreen::Bluetooth(BluetoothScreen::Scanning {
-                    scroll_y,
+                    scroll_y: _,
                     selected_item,
                 }) => {
-                    let peripherals = match &state.connection_action {
-                        ConnectionAction::Scan { peripherals } => peripherals,
-                        ConnectionAction::Connect(_) => unreachable!(),
+                    let (peripherals, paused) = match &mut state.connection_action {
+                        ConnectionAction::Scan {
+                            peripherals,
+                            paused,
+                        } => (peripherals, paused),
+                        ConnectionAction::Connect(_) | ConnectionAction::LocalOnly => {
+                            unreachable!()
+                        }
                     };
                     match input {
                         Input::Click => {
                             if *selected_item < ScanningSelectedItem::VARIANTS.len() {
-                                match ScanningSelectedItem::VARIANTS[*selected_item] {
-                                    ScanningSelectedItem::Back => {
-                                        state.screen = GameScreen::MainMenu(MainMenuScreen {
-                                            scroll_y: {
-                                                // TODO: Make sure it's visible
-                                                0
-                                            },
-                                            selected_item: MainMenuSelectedItem::Bluetooth as usize,
-                                        });
+                                match checked_variant(*selected_item) {
+                                    ScanningSelectedItem::Back => state.navigate_back(),
+                                    ScanningSelectedItem::Title => {
+                                        *paused = !*paused;
+                                        state.effects.push(GameEffect::RedrawScreen);
+                                    }
+                                    ScanningSelectedItem::Connect => {
+                                        let connection_statuses = peripherals
+                                            .iter()
+                                            .filter_map(|peripheral| {
+                                                Some(ConnectionStatus {
+                                                    peripheral_address: peripheral.address,
+                                                    role: peripheral.role?,
+                                                    state: ConnectState::Connecting,
+                                                    since: state.tick,
+                                                })
+                                            })
+                                            .collect::<heapless::Vec<_, MAX_PERIPHERALS>>();
+                                        if connection_statuses.iter().any(|status| {
+                                            status.role == PeripheralRole::FascistBoard
+                                        }) {
+                                            state.connection_action =
+                                                ConnectionAction::Connect(connection_statuses);
+                                            state.last_bluetooth_screen = None;
+                                            // This is the same Bluetooth screen, so we replace it instead of navigating to it
+                                            state.screen = GameScreen::Bluetooth(
+                                                BluetoothScreen::new(&state.connection_action),
+                                            );
+                                            state.effects.push(GameEffect::RedrawScreen);
+                                        } else {
+                                            log_warn!(
+                                                "Not connecting because no fascist board was chosen"
+                                            );
+                                        }
                                     }
-                                    ScanningSelectedItem::Title => {}
                                 }
                             } else {
-                                state.connection_action =
-                                    ConnectionAction::Connect(ConnectionStatus {
-                                        peripheral_address: peripherals
-                                            [*selected_item - ScanningSelectedItem::VARIANTS.len()],
-                                        state: ConnectState::Connecting,
-                                    });
-                                state.screen =
-                                    GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected {
-                                        scroll_y: 0, // TODO: make sure it's visible
-                                        selected_item: ConnectingConnectedSelectedItem::Title
-                                            as usize,
-                                    });
+                                let index = *selected_item - ScanningSelectedItem::VARIANTS.len();
+                                peripherals[index].role = next_role(peripherals, index);
                             }
                         }
                         Input::Down => {
@@ -456,57 +2331,249 @@ impl GameState {
                             *selected_item = selected_item.saturating_sub(1);
                             // TODO: Make sure it's visible
                         }
+                        Input::Back => state.navigate_back(),
+                        Input::DoubleClick => {}
                     }
                 }
                 GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected {
-                    scroll_y,
+                    scroll_y: _,
                     selected_item,
                 }) => {
+                    let statuses = match &state.connection_action {
+                        ConnectionAction::Connect(statuses) => statuses,
+                        ConnectionAction::Scan { .. } | ConnectionAction::LocalOnly => {
+                            unreachable!()
+                        }
+                    };
                     match input {
-                        Input::Click => {
-                            match ConnectingConnectedSelectedItem::VARIANTS[*selected_item] {
-                                ConnectingConnectedSelectedItem::Back => {
-                                    state.screen = GameScreen::MainMenu(MainMenuScreen {
-                                        scroll_y: {
-                                            // TODO: Make sure it's visible
-                                            0
-                                        },
-                                        selected_item: MainMenuSelectedItem::Bluetooth as usize,
-                                    });
+                        Input::Click
+                            if *selected_item
+                                >= ConnectingConnectedSelectedItem::VARIANTS.len() =>
+                        {
+                            let address = statuses
+                                [*selected_item - ConnectingConnectedSelectedItem::VARIANTS.len()]
+                            .peripheral_address
+                            .addr;
+                            let name = state
+                                .peripheral_name(address)
+                                .unwrap_or_default()
+                                .try_into()
+                                .unwrap_or_default();
+                            state.navigate_to(GameScreen::TextEntry(TextEntryScreen::new(
+                                TextEntryPurpose::RenamePeripheral(address),
+                                name,
+                            )));
+                        }
+                        Input::Click => match checked_variant(*selected_item) {
+                            ConnectingConnectedSelectedItem::Back => state.navigate_back(),
+                            ConnectingConnectedSelectedItem::Title => {
+                                if all_connected(statuses) {
+                                    state.navigate_to(GameScreen::Bluetooth(
+                                        BluetoothScreen::ConnectionDetails,
+                                    ));
+                                } else {
+                                    state.retry_connecting();
                                 }
-                                ConnectingConnectedSelectedItem::Title => {}
-                                ConnectingConnectedSelectedItem::Cancel => {
-                                    state.connection_action = ConnectionAction::Scan {
-                                        peripherals: Default::default(),
-                                    };
-                                    state.screen =
-                                        GameScreen::Bluetooth(BluetoothScreen::Scanning {
-                                            scroll_y: 0,
-                                            selected_item: 0,
-                                        });
+                            }
+                            ConnectingConnectedSelectedItem::Cancel => {
+                                let connected_peripherals = state.stop_connecting();
+                                if !connected_peripherals.is_empty() {
+                                    state
+                                        .effects
+                                        .push(GameEffect::Disconnect(connected_peripherals));
                                 }
                             }
-                        }
+                        },
                         Input::Down => {
-                            *selected_item = selected_item
-                                .saturating_add(1)
-                                .min(ConnectingConnectedSelectedItem::VARIANTS.len() - 1);
+                            *selected_item = selected_item.saturating_add(1).min(
+                                ConnectingConnectedSelectedItem::VARIANTS.len() + statuses.len()
+                                    - 1,
+                            );
                             // TODO: adjust scroll
                         }
                         Input::Up => {
                             *selected_item = selected_item.saturating_sub(1);
                             // TODO: adjust scroll
                         }
+                        Input::Back => state.navigate_back(),
+                        Input::DoubleClick => {}
                     }
                 }
+                GameScreen::Bluetooth(
+                    BluetoothScreen::Unavailable | BluetoothScreen::ConnectionDetails,
+                ) => match input {
+                    Input::Click | Input::Back => state.navigate_back(),
+                    Input::Up | Input::Down | Input::DoubleClick => {}
+                },
+                GameScreen::TextEntry(screen) => match screen.process_input(input) {
+                    Some(TextEntryResult::Done) => {
+                        let TextEntryPurpose::RenamePeripheral(address) = screen.purpose;
+                        let name = screen.text.clone();
+                        state.set_peripheral_name(address, name);
+                        state.effects.push(GameEffect::SaveKnownPeripherals(
+                            state.known_peripherals.clone(),
+                        ));
+                        state.navigate_back();
+                    }
+                    Some(TextEntryResult::Cancelled) => state.navigate_back(),
+                    None => {}
+                },
+                GameScreen::About(screen) => match input {
+                    Input::Click => {
+                        if screen.selected_item == 0 {
+                            state.navigate_back();
+                        }
+                    }
+                    Input::Down => {
+                        screen.selected_item =
+                            screen.selected_item.saturating_add(1).min(ABOUT_LINES);
+                    }
+                    Input::Up => {
+                        screen.selected_item = screen.selected_item.saturating_sub(1);
+                    }
+                    Input::Back => state.navigate_back(),
+                    Input::DoubleClick => {}
+                },
+                GameScreen::AutoStartCountdown { .. } => state.cancel_auto_start(),
             },
             Self::Playing(state) => {
-                if state.pending_action
-                    && latest_action(state.players, state.fascist_policies_placed)
-                        .unwrap()
-                        .can_clear_with_button_press()
-                {
-                    state.pending_action = false;
+                match state.screen {
+                    PlayingScreen::Board => match input {
+                        Input::Click => state.show_screen(PlayingScreen::Menu {
+                            selected_item: PlayingMenuSelectedItem::DismissHint as usize,
+                        }),
+                        _ => state.dismiss_hint(),
+                    },
+                    PlayingScreen::Menu { selected_item } => match input {
+                        Input::Up => {
+                            state.screen = PlayingScreen::Menu {
+                                selected_item: selected_item.saturating_sub(1),
+                            };
+                        }
+                        Input::Down => {
+                            state.screen = PlayingScreen::Menu {
+                                selected_item: selected_item
+                                    .saturating_add(1)
+                                    .min(state.menu_items().len() - 1),
+                            };
+                        }
+                        Input::Click => match checked_variant(selected_item) {
+                            PlayingMenuSelectedItem::DismissHint => {
+                                state.show_screen(PlayingScreen::Board);
+                                state.dismiss_hint();
+                            }
+                            PlayingMenuSelectedItem::Pause => state.set_paused(true),
+                            PlayingMenuSelectedItem::AdjustPlayers => {
+                                state.show_screen(PlayingScreen::AdjustPlayers {
+                                    players: state.players,
+                                });
+                            }
+                            PlayingMenuSelectedItem::ReaderDebug => {
+                                state.reader_debug = !state.reader_debug;
+                                state.show_screen(PlayingScreen::Board);
+                            }
+                            PlayingMenuSelectedItem::DebugDump => {
+                                state.effects.push(GameEffect::DebugDump);
+                                state.show_screen(PlayingScreen::Board);
+                            }
+                            PlayingMenuSelectedItem::EndGame => {
+                                state.show_screen(PlayingScreen::ConfirmEndGame {
+                                    selected_item: EndGameSelectedItem::KeepPlaying as usize,
+                                });
+                            }
+                            PlayingMenuSelectedItem::Notes => {
+                                state.show_screen(PlayingScreen::Notes)
+                            }
+                        },
+                        Input::Back => state.show_screen(PlayingScreen::Board),
+                        Input::DoubleClick => state.double_click(),
+                    },
+                    PlayingScreen::ConfirmEndGame { selected_item } => match input {
+                        Input::Up => {
+                            state.screen = PlayingScreen::ConfirmEndGame {
+                                selected_item: selected_item.saturating_sub(1),
+                            };
+                        }
+                        Input::Down => {
+                            state.screen = PlayingScreen::ConfirmEndGame {
+                                selected_item: selected_item
+                                    .saturating_add(1)
+                                    .min(EndGameSelectedItem::VARIANTS.len() - 1),
+                            };
+                        }
+                        Input::Click => match checked_variant(selected_item) {
+                            EndGameSelectedItem::KeepPlaying => {
+                                state.show_screen(PlayingScreen::Board)
+                            }
+                            EndGameSelectedItem::EndGame => {
+                                *self = GameState::SettingUp(state.end_game());
+                            }
+                        },
+                        Input::Back => state.show_screen(PlayingScreen::Board),
+                        Input::DoubleClick => state.double_click(),
+                    },
+                    PlayingScreen::Paused => match input {
+                        Input::Click => state.set_paused(false),
+                        // Bumping the rotary encoder during the break shouldn't do anything
+                        Input::Up | Input::Down | Input::Back | Input::DoubleClick => {}
+                    },
+                    PlayingScreen::AdjustPlayers { players } => match input {
+                        Input::Up => {
+                            state.screen = PlayingScreen::AdjustPlayers {
+                                players: players.saturating_sub(1).max(state.min_players()),
+                            };
+                        }
+                        Input::Down => {
+                            state.screen = PlayingScreen::AdjustPlayers {
+                                players: players.saturating_add(1).min(*PLAYERS.end()),
+                            };
+                        }
+                        Input::Click => {
+                            state.show_screen(PlayingScreen::Board);
+                            // Only valid counts can be chosen
+                            let _ = self.adjust_player_count(players);
+                        }
+                        Input::Back => state.show_screen(PlayingScreen::Board),
+                        Input::DoubleClick => state.double_click(),
+                    },
+                    PlayingScreen::NoteEntry {
+                        policy_index,
+                        selected_item,
+                    } => match input {
+                        Input::Up => {
+                            state.screen = PlayingScreen::NoteEntry {
+                                policy_index,
+                                selected_item: selected_item.saturating_sub(1),
+                            };
+                        }
+                        Input::Down => {
+                            state.screen = PlayingScreen::NoteEntry {
+                                policy_index,
+                                selected_item: selected_item
+                                    .saturating_add(1)
+                                    .min(NoteEntrySelectedItem::VARIANTS.len() - 1),
+                            };
+                        }
+                        Input::Click => {
+                            match checked_variant(selected_item) {
+                                NoteEntrySelectedItem::Skip => {}
+                                NoteEntrySelectedItem::Liberal => {
+                                    state.record_investigation(policy_index, Team::Liberal)
+                                }
+                                NoteEntrySelectedItem::Fascist => {
+                                    state.record_investigation(policy_index, Team::Fascist)
+                                }
+                            }
+                            state.show_screen(PlayingScreen::Board);
+                        }
+                        Input::Back => state.show_screen(PlayingScreen::Board),
+                        Input::DoubleClick => state.double_click(),
+                    },
+                    PlayingScreen::Notes => match input {
+                        Input::Click | Input::Back => state.show_screen(PlayingScreen::Board),
+                        Input::DoubleClick => state.double_click(),
+                        Input::Up | Input::Down => {}
+                    },
                 }
             }
         }
@@ -514,11 +2581,17 @@ impl GameState {
 
     pub fn get_leds(&self) -> LedsDisplay {
         match self {
-            Self::SettingUp(_) => LedsDisplay {
+            Self::SettingUp(state) => LedsDisplay {
                 aura_led_color: AuraLedColor::BoardSpecific,
                 liberal_policy_leds: 0,
                 fascist_policy_leds: 0,
                 election_tracker_leds: 0,
+                election_tracker_warning: false,
+                blink_aura: false,
+                misplaced_board: None,
+                dimmed: state.supply.level == SupplyLevel::Critical,
+                election_tracker_placement: state.settings.election_tracker_placement,
+                phase: sync::GamePhase::Setup,
             },
             Self::Playing(state) => LedsDisplay {
                 aura_led_color: match state.winner() {
@@ -529,72 +2602,208 @@ impl GameState {
                 liberal_policy_leds: state.liberal_policies_placed,
                 fascist_policy_leds: state.fascist_policies_placed,
                 election_tracker_leds: state.election_fail_streak,
+                election_tracker_warning: state.failures_until_chaos() == 1,
+                blink_aura: state.link_degraded,
+                misplaced_board: state.misplaced_board_blinking(),
+                dimmed: state.paused() || state.supply.level == SupplyLevel::Critical,
+                election_tracker_placement: state.settings.election_tracker_placement,
+                phase: match state.winner() {
+                    Some(Team::Liberal) => sync::GamePhase::LiberalWin,
+                    Some(Team::Fascist) => sync::GamePhase::FascistWin,
+                    None => sync::GamePhase::Playing,
+                },
             },
         }
     }
 
-    pub fn screen(&self) -> Option<Screen<String, Vec<String>>> {
+    /// `runtime_info` is shown on the About screen
+    /// This doesn't allocate, so that redrawing doesn't fragment the heap
+    pub fn screen(
+        &self,
+        runtime_info: &RuntimeInfo,
+    ) -> Option<Screen<ScreenText, heapless::Vec<ScreenText, MAX_SCREEN_ITEMS>>> {
         match self {
             Self::SettingUp(state) => match state.screen {
                 GameScreen::MainMenu(MainMenuScreen {
-                    scroll_y,
+                    scroll_y: _,
                     selected_item,
                 }) => {
                     Some(Screen {
-                        title: "Setup".into(),
+                        title: screen_text("Setup"),
                         can_go_back: false,
                         items: MainMenuSelectedItem::VARIANTS
                             .iter()
                             .map(|item| {
-                                match item {
+                                screen_text(match item {
                                     MainMenuSelectedItem::StartGame => "Start Game",
                                     MainMenuSelectedItem::Bluetooth => "Bluetooth",
-                                }
-                                .into()
+                                    MainMenuSelectedItem::RestartBoards => labels::RESTART_BOARDS,
+                                    MainMenuSelectedItem::About => labels::ABOUT,
+                                })
                             })
                             .collect(),
-                        selected_item: SelectedItem::Item(0),
+                        selected_item: SelectedItem::Item(selected_item),
                     })
                     // None
                 }
-                GameScreen::Bluetooth(BluetoothScreen::Scanning {
-                    scroll_y,
-                    selected_item,
-                }) => Some(Screen {
-                    title: "Bluetooth".into(),
+                GameScreen::Bluetooth(BluetoothScreen::Scanning { .. }) => Some(Screen {
+                    title: screen_text(state.connection_action.scan_title()),
                     can_go_back: true,
                     items: match &state.connection_action {
-                        ConnectionAction::Scan { peripherals } => peripherals,
+                        ConnectionAction::Scan { peripherals, .. } => peripherals,
                         _ => unreachable!(),
                     }
                     .iter()
-                    .copied()
-                    .map(|addr| {
-                        Address {
-                            addr,
-                            kind: AddrKind::RANDOM,
-                        }
-                        .to_string()
-                    })
+                    .map(
+                        |peripheral| match state.peripheral_name(peripheral.address.addr) {
+                            Some(name) => screen_text(name),
+                            None => screen_text(&fmt_bd_addr(&peripheral.address.addr)),
+                        },
+                    )
                     .collect(),
                     selected_item: SelectedItem::Item(0),
                 }),
+                GameScreen::Bluetooth(BluetoothScreen::Unavailable) => Some(Screen {
+                    title: screen_text(labels::BLE_UNAVAILABLE),
+                    can_go_back: true,
+                    items: heapless::Vec::new(),
+                    selected_item: SelectedItem::Back,
+                }),
+                GameScreen::Bluetooth(BluetoothScreen::ConnectionDetails) => Some(Screen {
+                    title: screen_text(labels::CONNECTION_DETAILS),
+                    can_go_back: true,
+                    items: state.connection_details(state.tick).into_iter().collect(),
+                    selected_item: SelectedItem::Back,
+                }),
+                GameScreen::About(AboutScreen {
+                    scroll_y: _,
+                    selected_item,
+                }) => Some(Screen {
+                    title: screen_text(labels::ABOUT),
+                    can_go_back: true,
+                    items: runtime_info
+                        .about_lines()
+                        .iter()
+                        .map(|line| screen_text(line))
+                        .collect(),
+                    selected_item: match selected_item {
+                        0 => SelectedItem::Back,
+                        selected_item => SelectedItem::Item(selected_item - 1),
+                    },
+                }),
+                GameScreen::AutoStartCountdown { remaining_ticks } => Some(Screen {
+                    title: screen_text(labels::AUTO_START),
+                    can_go_back: false,
+                    items: [
+                        state.auto_start_text(remaining_ticks),
+                        screen_text(labels::CANCEL_AUTO_START),
+                    ]
+                    .into_iter()
+                    .collect(),
+                    selected_item: SelectedItem::Item(1),
+                }),
                 _ => None,
             },
-            _ => None,
+            Self::Playing(state) => match state.screen {
+                PlayingScreen::Board => None,
+                PlayingScreen::NoteEntry {
+                    policy_index: _,
+                    selected_item,
+                } => Some(Screen {
+                    title: screen_text(labels::NOTE_ENTRY_TITLE),
+                    can_go_back: true,
+                    items: NoteEntrySelectedItem::VARIANTS
+                        .iter()
+                        .map(|item| screen_text(item.label()))
+                        .collect(),
+                    selected_item: SelectedItem::Item(selected_item),
+                }),
+                PlayingScreen::Notes => Some(Screen {
+                    title: screen_text(labels::PRESIDENT_NOTES),
+                    can_go_back: true,
+                    items: state
+                        .investigations
+                        .iter()
+                        .map(|&(policy_index, team)| investigation_text(policy_index, team))
+                        .collect(),
+                    selected_item: SelectedItem::Back,
+                }),
+                PlayingScreen::Menu { selected_item } => Some(Screen {
+                    title: screen_text(labels::GAME_MENU_TITLE),
+                    can_go_back: true,
+                    items: state
+                        .menu_items()
+                        .iter()
+                        .map(|item| screen_text(item.label()))
+                        .collect(),
+                    selected_item: SelectedItem::Item(selected_item),
+                }),
+                PlayingScreen::ConfirmEndGame { selected_item } => Some(Screen {
+                    title: screen_text(labels::END_GAME_TITLE),
+                    can_go_back: true,
+                    items: EndGameSelectedItem::VARIANTS
+                        .iter()
+                        .map(|item| screen_text(item.label()))
+                        .collect(),
+                    selected_item: SelectedItem::Item(selected_item),
+                }),
+                PlayingScreen::Paused => None,
+                PlayingScreen::AdjustPlayers { players } => Some(Screen {
+                    title: screen_text(labels::ADJUST_PLAYERS_TITLE),
+                    can_go_back: true,
+                    items: [players_text(players)].into_iter().collect(),
+                    selected_item: SelectedItem::Item(0),
+                }),
+            },
+        }
+    }
+
+    /// If this returns `true`, you should continuously poll the NFC readers in the policy slots.
+    /// Policy cards only matter while a game is being played, and not after a team won or while it is paused
+    pub fn scan_policy_slots(&self) -> bool {
+        match self {
+            Self::SettingUp(_) => false,
+            Self::Playing(state) => state.winner().is_none() && !state.paused(),
         }
     }
 
-    /// If this returns `true`, you should use continuously poll NFC readers to detect policy cards and dead character cards.
-    pub fn should_scan_cards(&self) -> bool {
+    /// If this returns `true`, you should continuously poll the NFC reader in the dead character area.
+    /// A dead character card is only expected while a player needs to be killed
+    pub fn scan_dead_character(&self) -> bool {
         match self {
             Self::SettingUp(_) => false,
-            Self::Playing(_) => true,
+            Self::Playing(state) => {
+                state.winner().is_none()
+                    && !state.paused()
+                    && state.pending_action == PendingAction::Pending(FascistAction::Kill)
+            }
+        }
+    }
+
+    /// How often the NFC readers should be polled. It only lowers as [`GameState::tick`] is called,
+    /// and any change to the policy cards or a pending action makes it [`ScanActivity::High`] right away.
+    pub fn expected_scan_activity(&self) -> ScanActivity {
+        match self {
+            Self::Playing(state) if self.scan_policy_slots() => {
+                let idle_ticks = state.tick.saturating_sub(state.last_card_change_tick);
+                if state.pending_action != PendingAction::None
+                    || idle_ticks < SCAN_ACTIVITY_HIGH_TICKS
+                {
+                    ScanActivity::High
+                } else if idle_ticks < SCAN_ACTIVITY_LOW_TICKS {
+                    ScanActivity::Normal
+                } else {
+                    ScanActivity::Low
+                }
+            }
+            // Nothing needs to be scanned
+            _ => ScanActivity::Low,
         }
     }
 
     /// Completely replaces the previous list of detected policy cards with the new list.
     /// Caller should handle debouncing if necessary.
+    /// Scans that were already in flight when the game was paused are ignored.
     pub fn update_scanned_policy_cards(&mut self, cards: DetectedPolicyCards) {
         let state = match self {
             Self::Playing(state) => state,
@@ -602,8 +2811,28 @@ impl GameState {
                 unreachable!("should not care about scanned policy cards during setup")
             }
         };
-        // For now we will not care which board a policy is placed on.
-        // We will process it anyways
+        if state.paused() {
+            return;
+        }
+        let winner = state.winner();
+        // Policies are counted no matter which board they are placed on,
+        // but the players are warned to move misplaced policies to the correct board.
+        // Sorting keeps the same card from being warned about when the same cards are scanned in a different order.
+        let misplacement = cards
+            .iter_sorted()
+            .find(|(board, card)| card.team != *board)
+            .map(|(board, card)| Misplacement { card, board });
+        if misplacement.map(|misplacement| misplacement.board)
+            != state.misplacement.map(|misplacement| misplacement.board)
+        {
+            state.sync_pending = true;
+            state.misplaced_since = state.tick;
+        }
+        if misplacement != state.misplacement {
+            state.last_card_change_tick = state.tick;
+        }
+        state.misplacement = misplacement;
+
         let mut liberal_policies_placed = 0;
         let mut fascist_policies_placed = 0;
         for card in [cards.liberal.iter(), cards.fascist.iter()]
@@ -616,25 +2845,98 @@ impl GameState {
             } += 1;
         }
 
-        // Reset election tracker if any new policy was placed
-        let new_policy_card_placed = liberal_policies_placed > state.liberal_policies_placed
-            || fascist_policies_placed > state.fascist_policies_placed;
+        // A card that was taken off the board isn't counted anymore, so the next policy placed there is new
+        for (highest, placed, removed_since) in [
+            (
+                &mut state.highest_liberal_policies_placed,
+                state.liberal_policies_placed,
+                state.liberal_policies_removed_since,
+            ),
+            (
+                &mut state.highest_fascist_policies_placed,
+                state.fascist_policies_placed,
+                state.fascist_policies_removed_since,
+            ),
+        ] {
+            if removed_since
+                .is_some_and(|since| state.tick.saturating_sub(since) >= POLICY_REMOVED_TICKS)
+            {
+                *highest = placed;
+            }
+        }
+
+        // Reset election tracker if any new policy was placed.
+        // A card that comes back after flickering out isn't new.
+        let new_liberal_policy = liberal_policies_placed > state.highest_liberal_policies_placed;
+        let new_fascist_policy = fascist_policies_placed > state.highest_fascist_policies_placed;
+        let new_policy_card_placed = new_liberal_policy || new_fascist_policy;
         if new_policy_card_placed {
             state.election_fail_streak = 0;
         }
 
         // Clear the action hint if any new policy was placed
-        if liberal_policies_placed > state.liberal_policies_placed {
-            state.pending_action = false;
+        if new_liberal_policy {
+            state.pending_action = PendingAction::None;
         }
-        if fascist_policies_placed > state.fascist_policies_placed {
-            state.pending_action = latest_action(state.players, fascist_policies_placed).is_some();
+        if new_fascist_policy && !state.chaos_policy_pending {
+            state.pending_action = match latest_action(state.players, fascist_policies_placed) {
+                Some(action) => PendingAction::Pending(action),
+                None => PendingAction::None,
+            };
+            state.hint_since = state.tick;
+        }
+        if new_policy_card_placed {
+            state.chaos_policy_pending = false;
         }
 
         // TODO: Undo some stuff if a policy was removed. The only reason policies are removed is if they were placed on accident.
 
+        if liberal_policies_placed != state.liberal_policies_placed
+            || fascist_policies_placed != state.fascist_policies_placed
+        {
+            state.sync_pending = true;
+            state.last_card_change_tick = state.tick;
+        }
         state.liberal_policies_placed = liberal_policies_placed;
         state.fascist_policies_placed = fascist_policies_placed;
+        state.detected_cards = cards;
+        state.highest_liberal_policies_placed = state
+            .highest_liberal_policies_placed
+            .max(liberal_policies_placed);
+        state.highest_fascist_policies_placed = state
+            .highest_fascist_policies_placed
+            .max(fascist_policies_placed);
+        let tick = state.tick;
+        state.liberal_policies_removed_since = (liberal_policies_placed
+            < state.highest_liberal_policies_placed)
+            .then(|| state.liberal_policies_removed_since.unwrap_or(tick));
+        state.fascist_policies_removed_since = (fascist_policies_placed
+            < state.highest_fascist_policies_placed)
+            .then(|| state.fascist_policies_removed_since.unwrap_or(tick));
+        state.push_game_completed(winner);
+    }
+
+    /// Called when an election fails.
+    /// Returns `true` if there is chaos, which means that the top policy must be enacted.
+    pub fn record_failed_election(&mut self) -> bool {
+        let state = match self {
+            Self::Playing(state) => state,
+            Self::SettingUp(_) => {
+                unreachable!("there are no elections during setup")
+            }
+        };
+        state.election_fail_streak += 1;
+        state.sync_pending = true;
+        if state.election_fail_streak == ELECTION_FAILS_FOR_CHAOS {
+            // The election tracker is reset, and the president's power from the enacted policy is ignored.
+            // Any hint from before is outdated since a new policy will be placed.
+            state.election_fail_streak = 0;
+            state.chaos_policy_pending = true;
+            state.pending_action = PendingAction::None;
+            true
+        } else {
+            false
+        }
     }
 
     /// Whenever a character dies, the player scans their character card in the dead character area, and then removes their character card from the scan area.
@@ -647,60 +2949,342 @@ impl GameState {
                 unreachable!("should not care about scanned dead character cards during setup")
             }
         };
-        if latest_action(state.players, state.fascist_policies_placed) == Some(FascistAction::Kill)
-            && state.pending_action
-        {
-            match character.secret_role {
-                SecretRole::Hitler => {
-                    state.hitler_state = HitlerState::Dead;
-                }
-                _ => {}
+        // Cards that are bumped during a break don't kill anyone
+        if state.paused() {
+            return;
+        }
+        if state.pending_action == PendingAction::Pending(FascistAction::Kill) {
+            state.dead_players += 1;
+            if let SecretRole::Hitler = character.secret_role {
+                let winner = state.winner();
+                state.hitler_state = HitlerState::Dead;
+                state.sync_pending = true;
+                state.push_game_completed(winner);
             }
-            state.pending_action = false;
+            state.pending_action = PendingAction::None;
         } else {
-            #[cfg(feature = "defmt")]
-            defmt::warn!(
+            log_warn!(
                 "Processed dead character {} when no one should have been killed.",
                 character
             );
         }
     }
 
+    /// For when someone leaves in the middle of the game.
+    /// The pending hint is cleared if the new number of players doesn't have that presidential power at the current fascist policy.
+    pub fn adjust_player_count(&mut self, players: u8) -> Result<(), AdjustPlayersError> {
+        let state = match self {
+            Self::Playing(state) => state,
+            Self::SettingUp(_) => {
+                unreachable!("the number of players can only be adjusted while playing")
+            }
+        };
+        if !PLAYERS.contains(&players) {
+            return Err(AdjustPlayersError::NotCovered);
+        }
+        if players < state.min_players() {
+            return Err(AdjustPlayersError::TooFewAlive);
+        }
+        log_info!(
+            "Adjusting the number of players from {} to {}",
+            state.players,
+            players
+        );
+        state.players = players;
+        if let PendingAction::Pending(action) | PendingAction::Confirming(action, _) =
+            state.pending_action
+            && latest_action(players, state.fascist_policies_placed) != Some(action)
+        {
+            state.pending_action = PendingAction::None;
+            state.effects.push(GameEffect::RedrawScreen);
+        }
+        Ok(())
+    }
+
+    /// The hint is held back while the fascist board is disconnected, so that the screen can show that we are reconnecting.
     pub fn display_action_hint(&self) -> Option<FascistAction> {
         match self {
-            Self::Playing(state) => {
-                if state.pending_action {
-                    latest_action(state.players, state.fascist_policies_placed)
-                } else {
-                    None
+            Self::Playing(state) if !state.link_degraded => match state.pending_action {
+                PendingAction::None => None,
+                PendingAction::Pending(action) | PendingAction::Confirming(action, _) => {
+                    Some(action)
+                }
+            },
+            _ => None,
+        }
+    }
+
+    /// The text to show for [`GameState::display_action_hint`]
+    pub fn display_action_hint_text(&self) -> Option<&'static str> {
+        match self {
+            Self::Playing(state) if !state.link_degraded => match state.pending_action {
+                PendingAction::None => None,
+                PendingAction::Pending(action) => Some(action.hint()),
+                PendingAction::Confirming(FascistAction::ExamineTop3, _) => {
+                    Some(labels::CONFIRM_EXAMINE_TOP_3_HINT)
                 }
+                PendingAction::Confirming(action, _) => Some(action.hint()),
+            },
+            _ => None,
+        }
+    }
+
+    /// [`GameState::display_action_hint_text`] with the rule that it comes from
+    pub fn action_hint(&self) -> Option<ActionHint> {
+        match (
+            self,
+            self.display_action_hint(),
+            self.display_action_hint_text(),
+        ) {
+            (Self::Playing(state), Some(action), Some(text)) => Some(ActionHint {
+                action,
+                policy_index: state.fascist_policies_placed,
+                players: state.players,
+                text,
+            }),
+            _ => None,
+        }
+    }
+
+    /// How many ticks [`GameState::display_action_hint`] has been shown for
+    pub fn hint_age(&self, now_tick: u64) -> Option<u64> {
+        match self {
+            Self::Playing(state) if self.display_action_hint().is_some() => {
+                Some(now_tick.saturating_sub(state.hint_since))
             }
+            _ => None,
+        }
+    }
+
+    /// How old [`GameState::hint_age`] can get before the hint dismisses itself,
+    /// from [`Settings::hint_auto_dismiss_ticks`]. `None` if the hint stays until it's dismissed.
+    pub fn hint_auto_dismiss_ticks(&self) -> Option<u64> {
+        match self {
+            Self::Playing(state) => state.hint_auto_dismiss_ticks(),
             Self::SettingUp(_) => None,
         }
     }
+
+    /// Advances everything that happens after a deadline. `tick` is a counter from the caller that must never decrease.
+    /// The firmware counts `TICK_INTERVAL`s (1s) since boot, and every duration in [`Settings`] and the `_TICKS` constants is in the same unit.
+    ///
+    /// - The auto start countdown counts down, and starts the game when it reaches 0
+    /// - While setting up, connecting that takes longer than [`Settings::connect_timeout_ticks`] is given up on
+    /// - Scanning switches to [`ScanPreset::LowPower`] after [`SCAN_IDLE_TICKS`] without input
+    /// - A confirmation that isn't completed within [`CONFIRM_ACTION_TICKS`] is reverted
+    /// - Hints are dismissed after [`Settings::hint_auto_dismiss_ticks`]
+    ///
+    /// [`GameState::next_tick`] is when calling this will change something next.
+    pub fn tick(&mut self, tick: u64) -> TickOutcome {
+        let mut outcome = TickOutcome::default();
+        let connection_elapsed = self.connection_elapsed(tick);
+        match self {
+            Self::SettingUp(state) => {
+                let elapsed = tick.saturating_sub(state.tick);
+                state.tick = tick;
+                if let GameScreen::AutoStartCountdown { remaining_ticks } = &mut state.screen {
+                    *remaining_ticks =
+                        remaining_ticks.saturating_sub(elapsed.try_into().unwrap_or(u8::MAX));
+                    outcome.redraw = true;
+                    if *remaining_ticks > 0 {
+                        state.effects.push(GameEffect::RedrawScreen);
+                    } else {
+                        match state.start_game() {
+                            Some(playing) => {
+                                *self = GameState::Playing(playing);
+                                self.effects_mut().push(GameEffect::RedrawScreen);
+                            }
+                            None => state.cancel_auto_start(),
+                        }
+                        outcome.effects = true;
+                        return outcome;
+                    }
+                    outcome.effects = true;
+                }
+                let timeout = state.settings.connect_timeout_ticks;
+                if timeout != 0
+                    && connection_elapsed.is_some_and(|elapsed| elapsed >= timeout.into())
+                {
+                    log_warn!("Connecting timed out after {} ticks", timeout);
+                    let connected_peripherals = state.stop_connecting();
+                    state
+                        .effects
+                        .push(GameEffect::ConnectTimedOut(connected_peripherals));
+                    outcome.redraw = true;
+                    outcome.effects = true;
+                }
+            }
+            // The game has to go on, so we never give up on reconnecting
+            Self::Playing(state) => {
+                let misplaced_board = state.misplaced_board_blinking();
+                state.tick = tick;
+                // The fascist board stops blinking too
+                if state.misplaced_board_blinking() != misplaced_board {
+                    state.sync_pending = true;
+                }
+                if let PendingAction::Confirming(action, deadline) = state.pending_action
+                    && tick > deadline
+                {
+                    state.pending_action = PendingAction::Pending(action);
+                    outcome.redraw = true;
+                }
+                if let Some(timeout) = state.hint_auto_dismiss_ticks() {
+                    // The bar under the hint shrinks
+                    outcome.redraw = true;
+                    if tick.saturating_sub(state.hint_since) >= timeout {
+                        log_info!("Hint dismissed itself after {} ticks", timeout);
+                        state.pending_action = PendingAction::None;
+                        state.effects.push(GameEffect::RedrawScreen);
+                        outcome.effects = true;
+                    }
+                }
+            }
+        }
+        outcome
+    }
+
+    /// The earliest tick at which [`GameState::tick`] will change something, from the deadline of everything that is waiting.
+    /// It can be in the past if `tick` wasn't called in a while.
+    /// `None` if nothing is waiting, so `tick` only needs to be called after something else changes the state.
+    pub fn next_tick(&self) -> Option<u64> {
+        match self {
+            Self::SettingUp(state) => {
+                let timeout = state.settings.connect_timeout_ticks;
+                let connect_timeout = match &state.connection_action {
+                    ConnectionAction::Connect(statuses) if timeout != 0 => statuses
+                        .iter()
+                        .filter(|status| status.state == ConnectState::Connecting)
+                        .map(|status| status.since + u64::from(timeout))
+                        .min(),
+                    _ => None,
+                };
+                // Every tick, since the time spent connecting and the connection details count up
+                let connecting = matches!(
+                    &state.connection_action,
+                    ConnectionAction::Connect(statuses)
+                        if statuses.iter().any(|status| status.state == ConnectState::Connecting)
+                );
+                let counting_up = (connecting
+                    || matches!(
+                        state.screen,
+                        GameScreen::Bluetooth(BluetoothScreen::ConnectionDetails)
+                    ))
+                .then_some(state.tick + 1);
+                let countdown = matches!(state.screen, GameScreen::AutoStartCountdown { .. })
+                    .then_some(state.tick + 1);
+                let scan_idle =
+                    (matches!(self.ble_action(), BleAction::Scan(ScanPreset::Aggressive))
+                        && matches!(
+                            state.screen,
+                            GameScreen::Bluetooth(BluetoothScreen::Scanning { .. })
+                        ))
+                    .then_some(state.last_input_tick + SCAN_IDLE_TICKS);
+                [connect_timeout, counting_up, countdown, scan_idle]
+                    .into_iter()
+                    .flatten()
+                    .min()
+            }
+            Self::Playing(state) => {
+                let confirm_timeout = match state.pending_action {
+                    PendingAction::Confirming(_, deadline) => Some(deadline + 1),
+                    _ => None,
+                };
+                // Every tick, since the bar under the hint shrinks
+                let hint = state.hint_auto_dismiss_ticks().map(|_| state.tick + 1);
+                let misplaced_blink = state
+                    .misplaced_board_blinking()
+                    .map(|_| state.misplaced_since + MISPLACED_BLINK_TICKS);
+                [confirm_timeout, hint, misplaced_blink]
+                    .into_iter()
+                    .flatten()
+                    .min()
+            }
+        }
+    }
+
+    /// How many ticks we have been trying to connect for, counting from the peripheral that has been connecting the longest.
+    /// `None` if we aren't connecting to anything or everything is connected.
+    pub fn connection_elapsed(&self, now: u64) -> Option<u64> {
+        let statuses: &[ConnectionStatus] = match self {
+            Self::SettingUp(GameStateSettingUp {
+                connection_action: ConnectionAction::Connect(statuses),
+                ..
+            }) => statuses,
+            Self::SettingUp(_) => &[],
+            Self::Playing(state) => &state.connection_statuses,
+        };
+        statuses
+            .iter()
+            .filter(|status| status.state == ConnectState::Connecting)
+            .map(|status| status.elapsed(now))
+            .max()
+    }
+
+    /// If `true`, the caller should keep calling [`GameState::tick`] because something will change after a deadline
+    pub fn needs_ticks(&self) -> bool {
+        self.next_tick().is_some()
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use trouble_host::prelude::BdAddr;
+    extern crate std;
+
+    use core::{
+        alloc::{GlobalAlloc, Layout},
+        cell::Cell,
+    };
+
+    use alloc::vec::Vec;
+    use std::alloc::System;
+    use trouble_host::{
+        Address,
+        prelude::{AddrKind, BdAddr},
+    };
 
     use super::*;
 
+    std::thread_local! {
+        /// The number of allocations made by the current thread
+        static ALLOCATIONS: Cell<usize> = const { Cell::new(0) };
+    }
+
+    /// Counts allocations, so that tests can check that something doesn't allocate
+    struct CountingAllocator;
+
+    unsafe impl GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+            unsafe { System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+            unsafe { System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static ALLOCATOR: CountingAllocator = CountingAllocator;
+
     #[test]
     fn six_fascist_policies() {
-        let mut state = GameState::new(None);
+        let mut state = GameState::new(None, Default::default(), Default::default());
         // Enter bluetooth menu
         state.process_input(Input::Down);
         state.process_input(Input::Click);
 
         // Simulate a bluetooth device showing up
-        assert_eq!(state.ble_action(), BleAction::Scan);
-        let address = BdAddr::new([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
         state.ble_peripheral_found(address);
 
-        // Select that bluetooth device
+        // Choose that bluetooth device as the fascist board and connect to it
         state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
         state.process_input(Input::Click);
+        state.ble_connected(address, 0);
 
         // Go back to main menu
         state.process_input(Input::Up);
@@ -711,8 +3295,12 @@ mod tests {
         state.process_input(Input::Click);
 
         assert!(matches!(state, GameState::Playing(_)));
-        assert_eq!(state.ble_action(), BleAction::MaintainConnection(address));
-        assert_eq!(state.should_scan_cards(), true);
+        assert_eq!(
+            state.ble_action(),
+            BleAction::MaintainConnections([address].into_iter().collect())
+        );
+        assert!(state.scan_policy_slots());
+        assert!(!state.scan_dead_character());
 
         // A fascist policy is placed
         state.update_scanned_policy_cards(DetectedPolicyCards {
@@ -727,7 +3315,7 @@ mod tests {
         // The hint should show up
         assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
         // Manually dismiss the hint
-        state.process_input(Input::Click);
+        dismiss_hint(&mut state);
         assert_eq!(state.display_action_hint(), None);
 
         // A liberal policy is placed
@@ -771,7 +3359,7 @@ mod tests {
         // The hint should show up
         assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
         // Manually dismiss the hint
-        state.process_input(Input::Click);
+        dismiss_hint(&mut state);
         assert_eq!(state.display_action_hint(), None);
 
         // Liberal policy placed
@@ -840,7 +3428,7 @@ mod tests {
             Some(FascistAction::ChooseNextPresident)
         );
         // Manually dismiss the hint
-        state.process_input(Input::Click);
+        dismiss_hint(&mut state);
         assert_eq!(state.display_action_hint(), None);
 
         // Fascist policy placed
@@ -880,12 +3468,16 @@ mod tests {
         });
         // The hint should show up
         assert_eq!(state.display_action_hint(), Some(FascistAction::Kill));
+        // Only scan for a dead character while someone needs to be killed
+        assert!(state.scan_dead_character());
         // A liberal is killed
         state.process_dead_character(CharacterCardId {
             secret_role: SecretRole::Liberal,
             id: 0,
         });
         assert_eq!(state.display_action_hint(), None);
+        assert!(!state.scan_dead_character());
+        assert!(state.scan_policy_slots());
 
         // Fascist policy placed
         state.update_scanned_policy_cards(DetectedPolicyCards {
@@ -980,5 +3572,2486 @@ mod tests {
         });
         // Fascists win
         assert_eq!(state.get_leds().aura_led_color, AuraLedColor::FascistWin);
+        // Nothing needs to be scanned after the game is over
+        assert!(!state.scan_policy_slots());
+        assert!(!state.scan_dead_character());
+    }
+
+    #[test]
+    fn back_restores_previous_screen() {
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        // Enter bluetooth menu
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+
+        // Select a bluetooth device
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        state.ble_peripheral_found(address);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected { .. })
+        ));
+
+        // Going back twice should end up on the main menu with the Bluetooth item still selected
+        state.process_input(Input::Back);
+        state.process_input(Input::Back);
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::MainMenu(MainMenuScreen {
+                scroll_y: 0,
+                selected_item,
+            }) if selected_item == MainMenuSelectedItem::Bluetooth as usize
+        ));
+        assert!(setting_up.back_stack.is_empty());
+
+        // Going back on the main menu does nothing, so there is nothing to redraw
+        drain_effects(&mut state);
+        state.process_input(Input::Back);
+        assert!(drain_effects(&mut state).is_empty());
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::MainMenu(MainMenuScreen {
+                scroll_y: 0,
+                selected_item,
+            }) if selected_item == MainMenuSelectedItem::Bluetooth as usize
+        ));
+        assert!(setting_up.back_stack.is_empty());
+
+        // The Bluetooth screen should show the connection we already have
+        state.process_input(Input::Click);
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected { .. })
+        ));
+    }
+
+    #[test]
+    fn resync_after_reconnect() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.ble_connected(address, 0);
+        // Start the game
+        state.process_input(Input::Click);
+        assert!(matches!(state, GameState::Playing(_)));
+        assert!(state.take_sync().is_some());
+        assert!(state.take_sync().is_none());
+
+        state.ble_disconnected(address, 0);
+        let GameState::Playing(playing) = &state else {
+            panic!("should be playing");
+        };
+        assert!(playing.link_degraded());
+        assert!(state.get_leds().blink_aura);
+
+        // Policies placed on our board are still processed, but not synced yet
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [PolicyCardId {
+                team: Team::Liberal,
+                id: 0,
+            }]
+            .into_iter()
+            .collect(),
+            fascist: [].into_iter().collect(),
+        });
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [].into_iter().collect(),
+            fascist: [PolicyCardId {
+                team: Team::Fascist,
+                id: 0,
+            }]
+            .into_iter()
+            .collect(),
+        });
+        assert!(state.take_sync().is_none());
+        // The hint is held back while disconnected
+        assert_eq!(state.display_action_hint(), None);
+
+        // After reconnecting, there is exactly one sync with the latest state
+        state.ble_connected(address, 0);
+        let sync = state.take_sync().unwrap();
+        assert_eq!(sync.liberal_policy_leds, 0);
+        assert_eq!(sync.fascist_policy_leds, 1);
+        assert!(!sync.blink_aura);
+        assert!(state.take_sync().is_none());
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+    }
+
+    #[test]
+    fn public_address() {
+        let public = Address {
+            kind: AddrKind::PUBLIC,
+            addr: BdAddr::new([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]),
+        };
+        let random = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        // Enter bluetooth menu
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.ble_peripheral_found(public);
+        state.ble_peripheral_found(public);
+        // A different peripheral, even though the bytes are the same
+        state.ble_peripheral_found(random);
+        let GameState::SettingUp(GameStateSettingUp {
+            connection_action: ConnectionAction::Scan { peripherals, .. },
+            ..
+        }) = &state
+        else {
+            panic!("should be scanning");
+        };
+        assert_eq!(peripherals.len(), 2);
+
+        // Connect to the public one as the fascist board
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        assert_eq!(
+            state.ble_action(),
+            BleAction::MaintainConnections([public].into_iter().collect())
+        );
+        state.ble_connected(random, 0);
+        assert_eq!(log::take_warnings().len(), 1);
+        state.ble_connected(public, 0);
+        assert!(all_connected(state.ble_connection_statuses().unwrap()));
+    }
+
+    #[test]
+    fn bonded_peripheral_collapsed() {
+        let identity = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0xC5]);
+        // The resolvable private addresses that the bonded peripheral advertised with
+        let private = [
+            Address::random([0x21, 0x22, 0x23, 0x24, 0x25, 0x56]),
+            Address::random([0x31, 0x32, 0x33, 0x34, 0x35, 0x66]),
+            Address::random([0x41, 0x42, 0x43, 0x44, 0x45, 0x76]),
+        ];
+        // Stands in for resolving with the peripheral's IRK
+        let resolve = |address: Address| {
+            (address == identity || private.contains(&address)).then_some(identity)
+        };
+        let other = Address::random([0x10, 0x11, 0x12, 0x13, 0x14, 0x15]);
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        // Enter bluetooth menu
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        // Found with two private addresses before the bond was restored
+        state.ble_peripheral_found(private[0]);
+        state.ble_peripheral_found(other);
+        state.ble_peripheral_found(private[1]);
+        // A new private address, like after the peripheral restarts
+        state.ble_bonded_peripheral_found(private[2], resolve);
+        state.ble_bonded_peripheral_found(identity, resolve);
+        let GameState::SettingUp(GameStateSettingUp {
+            connection_action: ConnectionAction::Scan { peripherals, .. },
+            ..
+        }) = &state
+        else {
+            panic!("should be scanning");
+        };
+        assert_eq!(
+            &**peripherals,
+            [
+                ScannedPeripheral {
+                    address: identity,
+                    role: None,
+                    known: true,
+                    in_range: true,
+                },
+                ScannedPeripheral {
+                    address: other,
+                    role: None,
+                    known: false,
+                    in_range: true,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn two_peripherals() {
+        let fascist_board = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let tracker_board = Address::random([0x10, 0x11, 0x12, 0x13, 0x14, 0x15]);
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        // Enter bluetooth menu
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.ble_peripheral_found(tracker_board);
+        state.ble_peripheral_found(fascist_board);
+
+        // Connecting without a fascist board does nothing
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        assert_eq!(state.fascist_board_address(), None);
+
+        // The first click assigns the fascist board role, the second click skips to the tracker board role
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Click);
+        // The fascist board role is still available for the other peripheral
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        // Clicking the tracker board again removes its role, since the fascist board role is taken
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        let GameState::SettingUp(GameStateSettingUp {
+            connection_action: ConnectionAction::Scan { peripherals, .. },
+            ..
+        }) = &state
+        else {
+            panic!("should be scanning");
+        };
+        assert_eq!(peripherals[0].role, None);
+        assert_eq!(peripherals[1].role, Some(PeripheralRole::FascistBoard));
+        state.process_input(Input::Click);
+
+        // Connect to both
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        assert_eq!(
+            state.ble_action(),
+            BleAction::MaintainConnections([tracker_board, fascist_board].into_iter().collect())
+        );
+        // The sync protocol only runs with the fascist board, which isn't the first peripheral
+        assert_eq!(state.fascist_board_address(), Some(fascist_board));
+
+        // The game can't start until both are connected
+        state.process_input(Input::Back);
+        state.process_input(Input::Up);
+        state.ble_connected(fascist_board, 0);
+        state.process_input(Input::Click);
+        assert!(matches!(state, GameState::SettingUp(_)));
+        state.process_input(Input::Back);
+        state.ble_connected(tracker_board, 0);
+        state.ble_disconnected(fascist_board, 0);
+        state.process_input(Input::Click);
+        assert!(matches!(state, GameState::SettingUp(_)));
+        state.process_input(Input::Back);
+        state.ble_connected(fascist_board, 0);
+        state.process_input(Input::Click);
+        assert!(matches!(state, GameState::Playing(_)));
+        assert_eq!(state.fascist_board_address(), Some(fascist_board));
+        assert!(state.take_sync().is_some());
+
+        // The link is degraded until every peripheral is connected again
+        state.ble_disconnected(tracker_board, 0);
+        state.ble_disconnected(fascist_board, 0);
+        assert!(state.get_leds().blink_aura);
+        state.ble_connected(fascist_board, 0);
+        assert!(state.get_leds().blink_aura);
+        assert!(state.take_sync().is_none());
+        state.ble_connected(tracker_board, 0);
+        assert!(!state.get_leds().blink_aura);
+        assert!(state.take_sync().is_some());
+
+        // Unknown peripherals are ignored
+        state.ble_disconnected(Address::random([0xFF; 6]), 0);
+        assert!(!state.get_leds().blink_aura);
+    }
+
+    #[test]
+    fn cancel_connection() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let go_to_cancel = |state: &mut GameState| {
+            // Enter bluetooth menu
+            state.process_input(Input::Down);
+            state.process_input(Input::Click);
+            assert_eq!(drain_effects(state), [GameEffect::RedrawScreen]);
+            state.process_input(Input::Down);
+        };
+
+        // Cancelling while connecting does not need a disconnect
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        go_to_cancel(&mut state);
+        state.process_input(Input::Click);
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+
+        // Cancelling an established connection disconnects
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.ble_connected(address, 0);
+        go_to_cancel(&mut state);
+        state.process_input(Input::Click);
+        assert_eq!(
+            drain_effects(&mut state),
+            [
+                GameEffect::RedrawScreen,
+                GameEffect::Disconnect([address.addr].into_iter().collect())
+            ]
+        );
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+    }
+
+    #[test]
+    fn connect_timeout() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        // Enter bluetooth menu
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        drain_effects(&mut state);
+        assert!(state.needs_ticks());
+        state.tick(29);
+        assert!(drain_effects(&mut state).is_empty());
+        assert_eq!(state.connection_elapsed(29), Some(29));
+
+        // Connecting again after disconnecting starts over
+        state.ble_connected(address, 29);
+        assert_eq!(state.connection_elapsed(29), None);
+        assert!(!state.needs_ticks());
+        state.tick(100);
+        state.ble_disconnected(address, 100);
+        state.tick(129);
+        assert!(drain_effects(&mut state).is_empty());
+        state.tick(130);
+        assert_eq!(
+            drain_effects(&mut state),
+            [
+                GameEffect::RedrawScreen,
+                GameEffect::ConnectTimedOut(Default::default())
+            ]
+        );
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        // Until scanning switches to low power
+        assert!(state.needs_ticks());
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::Bluetooth(BluetoothScreen::Scanning { .. })
+        ));
+
+        // Timing out while renaming the peripheral doesn't go back to the connecting screen
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.tick(30);
+        assert!(
+            drain_effects(&mut state)
+                .iter()
+                .any(|effect| matches!(effect, GameEffect::ConnectTimedOut(_)))
+        );
+        state.process_input(Input::Back);
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::Bluetooth(BluetoothScreen::Scanning { .. })
+        ));
+
+        // A timeout of 0 never gives up
+        let settings = Settings {
+            connect_timeout_ticks: 0,
+            ..Default::default()
+        };
+        let mut state = GameState::new(Some(address), Default::default(), settings);
+        // Only ticks to count up the time spent connecting
+        assert_eq!(state.next_tick(), Some(1));
+        state.tick(10_000);
+        assert!(drain_effects(&mut state).is_empty());
+        assert!(matches!(
+            state.ble_action(),
+            BleAction::MaintainConnections(_)
+        ));
+    }
+
+    #[test]
+    fn late_ble_events_ignored() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        // Timing out while a connection update is still queued
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.tick(30);
+        drain_effects(&mut state);
+        log::take_warnings();
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        state.ble_connected(address, 30);
+        state.ble_disconnected(address, 30);
+        assert_eq!(log::take_warnings().len(), 2);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        // A scan result is expected now
+        state.ble_peripheral_found(address);
+        assert!(log::take_warnings().is_empty());
+
+        // Cancelling while a connection update is still queued
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        drain_effects(&mut state);
+        let before = state.clone();
+        state.ble_connected(address, 1);
+        assert_eq!(state, before);
+        assert_eq!(log::take_warnings().len(), 1);
+
+        // A scan result that is still queued after choosing a peripheral to connect to
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        let before = state.clone();
+        state.ble_peripheral_found(Address::random([0xFF; 6]));
+        assert_eq!(state, before);
+        assert_eq!(log::take_warnings().len(), 1);
+        // The same goes for while playing
+        state.ble_connected(address, 1);
+        state.process_input(Input::Click);
+        assert!(matches!(state, GameState::Playing(_)));
+        let before = state.clone();
+        state.ble_peripheral_found(Address::random([0xFF; 6]));
+        assert_eq!(state, before);
+        assert_eq!(log::take_warnings().len(), 1);
+    }
+
+    fn auto_start_state(auto_start: bool) -> (GameState, Address) {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(
+            Some(address),
+            Default::default(),
+            Settings {
+                auto_start,
+                default_players: 7,
+                ..Default::default()
+            },
+        );
+        state.tick(3);
+        state.ble_connected(address, 3);
+        (state, address)
+    }
+
+    fn auto_start_screen(state: &GameState) -> &GameScreen {
+        match state {
+            GameState::SettingUp(setting_up) => &setting_up.screen,
+            GameState::Playing(_) => panic!("should still be setting up"),
+        }
+    }
+
+    #[test]
+    fn auto_start_countdown() {
+        let (mut state, _) = auto_start_state(true);
+        assert_eq!(
+            *auto_start_screen(&state),
+            GameScreen::AutoStartCountdown {
+                remaining_ticks: AUTO_START_COUNTDOWN_TICKS
+            }
+        );
+        assert!(state.needs_ticks());
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.items[0], "7 players in 5s");
+        state.tick(4);
+        state.tick(7);
+        assert_eq!(
+            *auto_start_screen(&state),
+            GameScreen::AutoStartCountdown { remaining_ticks: 1 }
+        );
+        drain_effects(&mut state);
+        state.tick(8);
+        assert_eq!(
+            drain_effects(&mut state),
+            [GameEffect::GameStarted, GameEffect::RedrawScreen]
+        );
+        let GameState::Playing(playing) = &state else {
+            panic!("the game should have started");
+        };
+        assert_eq!(playing.players, 7);
+        assert_eq!(playing.last_card_change_tick, 8);
+    }
+
+    /// Steps through every deadline with [`GameState::next_tick`]:
+    /// the auto start countdown, a confirmation that times out, and then a hint that dismisses itself
+    #[test]
+    fn tick_deadlines_in_order() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(
+            Some(address),
+            Default::default(),
+            Settings {
+                auto_start: true,
+                default_players: 5,
+                hint_auto_dismiss_ticks: 20,
+                ..Default::default()
+            },
+        );
+        state.tick(3);
+        state.ble_connected(address, 3);
+        drain_effects(&mut state);
+        let mut countdown = Vec::new();
+        while let GameState::SettingUp(_) = state {
+            let tick = state.next_tick().unwrap();
+            assert_eq!(
+                state.tick(tick),
+                TickOutcome {
+                    redraw: true,
+                    effects: true
+                }
+            );
+            countdown.push(tick);
+        }
+        assert_eq!(countdown, [4, 5, 6, 7, 8]);
+        assert_eq!(
+            drain_effects(&mut state),
+            [GameEffect::RedrawScreen, GameEffect::GameStarted]
+        );
+        assert_eq!(state.next_tick(), None);
+
+        // The examine top 3 hint is shown at tick 8, and the first click confirms until tick 18
+        state.update_scanned_policy_cards(fascist_policies(3));
+        state.process_input(Input::DoubleClick);
+        drain_effects(&mut state);
+        assert_eq!(
+            state.display_action_hint(),
+            Some(FascistAction::ExamineTop3)
+        );
+        let mut confirm_reverted = None;
+        while let Some(tick) = state.next_tick() {
+            let outcome = state.tick(tick);
+            assert!(outcome.redraw);
+            if confirm_reverted.is_none()
+                && matches!(playing(&state).pending_action, PendingAction::Pending(_))
+            {
+                confirm_reverted = Some(tick);
+            }
+            if outcome.effects {
+                assert_eq!(tick, 28);
+                assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+            }
+        }
+        assert_eq!(confirm_reverted, Some(19));
+        assert_eq!(state.display_action_hint(), None);
+        assert_eq!(state.tick(100), TickOutcome::default());
+    }
+
+    #[test]
+    fn auto_start_cancelled() {
+        for input in [Input::Up, Input::Down, Input::Click, Input::Back] {
+            let (mut state, _) = auto_start_state(true);
+            state.tick(4);
+            state.process_input(input);
+            assert_eq!(
+                *auto_start_screen(&state),
+                GameScreen::MainMenu(MainMenuScreen {
+                    scroll_y: 0,
+                    selected_item: 0
+                })
+            );
+            state.tick(20);
+            assert!(matches!(state, GameState::SettingUp(_)));
+        }
+
+        // Reconnecting later doesn't start the countdown again
+        let (mut state, address) = auto_start_state(true);
+        state.ble_disconnected(address, 4);
+        assert!(matches!(auto_start_screen(&state), GameScreen::MainMenu(_)));
+        state.ble_connected(address, 5);
+        assert!(matches!(auto_start_screen(&state), GameScreen::MainMenu(_)));
+    }
+
+    #[test]
+    fn auto_start_disabled() {
+        let (mut state, _) = auto_start_state(false);
+        assert!(matches!(auto_start_screen(&state), GameScreen::MainMenu(_)));
+        state.tick(20);
+        assert!(matches!(state, GameState::SettingUp(_)));
+
+        // Only booting into auto-connect counts down
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(
+            None,
+            Default::default(),
+            Settings {
+                auto_start: true,
+                ..Default::default()
+            },
+        );
+        state.ble_peripheral_found(address);
+        let GameState::SettingUp(setting_up) = &state else {
+            unreachable!()
+        };
+        assert!(!setting_up.auto_start_pending);
+    }
+
+    #[test]
+    fn scan_low_power_when_idle() {
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        // Scanning in the background doesn't use less power, since the main menu isn't the scanned peripherals
+        state.tick(SCAN_IDLE_TICKS);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        assert!(!state.needs_ticks());
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert!(state.needs_ticks());
+        state.tick(2 * SCAN_IDLE_TICKS - 1);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        state.tick(2 * SCAN_IDLE_TICKS);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::LowPower));
+        assert!(!state.needs_ticks());
+        // Scrolling through the list scans aggressively again
+        state.process_input(Input::Down);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        state.tick(3 * SCAN_IDLE_TICKS);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::LowPower));
+        // Leaving the screen too
+        state.process_input(Input::Back);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+    }
+
+    #[test]
+    fn bluetooth_screen_restored() {
+        fn screen(state: &GameState) -> GameScreen {
+            let GameState::SettingUp(state) = state else {
+                panic!("should still be setting up");
+            };
+            state.screen.clone()
+        }
+
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        for i in 0..2 {
+            state.ble_peripheral_found(Address::random([i; 6]));
+        }
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        for _ in 0..3 {
+            state.process_input(Input::Down);
+        }
+        let bluetooth = screen(&state);
+        assert_ne!(
+            bluetooth,
+            GameScreen::Bluetooth(BluetoothScreen::new(&ConnectionAction::Scan {
+                peripherals: Default::default(),
+                paused: false
+            }))
+        );
+        state.process_input(Input::Back);
+        assert_eq!(
+            screen(&state),
+            GameScreen::MainMenu(MainMenuScreen {
+                scroll_y: 0,
+                selected_item: MainMenuSelectedItem::Bluetooth as usize
+            })
+        );
+        state.process_input(Input::Click);
+        assert_eq!(screen(&state), bluetooth);
+
+        // Giving up on connecting changes the screen, so it isn't restored
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Down);
+        state.process_input(Input::Back);
+        state.tick(30);
+        state.process_input(Input::Click);
+        assert!(matches!(
+            screen(&state),
+            GameScreen::Bluetooth(BluetoothScreen::Scanning { .. })
+        ));
+    }
+
+    #[test]
+    fn pause_scanning() {
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        // Enter bluetooth menu, where the title is selected
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        drain_effects(&mut state);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        state.process_input(Input::Click);
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        assert_eq!(state.ble_action(), BleAction::Off);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::SCAN_PAUSED);
+        state.process_input(Input::Click);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::SCANNING);
+
+        // Connecting scans again after it gives up
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        state.ble_peripheral_found(address);
+        state.process_input(Input::Click);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        assert_eq!(
+            state.ble_action(),
+            BleAction::MaintainConnections([address].into_iter().collect())
+        );
+        state.tick(30);
+        assert_eq!(state.ble_action(), BleAction::Scan(ScanPreset::Aggressive));
+    }
+
+    #[test]
+    fn retry_connecting() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        // Enter bluetooth menu, where the title is selected
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.tick(20);
+        assert_eq!(state.connection_elapsed(20), Some(20));
+        drain_effects(&mut state);
+        state.process_input(Input::Click);
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        assert_eq!(state.connection_elapsed(20), Some(0));
+        // The timeout starts over
+        state.tick(49);
+        assert!(drain_effects(&mut state).is_empty());
+        assert!(matches!(
+            state,
+            GameState::SettingUp(GameStateSettingUp {
+                screen: GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected { .. }),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn connection_details() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.ble_connected(address, 5);
+        // Enter bluetooth menu, where the title is selected
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.tick(17);
+        state.process_input(Input::Click);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::CONNECTION_DETAILS);
+        assert_eq!(screen.items, ["05:04:03:02:01:00", "Up 12s"]);
+
+        // Going back to scanning also leaves the details
+        state.ble_disconnected(address, 17);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.items, ["05:04:03:02:01:00", "Connecting"]);
+        state.tick(47);
+        assert!(matches!(
+            state,
+            GameState::SettingUp(GameStateSettingUp {
+                screen: GameScreen::Bluetooth(BluetoothScreen::Scanning { .. }),
+                ..
+            })
+        ));
+        state.process_input(Input::Back);
+        assert!(matches!(
+            state,
+            GameState::SettingUp(GameStateSettingUp {
+                screen: GameScreen::Bluetooth(BluetoothScreen::Scanning { .. }),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn next_tick_while_connecting() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        // Every tick instead of only at the timeout, since the time spent connecting is shown
+        assert_eq!(state.next_tick(), Some(1));
+        state.tick(5);
+        assert_eq!(state.next_tick(), Some(6));
+
+        // Nothing counts up once connected
+        state.ble_connected(address, 5);
+        assert_eq!(state.next_tick(), None);
+        // Until the connection details are shown, which count up how long it has been connected
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Click);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::CONNECTION_DETAILS);
+        assert_eq!(state.next_tick(), Some(6));
+        state.tick(6);
+        assert_eq!(state.next_tick(), Some(7));
+        state.process_input(Input::Back);
+        assert_eq!(state.next_tick(), None);
+    }
+
+    #[test]
+    fn playing_never_times_out() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = playing_state(6);
+        let GameState::Playing(playing) = &mut state else {
+            unreachable!()
+        };
+        playing
+            .connection_statuses
+            .push(ConnectionStatus {
+                peripheral_address: address,
+                role: PeripheralRole::FascistBoard,
+                state: ConnectState::Connecting,
+                since: 0,
+            })
+            .unwrap();
+        assert!(!state.needs_ticks());
+        state.tick(10_000);
+        assert!(drain_effects(&mut state).is_empty());
+        assert_eq!(state.connection_elapsed(10_000), Some(10_000));
+        assert!(matches!(state, GameState::Playing(_)));
+        assert_eq!(
+            state.ble_action(),
+            BleAction::MaintainConnections([address].into_iter().collect())
+        );
+    }
+
+    #[test]
+    fn bd_addr_formatting() {
+        let address = BdAddr::new([0x0a, 0x1b, 0x2c, 0x3d, 0x4e, 0xf5]);
+        // Must match trouble_host's Display impl exactly
+        assert_eq!(fmt_bd_addr(&address), "F5:4E:3D:2C:1B:0A");
+        assert_eq!(
+            fmt_bd_addr(&address),
+            alloc::format!(
+                "{}",
+                trouble_host::Address {
+                    kind: trouble_host::prelude::AddrKind::RANDOM,
+                    addr: address,
+                }
+            )
+            .as_str()
+        );
+        assert_eq!(fmt_bd_addr_short(&address), "2C:1B:0A");
+        assert_eq!(fmt_bd_addr(&BdAddr::new([0; 6])), "00:00:00:00:00:00");
+    }
+
+    #[test]
+    fn text_entry_character_cycling() {
+        let mut screen = TextEntryScreen::new(
+            TextEntryPurpose::RenamePeripheral(BdAddr::new([0; 6])),
+            Default::default(),
+        );
+        assert_eq!(screen.process_input(Input::Click), None);
+        screen.process_input(Input::Down);
+        screen.process_input(Input::Click);
+        assert_eq!(screen.text, "AB");
+        // Going up from the first character wraps around to done
+        screen.process_input(Input::Up);
+        screen.process_input(Input::Up);
+        assert_eq!(
+            TextEntryChoice::get(screen.selected_choice),
+            TextEntryChoice::Done
+        );
+        screen.process_input(Input::Up);
+        assert_eq!(
+            TextEntryChoice::get(screen.selected_choice),
+            TextEntryChoice::Backspace
+        );
+        screen.process_input(Input::Up);
+        assert_eq!(
+            TextEntryChoice::get(screen.selected_choice),
+            TextEntryChoice::Character('-')
+        );
+        // And back down past done wraps around to the first character
+        screen.process_input(Input::Down);
+        screen.process_input(Input::Down);
+        screen.process_input(Input::Down);
+        assert_eq!(
+            TextEntryChoice::get(screen.selected_choice),
+            TextEntryChoice::Character('A')
+        );
+    }
+
+    #[test]
+    fn text_entry_backspace() {
+        let mut screen = TextEntryScreen::new(
+            TextEntryPurpose::RenamePeripheral(BdAddr::new([0; 6])),
+            "AB".try_into().unwrap(),
+        );
+        screen.selected_choice = TEXT_ENTRY_CHARACTERS.len();
+        screen.process_input(Input::Click);
+        assert_eq!(screen.text, "A");
+        screen.process_input(Input::Click);
+        screen.process_input(Input::Click);
+        assert_eq!(screen.text, "");
+        screen.process_input(Input::Down);
+        assert_eq!(
+            screen.process_input(Input::Click),
+            Some(TextEntryResult::Done)
+        );
+        assert_eq!(
+            screen.process_input(Input::Back),
+            Some(TextEntryResult::Cancelled)
+        );
+    }
+
+    #[test]
+    fn text_entry_max_length() {
+        let mut screen = TextEntryScreen::new(
+            TextEntryPurpose::RenamePeripheral(BdAddr::new([0; 6])),
+            Default::default(),
+        );
+        for _ in 0..PERIPHERAL_NAME_LEN + 3 {
+            screen.process_input(Input::Click);
+        }
+        assert_eq!(screen.text.len(), PERIPHERAL_NAME_LEN);
+        assert!(screen.text.chars().all(|character| character == 'A'));
+    }
+
+    #[test]
+    fn rename_peripheral() {
+        let address = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        // Enter bluetooth menu and click on the fascist board
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        // Navigating twice only needs one redraw
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+
+        // Name it "B"
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
+        state.process_input(Input::Up);
+        assert!(drain_effects(&mut state).is_empty());
+        let name: heapless::String<PERIPHERAL_NAME_LEN> = "B".try_into().unwrap();
+        // One click both saves the name and goes back
+        state.process_input(Input::Click);
+        assert_eq!(
+            drain_effects(&mut state),
+            [
+                GameEffect::SaveKnownPeripherals(
+                    [KnownPeripheral {
+                        address: address.addr,
+                        name: name.clone(),
+                    }]
+                    .into_iter()
+                    .collect()
+                ),
+                GameEffect::RedrawScreen
+            ]
+        );
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected { .. })
+        ));
+
+        // The name is shown when scanning
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        state.ble_peripheral_found(address);
+        assert_eq!(
+            state.screen(&runtime_info()).unwrap().items,
+            [ScreenText::try_from("B").unwrap()]
+        );
+    }
+
+    fn runtime_info() -> RuntimeInfo {
+        RuntimeInfo {
+            firmware_version: "0.1.0",
+            git_hash: "abc1234",
+            protocol_version: 2,
+            our_address: BdAddr::new([1, 2, 3, 4, 5, 6]),
+            free_heap: 1024,
+            peak_heap: 2048,
+            uptime_secs: 3723,
+            leds_disabled: true,
+            ble_unavailable: false,
+            rotary_resyncs: 3,
+            clock_sync: Some(ClockSync {
+                offset_ms: -1500,
+                round_trip_ms: 24,
+            }),
+        }
+    }
+
+    #[test]
+    fn about_screen() {
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::ABOUT);
+        assert!(matches!(screen.selected_item, SelectedItem::Back));
+        assert_eq!(screen.items.len(), ABOUT_LINES);
+        assert_eq!(screen.items[0], "Version: 0.1.0");
+        assert_eq!(screen.items[1], "Commit: abc1234");
+        assert_eq!(screen.items[2], "Protocol: 2");
+        assert_eq!(screen.items[4], "06:05:04:03:02:01");
+        assert_eq!(screen.items[6], "Peak heap: 2048B");
+        assert_eq!(screen.items[7], "Uptime: 1h2m3s");
+        assert_eq!(screen.items[8], "LEDs: disabled");
+        assert_eq!(screen.items[9], "BLE: OK");
+        assert_eq!(screen.items[10], "Knob resyncs: 3");
+        assert_eq!(screen.items[11], "Clock: -1500ms");
+        assert_eq!(screen.items[12], "Sync RTT: 24ms");
+        let not_synced = RuntimeInfo {
+            clock_sync: None,
+            ..runtime_info()
+        };
+        assert_eq!(not_synced.about_lines()[11], "Clock: not synced");
+        assert_eq!(not_synced.about_lines()[12], "Sync RTT: -");
+        assert!(screen.items.iter().any(|item| item.contains("AGPL")));
+
+        // Scrolling stops at the last line
+        for _ in 0..ABOUT_LINES + 5 {
+            state.process_input(Input::Down);
+        }
+        assert!(matches!(
+            state.screen(&runtime_info()).unwrap().selected_item,
+            SelectedItem::Item(i) if i == ABOUT_LINES - 1
+        ));
+        for _ in 0..ABOUT_LINES + 5 {
+            state.process_input(Input::Up);
+        }
+        assert!(matches!(
+            state.screen(&runtime_info()).unwrap().selected_item,
+            SelectedItem::Back
+        ));
+
+        // Clicking back goes to the main menu
+        state.process_input(Input::Click);
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("should still be setting up");
+        };
+        assert!(matches!(setting_up.screen, GameScreen::MainMenu(_)));
+    }
+
+    #[test]
+    fn out_of_range_selected_item() {
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        let GameState::SettingUp(setting_up) = &mut state else {
+            unreachable!()
+        };
+        setting_up.screen = GameScreen::MainMenu(MainMenuScreen {
+            scroll_y: 0,
+            selected_item: 10,
+        });
+        log::take_warnings();
+        // The last item is clicked instead of panicking
+        state.process_input(Input::Click);
+        assert_eq!(log::take_warnings().len(), 1);
+        let GameState::SettingUp(setting_up) = &mut state else {
+            unreachable!()
+        };
+        assert!(matches!(setting_up.screen, GameScreen::About(_)));
+
+        // Going back restores the stale selected item, which is clamped
+        state.process_input(Input::Back);
+        assert_eq!(log::take_warnings().len(), 1);
+        let GameState::SettingUp(setting_up) = &mut state else {
+            unreachable!()
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::MainMenu(MainMenuScreen {
+                selected_item,
+                ..
+            }) if selected_item == MainMenuSelectedItem::VARIANTS.len() - 1
+        ));
+
+        // A scanning screen with fewer peripherals than when it was left
+        setting_up
+            .back_stack
+            .push(GameScreen::Bluetooth(BluetoothScreen::Scanning {
+                scroll_y: 0,
+                selected_item: ScanningSelectedItem::VARIANTS.len() + 3,
+            }))
+            .unwrap();
+        state.process_input(Input::Back);
+        assert_eq!(log::take_warnings().len(), 1);
+        let GameState::SettingUp(setting_up) = &mut state else {
+            unreachable!()
+        };
+        assert!(matches!(
+            setting_up.screen,
+            GameScreen::Bluetooth(BluetoothScreen::Scanning {
+                selected_item: 2,
+                ..
+            })
+        ));
+        // Clicking doesn't index a peripheral that isn't there
+        state.process_input(Input::Click);
+        assert_eq!(
+            log::take_warnings(),
+            ["Not connecting because no fascist board was chosen"]
+        );
+    }
+
+    #[test]
+    fn checked_variant_clamps() {
+        log::take_warnings();
+        assert!(matches!(
+            checked_variant::<MainMenuSelectedItem>(1),
+            MainMenuSelectedItem::Bluetooth
+        ));
+        assert!(log::take_warnings().is_empty());
+        assert!(matches!(
+            checked_variant::<MainMenuSelectedItem>(usize::MAX),
+            MainMenuSelectedItem::About
+        ));
+        assert_eq!(log::take_warnings().len(), 1);
+    }
+
+    fn drain_effects(state: &mut GameState) -> Vec<GameEffect> {
+        state.drain_effects().collect()
+    }
+
+    #[test]
+    fn restart_boards() {
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        drain_effects(&mut state);
+        let GameState::SettingUp(setting_up) = &mut state else {
+            unreachable!()
+        };
+        setting_up.screen = GameScreen::MainMenu(MainMenuScreen {
+            scroll_y: 0,
+            selected_item: MainMenuSelectedItem::RestartBoards as usize,
+        });
+        assert_eq!(
+            state
+                .screen(&runtime_info())
+                .unwrap()
+                .items
+                .get(MainMenuSelectedItem::RestartBoards as usize)
+                .map(|item| item.as_str()),
+            Some(labels::RESTART_BOARDS)
+        );
+        state.process_input(Input::Click);
+        // The firmware restarts, so the screen doesn't change
+        assert_eq!(drain_effects(&mut state), [GameEffect::RestartBoards]);
+        assert!(GameEffect::RestartBoards.is_critical());
+        let GameState::SettingUp(setting_up) = &state else {
+            unreachable!()
+        };
+        assert!(matches!(setting_up.screen, GameScreen::MainMenu(_)));
+    }
+
+    fn playing_state(players: u8) -> GameState {
+        GameState::Playing(GameStatePlaying {
+            players,
+            dead_players: 0,
+            settings: Default::default(),
+            connection_statuses: Default::default(),
+            liberal_policies_placed: 0,
+            fascist_policies_placed: 0,
+            detected_cards: Default::default(),
+            highest_liberal_policies_placed: 0,
+            highest_fascist_policies_placed: 0,
+            liberal_policies_removed_since: None,
+            fascist_policies_removed_since: None,
+            hitler_state: HitlerState::Secret,
+            election_fail_streak: 0,
+            chaos_policy_pending: false,
+            pending_action: PendingAction::None,
+            hint_since: 0,
+            tick: 0,
+            last_card_change_tick: 0,
+            link_degraded: false,
+            sync_pending: false,
+            local_only: false,
+            misplacement: None,
+            misplaced_since: 0,
+            screen: PlayingScreen::Board,
+            investigations: heapless::Vec::new(),
+            known_peripherals: Default::default(),
+            reader_debug: false,
+            supply: Default::default(),
+            effects: Default::default(),
+        })
+    }
+
+    #[test]
+    fn game_completed_once() {
+        let mut state = playing_state(6);
+        let liberal_policies = |count| DetectedPolicyCards {
+            liberal: (0..count)
+                .map(|id| PolicyCardId {
+                    team: Team::Liberal,
+                    id,
+                })
+                .collect(),
+            fascist: Default::default(),
+        };
+        state.update_scanned_policy_cards(liberal_policies(LIBERAL_BOARD_SLOTS - 1));
+        assert!(drain_effects(&mut state).is_empty());
+        state.update_scanned_policy_cards(liberal_policies(LIBERAL_BOARD_SLOTS));
+        assert_eq!(
+            drain_effects(&mut state),
+            [GameEffect::GameCompleted(Team::Liberal)]
+        );
+        // Scanning the same cards again doesn't complete the game again
+        state.update_scanned_policy_cards(liberal_policies(LIBERAL_BOARD_SLOTS));
+        assert!(drain_effects(&mut state).is_empty());
+    }
+
+    #[test]
+    fn detected_policy_cards_eq() {
+        let cards = |ids: &[usize]| DetectedPolicyCards {
+            liberal: ids
+                .iter()
+                .map(|&id| PolicyCardId {
+                    team: Team::Liberal,
+                    id,
+                })
+                .collect(),
+            fascist: Default::default(),
+        };
+        // The order that the cards were detected in doesn't matter
+        assert_eq!(cards(&[0, 1, 2]), cards(&[2, 0, 1]));
+        assert_ne!(cards(&[0, 1, 2]), cards(&[0, 1]));
+        assert_ne!(cards(&[0, 1, 2]), cards(&[0, 1, 3]));
+    }
+
+    #[test]
+    fn detected_policy_cards_sorted() {
+        let cards = |liberal: &[usize], fascist: &[usize]| DetectedPolicyCards {
+            // Fascist policies on the liberal board
+            liberal: liberal
+                .iter()
+                .map(|&id| PolicyCardId {
+                    team: if id < 3 { Team::Liberal } else { Team::Fascist },
+                    id,
+                })
+                .collect(),
+            fascist: fascist
+                .iter()
+                .map(|&id| PolicyCardId {
+                    team: Team::Fascist,
+                    id,
+                })
+                .collect(),
+        };
+        let card = |team, id| PolicyCardId { team, id };
+        let expected = [
+            (Team::Liberal, card(Team::Liberal, 0)),
+            (Team::Liberal, card(Team::Liberal, 2)),
+            (Team::Liberal, card(Team::Fascist, 4)),
+            (Team::Liberal, card(Team::Fascist, 5)),
+            (Team::Fascist, card(Team::Fascist, 1)),
+            (Team::Fascist, card(Team::Fascist, 3)),
+        ];
+        let orders = [
+            cards(&[0, 2, 4, 5], &[1, 3]),
+            cards(&[5, 0, 4, 2], &[3, 1]),
+            cards(&[4, 5, 2, 0], &[1, 3]),
+        ];
+        for cards in &orders {
+            assert!(cards.iter_sorted().eq(expected));
+            assert_eq!(
+                alloc::format!("{cards:?}"),
+                alloc::format!("{:?}", orders[0])
+            );
+        }
+
+        // Scanning the same cards in a different order doesn't change anything
+        let mut state = playing_state(6);
+        state.tick(1);
+        state.update_scanned_policy_cards(orders[0].clone());
+        let GameState::Playing(playing) = &state else {
+            unreachable!()
+        };
+        assert_eq!(
+            playing.misplacement(),
+            Some(Misplacement {
+                card: card(Team::Fascist, 4),
+                board: Team::Liberal
+            })
+        );
+        let misplacement = playing.misplacement();
+        drain_effects(&mut state);
+        state.tick(2);
+        for cards in &orders[1..] {
+            state.update_scanned_policy_cards(cards.clone());
+            assert!(drain_effects(&mut state).is_empty());
+        }
+        let GameState::Playing(playing) = &state else {
+            unreachable!()
+        };
+        assert_eq!(playing.misplacement(), misplacement);
+        assert_eq!(playing.last_card_change_tick, 1);
+    }
+
+    #[test]
+    fn game_state_eq() {
+        let state = playing_state(6);
+        let mut changed = state.clone();
+        assert_eq!(changed, state);
+        changed.process_input(Input::Click);
+        assert_ne!(changed, state);
+    }
+
+    /// A game with 6 players where 3 fascist policies were just placed
+    fn examine_top_3_state() -> GameState {
+        let mut state = playing_state(6);
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [].into_iter().collect(),
+            fascist: (0..3)
+                .map(|id| PolicyCardId {
+                    team: Team::Fascist,
+                    id,
+                })
+                .collect(),
+        });
+        assert_eq!(
+            state.display_action_hint(),
+            Some(FascistAction::ExamineTop3)
+        );
+        assert_eq!(
+            state.display_action_hint_text(),
+            Some(labels::EXAMINE_TOP_3_HINT)
+        );
+        state
+    }
+
+    #[test]
+    fn examine_top_3_confirm() {
+        let mut state = examine_top_3_state();
+        state.tick(5);
+        dismiss_hint(&mut state);
+        assert_eq!(
+            state.display_action_hint_text(),
+            Some(labels::CONFIRM_EXAMINE_TOP_3_HINT)
+        );
+        assert!(state.needs_ticks());
+        state.tick(15);
+        dismiss_hint(&mut state);
+        assert_eq!(state.display_action_hint(), None);
+        assert!(!state.needs_ticks());
+    }
+
+    #[test]
+    fn examine_top_3_confirm_timeout() {
+        let mut state = examine_top_3_state();
+        state.tick(5);
+        dismiss_hint(&mut state);
+        assert_eq!(
+            state.display_action_hint_text(),
+            Some(labels::CONFIRM_EXAMINE_TOP_3_HINT)
+        );
+        // Too late, so the hint goes back to what it was
+        state.tick(16);
+        assert_eq!(
+            state.display_action_hint(),
+            Some(FascistAction::ExamineTop3)
+        );
+        assert_eq!(
+            state.display_action_hint_text(),
+            Some(labels::EXAMINE_TOP_3_HINT)
+        );
+        assert!(!state.needs_ticks());
+
+        // Dismissing again starts confirming again instead of dismissing the hint
+        dismiss_hint(&mut state);
+        assert_eq!(
+            state.display_action_hint_text(),
+            Some(labels::CONFIRM_EXAMINE_TOP_3_HINT)
+        );
+    }
+
+    #[test]
+    fn election_tracker_placement() {
+        for (placement, liberal, fascist) in [
+            (ElectionTrackerPlacement::Liberal, true, false),
+            (ElectionTrackerPlacement::Fascist, false, true),
+            (ElectionTrackerPlacement::Both, true, true),
+        ] {
+            let mut state = playing_state(10);
+            let GameState::Playing(playing) = &mut state else {
+                unreachable!()
+            };
+            playing.settings.election_tracker_placement = placement;
+            state.record_failed_election();
+            state.record_failed_election();
+            let leds = state.get_leds();
+            // The count is synced either way, so that the spectator state has it
+            assert_eq!(leds.election_tracker_leds, 2);
+            for (board, shown) in [(Team::Liberal, liberal), (Team::Fascist, fascist)] {
+                assert_eq!(
+                    leds.election_tracker_leds_on(board),
+                    if shown { 2 } else { 0 }
+                );
+                assert_eq!(leds.election_tracker_warning_on(board), shown);
+            }
+        }
+    }
+
+    #[test]
+    fn chaos_warning() {
+        let mut state = playing_state(10);
+        assert!(!state.get_leds().election_tracker_warning);
+        assert!(!state.record_failed_election());
+        assert!(!state.get_leds().election_tracker_warning);
+        assert!(!state.record_failed_election());
+        assert!(state.get_leds().election_tracker_warning);
+        let GameState::Playing(playing) = &state else {
+            unreachable!()
+        };
+        assert_eq!(playing.failures_until_chaos(), 1);
+
+        // A policy is placed, which resets the election tracker
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [PolicyCardId {
+                team: Team::Liberal,
+                id: 0,
+            }]
+            .into_iter()
+            .collect(),
+            fascist: [].into_iter().collect(),
+        });
+        assert!(!state.get_leds().election_tracker_warning);
+        assert_eq!(state.get_leds().election_tracker_leds, 0);
+
+        // Chaos also resets the election tracker
+        assert!(!state.record_failed_election());
+        assert!(!state.record_failed_election());
+        assert!(state.get_leds().election_tracker_warning);
+        assert!(state.record_failed_election());
+        assert!(!state.get_leds().election_tracker_warning);
+        assert_eq!(state.get_leds().election_tracker_leds, 0);
+
+        // The top policy is fascist, but its power is ignored
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [PolicyCardId {
+                team: Team::Liberal,
+                id: 0,
+            }]
+            .into_iter()
+            .collect(),
+            fascist: [PolicyCardId {
+                team: Team::Fascist,
+                id: 0,
+            }]
+            .into_iter()
+            .collect(),
+        });
+        assert_eq!(state.display_action_hint(), None);
+    }
+
+    fn scanned(state: &GameState) -> heapless::Vec<ScannedPeripheral, SCAN_LIST_SIZE> {
+        match state {
+            GameState::SettingUp(GameStateSettingUp {
+                connection_action: ConnectionAction::Scan { peripherals, .. },
+                ..
+            }) => heapless::Vec::from_slice(peripherals).unwrap(),
+            _ => panic!("should be scanning"),
+        }
+    }
+
+    #[test]
+    fn saved_peripherals_listed() {
+        let last_connected = Address::random([1, 0, 0, 0, 0, 0]);
+        let named = Address::random([2, 0, 0, 0, 0, 0]);
+        // The last connected peripheral also has a name, and is only listed once
+        let known_peripherals = [last_connected, named]
+            .into_iter()
+            .map(|address| KnownPeripheral {
+                address: address.addr,
+                name: "Board".try_into().unwrap(),
+            })
+            .collect();
+        let mut state = GameState::new(None, known_peripherals, Default::default());
+        state.load_last_connected_peripheral(last_connected);
+        let saved = |address| ScannedPeripheral {
+            address,
+            role: None,
+            known: false,
+            in_range: false,
+        };
+        assert_eq!(scanned(&state), [saved(last_connected), saved(named)]);
+
+        // Found later, so it becomes a normal entry in the same place
+        state.ble_peripheral_found(named);
+        state.ble_peripheral_found(named);
+        let other = Address::random([3, 0, 0, 0, 0, 0]);
+        state.ble_peripheral_found(other);
+        assert_eq!(
+            scanned(&state),
+            [
+                saved(last_connected),
+                ScannedPeripheral {
+                    in_range: true,
+                    ..saved(named)
+                },
+                ScannedPeripheral {
+                    in_range: true,
+                    ..saved(other)
+                },
+            ]
+        );
+
+        // Connecting to a peripheral that isn't in range is tried anyway
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        // From the title to the first peripheral, and back up to connect
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        assert_eq!(
+            state.ble_action(),
+            BleAction::MaintainConnections([last_connected].into_iter().collect())
+        );
+
+        // Cancelling lists the saved peripherals again
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert_eq!(scanned(&state), [saved(last_connected), saved(named)]);
+        assert!(log::take_warnings().is_empty());
+    }
+
+    #[test]
+    fn saved_peripherals_make_room() {
+        let known_peripherals = (0..KNOWN_PERIPHERALS_SIZE as u8)
+            .map(|i| KnownPeripheral {
+                address: BdAddr::new([i, 0, 0, 0, 0, 0]),
+                name: "Board".try_into().unwrap(),
+            })
+            .collect();
+        let mut state = GameState::new(None, known_peripherals, Default::default());
+        assert_eq!(scanned(&state).len(), SCAN_LIST_SIZE);
+        // A new peripheral replaces the last saved one that wasn't found
+        let new = Address::random([0xFF, 0, 0, 0, 0, 0]);
+        state.ble_peripheral_found(new);
+        let peripherals = scanned(&state);
+        assert_eq!(peripherals.len(), SCAN_LIST_SIZE);
+        assert_eq!(
+            peripherals.last().map(|peripheral| peripheral.address),
+            Some(new)
+        );
+        assert!(
+            !peripherals
+                .iter()
+                .any(|peripheral| peripheral.address.addr.into_inner()[0]
+                    == SCAN_LIST_SIZE as u8 - 1)
+        );
+        assert!(log::take_warnings().is_empty());
+    }
+
+    #[test]
+    fn scan_list_full_keeps_newest() {
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        // Enter bluetooth menu
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        log::take_warnings();
+
+        let address = |i| Address::random([i, 0, 0, 0, 0, 0]);
+        for i in 0..SCAN_LIST_SIZE as u8 {
+            state.ble_peripheral_found(address(i));
+        }
+        // Select the second peripheral, starting from the title
+        for _ in ScanningSelectedItem::Title as usize..ScanningSelectedItem::VARIANTS.len() + 1 {
+            state.process_input(Input::Down);
+        }
+        // Finding a peripheral that is already in the list doesn't change anything
+        state.ble_peripheral_found(address(0));
+        assert_eq!(scanned(&state).len(), SCAN_LIST_SIZE);
+
+        // The oldest peripheral is replaced, and the selection stays on the same peripheral
+        let newest = address(0xFF);
+        state.ble_peripheral_found(newest);
+        let peripherals = scanned(&state);
+        assert_eq!(
+            peripherals
+                .iter()
+                .map(|peripheral| peripheral.address)
+                .collect::<heapless::Vec<_, SCAN_LIST_SIZE>>(),
+            [address(1), address(2), address(3), newest]
+        );
+        state.process_input(Input::Click);
+        assert_eq!(scanned(&state)[0].role, Some(PeripheralRole::FascistBoard));
+        assert!(log::take_warnings().is_empty());
+    }
+
+    #[test]
+    fn misplaced_policy() {
+        let mut state = playing_state(6);
+        let card = PolicyCardId {
+            team: Team::Fascist,
+            id: 0,
+        };
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [card].into_iter().collect(),
+            fascist: [].into_iter().collect(),
+        });
+        let GameState::Playing(playing) = &state else {
+            unreachable!()
+        };
+        let misplacement = playing.misplacement().unwrap();
+        assert_eq!(
+            misplacement,
+            Misplacement {
+                card,
+                board: Team::Liberal
+            }
+        );
+        assert_eq!(misplacement.warning(), labels::MISPLACED_FASCIST_POLICY);
+        let leds = state.get_leds();
+        assert_eq!(leds.liberal_policy_leds, 0);
+        assert_eq!(leds.fascist_policy_leds, 1);
+        assert_eq!(leds.misplaced_board, Some(Team::Liberal));
+        assert_eq!(state.next_tick(), Some(MISPLACED_BLINK_TICKS));
+
+        // The aura only blinks briefly, but the warning stays
+        state.take_sync();
+        state.tick(MISPLACED_BLINK_TICKS);
+        let GameState::Playing(playing) = &state else {
+            unreachable!()
+        };
+        assert_eq!(playing.misplacement(), Some(misplacement));
+        assert_eq!(state.get_leds().misplaced_board, None);
+        assert_eq!(state.take_sync().unwrap().misplaced_board, None);
+        assert_eq!(state.next_tick(), None);
+
+        // Moving the card to the fascist board clears the warning
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [].into_iter().collect(),
+            fascist: [card].into_iter().collect(),
+        });
+        let GameState::Playing(playing) = &state else {
+            unreachable!()
+        };
+        assert_eq!(playing.misplacement(), None);
+        let leds = state.get_leds();
+        assert_eq!(leds.fascist_policy_leds, 1);
+        assert_eq!(leds.misplaced_board, None);
+    }
+
+    #[test]
+    fn action_hint_text() {
+        let hints = |players| {
+            (1..FASCIST_BOARD_SLOTS)
+                .filter_map(|fascist_policies| {
+                    let mut state = playing_state(players);
+                    state.update_scanned_policy_cards(DetectedPolicyCards {
+                        liberal: [].into_iter().collect(),
+                        fascist: (0..fascist_policies)
+                            .map(|id| PolicyCardId {
+                                team: Team::Fascist,
+                                id,
+                            })
+                            .collect(),
+                    });
+                    state.action_hint().map(|hint| alloc::format!("{hint}"))
+                })
+                .collect::<Vec<_>>()
+        };
+        for players in [5, 6] {
+            assert_eq!(
+                hints(players),
+                [
+                    "Fascist policy #3 (5-6 players): President: examine the top 3 cards",
+                    "Fascist policy #4 (5-6 players): President: kill a player",
+                    "Fascist policy #5 (5-6 players): President: kill a player",
+                ]
+            );
+        }
+        for players in [7, 8] {
+            assert_eq!(
+                hints(players),
+                [
+                    "Fascist policy #2 (7-8 players): President: check a player's party",
+                    "Fascist policy #3 (7-8 players): President: choose the next president",
+                    "Fascist policy #4 (7-8 players): President: kill a player",
+                    "Fascist policy #5 (7-8 players): President: kill a player",
+                ]
+            );
+        }
+        for players in [9, 10] {
+            assert_eq!(
+                hints(players),
+                [
+                    "Fascist policy #1 (9-10 players): President: check a player's party",
+                    "Fascist policy #2 (9-10 players): President: check a player's party",
+                    "Fascist policy #3 (9-10 players): President: choose the next president",
+                    "Fascist policy #4 (9-10 players): President: kill a player",
+                    "Fascist policy #5 (9-10 players): President: kill a player",
+                ]
+            );
+        }
+    }
+
+    /// A 9 player game with president notes, and the check party hint from the first fascist policy
+    fn check_party_state(president_notes: bool) -> GameState {
+        let mut state = playing_state(9);
+        if let GameState::Playing(state) = &mut state {
+            state.settings.president_notes = president_notes;
+        }
+        state.update_scanned_policy_cards(fascist_policies(1));
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        state
+    }
+
+    fn fascist_policies(count: usize) -> DetectedPolicyCards {
+        DetectedPolicyCards {
+            liberal: Default::default(),
+            fascist: (0..count)
+                .map(|id| PolicyCardId {
+                    team: Team::Fascist,
+                    id,
+                })
+                .collect(),
+        }
+    }
+
+    /// Clicks on the board, and then on Dismiss hint in the menu
+    fn dismiss_hint(state: &mut GameState) {
+        state.process_input(Input::Click);
+        assert!(matches!(
+            playing(state).screen(),
+            PlayingScreen::Menu { .. }
+        ));
+        state.process_input(Input::Click);
+    }
+
+    fn playing(state: &GameState) -> &GameStatePlaying {
+        match state {
+            GameState::Playing(state) => state,
+            GameState::SettingUp(_) => unreachable!(),
+        }
+    }
+
+    /// A 9 player game with hints dismissing themselves after 10 ticks, and `fascist_policies` placed at tick 5
+    fn auto_dismiss_state(fascist_policies_placed: usize) -> GameState {
+        let mut state = playing_state(9);
+        if let GameState::Playing(state) = &mut state {
+            state.settings.hint_auto_dismiss_ticks = 10;
+        }
+        state.tick(5);
+        state.update_scanned_policy_cards(fascist_policies(fascist_policies_placed));
+        drain_effects(&mut state);
+        state
+    }
+
+    #[test]
+    fn hint_auto_dismiss() {
+        let mut state = auto_dismiss_state(1);
+        assert_eq!(state.hint_age(5), Some(0));
+        assert_eq!(state.hint_auto_dismiss_ticks(), Some(10));
+        assert!(state.needs_ticks());
+        state.tick(14);
+        assert_eq!(state.hint_age(14), Some(9));
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        assert!(drain_effects(&mut state).is_empty());
+        state.tick(15);
+        assert_eq!(state.display_action_hint(), None);
+        assert_eq!(state.hint_age(15), None);
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        assert!(!state.needs_ticks());
+    }
+
+    #[test]
+    fn kill_hint_never_auto_dismissed() {
+        let mut state = auto_dismiss_state(4);
+        assert_eq!(state.display_action_hint(), Some(FascistAction::Kill));
+        assert_eq!(state.hint_auto_dismiss_ticks(), None);
+        assert!(!state.needs_ticks());
+        state.tick(1_000);
+        assert_eq!(state.display_action_hint(), Some(FascistAction::Kill));
+        assert_eq!(state.hint_age(1_000), Some(995));
+    }
+
+    #[test]
+    fn flickering_policy_not_new() {
+        let mut state = playing_state(9);
+        state.update_scanned_policy_cards(fascist_policies(1));
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        dismiss_hint(&mut state);
+        state.record_failed_election();
+        // The card wasn't scanned once, which the debouncer didn't catch
+        let mut hints = 0;
+        for count in [0, 1, 0, 1] {
+            state.update_scanned_policy_cards(fascist_policies(count));
+            hints += usize::from(state.display_action_hint().is_some());
+        }
+        assert_eq!(hints, 0);
+        assert_eq!(playing(&state).election_fail_streak, 1);
+        assert_eq!(playing(&state).fascist_policies_placed, 1);
+
+        // The next policy is new, and a liberal card flickering doesn't clear its hint
+        state.update_scanned_policy_cards(fascist_policies(2));
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        assert_eq!(playing(&state).election_fail_streak, 0);
+        let liberal = |count: usize| DetectedPolicyCards {
+            liberal: (0..count)
+                .map(|id| PolicyCardId {
+                    team: Team::Liberal,
+                    id,
+                })
+                .collect(),
+            fascist: fascist_policies(2).fascist,
+        };
+        state.update_scanned_policy_cards(liberal(1));
+        assert_eq!(state.display_action_hint(), None);
+        state.record_failed_election();
+        state.update_scanned_policy_cards(liberal(0));
+        state.update_scanned_policy_cards(liberal(1));
+        assert_eq!(playing(&state).election_fail_streak, 1);
+    }
+
+    #[test]
+    fn removed_policy_placed_again() {
+        let mut state = playing_state(9);
+        // Placed by accident
+        state.update_scanned_policy_cards(fascist_policies(1));
+        dismiss_hint(&mut state);
+        state.update_scanned_policy_cards(fascist_policies(0));
+        state.tick(POLICY_REMOVED_TICKS);
+        state.record_failed_election();
+        // The real policy
+        state.update_scanned_policy_cards(fascist_policies(1));
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        assert_eq!(playing(&state).election_fail_streak, 0);
+        assert_eq!(playing(&state).fascist_policies_removed_since, None);
+    }
+
+    #[test]
+    fn hint_auto_dismiss_off_by_default() {
+        assert_eq!(Settings::default().hint_auto_dismiss_ticks, 0);
+        let mut state = check_party_state(false);
+        assert_eq!(state.hint_auto_dismiss_ticks(), None);
+        assert!(!state.needs_ticks());
+        state.tick(u16::MAX.into());
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        assert_eq!(state.hint_age(u16::MAX.into()), Some(u16::MAX.into()));
+    }
+
+    #[test]
+    fn president_notes_entry() {
+        let mut state = check_party_state(true);
+        drain_effects(&mut state);
+        dismiss_hint(&mut state);
+        assert_eq!(state.display_action_hint(), None);
+        assert_eq!(
+            playing(&state).screen(),
+            PlayingScreen::NoteEntry {
+                policy_index: 1,
+                selected_item: NoteEntrySelectedItem::Skip as usize
+            }
+        );
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::NOTE_ENTRY_TITLE);
+        assert_eq!(screen.items, ["Skip", "Liberal", "Fascist"]);
+
+        // Skip is the default, so clicking right away doesn't write anything down
+        state.process_input(Input::Click);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert!(playing(&state).investigations().is_empty());
+        // There are no notes to show
+        dismiss_hint(&mut state);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+
+        // Second check party action
+        state.update_scanned_policy_cards(fascist_policies(2));
+        dismiss_hint(&mut state);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        // Can't go past the last item
+        state.process_input(Input::Down);
+        assert_eq!(
+            playing(&state).screen(),
+            PlayingScreen::NoteEntry {
+                policy_index: 2,
+                selected_item: NoteEntrySelectedItem::Fascist as usize
+            }
+        );
+        state.process_input(Input::Click);
+        assert_eq!(playing(&state).investigations(), [(2, Team::Fascist)]);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+
+        // The notes are opened from the menu
+        click_menu_item(&mut state, PlayingMenuSelectedItem::Notes);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Notes);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::PRESIDENT_NOTES);
+        assert_eq!(screen.items, ["Policy #2: Fascist"]);
+        state.process_input(Input::Back);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+
+        // Going back from the entry doesn't write anything down
+        state.update_scanned_policy_cards(fascist_policies(1));
+        state.update_scanned_policy_cards(fascist_policies(2));
+        dismiss_hint(&mut state);
+        state.process_input(Input::Down);
+        state.process_input(Input::Back);
+        assert_eq!(playing(&state).investigations(), [(2, Team::Fascist)]);
+    }
+
+    #[test]
+    fn president_notes_disabled() {
+        let mut state = check_party_state(false);
+        dismiss_hint(&mut state);
+        assert_eq!(state.display_action_hint(), None);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert!(state.screen(&runtime_info()).is_none());
+        // There is no notes item in the menu, so End game is the last item
+        state.process_input(Input::Click);
+        assert_eq!(
+            state
+                .screen(&runtime_info())
+                .unwrap()
+                .items
+                .last()
+                .map(|item| item.as_str()),
+            Some(PlayingMenuSelectedItem::EndGame.label())
+        );
+        for _ in 0..PlayingMenuSelectedItem::VARIANTS.len() {
+            state.process_input(Input::Down);
+        }
+        state.process_input(Input::Click);
+        assert!(matches!(
+            playing(&state).screen(),
+            PlayingScreen::ConfirmEndGame { .. }
+        ));
+    }
+
+    #[test]
+    fn president_notes_bound() {
+        let mut state = check_party_state(true);
+        let GameState::Playing(playing) = &mut state else {
+            unreachable!()
+        };
+        playing.record_investigation(2, Team::Liberal);
+        playing.record_investigation(1, Team::Fascist);
+        // The same policy replaces its note
+        playing.record_investigation(2, Team::Fascist);
+        playing.record_investigation(4, Team::Liberal);
+        assert_eq!(
+            playing.investigations(),
+            [(1, Team::Fascist), (2, Team::Fascist), (4, Team::Liberal)]
+        );
+        assert!(log::take_warnings().is_empty());
+        playing.record_investigation(5, Team::Liberal);
+        assert_eq!(playing.investigations().len(), MAX_INVESTIGATIONS);
+        assert_eq!(log::take_warnings().len(), 1);
+        // Replacing still works when full
+        playing.record_investigation(4, Team::Fascist);
+        assert_eq!(playing.investigations()[2], (4, Team::Fascist));
+
+        // A new game starts without notes
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        let address = Address::random([1, 2, 3, 4, 5, 6]);
+        state.ble_peripheral_found(address);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        state.ble_connected(address, 0);
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        assert!(self::playing(&state).investigations().is_empty());
+    }
+
+    #[test]
+    fn debug_dump() {
+        let fascist_board = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+        let mut state = playing_state(7);
+        if let GameState::Playing(state) = &mut state {
+            state.connection_statuses = [ConnectionStatus {
+                peripheral_address: fascist_board,
+                role: PeripheralRole::FascistBoard,
+                state: ConnectState::Connected,
+                since: 3,
+            }]
+            .into_iter()
+            .collect();
+        }
+        state.update_scanned_policy_cards(DetectedPolicyCards {
+            liberal: [PolicyCardId {
+                team: Team::Liberal,
+                id: 0,
+            }]
+            .into_iter()
+            .collect(),
+            fascist: fascist_policies(2).fascist,
+        });
+        state.record_failed_election();
+        state.tick(42);
+        let events = [
+            RecordedEvent::PolicyCard {
+                board: Team::Fascist,
+                card: PolicyCardId {
+                    team: Team::Fascist,
+                    id: 1,
+                },
+                present: true,
+            },
+            RecordedEvent::Input(Input::Click),
+        ];
+        let mut dump = alloc::string::String::new();
+        state
+            .debug_dump(
+                (0..)
+                    .zip(events)
+                    .map(|(i, event)| RecordEntry::new(i, (i + 1) * 1_000, event)),
+                &mut dump,
+            )
+            .unwrap();
+        assert_eq!(
+            dump,
+            "Playing, 7 players, tick 42
+policies: 1 liberal, 2 fascist
+hitler: Secret
+fail streak: 1, chaos pending: false
+pending: Pending(CheckParty)
+misplaced: None
+screen: Board
+link degraded: false, sync pending: true, local only: false
+05:04:03:02:01:00 FascistBoard: Connected since 3
+notes: []
+cards: liberal board [L0], fascist board [F0, F1]
+recent events:
+1000ms placed on Fascist board [F1]
+2000ms Click
+"
+        );
+
+        // Double clicking is for dismissing the hint, so the dump has its own menu item
+        drain_effects(&mut state);
+        state.process_input(Input::DoubleClick);
+        assert!(!drain_effects(&mut state).contains(&GameEffect::DebugDump));
+        click_menu_item(&mut state, PlayingMenuSelectedItem::DebugDump);
+        assert!(drain_effects(&mut state).contains(&GameEffect::DebugDump));
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+
+        // The longest events still fit
+        let mut dump = alloc::string::String::new();
+        state
+            .debug_dump(
+                [RecordEntry::new(
+                    u32::MAX,
+                    u32::MAX,
+                    RecordedEvent::Disconnected(fascist_board),
+                ); DEBUG_DUMP_EVENTS],
+                &mut dump,
+            )
+            .unwrap();
+        assert!(dump.len() < DEBUG_DUMP_LEN);
+    }
+
+    #[test]
+    fn screen_does_not_allocate() {
+        let runtime_info = runtime_info();
+        let assert_no_alloc = |state: &GameState, title: &str| {
+            let allocations = ALLOCATIONS.with(Cell::get);
+            let screen = state.screen(&runtime_info);
+            assert_eq!(ALLOCATIONS.with(Cell::get), allocations);
+            assert_eq!(screen.unwrap().title, title);
+        };
+
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        assert_no_alloc(&state, "Setup");
+
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        state.ble_peripheral_found(Address::random([1, 2, 3, 4, 5, 6]));
+        assert_no_alloc(&state, labels::SCANNING);
+
+        state.process_input(Input::Back);
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert_no_alloc(&state, labels::ABOUT);
+    }
+
+    /// Opens the menu and clicks on `item`
+    fn click_menu_item(state: &mut GameState, item: PlayingMenuSelectedItem) {
+        state.process_input(Input::Click);
+        for _ in 0..item as usize {
+            state.process_input(Input::Down);
+        }
+        state.process_input(Input::Click);
+    }
+
+    #[test]
+    fn adjust_players_clears_hint() {
+        let mut state = check_party_state(false);
+        // 10 players still check a party at 1 fascist policy
+        assert_eq!(state.adjust_player_count(10), Ok(()));
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        drain_effects(&mut state);
+        // 7 players don't
+        assert_eq!(state.adjust_player_count(7), Ok(()));
+        assert_eq!(playing(&state).players(), 7);
+        assert_eq!(state.display_action_hint(), None);
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        // The next fascist policy gives 7 players their check party action
+        state.update_scanned_policy_cards(fascist_policies(2));
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+    }
+
+    #[test]
+    fn adjust_players_invalid() {
+        let mut state = check_party_state(false);
+        for players in [0, 4, 11] {
+            assert_eq!(
+                state.adjust_player_count(players),
+                Err(AdjustPlayersError::NotCovered)
+            );
+        }
+        if let GameState::Playing(state) = &mut state {
+            state.dead_players = 3;
+        }
+        assert_eq!(playing(&state).min_players(), 6);
+        assert_eq!(
+            state.adjust_player_count(5),
+            Err(AdjustPlayersError::TooFewAlive)
+        );
+        // Nothing changed
+        assert_eq!(playing(&state).players(), 9);
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+    }
+
+    #[test]
+    fn adjust_players_screen() {
+        let mut state = playing_state(6);
+        click_menu_item(&mut state, PlayingMenuSelectedItem::AdjustPlayers);
+        assert_eq!(
+            playing(&state).screen(),
+            PlayingScreen::AdjustPlayers { players: 6 }
+        );
+        // Can't go below 5 players
+        for _ in 0..3 {
+            state.process_input(Input::Up);
+        }
+        assert_eq!(
+            state.screen(&runtime_info()).unwrap().items[0],
+            players_text(5)
+        );
+        state.process_input(Input::Down);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert_eq!(playing(&state).players(), 7);
+
+        // Going back keeps the number of players
+        click_menu_item(&mut state, PlayingMenuSelectedItem::AdjustPlayers);
+        state.process_input(Input::Down);
+        state.process_input(Input::Back);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert_eq!(playing(&state).players(), 7);
+    }
+
+    #[test]
+    fn pause_ignores_scans() {
+        let mut state = playing_state(10);
+        // The kill hint needs the dead character area to be scanned
+        state.update_scanned_policy_cards(fascist_policies(4));
+        drain_effects(&mut state);
+        state.take_sync();
+        let before = std::format!("{:?}", playing(&state));
+        assert!(state.scan_dead_character());
+
+        click_menu_item(&mut state, PlayingMenuSelectedItem::Pause);
+        assert!(playing(&state).paused());
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        assert!(!state.scan_policy_slots());
+        assert!(!state.scan_dead_character());
+        assert!(state.screen(&runtime_info()).is_none());
+        // The fascist board is dimmed too
+        assert!(state.get_leds().dimmed);
+        assert!(state.take_sync().unwrap().dimmed);
+        assert_eq!(state.get_leds().brightness(200), 50);
+        assert_eq!(state.get_leds().brightness(1), 1);
+
+        // Cards that were bumped during the break, and scans that were already in flight
+        state.update_scanned_policy_cards(fascist_policies(5));
+        state.update_scanned_policy_cards(fascist_policies(0));
+        state.process_dead_character(CharacterCardId {
+            secret_role: SecretRole::Hitler,
+            id: 0,
+        });
+        // Only clicking resumes
+        state.process_input(Input::Down);
+        state.process_input(Input::Back);
+        assert!(playing(&state).paused());
+        assert!(log::take_warnings().is_empty());
+
+        state.process_input(Input::Click);
+        assert!(!playing(&state).paused());
+        assert!(!state.get_leds().dimmed);
+        assert_eq!(state.get_leds().brightness(200), 200);
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+        assert!(state.take_sync().is_some());
+        assert_eq!(std::format!("{:?}", playing(&state)), before);
+    }
+
+    #[test]
+    fn expected_scan_activity() {
+        let mut state = playing_state(6);
+        // The first legislative session is about to start
+        assert_eq!(state.expected_scan_activity(), ScanActivity::High);
+        state.tick(SCAN_ACTIVITY_HIGH_TICKS - 1);
+        assert_eq!(state.expected_scan_activity(), ScanActivity::High);
+        state.tick(SCAN_ACTIVITY_HIGH_TICKS);
+        assert_eq!(state.expected_scan_activity(), ScanActivity::Normal);
+        state.tick(SCAN_ACTIVITY_LOW_TICKS - 1);
+        assert_eq!(state.expected_scan_activity(), ScanActivity::Normal);
+        state.tick(SCAN_ACTIVITY_LOW_TICKS);
+        assert_eq!(state.expected_scan_activity(), ScanActivity::Low);
+
+        // A card placed at a low tempo is still seen, just later
+        let tick = SCAN_ACTIVITY_LOW_TICKS + 2;
+        state.tick(tick);
+        state.update_scanned_policy_cards(fascist_policies(1));
+        assert_eq!(playing(&state).fascist_policies_placed, 1);
+        assert_eq!(state.expected_scan_activity(), ScanActivity::High);
+        state.tick(tick + SCAN_ACTIVITY_HIGH_TICKS);
+        // Scanning the same cards again isn't a change
+        state.update_scanned_policy_cards(fascist_policies(1));
+        assert_eq!(state.expected_scan_activity(), ScanActivity::Normal);
+
+        // Stays high until the pending action is done
+        state.update_scanned_policy_cards(fascist_policies(3));
+        assert!(state.display_action_hint().is_some());
+        state.tick(tick + SCAN_ACTIVITY_LOW_TICKS * 2);
+        assert_eq!(state.expected_scan_activity(), ScanActivity::High);
+
+        // Nothing is scanned while paused
+        click_menu_item(&mut state, PlayingMenuSelectedItem::Pause);
+        assert_eq!(state.expected_scan_activity(), ScanActivity::Low);
+    }
+
+    #[test]
+    fn playing_menu() {
+        let mut state = check_party_state(false);
+        state.process_input(Input::Click);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::GAME_MENU_TITLE);
+        assert_eq!(
+            screen.items,
+            [
+                "Dismiss hint",
+                "Pause game",
+                "Adjust players",
+                "NFC debug",
+                "Log state",
+                "End game"
+            ]
+        );
+        assert!(matches!(screen.selected_item, SelectedItem::Item(0)));
+        // Going back doesn't dismiss the hint
+        state.process_input(Input::Back);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+
+        // Keep playing is selected first
+        click_menu_item(&mut state, PlayingMenuSelectedItem::EndGame);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::END_GAME_TITLE);
+        assert_eq!(screen.items, ["Keep playing", "End game"]);
+        state.process_input(Input::Click);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+    }
+
+    #[test]
+    fn double_click_dismisses_hint() {
+        let mut state = check_party_state(false);
+        // From the menu
+        state.process_input(Input::Click);
+        state.process_input(Input::DoubleClick);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert_eq!(state.display_action_hint(), None);
+
+        // Ignored during the break, and while setting up
+        let mut state = check_party_state(false);
+        click_menu_item(&mut state, PlayingMenuSelectedItem::Pause);
+        state.process_input(Input::DoubleClick);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Paused);
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+
+        let mut state = GameState::new(None, Default::default(), Default::default());
+        let screen = state.screen(&runtime_info()).unwrap();
+        state.process_input(Input::DoubleClick);
+        assert_eq!(state.screen(&runtime_info()).unwrap(), screen);
+    }
+
+    #[test]
+    fn toggle_reader_debug() {
+        let mut state = check_party_state(false);
+        assert!(!playing(&state).reader_debug());
+        click_menu_item(&mut state, PlayingMenuSelectedItem::ReaderDebug);
+        assert!(playing(&state).reader_debug());
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        // The hint is still there
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+        click_menu_item(&mut state, PlayingMenuSelectedItem::ReaderDebug);
+        assert!(!playing(&state).reader_debug());
+    }
+
+    #[test]
+    fn supply_popup_once() {
+        let mut state = check_party_state(false);
+        drain_effects(&mut state);
+        state.supply_level_changed(SupplyLevel::Low);
+        assert!(drain_effects(&mut state).contains(&GameEffect::RedrawScreen));
+        assert!(state.supply().popup);
+        assert!(!state.get_leds().dimmed);
+        // Dismissing the popup doesn't open the menu or dismiss the hint
+        state.process_input(Input::Click);
+        assert!(!state.supply().popup);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert_eq!(state.display_action_hint(), Some(FascistAction::CheckParty));
+
+        // Getting worse doesn't show it again
+        state.supply_level_changed(SupplyLevel::Critical);
+        assert!(!state.supply().popup);
+        assert!(state.get_leds().dimmed);
+        state.supply_level_changed(SupplyLevel::Normal);
+        assert!(!state.get_leds().dimmed);
+        // It is shown again after recovering
+        state.supply_level_changed(SupplyLevel::Critical);
+        assert!(state.supply().popup);
+        assert!(state.get_leds().dimmed);
+        // Recovering also hides it
+        state.supply_level_changed(SupplyLevel::Normal);
+        assert!(!state.supply().popup);
+        assert_eq!(log::take_warnings().len(), 5);
+    }
+
+    #[test]
+    fn end_game() {
+        let address = Address::random([1, 2, 3, 4, 5, 6]);
+        let known_peripherals: heapless::Vec<_, KNOWN_PERIPHERALS_SIZE> = [KnownPeripheral {
+            address: address.addr,
+            name: "Fascist".try_into().unwrap(),
+        }]
+        .into_iter()
+        .collect();
+        let mut state =
+            GameState::new(Some(address), known_peripherals.clone(), Default::default());
+        state.ble_connected(address, 0);
+        state.process_input(Input::Click);
+        state.update_scanned_policy_cards(fascist_policies(2));
+        drain_effects(&mut state);
+
+        click_menu_item(&mut state, PlayingMenuSelectedItem::EndGame);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        let GameState::SettingUp(setting_up) = &state else {
+            panic!("the game didn't end");
+        };
+        assert!(matches!(setting_up.screen, GameScreen::MainMenu(_)));
+        assert_eq!(setting_up.known_peripherals, known_peripherals);
+        // Still connected to the fascist board
+        assert_eq!(
+            state.ble_action(),
+            BleAction::MaintainConnections([address].into_iter().collect())
+        );
+        assert_eq!(drain_effects(&mut state), [GameEffect::RedrawScreen]);
+
+        // A new game starts from the beginning
+        state.process_input(Input::Click);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert_eq!(state.get_leds().fascist_policy_leds, 0);
+        assert_eq!(state.display_action_hint(), None);
+    }
+
+    #[test]
+    fn menu_works_while_disconnected() {
+        let address = Address::random([1, 2, 3, 4, 5, 6]);
+        let mut state = GameState::new(Some(address), Default::default(), Default::default());
+        state.ble_connected(address, 0);
+        state.process_input(Input::Click);
+        state.update_scanned_policy_cards(fascist_policies(2));
+        state.ble_disconnected(address, 0);
+        let pending_action = playing(&state).pending_action;
+
+        // Only dismissing the hint is held back
+        state.process_input(Input::DoubleClick);
+        assert_eq!(playing(&state).pending_action, pending_action);
+        state.process_input(Input::Click);
+        assert!(matches!(
+            playing(&state).screen(),
+            PlayingScreen::Menu { .. }
+        ));
+        state.process_input(Input::Back);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+
+        click_menu_item(&mut state, PlayingMenuSelectedItem::EndGame);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert!(matches!(state, GameState::SettingUp(_)));
+    }
+
+    #[test]
+    fn local_only_navigation() {
+        let mut state = GameState::new_local_only(Default::default(), Default::default());
+        assert_eq!(state.ble_action(), BleAction::Off);
+        // The Bluetooth screen only says that BLE is unavailable
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        let screen = state.screen(&runtime_info()).unwrap();
+        assert_eq!(screen.title, labels::BLE_UNAVAILABLE);
+        assert!(screen.items.is_empty());
+        assert!(matches!(screen.selected_item, SelectedItem::Back));
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert_eq!(state.screen(&runtime_info()).unwrap().title, "Setup");
+
+        // A game can be started without a fascist board
+        state.process_input(Input::Up);
+        state.process_input(Input::Click);
+        assert_eq!(playing(&state).screen(), PlayingScreen::Board);
+        assert!(state.local_only());
+        assert_eq!(state.ble_action(), BleAction::Off);
+        state.update_scanned_policy_cards(fascist_policies(1));
+        assert_eq!(state.get_leds().fascist_policy_leds, 1);
+        assert_eq!(state.take_sync(), None);
+
+        // Ending the game goes back to playing locally
+        click_menu_item(&mut state, PlayingMenuSelectedItem::EndGame);
+        state.process_input(Input::Down);
+        state.process_input(Input::Click);
+        assert!(matches!(state, GameState::SettingUp(_)));
+        assert!(state.local_only());
+        assert!(log::take_warnings().is_empty());
+    }
+
+    #[test]
+    fn local_only_ignores_ble() {
+        let address = Address::random([1, 2, 3, 4, 5, 6]);
+        let mut state = GameState::new_local_only(Default::default(), Default::default());
+        let before = state.clone();
+        state.ble_peripheral_found(address);
+        state.ble_connected(address, 1);
+        state.ble_disconnected(address, 2);
+        assert_eq!(state, before);
+        assert_eq!(log::take_warnings().len(), 3);
+
+        state.process_input(Input::Click);
+        let before = state.clone();
+        state.ble_peripheral_found(address);
+        state.ble_connected(address, 1);
+        state.ble_disconnected(address, 2);
+        assert_eq!(state, before);
+        assert!(!playing(&state).link_degraded());
+        assert_eq!(log::take_warnings().len(), 3);
     }
 }
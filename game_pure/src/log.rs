@@ -0,0 +1,120 @@
+use core::fmt;
+
+use trouble_host::prelude::BdAddr;
+
+use crate::{CharacterCardId, fmt_bd_addr};
+
+/// Logs a warning with defmt when the `defmt` feature is enabled.
+/// In tests, the message is also formatted into a sink that tests can check with [`take_warnings`].
+/// Otherwise it compiles to nothing.
+/// Arguments must implement both [`defmt::Format`] and [`core::fmt::Display`].
+macro_rules! log_warn {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "defmt")]
+        defmt::warn!($($arg)*);
+        #[cfg(any(test, not(feature = "defmt")))]
+        $crate::log::sink(format_args!($($arg)*));
+    }};
+}
+pub(crate) use log_warn;
+
+/// Logs info with defmt when the `defmt` feature is enabled. Otherwise it compiles to nothing.
+macro_rules! log_info {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "defmt")]
+        defmt::info!($($arg)*);
+    }};
+}
+pub(crate) use log_info;
+
+/// Formats a [`BdAddr`] as `XX:XX:XX:XX:XX:XX`, with both defmt and `core::fmt`
+#[derive(Debug, Clone, Copy)]
+pub struct BdAddrFmt(pub BdAddr);
+
+impl fmt::Display for BdAddrFmt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&fmt_bd_addr(&self.0))
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for BdAddrFmt {
+    fn format(&self, f: defmt::Formatter) {
+        let a = self.0.into_inner();
+        defmt::write!(
+            f,
+            "{=u8:02X}:{=u8:02X}:{=u8:02X}:{=u8:02X}:{=u8:02X}:{=u8:02X}",
+            a[5],
+            a[4],
+            a[3],
+            a[2],
+            a[1],
+            a[0]
+        );
+    }
+}
+
+impl fmt::Display for CharacterCardId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}#{}", self.secret_role, self.id)
+    }
+}
+
+#[cfg(feature = "defmt")]
+impl defmt::Format for CharacterCardId {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{}#{=usize}", self.secret_role, self.id);
+    }
+}
+
+#[cfg(not(any(test, feature = "defmt")))]
+#[inline(always)]
+pub(crate) fn sink(_args: fmt::Arguments) {}
+
+#[cfg(test)]
+extern crate std;
+
+#[cfg(test)]
+std::thread_local! {
+    static WARNINGS: core::cell::RefCell<alloc::vec::Vec<alloc::string::String>> =
+        const { core::cell::RefCell::new(alloc::vec::Vec::new()) };
+}
+
+#[cfg(test)]
+pub(crate) fn sink(args: fmt::Arguments) {
+    WARNINGS.with_borrow_mut(|warnings| warnings.push(alloc::format!("{args}")));
+}
+
+/// Returns the warnings logged by the current thread since the last call
+#[cfg(test)]
+pub(crate) fn take_warnings() -> alloc::vec::Vec<alloc::string::String> {
+    WARNINGS.take()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::SecretRole;
+
+    #[test]
+    fn format_shims() {
+        let address = BdAddr::new([0x0A, 0x1B, 0x2C, 0x3D, 0x4E, 0xF5]);
+        assert_eq!(
+            alloc::format!("{}", BdAddrFmt(address)),
+            "F5:4E:3D:2C:1B:0A"
+        );
+        let character = CharacterCardId {
+            secret_role: SecretRole::Hitler,
+            id: 0,
+        };
+        assert_eq!(alloc::format!("{character}"), "Hitler#0");
+    }
+
+    #[test]
+    fn sink_collects_warnings() {
+        log_warn!("Warning {}", 1);
+        log_warn!("Warning {}", 2);
+        assert_eq!(take_warnings(), ["Warning 1", "Warning 2"]);
+        assert!(take_warnings().is_empty());
+    }
+}
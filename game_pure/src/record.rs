@@ -0,0 +1,304 @@
+//! A compact record of the events that change the [`GameState`](crate::GameState) on the liberal board,
+//! so that a bug seen on the real boards can be reproduced on the host by replaying the record.
+//!
+//! Each event is a [`RecordEntry`] of [`RECORD_ENTRY_LEN`] bytes, so the record can be kept in a circular buffer
+//! and dumped as raw bytes. Erased flash reads as `0xFF`, which is never a valid entry, so unused entries are skipped.
+//!
+//! An entry is laid out as:
+//! - The sequence number, as a little endian `u32`
+//! - The timestamp in ms, as a little endian `u32`
+//! - The kind of event
+//! - 7 bytes of data for the event
+
+use alloc::vec::Vec;
+
+use trouble_host::{
+    Address,
+    prelude::{AddrKind, BdAddr},
+};
+
+use crate::{Input, PolicyCardId, SupplyLevel, Team};
+
+pub const RECORD_ENTRY_LEN: usize = 16;
+
+/// The kinds of addresses that can be recorded
+const ADDR_KINDS: [AddrKind; 4] = [
+    AddrKind::PUBLIC,
+    AddrKind::RANDOM,
+    AddrKind::RESOLVABLE_PRIVATE_OR_PUBLIC,
+    AddrKind::RESOLVABLE_PRIVATE_OR_RANDOM,
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecordedEvent {
+    Input(Input),
+    /// A debounced change to the policy cards on `board`
+    PolicyCard {
+        board: Team,
+        card: PolicyCardId,
+        present: bool,
+    },
+    PeripheralFound(Address),
+    Connected(Address),
+    Disconnected(Address),
+    SupplyLevel(SupplyLevel),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RecordDecodeError {
+    /// The entry was never written
+    Empty,
+    Invalid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecordEntry {
+    /// Increases by one for each entry, so that the oldest entry in a circular buffer can be found
+    pub sequence: u32,
+    /// Wraps after about 49 days, which is much longer than a game
+    pub timestamp_ms: u32,
+    kind: u8,
+    data: [u8; 7],
+}
+
+impl RecordEntry {
+    pub fn new(sequence: u32, timestamp_ms: u32, event: RecordedEvent) -> Self {
+        let mut data = [0; 7];
+        let mut address = |address: Address| {
+            data[0] = address.kind.into_inner();
+            data[1..].copy_from_slice(&address.addr.into_inner());
+        };
+        let kind = match event {
+            RecordedEvent::Input(input) => {
+                data[0] = match input {
+                    Input::Up => 0,
+                    Input::Down => 1,
+                    Input::Click => 2,
+                    Input::Back => 3,
+                    Input::DoubleClick => 4,
+                };
+                0
+            }
+            RecordedEvent::PolicyCard {
+                board,
+                card,
+                present,
+            } => {
+                data[..4].copy_from_slice(&[
+                    team_byte(board),
+                    team_byte(card.team),
+                    card.id as u8,
+                    present.into(),
+                ]);
+                1
+            }
+            RecordedEvent::PeripheralFound(found) => {
+                address(found);
+                2
+            }
+            RecordedEvent::Connected(connected) => {
+                address(connected);
+                3
+            }
+            RecordedEvent::Disconnected(disconnected) => {
+                address(disconnected);
+                4
+            }
+            RecordedEvent::SupplyLevel(level) => {
+                data[0] = match level {
+                    SupplyLevel::Normal => 0,
+                    SupplyLevel::Low => 1,
+                    SupplyLevel::Critical => 2,
+                };
+                5
+            }
+        };
+        Self {
+            sequence,
+            timestamp_ms,
+            kind,
+            data,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; RECORD_ENTRY_LEN] {
+        let mut bytes = [0; RECORD_ENTRY_LEN];
+        bytes[..4].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.timestamp_ms.to_le_bytes());
+        bytes[8] = self.kind;
+        bytes[9..].copy_from_slice(&self.data);
+        bytes
+    }
+
+    /// Any bytes make an entry, but [`RecordEntry::event`] checks if it is valid
+    pub fn from_bytes(bytes: &[u8; RECORD_ENTRY_LEN]) -> Self {
+        Self {
+            sequence: u32::from_le_bytes(bytes[..4].try_into().unwrap()),
+            timestamp_ms: u32::from_le_bytes(bytes[4..8].try_into().unwrap()),
+            kind: bytes[8],
+            data: bytes[9..].try_into().unwrap(),
+        }
+    }
+
+    pub fn event(&self) -> Result<RecordedEvent, RecordDecodeError> {
+        let address = || {
+            Ok(Address {
+                kind: *ADDR_KINDS
+                    .iter()
+                    .find(|kind| kind.into_inner() == self.data[0])
+                    .ok_or(RecordDecodeError::Invalid)?,
+                addr: BdAddr::new(self.data[1..].try_into().unwrap()),
+            })
+        };
+        Ok(match self.kind {
+            0 => RecordedEvent::Input(match self.data[0] {
+                0 => Input::Up,
+                1 => Input::Down,
+                2 => Input::Click,
+                3 => Input::Back,
+                4 => Input::DoubleClick,
+                _ => return Err(RecordDecodeError::Invalid),
+            }),
+            1 => RecordedEvent::PolicyCard {
+                board: team_from_byte(self.data[0])?,
+                card: PolicyCardId {
+                    team: team_from_byte(self.data[1])?,
+                    id: self.data[2].into(),
+                },
+                present: match self.data[3] {
+                    0 => false,
+                    1 => true,
+                    _ => return Err(RecordDecodeError::Invalid),
+                },
+            },
+            2 => RecordedEvent::PeripheralFound(address()?),
+            3 => RecordedEvent::Connected(address()?),
+            4 => RecordedEvent::Disconnected(address()?),
+            5 => RecordedEvent::SupplyLevel(match self.data[0] {
+                0 => SupplyLevel::Normal,
+                1 => SupplyLevel::Low,
+                2 => SupplyLevel::Critical,
+                _ => return Err(RecordDecodeError::Invalid),
+            }),
+            0xFF => return Err(RecordDecodeError::Empty),
+            _ => return Err(RecordDecodeError::Invalid),
+        })
+    }
+}
+
+fn team_byte(team: Team) -> u8 {
+    match team {
+        Team::Liberal => 0,
+        Team::Fascist => 1,
+    }
+}
+
+fn team_from_byte(byte: u8) -> Result<Team, RecordDecodeError> {
+    match byte {
+        0 => Ok(Team::Liberal),
+        1 => Ok(Team::Fascist),
+        _ => Err(RecordDecodeError::Invalid),
+    }
+}
+
+/// The entries of a dumped circular buffer, oldest first. Entries that were never written are left out.
+/// A partial entry at the end is ignored.
+pub fn read_record(bytes: &[u8]) -> Vec<RecordEntry> {
+    let mut entries = bytes
+        .as_chunks::<RECORD_ENTRY_LEN>()
+        .0
+        .iter()
+        .map(RecordEntry::from_bytes)
+        .filter(|entry| entry.event() != Err(RecordDecodeError::Empty))
+        .collect::<Vec<_>>();
+    entries.sort_by_key(|entry| entry.sequence);
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn events() -> [RecordedEvent; 7] {
+        let address = Address {
+            kind: AddrKind::RANDOM,
+            addr: BdAddr::new([0x0a, 0x1b, 0x2c, 0x3d, 0x4e, 0xf5]),
+        };
+        [
+            RecordedEvent::Input(Input::Back),
+            RecordedEvent::PolicyCard {
+                board: Team::Liberal,
+                card: PolicyCardId {
+                    team: Team::Fascist,
+                    id: 10,
+                },
+                present: true,
+            },
+            RecordedEvent::PolicyCard {
+                board: Team::Fascist,
+                card: PolicyCardId {
+                    team: Team::Liberal,
+                    id: 0,
+                },
+                present: false,
+            },
+            RecordedEvent::PeripheralFound(address),
+            RecordedEvent::Connected(address),
+            RecordedEvent::Disconnected(Address {
+                kind: AddrKind::PUBLIC,
+                ..address
+            }),
+            RecordedEvent::SupplyLevel(SupplyLevel::Critical),
+        ]
+    }
+
+    #[test]
+    fn encode_decode() {
+        for (i, event) in events().into_iter().enumerate() {
+            let entry = RecordEntry::new(i as u32, 1_000 * i as u32, event);
+            let decoded = RecordEntry::from_bytes(&entry.to_bytes());
+            assert_eq!(decoded.sequence, i as u32);
+            assert_eq!(decoded.timestamp_ms, 1_000 * i as u32);
+            assert_eq!(decoded.event(), Ok(event));
+        }
+        // Little endian, so that dumps read the same on any host
+        let entry = RecordEntry::new(0x0102, 0x0304, RecordedEvent::Input(Input::Click));
+        assert_eq!(
+            entry.to_bytes(),
+            [2, 1, 0, 0, 4, 3, 0, 0, 0, 2, 0, 0, 0, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn invalid() {
+        let mut bytes = [0xFF; RECORD_ENTRY_LEN];
+        let entry = RecordEntry::from_bytes(&bytes);
+        assert_eq!(entry.event(), Err(RecordDecodeError::Empty));
+        bytes[8] = 5;
+        let entry = RecordEntry::from_bytes(&bytes);
+        assert_eq!(entry.event(), Err(RecordDecodeError::Invalid));
+        // An input that doesn't exist
+        bytes[8] = 0;
+        bytes[9] = 5;
+        let entry = RecordEntry::from_bytes(&bytes);
+        assert_eq!(entry.event(), Err(RecordDecodeError::Invalid));
+    }
+
+    #[test]
+    fn wrapped_buffer() {
+        // The buffer wrapped around, so the newest entries are at the start
+        let mut bytes = Vec::new();
+        for sequence in [4, 5, 2, 3] {
+            let event = RecordedEvent::Input(Input::Down);
+            bytes.extend_from_slice(&RecordEntry::new(sequence, 0, event).to_bytes());
+        }
+        bytes.extend_from_slice(&[0xFF; RECORD_ENTRY_LEN]);
+        // Cut off while dumping
+        bytes.extend_from_slice(&[0; 3]);
+        let sequences = read_record(&bytes)
+            .iter()
+            .map(|entry| entry.sequence)
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, [2, 3, 4, 5]);
+    }
+}
@@ -0,0 +1,396 @@
+//! The newest [`RecordEntry`]s kept in a dedicated flash region, so that the record of the last session
+//! survives a reset or a crash and can be dumped on the next boot.
+//!
+//! The region is [`RECORD_LOG_SECTORS`] sectors of [`RECORD_LOG_SECTOR_LEN`] bytes, separate from NVS.
+//! Each boot starts a new session in the sector after the newest one, erasing the oldest sector,
+//! so the previous session is still in flash when [`read_last_session`] reads it at boot.
+//! When a sector is full, the log moves on to the next sector the same way.
+//!
+//! A sector is laid out as:
+//! - A header of [`RECORD_LOG_HEADER_LEN`] bytes: a magic number, the sector's sequence number (which only goes up),
+//!   the session number, and a checksum of those, each as a little endian `u32`
+//! - Slots of [`RECORD_LOG_SLOT_LEN`] bytes, written in order: a [`RecordEntry`] and a little endian `u32` checksum of it
+//!
+//! A slot that is all `0xFF` was never written, so it is the end of the sector.
+//! A write that was cut off by a reset leaves a slot with a wrong checksum, which is skipped.
+//! A header that was cut off makes the whole sector unused.
+
+use alloc::vec::Vec;
+
+use crate::{
+    log::log_warn,
+    record::{RECORD_ENTRY_LEN, RecordEntry},
+};
+
+pub const RECORD_LOG_SECTOR_LEN: u32 = 4096;
+pub const RECORD_LOG_SECTORS: u32 = 2;
+/// The size of the flash partition
+pub const RECORD_LOG_LEN: u32 = RECORD_LOG_SECTOR_LEN * RECORD_LOG_SECTORS;
+pub const RECORD_LOG_HEADER_LEN: u32 = 16;
+pub const RECORD_LOG_SLOT_LEN: u32 = RECORD_ENTRY_LEN as u32 + 4;
+pub const RECORD_LOG_SLOTS_PER_SECTOR: u32 =
+    (RECORD_LOG_SECTOR_LEN - RECORD_LOG_HEADER_LEN) / RECORD_LOG_SLOT_LEN;
+
+const MAGIC: u32 = u32::from_le_bytes(*b"RLOG");
+
+/// The flash region, with offsets from the start of the region.
+/// Offsets and lengths are multiples of 4, which is what the ESP32-C3 can write.
+pub trait RecordLogFlash {
+    type Error;
+
+    fn read(
+        &mut self,
+        offset: u32,
+        bytes: &mut [u8],
+    ) -> impl Future<Output = Result<(), Self::Error>>;
+    /// Only changes bits from 1 to 0, like NOR flash
+    fn write(&mut self, offset: u32, bytes: &[u8])
+    -> impl Future<Output = Result<(), Self::Error>>;
+    /// Sets the [`RECORD_LOG_SECTOR_LEN`] bytes at `offset` to `0xFF`
+    fn erase_sector(&mut self, offset: u32) -> impl Future<Output = Result<(), Self::Error>>;
+}
+
+/// FNV-1a, which is small and good enough to tell a torn write apart
+fn checksum(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0x811c_9dc5, |hash, byte| {
+        (hash ^ u32::from(*byte)).wrapping_mul(0x0100_0193)
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct SectorHeader {
+    sequence: u32,
+    session: u32,
+}
+
+impl SectorHeader {
+    fn to_bytes(self) -> [u8; RECORD_LOG_HEADER_LEN as usize] {
+        let mut bytes = [0; RECORD_LOG_HEADER_LEN as usize];
+        bytes[..4].copy_from_slice(&MAGIC.to_le_bytes());
+        bytes[4..8].copy_from_slice(&self.sequence.to_le_bytes());
+        bytes[8..12].copy_from_slice(&self.session.to_le_bytes());
+        let checksum = checksum(&bytes[..12]);
+        bytes[12..].copy_from_slice(&checksum.to_le_bytes());
+        bytes
+    }
+
+    /// `None` if the sector is erased or its header was cut off
+    fn from_bytes(bytes: &[u8; RECORD_LOG_HEADER_LEN as usize]) -> Option<Self> {
+        let word = |i: usize| u32::from_le_bytes(bytes[i..i + 4].try_into().unwrap());
+        (word(0) == MAGIC && word(12) == checksum(&bytes[..12])).then(|| Self {
+            sequence: word(4),
+            session: word(8),
+        })
+    }
+}
+
+/// The valid headers of all sectors, by sector index
+async fn read_headers<F: RecordLogFlash>(
+    flash: &mut F,
+) -> Result<[Option<SectorHeader>; RECORD_LOG_SECTORS as usize], F::Error> {
+    let mut headers = [None; RECORD_LOG_SECTORS as usize];
+    for (sector, header) in headers.iter_mut().enumerate() {
+        let mut bytes = [0; RECORD_LOG_HEADER_LEN as usize];
+        flash
+            .read(sector as u32 * RECORD_LOG_SECTOR_LEN, &mut bytes)
+            .await?;
+        *header = SectorHeader::from_bytes(&bytes);
+    }
+    Ok(headers)
+}
+
+/// The entries that were in flash at boot
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct RecoveredLog {
+    /// `None` if nothing was ever logged
+    pub session: Option<u32>,
+    /// Oldest first
+    pub entries: Vec<RecordEntry>,
+    /// Slots that were cut off while being written, which is expected for the last write before a reset
+    pub torn: usize,
+}
+
+/// Reads the entries of the newest session in flash. Call this before [`RecordLog::start_session`],
+/// which erases the oldest sector.
+pub async fn read_last_session<F: RecordLogFlash>(flash: &mut F) -> Result<RecoveredLog, F::Error> {
+    let headers = read_headers(flash).await?;
+    let Some(session) = headers.iter().flatten().map(|header| header.session).max() else {
+        return Ok(RecoveredLog::default());
+    };
+    // The sectors of the session, in the order they were written, which isn't their order in flash after wrapping around
+    let mut sectors = headers
+        .iter()
+        .enumerate()
+        .filter_map(|(sector, header)| {
+            header
+                .filter(|header| header.session == session)
+                .map(|header| (header.sequence, sector as u32))
+        })
+        .collect::<Vec<_>>();
+    sectors.sort_unstable();
+    let mut log = RecoveredLog {
+        session: Some(session),
+        ..Default::default()
+    };
+    for (_, sector) in sectors {
+        for slot in 0..RECORD_LOG_SLOTS_PER_SECTOR {
+            let mut bytes = [0; RECORD_LOG_SLOT_LEN as usize];
+            flash.read(slot_offset(sector, slot), &mut bytes).await?;
+            if bytes.iter().all(|byte| *byte == 0xFF) {
+                break;
+            }
+            let (entry, check) = bytes.split_at(RECORD_ENTRY_LEN);
+            if u32::from_le_bytes(check.try_into().unwrap()) == checksum(entry) {
+                log.entries
+                    .push(RecordEntry::from_bytes(entry.try_into().unwrap()));
+            } else {
+                log.torn += 1;
+            }
+        }
+    }
+    Ok(log)
+}
+
+fn slot_offset(sector: u32, slot: u32) -> u32 {
+    sector * RECORD_LOG_SECTOR_LEN + RECORD_LOG_HEADER_LEN + slot * RECORD_LOG_SLOT_LEN
+}
+
+/// Appends entries to the flash region
+pub struct RecordLog<F> {
+    flash: F,
+    sector: u32,
+    header: SectorHeader,
+    next_slot: u32,
+}
+
+impl<F: RecordLogFlash> RecordLog<F> {
+    /// Starts a new session in the sector after the newest one
+    pub async fn start_session(mut flash: F) -> Result<Self, F::Error> {
+        let headers = read_headers(&mut flash).await?;
+        let newest = headers
+            .iter()
+            .enumerate()
+            .filter_map(|(sector, header)| header.map(|header| (sector as u32, header)))
+            .max_by_key(|(_, header)| header.sequence);
+        let session = headers
+            .iter()
+            .flatten()
+            .map(|header| header.session.wrapping_add(1))
+            .max()
+            .unwrap_or_default();
+        let (sector, header) = match newest {
+            Some((sector, header)) => (
+                (sector + 1) % RECORD_LOG_SECTORS,
+                SectorHeader {
+                    sequence: header.sequence.wrapping_add(1),
+                    session,
+                },
+            ),
+            None => (
+                0,
+                SectorHeader {
+                    sequence: 0,
+                    session,
+                },
+            ),
+        };
+        let mut log = Self {
+            flash,
+            sector,
+            header,
+            next_slot: 0,
+        };
+        log.start_sector().await?;
+        Ok(log)
+    }
+
+    async fn start_sector(&mut self) -> Result<(), F::Error> {
+        let offset = self.sector * RECORD_LOG_SECTOR_LEN;
+        self.flash.erase_sector(offset).await?;
+        self.flash.write(offset, &self.header.to_bytes()).await
+    }
+
+    pub fn session(&self) -> u32 {
+        self.header.session
+    }
+
+    /// Appends `entry`, moving on to the next sector if this one is full
+    pub async fn append(&mut self, entry: &RecordEntry) -> Result<(), F::Error> {
+        if self.next_slot == RECORD_LOG_SLOTS_PER_SECTOR {
+            self.sector = (self.sector + 1) % RECORD_LOG_SECTORS;
+            self.header.sequence = self.header.sequence.wrapping_add(1);
+            self.next_slot = 0;
+            if let Err(e) = self.start_sector().await {
+                // Without a header, nothing in this sector would be read back
+                self.next_slot = RECORD_LOG_SLOTS_PER_SECTOR;
+                return Err(e);
+            }
+        }
+        let entry = entry.to_bytes();
+        let mut bytes = [0; RECORD_LOG_SLOT_LEN as usize];
+        bytes[..RECORD_ENTRY_LEN].copy_from_slice(&entry);
+        bytes[RECORD_ENTRY_LEN..].copy_from_slice(&checksum(&entry).to_le_bytes());
+        let offset = slot_offset(self.sector, self.next_slot);
+        // A failed write can leave the slot partly written, so it isn't written again
+        self.next_slot += 1;
+        self.flash.write(offset, &bytes).await
+    }
+
+    /// Appends `entries`, logging errors instead of returning them, since the log is only for diagnostics
+    pub async fn append_all(&mut self, entries: impl IntoIterator<Item = RecordEntry>) {
+        for entry in entries {
+            if self.append(&entry).await.is_err() {
+                log_warn!(
+                    "Failed to append entry {} to the flash record log",
+                    entry.sequence
+                );
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+    use crate::{Input, record::RecordedEvent};
+
+    fn block_on<F: Future>(future: F) -> F::Output {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => unreachable!(),
+        }
+    }
+
+    struct MockFlash {
+        bytes: Vec<u8>,
+        /// The next write only writes this many bytes, like a reset in the middle of the write
+        tear_next_write: Option<usize>,
+    }
+
+    impl MockFlash {
+        fn new() -> Self {
+            Self {
+                bytes: alloc::vec![0xFF; RECORD_LOG_LEN as usize],
+                tear_next_write: None,
+            }
+        }
+    }
+
+    impl RecordLogFlash for &mut MockFlash {
+        type Error = ();
+
+        async fn read(&mut self, offset: u32, bytes: &mut [u8]) -> Result<(), ()> {
+            assert_eq!(offset % 4, 0);
+            let offset = offset as usize;
+            bytes.copy_from_slice(&self.bytes[offset..offset + bytes.len()]);
+            Ok(())
+        }
+
+        async fn write(&mut self, offset: u32, bytes: &[u8]) -> Result<(), ()> {
+            assert_eq!(offset % 4, 0);
+            assert_eq!(bytes.len() % 4, 0);
+            let len = self.tear_next_write.take().unwrap_or(bytes.len());
+            let offset = offset as usize;
+            for (flash, byte) in self.bytes[offset..offset + len].iter_mut().zip(bytes) {
+                *flash &= byte;
+            }
+            Ok(())
+        }
+
+        async fn erase_sector(&mut self, offset: u32) -> Result<(), ()> {
+            assert_eq!(offset % RECORD_LOG_SECTOR_LEN, 0);
+            let offset = offset as usize;
+            self.bytes[offset..offset + RECORD_LOG_SECTOR_LEN as usize].fill(0xFF);
+            Ok(())
+        }
+    }
+
+    fn entry(sequence: u32) -> RecordEntry {
+        RecordEntry::new(sequence, sequence * 10, RecordedEvent::Input(Input::Click))
+    }
+
+    /// Starts a session and appends the entries `sequences`
+    fn session(flash: &mut MockFlash, sequences: core::ops::Range<u32>) -> u32 {
+        let mut log = block_on(RecordLog::start_session(flash)).unwrap();
+        block_on(log.append_all(sequences.map(entry)));
+        log.session()
+    }
+
+    fn sequences(log: &RecoveredLog) -> Vec<u32> {
+        log.entries.iter().map(|entry| entry.sequence).collect()
+    }
+
+    #[test]
+    fn empty() {
+        let mut flash = MockFlash::new();
+        assert_eq!(
+            block_on(read_last_session(&mut &mut flash)),
+            Ok(RecoveredLog::default())
+        );
+        assert_eq!(session(&mut flash, 0..3), 0);
+        let log = block_on(read_last_session(&mut &mut flash)).unwrap();
+        assert_eq!(log.session, Some(0));
+        assert_eq!(log.entries, [entry(0), entry(1), entry(2)]);
+        assert_eq!(log.torn, 0);
+    }
+
+    #[test]
+    fn wrap_around() {
+        let mut flash = MockFlash::new();
+        let len = 3 * RECORD_LOG_SLOTS_PER_SECTOR + 5;
+        session(&mut flash, 0..len);
+        let log = block_on(read_last_session(&mut &mut flash)).unwrap();
+        // The full sector before the one being written is kept
+        assert_eq!(
+            sequences(&log),
+            (len - RECORD_LOG_SLOTS_PER_SECTOR - 5..len).collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn torn_final_write() {
+        let mut flash = MockFlash::new();
+        let mut log = block_on(RecordLog::start_session(&mut flash)).unwrap();
+        block_on(log.append_all((0..3).map(entry)));
+        log.flash.tear_next_write = Some(8);
+        block_on(log.append(&entry(3))).unwrap();
+        let log = block_on(read_last_session(&mut &mut flash)).unwrap();
+        assert_eq!(sequences(&log), [0, 1, 2]);
+        assert_eq!(log.torn, 1);
+    }
+
+    #[test]
+    fn torn_header() {
+        let mut flash = MockFlash::new();
+        session(&mut flash, 0..3);
+        flash.tear_next_write = Some(8);
+        // The new session's header is cut off, so the previous session is still the last one
+        block_on(RecordLog::start_session(&mut flash)).unwrap();
+        let log = block_on(read_last_session(&mut &mut flash)).unwrap();
+        assert_eq!(log.session, Some(0));
+        assert_eq!(sequences(&log), [0, 1, 2]);
+        // The sector is erased again next time
+        assert_eq!(session(&mut flash, 0..1), 1);
+    }
+
+    #[test]
+    fn recovery_order() {
+        let mut flash = MockFlash::new();
+        session(&mut flash, 0..3);
+        // Session 1 starts in sector 1 and wraps around to sector 0, erasing session 0
+        let len = RECORD_LOG_SLOTS_PER_SECTOR + 2;
+        assert_eq!(session(&mut flash, 100..100 + len), 1);
+        let log = block_on(read_last_session(&mut &mut flash)).unwrap();
+        assert_eq!(log.session, Some(1));
+        assert_eq!(sequences(&log), (100..100 + len).collect::<Vec<_>>());
+        // The next session starts in sector 1, and the read before it starts still has session 1's tail
+        assert_eq!(session(&mut flash, 200..202), 2);
+        let log = block_on(read_last_session(&mut &mut flash)).unwrap();
+        assert_eq!(sequences(&log), [200, 201]);
+    }
+}
@@ -0,0 +1,320 @@
+use core::ops::{Deref, DerefMut};
+
+use crate::{
+    BdAddrFmt, SCAN_LIST_SIZE, ScannedPeripheral,
+    log::{log_info, log_warn},
+};
+
+/// The peripherals listed while scanning, in the order that they were listed.
+///
+/// When the list is full, a new peripheral replaces the last saved peripheral that wasn't found,
+/// and then the oldest peripheral that was found. Peripherals with a role and the selected peripheral are never replaced,
+/// so the user doesn't lose what they chose or what they are about to click.
+/// The selection is an index into the list, which follows its peripheral when other peripherals are removed.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ScanList {
+    peripherals: heapless::Vec<ScannedPeripheral, SCAN_LIST_SIZE>,
+}
+
+impl ScanList {
+    pub const fn new() -> Self {
+        Self {
+            peripherals: heapless::Vec::new(),
+        }
+    }
+
+    /// Lists `peripheral`, or refreshes the peripherals that `same` matches.
+    /// If more than one matches, like the private addresses of a bonded peripheral, they are merged into the first one,
+    /// which keeps the role that any of them had.
+    /// `selected` is the selected index in the list, if a peripheral is selected.
+    pub fn insert_or_refresh(
+        &mut self,
+        peripheral: ScannedPeripheral,
+        same: impl Fn(&ScannedPeripheral) -> bool,
+        selected: &mut Option<usize>,
+    ) {
+        if let Some(index) = self.peripherals.iter().position(&same) {
+            let matching = || {
+                self.peripherals
+                    .iter()
+                    .filter(|peripheral| same(peripheral))
+            };
+            let role = matching().find_map(|peripheral| peripheral.role);
+            let known = matching().any(|peripheral| peripheral.known);
+            self.peripherals[index] = ScannedPeripheral {
+                role,
+                known: peripheral.known || known,
+                ..peripheral
+            };
+            // Keep the first one in place, so that the selected peripheral doesn't move
+            let mut i = self.peripherals.len();
+            while i > index + 1 {
+                i -= 1;
+                if same(&self.peripherals[i]) {
+                    self.remove(i, selected, index);
+                }
+            }
+            return;
+        }
+        if self.peripherals.is_full() {
+            let replaceable = |i: usize, peripheral: &ScannedPeripheral| {
+                peripheral.role.is_none() && *selected != Some(i)
+            };
+            let saved = self
+                .peripherals
+                .iter()
+                .enumerate()
+                .rposition(|(i, peripheral)| !peripheral.in_range && replaceable(i, peripheral));
+            let oldest = || {
+                self.peripherals
+                    .iter()
+                    .enumerate()
+                    .position(|(i, peripheral)| replaceable(i, peripheral))
+            };
+            match saved.or_else(oldest) {
+                Some(index) => {
+                    let replaced = self.peripherals[index];
+                    if replaced.in_range {
+                        log_info!(
+                            "The scan list is full, so {} is replaced by {}",
+                            BdAddrFmt(replaced.address.addr),
+                            BdAddrFmt(peripheral.address.addr)
+                        );
+                    }
+                    // The selected peripheral is never replaced
+                    self.remove(index, selected, 0);
+                }
+                None => {
+                    log_warn!(
+                        "Failed to push address {} to list of scanned peripherals because the list is full. Consider rebuilding with a larger max size.",
+                        BdAddrFmt(peripheral.address.addr)
+                    );
+                    return;
+                }
+            }
+        }
+        // There is room after replacing a peripheral
+        let _ = self.peripherals.push(peripheral);
+    }
+
+    /// Removes the peripheral at `index`. If it was selected, `merged_into` is selected instead.
+    fn remove(&mut self, index: usize, selected: &mut Option<usize>, merged_into: usize) {
+        self.peripherals.remove(index);
+        *selected = selected.map(|selected| match selected.cmp(&index) {
+            core::cmp::Ordering::Less => selected,
+            core::cmp::Ordering::Equal => merged_into,
+            core::cmp::Ordering::Greater => selected - 1,
+        });
+    }
+}
+
+/// Saved peripherals that don't fit are left out
+impl FromIterator<ScannedPeripheral> for ScanList {
+    fn from_iter<I: IntoIterator<Item = ScannedPeripheral>>(iter: I) -> Self {
+        Self {
+            peripherals: iter.into_iter().take(SCAN_LIST_SIZE).collect(),
+        }
+    }
+}
+
+impl Deref for ScanList {
+    type Target = [ScannedPeripheral];
+
+    fn deref(&self) -> &Self::Target {
+        &self.peripherals
+    }
+}
+
+/// For changing the roles. The addresses shouldn't be changed, since they are how peripherals are told apart.
+impl DerefMut for ScanList {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.peripherals
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use trouble_host::Address;
+
+    use super::*;
+    use crate::{PeripheralRole, log::take_warnings};
+
+    fn address(i: u8) -> Address {
+        Address::random([i, 0, 0, 0, 0, 0])
+    }
+
+    fn found(i: u8) -> ScannedPeripheral {
+        ScannedPeripheral {
+            address: address(i),
+            role: None,
+            known: false,
+            in_range: true,
+        }
+    }
+
+    fn saved(i: u8) -> ScannedPeripheral {
+        ScannedPeripheral {
+            in_range: false,
+            ..found(i)
+        }
+    }
+
+    /// Inserts `peripheral`, which is the same as another peripheral with the same address
+    fn insert(list: &mut ScanList, peripheral: ScannedPeripheral, selected: &mut Option<usize>) {
+        list.insert_or_refresh(
+            peripheral,
+            |listed| listed.address == peripheral.address,
+            selected,
+        );
+    }
+
+    fn addresses(list: &ScanList) -> heapless::Vec<u8, SCAN_LIST_SIZE> {
+        list.iter()
+            .map(|peripheral| peripheral.address.addr.into_inner()[0])
+            .collect()
+    }
+
+    /// A full list of found peripherals 0 to 3
+    fn full() -> ScanList {
+        (0..SCAN_LIST_SIZE as u8).map(found).collect()
+    }
+
+    #[test]
+    fn refresh() {
+        let mut list: ScanList = [saved(0), found(1)].into_iter().collect();
+        let mut selected = Some(1);
+        list[0].role = Some(PeripheralRole::FascistBoard);
+        insert(&mut list, found(0), &mut selected);
+        insert(&mut list, found(1), &mut selected);
+        assert_eq!(
+            &*list,
+            [
+                ScannedPeripheral {
+                    role: Some(PeripheralRole::FascistBoard),
+                    ..found(0)
+                },
+                found(1)
+            ]
+        );
+        assert_eq!(selected, Some(1));
+    }
+
+    #[test]
+    fn keeps_newest() {
+        let mut list = full();
+        let mut selected = None;
+        insert(&mut list, found(4), &mut selected);
+        assert_eq!(addresses(&list), [1, 2, 3, 4]);
+        insert(&mut list, found(5), &mut selected);
+        assert_eq!(addresses(&list), [2, 3, 4, 5]);
+        // Replacing a found peripheral isn't a problem, since it is listed again when it advertises
+        assert!(take_warnings().is_empty());
+    }
+
+    #[test]
+    fn saved_replaced_first() {
+        let mut list: ScanList = [found(0), saved(1), saved(2), found(3)]
+            .into_iter()
+            .collect();
+        let mut selected = None;
+        insert(&mut list, found(4), &mut selected);
+        assert_eq!(addresses(&list), [0, 1, 3, 4]);
+        insert(&mut list, found(5), &mut selected);
+        assert_eq!(addresses(&list), [0, 3, 4, 5]);
+        insert(&mut list, found(6), &mut selected);
+        assert_eq!(addresses(&list), [3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn selection_follows_peripheral() {
+        let mut list = full();
+        let mut selected = Some(2);
+        insert(&mut list, found(4), &mut selected);
+        assert_eq!(addresses(&list), [1, 2, 3, 4]);
+        assert_eq!(selected, Some(1));
+        // A selection before the replaced peripheral doesn't move
+        let mut list: ScanList = [found(0), saved(1), found(2), found(3)]
+            .into_iter()
+            .collect();
+        let mut selected = Some(0);
+        insert(&mut list, found(4), &mut selected);
+        assert_eq!(addresses(&list), [0, 2, 3, 4]);
+        assert_eq!(selected, Some(0));
+    }
+
+    #[test]
+    fn selected_not_replaced() {
+        // The oldest peripheral is selected, so the next oldest is replaced instead
+        let mut list = full();
+        let mut selected = Some(0);
+        insert(&mut list, found(4), &mut selected);
+        assert_eq!(addresses(&list), [0, 2, 3, 4]);
+        assert_eq!(selected, Some(0));
+        // The same goes for a saved peripheral
+        let mut list: ScanList = [found(0), found(1), found(2), saved(3)]
+            .into_iter()
+            .collect();
+        let mut selected = Some(3);
+        insert(&mut list, found(4), &mut selected);
+        assert_eq!(addresses(&list), [1, 2, 3, 4]);
+        assert_eq!(selected, Some(2));
+    }
+
+    #[test]
+    fn roles_not_replaced() {
+        let mut list = full();
+        list[0].role = Some(PeripheralRole::FascistBoard);
+        list[1].role = Some(PeripheralRole::TrackerBoard);
+        let mut selected = Some(2);
+        insert(&mut list, found(4), &mut selected);
+        assert_eq!(addresses(&list), [0, 1, 2, 4]);
+        assert_eq!(selected, Some(2));
+        // Nothing can be replaced
+        list[3].role = Some(PeripheralRole::FascistBoard);
+        insert(&mut list, found(5), &mut selected);
+        assert_eq!(addresses(&list), [0, 1, 2, 4]);
+        let warnings = take_warnings();
+        assert_eq!(warnings.len(), 1);
+        assert!(warnings[0].contains("00:00:00:00:00:05"));
+    }
+
+    #[test]
+    fn merge() {
+        // 0 and 2 are the same peripheral, like two private addresses of a bonded peripheral
+        let mut list: ScanList = [found(0), found(1), found(2)].into_iter().collect();
+        list[2].role = Some(PeripheralRole::FascistBoard);
+        let same = |peripheral: &ScannedPeripheral| {
+            matches!(peripheral.address.addr.into_inner()[0], 0 | 2 | 9)
+        };
+        let identity = ScannedPeripheral {
+            known: true,
+            ..found(9)
+        };
+        // The selected duplicate is merged into the first one
+        let mut selected = Some(2);
+        list.insert_or_refresh(identity, same, &mut selected);
+        assert_eq!(
+            &*list,
+            [
+                ScannedPeripheral {
+                    role: Some(PeripheralRole::FascistBoard),
+                    ..identity
+                },
+                found(1)
+            ]
+        );
+        assert_eq!(selected, Some(0));
+        // A selection after the duplicate moves up
+        let mut list: ScanList = [found(0), found(2), found(1)].into_iter().collect();
+        let mut selected = Some(2);
+        list.insert_or_refresh(identity, same, &mut selected);
+        assert_eq!(addresses(&list), [9, 1]);
+        assert_eq!(selected, Some(1));
+    }
+
+    #[test]
+    fn from_iter_left_out() {
+        let list: ScanList = (0..SCAN_LIST_SIZE as u8 + 2).map(saved).collect();
+        assert_eq!(addresses(&list), [0, 1, 2, 3]);
+    }
+}
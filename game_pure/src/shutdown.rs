@@ -0,0 +1,229 @@
+//! Restarting or turning off both boards together, so that neither loses what it didn't save yet.
+//!
+//! The liberal board (central) starts it from the main menu. The order is what matters:
+//! each board saves before the fascist board acks, and the liberal board only resets after the ack or a timeout.
+//! The IO is behind traits so that the order can be tested without the hardware.
+
+use crate::{log::log_warn, sync::BoardCommand};
+
+/// How long the liberal board waits for the fascist board's ack before resetting anyway, in ms
+pub const SHUTDOWN_ACK_TIMEOUT_MS: u64 = 2_000;
+
+/// Storage with writes that could still be pending
+pub trait ShutdownStorage {
+    /// Writes everything that is pending. Errors are only logged, since resetting is still better than hanging.
+    fn flush_before_reset(&mut self) -> impl Future<Output = ()>;
+}
+
+/// The liberal board's sync channel to the fascist board
+pub trait CommandTransport {
+    /// Sends `command` to the other board. Returns `false` if there is no connection to send it over.
+    fn send_command(&mut self, command: BoardCommand) -> impl Future<Output = bool>;
+    /// Waits up to `timeout_ms` for the other board to ack the command. Returns `false` if it timed out.
+    fn wait_for_ack(&mut self, timeout_ms: u64) -> impl Future<Output = bool>;
+}
+
+/// The fascist board's sync channel to the liberal board
+pub trait CommandAckTransport {
+    /// Tells the other board that its command is done
+    fn send_ack(&mut self) -> impl Future<Output = ()>;
+}
+
+/// What happened before [`shutdown_central`] called `reset`
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeripheralShutdown {
+    Acked,
+    TimedOut,
+    /// The fascist board wasn't connected, so it has to be reset by hand
+    NotConnected,
+}
+
+/// The liberal board's side: saves, tells the fascist board to shut down, waits for its ack, and then calls `reset`.
+/// `reset` never returns on the hardware.
+pub async fn shutdown_central(
+    storage: &mut impl ShutdownStorage,
+    transport: &mut impl CommandTransport,
+    command: BoardCommand,
+    reset: impl FnOnce(BoardCommand),
+) -> PeripheralShutdown {
+    storage.flush_before_reset().await;
+    let peripheral = if !transport.send_command(command).await {
+        PeripheralShutdown::NotConnected
+    } else if transport.wait_for_ack(SHUTDOWN_ACK_TIMEOUT_MS).await {
+        PeripheralShutdown::Acked
+    } else {
+        log_warn!("The fascist board didn't ack the shutdown, resetting anyway");
+        PeripheralShutdown::TimedOut
+    };
+    reset(command);
+    peripheral
+}
+
+/// The fascist board's side, after receiving `command`: saves, acks, and then calls `reset`.
+/// `reset` never returns on the hardware.
+pub async fn shutdown_peripheral(
+    storage: &mut impl ShutdownStorage,
+    transport: &mut impl CommandAckTransport,
+    command: BoardCommand,
+    reset: impl FnOnce(BoardCommand),
+) {
+    storage.flush_before_reset().await;
+    transport.send_ack().await;
+    reset(command);
+}
+
+#[cfg(test)]
+mod tests {
+    use core::{
+        cell::RefCell,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+
+    use super::*;
+
+    /// The mocks never wait, so this only polls once
+    fn block_on<F: Future>(future: F) -> F::Output {
+        match pin!(future).poll(&mut Context::from_waker(Waker::noop())) {
+            Poll::Ready(output) => output,
+            Poll::Pending => unreachable!(),
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    enum Step {
+        Flush,
+        SendCommand(BoardCommand),
+        WaitForAck(u64),
+        SendAck,
+        Reset(BoardCommand),
+    }
+
+    type Steps = RefCell<alloc::vec::Vec<Step>>;
+
+    struct MockStorage<'a> {
+        steps: &'a Steps,
+    }
+
+    impl ShutdownStorage for MockStorage<'_> {
+        async fn flush_before_reset(&mut self) {
+            self.steps.borrow_mut().push(Step::Flush);
+        }
+    }
+
+    struct MockTransport<'a> {
+        steps: &'a Steps,
+        connected: bool,
+        /// The other board acks before the timeout
+        acks: bool,
+    }
+
+    impl CommandTransport for MockTransport<'_> {
+        async fn send_command(&mut self, command: BoardCommand) -> bool {
+            self.steps.borrow_mut().push(Step::SendCommand(command));
+            self.connected
+        }
+
+        async fn wait_for_ack(&mut self, timeout_ms: u64) -> bool {
+            self.steps.borrow_mut().push(Step::WaitForAck(timeout_ms));
+            self.acks
+        }
+    }
+
+    impl CommandAckTransport for MockTransport<'_> {
+        async fn send_ack(&mut self) {
+            self.steps.borrow_mut().push(Step::SendAck);
+        }
+    }
+
+    const RESTART: BoardCommand = BoardCommand::Shutdown { reboot: true };
+
+    /// Runs [`shutdown_central`] and returns what it did in order
+    fn central(connected: bool, acks: bool) -> (PeripheralShutdown, alloc::vec::Vec<Step>) {
+        let steps = Steps::default();
+        let outcome = block_on(shutdown_central(
+            &mut MockStorage { steps: &steps },
+            &mut MockTransport {
+                steps: &steps,
+                connected,
+                acks,
+            },
+            RESTART,
+            |command| steps.borrow_mut().push(Step::Reset(command)),
+        ));
+        (outcome, steps.into_inner())
+    }
+
+    #[test]
+    fn central_acked() {
+        assert_eq!(
+            central(true, true),
+            (
+                PeripheralShutdown::Acked,
+                alloc::vec![
+                    Step::Flush,
+                    Step::SendCommand(RESTART),
+                    Step::WaitForAck(SHUTDOWN_ACK_TIMEOUT_MS),
+                    Step::Reset(RESTART),
+                ]
+            )
+        );
+        assert!(crate::log::take_warnings().is_empty());
+    }
+
+    #[test]
+    fn central_timed_out() {
+        // Still resets, since the liberal board's own storage was saved
+        assert_eq!(
+            central(true, false),
+            (
+                PeripheralShutdown::TimedOut,
+                alloc::vec![
+                    Step::Flush,
+                    Step::SendCommand(RESTART),
+                    Step::WaitForAck(SHUTDOWN_ACK_TIMEOUT_MS),
+                    Step::Reset(RESTART),
+                ]
+            )
+        );
+        assert_eq!(crate::log::take_warnings().len(), 1);
+    }
+
+    #[test]
+    fn central_not_connected() {
+        // There's nothing to wait for
+        assert_eq!(
+            central(false, false),
+            (
+                PeripheralShutdown::NotConnected,
+                alloc::vec![
+                    Step::Flush,
+                    Step::SendCommand(RESTART),
+                    Step::Reset(RESTART),
+                ]
+            )
+        );
+    }
+
+    #[test]
+    fn peripheral() {
+        let steps = Steps::default();
+        let command = BoardCommand::Shutdown { reboot: false };
+        block_on(shutdown_peripheral(
+            &mut MockStorage { steps: &steps },
+            &mut MockTransport {
+                steps: &steps,
+                connected: true,
+                acks: true,
+            },
+            command,
+            |command| steps.borrow_mut().push(Step::Reset(command)),
+        ));
+        // The ack means that the fascist board saved, so it has to come after the flush
+        assert_eq!(
+            steps.into_inner(),
+            [Step::Flush, Step::SendAck, Step::Reset(command)]
+        );
+    }
+}
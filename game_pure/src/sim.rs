@@ -0,0 +1,790 @@
+//! Runs the liberal board (central) and the fascist board (peripheral) on the host,
+//! connected by an in-memory transport instead of BLE, so that sync protocol regressions are caught without radios.
+
+use alloc::{collections::VecDeque, rc::Rc, vec::Vec};
+use core::cell::RefCell;
+
+use heapless::index_set::FnvIndexSet;
+use trouble_host::Address;
+
+use crate::{
+    ConnectState, ConnectionStatus, DetectedPolicyCards, GameState, GameStatePlaying, HitlerState,
+    LIBERAL_BOARD_SLOTS, PendingAction, PeripheralRole, PlayingScreen, PolicyCardId, Team,
+    log::log_warn,
+    record::{RecordDecodeError, RecordedEvent, read_record},
+    sync::{
+        BoardCommand, CentralSync, FascistBoardCards, FascistBoardLeds, PeripheralSync, SyncEngine,
+        SyncFrame, SyncMessage, SyncPayload,
+    },
+};
+
+/// How much time passes in each [`Sim::step`], in ms
+pub const SIM_STEP_MS: u64 = 10;
+
+/// The fascist board's address
+fn fascist_board() -> Address {
+    Address::random([1, 2, 3, 4, 5, 6])
+}
+
+/// Carries messages between the two boards, like an L2CAP channel.
+/// Messages are never split or merged.
+pub trait Transport {
+    fn send(&mut self, frame: &[u8]);
+    fn receive(&mut self) -> Option<SyncFrame>;
+    /// The link dropped, so messages that are in flight are lost
+    fn reset(&mut self);
+}
+
+type Queue = Rc<RefCell<VecDeque<SyncFrame>>>;
+
+/// A perfect transport
+#[derive(Debug)]
+pub struct InMemoryTransport {
+    tx: Queue,
+    rx: Queue,
+}
+
+impl InMemoryTransport {
+    /// Returns both ends of a link
+    pub fn pair() -> (Self, Self) {
+        let a = Queue::default();
+        let b = Queue::default();
+        (
+            Self {
+                tx: a.clone(),
+                rx: b.clone(),
+            },
+            Self { tx: b, rx: a },
+        )
+    }
+}
+
+impl Transport for InMemoryTransport {
+    fn send(&mut self, frame: &[u8]) {
+        self.tx
+            .borrow_mut()
+            .push_back(SyncFrame::from_slice(frame).expect("frame should fit in the MTU"));
+    }
+
+    fn receive(&mut self) -> Option<SyncFrame> {
+        self.rx.borrow_mut().pop_front()
+    }
+
+    fn reset(&mut self) {
+        self.tx.borrow_mut().clear();
+        self.rx.borrow_mut().clear();
+    }
+}
+
+/// Drops, duplicates, and reorders sent messages
+#[derive(Debug)]
+pub struct LossyTransport<T> {
+    inner: T,
+    /// xorshift state
+    rng: u64,
+    /// Out of 100
+    drop_chance: u64,
+    /// Out of 100
+    duplicate_chance: u64,
+    /// Sent messages are held back until there are more than `window` of them, and then a random one is sent.
+    /// So a message can be overtaken by up to `window` later messages.
+    window: usize,
+    held: Vec<SyncFrame>,
+}
+
+impl<T: Transport> LossyTransport<T> {
+    pub fn new(
+        inner: T,
+        seed: u64,
+        drop_chance: u64,
+        duplicate_chance: u64,
+        window: usize,
+    ) -> Self {
+        Self {
+            inner,
+            rng: seed.max(1),
+            drop_chance,
+            duplicate_chance,
+            window,
+            held: Vec::new(),
+        }
+    }
+
+    fn random(&mut self, below: u64) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng % below
+    }
+}
+
+impl<T: Transport> Transport for LossyTransport<T> {
+    fn send(&mut self, frame: &[u8]) {
+        if self.random(100) < self.drop_chance {
+            return;
+        }
+        let frame = SyncFrame::from_slice(frame).expect("frame should fit in the MTU");
+        if self.random(100) < self.duplicate_chance {
+            self.held.push(frame.clone());
+        }
+        self.held.push(frame);
+        while self.held.len() > self.window {
+            let index = self.random(self.held.len() as u64) as usize;
+            let frame = self.held.swap_remove(index);
+            self.inner.send(&frame);
+        }
+    }
+
+    fn receive(&mut self) -> Option<SyncFrame> {
+        self.inner.receive()
+    }
+
+    fn reset(&mut self) {
+        self.held.clear();
+        self.inner.reset();
+    }
+}
+
+fn transmit<Out: SyncPayload + Clone, In: SyncPayload>(
+    engine: &mut SyncEngine<Out, In>,
+    transport: &mut impl Transport,
+    now: u64,
+) {
+    while let Some(message) = engine.poll_transmit(now) {
+        transport.send(&message.encode().expect("message should fit in the MTU"));
+    }
+}
+
+/// Returns the latest state received
+fn receive<Out: SyncPayload + Clone, In: SyncPayload>(
+    engine: &mut SyncEngine<Out, In>,
+    transport: &mut impl Transport,
+    now: u64,
+) -> Option<In> {
+    let mut latest = None;
+    while let Some(frame) = transport.receive() {
+        match SyncMessage::decode(&frame) {
+            Ok(message) => {
+                if let Some(state) = engine.receive(message, now) {
+                    latest = Some(state);
+                }
+            }
+            Err(_) => log_warn!("Received invalid sync message"),
+        }
+    }
+    latest
+}
+
+/// The central, which runs the game
+pub struct LiberalBoard {
+    pub game_state: GameState,
+    pub sync: CentralSync,
+    pub cards: FnvIndexSet<PolicyCardId, { LIBERAL_BOARD_SLOTS.next_power_of_two() }>,
+    /// The latest cards received from the fascist board
+    pub fascist_cards: FascistBoardCards,
+}
+
+impl LiberalBoard {
+    fn update_scanned_policy_cards(&mut self) {
+        // The fascist board syncs its cards as soon as it connects, which can be while setting up
+        if let GameState::Playing(_) = self.game_state {
+            self.game_state
+                .update_scanned_policy_cards(DetectedPolicyCards {
+                    liberal: self.cards.clone(),
+                    fascist: self.fascist_cards.clone(),
+                });
+        }
+    }
+}
+
+/// The peripheral, which only scans cards and shows LEDs
+pub struct FascistBoard {
+    pub sync: PeripheralSync,
+    pub cards: FascistBoardCards,
+    /// From the LEDs and commands received from the liberal board
+    pub leds: FascistBoardLeds,
+    /// A command that isn't about the LEDs, like a shutdown, which the test handles
+    pub command: Option<BoardCommand>,
+    /// How far the fascist board's clock is ahead of the liberal board's, like if it booted first
+    pub clock_ahead_ms: u64,
+}
+
+impl FascistBoard {
+    /// Like after booting
+    pub fn new() -> Self {
+        let mut sync = PeripheralSync::new();
+        let cards = FascistBoardCards::new();
+        sync.set_outgoing(cards.clone());
+        Self {
+            sync,
+            cards,
+            leds: FascistBoardLeds::new(),
+            command: None,
+            clock_ahead_ms: 0,
+        }
+    }
+
+    /// The fascist board's time from the sim's time, which is the liberal board's
+    pub fn now(&self, now: u64) -> u64 {
+        now + self.clock_ahead_ms
+    }
+}
+
+impl Default for FascistBoard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub struct Sim<T> {
+    pub liberal: LiberalBoard,
+    pub fascist: FascistBoard,
+    liberal_transport: T,
+    fascist_transport: T,
+    /// In ms
+    pub now: u64,
+    connected: bool,
+}
+
+impl<T: Transport> Sim<T> {
+    /// Starts a game with `players` players. The boards are not connected yet.
+    pub fn new(players: u8, liberal_transport: T, fascist_transport: T) -> Self {
+        let mut connection_statuses = heapless::Vec::new();
+        connection_statuses
+            .push(ConnectionStatus {
+                peripheral_address: fascist_board(),
+                role: PeripheralRole::FascistBoard,
+                state: ConnectState::Connecting,
+                since: 0,
+            })
+            .unwrap();
+        Self {
+            liberal: LiberalBoard {
+                game_state: GameState::Playing(GameStatePlaying {
+                    players,
+                    dead_players: 0,
+                    settings: Default::default(),
+                    connection_statuses,
+                    liberal_policies_placed: 0,
+                    fascist_policies_placed: 0,
+                    detected_cards: Default::default(),
+                    highest_liberal_policies_placed: 0,
+                    highest_fascist_policies_placed: 0,
+                    liberal_policies_removed_since: None,
+                    fascist_policies_removed_since: None,
+                    hitler_state: HitlerState::Secret,
+                    election_fail_streak: 0,
+                    chaos_policy_pending: false,
+                    pending_action: PendingAction::None,
+                    hint_since: 0,
+                    tick: 0,
+                    last_card_change_tick: 0,
+                    link_degraded: false,
+                    sync_pending: true,
+                    local_only: false,
+                    misplacement: None,
+                    misplaced_since: 0,
+                    screen: PlayingScreen::Board,
+                    investigations: heapless::Vec::new(),
+                    known_peripherals: Default::default(),
+                    reader_debug: false,
+                    supply: Default::default(),
+                    effects: Default::default(),
+                }),
+                sync: {
+                    let mut sync = CentralSync::new();
+                    sync.send_time();
+                    sync
+                },
+                cards: Default::default(),
+                fascist_cards: Default::default(),
+            },
+            fascist: FascistBoard::new(),
+            liberal_transport,
+            fascist_transport,
+            now: 0,
+            connected: false,
+        }
+    }
+
+    pub fn connect(&mut self) {
+        self.connected = true;
+        self.liberal
+            .game_state
+            .ble_connected(fascist_board(), self.now);
+        self.liberal.sync.connected();
+        self.fascist.sync.connected();
+    }
+
+    pub fn disconnect(&mut self) {
+        self.connected = false;
+        self.liberal_transport.reset();
+        self.fascist_transport.reset();
+        self.liberal
+            .game_state
+            .ble_disconnected(fascist_board(), self.now);
+        self.liberal.sync.disconnected();
+        self.fascist.sync.disconnected();
+    }
+
+    /// Places a card on the liberal board
+    pub fn place_liberal(&mut self, card: PolicyCardId) {
+        self.liberal.cards.insert(card).unwrap();
+        self.liberal.update_scanned_policy_cards();
+    }
+
+    /// Places a card on the fascist board
+    pub fn place_fascist(&mut self, card: PolicyCardId) {
+        self.fascist.cards.insert(card).unwrap();
+        self.fascist.sync.set_outgoing(self.fascist.cards.clone());
+    }
+
+    pub fn step(&mut self) {
+        self.now += SIM_STEP_MS;
+        for effect in self.liberal.game_state.drain_effects() {
+            if let Some(command) = effect.board_command() {
+                self.liberal.sync.send_command(command);
+            }
+        }
+        if let Some(leds) = self.liberal.game_state.take_sync() {
+            self.liberal.sync.set_outgoing(leds);
+        }
+        let fascist_now = self.fascist.now(self.now);
+        if self.connected {
+            transmit(
+                &mut self.liberal.sync,
+                &mut self.liberal_transport,
+                self.now,
+            );
+            transmit(
+                &mut self.fascist.sync,
+                &mut self.fascist_transport,
+                fascist_now,
+            );
+        }
+        if let Some(cards) = receive(
+            &mut self.liberal.sync,
+            &mut self.liberal_transport,
+            self.now,
+        ) {
+            self.liberal.fascist_cards = cards;
+            self.liberal.update_scanned_policy_cards();
+        }
+        if let Some(leds) = receive(
+            &mut self.fascist.sync,
+            &mut self.fascist_transport,
+            fascist_now,
+        ) {
+            self.fascist.leds.synced(leds);
+        }
+        if let Some(command) = self.fascist.sync.take_command() {
+            if self.fascist.leds.command(command) {
+                self.fascist.sync.ack_command();
+            } else {
+                self.fascist.command = Some(command);
+            }
+        }
+    }
+
+    pub fn run(&mut self, ms: u64) {
+        for _ in 0..ms / SIM_STEP_MS {
+            self.step();
+        }
+    }
+
+    /// Both boards have each other's latest state
+    pub fn converged(&self) -> bool {
+        self.liberal.fascist_cards == self.fascist.cards
+            && self.fascist.leds.leds() == Some(self.liberal.game_state.get_leds())
+    }
+}
+
+impl Sim<InMemoryTransport> {
+    pub fn in_memory(players: u8) -> Self {
+        let (liberal_transport, fascist_transport) = InMemoryTransport::pair();
+        Self::new(players, liberal_transport, fascist_transport)
+    }
+}
+
+/// Replays a record dumped from the liberal board, starting from `state`, which should be the state that the board started with.
+/// Like on the board, the state is ticked before each event. `tick_ms` is the length of a tick on the board.
+pub fn replay(
+    mut state: GameState,
+    record: &[u8],
+    tick_ms: u64,
+) -> Result<GameState, RecordDecodeError> {
+    let mut cards = DetectedPolicyCards {
+        liberal: Default::default(),
+        fascist: Default::default(),
+    };
+    for entry in read_record(record) {
+        let tick = u64::from(entry.timestamp_ms) / tick_ms;
+        state.tick(tick);
+        match entry.event()? {
+            RecordedEvent::Input(input) => state.process_input(input),
+            RecordedEvent::PolicyCard {
+                board,
+                card,
+                present,
+            } => {
+                let changed = match (board, present) {
+                    (Team::Liberal, true) => cards.liberal.insert(card).is_ok_and(|new| new),
+                    (Team::Liberal, false) => cards.liberal.remove(&card),
+                    (Team::Fascist, true) => cards.fascist.insert(card).is_ok_and(|new| new),
+                    (Team::Fascist, false) => cards.fascist.remove(&card),
+                };
+                if !changed {
+                    log_warn!("Replayed a card change that doesn't change anything");
+                }
+                state.update_scanned_policy_cards(cards.clone());
+            }
+            RecordedEvent::PeripheralFound(address) => state.ble_peripheral_found(address),
+            RecordedEvent::Connected(address) => state.ble_connected(address, tick),
+            RecordedEvent::Disconnected(address) => state.ble_disconnected(address, tick),
+            RecordedEvent::SupplyLevel(level) => state.supply_level_changed(level),
+        }
+        state.drain_effects().for_each(drop);
+    }
+    Ok(state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AuraLedColor, FascistAction, Input, PlayingMenuSelectedItem, TIME_SYNC_INTERVAL_MS,
+        log::take_warnings,
+        record::{RECORD_ENTRY_LEN, RecordEntry},
+        sync::{GamePhase, SyncStatus},
+    };
+
+    fn fascist_card(id: usize) -> PolicyCardId {
+        PolicyCardId {
+            team: Team::Fascist,
+            id,
+        }
+    }
+
+    #[test]
+    fn fascist_policy_propagates() {
+        let mut sim = Sim::in_memory(6);
+        sim.connect();
+        sim.run(100);
+        assert!(sim.converged());
+        for id in 0..3 {
+            sim.place_fascist(fascist_card(id));
+        }
+        sim.run(100);
+        assert!(sim.converged());
+        assert_eq!(
+            sim.liberal.game_state.display_action_hint(),
+            Some(FascistAction::ExamineTop3)
+        );
+        assert_eq!(sim.fascist.leds.leds().unwrap().fascist_policy_leds, 3);
+        assert!(sim.liberal.sync.is_acked() && sim.fascist.sync.is_acked());
+    }
+
+    #[test]
+    fn link_drop_and_resync() {
+        let mut sim = Sim::in_memory(6);
+        sim.connect();
+        sim.place_fascist(fascist_card(0));
+        sim.run(100);
+        assert!(sim.converged());
+
+        sim.disconnect();
+        sim.place_fascist(fascist_card(1));
+        sim.place_fascist(fascist_card(2));
+        sim.run(1_000);
+        assert!(!sim.converged());
+        assert_eq!(sim.liberal.game_state.get_leds().fascist_policy_leds, 1);
+        assert!(sim.liberal.game_state.get_leds().blink_aura);
+
+        sim.connect();
+        sim.run(100);
+        assert!(sim.converged());
+        let leds = sim.fascist.leds.leds().unwrap();
+        assert_eq!(leds.fascist_policy_leds, 3);
+        assert!(!leds.blink_aura);
+    }
+
+    #[test]
+    fn fascist_board_restart() {
+        let mut sim = Sim::in_memory(6);
+        sim.connect();
+        sim.place_fascist(fascist_card(0));
+        sim.run(100);
+        sim.disconnect();
+        // The fascist board's seq starts over, and it has to rescan its cards
+        sim.fascist = FascistBoard::new();
+        sim.place_fascist(fascist_card(0));
+        sim.place_fascist(fascist_card(1));
+        sim.connect();
+        sim.run(100);
+        assert!(sim.converged());
+        assert_eq!(sim.fascist.leds.leds().unwrap().fascist_policy_leds, 2);
+    }
+
+    #[test]
+    fn shutdown_command() {
+        let command = BoardCommand::Shutdown { reboot: true };
+        let mut sim = Sim::in_memory(6);
+        // Sent once the fascist board connects
+        sim.liberal.sync.send_command(command);
+        sim.run(1_000);
+        assert_eq!(sim.fascist.command.take(), None);
+        sim.connect();
+        sim.run(100);
+        assert_eq!(sim.fascist.command.take(), Some(command));
+        // Re-sent until the fascist board is done saving
+        sim.run(1_000);
+        assert!(!sim.liberal.sync.is_command_acked());
+        assert_eq!(sim.fascist.command.take(), Some(command));
+        sim.fascist.sync.ack_command();
+        sim.run(100);
+        assert!(sim.liberal.sync.is_command_acked());
+        sim.run(1_000);
+        assert_eq!(sim.fascist.command.take(), None);
+        // Syncing the state isn't held up by the command
+        assert!(sim.converged());
+    }
+
+    #[test]
+    fn time_sync() {
+        let mut sim = Sim::in_memory(6);
+        sim.fascist.clock_ahead_ms = 60_000;
+        sim.connect();
+        sim.run(100);
+        // The in-memory transport delivers right away, so the first offset is exact
+        let central_time = sim.fascist.sync.central_time(sim.fascist.now(sim.now));
+        assert_eq!(central_time, sim.now);
+        // Not until the second time sync, which has the round trip of the first
+        assert_eq!(sim.fascist.sync.clock_sync(), None);
+        sim.run(TIME_SYNC_INTERVAL_MS);
+        // The reply waits for the next step, so the round trip is one step
+        let clock_sync = sim.fascist.sync.clock_sync().unwrap();
+        assert_eq!(clock_sync.round_trip_ms, SIM_STEP_MS as u16);
+        assert!(clock_sync.offset_ms.abs_diff(-60_000) <= SIM_STEP_MS / 2);
+        let liberal_clock_sync = sim.liberal.sync.clock_sync().unwrap();
+        assert_eq!(liberal_clock_sync.round_trip_ms, SIM_STEP_MS as u16);
+        assert!(liberal_clock_sync.offset_ms.abs_diff(-60_000) <= SIM_STEP_MS / 2);
+        // The central's own time doesn't change
+        assert_eq!(sim.liberal.sync.central_time(sim.now), sim.now);
+        assert!(sim.converged());
+    }
+
+    /// The win is sent with [`BoardCommand::GameOver`], which is sent again after reconnecting in case the fascist board restarted,
+    /// and is shown until [`BoardCommand::NewGame`]
+    #[test]
+    fn liberal_win_propagates() {
+        let mut sim = Sim::in_memory(6);
+        sim.connect();
+        for id in 0..LIBERAL_BOARD_SLOTS {
+            sim.place_liberal(PolicyCardId {
+                team: Team::Liberal,
+                id,
+            });
+        }
+        sim.run(100);
+        assert!(sim.converged());
+        assert!(sim.liberal.sync.is_command_acked());
+        assert_eq!(
+            sim.fascist.leds.leds().unwrap().aura_led_color,
+            AuraLedColor::LiberalWin
+        );
+        assert_eq!(
+            sim.fascist.leds.leds().unwrap().phase,
+            GamePhase::LiberalWin
+        );
+
+        // Ending the game syncs the setup phase, and the fascist board keeps showing who won
+        sim.liberal.game_state.process_input(Input::Click);
+        for _ in 0..PlayingMenuSelectedItem::EndGame as usize {
+            sim.liberal.game_state.process_input(Input::Down);
+        }
+        sim.liberal.game_state.process_input(Input::Click);
+        sim.liberal.game_state.process_input(Input::Down);
+        sim.liberal.game_state.process_input(Input::Click);
+        assert!(matches!(sim.liberal.game_state, GameState::SettingUp(_)));
+        sim.run(100);
+        assert!(sim.liberal.sync.is_acked());
+        assert_eq!(
+            sim.fascist.leds.leds().unwrap().aura_led_color,
+            AuraLedColor::LiberalWin
+        );
+        assert_eq!(sim.fascist.leds.leds().unwrap().phase, GamePhase::Setup);
+
+        // The fascist board restarts, and is told who won again after reconnecting
+        sim.disconnect();
+        sim.fascist = FascistBoard::new();
+        sim.connect();
+        assert!(!sim.liberal.sync.is_command_acked());
+        sim.run(100);
+        assert!(sim.liberal.sync.is_command_acked());
+        assert_eq!(
+            sim.fascist.leds.leds().unwrap().aura_led_color,
+            AuraLedColor::LiberalWin
+        );
+
+        // A new game is started, and the cards are taken off the board
+        sim.liberal.game_state.process_input(Input::Click);
+        assert!(matches!(sim.liberal.game_state, GameState::Playing(_)));
+        sim.liberal.cards.clear();
+        sim.liberal.update_scanned_policy_cards();
+        sim.run(100);
+        assert!(sim.converged());
+        assert!(sim.liberal.sync.is_command_acked());
+        assert_eq!(
+            sim.fascist.leds.leds().unwrap().aura_led_color,
+            AuraLedColor::BoardSpecific
+        );
+        assert_eq!(sim.fascist.leds.leds().unwrap().phase, GamePhase::Playing);
+
+        // Only the game over is sent again after reconnecting
+        sim.disconnect();
+        sim.connect();
+        assert!(sim.liberal.sync.is_command_acked());
+    }
+
+    /// The fascist board's cards are received while setting up
+    #[test]
+    fn connect_while_setting_up() {
+        let mut sim = Sim::in_memory(6);
+        sim.liberal.game_state.process_input(Input::Click);
+        for _ in 0..PlayingMenuSelectedItem::EndGame as usize {
+            sim.liberal.game_state.process_input(Input::Down);
+        }
+        sim.liberal.game_state.process_input(Input::Click);
+        sim.liberal.game_state.process_input(Input::Down);
+        sim.liberal.game_state.process_input(Input::Click);
+        assert!(matches!(sim.liberal.game_state, GameState::SettingUp(_)));
+        sim.place_fascist(fascist_card(0));
+        sim.connect();
+        sim.run(100);
+        assert_eq!(sim.liberal.fascist_cards, sim.fascist.cards);
+    }
+
+    #[test]
+    fn version_mismatch() {
+        let mut sim = Sim::in_memory(6);
+        sim.fascist.sync = PeripheralSync::with_version(0);
+        sim.fascist.sync.set_outgoing(FascistBoardCards::new());
+        sim.connect();
+        sim.place_fascist(fascist_card(0));
+        sim.run(1_000);
+        assert_eq!(
+            sim.liberal.sync.status(),
+            SyncStatus::VersionMismatch { peer_version: 0 }
+        );
+        assert!(matches!(
+            sim.fascist.sync.status(),
+            SyncStatus::VersionMismatch { .. }
+        ));
+        // Nothing is accepted from a board with a different version
+        assert!(sim.liberal.fascist_cards.is_empty());
+        assert_eq!(sim.fascist.leds.leds(), None);
+        assert!(
+            take_warnings()
+                .iter()
+                .any(|warning| warning.contains("version mismatch"))
+        );
+    }
+
+    #[test]
+    fn lossy_transport_converges() {
+        for seed in 1..=20 {
+            let (liberal_transport, fascist_transport) = InMemoryTransport::pair();
+            let mut sim = Sim::new(
+                7,
+                LossyTransport::new(liberal_transport, seed, 30, 20, 4),
+                LossyTransport::new(fascist_transport, seed * 31, 30, 20, 4),
+            );
+            sim.connect();
+            for id in 0..4 {
+                sim.place_fascist(fascist_card(id));
+                sim.place_liberal(PolicyCardId {
+                    team: Team::Liberal,
+                    id,
+                });
+                sim.run(50);
+                if id == 2 {
+                    sim.disconnect();
+                    sim.run(500);
+                    sim.connect();
+                }
+            }
+            sim.run(5_000);
+            assert!(sim.converged(), "seed {seed} did not converge");
+            assert_eq!(sim.liberal.game_state.get_leds().fascist_policy_leds, 4);
+        }
+    }
+
+    #[test]
+    fn record_replay() {
+        let liberal_card = PolicyCardId {
+            team: Team::Liberal,
+            id: 1,
+        };
+        let events = [
+            (0, RecordedEvent::Connected(fascist_board())),
+            // Start the game
+            (1_500, RecordedEvent::Input(Input::Click)),
+            (
+                3_000,
+                RecordedEvent::PolicyCard {
+                    board: Team::Fascist,
+                    card: fascist_card(0),
+                    present: true,
+                },
+            ),
+            (
+                3_500,
+                RecordedEvent::PolicyCard {
+                    board: Team::Liberal,
+                    card: liberal_card,
+                    present: true,
+                },
+            ),
+            (5_000, RecordedEvent::Disconnected(fascist_board())),
+            (7_200, RecordedEvent::Input(Input::Back)),
+        ];
+        // Written to a circular buffer that wrapped around
+        let mut buffer = [0xFF; 8 * RECORD_ENTRY_LEN];
+        for (i, (timestamp_ms, event)) in events.into_iter().enumerate() {
+            let slot = (i + 5) % 8;
+            buffer[slot * RECORD_ENTRY_LEN..][..RECORD_ENTRY_LEN]
+                .copy_from_slice(&RecordEntry::new(i as u32, timestamp_ms, event).to_bytes());
+        }
+
+        // What the board did
+        let initial = GameState::new(
+            Some(fascist_board()),
+            Default::default(),
+            Default::default(),
+        );
+        let mut reference = initial.clone();
+        reference.ble_connected(fascist_board(), 0);
+        reference.tick(1);
+        reference.process_input(Input::Click);
+        reference.tick(3);
+        let mut cards = DetectedPolicyCards {
+            liberal: Default::default(),
+            fascist: [fascist_card(0)].into_iter().collect(),
+        };
+        reference.update_scanned_policy_cards(cards.clone());
+        cards.liberal.insert(liberal_card).unwrap();
+        reference.update_scanned_policy_cards(cards);
+        reference.tick(5);
+        reference.ble_disconnected(fascist_board(), 5);
+        reference.tick(7);
+        reference.process_input(Input::Back);
+        reference.drain_effects().for_each(drop);
+
+        let replayed = replay(initial, &buffer, 1_000).unwrap();
+        assert_eq!(replayed, reference);
+        assert_eq!(replayed.get_leds().fascist_policy_leds, 1);
+        assert_eq!(replayed.get_leds().liberal_policy_leds, 1);
+        assert!(replayed.get_leds().blink_aura);
+        assert!(take_warnings().is_empty());
+    }
+}
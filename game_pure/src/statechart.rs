@@ -0,0 +1,289 @@
+//! Finds the transitions between screens by pressing every input on every screen that can be reached,
+//! so that the navigation can be documented as a graph instead of being read from [`GameState::process_input`].
+//!
+//! Screens that need a connection or scanned cards can't be reached with inputs alone,
+//! so exploring starts from a few states that are set up the way BLE and the NFC readers would.
+
+use alloc::{
+    collections::{BTreeMap, BTreeSet, VecDeque},
+    format,
+    string::String,
+    vec::Vec,
+};
+use core::fmt::Write;
+
+use heapless::index_set::FnvIndexSet;
+use trouble_host::Address;
+
+use crate::{
+    BluetoothScreen, DetectedPolicyCards, GameScreen, GameState, Input, PlayingScreen,
+    PolicyCardId, Settings, Team,
+};
+
+/// Every screen in [`screen_name`], with the main menu first
+pub const SCREENS: [&str; 15] = [
+    "MainMenu",
+    "Bluetooth::Scanning",
+    "Bluetooth::ConnectingConnected",
+    "Bluetooth::Unavailable",
+    "Bluetooth::ConnectionDetails",
+    "TextEntry",
+    "About",
+    "AutoStartCountdown",
+    "Playing::Board",
+    "Playing::NoteEntry",
+    "Playing::Notes",
+    "Playing::Menu",
+    "Playing::ConfirmEndGame",
+    "Playing::Paused",
+    "Playing::AdjustPlayers",
+];
+
+pub const INPUTS: [Input; 5] = [
+    Input::Up,
+    Input::Down,
+    Input::Click,
+    Input::Back,
+    Input::DoubleClick,
+];
+
+/// The screen that is shown, without what is selected on it
+pub fn screen_name(state: &GameState) -> &'static str {
+    match state {
+        GameState::SettingUp(state) => match &state.screen {
+            GameScreen::MainMenu(_) => "MainMenu",
+            GameScreen::Bluetooth(BluetoothScreen::Scanning { .. }) => "Bluetooth::Scanning",
+            GameScreen::Bluetooth(BluetoothScreen::ConnectingConnected { .. }) => {
+                "Bluetooth::ConnectingConnected"
+            }
+            GameScreen::Bluetooth(BluetoothScreen::Unavailable) => "Bluetooth::Unavailable",
+            GameScreen::Bluetooth(BluetoothScreen::ConnectionDetails) => {
+                "Bluetooth::ConnectionDetails"
+            }
+            GameScreen::TextEntry(_) => "TextEntry",
+            GameScreen::About(_) => "About",
+            GameScreen::AutoStartCountdown { .. } => "AutoStartCountdown",
+        },
+        GameState::Playing(state) => match state.screen {
+            PlayingScreen::Board => "Playing::Board",
+            PlayingScreen::NoteEntry { .. } => "Playing::NoteEntry",
+            PlayingScreen::Notes => "Playing::Notes",
+            PlayingScreen::Menu { .. } => "Playing::Menu",
+            PlayingScreen::ConfirmEndGame { .. } => "Playing::ConfirmEndGame",
+            PlayingScreen::Paused => "Playing::Paused",
+            PlayingScreen::AdjustPlayers { .. } => "Playing::AdjustPlayers",
+        },
+    }
+}
+
+pub fn input_name(input: Input) -> &'static str {
+    match input {
+        Input::Up => "Up",
+        Input::Down => "Down",
+        Input::Click => "Click",
+        Input::Back => "Back",
+        Input::DoubleClick => "DoubleClick",
+    }
+}
+
+/// Pressing `input` on `from` shows `to`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Transition {
+    pub from: &'static str,
+    pub input: &'static str,
+    pub to: &'static str,
+}
+
+/// Identifies the states that inputs behave the same on.
+/// The entered text and the tick are left out, since inputs don't depend on them and they would make the search endless.
+fn explore_key(state: &GameState) -> String {
+    match state {
+        GameState::SettingUp(state) => {
+            let screen = |screen: &GameScreen| match screen {
+                GameScreen::TextEntry(screen) => format!("TextEntry({})", screen.selected_choice),
+                screen => format!("{screen:?}"),
+            };
+            format!(
+                "{} {:?} {:?} {:?}",
+                screen(&state.screen),
+                state.back_stack.iter().map(screen).collect::<Vec<_>>(),
+                state.last_bluetooth_screen,
+                state.connection_action
+            )
+        }
+        GameState::Playing(state) => format!(
+            "{:?} {:?} {:?}",
+            state.screen, state.pending_action, state.investigations
+        ),
+    }
+}
+
+/// The states that exploring starts from, one for each way that the board can be set up
+fn initial_states() -> [GameState; 5] {
+    let fascist_board = Address::random([0x00, 0x01, 0x02, 0x03, 0x04, 0x05]);
+
+    let mut scanning = GameState::new(None, Default::default(), Default::default());
+    scanning.ble_peripheral_found(fascist_board);
+
+    let mut connected = GameState::new(Some(fascist_board), Default::default(), Default::default());
+    connected.ble_connected(fascist_board, 0);
+
+    let local_only = GameState::new_local_only(Default::default(), Default::default());
+
+    let mut auto_start = GameState::new(
+        Some(fascist_board),
+        Default::default(),
+        Settings {
+            auto_start: true,
+            ..Default::default()
+        },
+    );
+    auto_start.ble_connected(fascist_board, 0);
+
+    // The note entry and notes screens need president notes and a check party action
+    let mut check_party = GameState::new(
+        Some(fascist_board),
+        Default::default(),
+        Settings {
+            president_notes: true,
+            ..Default::default()
+        },
+    );
+    check_party.ble_connected(fascist_board, 0);
+    check_party.process_input(Input::Click);
+    let mut fascist = FnvIndexSet::new();
+    let _ = fascist.insert(PolicyCardId {
+        team: Team::Fascist,
+        id: 0,
+    });
+    check_party.update_scanned_policy_cards(DetectedPolicyCards {
+        liberal: Default::default(),
+        fascist,
+    });
+
+    [scanning, connected, local_only, auto_start, check_party]
+}
+
+/// Every transition between different screens. Inputs that stay on the same screen aren't included.
+pub fn transitions() -> BTreeSet<Transition> {
+    let mut transitions = BTreeSet::new();
+    let mut explored = BTreeSet::new();
+    let mut queue = VecDeque::new();
+    for mut state in initial_states() {
+        state.drain_effects().for_each(drop);
+        if explored.insert(explore_key(&state)) {
+            queue.push_back(state);
+        }
+    }
+    while let Some(state) = queue.pop_front() {
+        for input in INPUTS {
+            let mut next = state.clone();
+            next.process_input(input);
+            next.drain_effects().for_each(drop);
+            let (from, to) = (screen_name(&state), screen_name(&next));
+            if from != to {
+                transitions.insert(Transition {
+                    from,
+                    input: input_name(input),
+                    to,
+                });
+            }
+            if explored.insert(explore_key(&next)) {
+                queue.push_back(next);
+            }
+        }
+    }
+    transitions
+}
+
+/// The transitions as a Graphviz graph. Inputs that lead to the same screen share an edge.
+pub fn to_dot(transitions: &BTreeSet<Transition>) -> String {
+    let mut edges = BTreeMap::<_, Vec<_>>::new();
+    for transition in transitions {
+        edges
+            .entry((transition.from, transition.to))
+            .or_default()
+            .push(transition.input);
+    }
+    let mut dot = String::from("digraph game_states {\n");
+    for screen in SCREENS {
+        let _ = writeln!(dot, "    \"{screen}\";");
+    }
+    for ((from, to), inputs) in edges {
+        let _ = writeln!(
+            dot,
+            "    \"{from}\" -> \"{to}\" [label=\"{}\"];",
+            inputs.join(", ")
+        );
+    }
+    dot.push_str("}\n");
+    dot
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The fewest transitions needed to get to each screen from the main menu
+    fn depths(transitions: &BTreeSet<Transition>) -> BTreeMap<&'static str, usize> {
+        let mut depths = BTreeMap::from([(SCREENS[0], 0)]);
+        let mut queue = VecDeque::from([SCREENS[0]]);
+        while let Some(screen) = queue.pop_front() {
+            let depth = depths[screen];
+            for transition in transitions.iter().filter(|t| t.from == screen) {
+                if !depths.contains_key(transition.to) {
+                    depths.insert(transition.to, depth + 1);
+                    queue.push_back(transition.to);
+                }
+            }
+        }
+        depths
+    }
+
+    #[test]
+    fn all_reachable() {
+        let transitions = transitions();
+        let depths = depths(&transitions);
+        // The countdown is only shown by reconnecting on boot, before anything is pressed
+        for screen in SCREENS
+            .into_iter()
+            .filter(|&screen| screen != "AutoStartCountdown")
+        {
+            assert!(
+                depths.contains_key(screen),
+                "{screen} can't be reached from the main menu"
+            );
+        }
+    }
+
+    #[test]
+    fn back_not_deeper() {
+        let transitions = transitions();
+        let depths = depths(&transitions);
+        for transition in transitions.iter().filter(|t| t.input == "Back") {
+            // Back dismisses the hint on the board, which opens note entry after a check party action
+            // The countdown isn't reached from the main menu, so it has no depth
+            if transition.from == "Playing::Board" || transition.from == "AutoStartCountdown" {
+                continue;
+            }
+            assert!(
+                depths[transition.to] <= depths[transition.from],
+                "{transition:?} goes deeper"
+            );
+        }
+    }
+
+    #[test]
+    fn dot() {
+        let dot = to_dot(&transitions());
+        assert!(dot.starts_with("digraph game_states {\n"));
+        assert!(dot.contains("    \"MainMenu\" -> \"About\" [label=\"Click\"];\n"));
+        assert!(dot.contains(
+            "    \"AutoStartCountdown\" -> \"MainMenu\" [label=\"Back, Click, DoubleClick, Down, Up\"];\n"
+        ));
+        assert!(dot.contains(
+            "    \"Playing::Menu\" -> \"Playing::Board\" [label=\"Back, Click, DoubleClick\"];\n"
+        ));
+        assert!(dot.ends_with("}\n"));
+    }
+}
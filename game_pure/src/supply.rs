@@ -0,0 +1,219 @@
+use core::fmt;
+
+use heapless::Deque;
+
+/// How healthy the supply voltage is. Worse levels are greater.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub enum SupplyLevel {
+    #[default]
+    Normal,
+    /// The power bank could be about to sleep or run out, so the players are warned
+    Low,
+    /// The LEDs are dimmed to make the power bank last longer
+    Critical,
+}
+
+impl fmt::Display for SupplyLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::Normal => "normal",
+            Self::Low => "low",
+            Self::Critical => "critical",
+        })
+    }
+}
+
+/// The supply voltages where [`SupplyLevel`] changes, in mV
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+pub struct SupplyThresholds {
+    /// Below this is [`SupplyLevel::Low`]
+    pub low_mv: u16,
+    /// Below this is [`SupplyLevel::Critical`]
+    pub critical_mv: u16,
+    /// Going back to a better level needs this much more than its threshold, so that a voltage
+    /// right at a threshold doesn't keep changing the level
+    pub hysteresis_mv: u16,
+}
+
+impl SupplyThresholds {
+    /// For boards powered from USB, which should be 5 V
+    pub const USB: Self = Self {
+        low_mv: 4_600,
+        critical_mv: 4_400,
+        hysteresis_mv: 100,
+    };
+
+    /// The level at `mv`, coming from `current`
+    pub fn level(&self, current: SupplyLevel, mv: u16) -> SupplyLevel {
+        let level = |margin: u16| {
+            if mv < self.critical_mv.saturating_add(margin) {
+                SupplyLevel::Critical
+            } else if mv < self.low_mv.saturating_add(margin) {
+                SupplyLevel::Low
+            } else {
+                SupplyLevel::Normal
+            }
+        };
+        let level_without_hysteresis = level(0);
+        if level_without_hysteresis >= current {
+            level_without_hysteresis
+        } else {
+            level(self.hysteresis_mv).min(current)
+        }
+    }
+}
+
+/// Averages the last `N` supply voltage samples and tells when the [`SupplyLevel`] changes.
+/// The level stays [`SupplyLevel::Normal`] until there are `N` samples, so that one noisy sample at boot doesn't warn.
+#[derive(Debug, Clone)]
+pub struct SupplyMonitor<const N: usize> {
+    thresholds: SupplyThresholds,
+    samples: Deque<u16, N>,
+    level: SupplyLevel,
+}
+
+impl<const N: usize> SupplyMonitor<N> {
+    pub const fn new(thresholds: SupplyThresholds) -> Self {
+        Self {
+            thresholds,
+            samples: Deque::new(),
+            level: SupplyLevel::Normal,
+        }
+    }
+
+    /// Adds a sample in mV, replacing the oldest one. Returns the new level if it changed.
+    pub fn record(&mut self, mv: u16) -> Option<SupplyLevel> {
+        if self.samples.is_full() {
+            self.samples.pop_front();
+        }
+        // There is room after removing the oldest sample
+        let _ = self.samples.push_back(mv);
+        let average = self.average()?;
+        let level = self.thresholds.level(self.level, average);
+        (level != self.level).then(|| {
+            self.level = level;
+            level
+        })
+    }
+
+    /// The average of the last `N` samples in mV. `None` until there are `N` samples.
+    pub fn average(&self) -> Option<u16> {
+        if !self.samples.is_full() {
+            return None;
+        }
+        let sum = self.samples.iter().map(|&mv| u32::from(mv)).sum::<u32>();
+        Some((sum / N as u32) as u16)
+    }
+
+    pub fn level(&self) -> SupplyLevel {
+        self.level
+    }
+}
+
+/// What the game state knows about the supply voltage
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SupplyStatus {
+    pub level: SupplyLevel,
+    /// The screen is covered by a warning that the supply is low, until any input dismisses it.
+    /// It is shown once each time the level gets worse than [`SupplyLevel::Normal`].
+    pub popup: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor() -> SupplyMonitor<4> {
+        SupplyMonitor::new(SupplyThresholds::USB)
+    }
+
+    #[test]
+    fn waits_for_full_window() {
+        let mut monitor = monitor();
+        for _ in 0..3 {
+            assert_eq!(monitor.record(3_000), None);
+            assert_eq!(monitor.average(), None);
+        }
+        assert_eq!(monitor.record(3_000), Some(SupplyLevel::Critical));
+        assert_eq!(monitor.average(), Some(3_000));
+    }
+
+    #[test]
+    fn averaged() {
+        let mut monitor = monitor();
+        for _ in 0..4 {
+            assert_eq!(monitor.record(5_000), None);
+        }
+        // One sample that drops because of a burst of current isn't enough
+        assert_eq!(monitor.record(4_000), None);
+        assert_eq!(monitor.average(), Some(4_750));
+        assert_eq!(monitor.record(4_300), Some(SupplyLevel::Low));
+        assert_eq!(monitor.average(), Some(4_575));
+        // The oldest samples are replaced
+        for _ in 0..4 {
+            monitor.record(5_000);
+        }
+        assert_eq!(monitor.average(), Some(5_000));
+        assert_eq!(monitor.level(), SupplyLevel::Normal);
+    }
+
+    #[test]
+    fn no_flapping_around_threshold() {
+        let mut monitor = monitor();
+        for _ in 0..4 {
+            monitor.record(4_610);
+        }
+        assert_eq!(monitor.level(), SupplyLevel::Normal);
+        let mut changes = 0;
+        for i in 0..40 {
+            // Noise of ±20 mV around the low threshold
+            let mv = if i % 2 == 0 { 4_580 } else { 4_620 };
+            changes += monitor.record(mv).iter().count();
+        }
+        // It got low once and stayed there
+        assert_eq!(changes, 1);
+        assert_eq!(monitor.level(), SupplyLevel::Low);
+        // Going back above the threshold isn't enough to recover
+        for i in 0..40 {
+            let mv = if i % 2 == 0 { 4_610 } else { 4_650 };
+            assert_eq!(monitor.record(mv), None);
+        }
+        assert_eq!(monitor.level(), SupplyLevel::Low);
+        for _ in 0..3 {
+            monitor.record(4_700);
+        }
+        assert_eq!(monitor.record(4_700), Some(SupplyLevel::Normal));
+    }
+
+    #[test]
+    fn critical() {
+        let thresholds = SupplyThresholds::USB;
+        assert_eq!(
+            thresholds.level(SupplyLevel::Normal, 4_399),
+            SupplyLevel::Critical
+        );
+        assert_eq!(
+            thresholds.level(SupplyLevel::Low, 4_399),
+            SupplyLevel::Critical
+        );
+        // Recovering needs the hysteresis above the threshold of the better level
+        assert_eq!(
+            thresholds.level(SupplyLevel::Critical, 4_450),
+            SupplyLevel::Critical
+        );
+        assert_eq!(
+            thresholds.level(SupplyLevel::Critical, 4_550),
+            SupplyLevel::Low
+        );
+        assert_eq!(
+            thresholds.level(SupplyLevel::Critical, 4_650),
+            SupplyLevel::Low
+        );
+        assert_eq!(
+            thresholds.level(SupplyLevel::Critical, 4_700),
+            SupplyLevel::Normal
+        );
+    }
+}
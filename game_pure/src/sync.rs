@@ -0,0 +1,1172 @@
+//! The protocol that keeps the liberal board (central) and the fascist board (peripheral) in sync over BLE.
+//!
+//! Each message is one L2CAP SDU, so there is no extra framing.
+//! Both ends send their whole latest state instead of changes, and keep re-sending it until the other end acks it.
+//! This way, lost, duplicated, and reordered messages are harmless, and a dropped link is resynced by just reconnecting.
+
+use heapless::index_set::FnvIndexSet;
+use trouble_host::prelude::{AdStructure, BR_EDR_NOT_SUPPORTED, LE_GENERAL_DISCOVERABLE};
+
+use crate::{
+    AuraLedColor, ClockOffsetEstimator, ClockSync, ELECTION_FAILS_FOR_CHAOS,
+    ElectionTrackerPlacement, FASCIST_BOARD_SLOTS, LIBERAL_BOARD_SLOTS, LedsDisplay, PolicyCardId,
+    TIME_SYNC_INTERVAL_MS, Team, log::log_warn, sorted_policy_cards,
+};
+
+/// Incremented whenever the format of a message changes
+pub const SYNC_PROTOCOL_VERSION: u16 = 6;
+/// The max size of a message, which is the MTU of the L2CAP channel
+pub const SYNC_MTU: usize = 27;
+/// How long to wait for an ack (or for the other end's hello) before re-sending, in ms
+pub const SYNC_RESEND_MS: u64 = 200;
+
+pub type SyncFrame = heapless::Vec<u8, SYNC_MTU>;
+
+/// The company identifier in our manufacturer data. 0xFFFF is reserved by the Bluetooth SIG for testing.
+pub const ADV_COMPANY_ID: u16 = 0xFFFF;
+/// The max size of legacy advertising data
+pub const ADV_DATA_LEN: usize = 31;
+
+pub type AdvData = heapless::Vec<u8, ADV_DATA_LEN>;
+
+/// What the fascist board advertises about the game, so that a spectator app can show it without connecting
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GamePhase {
+    /// The liberal board didn't send any state yet, or went back to the main menu after a game
+    Setup,
+    Playing,
+    LiberalWin,
+    FascistWin,
+}
+
+impl GamePhase {
+    fn byte(self) -> u8 {
+        match self {
+            Self::Setup => 0,
+            Self::Playing => 1,
+            Self::LiberalWin => 2,
+            Self::FascistWin => 3,
+        }
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, DecodeError> {
+        match byte {
+            0 => Ok(Self::Setup),
+            1 => Ok(Self::Playing),
+            2 => Ok(Self::LiberalWin),
+            3 => Ok(Self::FascistWin),
+            _ => Err(DecodeError),
+        }
+    }
+}
+
+/// The fascist board's advertising data: the flags, and manufacturer data with [`SYNC_PROTOCOL_VERSION`] (little endian) followed by the [`GamePhase`].
+/// The name and service UUID don't fit, so they go in the scan response.
+pub fn fascist_adv_data(phase: GamePhase) -> AdvData {
+    let [version_low, version_high] = SYNC_PROTOCOL_VERSION.to_le_bytes();
+    let mut data = [0; ADV_DATA_LEN];
+    // This is much smaller than the max size
+    let len = AdStructure::encode_slice(
+        &[
+            AdStructure::Flags(LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED),
+            AdStructure::ManufacturerSpecificData {
+                company_identifier: ADV_COMPANY_ID,
+                payload: &[version_low, version_high, phase.byte()],
+            },
+        ],
+        &mut data,
+    )
+    .unwrap();
+    AdvData::from_slice(&data[..len]).unwrap()
+}
+
+/// The sync protocol version and game phase from [`fascist_adv_data`].
+/// Returns `None` if the advertising data doesn't have them.
+pub fn decode_fascist_adv_data(data: &[u8]) -> Option<(u16, GamePhase)> {
+    AdStructure::decode(data)
+        .map_while(Result::ok)
+        .find_map(|ad_structure| match ad_structure {
+            AdStructure::ManufacturerSpecificData {
+                company_identifier: ADV_COMPANY_ID,
+                payload: &[version_low, version_high, phase],
+            } => Some((
+                u16::from_le_bytes([version_low, version_high]),
+                GamePhase::from_byte(phase).ok()?,
+            )),
+            _ => None,
+        })
+}
+
+/// The number of GATT characteristics in [`SpectatorState::encode`]
+pub const SPECTATOR_CHARACTERISTICS: usize = 5;
+
+/// The game's progress, which a spectator phone app can read from the fascist board over GATT.
+/// Anyone can read it without pairing, since everything here is already shown on the boards.
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SpectatorState {
+    pub liberal_policies: u8,
+    pub fascist_policies: u8,
+    /// The number of failed elections in a row
+    pub election_tracker: u8,
+    pub phase: GamePhase,
+}
+
+impl SpectatorState {
+    /// Before the liberal board synced
+    pub const SETUP: Self = Self {
+        liberal_policies: 0,
+        fascist_policies: 0,
+        election_tracker: 0,
+        phase: GamePhase::Setup,
+    };
+
+    /// Each lit policy LED is a placed policy
+    pub fn from_leds(leds: &LedsDisplay) -> Self {
+        Self {
+            liberal_policies: leds.liberal_policy_leds as u8,
+            fascist_policies: leds.fascist_policy_leds as u8,
+            election_tracker: leds.election_tracker_leds as u8,
+            phase: leds.phase,
+        }
+    }
+
+    pub fn winner(&self) -> Option<Team> {
+        match self.phase {
+            GamePhase::Setup | GamePhase::Playing => None,
+            GamePhase::LiberalWin => Some(Team::Liberal),
+            GamePhase::FascistWin => Some(Team::Fascist),
+        }
+    }
+
+    /// One byte for each characteristic: the liberal policies, the fascist policies, the election tracker,
+    /// the [`GamePhase`] (like in [`fascist_adv_data`]), and the winner (0 for nobody yet, 1 for the liberals, and 2 for the fascists)
+    pub fn encode(&self) -> [u8; SPECTATOR_CHARACTERISTICS] {
+        [
+            self.liberal_policies,
+            self.fascist_policies,
+            self.election_tracker,
+            self.phase.byte(),
+            self.winner().map_or(0, |team| team_byte(team) + 1),
+        ]
+    }
+
+    /// For spectator apps
+    pub fn decode(bytes: [u8; SPECTATOR_CHARACTERISTICS]) -> Result<Self, DecodeError> {
+        let [
+            liberal_policies,
+            fascist_policies,
+            election_tracker,
+            phase,
+            winner,
+        ] = bytes;
+        let state = Self {
+            liberal_policies,
+            fascist_policies,
+            election_tracker,
+            phase: GamePhase::from_byte(phase)?,
+        };
+        let winner = match winner {
+            0 => None,
+            byte => Some(team_from_byte(byte - 1)?),
+        };
+        if usize::from(liberal_policies) > LIBERAL_BOARD_SLOTS
+            || usize::from(fascist_policies) > FASCIST_BOARD_SLOTS
+            || usize::from(election_tracker) > ELECTION_FAILS_FOR_CHAOS
+            || winner != state.winner()
+        {
+            return Err(DecodeError);
+        }
+        Ok(state)
+    }
+}
+
+/// The policy cards that the fascist board scanned
+pub type FascistBoardCards = FnvIndexSet<PolicyCardId, { FASCIST_BOARD_SLOTS.next_power_of_two() }>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeError;
+
+/// A message didn't fit in [`SYNC_MTU`] bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FrameFull;
+
+/// A state that is synced to the other end
+pub trait SyncPayload: Sized {
+    fn encode(&self, frame: &mut SyncFrame) -> Result<(), FrameFull>;
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError>;
+}
+
+fn team_byte(team: Team) -> u8 {
+    match team {
+        Team::Liberal => 0,
+        Team::Fascist => 1,
+    }
+}
+
+fn team_from_byte(byte: u8) -> Result<Team, DecodeError> {
+    match byte {
+        0 => Ok(Team::Liberal),
+        1 => Ok(Team::Fascist),
+        _ => Err(DecodeError),
+    }
+}
+
+/// Sent by the liberal board
+impl SyncPayload for LedsDisplay {
+    fn encode(&self, frame: &mut SyncFrame) -> Result<(), FrameFull> {
+        let aura_led_color = match self.aura_led_color {
+            AuraLedColor::BoardSpecific => 0,
+            AuraLedColor::LiberalWin => 1,
+            AuraLedColor::FascistWin => 2,
+        };
+        let election_tracker_placement = match self.election_tracker_placement {
+            ElectionTrackerPlacement::Liberal => 0,
+            ElectionTrackerPlacement::Fascist => 1,
+            ElectionTrackerPlacement::Both => 2,
+        };
+        let flags = u8::from(self.election_tracker_warning)
+            | u8::from(self.blink_aura) << 1
+            | u8::from(self.dimmed) << 2
+            | election_tracker_placement << 3;
+        let misplaced_board = self.misplaced_board.map_or(0, |team| team_byte(team) + 1);
+        frame
+            .extend_from_slice(&[
+                aura_led_color,
+                self.liberal_policy_leds as u8,
+                self.fascist_policy_leds as u8,
+                self.election_tracker_leds as u8,
+                flags,
+                misplaced_board,
+                self.phase.byte(),
+            ])
+            .map_err(|_| FrameFull)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let &[
+            aura_led_color,
+            liberal_policy_leds,
+            fascist_policy_leds,
+            election_tracker_leds,
+            flags,
+            misplaced_board,
+            phase,
+        ] = bytes
+        else {
+            return Err(DecodeError);
+        };
+        Ok(Self {
+            aura_led_color: match aura_led_color {
+                0 => AuraLedColor::BoardSpecific,
+                1 => AuraLedColor::LiberalWin,
+                2 => AuraLedColor::FascistWin,
+                _ => return Err(DecodeError),
+            },
+            liberal_policy_leds: liberal_policy_leds.into(),
+            fascist_policy_leds: fascist_policy_leds.into(),
+            election_tracker_leds: election_tracker_leds.into(),
+            election_tracker_warning: flags & 1 != 0,
+            blink_aura: flags & 2 != 0,
+            dimmed: flags & 4 != 0,
+            misplaced_board: match misplaced_board {
+                0 => None,
+                byte => Some(team_from_byte(byte - 1)?),
+            },
+            election_tracker_placement: match flags >> 3 {
+                0 => ElectionTrackerPlacement::Liberal,
+                1 => ElectionTrackerPlacement::Fascist,
+                2 => ElectionTrackerPlacement::Both,
+                _ => return Err(DecodeError),
+            },
+            phase: GamePhase::from_byte(phase)?,
+        })
+    }
+}
+
+/// Sent by the fascist board, sorted so that the same cards are always encoded the same way
+impl SyncPayload for FascistBoardCards {
+    fn encode(&self, frame: &mut SyncFrame) -> Result<(), FrameFull> {
+        for card in sorted_policy_cards(self) {
+            frame
+                .extend_from_slice(&[team_byte(card.team), card.id as u8])
+                .map_err(|_| FrameFull)?;
+        }
+        Ok(())
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        let (cards, []) = bytes.as_chunks::<2>() else {
+            return Err(DecodeError);
+        };
+        let mut set = Self::new();
+        for &[team, id] in cards {
+            set.insert(PolicyCardId {
+                team: team_from_byte(team)?,
+                id: id.into(),
+            })
+            .map_err(|_| DecodeError)?;
+        }
+        Ok(set)
+    }
+}
+
+/// Something that the liberal board tells the fascist board to do, instead of syncing state
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BoardCommand {
+    /// Save everything and then reset. Without `reboot`, the board stays off until it is power cycled.
+    Shutdown { reboot: bool },
+    /// Show the winner on the aura until [`BoardCommand::NewGame`], see [`FascistBoardLeds`]
+    GameOver { winner: Team },
+    /// Stop showing the winner from [`BoardCommand::GameOver`]
+    NewGame,
+}
+
+impl BoardCommand {
+    fn encode(self, frame: &mut SyncFrame) -> Result<(), FrameFull> {
+        match self {
+            Self::Shutdown { reboot } => frame.extend_from_slice(&[0, reboot.into()]),
+            Self::GameOver { winner } => frame.extend_from_slice(&[1, team_byte(winner)]),
+            Self::NewGame => frame.extend_from_slice(&[2]),
+        }
+        .map_err(|_| FrameFull)
+    }
+
+    fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        match bytes {
+            &[0, reboot @ (0 | 1)] => Ok(Self::Shutdown {
+                reboot: reboot == 1,
+            }),
+            &[1, winner] => Ok(Self::GameOver {
+                winner: team_from_byte(winner)?,
+            }),
+            [2] => Ok(Self::NewGame),
+            _ => Err(DecodeError),
+        }
+    }
+
+    /// Sent again after reconnecting even if it was acked, since the other end may have restarted and forgotten it
+    fn resend_on_reconnect(self) -> bool {
+        matches!(self, Self::GameOver { .. })
+    }
+}
+
+/// What the fascist board's LEDs show: the LEDs synced from the liberal board,
+/// with the aura overridden by the winner from [`BoardCommand::GameOver`] until [`BoardCommand::NewGame`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FascistBoardLeds {
+    synced: Option<LedsDisplay>,
+    winner: Option<Team>,
+}
+
+impl FascistBoardLeds {
+    pub const fn new() -> Self {
+        Self {
+            synced: None,
+            winner: None,
+        }
+    }
+
+    pub fn synced(&mut self, leds: LedsDisplay) {
+        self.synced = Some(leds);
+    }
+
+    /// Returns `false` if the command isn't about the LEDs, so it still has to be done
+    pub fn command(&mut self, command: BoardCommand) -> bool {
+        match command {
+            BoardCommand::GameOver { winner } => self.winner = Some(winner),
+            BoardCommand::NewGame => self.winner = None,
+            BoardCommand::Shutdown { .. } => return false,
+        }
+        true
+    }
+
+    /// `None` until the liberal board synced or told us who won
+    pub fn leds(&self) -> Option<LedsDisplay> {
+        let Some(winner) = self.winner else {
+            return self.synced.clone();
+        };
+        // After restarting, only the winner is known until the next game is synced
+        let leds = self.synced.clone().unwrap_or(LedsDisplay {
+            aura_led_color: AuraLedColor::BoardSpecific,
+            liberal_policy_leds: 0,
+            fascist_policy_leds: 0,
+            election_tracker_leds: 0,
+            election_tracker_warning: false,
+            blink_aura: false,
+            misplaced_board: None,
+            dimmed: false,
+            election_tracker_placement: Default::default(),
+            phase: GamePhase::Setup,
+        });
+        Some(LedsDisplay {
+            aura_led_color: match winner {
+                Team::Liberal => AuraLedColor::LiberalWin,
+                Team::Fascist => AuraLedColor::FascistWin,
+            },
+            ..leds
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SyncMessage<T> {
+    /// Sent after connecting, until the other end shows that it received it
+    Hello {
+        version: u16,
+    },
+    /// The latest state. `seq` increases every time the state changes.
+    State {
+        seq: u32,
+        state: T,
+    },
+    Ack {
+        seq: u32,
+    },
+    /// Re-sent until the other end sends a [`SyncMessage::CommandAck`]
+    Command {
+        command: BoardCommand,
+    },
+    /// The other end is done with the command that it received, such as saving before a shutdown
+    CommandAck,
+    /// The central's time since it booted, sent every [`TIME_SYNC_INTERVAL_MS`]
+    TimeSync {
+        central_ms: u64,
+        /// How long the last time sync took to come back, so that the peripheral can account for the delay.
+        /// 0 until the first reply.
+        round_trip_ms: u16,
+    },
+    /// Sent by the peripheral right after receiving a [`SyncMessage::TimeSync`]
+    TimeSyncReply {
+        /// From the time sync
+        central_ms: u64,
+        /// The peripheral's time since it booted, when it received the time sync
+        peripheral_ms: u64,
+    },
+}
+
+impl<T: SyncPayload> SyncMessage<T> {
+    pub fn encode(&self) -> Result<SyncFrame, FrameFull> {
+        let mut frame = SyncFrame::new();
+        match self {
+            Self::Hello { version } => {
+                frame.push(0).map_err(|_| FrameFull)?;
+                frame
+                    .extend_from_slice(&version.to_le_bytes())
+                    .map_err(|_| FrameFull)?;
+            }
+            Self::State { seq, state } => {
+                frame.push(1).map_err(|_| FrameFull)?;
+                frame
+                    .extend_from_slice(&seq.to_le_bytes())
+                    .map_err(|_| FrameFull)?;
+                state.encode(&mut frame)?;
+            }
+            Self::Ack { seq } => {
+                frame.push(2).map_err(|_| FrameFull)?;
+                frame
+                    .extend_from_slice(&seq.to_le_bytes())
+                    .map_err(|_| FrameFull)?;
+            }
+            Self::Command { command } => {
+                frame.push(3).map_err(|_| FrameFull)?;
+                command.encode(&mut frame)?;
+            }
+            Self::CommandAck => {
+                frame.push(4).map_err(|_| FrameFull)?;
+            }
+            Self::TimeSync {
+                central_ms,
+                round_trip_ms,
+            } => {
+                frame.push(5).map_err(|_| FrameFull)?;
+                frame
+                    .extend_from_slice(&central_ms.to_le_bytes())
+                    .map_err(|_| FrameFull)?;
+                frame
+                    .extend_from_slice(&round_trip_ms.to_le_bytes())
+                    .map_err(|_| FrameFull)?;
+            }
+            Self::TimeSyncReply {
+                central_ms,
+                peripheral_ms,
+            } => {
+                frame.push(6).map_err(|_| FrameFull)?;
+                frame
+                    .extend_from_slice(&central_ms.to_le_bytes())
+                    .map_err(|_| FrameFull)?;
+                frame
+                    .extend_from_slice(&peripheral_ms.to_le_bytes())
+                    .map_err(|_| FrameFull)?;
+            }
+        }
+        Ok(frame)
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self, DecodeError> {
+        fn seq(bytes: &[u8]) -> Result<(u32, &[u8]), DecodeError> {
+            let (seq, rest) = bytes.split_first_chunk::<4>().ok_or(DecodeError)?;
+            Ok((u32::from_le_bytes(*seq), rest))
+        }
+        fn ms(bytes: &[u8]) -> Result<(u64, &[u8]), DecodeError> {
+            let (ms, rest) = bytes.split_first_chunk::<8>().ok_or(DecodeError)?;
+            Ok((u64::from_le_bytes(*ms), rest))
+        }
+        match bytes.split_first().ok_or(DecodeError)? {
+            (0, &[a, b]) => Ok(Self::Hello {
+                version: u16::from_le_bytes([a, b]),
+            }),
+            (1, rest) => {
+                let (seq, state) = seq(rest)?;
+                Ok(Self::State {
+                    seq,
+                    state: T::decode(state)?,
+                })
+            }
+            (2, rest) => match seq(rest)? {
+                (seq, []) => Ok(Self::Ack { seq }),
+                _ => Err(DecodeError),
+            },
+            (3, rest) => Ok(Self::Command {
+                command: BoardCommand::decode(rest)?,
+            }),
+            (4, []) => Ok(Self::CommandAck),
+            (5, rest) => match ms(rest)? {
+                (central_ms, &[a, b]) => Ok(Self::TimeSync {
+                    central_ms,
+                    round_trip_ms: u16::from_le_bytes([a, b]),
+                }),
+                _ => Err(DecodeError),
+            },
+            (6, rest) => {
+                let (central_ms, rest) = ms(rest)?;
+                match ms(rest)? {
+                    (peripheral_ms, []) => Ok(Self::TimeSyncReply {
+                        central_ms,
+                        peripheral_ms,
+                    }),
+                    _ => Err(DecodeError),
+                }
+            }
+            _ => Err(DecodeError),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SyncStatus {
+    /// Not connected
+    Disconnected,
+    /// Waiting for the other end's hello
+    Handshaking,
+    Ready,
+    /// The other end uses a different protocol version, so nothing is sent or accepted until reconnecting
+    VersionMismatch {
+        peer_version: u16,
+    },
+}
+
+/// One end of the sync protocol. `Out` is the state that we send, and `In` is the state that the other end sends.
+/// This doesn't do any IO, so the same logic runs over BLE and in tests.
+#[derive(Debug, Clone)]
+pub struct SyncEngine<Out, In> {
+    /// The protocol version that we send in our hello
+    version: u16,
+    status: SyncStatus,
+    /// The other end received our hello, which we know because it sent something other than a hello
+    hello_received: bool,
+    /// When we last sent a hello or our state, in ms
+    last_sent: Option<u64>,
+    outgoing: Option<Out>,
+    outgoing_seq: u32,
+    acked_seq: u32,
+    /// The seq of the latest state we received
+    incoming_seq: u32,
+    ack_pending: bool,
+    /// The command to send until the other end acks it
+    outgoing_command: Option<BoardCommand>,
+    command_acked: bool,
+    /// A command that was received, until it is taken with [`Self::take_command`]
+    incoming_command: Option<BoardCommand>,
+    command_ack_pending: bool,
+    /// We are the central, which sends its time so that both boards' logs use the same clock
+    sends_time: bool,
+    /// When we last sent a time sync, in ms
+    last_time_sync: Option<u64>,
+    /// The reply to the time sync that we received: the central's time, and ours when we received it
+    time_sync_reply: Option<(u64, u64)>,
+    round_trip_ms: Option<u16>,
+    clock_offset: ClockOffsetEstimator,
+    _in: core::marker::PhantomData<In>,
+}
+
+impl<Out: SyncPayload + Clone, In: SyncPayload> SyncEngine<Out, In> {
+    pub const fn new() -> Self {
+        Self::with_version(SYNC_PROTOCOL_VERSION)
+    }
+
+    /// Tests use this to pretend to be a different version of the firmware
+    pub(crate) const fn with_version(version: u16) -> Self {
+        Self {
+            version,
+            status: SyncStatus::Disconnected,
+            hello_received: false,
+            last_sent: None,
+            outgoing: None,
+            outgoing_seq: 0,
+            acked_seq: 0,
+            incoming_seq: 0,
+            ack_pending: false,
+            outgoing_command: None,
+            command_acked: false,
+            incoming_command: None,
+            command_ack_pending: false,
+            sends_time: false,
+            last_time_sync: None,
+            time_sync_reply: None,
+            round_trip_ms: None,
+            clock_offset: ClockOffsetEstimator::new(),
+            _in: core::marker::PhantomData,
+        }
+    }
+
+    pub fn status(&self) -> SyncStatus {
+        self.status
+    }
+
+    /// Call this when the L2CAP channel is opened
+    pub fn connected(&mut self) {
+        self.status = SyncStatus::Handshaking;
+        self.hello_received = false;
+        self.last_sent = None;
+        // The other end may have restarted, so it needs our state again
+        self.acked_seq = 0;
+        self.ack_pending = false;
+        self.last_time_sync = None;
+        self.time_sync_reply = None;
+        if self
+            .outgoing_command
+            .is_some_and(BoardCommand::resend_on_reconnect)
+        {
+            self.command_acked = false;
+        }
+    }
+
+    pub fn disconnected(&mut self) {
+        self.status = SyncStatus::Disconnected;
+    }
+
+    /// Replaces the state to send. The latest state is sent once the other end is ready.
+    pub fn set_outgoing(&mut self, state: Out) {
+        self.outgoing_seq += 1;
+        self.outgoing = Some(state);
+        // Send the new state right away instead of waiting to resend the old one
+        self.last_sent = None;
+    }
+
+    /// The other end has the latest state that we gave to [`Self::set_outgoing`]
+    pub fn is_acked(&self) -> bool {
+        self.acked_seq == self.outgoing_seq
+    }
+
+    /// Sends `command` once the other end is ready, and keeps re-sending it until it is acked,
+    /// even across reconnecting
+    pub fn send_command(&mut self, command: BoardCommand) {
+        self.outgoing_command = Some(command);
+        self.command_acked = false;
+        self.last_sent = None;
+    }
+
+    /// The other end acked the command that was given to [`Self::send_command`]
+    pub fn is_command_acked(&self) -> bool {
+        self.command_acked
+    }
+
+    /// The command that the other end sent, if it wasn't taken yet.
+    /// Call [`Self::ack_command`] once it is done.
+    pub fn take_command(&mut self) -> Option<BoardCommand> {
+        self.incoming_command.take()
+    }
+
+    /// Tells the other end that its command is done
+    pub fn ack_command(&mut self) {
+        self.command_ack_pending = true;
+    }
+
+    /// Call this on the central, which sends its time every [`TIME_SYNC_INTERVAL_MS`].
+    /// The peripheral learns the offset between the clocks from it, so that both boards can log with the central's clock.
+    pub fn send_time(&mut self) {
+        self.sends_time = true;
+    }
+
+    /// The central's time in ms, from our time `now`.
+    /// Until the clocks are synced, the peripheral's own time is used.
+    pub fn central_time(&self, now: u64) -> u64 {
+        match self.clock_offset.offset_ms() {
+            Some(offset_ms) if !self.sends_time => now.saturating_add_signed(offset_ms),
+            _ => now,
+        }
+    }
+
+    /// `None` until a time sync came back
+    pub fn clock_sync(&self) -> Option<ClockSync> {
+        Some(ClockSync {
+            offset_ms: self.clock_offset.offset_ms()?,
+            round_trip_ms: self.round_trip_ms?,
+        })
+    }
+
+    /// Returns the next message to send. Call this until it returns `None`.
+    /// `now` is in ms.
+    pub fn poll_transmit(&mut self, now: u64) -> Option<SyncMessage<Out>> {
+        let resend_due = self
+            .last_sent
+            .is_none_or(|last_sent| now >= last_sent + SYNC_RESEND_MS);
+        match self.status {
+            SyncStatus::Disconnected | SyncStatus::VersionMismatch { .. } => None,
+            SyncStatus::Handshaking => {
+                if resend_due {
+                    self.last_sent = Some(now);
+                    Some(SyncMessage::Hello {
+                        version: self.version,
+                    })
+                } else {
+                    None
+                }
+            }
+            SyncStatus::Ready => {
+                if self.ack_pending {
+                    self.ack_pending = false;
+                    Some(SyncMessage::Ack {
+                        seq: self.incoming_seq,
+                    })
+                } else if self.command_ack_pending {
+                    self.command_ack_pending = false;
+                    Some(SyncMessage::CommandAck)
+                } else if let Some((central_ms, peripheral_ms)) = self.time_sync_reply.take() {
+                    Some(SyncMessage::TimeSyncReply {
+                        central_ms,
+                        peripheral_ms,
+                    })
+                } else if self.sends_time
+                    && self.hello_received
+                    && self
+                        .last_time_sync
+                        .is_none_or(|last| now >= last + TIME_SYNC_INTERVAL_MS)
+                {
+                    self.last_time_sync = Some(now);
+                    Some(SyncMessage::TimeSync {
+                        central_ms: now,
+                        round_trip_ms: self.round_trip_ms.unwrap_or(0),
+                    })
+                } else if !resend_due {
+                    None
+                } else if !self.hello_received {
+                    self.last_sent = Some(now);
+                    Some(SyncMessage::Hello {
+                        version: self.version,
+                    })
+                } else if let Some(command) = self.outgoing_command.filter(|_| !self.command_acked)
+                {
+                    self.last_sent = Some(now);
+                    Some(SyncMessage::Command { command })
+                } else if let Some(state) = self.outgoing.as_ref().filter(|_| !self.is_acked()) {
+                    self.last_sent = Some(now);
+                    Some(SyncMessage::State {
+                        seq: self.outgoing_seq,
+                        state: state.clone(),
+                    })
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    /// The other end received our hello
+    fn peer_ready(&mut self) {
+        if !self.hello_received {
+            self.hello_received = true;
+            // Send our state right away instead of waiting to resend the hello
+            self.last_sent = None;
+        }
+    }
+
+    /// Returns the other end's state if it is newer than the last one received.
+    /// `now` is in ms.
+    pub fn receive(&mut self, message: SyncMessage<In>, now: u64) -> Option<In> {
+        match (self.status, message) {
+            (SyncStatus::Disconnected | SyncStatus::VersionMismatch { .. }, _) => None,
+            (_, SyncMessage::Hello { version }) => {
+                if version != self.version {
+                    log_warn!(
+                        "Sync protocol version mismatch: ours is {} but theirs is {}",
+                        self.version,
+                        version
+                    );
+                    self.status = SyncStatus::VersionMismatch {
+                        peer_version: version,
+                    };
+                } else if self.status == SyncStatus::Handshaking {
+                    self.status = SyncStatus::Ready;
+                    // The other end may have restarted, so its seq could have gone back to 0
+                    self.incoming_seq = 0;
+                    // Ack right away so that the other end knows that we received its hello
+                    self.ack_pending = true;
+                } else {
+                    // The other end didn't get our ack yet
+                    self.ack_pending = true;
+                }
+                None
+            }
+            (SyncStatus::Handshaking, _) => None,
+            (SyncStatus::Ready, SyncMessage::State { seq, state }) => {
+                self.peer_ready();
+                self.ack_pending = true;
+                if seq > self.incoming_seq {
+                    self.incoming_seq = seq;
+                    Some(state)
+                } else {
+                    // Duplicate or reordered
+                    None
+                }
+            }
+            (SyncStatus::Ready, SyncMessage::Ack { seq }) => {
+                self.peer_ready();
+                if seq <= self.outgoing_seq {
+                    self.acked_seq = self.acked_seq.max(seq);
+                }
+                None
+            }
+            (SyncStatus::Ready, SyncMessage::Command { command }) => {
+                self.peer_ready();
+                self.incoming_command = Some(command);
+                None
+            }
+            (
+                SyncStatus::Ready,
+                SyncMessage::TimeSync {
+                    central_ms,
+                    round_trip_ms,
+                },
+            ) => {
+                self.peer_ready();
+                // The central's time is about half of the round trip old
+                let central_now = central_ms + u64::from(round_trip_ms / 2);
+                self.clock_offset.record(central_now as i64 - now as i64);
+                if round_trip_ms != 0 {
+                    self.round_trip_ms = Some(round_trip_ms);
+                }
+                self.time_sync_reply = Some((central_ms, now));
+                None
+            }
+            (
+                SyncStatus::Ready,
+                SyncMessage::TimeSyncReply {
+                    central_ms,
+                    peripheral_ms,
+                },
+            ) => {
+                self.peer_ready();
+                // A reply to a time sync from before we rebooted would be from the future
+                if let Some(round_trip_ms) = now.checked_sub(central_ms) {
+                    let round_trip_ms = u16::try_from(round_trip_ms).unwrap_or(u16::MAX);
+                    self.round_trip_ms = Some(round_trip_ms);
+                    let central_ms = central_ms + u64::from(round_trip_ms / 2);
+                    self.clock_offset
+                        .record(central_ms as i64 - peripheral_ms as i64);
+                }
+                None
+            }
+            (SyncStatus::Ready, SyncMessage::CommandAck) => {
+                self.peer_ready();
+                if self.outgoing_command.is_some() {
+                    self.command_acked = true;
+                    // Send the state right away instead of waiting to resend the command
+                    self.last_sent = None;
+                }
+                None
+            }
+        }
+    }
+}
+
+impl<Out: SyncPayload + Clone, In: SyncPayload> Default for SyncEngine<Out, In> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The liberal board's end
+pub type CentralSync = SyncEngine<LedsDisplay, FascistBoardCards>;
+/// The fascist board's end
+pub type PeripheralSync = SyncEngine<FascistBoardCards, LedsDisplay>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn message_round_trip() {
+        let leds = LedsDisplay {
+            aura_led_color: AuraLedColor::FascistWin,
+            liberal_policy_leds: 2,
+            fascist_policy_leds: 6,
+            election_tracker_leds: 2,
+            election_tracker_warning: true,
+            blink_aura: false,
+            misplaced_board: Some(Team::Fascist),
+            dimmed: true,
+            election_tracker_placement: ElectionTrackerPlacement::Both,
+            phase: GamePhase::FascistWin,
+        };
+        let message = SyncMessage::State {
+            seq: 7,
+            state: leds.clone(),
+        };
+        let frame = message.encode().unwrap();
+        assert_eq!(SyncMessage::decode(&frame), Ok(message));
+        for election_tracker_placement in [
+            ElectionTrackerPlacement::Liberal,
+            ElectionTrackerPlacement::Fascist,
+        ] {
+            let message = SyncMessage::State {
+                seq: 7,
+                state: LedsDisplay {
+                    election_tracker_placement,
+                    ..leds.clone()
+                },
+            };
+            assert_eq!(SyncMessage::decode(&message.encode().unwrap()), Ok(message));
+        }
+
+        let cards = (0..FASCIST_BOARD_SLOTS)
+            .map(|id| PolicyCardId {
+                team: Team::Fascist,
+                id,
+            })
+            .chain([PolicyCardId {
+                team: Team::Liberal,
+                id: 5,
+            }])
+            .collect::<FascistBoardCards>();
+        let message = SyncMessage::State {
+            seq: u32::MAX,
+            state: cards,
+        };
+        let frame = message.encode().unwrap();
+        assert!(frame.len() <= SYNC_MTU);
+        assert_eq!(SyncMessage::decode(&frame), Ok(message.clone()));
+        // The same cards scanned in a different order are sent the same way
+        let SyncMessage::State { seq, state } = message else {
+            unreachable!()
+        };
+        let reversed = SyncMessage::State {
+            seq,
+            state: state
+                .iter()
+                .copied()
+                .collect::<alloc::vec::Vec<_>>()
+                .into_iter()
+                .rev()
+                .collect::<FascistBoardCards>(),
+        };
+        assert_eq!(reversed.encode().unwrap(), frame);
+
+        for message in [
+            SyncMessage::<LedsDisplay>::Hello { version: 0x1234 },
+            SyncMessage::Ack { seq: 3 },
+            SyncMessage::Command {
+                command: BoardCommand::Shutdown { reboot: true },
+            },
+            SyncMessage::Command {
+                command: BoardCommand::Shutdown { reboot: false },
+            },
+            SyncMessage::Command {
+                command: BoardCommand::GameOver {
+                    winner: Team::Liberal,
+                },
+            },
+            SyncMessage::Command {
+                command: BoardCommand::GameOver {
+                    winner: Team::Fascist,
+                },
+            },
+            SyncMessage::Command {
+                command: BoardCommand::NewGame,
+            },
+            SyncMessage::CommandAck,
+            SyncMessage::TimeSync {
+                central_ms: u64::MAX,
+                round_trip_ms: 40,
+            },
+            SyncMessage::TimeSyncReply {
+                central_ms: 1,
+                peripheral_ms: u64::MAX,
+            },
+        ] {
+            assert_eq!(SyncMessage::decode(&message.encode().unwrap()), Ok(message));
+        }
+    }
+
+    #[test]
+    fn fascist_board_leds() {
+        let mut leds = FascistBoardLeds::new();
+        assert_eq!(leds.leds(), None);
+        // After restarting, the winner is shown before anything is synced
+        assert!(leds.command(BoardCommand::GameOver {
+            winner: Team::Fascist
+        }));
+        assert_eq!(
+            leds.leds().unwrap().aura_led_color,
+            AuraLedColor::FascistWin
+        );
+        let synced = LedsDisplay {
+            aura_led_color: AuraLedColor::BoardSpecific,
+            liberal_policy_leds: 1,
+            fascist_policy_leds: 6,
+            election_tracker_leds: 0,
+            election_tracker_warning: false,
+            blink_aura: false,
+            misplaced_board: None,
+            dimmed: false,
+            election_tracker_placement: ElectionTrackerPlacement::Liberal,
+            phase: GamePhase::Playing,
+        };
+        leds.synced(synced.clone());
+        assert_eq!(
+            leds.leds(),
+            Some(LedsDisplay {
+                aura_led_color: AuraLedColor::FascistWin,
+                ..synced.clone()
+            })
+        );
+        assert!(!leds.command(BoardCommand::Shutdown { reboot: true }));
+        assert!(leds.command(BoardCommand::NewGame));
+        assert_eq!(leds.leds(), Some(synced));
+    }
+
+    #[test]
+    fn decode_invalid() {
+        for bytes in [
+            &[][..],
+            &[0, 1],
+            &[2, 0, 0, 0],
+            &[2, 0, 0, 0, 0, 0],
+            &[3, 0],
+            // A reboot flag that isn't a bool
+            &[3, 0, 2],
+            // A team that doesn't exist
+            &[3, 1, 2],
+            &[3, 2, 0],
+            &[4, 0],
+            &[5, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            &[6, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+            &[7, 0, 0],
+            &[1, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0, 0],
+            // An election tracker placement that doesn't exist
+            &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0b11 << 3, 0, 0],
+            // A phase that doesn't exist
+            &[1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 4],
+        ] {
+            assert_eq!(SyncMessage::<LedsDisplay>::decode(bytes), Err(DecodeError));
+        }
+        // Odd number of bytes for the cards
+        assert_eq!(
+            SyncMessage::<FascistBoardCards>::decode(&[1, 0, 0, 0, 0, 1, 0, 1]),
+            Err(DecodeError)
+        );
+    }
+
+    #[test]
+    fn adv_data() {
+        let [version_low, version_high] = SYNC_PROTOCOL_VERSION.to_le_bytes();
+        assert_eq!(
+            fascist_adv_data(GamePhase::FascistWin),
+            [
+                0x02,
+                0x01,
+                LE_GENERAL_DISCOVERABLE | BR_EDR_NOT_SUPPORTED,
+                0x06,
+                0xff,
+                0xff,
+                0xff,
+                version_low,
+                version_high,
+                3
+            ]
+        );
+        for phase in [
+            GamePhase::Setup,
+            GamePhase::Playing,
+            GamePhase::LiberalWin,
+            GamePhase::FascistWin,
+        ] {
+            assert_eq!(
+                decode_fascist_adv_data(&fascist_adv_data(phase)),
+                Some((SYNC_PROTOCOL_VERSION, phase))
+            );
+        }
+        // Another device's manufacturer data, and an unknown phase
+        assert_eq!(
+            decode_fascist_adv_data(&[0x02, 0x01, 0x06, 0x06, 0xff, 0x4c, 0x00, 1, 0, 1]),
+            None
+        );
+        assert_eq!(
+            decode_fascist_adv_data(&[0x06, 0xff, 0xff, 0xff, 1, 0, 4]),
+            None
+        );
+    }
+
+    #[test]
+    fn spectator_state() {
+        assert_eq!(SpectatorState::SETUP.encode(), [0, 0, 0, 0, 0]);
+        let mut leds = LedsDisplay {
+            aura_led_color: AuraLedColor::BoardSpecific,
+            liberal_policy_leds: 3,
+            fascist_policy_leds: 2,
+            election_tracker_leds: 1,
+            election_tracker_warning: false,
+            blink_aura: true,
+            misplaced_board: Some(Team::Liberal),
+            dimmed: true,
+            election_tracker_placement: ElectionTrackerPlacement::Liberal,
+            phase: GamePhase::Playing,
+        };
+        let playing = SpectatorState::from_leds(&leds);
+        assert_eq!(playing.encode(), [3, 2, 1, 1, 0]);
+        assert_eq!(playing.winner(), None);
+        leds.phase = GamePhase::LiberalWin;
+        assert_eq!(SpectatorState::from_leds(&leds).encode(), [3, 2, 1, 2, 1]);
+        leds.phase = GamePhase::FascistWin;
+        let fascist_win = SpectatorState::from_leds(&leds);
+        assert_eq!(fascist_win.encode(), [3, 2, 1, 3, 2]);
+        assert_eq!(fascist_win.winner(), Some(Team::Fascist));
+
+        for state in [SpectatorState::SETUP, playing, fascist_win] {
+            assert_eq!(SpectatorState::decode(state.encode()), Ok(state));
+        }
+        for invalid in [
+            // More policies than slots
+            [6, 0, 0, 1, 0],
+            [0, 7, 0, 1, 0],
+            [0, 0, 4, 1, 0],
+            // Not a phase
+            [0, 0, 0, 4, 0],
+            // Not a team
+            [0, 0, 0, 1, 3],
+            // A winner that doesn't match the phase
+            [0, 0, 0, 1, 1],
+            [5, 0, 0, 2, 2],
+            [0, 6, 0, 3, 0],
+        ] {
+            assert_eq!(
+                SpectatorState::decode(invalid),
+                Err(DecodeError),
+                "{invalid:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use heapless::Deque;
+
+/// How often the central sends its time to the peripheral, in ms
+pub const TIME_SYNC_INTERVAL_MS: u64 = 5_000;
+/// How many of the latest samples [`ClockOffsetEstimator`] takes the median of
+pub const CLOCK_OFFSET_SAMPLES: usize = 5;
+/// A sample this far from the median is an outlier, like one that was held up by a BLE retransmission
+pub const CLOCK_OFFSET_OUTLIER_MS: i64 = 50;
+/// This many outliers in a row mean that the other clock really jumped, like after the central rebooted
+pub const CLOCK_OFFSET_STEP_SAMPLES: usize = 3;
+
+/// Estimates how far the central's clock is ahead of ours, from samples that are each off by some jitter.
+/// The estimate is the median of the last [`CLOCK_OFFSET_SAMPLES`] samples, without outliers.
+#[derive(Debug, Clone, Default)]
+pub struct ClockOffsetEstimator {
+    samples: Deque<i64, CLOCK_OFFSET_SAMPLES>,
+    /// Outliers in a row, which replace the samples once there are [`CLOCK_OFFSET_STEP_SAMPLES`] of them
+    outliers: Deque<i64, CLOCK_OFFSET_STEP_SAMPLES>,
+}
+
+impl ClockOffsetEstimator {
+    pub const fn new() -> Self {
+        Self {
+            samples: Deque::new(),
+            outliers: Deque::new(),
+        }
+    }
+
+    /// Adds a sample of the central's time minus our time, in ms
+    pub fn record(&mut self, offset_ms: i64) {
+        let outlier = self
+            .offset_ms()
+            .is_some_and(|median| median.abs_diff(offset_ms) > CLOCK_OFFSET_OUTLIER_MS as u64);
+        if !outlier {
+            self.outliers.clear();
+            push_replacing_oldest(&mut self.samples, offset_ms);
+            return;
+        }
+        push_replacing_oldest(&mut self.outliers, offset_ms);
+        if self.outliers.is_full() {
+            // The old samples are from before the jump, so they would hold the estimate back
+            self.samples.clear();
+            while let Some(outlier) = self.outliers.pop_front() {
+                push_replacing_oldest(&mut self.samples, outlier);
+            }
+        }
+    }
+
+    /// The central's time minus our time in ms. `None` until there is a sample.
+    pub fn offset_ms(&self) -> Option<i64> {
+        let mut sorted = heapless::Vec::<i64, CLOCK_OFFSET_SAMPLES>::new();
+        for &sample in &self.samples {
+            // There are at most as many samples as the capacity
+            let _ = sorted.push(sample);
+        }
+        sorted.sort_unstable();
+        sorted.get(sorted.len() / 2).copied()
+    }
+
+    /// Forgets every sample, like when the other board disconnects and may reboot
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.outliers.clear();
+    }
+}
+
+fn push_replacing_oldest<const N: usize>(deque: &mut Deque<i64, N>, value: i64) {
+    if deque.is_full() {
+        deque.pop_front();
+    }
+    // There is room after removing the oldest value
+    let _ = deque.push_back(value);
+}
+
+/// How well the boards' clocks are synced, for the About screen
+#[cfg_attr(feature = "defmt", derive(defmt::Format))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSync {
+    /// The central's time minus the peripheral's time, in ms
+    pub offset_ms: i64,
+    /// How long the last time sync took to come back to the central, in ms
+    pub round_trip_ms: u16,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Jitter of up to ±10 ms, which always comes out the same
+    fn jitter(i: i64) -> i64 {
+        (i * 7) % 21 - 10
+    }
+
+    #[test]
+    fn median_of_jittered_samples() {
+        let mut estimator = ClockOffsetEstimator::new();
+        assert_eq!(estimator.offset_ms(), None);
+        for i in 0..20 {
+            estimator.record(1_000 + jitter(i));
+            let offset = estimator.offset_ms().unwrap();
+            assert!((990..=1_010).contains(&offset), "{offset}");
+        }
+        // Only the last 5 samples count
+        for sample in [1_003, 998, 1_001, 1_000, 1_002] {
+            estimator.record(sample);
+        }
+        assert_eq!(estimator.offset_ms(), Some(1_001));
+    }
+
+    #[test]
+    fn outlier_rejected() {
+        let mut estimator = ClockOffsetEstimator::new();
+        for i in 0..5 {
+            estimator.record(-500 + jitter(i));
+        }
+        let before = estimator.offset_ms();
+        // A message that was held up by a retransmission
+        estimator.record(-500 + 400);
+        assert_eq!(estimator.offset_ms(), before);
+        // Two outliers in a row still isn't a jump
+        estimator.record(-500 + 400);
+        assert_eq!(estimator.offset_ms(), before);
+        // A good sample ends the streak
+        estimator.record(-500);
+        estimator.record(-500 + 400);
+        estimator.record(-500 + 400);
+        assert_eq!(estimator.offset_ms(), before);
+    }
+
+    #[test]
+    fn step_after_central_reboot() {
+        let mut estimator = ClockOffsetEstimator::new();
+        for i in 0..10 {
+            estimator.record(60_000 + jitter(i));
+        }
+        // The central rebooted, so its clock is now behind ours
+        let mut estimates = heapless::Vec::<_, 10>::new();
+        for i in 0..10 {
+            estimator.record(-2_000 + jitter(i));
+            estimates.push(estimator.offset_ms().unwrap()).unwrap();
+        }
+        // The first two are treated as outliers
+        assert!(
+            estimates[..2]
+                .iter()
+                .all(|offset| offset.abs_diff(60_000) <= 10)
+        );
+        // And then it follows the new clock right away
+        assert!(
+            estimates[2..]
+                .iter()
+                .all(|offset| offset.abs_diff(-2_000) <= 10)
+        );
+
+        estimator.reset();
+        assert_eq!(estimator.offset_ms(), None);
+    }
+}
@@ -1,9 +1,11 @@
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SelectedItem {
     /// The back button is selected
     Back,
     Item(usize),
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Screen<Title, Items> {
     pub title: Title,
     pub can_go_back: bool,
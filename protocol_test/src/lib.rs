@@ -0,0 +1,106 @@
+//! Host-only helpers for testing the UART protocol between the ESP and the STM32 without hardware
+
+use std::collections::VecDeque;
+
+/// A small deterministic random number generator, so that failing tests can be reproduced
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // xorshift gets stuck at 0
+        Self(seed.max(1))
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Returns a number in `range`
+    pub fn range(&mut self, range: std::ops::Range<usize>) -> usize {
+        range.start + (self.next_u64() % (range.end - range.start) as u64) as usize
+    }
+}
+
+/// One direction of an in-memory UART connection.
+///
+/// Bytes arrive `latency` ticks after they are written, and reads return a random number of the bytes that arrived,
+/// like a UART peripheral that returns whatever is in its buffer.
+#[derive(Debug)]
+pub struct Wire {
+    rng: Rng,
+    latency: u64,
+    tick: u64,
+    /// Bytes and the tick at which they arrive
+    in_flight: VecDeque<(u64, u8)>,
+    corrupt_next_packet: bool,
+}
+
+impl Wire {
+    pub fn new(seed: u64, latency: u64) -> Self {
+        Self {
+            rng: Rng::new(seed),
+            latency,
+            tick: 0,
+            in_flight: Default::default(),
+            corrupt_next_packet: false,
+        }
+    }
+
+    pub fn tick(&mut self) {
+        self.tick += 1;
+    }
+
+    /// The next packet that is written will not be readable, but the packets after it will not be affected
+    pub fn corrupt_next_packet(&mut self) {
+        self.corrupt_next_packet = true;
+    }
+
+    /// `packet` is a COBS-encoded packet including the `0` at the end
+    pub fn write(&mut self, packet: &[u8]) {
+        let arrives_at = self.tick + self.latency;
+        for (i, &byte) in packet.iter().enumerate() {
+            let byte = if self.corrupt_next_packet && i == 0 {
+                // The first COBS code now points past the end of the packet
+                0xFF
+            } else {
+                byte
+            };
+            self.in_flight.push_back((arrives_at, byte));
+        }
+        self.corrupt_next_packet = false;
+    }
+
+    /// Reads up to `buffer.len()` bytes that have arrived. Returns the number of bytes read, which can be 0.
+    pub fn read(&mut self, buffer: &mut [u8]) -> usize {
+        let arrived = self
+            .in_flight
+            .iter()
+            .take_while(|(arrives_at, _)| *arrives_at <= self.tick)
+            .count();
+        let max_len = arrived.min(buffer.len());
+        let len = if max_len == 0 {
+            0
+        } else {
+            self.rng.range(1..max_len + 1)
+        };
+        for byte in &mut buffer[..len] {
+            *byte = self.in_flight.pop_front().unwrap().1;
+        }
+        len
+    }
+
+    /// `true` if there are no bytes left to read
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
+
+/// Encodes `value` the same way the firmware does, and writes it to `wire`
+pub fn send<T: serde::Serialize>(wire: &mut Wire, value: &T) {
+    let mut buffer = [0; 1024];
+    wire.write(postcard::to_slice_cobs(value, &mut buffer).unwrap());
+}
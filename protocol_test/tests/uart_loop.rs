@@ -0,0 +1,146 @@
+use common::{Event, LedStaging, PacketReader, Request, led_frame_requests};
+use protocol_test::{Wire, send};
+use smart_leds::RGB8;
+
+const TOTAL_LEDS: usize = 64;
+const FRAMES: usize = 50;
+
+fn frame(i: usize) -> [RGB8; TOTAL_LEDS] {
+    core::array::from_fn(|led| RGB8::new(i as u8, led as u8, 255 - i as u8))
+}
+
+/// The STM32 side, which handles requests the same way as the firmware
+#[derive(Default)]
+struct Stm {
+    reader: PacketReader<1024>,
+    led_staging: LedStaging<TOTAL_LEDS>,
+    watching_nfc: bool,
+    requests: Vec<String>,
+    committed_frames: Vec<[RGB8; TOTAL_LEDS]>,
+    errors: usize,
+}
+
+impl Stm {
+    fn read(&mut self, from_esp: &mut Wire, to_esp: &mut Wire) {
+        let bytes_read = from_esp.read(self.reader.unfilled());
+        self.reader.received(bytes_read);
+        while let Some(result) = self.reader.next_packet::<Request>() {
+            let Ok(request) = result else {
+                self.errors += 1;
+                continue;
+            };
+            self.requests.push(format!("{request:?}"));
+            match request {
+                Request::SoftReset => {
+                    self.led_staging = LedStaging::new();
+                    self.watching_nfc = false;
+                    send(to_esp, &Event::SoftResetComplete);
+                }
+                Request::SetLedsFrame { offset, colors } => {
+                    self.led_staging.stage(offset, &colors).unwrap();
+                }
+                Request::CommitLeds => {
+                    self.committed_frames.push(*self.led_staging.frame());
+                }
+                Request::WatchNfc(watch) => {
+                    self.watching_nfc = watch;
+                }
+                _ => unreachable!("not sent in this test"),
+            }
+        }
+    }
+}
+
+/// The ESP side
+#[derive(Default)]
+struct Esp {
+    reader: PacketReader<1024>,
+    soft_reset_completes: usize,
+    nfc_events: Vec<usize>,
+    errors: usize,
+}
+
+impl Esp {
+    fn read(&mut self, from_stm: &mut Wire) {
+        let bytes_read = from_stm.read(self.reader.unfilled());
+        self.reader.received(bytes_read);
+        while let Some(result) = self.reader.next_packet::<Event>() {
+            match result {
+                Ok(Event::SoftResetComplete) => {
+                    assert!(self.nfc_events.is_empty());
+                    self.soft_reset_completes += 1;
+                }
+                Ok(Event::Nfc(uids)) => self.nfc_events.push(uids.len()),
+                Ok(event) => panic!("unexpected event: {event:?}"),
+                Err(_) => self.errors += 1,
+            }
+        }
+    }
+}
+
+#[test]
+fn uart_loop() {
+    let mut esp_to_stm = Wire::new(1, 3);
+    let mut stm_to_esp = Wire::new(2, 5);
+    let mut esp = Esp::default();
+    let mut stm = Stm::default();
+
+    let mut requests = vec![Request::SoftReset, Request::WatchNfc(true)];
+    for i in 0..FRAMES {
+        requests.extend(led_frame_requests(&frame(i)));
+    }
+    // Stop watching so that the STM32 stops sending events
+    requests.push(Request::WatchNfc(false));
+    // The packets of the last frame, and the final request
+    let last_requests = requests.len() - 4;
+    let mut expected_requests = Vec::new();
+    let mut corrupted_requests = 0;
+    let mut requests = requests.into_iter().enumerate().peekable();
+
+    let mut nfc_events_sent = Vec::new();
+    let mut corrupted_events = 0;
+    let mut tick = 0;
+    while requests.peek().is_some() || !esp_to_stm.is_empty() || !stm_to_esp.is_empty() {
+        // Corrupt some of the LED packets, but not the ones of the last frame
+        if let Some((i, request)) = requests.next() {
+            if matches!(request, Request::SetLedsFrame { .. }) && i % 7 == 0 && i < last_requests {
+                esp_to_stm.corrupt_next_packet();
+                corrupted_requests += 1;
+            } else {
+                expected_requests.push(format!("{request:?}"));
+            }
+            send(&mut esp_to_stm, &request);
+        }
+        stm.read(&mut esp_to_stm, &mut stm_to_esp);
+
+        if stm.watching_nfc && tick % 3 == 0 {
+            let readers = tick % 6 + 1;
+            if tick % 5 == 0 {
+                stm_to_esp.corrupt_next_packet();
+                corrupted_events += 1;
+            } else {
+                nfc_events_sent.push(readers);
+            }
+            send(
+                &mut stm_to_esp,
+                &Event::Nfc(core::iter::repeat_n(None, readers).collect()),
+            );
+        }
+        esp.read(&mut stm_to_esp);
+
+        esp_to_stm.tick();
+        stm_to_esp.tick();
+        tick += 1;
+    }
+
+    assert!(corrupted_requests > 0);
+    assert_eq!(stm.requests, expected_requests);
+    assert_eq!(stm.errors, corrupted_requests);
+    assert_eq!(stm.committed_frames.len(), FRAMES);
+    assert_eq!(stm.committed_frames.last(), Some(&frame(FRAMES - 1)));
+
+    assert_eq!(esp.soft_reset_completes, 1);
+    assert!(corrupted_events > 0);
+    assert_eq!(esp.nfc_events, nfc_events_sent);
+    assert_eq!(esp.errors, corrupted_events);
+}
@@ -6,6 +6,8 @@ pub struct Debouncer<T> {
     value: Option<T>,
     pending_value: Option<(T, Instant)>,
     debounce_time: Duration,
+    /// When the stable value first started being processed
+    stable_since: Option<Instant>,
 }
 
 impl<T: PartialEq> Debouncer<T> {
@@ -14,6 +16,7 @@ impl<T: PartialEq> Debouncer<T> {
             value: None,
             pending_value: None,
             debounce_time,
+            stable_since: None,
         }
     }
 
@@ -26,7 +29,9 @@ impl<T: PartialEq> Debouncer<T> {
             && pending_value == &latest_data
         {
             if (now - *instant) >= self.debounce_time {
-                let new_value = self.value.insert(self.pending_value.take().unwrap().0);
+                let (pending_value, instant) = self.pending_value.take().unwrap();
+                self.stable_since = Some(instant);
+                let new_value = self.value.insert(pending_value);
                 Some(new_value)
             } else {
                 None
@@ -59,6 +64,11 @@ impl<T: PartialEq> Debouncer<T> {
         self.value.as_ref()
     }
 
+    /// When the stable value started. This is before the debounce time passed, so it can be used to measure durations between changes.
+    pub fn stable_since(&self) -> Option<Instant> {
+        self.stable_since
+    }
+
     // pub fn value(&self) -> T {
     //     self.value
     // }
@@ -0,0 +1,423 @@
+use core::{
+    cell::Cell,
+    fmt::{self, Write as _},
+    sync::atomic::Ordering,
+};
+
+use common::{
+    CONSOLE_HELP, CONSOLE_LINE_LEN, ConsoleCommand, ConsoleError, DWELL_SWEEP_ATTEMPTS, DwellSweep,
+    DwellSweepResults, LINK_DOWN_TIMEOUT_MS, LineBuffer, MAX_NFC_READERS,
+    NFC_SOAK_SUMMARY_INTERVAL_S, NfcSoakStats, PROTOCOL_VERSION,
+};
+use defmt::{Display2Format, info, warn};
+use embassy_futures::select::{Either, select};
+use embassy_stm32::{
+    Peri, bind_interrupts,
+    flash::{Blocking, FLASH_SIZE, Flash, MAX_ERASE_SIZE},
+    peripherals::{FLASH, PA9, PA10, USART1},
+    usart::{self, BufferedUart, BufferedUartTx},
+};
+use embassy_sync::signal::Signal;
+use embassy_time::{Duration, Instant, Timer, WithTimeout};
+use embedded_io_async::{Read, Write};
+use heapless::Vec;
+use smart_leds::RGB;
+
+use crate::{
+    FW_VERSION, HOLD_LEDS_FRAME, LAST_REQUEST_AT, LEDS_DISABLED, LEDS_SIGNAL, M, NFC_DWELL_MS,
+    NfcReader, PACKET_ERRORS, REQUESTS_RECEIVED, SOFT_RESET_SIGNALS, TOTAL_LEDS,
+    WORKING_NFC_READERS, acknowledge_soft_reset, read_card, read_card_result,
+};
+
+bind_interrupts!(struct Irqs {
+    USART1 => usart::BufferedInterruptHandler<USART1>;
+});
+
+/// Asks the NFC task to read the version of every working NFC reader
+pub static NFC_PROBE_SIGNAL: Signal<M, ()> = Signal::new();
+/// The chip type and version of each working NFC reader, or `None` if reading it failed
+pub static NFC_PROBE_RESULT_SIGNAL: Signal<M, Vec<Option<(u8, u8)>, MAX_NFC_READERS>> =
+    Signal::new();
+/// Asks the NFC task to sweep the dwell of the NFC reader at this index
+pub static NFC_DWELL_SWEEP_SIGNAL: Signal<M, u8> = Signal::new();
+/// `None` if the reader isn't working
+pub static NFC_DWELL_SWEEP_RESULT_SIGNAL: Signal<M, Option<DwellSweepResults>> = Signal::new();
+/// Asks the NFC task to start (`true`) or stop (`false`) a soak test
+pub static NFC_SOAK_SIGNAL: Signal<M, bool> = Signal::new();
+/// A summary of the soak test, and whether the soak test is finished
+pub static NFC_SOAK_REPORT_SIGNAL: Signal<M, (NfcSoakStats, bool)> = Signal::new();
+
+const BAUD_RATE: u32 = 115_200;
+/// The NFC task only checks for a probe between scans
+const NFC_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+/// How long the antenna is off between reads of a dwell sweep, so that the card fully powers down
+const DWELL_SWEEP_OFF_MS: u64 = 10;
+/// A sweep takes about 5s
+const NFC_DWELL_SWEEP_TIMEOUT: Duration = Duration::from_secs(15);
+/// The soak report is saved to the last page of flash, which the firmware is far too small to reach
+const SOAK_REPORT_OFFSET: u32 = (FLASH_SIZE - MAX_ERASE_SIZE) as u32;
+/// A `u16` length followed by the postcard bytes.
+/// This fits the largest possible report, and is a multiple of the flash's write size.
+const SOAK_REPORT_LEN: usize = 160;
+/// Summaries are saved less often than they are printed, since each save erases the flash page.
+/// A page can only be erased about 10,000 times.
+const SOAK_SAVE_INTERVAL_S: u32 = 600;
+
+/// Reads the card on `device` at every dwell of a [`DwellSweep`]
+pub async fn sweep_dwell(device: &mut NfcReader<'_>) -> DwellSweepResults {
+    let mut sweep = DwellSweep::new(DWELL_SWEEP_ATTEMPTS);
+    while let Some(dwell_ms) = sweep.dwell_ms() {
+        sweep.record(read_card(device, dwell_ms).await.is_some());
+        Timer::after_millis(DWELL_SWEEP_OFF_MS).await;
+    }
+    sweep.results()
+}
+
+/// Reads every reader with the antenna off in between, until [`NFC_SOAK_SIGNAL`] is `false`.
+/// Summaries are sent with [`NFC_SOAK_REPORT_SIGNAL`] every [`NFC_SOAK_SUMMARY_INTERVAL_S`], and when it stops.
+pub async fn soak(devices: &mut [NfcReader<'_>]) {
+    info!("Starting NFC soak test with {} readers", devices.len());
+    let start = Instant::now();
+    let mut stats = NfcSoakStats::new(devices.len());
+    let mut next_summary_at = start + Duration::from_secs(NFC_SOAK_SUMMARY_INTERVAL_S);
+    loop {
+        let dwell_ms = NFC_DWELL_MS.lock(Cell::get);
+        for (i, device) in devices.iter_mut().enumerate() {
+            stats.record_retried(i, &read_card_result(device, dwell_ms[i]).await);
+        }
+        // Starting a soak test while one is running does nothing
+        let finished = NFC_SOAK_SIGNAL.try_take() == Some(false);
+        let now = Instant::now();
+        if finished || now >= next_summary_at {
+            stats.elapsed_s = (now - start).as_secs() as u32;
+            NFC_SOAK_REPORT_SIGNAL.signal((stats.clone(), finished));
+            next_summary_at += Duration::from_secs(NFC_SOAK_SUMMARY_INTERVAL_S);
+        }
+        if finished {
+            return;
+        }
+        Timer::after_millis(DWELL_SWEEP_OFF_MS).await;
+    }
+}
+
+fn save_soak_report(flash: &mut Flash<'_, Blocking>, stats: &NfcSoakStats) {
+    let mut buffer = [0xFF; SOAK_REPORT_LEN];
+    let len = match postcard::to_slice(stats, &mut buffer[2..]) {
+        Ok(bytes) => bytes.len(),
+        Err(e) => {
+            warn!("Error serializing NFC soak report: {}", e);
+            return;
+        }
+    };
+    buffer[..2].copy_from_slice(&(len as u16).to_le_bytes());
+    let result = flash
+        .blocking_erase(
+            SOAK_REPORT_OFFSET,
+            SOAK_REPORT_OFFSET + MAX_ERASE_SIZE as u32,
+        )
+        .and_then(|()| flash.blocking_write(SOAK_REPORT_OFFSET, &buffer));
+    if let Err(e) = result {
+        warn!("Error saving NFC soak report: {}", e);
+    }
+}
+
+/// `None` if no report was saved
+fn load_soak_report(flash: &mut Flash<'_, Blocking>) -> Option<NfcSoakStats> {
+    let mut buffer = [0; SOAK_REPORT_LEN];
+    flash.blocking_read(SOAK_REPORT_OFFSET, &mut buffer).ok()?;
+    // Erased flash has a length of 0xFFFF
+    let len = usize::from(u16::from_le_bytes([buffer[0], buffer[1]]));
+    postcard::from_bytes(buffer[2..].get(..len)?).ok()
+}
+
+/// Prints the summary to the console and defmt.
+/// If there is a fail threshold, each reader's LED turns green or red.
+async fn print_soak_report(
+    tx: &mut BufferedUartTx<'_>,
+    stats: &NfcSoakStats,
+    fail_threshold_percent: Option<u8>,
+) {
+    info!("{}", Display2Format(&stats.header()));
+    print(tx, format_args!("{}", stats.header())).await;
+    for (i, reader) in stats.readers.iter().enumerate() {
+        let failing = fail_threshold_percent.is_some_and(|threshold| reader.exceeds(threshold));
+        let fail = if failing { " FAIL" } else { "" };
+        info!("[{}] {}{}", i, Display2Format(reader), fail);
+        print(tx, format_args!("[{i}] {reader}{fail}")).await;
+    }
+    if let Some(threshold) = fail_threshold_percent {
+        let mut frame = [RGB::default(); TOTAL_LEDS];
+        for (i, reader) in stats.readers.iter().enumerate() {
+            frame[i] = if reader.exceeds(threshold) {
+                RGB::new(64, 0, 0)
+            } else {
+                RGB::new(0, 64, 0)
+            };
+        }
+        HOLD_LEDS_FRAME.store(true, Ordering::Relaxed);
+        LEDS_SIGNAL.signal(frame);
+    }
+}
+
+/// The ESP sent a valid request recently
+fn esp_session_active() -> bool {
+    REQUESTS_RECEIVED.load(Ordering::Relaxed) > 0
+        && LAST_REQUEST_AT.lock(Cell::get).elapsed() < Duration::from_millis(LINK_DOWN_TIMEOUT_MS)
+}
+
+/// Lines end with `\r\n` so that they look right in any serial terminal
+async fn print(tx: &mut BufferedUartTx<'_>, args: fmt::Arguments<'_>) {
+    let mut line = heapless::String::<128>::new();
+    // Text that is too long is cut off
+    let _ = line.write_fmt(args);
+    let _ = line.push_str("\r\n");
+    if let Err(e) = tx.write_all(line.as_bytes()).await {
+        warn!("Error writing to debug console: {}", e);
+    }
+}
+
+/// The state of the console that lasts between commands
+struct Console {
+    flash: Flash<'static, Blocking>,
+    /// Of the soak test that is running
+    fail_threshold_percent: Option<u8>,
+    /// When the soak report was last saved, in s since the soak test started
+    soak_saved_at_s: u32,
+}
+
+async fn run(tx: &mut BufferedUartTx<'_>, console: &mut Console, command: ConsoleCommand) {
+    info!("Debug console command: {}", command);
+    if command.conflicts_with_esp() && esp_session_active() {
+        print(
+            tx,
+            format_args!("Warning: the ESP is connected and may undo or be confused by this"),
+        )
+        .await;
+    }
+    match command {
+        ConsoleCommand::LedsOff => {
+            // Stays off until the ESP sends a new frame
+            HOLD_LEDS_FRAME.store(false, Ordering::Relaxed);
+            LEDS_SIGNAL.signal([RGB::default(); TOTAL_LEDS]);
+            print(tx, format_args!("LEDs off")).await;
+        }
+        ConsoleCommand::NfcProbe => {
+            NFC_PROBE_SIGNAL.signal(());
+            match NFC_PROBE_RESULT_SIGNAL
+                .wait()
+                .with_timeout(NFC_PROBE_TIMEOUT)
+                .await
+            {
+                Ok(versions) => {
+                    print(
+                        tx,
+                        format_args!("{}/{} working NFC readers", versions.len(), MAX_NFC_READERS),
+                    )
+                    .await;
+                    for (i, version) in versions.iter().enumerate() {
+                        match version {
+                            Some((chip_type, version)) => {
+                                print(
+                                    tx,
+                                    format_args!(
+                                        "[{i}] chip type: {chip_type:#04X}, version: {version:#04X}"
+                                    ),
+                                )
+                                .await
+                            }
+                            None => print(tx, format_args!("[{i}] error reading version")).await,
+                        }
+                    }
+                }
+                Err(_) => print(tx, format_args!("NFC task didn't respond")).await,
+            }
+        }
+        ConsoleCommand::NfcDwellSweep { reader } => {
+            print(
+                tx,
+                format_args!("Sweeping the dwell of NFC reader {reader}, hold a card on it"),
+            )
+            .await;
+            NFC_DWELL_SWEEP_SIGNAL.signal(reader);
+            match NFC_DWELL_SWEEP_RESULT_SIGNAL
+                .wait()
+                .with_timeout(NFC_DWELL_SWEEP_TIMEOUT)
+                .await
+            {
+                Ok(Some(results)) => {
+                    for (dwell_ms, success_rate) in results.success_rates() {
+                        print(tx, format_args!("{dwell_ms:>2}ms: {success_rate:>3}%")).await;
+                    }
+                    match results.min_reliable_dwell_ms() {
+                        Some(dwell_ms) => {
+                            print(
+                                tx,
+                                format_args!("Reliable with a dwell of {dwell_ms}ms or more"),
+                            )
+                            .await
+                        }
+                        None => print(tx, format_args!("Never reliable")).await,
+                    }
+                }
+                Ok(None) => print(tx, format_args!("NFC reader {reader} isn't working")).await,
+                Err(_) => print(tx, format_args!("NFC task didn't respond")).await,
+            }
+        }
+        ConsoleCommand::NfcSoak {
+            fail_threshold_percent,
+        } => {
+            console.fail_threshold_percent = fail_threshold_percent;
+            console.soak_saved_at_s = 0;
+            print(
+                tx,
+                format_args!(
+                    "Soaking {} NFC readers, hold a card on each. Summary every {NFC_SOAK_SUMMARY_INTERVAL_S}s, stop with: nfc stop",
+                    WORKING_NFC_READERS.load(Ordering::Relaxed)
+                ),
+            )
+            .await;
+            NFC_SOAK_SIGNAL.signal(true);
+        }
+        ConsoleCommand::NfcSoakStop => {
+            // The final summary is printed once the NFC task stops
+            NFC_SOAK_SIGNAL.signal(false);
+        }
+        ConsoleCommand::NfcSoakReport => match load_soak_report(&mut console.flash) {
+            // The threshold of the saved soak test isn't known
+            Some(stats) => print_soak_report(tx, &stats, None).await,
+            None => print(tx, format_args!("No NFC soak report saved")).await,
+        },
+        ConsoleCommand::Stats => {
+            let uptime = Instant::now().as_secs();
+            print(
+                tx,
+                format_args!(
+                    "Firmware: {FW_VERSION}, protocol: {PROTOCOL_VERSION}, uptime: {}h{}m{}s",
+                    uptime / 3600,
+                    uptime / 60 % 60,
+                    uptime % 60
+                ),
+            )
+            .await;
+            let requests = REQUESTS_RECEIVED.load(Ordering::Relaxed);
+            if requests > 0 {
+                print(
+                    tx,
+                    format_args!(
+                        "Requests: {requests}, last {}ms ago",
+                        LAST_REQUEST_AT.lock(Cell::get).elapsed().as_millis()
+                    ),
+                )
+                .await;
+            } else {
+                print(tx, format_args!("Requests: 0")).await;
+            }
+            print(
+                tx,
+                format_args!("Packet errors: {}", PACKET_ERRORS.lock(Cell::get)),
+            )
+            .await;
+            print(
+                tx,
+                format_args!(
+                    "NFC readers: {}/{}",
+                    WORKING_NFC_READERS.load(Ordering::Relaxed),
+                    MAX_NFC_READERS
+                ),
+            )
+            .await;
+            if LEDS_DISABLED.load(Ordering::Relaxed) {
+                print(tx, format_args!("LEDs: disabled after too many errors")).await;
+            }
+        }
+        ConsoleCommand::Reset => {
+            print(tx, format_args!("Resetting")).await;
+            let _ = tx.flush().await;
+            cortex_m::peripheral::SCB::sys_reset();
+        }
+        ConsoleCommand::Help => print(tx, format_args!("{CONSOLE_HELP}")).await,
+    }
+}
+
+/// A line-based text console on USART1, for debugging without SWD
+#[embassy_executor::task]
+pub async fn debug_console_task(
+    usart: Peri<'static, USART1>,
+    tx: Peri<'static, PA9>,
+    rx: Peri<'static, PA10>,
+    flash: Peri<'static, FLASH>,
+) {
+    let mut tx_buffer = [0; 256];
+    let mut rx_buffer = [0; 64];
+    let uart = BufferedUart::new(usart, rx, tx, &mut tx_buffer, &mut rx_buffer, Irqs, {
+        let mut config = usart::Config::default();
+        config.baudrate = BAUD_RATE;
+        config
+    })
+    .unwrap();
+    let (mut tx, mut rx) = uart.split();
+    print(&mut tx, format_args!("Debug console. {CONSOLE_HELP}")).await;
+    let mut console = Console {
+        flash: Flash::new_blocking(flash),
+        fail_threshold_percent: None,
+        soak_saved_at_s: 0,
+    };
+    let mut line_buffer = LineBuffer::<CONSOLE_LINE_LEN>::new();
+    let mut buffer = [0; 16];
+    loop {
+        let bytes_read = match select(rx.read(&mut buffer), NFC_SOAK_REPORT_SIGNAL.wait()).await {
+            Either::First(Ok(n)) => n,
+            Either::Second((stats, finished)) => {
+                if finished {
+                    print(&mut tx, format_args!("NFC soak test finished")).await;
+                }
+                print_soak_report(&mut tx, &stats, console.fail_threshold_percent).await;
+                // Saved often enough that a crash doesn't lose much
+                if finished
+                    || stats.elapsed_s.saturating_sub(console.soak_saved_at_s)
+                        >= SOAK_SAVE_INTERVAL_S
+                {
+                    save_soak_report(&mut console.flash, &stats);
+                    console.soak_saved_at_s = stats.elapsed_s;
+                }
+                continue;
+            }
+            Either::First(Err(e)) => {
+                warn!("Error reading debug console: {}", e);
+                continue;
+            }
+        };
+        for &byte in &buffer[..bytes_read] {
+            let line = match line_buffer.push(byte) {
+                Some(line) => line,
+                None => {
+                    // Echo what is typed
+                    let _ = tx.write_all(&[byte]).await;
+                    continue;
+                }
+            };
+            let _ = tx.write_all(b"\r\n").await;
+            match line.and_then(|line| ConsoleCommand::parse(&line)) {
+                Ok(command) => run(&mut tx, &mut console, command).await,
+                Err(ConsoleError::Empty) => {}
+                Err(ConsoleError::UnknownCommand) => {
+                    print(&mut tx, format_args!("Unknown command. {CONSOLE_HELP}")).await
+                }
+                Err(ConsoleError::TooLong) => print(&mut tx, format_args!("Line too long")).await,
+            }
+        }
+    }
+}
+
+/// The debug console uses the rotary encoder's and switch's pins, so their tasks don't run.
+/// This acknowledges soft resets for them so that soft resets still complete.
+#[embassy_executor::task]
+pub async fn rotary_disabled_task() {
+    loop {
+        // Every task is signaled on each soft reset
+        let generation = SOFT_RESET_SIGNALS[1].wait().await;
+        acknowledge_soft_reset(1, generation);
+        let generation = SOFT_RESET_SIGNALS[2].wait().await;
+        acknowledge_soft_reset(2, generation);
+    }
+}
@@ -1,44 +1,68 @@
 #![no_std]
 #![no_main]
+#[cfg(not(feature = "debug-console"))]
 mod debouncer;
+#[cfg(feature = "debug-console")]
+mod debug_console;
 
-use core::array;
+use core::{
+    array,
+    cell::{Cell, RefCell},
+    sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering},
+};
 
-use crate::debouncer::Debouncer;
-use common::{Event, MAX_NFC_READERS, Request};
+use common::{
+    DEFAULT_NFC_DWELL_MS, Event, EventSlot, LINK_DOWN_TIMEOUT_MS, LedStaging, LedWriter,
+    LinkNoiseDetector, MAX_EVENT_PACKET_LEN, MAX_FRAME_LEDS, MAX_NFC_DWELL_MS, MAX_NFC_READERS,
+    NFC_ALIVE_INTERVAL_MS, NfcReadError, NfcRetryPolicy, PROTOCOL_VERSION, PacketError,
+    PacketErrorCounts, PacketReader, Request, RetriedRead, SoftResetBarrier, boot_animation,
+    breathing, next_event, nfc_scan_changed,
+};
 use defmt::{Debug2Format, debug, info, warn};
 use embassy_embedded_hal::shared_bus::asynch::spi::SpiDeviceWithConfig;
 use embassy_executor::Spawner;
-use embassy_futures::select::{Either3, Either5, select3, select5};
+use embassy_futures::select::{Either, Either4, select, select4};
 use embassy_stm32::{
     Config, Peri, bind_interrupts,
-    exti::ExtiInput,
-    gpio::{AnyPin, Level, Output, Pull, Speed},
+    gpio::{AnyPin, Level, Output, Speed},
     mode::Async,
-    peripherals::{
-        DMA1_CH3, DMA1_CH4, DMA1_CH5, EXTI0, EXTI1, EXTI2, EXTI8, EXTI9, EXTI10, PA0, PA1, PA2,
-        PA7, PA8, PA9, PA10, PB13, PB14, PB15, SPI1, SPI2,
-    },
+    peripherals::{DMA1_CH3, DMA1_CH4, DMA1_CH5, PA7, PB13, PB14, PB15, SPI1, SPI2},
     rcc::{self, APBPrescaler, Hse, HseMode, Pll, PllMul, PllPreDiv, PllSource, Sysclk},
     spi::{self, Spi},
     time::{hz, khz, mhz},
     usart::{Uart, UartTx},
 };
-use embassy_sync::{blocking_mutex::raw::CriticalSectionRawMutex, mutex::Mutex, signal::Signal};
+use embassy_sync::{
+    blocking_mutex::{self, raw::CriticalSectionRawMutex},
+    mutex::Mutex,
+    signal::Signal,
+};
 use embassy_time::{Delay, Duration, Instant, Timer, WithTimeout};
 use embedded_io_async::Write;
 use heapless::{Vec, index_set::FnvIndexSet};
 use hex_fmt::HexFmt;
 use mfrc522::{
     AsyncMfrc522, AsyncPollingWaiterProvider, CardCommandError, Mfrc522, ReqWupA, RxGain, Select,
-    SpiRegisterAccess,
+    SpiRegisterAccess, Uid,
 };
-use pure_rotary_encoder::{Direction, RotaryEncoder, RotaryPinsState};
-use smart_leds::{RGB, SmartLedsWriteAsync};
+use smart_leds::RGB;
 use ws2812_async::{Grb, Ws2812};
 
 use {defmt_rtt as _, panic_probe as _};
 
+// The rotary encoder and switch pins are used by the debug console instead
+#[cfg(not(feature = "debug-console"))]
+use {
+    crate::debouncer::Debouncer,
+    embassy_futures::select::{Either3, Either5, select3, select5},
+    embassy_stm32::{
+        exti::ExtiInput,
+        gpio::Pull,
+        peripherals::{EXTI8, EXTI9, EXTI10, PA8, PA9, PA10},
+    },
+    pure_rotary_encoder::{Direction, RotaryEncoder, RotaryPinsState},
+};
+
 bind_interrupts!(struct Irqs {
     USART2 => embassy_stm32::usart::InterruptHandler<embassy_stm32::peripherals::USART2>;
     EXTI9_5 => embassy_stm32::exti::InterruptHandler<embassy_stm32::interrupt::typelevel::EXTI9_5>;
@@ -47,9 +71,42 @@ bind_interrupts!(struct Irqs {
 
 type M = CriticalSectionRawMutex;
 
+/// Increase this whenever the firmware changes
+const FW_VERSION: u32 = 1;
+
 static NEW_EVENT_SIGNAL: Signal<M, ()> = Signal::new();
-static EVENT_SIGNALS: [Signal<M, Event>; 4] =
+/// Indexed by [`EventSlot::index`]
+static EVENT_SIGNALS: [Signal<M, Event>; EventSlot::COUNT] = [
+    Signal::new(),
+    Signal::new(),
+    Signal::new(),
+    Signal::new(),
+    Signal::new(),
+    Signal::new(),
+    Signal::new(),
+];
+
+/// Replaces the event of the same kind that wasn't sent yet
+fn send_event(event: Event) {
+    EVENT_SIGNALS[EventSlot::of(&event).index()].signal(event);
+    NEW_EVENT_SIGNAL.signal(());
+}
+
+/// The number of tasks that clear their state on a soft reset
+const SOFT_RESET_TASKS: usize = 4;
+/// Each task drops its state and starts over when it receives a new generation
+static SOFT_RESET_SIGNALS: [Signal<M, u32>; SOFT_RESET_TASKS] =
     [Signal::new(), Signal::new(), Signal::new(), Signal::new()];
+static SOFT_RESET_BARRIER: blocking_mutex::Mutex<M, RefCell<SoftResetBarrier<SOFT_RESET_TASKS>>> =
+    blocking_mutex::Mutex::new(RefCell::new(SoftResetBarrier::new()));
+
+/// Call this after a task has dropped its state.
+/// Once all tasks reset, the ESP is told that the soft reset is complete.
+fn acknowledge_soft_reset(task: usize, generation: u32) {
+    if SOFT_RESET_BARRIER.lock(|barrier| barrier.borrow_mut().acknowledge(task, generation)) {
+        send_event(Event::SoftResetComplete);
+    }
+}
 
 #[embassy_executor::main]
 async fn main(spawner: Spawner) -> ! {
@@ -75,10 +132,24 @@ async fn main(spawner: Spawner) -> ! {
     });
 
     spawner.spawn(leds_task(p.SPI1, p.PA7, p.DMA1_CH3)).unwrap();
-    spawner.spawn(rotary_switch_task(p.PA10, p.EXTI10)).unwrap();
-    spawner
-        .spawn(rotary_encoder_task(p.PA9, p.EXTI9, p.PA8, p.EXTI8))
-        .unwrap();
+    #[cfg(not(feature = "debug-console"))]
+    {
+        spawner.spawn(rotary_switch_task(p.PA10, p.EXTI10)).unwrap();
+        spawner
+            .spawn(rotary_encoder_task(p.PA9, p.EXTI9, p.PA8, p.EXTI8))
+            .unwrap();
+    }
+    #[cfg(feature = "debug-console")]
+    {
+        spawner
+            .spawn(debug_console::debug_console_task(
+                p.USART1, p.PA9, p.PA10, p.FLASH,
+            ))
+            .unwrap();
+        spawner
+            .spawn(debug_console::rotary_disabled_task())
+            .unwrap();
+    }
 
     let mut reset_pin = Output::new(p.PB11, Level::High, Speed::Low);
     // reset_pin.set_low();
@@ -122,45 +193,70 @@ async fn main(spawner: Spawner) -> ! {
 
     let mut dma_buf = [Default::default(); 1024];
     let mut uart_rx = uart_rx.into_ring_buffered(&mut dma_buf);
-    let mut buffer = [Default::default(); 1024];
-    let mut buffer_bytes = 0;
+    let mut reader = PacketReader::<1024>::new();
+    let mut led_staging = LedStaging::<TOTAL_LEDS>::new();
+    let mut link_noise = LinkNoiseDetector::new();
     loop {
         debug!("waiting to read bytes");
-        let new_bytes_read = match uart_rx.read(&mut buffer[buffer_bytes..]).await {
+        let unfilled = reader.unfilled();
+        let new_bytes_read = match uart_rx.read(unfilled).await {
             Ok(n) => n,
             Err(e) => {
                 warn!("error reading UART: {}", e);
                 continue;
             }
         };
-        {
-            let new_bytes = &buffer[buffer_bytes..buffer_bytes + new_bytes_read];
-            debug!("received bytes: {}", new_bytes);
-            buffer_bytes += new_bytes_read;
-        }
-        loop {
-            let bytes = &mut buffer[..buffer_bytes];
-            let zero_index = match bytes.iter().copied().position(|byte| byte == 0) {
-                Some(zero_index) => zero_index,
-                None => break,
-            };
-            let packet_len = zero_index + 1;
-            match postcard::from_bytes_cobs::<Request>(&mut buffer[..packet_len]) {
+        debug!("received bytes: {}", &unfilled[..new_bytes_read]);
+        reader.received(new_bytes_read);
+        while let Some(result) = reader.next_packet::<Request>() {
+            match &result {
+                Ok(_) => {
+                    LAST_REQUEST_AT.lock(|last_request_at| last_request_at.set(Instant::now()));
+                    REQUESTS_RECEIVED.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    PACKET_ERRORS.lock(|counts| {
+                        let mut new_counts = counts.get();
+                        new_counts.record(e);
+                        counts.set(new_counts);
+                    });
+                    if let PacketError::Cobs = e
+                        && let Some(cobs_errors) =
+                            link_noise.record_cobs_error(Instant::now().as_millis())
+                    {
+                        send_event(Event::LinkQualityWarning { cobs_errors });
+                    }
+                }
+            }
+            match result {
                 Ok(request) => match request {
                     Request::SoftReset => {
                         led.set_high();
-                        LEDS_SIGNAL.signal([Default::default(); _]);
-                        WATCH_ROTARY_SWITCH_SIGNAL.signal(false);
-                        WATCH_ROTARY_ENCODER_SIGNAL.signal(false);
-                        WATCH_NFC_SIGNAL.signal(false);
-                        EVENT_SIGNALS[0].signal(Event::SoftResetComplete);
-                        NEW_EVENT_SIGNAL.signal(());
+                        // Tasks start over waiting for new values
+                        LEDS_SIGNAL.reset();
+                        led_staging = LedStaging::new();
+                        WATCH_ROTARY_SWITCH_SIGNAL.reset();
+                        WATCH_ROTARY_ENCODER_SIGNAL.reset();
+                        WATCH_NFC_SIGNAL.reset();
+                        NFC_DWELL_MS
+                            .lock(|dwell_ms| dwell_ms.set([DEFAULT_NFC_DWELL_MS; MAX_NFC_READERS]));
+                        // SoftResetComplete is sent once all tasks reset
+                        let generation =
+                            SOFT_RESET_BARRIER.lock(|barrier| barrier.borrow_mut().reset());
+                        for signal in &SOFT_RESET_SIGNALS {
+                            signal.signal(generation);
+                        }
                     }
                     Request::SetLed(state) => {
                         led.set_level(state.into());
                     }
-                    Request::SetLeds(colors) => {
-                        LEDS_SIGNAL.signal(colors);
+                    Request::SetLedsFrame { offset, colors } => {
+                        if let Err(e) = led_staging.stage(offset, &colors) {
+                            warn!("Ignoring {} LEDs at offset {}: {}", colors.len(), offset, e);
+                        }
+                    }
+                    Request::CommitLeds => {
+                        LEDS_SIGNAL.signal(*led_staging.frame());
                     }
                     Request::WatchRotarySwitch(watch) => {
                         WATCH_ROTARY_SWITCH_SIGNAL.signal(watch);
@@ -171,25 +267,64 @@ async fn main(spawner: Spawner) -> ! {
                     Request::WatchNfc(watch) => {
                         WATCH_NFC_SIGNAL.signal(watch);
                     }
+                    Request::ConfigureNfc { reader, dwell_ms } => {
+                        if usize::from(reader) < MAX_NFC_READERS {
+                            if dwell_ms > MAX_NFC_DWELL_MS {
+                                warn!(
+                                    "Clamping NFC dwell of {}ms to {}ms",
+                                    dwell_ms, MAX_NFC_DWELL_MS
+                                );
+                            }
+                            NFC_DWELL_MS.lock(|dwells| {
+                                let mut new_dwells = dwells.get();
+                                new_dwells[usize::from(reader)] = dwell_ms.min(MAX_NFC_DWELL_MS);
+                                dwells.set(new_dwells);
+                            });
+                        } else {
+                            warn!(
+                                "Ignoring dwell for NFC reader {}, which doesn't exist",
+                                reader
+                            );
+                        }
+                    }
+                    Request::GetInfo => {
+                        send_event(Event::Info {
+                            fw_version: FW_VERSION,
+                            protocol_version: PROTOCOL_VERSION,
+                            nfc_readers: MAX_NFC_READERS as u8,
+                            total_leds: TOTAL_LEDS as u8,
+                            uptime_ms: Instant::now().as_millis() as u32,
+                            packet_errors: PACKET_ERRORS.lock(Cell::get),
+                        });
+                    }
                 },
+                // Noise is counted instead of flooding the log
+                Err(e @ (PacketError::Empty | PacketError::Cobs)) => {
+                    debug!("Corrupted request: {}", e);
+                }
                 Err(e) => {
                     warn!("Error: {}", e);
                 }
             }
-            buffer.copy_within(packet_len..buffer_bytes, 0);
-            buffer_bytes -= packet_len;
         }
     }
 }
 
 #[embassy_executor::task]
 async fn uart_tx_task(mut uart_tx: UartTx<'static, Async>) {
-    let mut buffer = [Default::default(); 1024];
+    let mut buffer = [Default::default(); MAX_EVENT_PACKET_LEN];
     loop {
         NEW_EVENT_SIGNAL.wait().await;
-        for event in EVENT_SIGNALS.iter().flat_map(|event| event.try_take()) {
-            let bytes_written = postcard::to_slice_cobs(&event, &mut buffer).unwrap().len();
-            match uart_tx.write_all(&buffer[..bytes_written]).await {
+        // Input that happens while an event is being sent goes before the rest of the pending events
+        while let Some(event) = next_event(&EVENT_SIGNALS, Signal::try_take) {
+            let bytes = match postcard::to_slice_cobs(&event, &mut buffer) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    warn!("Not sending {}: {}", event, e);
+                    continue;
+                }
+            };
+            match uart_tx.write_all(bytes).await {
                 Ok(()) => {}
                 Err(e) => {
                     warn!("Error writing to UART: {}", e);
@@ -199,8 +334,34 @@ async fn uart_tx_task(mut uart_tx: UartTx<'static, Async>) {
     }
 }
 
+/// The size of the LED staging buffer. Frames can be smaller than this.
 const TOTAL_LEDS: usize = 64;
+const _: () = assert!(
+    TOTAL_LEDS <= MAX_FRAME_LEDS,
+    "the number of LEDs must fit in Event::Info"
+);
 static LEDS_SIGNAL: Signal<CriticalSectionRawMutex, [RGB<u8>; TOTAL_LEDS]> = Signal::new();
+/// Updated by the UART task whenever a valid request is received
+static LAST_REQUEST_AT: blocking_mutex::Mutex<M, Cell<Instant>> =
+    blocking_mutex::Mutex::new(Cell::new(Instant::from_ticks(0)));
+/// The number of valid requests received since booting
+static REQUESTS_RECEIVED: AtomicU32 = AtomicU32::new(0);
+/// The packets from the ESP that couldn't be decoded since booting
+static PACKET_ERRORS: blocking_mutex::Mutex<M, Cell<PacketErrorCounts>> =
+    blocking_mutex::Mutex::new(Cell::new(PacketErrorCounts {
+        too_long: 0,
+        empty: 0,
+        cobs: 0,
+        unknown: 0,
+        deserialize: 0,
+    }));
+/// LED writes kept failing, so they were disabled
+static LEDS_DISABLED: AtomicBool = AtomicBool::new(false);
+/// Set by the debug console so that its frame is shown even if the ESP isn't connected.
+/// Only cleared together with sending a new frame.
+static HOLD_LEDS_FRAME: AtomicBool = AtomicBool::new(false);
+/// How often the boot animation and breathing are updated
+const ANIMATION_FRAME_INTERVAL: Duration = Duration::from_millis(20);
 #[embassy_executor::task]
 async fn leds_task(
     spi: Peri<'static, SPI1>,
@@ -212,63 +373,138 @@ async fn leds_task(
         config.frequency = khz(3800);
         config
     });
-    let mut leds = Ws2812::<_, Grb, TOTAL_LEDS>::new(spi);
+    let mut leds = LedWriter::new(Ws2812::<_, Grb, TOTAL_LEDS>::new(spi));
     loop {
-        let colors = LEDS_SIGNAL.wait().await;
-        leds.write(colors).await.unwrap();
+        let generation = match select(
+            async {
+                // The boot animation starts over on a soft reset
+                let boot_start = Instant::now();
+                // A frame from the ESP is shown until the link is down
+                let mut showing_frame = false;
+                loop {
+                    let now = Instant::now();
+                    // A held frame is shown as if the link never goes down
+                    let link_down_at = if HOLD_LEDS_FRAME.load(Ordering::Relaxed) {
+                        Instant::MAX
+                    } else {
+                        LAST_REQUEST_AT.lock(Cell::get)
+                            + Duration::from_millis(LINK_DOWN_TIMEOUT_MS)
+                    };
+                    let (frame, wake_at) = if showing_frame && now < link_down_at {
+                        (None, link_down_at)
+                    } else if let Some(frame) = boot_animation((now - boot_start).as_millis()) {
+                        (Some(frame), now + ANIMATION_FRAME_INTERVAL)
+                    } else if now >= link_down_at {
+                        (
+                            Some([breathing(now.as_millis()); _]),
+                            now + ANIMATION_FRAME_INTERVAL,
+                        )
+                    } else {
+                        (Some([Default::default(); _]), link_down_at)
+                    };
+                    if let Some(frame) = frame {
+                        leds.write(&frame).await;
+                    }
+                    if let Either::First(colors) =
+                        select(LEDS_SIGNAL.wait(), Timer::at(wake_at)).await
+                    {
+                        leds.write(&colors).await;
+                        showing_frame = true;
+                    }
+                    LEDS_DISABLED.store(leds.disabled(), Ordering::Relaxed);
+                }
+            },
+            SOFT_RESET_SIGNALS[0].wait(),
+        )
+        .await
+        {
+            Either::First(()) => unreachable!(),
+            Either::Second(generation) => generation,
+        };
+        acknowledge_soft_reset(0, generation);
     }
 }
 
 static WATCH_ROTARY_SWITCH_SIGNAL: Signal<M, bool> = Signal::new();
+#[cfg(not(feature = "debug-console"))]
 #[embassy_executor::task]
 async fn rotary_switch_task(pin: Peri<'static, PA10>, exti: Peri<'static, EXTI10>) {
     let mut sw = ExtiInput::new(pin, exti, Pull::Up, Irqs);
     loop {
-        // Wait for enable
-        loop {
-            if WATCH_ROTARY_SWITCH_SIGNAL.wait().await {
-                break;
-            }
-        }
-        let mut debouncer = Debouncer::new(Duration::from_millis(1));
-        loop {
-            let new_value = debouncer.process_data(sw.get_level(), Instant::now());
-            if let Some(&new_value) = new_value {
-                EVENT_SIGNALS[1].signal(Event::RotarySwitch(new_value == Level::Low));
-                NEW_EVENT_SIGNAL.signal(());
-            }
-            match select3(
-                {
-                    let value = *debouncer.maybe_stable_value().unwrap();
-                    let sw = &mut sw;
-                    async move {
-                        match value {
-                            Level::Low => sw.wait_for_high().await,
-                            Level::High => sw.wait_for_low().await,
+        let generation = match select(
+            async {
+                loop {
+                    // Wait for enable
+                    loop {
+                        if WATCH_ROTARY_SWITCH_SIGNAL.wait().await {
+                            break;
                         }
                     }
-                },
-                debouncer.wait(),
-                async {
+                    let mut debouncer = Debouncer::new(Duration::from_millis(1));
+                    let mut pressed_at = None;
                     loop {
-                        if !WATCH_ROTARY_SWITCH_SIGNAL.wait().await {
-                            break;
+                        let had_value = debouncer.stable_value().is_some();
+                        let new_value = debouncer.process_data(sw.get_level(), Instant::now());
+                        if let Some(&new_value) = new_value {
+                            let changed_at = debouncer.stable_since().unwrap();
+                            let pressed = new_value == Level::Low;
+                            let duration_ms = if pressed {
+                                // If the switch was already pressed when we started watching, we don't know when it was pressed
+                                pressed_at = had_value.then_some(changed_at);
+                                None
+                            } else {
+                                pressed_at
+                                    .take()
+                                    .map(|pressed_at| (changed_at - pressed_at).as_millis() as u32)
+                            };
+                            send_event(Event::RotarySwitch {
+                                pressed,
+                                duration_ms,
+                            });
+                        }
+                        match select3(
+                            {
+                                let value = *debouncer.maybe_stable_value().unwrap();
+                                let sw = &mut sw;
+                                async move {
+                                    match value {
+                                        Level::Low => sw.wait_for_high().await,
+                                        Level::High => sw.wait_for_low().await,
+                                    }
+                                }
+                            },
+                            debouncer.wait(),
+                            async {
+                                loop {
+                                    if !WATCH_ROTARY_SWITCH_SIGNAL.wait().await {
+                                        break;
+                                    }
+                                }
+                            },
+                        )
+                        .await
+                        {
+                            Either3::First(()) | Either3::Second(()) => {}
+                            Either3::Third(()) => {
+                                break;
+                            }
                         }
                     }
-                },
-            )
-            .await
-            {
-                Either3::First(()) | Either3::Second(()) => {}
-                Either3::Third(()) => {
-                    break;
                 }
-            }
-        }
+            },
+            SOFT_RESET_SIGNALS[1].wait(),
+        )
+        .await
+        {
+            Either::First(()) => unreachable!(),
+            Either::Second(generation) => generation,
+        };
+        acknowledge_soft_reset(1, generation);
     }
 }
 
 static WATCH_ROTARY_ENCODER_SIGNAL: Signal<M, bool> = Signal::new();
+#[cfg(not(feature = "debug-console"))]
 #[embassy_executor::task]
 async fn rotary_encoder_task(
     dt: Peri<'static, PA9>,
@@ -279,88 +515,167 @@ async fn rotary_encoder_task(
     let mut dt = ExtiInput::new(dt, dt_exti, Pull::Up, Irqs);
     let mut clk = ExtiInput::new(clk, clk_exti, Pull::Up, Irqs);
     loop {
-        // Wait for enable
-        loop {
-            if WATCH_ROTARY_ENCODER_SIGNAL.wait().await {
-                break;
-            }
-        }
-        let mut dt_debouncer = Debouncer::new(Duration::from_millis(1));
-        let mut clk_debouncer = Debouncer::new(Duration::from_millis(1));
-        let mut rotary_encoder = None;
-        let mut position = 0;
-        loop {
-            let new_dt = dt_debouncer.process_data(dt.get_level(), Instant::now());
-            let new_clk = clk_debouncer.process_data(clk.get_level(), Instant::now());
-            let state_changed = new_dt.is_some() || new_clk.is_some();
-            if state_changed
-                && let Some((dt, clk)) = dt_debouncer
-                    .stable_value()
-                    .and_then(|dt| clk_debouncer.stable_value().map(|clk| (*dt, *clk)))
-            {
-                let pins_state = RotaryPinsState {
-                    dt: dt == Level::Low,
-                    clk: clk == Level::Low,
-                };
-                if let Some(direction) = rotary_encoder
-                    .get_or_insert(RotaryEncoder::new(pins_state))
-                    .process_data(pins_state)
-                {
-                    position += match direction {
-                        Direction::Clockwise => 1,
-                        Direction::CounterClockwise => -1,
-                    };
-                    info!("rotary position: {}", position);
-                    EVENT_SIGNALS[2].signal(Event::RotaryEncoder(position));
-                    NEW_EVENT_SIGNAL.signal(());
-                }
-            }
-            match select5(
-                {
-                    let value = *dt_debouncer.maybe_stable_value().unwrap();
-                    let dt = &mut dt;
-                    async move {
-                        match value {
-                            Level::Low => dt.wait_for_high().await,
-                            Level::High => dt.wait_for_low().await,
-                        }
-                    }
-                },
-                dt_debouncer.wait(),
-                {
-                    let value = *clk_debouncer.maybe_stable_value().unwrap();
-                    let clk = &mut clk;
-                    async move {
-                        match value {
-                            Level::Low => clk.wait_for_high().await,
-                            Level::High => clk.wait_for_low().await,
+        let generation = match select(
+            async {
+                loop {
+                    // Wait for enable
+                    loop {
+                        if WATCH_ROTARY_ENCODER_SIGNAL.wait().await {
+                            break;
                         }
                     }
-                },
-                clk_debouncer.wait(),
-                async {
+                    let mut dt_debouncer = Debouncer::new(Duration::from_millis(1));
+                    let mut clk_debouncer = Debouncer::new(Duration::from_millis(1));
+                    let mut rotary_encoder = None;
+                    let mut position = 0;
                     loop {
-                        if !WATCH_ROTARY_ENCODER_SIGNAL.wait().await {
-                            break;
+                        let new_dt = dt_debouncer.process_data(dt.get_level(), Instant::now());
+                        let new_clk = clk_debouncer.process_data(clk.get_level(), Instant::now());
+                        let state_changed = new_dt.is_some() || new_clk.is_some();
+                        if state_changed
+                            && let Some((dt, clk)) = dt_debouncer
+                                .stable_value()
+                                .and_then(|dt| clk_debouncer.stable_value().map(|clk| (*dt, *clk)))
+                        {
+                            let pins_state = RotaryPinsState {
+                                dt: dt == Level::Low,
+                                clk: clk == Level::Low,
+                            };
+                            if let Some(direction) = rotary_encoder
+                                .get_or_insert(RotaryEncoder::new(pins_state))
+                                .process_data(pins_state)
+                            {
+                                position += match direction {
+                                    Direction::Clockwise => 1,
+                                    Direction::CounterClockwise => -1,
+                                };
+                                info!("rotary position: {}", position);
+                                send_event(Event::RotaryEncoder(position));
+                            }
+                        }
+                        match select5(
+                            {
+                                let value = *dt_debouncer.maybe_stable_value().unwrap();
+                                let dt = &mut dt;
+                                async move {
+                                    match value {
+                                        Level::Low => dt.wait_for_high().await,
+                                        Level::High => dt.wait_for_low().await,
+                                    }
+                                }
+                            },
+                            dt_debouncer.wait(),
+                            {
+                                let value = *clk_debouncer.maybe_stable_value().unwrap();
+                                let clk = &mut clk;
+                                async move {
+                                    match value {
+                                        Level::Low => clk.wait_for_high().await,
+                                        Level::High => clk.wait_for_low().await,
+                                    }
+                                }
+                            },
+                            clk_debouncer.wait(),
+                            async {
+                                loop {
+                                    if !WATCH_ROTARY_ENCODER_SIGNAL.wait().await {
+                                        break;
+                                    }
+                                }
+                            },
+                        )
+                        .await
+                        {
+                            Either5::First(())
+                            | Either5::Second(())
+                            | Either5::Third(())
+                            | Either5::Fourth(()) => {}
+                            Either5::Fifth(()) => {
+                                break;
+                            }
                         }
                     }
-                },
-            )
-            .await
-            {
-                Either5::First(())
-                | Either5::Second(())
-                | Either5::Third(())
-                | Either5::Fourth(()) => {}
-                Either5::Fifth(()) => {
-                    break;
                 }
+            },
+            SOFT_RESET_SIGNALS[2].wait(),
+        )
+        .await
+        {
+            Either::First(()) => unreachable!(),
+            Either::Second(generation) => generation,
+        };
+        acknowledge_soft_reset(2, generation);
+    }
+}
+
+static WATCH_NFC_SIGNAL: Signal<M, bool> = Signal::new();
+/// Set once the NFC readers are initialized
+static WORKING_NFC_READERS: AtomicUsize = AtomicUsize::new(0);
+/// How long each reader's antenna is on before the WUPA, set with [`Request::ConfigureNfc`]
+static NFC_DWELL_MS: blocking_mutex::Mutex<M, Cell<[u8; MAX_NFC_READERS]>> =
+    blocking_mutex::Mutex::new(Cell::new([DEFAULT_NFC_DWELL_MS; MAX_NFC_READERS]));
+
+type NfcReader<'a> = AsyncMfrc522<
+    SpiRegisterAccess<SpiDeviceWithConfig<'a, M, Spi<'static, Async>, Output<'static>>>,
+    AsyncPollingWaiterProvider<Delay>,
+>;
+
+/// Turns on the antenna for `dwell_ms` so that the card can power up, and then reads the UID of the card
+async fn read_card(device: &mut NfcReader<'_>, dwell_ms: u8) -> Option<Uid> {
+    read_card_result(device, dwell_ms).await.result.ok()
+}
+
+/// Like [`read_card`], but with why the card couldn't be read.
+/// A missing card is a [`NfcReadError::WupaTimeout`].
+/// SELECT collisions are retried with [`NfcRetryPolicy::DEFAULT`] before turning off the antenna.
+async fn read_card_result(device: &mut NfcReader<'_>, dwell_ms: u8) -> RetriedRead<Uid> {
+    device.set_antenna_enabled(true).await.unwrap();
+    Timer::after_millis(dwell_ms.into()).await;
+    let read = NfcRetryPolicy::DEFAULT
+        .read(async || wupa_select(device).await)
+        .await;
+    if read.collision_recovered() {
+        debug!("Read the card after {} SELECT collisions", read.collisions);
+    }
+    device.set_antenna_enabled(false).await.unwrap();
+    read
+}
+
+/// One WUPA and SELECT, with the antenna already on
+async fn wupa_select(device: &mut NfcReader<'_>) -> Result<Uid, NfcReadError> {
+    debug!("Doing  WUPA");
+    match device.card_command(ReqWupA::new(true)).await {
+        Ok(atq_a) => {
+            if let Ok(select) = Select::new(&atq_a) {
+                match device.card_command(select).await {
+                    Ok(uid) => {
+                        // info!("detected uid: {}", uid);
+                        Ok(uid)
+                    }
+                    Err(CardCommandError::CardCommand(e)) => {
+                        debug!("SELECT error: {}", e);
+                        Err(NfcReadError::SelectCollision)
+                    }
+                    Err(_e) => {
+                        debug!("SELECT error");
+                        Err(NfcReadError::Spi)
+                    }
+                }
+            } else {
+                Err(NfcReadError::SelectCollision)
             }
         }
+        Err(CardCommandError::CardCommand(e)) => {
+            debug!("WupA error: {}", e);
+            Err(NfcReadError::WupaTimeout)
+        }
+        Err(_e) => {
+            debug!("WUPA error");
+            Err(NfcReadError::Spi)
+        }
     }
 }
 
-static WATCH_NFC_SIGNAL: Signal<M, bool> = Signal::new();
 #[embassy_executor::task]
 async fn nfc_task(
     spi: Peri<'static, SPI2>,
@@ -467,6 +782,7 @@ async fn nfc_task(
             working_nfc_readers, MAX_NFC_READERS
         );
         nfc_readers.drain(working_nfc_readers..);
+        WORKING_NFC_READERS.store(working_nfc_readers, Ordering::Relaxed);
         nfc_readers
     };
 
@@ -474,85 +790,127 @@ async fn nfc_task(
     // There are two reasons why we are only checking one device at a time
     // One is that they can interfere with each other
     // Another reason is to not overload the 5V to 3.3V converter on the esp32c3
-    let mut enabled = false;
     loop {
-        if let Some(new_enabled) = WATCH_NFC_SIGNAL.try_take() {
-            enabled = new_enabled;
-        }
-        if !enabled {
-            enabled = WATCH_NFC_SIGNAL.wait().await;
-            continue;
-        }
-
-        // let mut ids = FnvIndexSet::<_, { MAX_NFC_READERS.next_power_of_two() }>::new();
-        // let mut detected_ids = array::from_fn::<_, MAX_NFC_READERS, _>(|_| None);
-        let mut detected_ids = Vec::<_, MAX_NFC_READERS>::new();
-        // let before = Instant::now();
-        for (_i, device) in nfc_readers.iter_mut().enumerate() {
-            // let version = device.version().await.unwrap();
-            // if [0x8, 0x9].contains(&version.get_chip_type()) && version.get_version() == 0x2 {
-            //     info!("[{}] version good", i);
-            // } else {
-            //     info!(
-            //         "[{}] NFC reader chip type: {:#04X}, version: {:#04X}",
-            //         i,
-            //         version.get_chip_type(),
-            //         version.get_version()
-            //     );
-            // }
-            // Timer::after_millis(100).await;
-            device.set_antenna_enabled(true).await.unwrap();
-            debug!("Doing  WUPA");
-            let uid = match device.card_command(ReqWupA::new(true)).await {
-                Ok(atq_a) => {
-                    if let Ok(select) = Select::new(&atq_a) {
-                        match device.card_command(select).await {
-                            Ok(uid) => {
-                                // info!("detected uid: {}", uid);
-                                // ids.insert(uid).unwrap();
-                                Some(uid)
-                            }
-                            Err(CardCommandError::CardCommand(e)) => {
-                                debug!("SELECT error: {}", e);
-                                None
-                            }
-                            Err(_e) => {
-                                debug!("SELECT error");
-                                None
+        let generation = match select(
+            async {
+                let mut enabled = false;
+                // Only send the scanned cards if they changed
+                let mut previous_ids = None::<Vec<_, MAX_NFC_READERS>>;
+                let mut last_alive = None::<Instant>;
+                loop {
+                    #[cfg(feature = "debug-console")]
+                    if debug_console::NFC_PROBE_SIGNAL.try_take().is_some() {
+                        let mut versions = Vec::new();
+                        for nfc_reader in nfc_readers.iter_mut() {
+                            let version = nfc_reader.version().await.ok();
+                            let version = version.map(|v| (v.get_chip_type(), v.get_version()));
+                            versions.push(version).unwrap();
+                        }
+                        debug_console::NFC_PROBE_RESULT_SIGNAL.signal(versions);
+                    }
+                    #[cfg(feature = "debug-console")]
+                    if let Some(reader) = debug_console::NFC_DWELL_SWEEP_SIGNAL.try_take() {
+                        let results = match nfc_readers.get_mut(usize::from(reader)) {
+                            Some(device) => Some(debug_console::sweep_dwell(device).await),
+                            None => None,
+                        };
+                        debug_console::NFC_DWELL_SWEEP_RESULT_SIGNAL.signal(results);
+                        // The scan results are sent again, since they could have changed during the sweep
+                        previous_ids = None;
+                    }
+                    #[cfg(feature = "debug-console")]
+                    if debug_console::NFC_SOAK_SIGNAL.try_take() == Some(true) {
+                        debug_console::soak(&mut nfc_readers).await;
+                        previous_ids = None;
+                    }
+                    if let Some(new_enabled) = WATCH_NFC_SIGNAL.try_take() {
+                        enabled = new_enabled;
+                    }
+                    if !enabled {
+                        // Send the cards again after being re-enabled
+                        previous_ids = None;
+                        last_alive = None;
+                        #[cfg(not(feature = "debug-console"))]
+                        {
+                            enabled = WATCH_NFC_SIGNAL.wait().await;
+                        }
+                        // Probing is handled at the start of the loop
+                        #[cfg(feature = "debug-console")]
+                        match select4(
+                            WATCH_NFC_SIGNAL.wait(),
+                            debug_console::NFC_PROBE_SIGNAL.wait(),
+                            debug_console::NFC_DWELL_SWEEP_SIGNAL.wait(),
+                            debug_console::NFC_SOAK_SIGNAL.wait(),
+                        )
+                        .await
+                        {
+                            Either4::First(new_enabled) => enabled = new_enabled,
+                            Either4::Second(()) => debug_console::NFC_PROBE_SIGNAL.signal(()),
+                            Either4::Third(reader) => {
+                                debug_console::NFC_DWELL_SWEEP_SIGNAL.signal(reader)
                             }
+                            Either4::Fourth(soak) => debug_console::NFC_SOAK_SIGNAL.signal(soak),
                         }
-                    } else {
-                        None
+                        continue;
                     }
-                }
-                Err(CardCommandError::CardCommand(e)) => {
-                    debug!("WupA error: {}", e);
-                    None
-                }
-                Err(_e) => {
-                    debug!("WUPA error");
-                    None
-                }
-            };
-            detected_ids.push(uid).unwrap();
-            device.set_antenna_enabled(false).await.unwrap();
-        }
-        // let ids_hex = detected_ids
-        //     .iter()
-        //     .map(|id| id.as_ref().map(|id| HexFmt(id.as_bytes())))
-        //     .collect::<Vec<_, MAX_NFC_READERS>>();
-        // info!(
-        //     "scanned ids: {:#?} in {}us",
-        //     Debug2Format(&ids_hex),
-        //     before.elapsed().as_micros()
-        // );
-        EVENT_SIGNALS[3].signal(Event::Nfc(detected_ids));
-        NEW_EVENT_SIGNAL.signal(());
 
-        if nfc_readers.is_empty() {
-            // TODO: Only send this once
-            Timer::after_secs(1).await;
-        }
-        // Timer::after_millis(25).await;
+                    // let mut ids = FnvIndexSet::<_, { MAX_NFC_READERS.next_power_of_two() }>::new();
+                    // let mut detected_ids = array::from_fn::<_, MAX_NFC_READERS, _>(|_| None);
+                    let mut detected_ids = Vec::<_, MAX_NFC_READERS>::new();
+                    // let before = Instant::now();
+                    let dwell_ms = NFC_DWELL_MS.lock(Cell::get);
+                    for (i, device) in nfc_readers.iter_mut().enumerate() {
+                        // let version = device.version().await.unwrap();
+                        // if [0x8, 0x9].contains(&version.get_chip_type()) && version.get_version() == 0x2 {
+                        //     info!("[{}] version good", i);
+                        // } else {
+                        //     info!(
+                        //         "[{}] NFC reader chip type: {:#04X}, version: {:#04X}",
+                        //         i,
+                        //         version.get_chip_type(),
+                        //         version.get_version()
+                        //     );
+                        // }
+                        // Timer::after_millis(100).await;
+                        let uid = read_card(device, dwell_ms[i]).await;
+                        detected_ids.push(uid).unwrap();
+                    }
+                    // let ids_hex = detected_ids
+                    //     .iter()
+                    //     .map(|id| id.as_ref().map(|id| HexFmt(id.as_bytes())))
+                    //     .collect::<Vec<_, MAX_NFC_READERS>>();
+                    // info!(
+                    //     "scanned ids: {:#?} in {}us",
+                    //     Debug2Format(&ids_hex),
+                    //     before.elapsed().as_micros()
+                    // );
+                    if previous_ids
+                        .as_ref()
+                        .is_none_or(|previous_ids| nfc_scan_changed(previous_ids, &detected_ids))
+                    {
+                        send_event(Event::Nfc(detected_ids.clone()));
+                        previous_ids = Some(detected_ids);
+                    }
+                    if last_alive.is_none_or(|last_alive| {
+                        last_alive.elapsed() >= Duration::from_millis(NFC_ALIVE_INTERVAL_MS)
+                    }) {
+                        send_event(Event::NfcAlive);
+                        last_alive = Some(Instant::now());
+                    }
+
+                    if nfc_readers.is_empty() {
+                        Timer::after_secs(1).await;
+                    }
+                    // Timer::after_millis(25).await;
+                }
+            },
+            SOFT_RESET_SIGNALS[3].wait(),
+        )
+        .await
+        {
+            Either::First(()) => unreachable!(),
+            Either::Second(generation) => generation,
+        };
+        acknowledge_soft_reset(3, generation);
     }
 }
@@ -0,0 +1,193 @@
+//! Host-only helpers for golden image tests of the screens, to catch accidental layout changes.
+//!
+//! A frame is saved as text art with one character per pixel: `#` is on and `.` is off.
+//! The golden files are in `goldens/`. To accept a change to the screens, run the tests with `UPDATE_GOLDENS=1`
+//! and check the changed golden files in with the change.
+
+use std::{convert::Infallible, env, fmt::Write, fs, path::PathBuf};
+
+use embedded_graphics::{
+    Pixel,
+    pixelcolor::BinaryColor,
+    prelude::{DrawTarget, OriginDimensions, Size},
+};
+
+/// The size of the SSD1306
+pub const WIDTH: usize = 128;
+pub const HEIGHT: usize = 64;
+
+/// A 128x64 frame buffer that implements [`lib::Display`], so that the firmware's renderer can draw to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Frame {
+    pixels: [[bool; WIDTH]; HEIGHT],
+    pub flushes: usize,
+    /// Like the SSD1306, pixels are drawn rotated 180° when this is set, so that the frame is what would be seen
+    pub rotated: bool,
+}
+
+impl Frame {
+    pub fn new() -> Self {
+        Self {
+            pixels: [[false; WIDTH]; HEIGHT],
+            flushes: 0,
+            rotated: false,
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> bool {
+        self.pixels[y][x]
+    }
+
+    /// One line per row, with a newline at the end of each row
+    pub fn to_text_art(&self) -> String {
+        let mut text = String::with_capacity((WIDTH + 1) * HEIGHT);
+        for row in &self.pixels {
+            text.extend(row.iter().map(|&on| if on { '#' } else { '.' }));
+            text.push('\n');
+        }
+        text
+    }
+}
+
+impl Default for Frame {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl OriginDimensions for Frame {
+    fn size(&self) -> Size {
+        Size::new(WIDTH as u32, HEIGHT as u32)
+    }
+}
+
+impl DrawTarget for Frame {
+    type Color = BinaryColor;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(point, color) in pixels {
+            // Like the SSD1306, anything drawn off the screen is ignored
+            if let (Ok(x @ 0..WIDTH), Ok(y @ 0..HEIGHT)) =
+                (usize::try_from(point.x), usize::try_from(point.y))
+            {
+                if self.rotated {
+                    self.pixels[HEIGHT - 1 - y][WIDTH - 1 - x] = color.is_on();
+                } else {
+                    self.pixels[y][x] = color.is_on();
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl lib::Display for Frame {
+    async fn init(&mut self) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    fn clear_buffer(&mut self) {
+        let _ = self.clear(BinaryColor::Off);
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.flushes += 1;
+        Ok(())
+    }
+
+    async fn set_invert(&mut self, _invert: bool) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn set_brightness(&mut self, _brightness: u8) -> Result<(), Self::Error> {
+        Ok(())
+    }
+
+    async fn set_rotated(&mut self, rotated: bool) -> Result<(), Self::Error> {
+        self.rotated = rotated;
+        Ok(())
+    }
+}
+
+/// The renderer logs with defmt, which needs a logger and a timestamp to link on the host. The logs are dropped.
+#[defmt::global_logger]
+struct NoopLogger;
+
+unsafe impl defmt::Logger for NoopLogger {
+    fn acquire() {}
+
+    unsafe fn flush() {}
+
+    unsafe fn release() {}
+
+    unsafe fn write(_bytes: &[u8]) {}
+}
+
+defmt::timestamp!("");
+
+#[defmt::panic_handler]
+fn defmt_panic() -> ! {
+    panic!("defmt panic")
+}
+
+/// Lists the rows that are different, with a `^` under each pixel that changed
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected_rows = expected.lines().collect::<Vec<_>>();
+    let actual_rows = actual.lines().collect::<Vec<_>>();
+    let mut diff = String::new();
+    for row in 0..expected_rows.len().max(actual_rows.len()) {
+        let expected_row = expected_rows.get(row).copied().unwrap_or_default();
+        let actual_row = actual_rows.get(row).copied().unwrap_or_default();
+        if expected_row == actual_row {
+            continue;
+        }
+        let markers = expected_row
+            .chars()
+            .map(Some)
+            .chain(std::iter::repeat(None))
+            .zip(actual_row.chars().map(Some).chain(std::iter::repeat(None)))
+            .take(expected_row.len().max(actual_row.len()))
+            .map(|(expected, actual)| if expected == actual { ' ' } else { '^' })
+            .collect::<String>();
+        let _ = writeln!(diff, "row {row}:");
+        let _ = writeln!(diff, "  expected {expected_row}");
+        let _ = writeln!(diff, "  actual   {actual_row}");
+        let _ = writeln!(diff, "           {}", markers.trim_end());
+    }
+    diff
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("goldens")
+        .join(format!("{name}.txt"))
+}
+
+/// Compares `frame` with the golden file called `name`, or overwrites the golden file if `UPDATE_GOLDENS=1`.
+/// Panics with the changed rows if they are different.
+pub fn assert_golden(name: &str, frame: &Frame) {
+    let path = golden_path(name);
+    let actual = frame.to_text_art();
+    if env::var("UPDATE_GOLDENS").is_ok_and(|update| update == "1") {
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, actual).unwrap();
+        return;
+    }
+    let expected = fs::read_to_string(&path).unwrap_or_else(|e| {
+        panic!(
+            "Couldn't read {}: {e}. Run with UPDATE_GOLDENS=1 to create it.",
+            path.display()
+        )
+    });
+    if expected != actual {
+        panic!(
+            "The {name} screen doesn't match {}. Changed rows:\n{}\nRun with UPDATE_GOLDENS=1 if this change is intended.",
+            path.display(),
+            diff(&expected, &actual)
+        );
+    }
+}
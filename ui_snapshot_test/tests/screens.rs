@@ -0,0 +1,237 @@
+use embassy_futures::block_on;
+use game_pure::{
+    CharacterCardId, DetectedPolicyCards, GameState, Input, PlayingMenuSelectedItem, PolicyCardId,
+    SecretRole, Settings, Team,
+};
+use lib::{
+    CardKind, CardRegistry, CardUid, Display, FascistScreen, FrameTimer, READER_DEBUG, ReaderRole,
+    SlotMap, config::FRAME_STATS_LOG_INTERVAL, draw_fascist_screen, interpret_scan,
+    liberal_renderer::render_ui_2,
+};
+use trouble_host::Address;
+use ui_snapshot_test::{Frame, HEIGHT, WIDTH, assert_golden, diff};
+
+fn render(game_state: &GameState) -> Frame {
+    render_to(Frame::new(), game_state)
+}
+
+/// Rotated with [`Settings::rotate_display`], like the display task does before rendering
+fn render_rotated(game_state: &GameState) -> Frame {
+    let mut frame = Frame::new();
+    block_on(frame.set_rotated(game_state.settings().rotate_display)).unwrap();
+    render_to(frame, game_state)
+}
+
+fn render_to(mut frame: Frame, game_state: &GameState) -> Frame {
+    let mut frame_timer = FrameTimer::new(FRAME_STATS_LOG_INTERVAL);
+    block_on(render_ui_2(
+        &mut frame,
+        game_state.clone(),
+        &mut frame_timer,
+    ))
+    .unwrap();
+    assert_eq!(frame.flushes, 1);
+    frame
+}
+
+fn address(i: u8) -> Address {
+    Address::random([i, 1, 2, 3, 4, 5])
+}
+
+/// Scanning with 3 peripherals found
+fn scanning() -> GameState {
+    let mut state = GameState::new(None, Default::default(), Default::default());
+    state.process_input(Input::Down);
+    state.process_input(Input::Click);
+    for i in 0..3 {
+        state.ble_peripheral_found(address(i));
+    }
+    state
+}
+
+/// Connecting to the first peripheral as the fascist board
+fn connecting() -> GameState {
+    let mut state = scanning();
+    state.process_input(Input::Down);
+    state.process_input(Input::Down);
+    state.process_input(Input::Click);
+    state.process_input(Input::Up);
+    state.process_input(Input::Click);
+    state
+}
+
+/// A 10 player game, which is the default
+fn playing() -> GameState {
+    let mut state = GameState::new(Some(address(0)), Default::default(), Default::default());
+    state.ble_connected(address(0), 0);
+    // Start Game is selected
+    state.process_input(Input::Click);
+    assert!(matches!(state, GameState::Playing(_)));
+    state
+}
+
+fn policies(team: Team, count: usize) -> DetectedPolicyCards {
+    let cards = || (0..count).map(|id| PolicyCardId { team, id });
+    match team {
+        Team::Liberal => DetectedPolicyCards {
+            liberal: cards().collect(),
+            fascist: Default::default(),
+        },
+        Team::Fascist => DetectedPolicyCards {
+            liberal: Default::default(),
+            fascist: cards().collect(),
+        },
+    }
+}
+
+#[test]
+fn main_menu() {
+    let state = GameState::new(None, Default::default(), Default::default());
+    assert_golden("main_menu", &render(&state));
+}
+
+/// The renderer draws the same way, and the display shows it upside down
+#[test]
+fn main_menu_rotated() {
+    let state = GameState::new(
+        None,
+        Default::default(),
+        Settings {
+            rotate_display: true,
+            ..Default::default()
+        },
+    );
+    let rotated = render_rotated(&state);
+    assert_golden("main_menu_rotated", &rotated);
+    let upright = render(&state);
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            assert_eq!(
+                rotated.pixel(WIDTH - 1 - x, HEIGHT - 1 - y),
+                upright.pixel(x, y)
+            );
+        }
+    }
+}
+
+#[test]
+fn scanning_3_peripherals() {
+    assert_golden("scanning_3_peripherals", &render(&scanning()));
+}
+
+#[test]
+fn connecting_screen() {
+    assert_golden("connecting", &render(&connecting()));
+}
+
+/// Renaming a peripheral
+#[test]
+fn text_entry() {
+    let mut state = connecting();
+    // Click on the peripheral
+    state.process_input(Input::Down);
+    state.process_input(Input::Down);
+    state.process_input(Input::Click);
+    assert_golden("text_entry", &render(&state));
+}
+
+/// The fascist board's screen while the liberal board is pairing with it
+#[test]
+fn passkey() {
+    let mut frame = Frame::new();
+    draw_fascist_screen(&mut frame, FascistScreen::Passkey(123), address(0).addr);
+    assert_golden("passkey", &frame);
+}
+
+#[test]
+fn playing_kill_hint() {
+    // The 4th fascist policy gives the president the power to kill
+    let mut state = playing();
+    state.update_scanned_policy_cards(policies(Team::Fascist, 4));
+    assert_golden("playing_kill_hint", &render(&state));
+}
+
+#[test]
+fn game_over() {
+    let mut state = playing();
+    state.update_scanned_policy_cards(policies(Team::Liberal, 5));
+    assert_golden("game_over", &render(&state));
+}
+
+#[test]
+fn paused() {
+    let mut state = playing();
+    // Pause game is the second item in the menu
+    state.process_input(Input::Click);
+    state.process_input(Input::Down);
+    state.process_input(Input::Click);
+    assert_golden("paused", &render(&state));
+}
+
+#[test]
+fn reader_debug() {
+    let mut registry = CardRegistry::new();
+    registry
+        .register(
+            CardUid::new(&[1]),
+            CardKind::Policy(PolicyCardId {
+                team: Team::Liberal,
+                id: 0,
+            }),
+        )
+        .unwrap();
+    registry
+        .register(
+            CardUid::new(&[2]),
+            CardKind::Character(CharacterCardId {
+                secret_role: SecretRole::Hitler,
+                id: 0,
+            }),
+        )
+        .unwrap();
+    let roles: SlotMap = [ReaderRole::PolicySlot(Team::Liberal); 5]
+        .into_iter()
+        .chain([ReaderRole::DeadCharacter])
+        .collect();
+    // An empty reader, a liberal policy, an unknown card, a character card, and a reader that didn't report
+    let scan = [None, Some(1), Some(9), None, Some(2)]
+        .into_iter()
+        .map(|card| card.map(|byte| CardUid::new(&[byte])))
+        .collect();
+    READER_DEBUG.lock(|reader_debug| {
+        reader_debug.borrow_mut().update(
+            &interpret_scan(&scan, &registry, &roles),
+            &registry,
+            &roles,
+        )
+    });
+
+    let mut state = playing();
+    state.update_scanned_policy_cards(policies(Team::Liberal, 1));
+    state.process_input(Input::Click);
+    for _ in 0..PlayingMenuSelectedItem::ReaderDebug as usize {
+        state.process_input(Input::Down);
+    }
+    state.process_input(Input::Click);
+    assert_golden("reader_debug", &render(&state));
+}
+
+#[test]
+fn text_art() {
+    let mut frame = Frame::new();
+    assert_eq!(
+        frame.to_text_art(),
+        format!("{}\n", ".".repeat(128)).repeat(64)
+    );
+    block_on(lib::Display::flush(&mut frame)).unwrap();
+    assert_eq!(frame.flushes, 1);
+}
+
+#[test]
+fn diff_shows_changed_rows() {
+    let diff = diff("..\n.#\n##\n", "..\n#.\n##\n");
+    assert_eq!(
+        diff,
+        "row 1:\n  expected .#\n  actual   #.\n           ^^\n"
+    );
+}
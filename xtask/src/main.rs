@@ -0,0 +1,132 @@
+//! Tasks for working on this repository. Run with `cargo xtask <task>` from the repository's root.
+//!
+//! Every crate has its own manifest, and the firmware crates force their embedded target in their `.cargo/config.toml`,
+//! so there isn't one `cargo test` that runs everything. This runs each host test suite with the stable toolchain.
+
+use std::{
+    env,
+    path::{Path, PathBuf},
+    process::{Command, ExitCode},
+};
+
+/// A `cargo test` run in one crate
+struct HostSuite {
+    /// Relative to the repository's root
+    dir: &'static str,
+    args: &'static [&'static str],
+    /// The crate's `.cargo/config.toml` forces an embedded target, so the host target has to be given
+    override_target: bool,
+}
+
+const HOST_SUITES: &[HostSuite] = &[
+    HostSuite {
+        dir: "game_pure",
+        args: &["test"],
+        override_target: false,
+    },
+    HostSuite {
+        dir: "game_pure",
+        args: &["test", "--features", "statechart"],
+        override_target: false,
+    },
+    HostSuite {
+        dir: "game_pure",
+        args: &["test", "--features", "std"],
+        override_target: false,
+    },
+    HostSuite {
+        dir: "common",
+        args: &["test"],
+        override_target: false,
+    },
+    HostSuite {
+        dir: "protocol_test",
+        args: &["test"],
+        override_target: false,
+    },
+    // The library's tests, without the ESP's peripherals. The binaries only build for the ESP.
+    HostSuite {
+        dir: "code",
+        args: &[
+            "test",
+            "--lib",
+            "--no-default-features",
+            "--features",
+            "mock-display",
+        ],
+        override_target: true,
+    },
+    HostSuite {
+        dir: "ui_snapshot_test",
+        args: &["test"],
+        override_target: false,
+    },
+];
+
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .unwrap()
+        .to_path_buf()
+}
+
+/// The target triple that `rustc` builds for by default, like `x86_64-unknown-linux-gnu`
+fn host_target() -> Option<String> {
+    let output = Command::new(env::var("RUSTC").unwrap_or_else(|_| "rustc".into()))
+        .arg("-vV")
+        .output()
+        .ok()?;
+    String::from_utf8(output.stdout)
+        .ok()?
+        .lines()
+        .find_map(|line| line.strip_prefix("host: "))
+        .map(|host| host.to_owned())
+}
+
+/// Runs every suite, even after one fails, and lists the ones that failed at the end
+fn test_host() -> ExitCode {
+    let Some(host_target) = host_target() else {
+        eprintln!("Couldn't get the host target from `rustc -vV`");
+        return ExitCode::FAILURE;
+    };
+    let cargo = env::var("CARGO").unwrap_or_else(|_| "cargo".into());
+    let root = root();
+    let mut failed = Vec::new();
+    for suite in HOST_SUITES {
+        let mut command = Command::new(&cargo);
+        command.current_dir(root.join(suite.dir)).args(suite.args);
+        if suite.override_target {
+            command.args(["--target", &host_target]);
+        }
+        let description = format!("{}: cargo {}", suite.dir, suite.args.join(" "));
+        eprintln!("Running {description}");
+        if !command.status().is_ok_and(|status| status.success()) {
+            failed.push(description);
+        }
+    }
+    if failed.is_empty() {
+        eprintln!("All {} host test suites passed", HOST_SUITES.len());
+        ExitCode::SUCCESS
+    } else {
+        eprintln!("Failed:");
+        for description in failed {
+            eprintln!("  {description}");
+        }
+        ExitCode::FAILURE
+    }
+}
+
+fn main() -> ExitCode {
+    match env::args().nth(1).as_deref() {
+        Some("test-host") => test_host(),
+        _ => {
+            eprintln!("Usage: cargo xtask <task>");
+            eprintln!();
+            eprintln!("Tasks:");
+            eprintln!(
+                "  test-host  Runs every test suite that builds on the host, without an embedded toolchain"
+            );
+            ExitCode::FAILURE
+        }
+    }
+}